@@ -0,0 +1,22 @@
+@echo off
+rem Adds "Open WezTerm here" to the Explorer context menu of folders
+rem and of the background of an open folder.  Run this from the
+rem directory that holds wezterm.exe; pass /uninstall to remove it.
+rem The entries are made for the current user only.
+
+set EXE=%~dp0wezterm.exe
+set KEY=HKCU\Software\Classes\Directory
+
+if /i "%~1"=="/uninstall" goto uninstall
+
+reg add "%KEY%\shell\WezTerm" /ve /d "Open WezTerm here" /f >nul
+reg add "%KEY%\shell\WezTerm" /v Icon /d "\"%EXE%\"" /f >nul
+reg add "%KEY%\shell\WezTerm\command" /ve /d "\"%EXE%\" start --cwd \"%%1\"" /f >nul
+reg add "%KEY%\Background\shell\WezTerm" /ve /d "Open WezTerm here" /f >nul
+reg add "%KEY%\Background\shell\WezTerm" /v Icon /d "\"%EXE%\"" /f >nul
+reg add "%KEY%\Background\shell\WezTerm\command" /ve /d "\"%EXE%\" start --cwd \"%%V\"" /f >nul
+goto :eof
+
+:uninstall
+reg delete "%KEY%\shell\WezTerm" /f >nul
+reg delete "%KEY%\Background\shell\WezTerm" /f >nul
@@ -30,6 +30,7 @@ case $OSTYPE in
     rm -rf $zipdir $zipdir.zip
     mkdir $zipdir
     cp target/release/wezterm.exe target/release/wezterm.pdb $zipdir
+    cp assets/windows/explorer-context-menu.cmd $zipdir
     7z a -tzip $zipdir.zip $zipdir
     ;;
   linux-gnu)
@@ -37,6 +38,9 @@ case $OSTYPE in
       Ubuntu*|Debian*)
         rm -rf pkg
         mkdir -p pkg/debian/usr/bin pkg/debian/DEBIAN
+        mkdir -p pkg/debian/usr/share/applications
+        mkdir -p pkg/debian/usr/share/icons/hicolor/128x128/apps
+        mkdir -p pkg/debian/usr/share/terminfo
         cat > pkg/debian/DEBIAN/control <<EOF
 Package: wezterm
 Version: ${TAG_NAME}
@@ -52,6 +56,9 @@ Description: Wez's Terminal Emulator.
 Depends: libc6, libegl-mesa0, libxcb-icccm4, libxcb-ewmh2, libxcb-keysyms1, libxcb-xkb1, libxkbcommon0, libxkbcommon-x11-0, libfontconfig1, xdg-utils, libxcb-render0, libxcb-shape0, libx11-6, libegl1
 EOF
         cp target/release/wezterm pkg/debian/usr/bin
+        cp assets/linux/*.desktop pkg/debian/usr/share/applications
+        cp assets/icon/terminal.png pkg/debian/usr/share/icons/hicolor/128x128/apps/wezterm.png
+        tic -x -o pkg/debian/usr/share/terminfo assets/terminfo/wezterm.terminfo
         fakeroot dpkg-deb --build pkg/debian wezterm-$TAG_NAME.deb
         rm -rf pkg
       ;;
@@ -9,6 +9,8 @@ case $OSTYPE in
     cp -r assets/macos/WezTerm.app $APP && \
       cp target/release/wezterm $APP && \
         echo "Installed to $APP"
+    tic -x -o $HOME/.terminfo assets/terminfo/wezterm.terminfo && \
+      echo "Installed the wezterm terminfo entry to $HOME/.terminfo"
     ;;
   *)
     echo "Don't know how to install the app on this system"
@@ -2,6 +2,9 @@ use boxfnonce::SendBoxFnOnce;
 use failure::Error;
 use std::sync::{Arc, Condvar, Mutex};
 
+pub mod task;
+pub mod timer;
+
 type NextFunc<T> = SendBoxFnOnce<'static, (Result<T, Error>,)>;
 pub type SpawnFunc = SendBoxFnOnce<'static, ()>;
 
@@ -0,0 +1,160 @@
+//! A small API for spawning work onto the gui thread or onto a
+//! background thread pool.  Spawned tasks return a `Task` that can
+//! be waited upon or chained like a `Future`, and that can be
+//! cancelled if it has not yet started to run.
+use crate::{Executor, Future, RayonExecutor};
+use failure::{err_msg, Error};
+use lazy_static::lazy_static;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+lazy_static! {
+    static ref GUI_EXECUTOR: Mutex<Option<Box<Executor>>> = Mutex::new(None);
+}
+
+/// Register the executor that runs functions on the gui thread.
+/// This is called by the frontend when it is initialized.
+pub fn set_gui_executor(executor: Box<Executor>) {
+    *GUI_EXECUTOR.lock().unwrap() = Some(executor);
+}
+
+/// Returns the executor that runs functions on the gui thread,
+/// if one has been registered
+pub fn gui_executor() -> Option<Box<Executor>> {
+    GUI_EXECUTOR
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|executor| executor.clone_executor())
+}
+
+/// A handle to a spawned function and its eventual result
+pub struct Task<T> {
+    future: Future<T>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl<T: Send + 'static> Task<T> {
+    /// Prevent the task from running if it has not yet started.
+    /// Its result will be an error.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Blocks until the task has completed
+    pub fn wait(self) -> Result<T, Error> {
+        self.future.wait()
+    }
+
+    /// Converts the task into a future that can be chained
+    pub fn into_future(self) -> Future<T> {
+        self.future
+    }
+}
+
+/// Spawn `f` via the provided executor
+pub fn spawn_with<T, F, IF, EXEC>(executor: EXEC, f: F) -> Task<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> IF,
+    IF: Into<Future<T>>,
+    IF: 'static,
+    F: Send + 'static,
+    EXEC: Executor + Send + 'static,
+{
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let flag = Arc::clone(&cancelled);
+    let future = Future::with_executor(executor, move || -> Future<T> {
+        if flag.load(Ordering::SeqCst) {
+            Future::err(err_msg("task was cancelled"))
+        } else {
+            f().into()
+        }
+    });
+    Task { future, cancelled }
+}
+
+/// Spawn `f` to run on the gui thread.  The task fails if no gui
+/// executor has been registered.
+pub fn spawn_into_gui<T, F, IF>(f: F) -> Task<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> IF,
+    IF: Into<Future<T>>,
+    IF: 'static,
+    F: Send + 'static,
+{
+    match gui_executor() {
+        Some(executor) => spawn_with(executor, f),
+        None => Task {
+            future: Future::err(err_msg("no gui executor has been registered")),
+            cancelled: Arc::new(AtomicBool::new(false)),
+        },
+    }
+}
+
+/// Spawn `f` to run in the background thread pool
+pub fn spawn_background<T, F, IF>(f: F) -> Task<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> IF,
+    IF: Into<Future<T>>,
+    IF: 'static,
+    F: Send + 'static,
+{
+    spawn_with(RayonExecutor::new(), f)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::SpawnFunc;
+    use std::sync::mpsc::{channel, Receiver, Sender};
+
+    /// Queues functions until the test decides to run them
+    struct QueueExecutor {
+        tx: Sender<SpawnFunc>,
+    }
+
+    impl Executor for QueueExecutor {
+        fn execute(&self, f: SpawnFunc) {
+            self.tx.send(f).unwrap();
+        }
+        fn clone_executor(&self) -> Box<Executor> {
+            Box::new(QueueExecutor {
+                tx: self.tx.clone(),
+            })
+        }
+    }
+
+    fn queue() -> (QueueExecutor, Receiver<SpawnFunc>) {
+        let (tx, rx) = channel();
+        (QueueExecutor { tx }, rx)
+    }
+
+    #[test]
+    fn background() {
+        let task = spawn_background(|| Ok(21 * 2));
+        assert_eq!(task.wait().unwrap(), 42);
+    }
+
+    #[test]
+    fn cancel_before_run() {
+        let (executor, rx) = queue();
+        let task = spawn_with(executor, || Ok(true));
+        task.cancel();
+        rx.recv().unwrap().call();
+        assert_eq!(
+            format!("{}", task.wait().unwrap_err()),
+            "task was cancelled"
+        );
+    }
+
+    #[test]
+    fn run_queued() {
+        let (executor, rx) = queue();
+        let task = spawn_with(executor, || Ok("done"));
+        rx.recv().unwrap().call();
+        assert_eq!(task.wait().unwrap(), "done");
+    }
+}
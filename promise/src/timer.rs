@@ -0,0 +1,310 @@
+//! Timers that run a function via an `Executor` once a deadline has
+//! passed.  All timers are serviced by a single background thread,
+//! which is cheaper than spawning a thread that sleeps for each delay
+//! and allows pending timers to be cancelled.
+use crate::{Executor, SpawnFunc};
+use lazy_static::lazy_static;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::atomic::{self, AtomicBool};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Identifies a scheduled timer so that it can be cancelled.
+/// Dropping the handle does not cancel the timer.
+#[derive(Clone)]
+pub struct TimerHandle {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl TimerHandle {
+    /// Prevent the timer from firing.  A repeating timer will not
+    /// fire again, even if an invocation has already been queued
+    /// to its executor.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, atomic::Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(atomic::Ordering::SeqCst)
+    }
+}
+
+type RepeatingFunc = Arc<Mutex<Box<FnMut() + Send>>>;
+
+enum Callback {
+    Once(SpawnFunc),
+    Repeating {
+        interval: Duration,
+        func: RepeatingFunc,
+    },
+}
+
+struct Entry {
+    deadline: Instant,
+    /// Breaks ties between entries with the same deadline so that
+    /// they fire in the order that they were scheduled
+    seq: u64,
+    executor: Box<Executor>,
+    callback: Callback,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.deadline == other.deadline && self.seq == other.seq
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    /// BinaryHeap is a max-heap, so the comparison is reversed in
+    /// order to keep the earliest deadline at the top
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.deadline, other.seq).cmp(&(self.deadline, self.seq))
+    }
+}
+
+struct QueueState {
+    heap: BinaryHeap<Entry>,
+    next_seq: u64,
+}
+
+struct TimerQueue {
+    state: Mutex<QueueState>,
+    cond: Condvar,
+}
+
+lazy_static! {
+    static ref QUEUE: Arc<TimerQueue> = TimerQueue::start();
+}
+
+impl TimerQueue {
+    fn start() -> Arc<Self> {
+        let queue = Arc::new(Self {
+            state: Mutex::new(QueueState {
+                heap: BinaryHeap::new(),
+                next_seq: 0,
+            }),
+            cond: Condvar::new(),
+        });
+        let runner = Arc::clone(&queue);
+        std::thread::spawn(move || runner.run());
+        queue
+    }
+
+    fn push(&self, mut entry: Entry) {
+        let mut state = self.state.lock().unwrap();
+        entry.seq = state.next_seq;
+        state.next_seq += 1;
+        state.heap.push(entry);
+        self.cond.notify_one();
+    }
+
+    fn add(&self, deadline: Instant, executor: Box<Executor>, callback: Callback) -> TimerHandle {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.push(Entry {
+            deadline,
+            seq: 0,
+            executor,
+            callback,
+            cancelled: Arc::clone(&cancelled),
+        });
+        TimerHandle { cancelled }
+    }
+
+    /// Waits for and removes the next entry that is due
+    fn next_due(&self) -> Entry {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            match state.heap.peek().map(|entry| entry.deadline) {
+                None => state = self.cond.wait(state).unwrap(),
+                Some(deadline) if deadline > now => {
+                    state = self.cond.wait_timeout(state, deadline - now).unwrap().0;
+                }
+                Some(_) => return state.heap.pop().unwrap(),
+            }
+        }
+    }
+
+    fn run(&self) {
+        loop {
+            // The lock is not held while dispatching; an executor may
+            // block, and that must not prevent timers from being added.
+            let entry = self.next_due();
+            if entry.cancelled.load(atomic::Ordering::SeqCst) {
+                continue;
+            }
+            match entry.callback {
+                Callback::Once(func) => entry.executor.execute(func),
+                Callback::Repeating { interval, func } => {
+                    let cancelled = Arc::clone(&entry.cancelled);
+                    let invoke = Arc::clone(&func);
+                    entry.executor.execute(SpawnFunc::from(move || {
+                        if !cancelled.load(atomic::Ordering::SeqCst) {
+                            let mut func = invoke.lock().unwrap();
+                            (&mut **func)();
+                        }
+                    }));
+                    // If we fell behind, don't try to catch up by
+                    // firing repeatedly
+                    let deadline = (entry.deadline + interval).max(Instant::now());
+                    self.push(Entry {
+                        deadline,
+                        seq: 0,
+                        executor: entry.executor,
+                        callback: Callback::Repeating { interval, func },
+                        cancelled: entry.cancelled,
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Arrange for `func` to be called via `executor` once `delay`
+/// has elapsed
+pub fn schedule_timer<F>(executor: Box<Executor>, delay: Duration, func: F) -> TimerHandle
+where
+    F: FnOnce() + Send + 'static,
+{
+    QUEUE.add(
+        Instant::now() + delay,
+        executor,
+        Callback::Once(SpawnFunc::from(func)),
+    )
+}
+
+/// Arrange for `func` to be called via `executor` every `interval`
+/// until the returned handle is cancelled
+pub fn schedule_repeating_timer<F>(
+    executor: Box<Executor>,
+    interval: Duration,
+    func: F,
+) -> TimerHandle
+where
+    F: FnMut() + Send + 'static,
+{
+    QUEUE.add(
+        Instant::now() + interval,
+        executor,
+        Callback::Repeating {
+            interval,
+            func: Arc::new(Mutex::new(Box::new(func))),
+        },
+    )
+}
+
+/// Coalesces requests to be called back so that at most one timer
+/// is outstanding.  Requesting a deadline that is later than the
+/// pending one has no effect, while an earlier deadline replaces it.
+#[derive(Default)]
+pub struct CoalescingTimer {
+    pending: Option<(Instant, TimerHandle)>,
+}
+
+impl CoalescingTimer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn schedule<F>(&mut self, executor: Box<Executor>, delay: Duration, func: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let now = Instant::now();
+        let deadline = now + delay;
+        if let Some((pending, handle)) = self.pending.take() {
+            if pending > now && pending <= deadline && !handle.is_cancelled() {
+                self.pending = Some((pending, handle));
+                return;
+            }
+            handle.cancel();
+        }
+        let handle = schedule_timer(executor, delay, func);
+        self.pending = Some((deadline, handle));
+    }
+
+    pub fn cancel(&mut self) {
+        if let Some((_, handle)) = self.pending.take() {
+            handle.cancel();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::RayonExecutor;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn one_shot() {
+        let (tx, rx) = channel();
+        schedule_timer(
+            Box::new(RayonExecutor::new()),
+            Duration::from_millis(10),
+            move || tx.send(1).unwrap(),
+        );
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap(), 1);
+    }
+
+    #[test]
+    fn cancel() {
+        let (tx, rx) = channel();
+        let cancelled_tx = tx.clone();
+        let handle = schedule_timer(
+            Box::new(RayonExecutor::new()),
+            Duration::from_millis(10),
+            move || cancelled_tx.send("cancelled").unwrap(),
+        );
+        handle.cancel();
+        schedule_timer(
+            Box::new(RayonExecutor::new()),
+            Duration::from_millis(50),
+            move || tx.send("fired").unwrap(),
+        );
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap(), "fired");
+    }
+
+    #[test]
+    fn repeating() {
+        let (tx, rx) = channel();
+        let mut count = 0;
+        let handle = schedule_repeating_timer(
+            Box::new(RayonExecutor::new()),
+            Duration::from_millis(5),
+            move || {
+                count += 1;
+                tx.send(count).ok();
+            },
+        );
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap(), 1);
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap(), 2);
+        handle.cancel();
+    }
+
+    #[test]
+    fn coalesce() {
+        let (tx, rx) = channel();
+        let mut timer = CoalescingTimer::new();
+        for i in 0..3 {
+            let tx = tx.clone();
+            timer.schedule(
+                Box::new(RayonExecutor::new()),
+                Duration::from_millis(20 + i),
+                move || tx.send(i).unwrap(),
+            );
+        }
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap(), 0);
+        assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+}
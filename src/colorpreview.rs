@@ -0,0 +1,127 @@
+//! Renders a preview of a color scheme to the terminal in which
+//! wezterm was invoked, so that schemes can be compared without
+//! having to edit the configuration and start a new window each time.
+use crate::config::{Config, Palette};
+use failure::Error;
+use std::io::Read;
+use std::path::Path;
+use term::color::{ColorPalette, RgbColor};
+use termwiz::caps::{Capabilities, ColorLevel};
+use termwiz::cell::{AttributeChange, CellAttributes};
+use termwiz::color::ColorAttribute;
+use termwiz::surface::Change;
+use termwiz::terminal::{new_terminal, Terminal};
+
+const NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+/// Load a scheme from a TOML file that holds the same keys as the
+/// `[colors]` section of the configuration
+pub fn load_scheme(path: &Path) -> Result<ColorPalette, Error> {
+    let mut s = String::new();
+    std::fs::File::open(path)
+        .map_err(|e| format_err!("Error opening {}: {}", path.display(), e))?
+        .read_to_string(&mut s)?;
+    let palette: Palette = toml::from_str(&s)
+        .map_err(|e| format_err!("Error parsing TOML from {}: {:?}", path.display(), e))?;
+    Ok(palette.into())
+}
+
+/// Returns the palette that the gui would use for `config`
+pub fn configured_scheme(config: &Config) -> ColorPalette {
+    config
+        .colors
+        .as_ref()
+        .map(|colors| colors.clone().into())
+        .unwrap_or_else(ColorPalette::default)
+}
+
+fn fg(color: RgbColor) -> Change {
+    Change::Attribute(AttributeChange::Foreground(
+        ColorAttribute::TrueColorWithDefaultFallback(color),
+    ))
+}
+
+fn bg(color: RgbColor) -> Change {
+    Change::Attribute(AttributeChange::Background(
+        ColorAttribute::TrueColorWithDefaultFallback(color),
+    ))
+}
+
+fn reset() -> Change {
+    Change::AllAttributes(CellAttributes::default())
+}
+
+fn text(s: &str) -> Change {
+    Change::Text(s.to_owned())
+}
+
+fn build_preview(palette: &ColorPalette) -> Vec<Change> {
+    let mut changes = vec![];
+
+    // Each ANSI color as a swatch, as text on the default background
+    // and as the default text on it as a background
+    for idx in 0..16 {
+        let color = palette.colors.0[idx];
+        let name = format!(
+            "{:>2} {:<14}",
+            idx,
+            if idx < 8 {
+                NAMES[idx].to_owned()
+            } else {
+                format!("bright {}", NAMES[idx - 8])
+            }
+        );
+        changes.push(text(&format!("{} {} ", name, color.to_rgb_string())));
+        changes.push(bg(color));
+        changes.push(text("      "));
+        changes.push(reset());
+        changes.push(text(" "));
+        changes.push(bg(palette.background));
+        changes.push(fg(color));
+        changes.push(text(" sample text "));
+        changes.push(bg(color));
+        changes.push(fg(palette.foreground));
+        changes.push(text(" sample text "));
+        changes.push(reset());
+        changes.push(text("\r\n"));
+    }
+    changes.push(text("\r\n"));
+
+    let mut sample = |label: &str, fg_color: RgbColor, bg_color: RgbColor| {
+        changes.push(text(&format!(
+            "{:<10} {} on {} ",
+            label,
+            fg_color.to_rgb_string(),
+            bg_color.to_rgb_string()
+        )));
+        changes.push(bg(bg_color));
+        changes.push(fg(fg_color));
+        changes.push(text(" The quick brown fox "));
+        changes.push(reset());
+        changes.push(text("\r\n"));
+    };
+    sample("default", palette.foreground, palette.background);
+    sample("cursor", palette.cursor_fg, palette.cursor_bg);
+    sample("selection", palette.selection_fg, palette.selection_bg);
+    sample("scrollbar", palette.foreground, palette.scrollbar_thumb);
+
+    changes
+}
+
+/// Write the preview of `palette` to the controlling terminal
+pub fn show_preview(palette: &ColorPalette) -> Result<(), Error> {
+    let caps = Capabilities::new_from_env()?;
+    if caps.color_level() != ColorLevel::TrueColor {
+        eprintln!(
+            "This terminal doesn't advertise true color support; \
+             the preview may not be accurate.  Setting COLORTERM=truecolor \
+             will override the detection."
+        );
+    }
+    let mut terminal = new_terminal(caps)?;
+    terminal.render(&build_preview(palette))?;
+    terminal.flush()?;
+    Ok(())
+}
@@ -3,16 +3,19 @@
 use crate::font::FontSystemSelection;
 use crate::frontend::FrontEndSelection;
 use crate::get_shell;
+use crate::keyassignment::{Key, SpawnCommand};
+use crate::keyremap::KeyRemap;
 use crate::pty::CommandBuilder;
 use crate::pty::PtySystemSelection;
 use failure::{err_msg, Error};
 use lazy_static::lazy_static;
 use serde_derive::*;
 use std;
+use std::collections::HashMap;
 use std::ffi::OsStr;
 use std::fs;
 use std::io::prelude::*;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use term;
 use term::color::RgbColor;
 use termwiz::hyperlink;
@@ -28,6 +31,24 @@ pub struct Config {
     #[serde(default = "default_dpi")]
     pub dpi: f64,
 
+    /// When set, windows use this scale factor for their fonts in place
+    /// of the one that the windowing system reports for the monitor
+    /// they are on.  This is useful when the reported value is wrong,
+    /// such as on X11 systems whose RandR DPI is misconfigured, or when
+    /// multiple monitors confuse the detection.  It can also be set for
+    /// a single invocation with `wezterm start --dpi-scale`.
+    #[serde(default)]
+    pub dpi_scale: Option<f64>,
+
+    /// Extra space, in pixels, to add to the width and the height of
+    /// each cell over what the metrics of the font call for.  Negative
+    /// values tighten the spacing.  The text is centered within the
+    /// extra height and is aligned to the left of the extra width.
+    #[serde(default)]
+    pub cell_spacing_x: f64,
+    #[serde(default)]
+    pub cell_spacing_y: f64,
+
     /// The baseline font to use
     #[serde(default)]
     pub font: TextStyle,
@@ -37,11 +58,27 @@ pub struct Config {
     #[serde(default)]
     pub font_rules: Vec<StyleRule>,
 
+    /// Codepoints that should be drawn as a different codepoint, or
+    /// that should always be drawn using a particular font.  These
+    /// are applied to the text before it is shaped.
+    ///
+    /// ```toml
+    /// [[glyph_substitutions]]
+    /// first = "\u2500"
+    /// last = "\u257f"
+    /// font = 1
+    /// ```
+    #[serde(default)]
+    pub glyph_substitutions: Vec<GlyphSubstitution>,
+
     /// The color palette
     pub colors: Option<Palette>,
 
-    /// How many lines of scrollback you want to retain
-    pub scrollback_lines: Option<usize>,
+    /// How many lines of scrollback you want to retain.
+    /// Lines that are further back than the most recent 100 are stored
+    /// in a compact form, so large values are affordable.
+    #[serde(default = "default_scrollback_lines")]
+    pub scrollback_lines: usize,
 
     /// If no `prog` is specified on the command line, use this
     /// instead of running the user's shell.
@@ -60,10 +97,19 @@ pub struct Config {
     #[serde(default = "default_hyperlink_rules")]
     pub hyperlink_rules: Vec<hyperlink::Rule>,
 
-    /// What to set the TERM variable to
+    /// What to set the TERM variable to.  `wezterm` advertises all of
+    /// the capabilities of the terminal, but requires that its terminfo
+    /// entry is installed; `wezterm terminfo --install` takes care of
+    /// that.  The default is `wezterm` if the entry is installed and
+    /// `xterm-256color` otherwise, which is also used in place of
+    /// `wezterm` for as long as the entry isn't installed.
     #[serde(default = "default_term")]
     pub term: String,
 
+    /// What to set the COLORTERM variable to, such as `truecolor`.
+    /// It isn't set unless this is specified.
+    pub colorterm: Option<String>,
+
     #[serde(default)]
     pub font_system: FontSystemSelection,
 
@@ -73,9 +119,569 @@ pub struct Config {
     #[serde(default)]
     pub pty: PtySystemSelection,
 
+    /// When true, each tab is registered in utmp and wtmp so that it
+    /// is listed by `who` and `w` and receives `wall` messages, as
+    /// xterm does.  This requires libutempter, and is only supported
+    /// on unix systems.
+    #[serde(default)]
+    pub register_utmp: bool,
+
     /// When using the MuxServer, this specifies the path to the unix
     /// domain socket to use to communicate with the mux server.
     pub mux_server_unix_domain_socket_path: Option<String>,
+
+    /// The gui listens on this unix domain socket so that
+    /// `wezterm start --cwd DIR` can open a tab in the running
+    /// instance rather than starting another one.
+    pub single_instance_socket_path: Option<String>,
+
+    /// Additional key assignments; these take precedence over
+    /// the default key assignments.
+    #[serde(default)]
+    pub keys: Vec<Key>,
+
+    /// Rewrites keys before they are looked up in `keys` or sent to
+    /// the terminal, for example to make CapsLock act as CTRL or to
+    /// have F13-F24 send other keys.  This only affects wezterm; note
+    /// that the system still toggles its caps lock state when
+    /// CapsLock is pressed.
+    #[serde(default)]
+    pub key_remaps: Vec<KeyRemap>,
+
+    /// When true (the default), text copied within wezterm is
+    /// remembered so that it can be recalled later via the
+    /// `ShowClipboardHistory` key assignment.  Set this to false
+    /// to avoid retaining copied text in memory.
+    #[serde(default = "default_true")]
+    pub enable_clipboard_history: bool,
+
+    /// The maximum number of entries retained in the clipboard history
+    #[serde(default = "default_clipboard_history_size")]
+    pub clipboard_history_size: usize,
+
+    /// A list of transformations that are applied, in order, to
+    /// the clipboard contents before they are pasted.
+    /// For example, to avoid accidentally running a command that
+    /// was copied along with its trailing newline:
+    ///
+    /// ```toml
+    /// paste_transforms = ["CrlfToLf", "StripTrailingNewline"]
+    /// ```
+    #[serde(default)]
+    pub paste_transforms: Vec<PasteTransform>,
+
+    /// When true, show a scrollbar on the right edge of the window
+    /// while the viewport is scrolled back into the scrollback.
+    /// The scrollbar is hidden again when the viewport returns to
+    /// the bottom.
+    #[serde(default)]
+    pub enable_scroll_bar: bool,
+
+    /// Columns, counting from 1, that are marked by a translucent
+    /// guide, in the manner of vim's `colorcolumn`; for example
+    /// `column_guides = [80, 100]`
+    #[serde(default)]
+    pub column_guides: Vec<usize>,
+
+    /// When true, the row and the column that hold the cursor are
+    /// marked by translucent guides
+    #[serde(default)]
+    pub cursor_crosshair: bool,
+
+    /// The minimum contrast ratio to maintain between the foreground
+    /// and background colors of text, using the WCAG definition of
+    /// contrast ratio, which ranges from 1.0 (no contrast) to 21.0
+    /// (black on white).  When the resolved colors of a cell fall
+    /// below this ratio the foreground color is lightened or darkened
+    /// until it is met.  The default of 1.0 disables the adjustment;
+    /// 4.5 is a good value to use if programs emit dark blue text
+    /// on a black background.
+    #[serde(default = "default_min_contrast")]
+    pub min_contrast: f32,
+
+    /// Scales the hue, saturation and brightness of the foreground
+    /// color of text before `min_contrast` is applied.  Each of the
+    /// components is a multiplier that defaults to 1.0; this example
+    /// makes text 50% brighter:
+    ///
+    /// ```toml
+    /// [foreground_text_hsb]
+    /// brightness = 1.5
+    /// ```
+    #[serde(default)]
+    pub foreground_text_hsb: HsbTransform,
+
+    /// Whether to render with a high contrast palette, thicker
+    /// underlines and a `min_contrast` of at least 7.0.  By default
+    /// this follows the high contrast accessibility setting of the
+    /// operating system.
+    #[serde(default)]
+    pub high_contrast: HighContrast,
+
+    /// Large pastes are sent to the terminal in chunks of this many
+    /// bytes, so that they don't overwhelm the receiving program.
+    /// While such a paste is in progress, its progress is shown in
+    /// the window title and pressing Escape cancels the remainder.
+    #[serde(default = "default_paste_chunk_size")]
+    pub paste_chunk_size: usize,
+
+    /// How long to wait between sending chunks of a large paste,
+    /// measured in milliseconds.  Increasing this can help to avoid
+    /// overwhelming slow remote shells.
+    #[serde(default)]
+    pub paste_chunk_delay_ms: u64,
+
+    /// When set, text that is copied by selecting it is stored in
+    /// the named internal register rather than the system clipboard.
+    /// This keeps sensitive selections out of the system clipboard;
+    /// the register can be pasted via the `PasteFrom` key assignment:
+    ///
+    /// ```toml
+    /// copy_register = "yank"
+    ///
+    /// [[keys]]
+    /// key = "y"
+    /// mods = "CTRL|SHIFT"
+    /// action = {PasteFrom = "yank"}
+    /// ```
+    pub copy_register: Option<String>,
+
+    /// When true (the default), text that is both bold and set to one
+    /// of the 8 basic ANSI colors is rendered using the bright version
+    /// of that color.  Set this to false if your color scheme relies on
+    /// the bold font weight alone to distinguish bold text.
+    /// Text explicitly set to one of the bright colors (eg: via SGR 90-97)
+    /// is unaffected by this option.
+    #[serde(default = "default_true")]
+    pub bold_brightens_ansi_colors: bool,
+
+    /// Controls how text with the blink attribute is displayed.
+    /// Valid values are "Blink" (the default), "Steady" to show the
+    /// text without blinking, and "Italic" to show the text in italics
+    /// rather than blinking it.
+    #[serde(default)]
+    pub text_blink: TextBlink,
+
+    /// How long, in milliseconds, text with the slow blink attribute
+    /// is shown and then hidden for
+    #[serde(default = "default_text_blink_rate")]
+    pub text_blink_rate_ms: u64,
+
+    /// How long, in milliseconds, text with the rapid blink attribute
+    /// is shown and then hidden for
+    #[serde(default = "default_text_blink_rate_rapid")]
+    pub text_blink_rate_rapid_ms: u64,
+
+    /// When the selected font doesn't have a bold or italic face,
+    /// wezterm will synthesize one by emboldening or slanting the
+    /// glyphs of the regular face so that the style remains visible.
+    /// Set this to false to render using the regular face instead.
+    #[serde(default = "default_true")]
+    pub font_synthesis: bool,
+
+    /// When true (the default), escape sequences that wezterm doesn't
+    /// understand are logged to stderr.  They are always counted and
+    /// the most recent are available in the tab inspector and via
+    /// `wezterm cli unhandled-escapes`.
+    #[serde(default = "default_true")]
+    pub log_unhandled_escapes: bool,
+
+    /// When scrolled back, typing or pasting into a tab returns
+    /// it to the bottom of the scrollback
+    #[serde(default = "default_true")]
+    pub scroll_to_bottom_on_input: bool,
+
+    /// When scrolled back, new output from the program in a tab
+    /// returns it to the bottom of the scrollback.  This makes it
+    /// harder to read the history of a busy program.
+    #[serde(default)]
+    pub scroll_to_bottom_on_output: bool,
+
+    /// How many Up or Down arrow key presses to send for each tick of
+    /// the mouse wheel when the alternate screen is active and the
+    /// application hasn't enabled mouse reporting.  This makes the
+    /// wheel scroll programs such as `less`, `man` and `vim`.
+    /// Set to 0 to disable this translation.
+    #[serde(default = "default_alt_screen_wheel_scroll_lines")]
+    pub alt_screen_wheel_scroll_lines: usize,
+
+    /// How the terminal identifies itself to programs that ask: the
+    /// parameters of the responses to primary (`CSI c`) and secondary
+    /// (`CSI > c`) device attributes requests, and the name and version
+    /// reported in response to XTVERSION (`CSI > q`).  The defaults
+    /// describe a VT220 with ANSI color.
+    #[serde(default = "default_primary_device_attributes")]
+    pub primary_device_attributes: String,
+    #[serde(default = "default_secondary_device_attributes")]
+    pub secondary_device_attributes: String,
+    #[serde(default = "default_terminal_name_and_version")]
+    pub terminal_name_and_version: String,
+
+    /// When true, keys pressed with modifiers are always reported using
+    /// the `CSI u` encoding, so that programs can tell Ctrl-I from Tab
+    /// and Ctrl-Shift-A from Ctrl-A.  Otherwise modified keys are only
+    /// reported distinctly when a program enables xterm's
+    /// `modifyOtherKeys`.  Programs that don't understand the
+    /// encoding will misbehave with this enabled.
+    #[serde(default)]
+    pub enable_csi_u_key_encoding: bool,
+
+    /// When true, SGR 21 turns off bold, as it does on the Linux console
+    /// and some older terminals.  Otherwise it selects a double
+    /// underline, as specified by ECMA-48 and implemented by xterm.
+    #[serde(default)]
+    pub sgr_21_is_bold_off: bool,
+
+    /// When true, text that a program concealed with SGR 8 is included
+    /// when the selection is copied, even though it isn't shown.
+    /// Otherwise it is copied as spaces unless it has been revealed
+    /// with the `ToggleRevealConcealed` key assignment.
+    #[serde(default)]
+    pub copy_concealed_text: bool,
+
+    /// When true, pasted and typed text, including the text produced
+    /// by `key_remaps`, is converted to Unicode Normalization Form C
+    /// before it is sent to the program in the tab.  Text from macOS
+    /// often has accents as separate combining marks, which programs
+    /// may not match against the precomposed forms.
+    #[serde(default)]
+    pub normalize_input_to_nfc: bool,
+
+    /// When true, text printed by programs is converted to Unicode
+    /// Normalization Form C before it is displayed, so that decomposed
+    /// sequences are rendered, searched and copied as their
+    /// precomposed equivalents.
+    #[serde(default)]
+    pub normalize_output_to_nfc: bool,
+
+    /// The maximum number of times per second that a window is
+    /// repainted in response to output from its tab.  Output that
+    /// arrives more quickly than this is coalesced into a single frame.
+    #[serde(default = "default_max_fps")]
+    pub max_fps: u8,
+
+    /// When true, the time taken for key presses to travel to the
+    /// program in a tab and for its echo to be parsed and painted is
+    /// measured.  The measurements are shown as histograms in the
+    /// tab inspector.
+    #[serde(default)]
+    pub input_latency_instrumentation: bool,
+
+    /// When true, lines of output that are rapidly rewritten, such as
+    /// spinners and progress bars, are only announced to screen readers
+    /// once they have stopped changing for `screen_reader_coalesce_ms`
+    #[serde(default)]
+    pub screen_reader_filter: bool,
+    #[serde(default = "default_screen_reader_coalesce_ms")]
+    pub screen_reader_coalesce_ms: u64,
+
+    /// The regular expressions that quick select mode uses to find
+    /// text worth copying on the screen.  Setting this replaces the
+    /// defaults, which match URLs, paths, hashes, IP addresses and
+    /// long numbers.
+    #[serde(default = "default_quick_select_patterns")]
+    pub quick_select_patterns: Vec<String>,
+
+    /// Programs that can be spawned into a new tab from the launcher,
+    /// or via the `SpawnLaunchMenuItem` key assignment:
+    ///
+    /// ```toml
+    /// [[launch_menu]]
+    /// label = "top"
+    /// args = ["top"]
+    ///
+    /// [[launch_menu]]
+    /// label = "ssh prod"
+    /// args = ["ssh", "prod.example.com"]
+    /// term = "xterm-256color"
+    /// ```
+    #[serde(default)]
+    pub launch_menu: Vec<LaunchMenuItem>,
+
+    /// The program used to open hyperlinks that are clicked, in the
+    /// same form as `default_prog`.  The uri is passed as an additional
+    /// final argument.  If not set, the system default handler is used.
+    pub hyperlink_open_command: Option<Vec<String>>,
+
+    /// Programs used to open hyperlinks with particular schemes, which
+    /// take precedence over `hyperlink_open_command`:
+    ///
+    /// ```toml
+    /// [hyperlink_scheme_commands]
+    /// mailto = ["mutt"]
+    /// scp = ["my-remote-editor"]
+    /// ```
+    #[serde(default)]
+    pub hyperlink_scheme_commands: HashMap<String, Vec<String>>,
+
+    /// When true (the default), links to files are resolved before
+    /// they are opened: relative paths, such as those matched by the
+    /// `hyperlink_rules`, are taken to be relative to the working
+    /// directory that the program reported via OSC 7, and files on
+    /// another host are opened as `scp://host/path` urls, which can
+    /// be directed to a suitable program via `hyperlink_scheme_commands`
+    #[serde(default = "default_true")]
+    pub hyperlink_resolve_paths: bool,
+
+    /// What happens when the bell is rung by the active tab of the
+    /// window that has the keyboard focus
+    #[serde(default = "default_bell_focused")]
+    pub bell_focused: BellAction,
+    /// What happens when the bell is rung by any other tab
+    #[serde(default)]
+    pub bell_unfocused: BellAction,
+    /// A tab that rings the bell again within this many milliseconds
+    /// of the last bell that took effect is ignored, so that programs
+    /// that ring it constantly don't flood the display
+    #[serde(default = "default_bell_debounce_ms")]
+    pub bell_debounce_ms: u64,
+
+    /// What happens to a tab when its process exits.  `wezterm start
+    /// --hold` selects `Hold` for the tab that it starts.
+    #[serde(default)]
+    pub exit_behavior: ExitBehavior,
+
+    /// When true (the default), a notification is shown when the
+    /// process in a tab exits unsuccessfully, so that the reason
+    /// that the tab closed can be seen
+    #[serde(default = "default_true")]
+    pub notify_on_failed_exit: bool,
+
+    /// What happens when the program of a new tab can't be spawned,
+    /// such as when `default_prog` names a program that isn't installed.
+    /// A program given on the command line, as in
+    /// `wezterm start -- command`, is never replaced by the fallback;
+    /// wezterm exits with an error if it can't be spawned.
+    #[serde(default)]
+    pub spawn_failure: SpawnFailure,
+
+    /// The program that is run in place of one that can't be spawned
+    /// when `spawn_failure` is `RunFallbackProg`.  Defaults to
+    /// `/bin/sh`, or `cmd.exe` on Windows.
+    #[serde(default = "default_fallback_prog")]
+    pub fallback_prog: Vec<String>,
+
+    /// Files that programs send to the terminal to be downloaded, as
+    /// `it2dl` does, are saved into this directory once the user has
+    /// agreed to accept them.  Defaults to the user's download directory.
+    pub download_dir: Option<String>,
+
+    /// What happens when a program starts a ZMODEM file transfer by
+    /// running `sz` or `rz`, whose data would otherwise be displayed
+    /// as garbage
+    #[serde(default)]
+    pub zmodem_policy: ZmodemPolicy,
+
+    /// When set, the session is considered idle once there has been
+    /// no key press or mouse activity in any window for this many
+    /// minutes, at which point the `idle_actions` are carried out
+    pub idle_timeout_minutes: Option<u64>,
+
+    /// What happens when the session becomes idle.  Blanked tabs are
+    /// shown again once there is activity:
+    ///
+    /// ```toml
+    /// idle_timeout_minutes = 10
+    /// idle_actions = ["BlankSensitiveTabs", {RunCommand = ["my-recorder", "--toggle"]}]
+    /// ```
+    #[serde(default)]
+    pub idle_actions: Vec<IdleAction>,
+
+    /// What happens when the operating system locks the session.
+    /// Locking is detected via logind on Linux, which requires `gdbus`
+    /// to receive its signals, and is also detected on Windows; it
+    /// isn't detected on other systems.
+    #[serde(default)]
+    pub lock_actions: Vec<IdleAction>,
+
+    /// The names of the programs whose tabs are blanked by the
+    /// `BlankSensitiveTabs` action when they are running in the
+    /// foreground of the tab
+    #[serde(default = "default_sensitive_processes")]
+    pub sensitive_processes: Vec<String>,
+}
+
+/// An entry in the `launch_menu`
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct LaunchMenuItem {
+    /// The text shown in the launcher
+    pub label: String,
+    /// The program to run and its arguments, in the same form as
+    /// `default_prog`.  The default program is run if omitted.
+    pub args: Option<Vec<String>>,
+    /// The directory in which to start the program.  If omitted, it
+    /// starts in the current directory of wezterm.
+    pub cwd: Option<String>,
+    /// Overrides `term` for this program.  This is useful when it
+    /// connects to a remote host that lacks the terminfo entry that
+    /// is used locally, in which case `xterm-256color` is a safe choice.
+    pub term: Option<String>,
+    /// Overrides `colorterm` for this program
+    pub colorterm: Option<String>,
+}
+
+/// Controls how text with the blink attribute is displayed
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum TextBlink {
+    /// Periodically hide and show the text
+    Blink,
+    /// Show the text without blinking it
+    Steady,
+    /// Show the text in italics rather than blinking it
+    Italic,
+}
+
+impl Default for TextBlink {
+    fn default() -> Self {
+        TextBlink::Blink
+    }
+}
+
+/// Controls whether windows are rendered in high contrast
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum HighContrast {
+    /// Follow the high contrast setting of the operating system
+    FollowSystem,
+    /// Always render in high contrast
+    Always,
+    /// Never render in high contrast
+    Never,
+}
+
+impl Default for HighContrast {
+    fn default() -> Self {
+        HighContrast::FollowSystem
+    }
+}
+
+/// Multipliers for the hue, saturation and brightness of a color
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+pub struct HsbTransform {
+    #[serde(default = "default_hsb_component")]
+    pub hue: f32,
+    #[serde(default = "default_hsb_component")]
+    pub saturation: f32,
+    #[serde(default = "default_hsb_component")]
+    pub brightness: f32,
+}
+
+impl Default for HsbTransform {
+    fn default() -> Self {
+        Self {
+            hue: default_hsb_component(),
+            saturation: default_hsb_component(),
+            brightness: default_hsb_component(),
+        }
+    }
+}
+
+/// Controls what happens when a tab rings the bell
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum BellAction {
+    /// Do nothing
+    Ignore,
+    /// Briefly flash the edges of the window
+    Visual,
+    /// Show a notification in the window naming the tab
+    Notify,
+}
+
+impl Default for BellAction {
+    fn default() -> Self {
+        BellAction::Ignore
+    }
+}
+
+/// Controls what happens to a tab when its process exits
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ExitBehavior {
+    /// Close the tab straight away
+    Close,
+    /// Close the tab if the process exited successfully, otherwise
+    /// keep it open as for `Hold`
+    CloseOnCleanExit,
+    /// Keep the tab open, showing its final output and the exit
+    /// status of the process, until a key is pressed
+    Hold,
+}
+
+impl Default for ExitBehavior {
+    fn default() -> Self {
+        ExitBehavior::Close
+    }
+}
+
+/// Controls what happens when the program of a new tab fails to spawn
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum SpawnFailure {
+    /// Report the error without opening the tab.  If it was to be
+    /// the first tab of wezterm, then wezterm exits.
+    Fail,
+    /// Open the tab with the error shown at the top, running the
+    /// `fallback_prog` in it so that the problem can be fixed.  When
+    /// the tab is first shown, an overlay explains the error and
+    /// offers to close the tab instead.
+    RunFallbackProg,
+}
+
+impl Default for SpawnFailure {
+    fn default() -> Self {
+        SpawnFailure::RunFallbackProg
+    }
+}
+
+/// Controls how ZMODEM file transfers are handled
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ZmodemPolicy {
+    /// Receive the files that are sent with `sz` and offer to save
+    /// them into `download_dir`.  Sending files to `rz` isn't
+    /// supported, so those transfers are cancelled.
+    Receive,
+    /// Cancel all transfers
+    Cancel,
+    /// Don't look for transfers, leaving them to be displayed
+    Ignore,
+}
+
+impl Default for ZmodemPolicy {
+    fn default() -> Self {
+        ZmodemPolicy::Receive
+    }
+}
+
+/// Something that is done when the session becomes idle or is locked
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub enum IdleAction {
+    /// Hide the content of every tab
+    BlankTabs,
+    /// Hide the content of the tabs that are running one of the
+    /// `sensitive_processes`
+    BlankSensitiveTabs,
+    /// Run a command, in the same form as `default_prog`.  It is run
+    /// again once there is activity, and the `WEZTERM_SESSION_STATE`
+    /// environment variable is set to `idle`, `locked` or `active`
+    /// so that it can tell these apart.
+    RunCommand(Vec<String>),
+}
+
+/// A transformation that is applied to text prior to pasting it
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub enum PasteTransform {
+    /// Remove any newline characters from the end of the text
+    StripTrailingNewline,
+    /// Replace CRLF line endings with LF
+    CrlfToLf,
+    /// Replace each run of whitespace, including newlines, with a
+    /// single space and trim whitespace from both ends
+    CollapseWhitespace,
+    /// Run a command with the text as its stdin and paste its stdout
+    /// instead.  The 0th element is the command to run and the rest
+    /// of the elements are passed as its positional arguments:
+    ///
+    /// ```toml
+    /// paste_transforms = [{Command = ["sed", "s/^\\$ //"]}]
+    /// ```
+    Command(Vec<String>),
 }
 
 fn default_hyperlink_rules() -> Vec<hyperlink::Rule> {
@@ -87,8 +693,71 @@ fn default_hyperlink_rules() -> Vec<hyperlink::Rule> {
     ]
 }
 
+fn default_quick_select_patterns() -> Vec<String> {
+    vec![
+        // URL with a protocol
+        r"\b\w+://[\w.-]+\S*".into(),
+        // A path that contains at least one slash
+        r"[\w.~-]*/[\w.@~/-]+".into(),
+        // Hex digits, such as a git commit hash
+        r"\b[0-9a-f]{7,64}\b".into(),
+        // IPv4 address
+        r"\b\d{1,3}(?:\.\d{1,3}){3}\b".into(),
+        // Numbers with at least four digits
+        r"\b\d{4,}\b".into(),
+    ]
+}
+
+fn default_alt_screen_wheel_scroll_lines() -> usize {
+    1
+}
+
+fn default_primary_device_attributes() -> String {
+    term::DeviceIdentity::default().primary_attributes
+}
+
+fn default_secondary_device_attributes() -> String {
+    term::DeviceIdentity::default().secondary_attributes
+}
+
+fn default_terminal_name_and_version() -> String {
+    format!("wezterm {}", env!("CARGO_PKG_VERSION"))
+}
+
+fn default_scrollback_lines() -> usize {
+    3500
+}
+
 fn default_term() -> String {
-    "xterm-256color".into()
+    crate::terminfo::resolve_term(crate::terminfo::TERM_NAME).into()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_text_blink_rate() -> u64 {
+    500
+}
+
+fn default_text_blink_rate_rapid() -> u64 {
+    250
+}
+
+fn default_paste_chunk_size() -> usize {
+    1024
+}
+
+fn default_min_contrast() -> f32 {
+    1.0
+}
+
+fn default_hsb_component() -> f32 {
+    1.0
+}
+
+fn default_clipboard_history_size() -> usize {
+    32
 }
 
 fn default_font_size() -> f64 {
@@ -99,22 +768,113 @@ fn default_dpi() -> f64 {
     96.0
 }
 
+fn default_max_fps() -> u8 {
+    60
+}
+
+fn default_screen_reader_coalesce_ms() -> u64 {
+    500
+}
+
+fn default_bell_focused() -> BellAction {
+    BellAction::Visual
+}
+
+fn default_sensitive_processes() -> Vec<String> {
+    ["ssh", "mosh-client", "sudo", "su", "gpg", "pass"]
+        .iter()
+        .map(|name| name.to_string())
+        .collect()
+}
+
+fn default_fallback_prog() -> Vec<String> {
+    if cfg!(windows) {
+        vec!["cmd.exe".to_string()]
+    } else {
+        vec!["/bin/sh".to_string()]
+    }
+}
+
+fn default_bell_debounce_ms() -> u64 {
+    250
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
             font_size: default_font_size(),
             dpi: default_dpi(),
+            dpi_scale: None,
+            cell_spacing_x: 0.0,
+            cell_spacing_y: 0.0,
             font: TextStyle::default(),
             font_rules: Vec::new(),
+            glyph_substitutions: Vec::new(),
             font_system: FontSystemSelection::default(),
             front_end: FrontEndSelection::default(),
             pty: PtySystemSelection::default(),
+            register_utmp: false,
             colors: None,
-            scrollback_lines: None,
+            scrollback_lines: default_scrollback_lines(),
             hyperlink_rules: default_hyperlink_rules(),
             term: default_term(),
+            colorterm: None,
             default_prog: None,
             mux_server_unix_domain_socket_path: None,
+            single_instance_socket_path: None,
+            keys: vec![],
+            key_remaps: vec![],
+            enable_clipboard_history: default_true(),
+            clipboard_history_size: default_clipboard_history_size(),
+            paste_transforms: vec![],
+            enable_scroll_bar: false,
+            column_guides: vec![],
+            cursor_crosshair: false,
+            min_contrast: default_min_contrast(),
+            foreground_text_hsb: HsbTransform::default(),
+            high_contrast: HighContrast::default(),
+            paste_chunk_size: default_paste_chunk_size(),
+            paste_chunk_delay_ms: 0,
+            copy_register: None,
+            bold_brightens_ansi_colors: default_true(),
+            text_blink: TextBlink::default(),
+            text_blink_rate_ms: default_text_blink_rate(),
+            text_blink_rate_rapid_ms: default_text_blink_rate_rapid(),
+            font_synthesis: default_true(),
+            log_unhandled_escapes: default_true(),
+            scroll_to_bottom_on_input: default_true(),
+            scroll_to_bottom_on_output: false,
+            alt_screen_wheel_scroll_lines: default_alt_screen_wheel_scroll_lines(),
+            primary_device_attributes: default_primary_device_attributes(),
+            secondary_device_attributes: default_secondary_device_attributes(),
+            terminal_name_and_version: default_terminal_name_and_version(),
+            enable_csi_u_key_encoding: false,
+            sgr_21_is_bold_off: false,
+            copy_concealed_text: false,
+            normalize_input_to_nfc: false,
+            normalize_output_to_nfc: false,
+            max_fps: default_max_fps(),
+            input_latency_instrumentation: false,
+            screen_reader_filter: false,
+            screen_reader_coalesce_ms: default_screen_reader_coalesce_ms(),
+            quick_select_patterns: default_quick_select_patterns(),
+            launch_menu: vec![],
+            hyperlink_open_command: None,
+            hyperlink_scheme_commands: HashMap::new(),
+            hyperlink_resolve_paths: true,
+            bell_focused: default_bell_focused(),
+            bell_unfocused: BellAction::default(),
+            bell_debounce_ms: default_bell_debounce_ms(),
+            exit_behavior: ExitBehavior::default(),
+            notify_on_failed_exit: true,
+            spawn_failure: SpawnFailure::default(),
+            fallback_prog: default_fallback_prog(),
+            download_dir: None,
+            zmodem_policy: ZmodemPolicy::default(),
+            idle_timeout_minutes: None,
+            idle_actions: vec![],
+            lock_actions: vec![],
+            sensitive_processes: default_sensitive_processes(),
         }
     }
 }
@@ -150,6 +910,30 @@ fn empty_font_attributes() -> Vec<FontAttributes> {
     Vec::new()
 }
 
+/// Changes how a range of codepoints is drawn; see
+/// `Config::glyph_substitutions`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct GlyphSubstitution {
+    /// The first codepoint in the range
+    pub first: char,
+    /// The last codepoint in the range.  If omitted, the range
+    /// holds only `first`.
+    pub last: Option<char>,
+    /// If set, draw this codepoint in place of those in the range
+    pub replacement: Option<char>,
+    /// If set, always draw the range using this font from the
+    /// fallback sequence of the matching text style, where 0 is the
+    /// first entry in its `font` list.
+    pub font: Option<usize>,
+}
+
+impl GlyphSubstitution {
+    /// Returns true if `c` is in the range of this substitution
+    pub fn matches(&self, c: char) -> bool {
+        c >= self.first && c <= self.last.unwrap_or(self.first)
+    }
+}
+
 /// Represents textual styling.
 #[derive(Debug, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub struct TextStyle {
@@ -310,6 +1094,12 @@ lazy_static! {
     static ref RUNTIME_DIR: PathBuf = compute_runtime_dir().unwrap();
 }
 
+/// The per-user directory that holds the mux sockets and other
+/// transient state
+pub fn runtime_dir() -> &'static Path {
+    &RUNTIME_DIR
+}
+
 impl Config {
     pub fn load() -> Result<Self, Error> {
         // Note that the directories crate has methods for locating project
@@ -358,6 +1148,18 @@ impl Config {
                 RUNTIME_DIR.join("sock").to_str().map(str::to_owned);
         }
 
+        if cfg.single_instance_socket_path.is_none() {
+            cfg.single_instance_socket_path =
+                RUNTIME_DIR.join("gui-sock").to_str().map(str::to_owned);
+        }
+
+        if cfg.download_dir.is_none() {
+            cfg.download_dir = dirs::download_dir()
+                .unwrap_or_else(|| HOME_DIR.join("Downloads"))
+                .to_str()
+                .map(str::to_owned);
+        }
+
         if cfg.font_rules.is_empty() {
             // Expand out some reasonable default font rules
             let bold = self.font.make_bold();
@@ -424,6 +1226,15 @@ impl Config {
     }
 
     pub fn build_prog(&self, prog: Option<Vec<&OsStr>>) -> Result<CommandBuilder, Error> {
+        self.build_prog_with_term(prog, &self.term, self.colorterm.as_ref())
+    }
+
+    fn build_prog_with_term(
+        &self,
+        prog: Option<Vec<&OsStr>>,
+        term: &str,
+        colorterm: Option<&String>,
+    ) -> Result<CommandBuilder, Error> {
         let mut cmd = match prog {
             Some(args) => {
                 let mut args = args.iter();
@@ -440,8 +1251,86 @@ impl Config {
             }
         };
 
-        cmd.env("TERM", &self.term);
+        cmd.env("TERM", crate::terminfo::resolve_term(term));
+        if let Some(colorterm) = colorterm {
+            cmd.env("COLORTERM", colorterm);
+        }
+
+        Ok(cmd)
+    }
+
+    /// Returns the value of TERM to advertise to programs, which is
+    /// the configured `term` unless that is the wezterm entry and it
+    /// isn't installed
+    pub fn effective_term(&self) -> String {
+        crate::terminfo::resolve_term(&self.term).to_owned()
+    }
+
+    /// Returns the scale factor that windows should use for their fonts,
+    /// given the scale factor `reported` by the windowing system
+    pub fn effective_dpi_scale(&self, reported: f64) -> f64 {
+        match self.dpi_scale {
+            Some(scale) if scale > 0.0 => scale,
+            _ => reported,
+        }
+    }
+
+    /// Returns the responses to give to programs that ask the terminal
+    /// to identify itself
+    pub fn device_identity(&self) -> term::DeviceIdentity {
+        term::DeviceIdentity {
+            primary_attributes: self.primary_device_attributes.clone(),
+            secondary_attributes: self.secondary_device_attributes.clone(),
+            name_and_version: self.terminal_name_and_version.clone(),
+            term: self.effective_term(),
+        }
+    }
+
+    /// Returns the command that should be used to open `uri`, or None
+    /// if the system default handler should be used
+    pub fn hyperlink_command(&self, uri: &str) -> Option<Vec<String>> {
+        let scheme = uri.split(':').next().unwrap_or("").to_lowercase();
+        self.hyperlink_scheme_commands
+            .iter()
+            .find(|(name, _)| name.to_lowercase() == scheme)
+            .map(|(_, command)| command)
+            .or_else(|| self.hyperlink_open_command.as_ref())
+            .filter(|command| !command.is_empty())
+            .cloned()
+    }
+
+    /// Build the command for an entry in the `launch_menu`
+    pub fn build_launch_menu_prog(&self, item: &LaunchMenuItem) -> Result<CommandBuilder, Error> {
+        let args = match item.args.as_ref() {
+            Some(args) if args.is_empty() => {
+                bail!("launch_menu entry {} has empty args", item.label)
+            }
+            Some(args) => Some(args.iter().map(OsStr::new).collect()),
+            None => None,
+        };
+        let term = item.term.as_ref().unwrap_or(&self.term);
+        let colorterm = item.colorterm.as_ref().or_else(|| self.colorterm.as_ref());
+        let mut cmd = self.build_prog_with_term(args, term, colorterm)?;
+        if let Some(cwd) = item.cwd.as_ref() {
+            cmd.cwd(cwd);
+        }
+        Ok(cmd)
+    }
 
+    /// Returns the command that runs the program that `spawn` describes
+    pub fn build_spawn_command_prog(&self, spawn: &SpawnCommand) -> Result<CommandBuilder, Error> {
+        let args = match spawn.args.as_ref() {
+            Some(args) if args.is_empty() => bail!("SpawnCommand has empty args"),
+            Some(args) => Some(args.iter().map(OsStr::new).collect()),
+            None => None,
+        };
+        let mut cmd = self.build_prog(args)?;
+        if let Some(cwd) = spawn.cwd.as_ref() {
+            cmd.cwd(cwd);
+        }
+        for (name, value) in &spawn.env {
+            cmd.env(name, value);
+        }
         Ok(cmd)
     }
 }
@@ -458,6 +1347,8 @@ pub struct Palette {
     /// The color of selected text
     pub selection_fg: Option<RgbColor>,
     pub selection_bg: Option<RgbColor>,
+    /// The color of the scrollbar thumb
+    pub scrollbar_thumb: Option<RgbColor>,
     /// A list of 8 colors corresponding to the basic ANSI palette
     pub ansi: Option<[RgbColor; 8]>,
     /// A list of 8 colors corresponding to bright versions of the
@@ -481,6 +1372,7 @@ impl From<Palette> for term::color::ColorPalette {
         apply_color!(cursor_bg);
         apply_color!(selection_fg);
         apply_color!(selection_bg);
+        apply_color!(scrollbar_thumb);
 
         if let Some(ansi) = cfg.ansi {
             for (idx, col) in ansi.iter().enumerate() {
@@ -495,3 +1387,50 @@ impl From<Palette> for term::color::ColorPalette {
         p
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn hyperlink_command() {
+        let mut config = Config::default();
+        assert_eq!(config.hyperlink_command("https://example.com"), None);
+
+        config.hyperlink_open_command = Some(strings(&["xdg-open"]));
+        config
+            .hyperlink_scheme_commands
+            .insert("MailTo".into(), strings(&["mutt"]));
+        config
+            .hyperlink_scheme_commands
+            .insert("scp".into(), vec![]);
+
+        // Schemes are matched without regard to case
+        assert_eq!(
+            config.hyperlink_command("mailto:wez@example.com"),
+            Some(strings(&["mutt"]))
+        );
+        assert_eq!(
+            config.hyperlink_command("MAILTO:wez@example.com"),
+            Some(strings(&["mutt"]))
+        );
+        // Other schemes use the general command
+        assert_eq!(
+            config.hyperlink_command("https://example.com"),
+            Some(strings(&["xdg-open"]))
+        );
+        assert_eq!(
+            config.hyperlink_command("/etc/hosts"),
+            Some(strings(&["xdg-open"]))
+        );
+        // An empty command selects the system default handler
+        assert_eq!(config.hyperlink_command("scp://host/path"), None);
+
+        config.hyperlink_open_command = Some(vec![]);
+        assert_eq!(config.hyperlink_command("https://example.com"), None);
+    }
+}
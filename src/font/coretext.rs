@@ -175,6 +175,14 @@ impl Font for CoreTextFontImpl {
         self.has_color
     }
 
+    fn description(&self) -> String {
+        self.ct_font.family_name()
+    }
+
+    fn path(&self) -> Option<String> {
+        None
+    }
+
     fn metrics(&self) -> FontMetrics {
         self.metrics.font_metrics
     }
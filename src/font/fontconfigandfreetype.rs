@@ -49,7 +49,13 @@ impl FontSystem for FontConfigAndFreeType {
         pattern.add_double("size", config.font_size * font_scale)?;
         pattern.add_double("dpi", config.dpi)?;
 
-        Ok(Box::new(NamedFontImpl::new(pattern)?))
+        let mut font = NamedFontImpl::new(pattern)?;
+        if config.font_synthesis {
+            let attr = &fonts[0];
+            font.bold = attr.bold.unwrap_or(false);
+            font.italic = attr.italic.unwrap_or(false);
+        }
+        Ok(Box::new(font))
     }
 }
 
@@ -60,6 +66,10 @@ pub struct NamedFontImpl {
     pattern: fcwrap::Pattern,
     font_list: fcwrap::FontSet,
     fonts: Vec<FreeTypeFontImpl>,
+    /// The requested boldness and slant; these are synthesized for
+    /// any fallback whose face doesn't provide them
+    bold: bool,
+    italic: bool,
 }
 
 impl Drop for NamedFontImpl {
@@ -106,6 +116,8 @@ impl NamedFontImpl {
             font_list,
             pattern,
             fonts: Vec::new(),
+            bold: false,
+            italic: false,
         })
     }
 
@@ -124,9 +136,11 @@ impl NamedFontImpl {
 
         let size = pat.get_double("size")?;
         let dpi = pat.get_double("dpi")? as u32;
-        let face = self.lib.new_face(file, 0)?;
-        self.fonts
-            .push(FreeTypeFontImpl::with_face_size_and_dpi(face, size, dpi)?);
+        let face = self.lib.new_face(file.clone(), 0)?;
+        let mut font = FreeTypeFontImpl::with_face_size_and_dpi(face, size, dpi)?;
+        font.set_synthesis(self.bold, self.italic);
+        font.set_path(file);
+        self.fonts.push(font);
         Ok(())
     }
 
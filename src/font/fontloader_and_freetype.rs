@@ -46,17 +46,27 @@ impl FontSystem for FontLoaderAndFreeType {
 
         let mut fonts = Vec::new();
         let mut fontdata = Vec::new();
-        for (data, idx) in fontloader::load_system_fonts(config, style)? {
+        // load_system_fonts returns one font for each of the
+        // attributes in the fallback list, in the same order
+        let attrs = style.font_with_fallback();
+        for ((data, idx), attr) in fontloader::load_system_fonts(config, style)?
+            .into_iter()
+            .zip(attrs.iter())
+        {
             eprintln!("want idx {} in bytes of len {}", idx, data.len());
 
             let face = lib.new_face_from_slice(&data, idx.into())?;
             fontdata.push(data);
 
-            fonts.push(FreeTypeFontImpl::with_face_size_and_dpi(
+            let mut font = FreeTypeFontImpl::with_face_size_and_dpi(
                 face,
                 config.font_size * font_scale,
                 config.dpi as u32,
-            )?);
+            )?;
+            if config.font_synthesis {
+                font.set_synthesis(attr.bold.unwrap_or(false), attr.italic.unwrap_or(false));
+            }
+            fonts.push(font);
         }
         Ok(Box::new(NamedFontImpl {
             fonts,
@@ -13,6 +13,10 @@ pub struct FreeTypeFontImpl {
     cell_height: f64,
     /// nominal monospace cell width
     cell_width: f64,
+    /// styles that the face lacks and which are faked when rasterizing
+    synthesis: ftwrap::Synthesis,
+    /// the file from which the face was loaded, if known
+    path: Option<String>,
 }
 
 impl FreeTypeFontImpl {
@@ -68,8 +72,22 @@ impl FreeTypeFontImpl {
             font: RefCell::new(font),
             cell_height,
             cell_width,
+            synthesis: ftwrap::Synthesis::default(),
+            path: None,
         })
     }
+
+    /// Record the desired boldness and slant for this font.
+    /// If the face doesn't natively provide them, they will be
+    /// synthesized when glyphs are rasterized.
+    pub fn set_synthesis(&mut self, bold: bool, italic: bool) {
+        self.synthesis = self.face.borrow().synthesis_for(bold, italic);
+    }
+
+    /// Record the file from which the face was loaded
+    pub fn set_path(&mut self, path: String) {
+        self.path = Some(path);
+    }
 }
 
 impl Font for FreeTypeFontImpl {
@@ -85,6 +103,15 @@ impl Font for FreeTypeFontImpl {
         unsafe { (((*face.face).face_flags as u32) & (ftwrap::FT_FACE_FLAG_COLOR as u32)) != 0 }
     }
 
+    fn description(&self) -> String {
+        let (family, style) = self.face.borrow().names();
+        format!("{} {}", family, style)
+    }
+
+    fn path(&self) -> Option<String> {
+        self.path.clone()
+    }
+
     fn metrics(&self) -> FontMetrics {
         let face = self.face.borrow();
         FontMetrics {
@@ -117,7 +144,8 @@ impl Font for FreeTypeFontImpl {
         // single threaded and don't load any other glyphs in the body of
         // this load_glyph() function.
         let mut face = self.face.borrow_mut();
-        let ft_glyph = face.load_and_render_glyph(glyph_pos, load_flags, render_mode)?;
+        let ft_glyph =
+            face.load_and_render_glyph(glyph_pos, load_flags, render_mode, self.synthesis)?;
 
         let mode: ftwrap::FT_Pixel_Mode =
             unsafe { mem::transmute(u32::from(ft_glyph.bitmap.pixel_mode)) };
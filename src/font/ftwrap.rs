@@ -2,7 +2,7 @@
 
 use failure::Error;
 pub use freetype::*;
-use std::ffi::CString;
+use std::ffi::{CStr, CString};
 use std::ptr;
 
 #[inline]
@@ -23,6 +23,14 @@ pub struct Face {
     pub face: FT_Face,
 }
 
+/// Describes the styles that must be faked when rendering glyphs
+/// from a face that lacks a true bold or italic variant
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Synthesis {
+    pub bold: bool,
+    pub italic: bool,
+}
+
 impl Drop for Face {
     fn drop(&mut self) {
         unsafe {
@@ -65,15 +73,68 @@ impl Face {
         ft_result(unsafe { FT_Select_Size(self.face, idx as i32) }, ())
     }
 
+    /// Given the desired boldness and slant, determine which of those
+    /// styles the face is unable to provide and will need to be
+    /// synthesized
+    pub fn synthesis_for(&self, bold: bool, italic: bool) -> Synthesis {
+        let flags = unsafe { (*self.face).style_flags } as u32;
+        Synthesis {
+            bold: bold && (flags & FT_STYLE_FLAG_BOLD) == 0,
+            italic: italic && (flags & FT_STYLE_FLAG_ITALIC) == 0,
+        }
+    }
+
+    /// Returns the family and style names of the face, such as
+    /// "Fira Code" and "Bold"
+    pub fn names(&self) -> (String, String) {
+        fn to_string(s: *const FT_String) -> String {
+            if s.is_null() {
+                String::new()
+            } else {
+                unsafe { CStr::from_ptr(s) }.to_string_lossy().into_owned()
+            }
+        }
+        unsafe {
+            (
+                to_string((*self.face).family_name),
+                to_string((*self.face).style_name),
+            )
+        }
+    }
+
     pub fn load_and_render_glyph(
         &mut self,
         glyph_index: FT_UInt,
         load_flags: FT_Int32,
         render_mode: FT_Render_Mode,
+        synthesis: Synthesis,
     ) -> Result<&FT_GlyphSlotRec_, Error> {
         unsafe {
             let res = FT_Load_Glyph(self.face, glyph_index, load_flags);
             if succeeded(res) {
+                let slot = &mut *(*self.face).glyph;
+                // Synthesis can only be applied to scalable outlines;
+                // bitmap glyphs are rendered as-is.
+                if slot.format == FT_Glyph_Format::FT_GLYPH_FORMAT_OUTLINE {
+                    if synthesis.italic {
+                        // The same ~12 degree shear that FT_GlyphSlot_Oblique uses
+                        let matrix = FT_Matrix {
+                            xx: 0x10000,
+                            xy: 0x0366A,
+                            yx: 0,
+                            yy: 0x10000,
+                        };
+                        FT_Outline_Transform(&slot.outline, &matrix);
+                    }
+                    if synthesis.bold {
+                        // The same strength that FT_GlyphSlot_Embolden uses
+                        let strength = FT_MulFix(
+                            FT_Long::from((*self.face).units_per_EM),
+                            (*(*self.face).size).metrics.y_scale,
+                        ) / 24;
+                        FT_Outline_EmboldenXY(&mut slot.outline, strength, strength);
+                    }
+                }
                 let render = FT_Render_Glyph((*self.face).glyph, render_mode);
                 if !succeeded(render) {
                     bail!("FT_Render_Glyph failed: {:?}", render);
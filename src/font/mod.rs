@@ -14,6 +14,8 @@ pub use self::system::*;
 
 pub mod ftwrap;
 
+mod substitute;
+
 #[cfg(all(unix, any(feature = "fontconfig", not(target_os = "macos"))))]
 pub mod fcwrap;
 #[cfg(all(unix, any(feature = "fontconfig", not(target_os = "macos"))))]
@@ -32,6 +34,11 @@ use term::CellAttributes;
 
 type FontPtr = Rc<RefCell<Box<NamedFont>>>;
 
+/// Identifies one of the text styles from the configuration; 0 is
+/// the base `font` and `n` is `font_rules[n - 1]`.  This is much
+/// cheaper to hash and compare than the `TextStyle` that it refers to.
+pub type StyleId = usize;
+
 /// Matches and loads fonts for a given input style
 pub struct FontConfiguration {
     config: Arc<Config>,
@@ -158,6 +165,34 @@ impl FontConfiguration {
         Ok(font)
     }
 
+    /// Shape `text` using the font that matches `style`, first applying
+    /// the `glyph_substitutions` from the configuration.
+    pub fn shape(&self, style: &TextStyle, text: &str) -> Result<Vec<GlyphInfo>, Error> {
+        let font = self.cached_font(style)?;
+        let mut font = font.borrow_mut();
+
+        if self.config.glyph_substitutions.is_empty() {
+            return font.shape(text);
+        }
+
+        let mut glyphs = vec![];
+        for run in substitute::substitute(&self.config.glyph_substitutions, text) {
+            let mut shaped = match run.font {
+                Some(font_idx) => match shape_with_harfbuzz(&mut **font, font_idx, &run.text) {
+                    Ok(shaped) => shaped,
+                    Err(err) => {
+                        eprintln!("glyph_substitutions: font {}: {}", font_idx, err);
+                        font.shape(&run.text)?
+                    }
+                },
+                None => font.shape(&run.text)?,
+            };
+            run.fixup(&mut shaped);
+            glyphs.append(&mut shaped);
+        }
+        Ok(glyphs)
+    }
+
     pub fn change_scaling(&self, font_scale: f64, dpi_scale: f64) {
         *self.dpi_scale.borrow_mut() = dpi_scale;
         *self.font_scale.borrow_mut() = font_scale;
@@ -188,6 +223,12 @@ impl FontConfiguration {
 
         let font = self.default_font()?;
         let metrics = font.borrow_mut().get_fallback(0)?.metrics();
+        let dpi_scale = *self.dpi_scale.borrow();
+        let metrics = apply_cell_spacing(
+            metrics,
+            self.config.cell_spacing_x * dpi_scale,
+            self.config.cell_spacing_y * dpi_scale,
+        );
 
         *self.metrics.borrow_mut() = Some(metrics);
 
@@ -198,6 +239,11 @@ impl FontConfiguration {
     /// produce the text style that best matches the supplied input
     /// cell attributes.
     pub fn match_style(&self, attrs: &CellAttributes) -> &TextStyle {
+        self.match_style_and_id(attrs).1
+    }
+
+    /// Like `match_style`, but also returns the id of the style
+    pub fn match_style_and_id(&self, attrs: &CellAttributes) -> (StyleId, &TextStyle) {
         // a little macro to avoid boilerplate for matching the rules.
         // If the rule doesn't specify a value for an attribute then
         // it will implicitly match.  If it specifies an attribute
@@ -214,7 +260,7 @@ impl FontConfiguration {
             };
         };
 
-        for rule in &self.config.font_rules {
+        for (idx, rule) in self.config.font_rules.iter().enumerate() {
             attr_match!(intensity, &rule);
             attr_match!(underline, &rule);
             attr_match!(italic, &rule);
@@ -225,12 +271,22 @@ impl FontConfiguration {
 
             // If we get here, then none of the rules didn't match,
             // so we therefore assume that it did match overall.
-            return &rule.font;
+            return (idx + 1, &rule.font);
         }
-        &self.config.font
+        (0, &self.config.font)
     }
 }
 
+/// Add the configured cell spacing, in pixels, to `metrics`.  The
+/// baseline is moved up by half of the vertical spacing so that the
+/// text remains centered in the cell.
+fn apply_cell_spacing(mut metrics: FontMetrics, spacing_x: f64, spacing_y: f64) -> FontMetrics {
+    metrics.cell_width = (metrics.cell_width + spacing_x).max(1.0);
+    metrics.cell_height = (metrics.cell_height + spacing_y).max(1.0);
+    metrics.descender -= spacing_y / 2.0;
+    metrics
+}
+
 #[allow(dead_code)]
 pub fn shape_with_harfbuzz(
     font: &mut NamedFont,
@@ -379,3 +435,27 @@ pub fn shape_with_harfbuzz(
 
     Ok(cluster)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cell_spacing() {
+        let metrics = FontMetrics {
+            cell_width: 8.0,
+            cell_height: 16.0,
+            descender: -4.0,
+        };
+        let spaced = |x, y| {
+            let m = apply_cell_spacing(metrics, x, y);
+            (m.cell_width, m.cell_height, m.descender)
+        };
+
+        assert_eq!(spaced(0.0, 0.0), (8.0, 16.0, -4.0));
+        assert_eq!(spaced(2.0, 4.0), (10.0, 20.0, -6.0));
+        assert_eq!(spaced(-1.0, -2.0), (7.0, 14.0, -3.0));
+        // The cell never collapses entirely
+        assert_eq!(spaced(-20.0, -20.0), (1.0, 1.0, 6.0));
+    }
+}
@@ -0,0 +1,118 @@
+//! Applies the `glyph_substitutions` from the configuration to text
+//! prior to shaping it.
+use super::system::{FallbackIdx, GlyphInfo};
+use crate::config::GlyphSubstitution;
+use unicode_width::UnicodeWidthChar;
+
+/// A contiguous run of text that is shaped with the same font
+#[derive(Debug, PartialEq)]
+pub struct SubstitutedRun {
+    /// Byte offset of the start of the run in the original text
+    pub start: usize,
+    /// The text to shape, with replacements applied
+    pub text: String,
+    /// If set, the run must be shaped using this fallback font
+    pub font: Option<FallbackIdx>,
+    /// Maps each byte offset in `text` to the corresponding byte
+    /// offset in the original text, relative to `start`
+    offsets: Vec<usize>,
+    /// For each byte offset in `text` that starts a replacement,
+    /// holds the width of the codepoint that it replaced
+    widths: Vec<Option<u8>>,
+}
+
+impl SubstitutedRun {
+    /// Adjust glyphs that were shaped from `self.text` so that they
+    /// describe the original text
+    pub fn fixup(&self, glyphs: &mut [GlyphInfo]) {
+        for info in glyphs {
+            let pos = info.cluster as usize;
+            if let Some(width) = self.widths.get(pos).cloned().and_then(|w| w) {
+                info.num_cells = width;
+            }
+            let offset = self.offsets.get(pos).cloned().unwrap_or(0);
+            info.cluster = (self.start + offset) as u32;
+        }
+    }
+}
+
+/// Split `text` into runs that share the same font, replacing the
+/// codepoints that have a configured replacement
+pub fn substitute(subs: &[GlyphSubstitution], text: &str) -> Vec<SubstitutedRun> {
+    let mut runs: Vec<SubstitutedRun> = vec![];
+
+    for (pos, c) in text.char_indices() {
+        let sub = subs.iter().find(|sub| sub.matches(c));
+        let font = sub.and_then(|sub| sub.font);
+        let replacement = sub.and_then(|sub| sub.replacement);
+
+        if runs.last().map(|run| run.font != font).unwrap_or(true) {
+            runs.push(SubstitutedRun {
+                start: pos,
+                text: String::new(),
+                font,
+                offsets: vec![],
+                widths: vec![],
+            });
+        }
+        let run = runs.last_mut().unwrap();
+
+        let out = replacement.unwrap_or(c);
+        let out_pos = run.text.len();
+        run.text.push(out);
+        for _ in 0..out.len_utf8() {
+            run.offsets.push(pos - run.start);
+            run.widths.push(None);
+        }
+        if replacement.is_some() {
+            run.widths[out_pos] = Some(c.width().unwrap_or(1).max(1) as u8);
+        }
+    }
+
+    runs
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sub(
+        first: char,
+        last: Option<char>,
+        replacement: Option<char>,
+        font: Option<usize>,
+    ) -> GlyphSubstitution {
+        GlyphSubstitution {
+            first,
+            last,
+            replacement,
+            font,
+        }
+    }
+
+    #[test]
+    fn runs() {
+        let subs = vec![
+            sub('\u{2500}', Some('\u{257f}'), None, Some(1)),
+            sub('x', None, Some('\u{2715}'), None),
+        ];
+
+        let runs = substitute(&subs, "a\u{2500}\u{2502}bx");
+        assert_eq!(runs.len(), 3);
+
+        assert_eq!(runs[0].start, 0);
+        assert_eq!(runs[0].text, "a");
+        assert_eq!(runs[0].font, None);
+
+        assert_eq!(runs[1].start, 1);
+        assert_eq!(runs[1].text, "\u{2500}\u{2502}");
+        assert_eq!(runs[1].font, Some(1));
+        assert_eq!(runs[1].offsets, vec![0, 0, 0, 3, 3, 3]);
+
+        // The replacement is three bytes long but the original is one
+        assert_eq!(runs[2].start, 7);
+        assert_eq!(runs[2].text, "b\u{2715}");
+        assert_eq!(runs[2].offsets, vec![0, 1, 1, 1]);
+        assert_eq!(runs[2].widths, vec![None, Some(1), None, None]);
+    }
+}
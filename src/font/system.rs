@@ -110,6 +110,14 @@ pub trait Font {
     /// colorized.
     fn has_color(&self) -> bool;
 
+    /// Returns a human readable name for the font, such as its
+    /// family and style, for diagnostic purposes
+    fn description(&self) -> String;
+
+    /// Returns the path to the file from which the font was loaded,
+    /// if it is known
+    fn path(&self) -> Option<String>;
+
     /// Returns the font metrics
     fn metrics(&self) -> FontMetrics;
 
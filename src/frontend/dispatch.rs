@@ -0,0 +1,352 @@
+//! Carries out the key assignments that act upon the tabs of a mux
+//! window.  These don't depend upon how the window is presented, so
+//! the frontends share this implementation rather than each having
+//! their own, and provide the few things that do differ between them
+//! through the `FrontEndWindow` trait.
+use crate::config::Config;
+use crate::keyassignment::KeyAssignment;
+use crate::mux::renderable::Renderable;
+use crate::mux::tab::{Tab, TabId};
+use crate::mux::window::WindowId;
+use crate::mux::Mux;
+use crate::pty::{CommandBuilder, PtySize};
+use failure::Error;
+use std::rc::Rc;
+use std::sync::Arc;
+use term::color::RgbColor;
+use term::{HighlightSet, Pattern};
+
+/// The name of the highlights shown by `HighlightSelectionMatches`
+const SELECTION_MATCHES: &str = "selection-matches";
+
+/// The capabilities that a frontend provides for one of its windows
+pub trait FrontEndWindow {
+    /// Returns the id of the mux window that is being shown
+    fn mux_window_id(&self) -> WindowId;
+    fn config(&self) -> Arc<Config>;
+    /// Returns the size of a tab that is shown in the window
+    fn tab_size(&self) -> PtySize;
+    /// Called after `tab` was spawned into the window and made active,
+    /// so that the frontend can start to manage it
+    fn tab_was_spawned(&mut self, tab: &Rc<Tab>) -> Result<(), Error>;
+    /// Called after a different tab was made active
+    fn active_tab_changed(&mut self);
+}
+
+/// Spawn `cmd` into a new tab in the window and make it active
+pub fn spawn_tab(win: &mut FrontEndWindow, cmd: CommandBuilder) -> Result<TabId, Error> {
+    let config = win.config();
+    let tab = crate::spawn_tab_with_size(
+        &config,
+        cmd,
+        win.tab_size(),
+        Some(win.mux_window_id()),
+        false,
+        config.spawn_failure,
+    )?;
+
+    let mux = Mux::get().unwrap();
+    let len = {
+        let mut window = mux
+            .get_window_mut(win.mux_window_id())
+            .ok_or_else(|| format_err!("no such window!?"))?;
+        window.push(&tab);
+        window.len()
+    };
+    activate_tab(win, len - 1)?;
+
+    win.tab_was_spawned(&tab)?;
+
+    Ok(tab.tab_id())
+}
+
+/// Spawn the `idx`th entry of the `launch_menu` into a new tab
+pub fn spawn_launch_menu_item(win: &mut FrontEndWindow, idx: usize) -> Result<TabId, Error> {
+    let config = win.config();
+    let item = config
+        .launch_menu
+        .get(idx)
+        .ok_or_else(|| format_err!("launch_menu has no entry {}", idx))?;
+    let cmd = config.build_launch_menu_prog(item)?;
+    spawn_tab(win, cmd)
+}
+
+/// Make the `tab_idx`th tab of the window active.
+/// Nothing happens if there is no such tab.
+pub fn activate_tab(win: &mut FrontEndWindow, tab_idx: usize) -> Result<(), Error> {
+    let mux = Mux::get().unwrap();
+    let mut window = mux
+        .get_window_mut(win.mux_window_id())
+        .ok_or_else(|| format_err!("no such window"))?;
+
+    if tab_idx < window.len() {
+        window.set_active(tab_idx);
+
+        drop(window);
+        win.active_tab_changed();
+    }
+    Ok(())
+}
+
+/// Activate the tab that is `delta` positions away from the active
+/// tab, wrapping around at either end
+pub fn activate_tab_relative(win: &mut FrontEndWindow, delta: isize) -> Result<(), Error> {
+    let mux = Mux::get().unwrap();
+    let window = mux
+        .get_window(win.mux_window_id())
+        .ok_or_else(|| format_err!("no such window"))?;
+
+    let max = window.len() as isize;
+    if max == 0 {
+        return Ok(());
+    }
+    let active = window.get_active_idx() as isize;
+    let tab = (active + delta) % max;
+    let tab = if tab < 0 { max + tab } else { tab };
+    drop(window);
+    activate_tab(win, tab as usize)
+}
+
+/// Highlight the occurrences of the text selected in `term`, or
+/// remove them if they are already highlighted
+fn toggle_selection_matches(term: &mut Renderable) -> Result<(), Error> {
+    if term.has_highlights(SELECTION_MATCHES) {
+        term.clear_highlights(SELECTION_MATCHES);
+        return Ok(());
+    }
+    let text = term.get_selection_text();
+    if text.is_empty() || text.contains('\n') {
+        return Ok(());
+    }
+    let ranges = term.search(&Pattern::CaseSensitiveString(text))?;
+    term.set_highlights(
+        SELECTION_MATCHES,
+        HighlightSet {
+            ranges,
+            fg: RgbColor::new(0, 0, 0),
+            bg: RgbColor::new(0xff, 0xd7, 0x00),
+        },
+    );
+    Ok(())
+}
+
+/// Carry out `assignment` if it is one that is shared by all of
+/// the frontends.  Returns false if it is up to the frontend to
+/// carry it out instead.
+pub fn perform_key_assignment(
+    win: &mut FrontEndWindow,
+    assignment: &KeyAssignment,
+) -> Result<bool, Error> {
+    use crate::keyassignment::KeyAssignment::*;
+    match assignment {
+        SpawnTab => {
+            let cmd = win.config().build_prog(None)?;
+            spawn_tab(win, cmd)?;
+        }
+        SpawnLaunchMenuItem(n) => {
+            spawn_launch_menu_item(win, *n)?;
+        }
+        SpawnCommandInNewTab(spawn) => {
+            let cmd = win.config().build_spawn_command_prog(spawn)?;
+            spawn_tab(win, cmd)?;
+        }
+        ActivateTab(n) => activate_tab(win, *n)?,
+        ActivateTabRelative(n) => activate_tab_relative(win, *n)?,
+        ToggleRevealConcealed => {
+            let mux = Mux::get().unwrap();
+            if let Some(tab) = mux.get_active_tab_for_window(win.mux_window_id()) {
+                let mut term = tab.renderer();
+                let reveal = !term.is_concealed_revealed();
+                term.set_reveal_concealed(reveal);
+            }
+        }
+        HighlightSelectionMatches => {
+            let mux = Mux::get().unwrap();
+            if let Some(tab) = mux.get_active_tab_for_window(win.mux_window_id()) {
+                toggle_selection_matches(&mut *tab.renderer())?;
+            }
+        }
+        // Nominally copy, but that is implicit, so NOP
+        Copy => {}
+        Nop => {}
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::frontend::guicommon::overlay::{
+        OverlayAction, OverlayDelegate, OverlayHost, OverlayTab,
+    };
+    use term::{KeyCode, KeyModifiers, MouseButton, MouseEvent, MouseEventKind, Terminal};
+
+    struct Blank;
+
+    impl OverlayDelegate for Blank {
+        fn get_title(&self) -> String {
+            "blank".into()
+        }
+
+        fn render(&self, _rows: usize, _cols: usize) -> String {
+            String::new()
+        }
+
+        fn key_down(&mut self, _key: KeyCode, _mods: KeyModifiers) -> Result<OverlayAction, Error> {
+            Ok(OverlayAction::Continue)
+        }
+    }
+
+    struct TestWindow {
+        window_id: WindowId,
+        changes: usize,
+    }
+
+    impl FrontEndWindow for TestWindow {
+        fn mux_window_id(&self) -> WindowId {
+            self.window_id
+        }
+
+        fn config(&self) -> Arc<Config> {
+            Arc::clone(Mux::get().unwrap().config())
+        }
+
+        fn tab_size(&self) -> PtySize {
+            PtySize {
+                rows: 2,
+                cols: 10,
+                pixel_width: 0,
+                pixel_height: 0,
+            }
+        }
+
+        fn tab_was_spawned(&mut self, _tab: &Rc<Tab>) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn active_tab_changed(&mut self) {
+            self.changes += 1;
+        }
+    }
+
+    /// Sets up a mux with a window holding `num_tabs` tabs
+    fn window_with_tabs(num_tabs: usize) -> TestWindow {
+        let mux = Rc::new(Mux::new(&Arc::new(Config::default())));
+        Mux::set_mux(&mux);
+        let tab =
+            |window_id| -> Rc<Tab> { Rc::new(OverlayTab::new(window_id, 2, 10, Box::new(Blank))) };
+        let window_id = mux.add_new_window_with_tab(&tab(0)).unwrap();
+        for _ in 1..num_tabs {
+            mux.get_window_mut(window_id).unwrap().push(&tab(window_id));
+        }
+        TestWindow {
+            window_id,
+            changes: 0,
+        }
+    }
+
+    fn active_idx(win: &TestWindow) -> usize {
+        let mux = Mux::get().unwrap();
+        let window = mux.get_window(win.window_id).unwrap();
+        window.get_active_idx()
+    }
+
+    #[test]
+    fn activate() {
+        let mut win = window_with_tabs(3);
+        activate_tab(&mut win, 2).unwrap();
+        assert_eq!(active_idx(&win), 2);
+        assert_eq!(win.changes, 1);
+
+        // There is no such tab
+        activate_tab(&mut win, 3).unwrap();
+        assert_eq!(active_idx(&win), 2);
+        assert_eq!(win.changes, 1);
+    }
+
+    #[test]
+    fn activate_relative_wraps() {
+        let mut win = window_with_tabs(3);
+        activate_tab_relative(&mut win, -1).unwrap();
+        assert_eq!(active_idx(&win), 2);
+        activate_tab_relative(&mut win, 1).unwrap();
+        assert_eq!(active_idx(&win), 0);
+        activate_tab_relative(&mut win, 5).unwrap();
+        assert_eq!(active_idx(&win), 2);
+    }
+
+    #[test]
+    fn shared_assignments() {
+        let mut win = window_with_tabs(2);
+        assert!(perform_key_assignment(&mut win, &KeyAssignment::ActivateTab(1)).unwrap());
+        assert_eq!(active_idx(&win), 1);
+        assert!(perform_key_assignment(&mut win, &KeyAssignment::Nop).unwrap());
+
+        // These are up to the frontend
+        assert!(!perform_key_assignment(&mut win, &KeyAssignment::Paste).unwrap());
+        assert!(!perform_key_assignment(&mut win, &KeyAssignment::ToggleFullScreen).unwrap());
+        assert_eq!(win.changes, 1);
+    }
+
+    #[test]
+    fn toggle_reveal_concealed() {
+        let mut win = window_with_tabs(1);
+        let window_id = win.window_id;
+        let revealed = || {
+            let tab = Mux::get()
+                .unwrap()
+                .get_active_tab_for_window(window_id)
+                .unwrap();
+            let renderer = tab.renderer();
+            renderer.is_concealed_revealed()
+        };
+        assert!(!revealed());
+        perform_key_assignment(&mut win, &KeyAssignment::ToggleRevealConcealed).unwrap();
+        assert!(revealed());
+    }
+
+    #[test]
+    fn highlight_selection_matches() {
+        let mut term = Terminal::new(2, 12, 10, vec![]);
+        let mut host = OverlayHost::default();
+        term.advance_bytes(b"foo bar foo\r\nfood", &mut host);
+
+        // Nothing is highlighted without a selection
+        toggle_selection_matches(&mut term).unwrap();
+        assert!(!term.has_highlights(SELECTION_MATCHES));
+
+        // Double click to select the first word
+        for kind in &[
+            MouseEventKind::Press,
+            MouseEventKind::Release,
+            MouseEventKind::Press,
+            MouseEventKind::Release,
+        ] {
+            let event = MouseEvent {
+                kind: *kind,
+                x: 1,
+                y: 0,
+                x_pixel: 0,
+                y_pixel: 0,
+                button: MouseButton::Left,
+                modifiers: KeyModifiers::default(),
+            };
+            term.mouse_event(event, &mut host).unwrap();
+        }
+        assert_eq!(Renderable::get_selection_text(&term), "foo");
+
+        toggle_selection_matches(&mut term).unwrap();
+        let columns = |term: &Terminal, line_idx| -> Vec<std::ops::Range<usize>> {
+            Renderable::highlights_for_line(term, line_idx)
+                .into_iter()
+                .map(|h| h.columns)
+                .collect()
+        };
+        assert_eq!(columns(&term, 0), vec![0..3, 8..11]);
+        assert_eq!(columns(&term, 1), vec![0..3]);
+
+        toggle_selection_matches(&mut term).unwrap();
+        assert!(columns(&term, 0).is_empty());
+    }
+}
@@ -1,24 +1,28 @@
 use crate::config::Config;
-use crate::font::{FontConfiguration, FontSystemSelection};
 use crate::frontend::glium::window::GliumTerminalWindow;
+use crate::frontend::guicommon::displays::Display;
+use crate::frontend::guicommon::framepacer::{earliest, FramePacer};
+use crate::frontend::guicommon::idle;
 use crate::frontend::guicommon::window::TerminalWindow;
 use crate::frontend::FrontEnd;
-use crate::mux::tab::Tab;
+use crate::mux::tab::{Tab, TabId};
+use crate::mux::window::WindowId as MuxWindowId;
 use crate::mux::{Mux, SessionTerminated};
 use crate::spawn_tab;
 use failure::Error;
 use glium;
 use glium::glutin::EventsLoopProxy;
 use glium::glutin::WindowId;
-use promise::{Executor, Future, SpawnFunc};
-use std::cell::RefCell;
+use promise::task::spawn_into_gui;
+use promise::timer::CoalescingTimer;
+use promise::{Executor, SpawnFunc};
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::collections::VecDeque;
 use std::rc::Rc;
 use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError};
 use std::sync::Arc;
-use std::thread;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 
 /// The GuiSender is used as a handle that allows sending SpawnFunc
 /// instances to be executed on the gui thread.
@@ -94,12 +98,20 @@ pub struct GuiEventLoop {
     gui_tx: Arc<GuiSender>,
     gui_rx: Receiver<SpawnFunc>,
     gui_thread_sends: RefCell<VecDeque<SpawnFunc>>,
-    tick_rx: Receiver<()>,
+    /// Used to wake up the event loop when the next frame or blink
+    /// is due, as glutin has no native timer facility
+    wakeup_timer: RefCell<CoalescingTimer>,
+    pacer: FramePacer,
     mux: Rc<Mux>,
+    /// The monitors that were attached when they were last sampled
+    displays: RefCell<Vec<Display>>,
+    displays_sampled: Cell<Instant>,
 }
 
-const TICK_INTERVAL: Duration = Duration::from_millis(50);
 const MAX_POLL_LOOP_DURATION: Duration = Duration::from_millis(500);
+/// glutin doesn't report monitors being attached or detached, so the
+/// set of monitors is sampled at most this often while events arrive
+const DISPLAY_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
 
 pub struct GlutinFrontEnd {
     event_loop: Rc<GuiEventLoop>,
@@ -126,6 +138,7 @@ impl FrontEnd for GlutinFrontEnd {
         // This convoluted run() signature is present because of this issue:
         // https://github.com/tomaka/winit/issues/413
         let myself = &self.event_loop;
+        idle::start();
         loop {
             // Check the window count; if after processing the futures there
             // are no windows left, then we are done.
@@ -139,17 +152,12 @@ impl FrontEnd for GlutinFrontEnd {
 
             myself.run_event_loop()?;
             myself.process_gui_exec()?;
-            myself.process_tick()?;
+            myself.process_frame();
         }
     }
 
-    fn spawn_new_window(
-        &self,
-        config: &Arc<Config>,
-        fontconfig: &Rc<FontConfiguration>,
-        tab: &Rc<Tab>,
-    ) -> Result<(), Error> {
-        let window = GliumTerminalWindow::new(&self.event_loop, fontconfig, config, tab)?;
+    fn spawn_new_window(&self, config: &Arc<Config>, tab: &Rc<Tab>) -> Result<(), Error> {
+        let window = GliumTerminalWindow::new(&self.event_loop, config, tab)?;
 
         self.event_loop.add_window(window)
     }
@@ -161,28 +169,17 @@ impl GuiEventLoop {
 
         let (gui_tx, gui_rx) = GuiSender::new(event_loop.create_proxy());
 
-        // The glutin/glium plumbing has no native tick/timer stuff, so
-        // we implement one using a thread.  Nice.
-        let proxy = event_loop.create_proxy();
-        let (tick_tx, tick_rx) = mpsc::channel();
-        thread::spawn(move || loop {
-            std::thread::sleep(TICK_INTERVAL);
-            if tick_tx.send(()).is_err() {
-                return;
-            }
-            if proxy.wakeup().is_err() {
-                return;
-            }
-        });
-
         Ok(Self {
             gui_rx,
             gui_tx: Arc::new(gui_tx),
             gui_thread_sends: RefCell::new(VecDeque::new()),
-            tick_rx,
+            wakeup_timer: RefCell::new(CoalescingTimer::new()),
+            pacer: FramePacer::new(mux.config().max_fps),
             event_loop: RefCell::new(event_loop),
             windows: Rc::new(RefCell::new(Default::default())),
             mux: Rc::clone(mux),
+            displays: RefCell::new(vec![]),
+            displays_sampled: Cell::new(Instant::now()),
         })
     }
 
@@ -207,37 +204,54 @@ impl GuiEventLoop {
     }
 
     pub fn register_tab(&self, tab: &Rc<Tab>) -> Result<(), Error> {
-        self.mux.add_tab(self.gui_executor(), tab)
+        self.mux.add_tab(tab)
     }
 
-    fn do_spawn_new_window(
-        &self,
-        config: &Arc<Config>,
-        fonts: &Rc<FontConfiguration>,
-    ) -> Result<(), Error> {
-        let tab = spawn_tab(&config, None)?;
-        self.mux.add_tab(self.gui_executor(), &tab)?;
+    fn do_spawn_new_window(&self, config: &Arc<Config>) -> Result<(), Error> {
+        let tab = spawn_tab(&config, config.build_prog(None)?, false)?;
+        self.mux.add_tab(&tab)?;
         let events = Self::get().expect("to be called on gui thread");
-        let window = GliumTerminalWindow::new(&events, &fonts, &config, &tab)?;
+        let window = GliumTerminalWindow::new(&events, &config, &tab)?;
 
         events.add_window(window)
     }
 
     pub fn schedule_spawn_new_window(&self, config: &Arc<Config>) {
         let config = Arc::clone(config);
-        Future::with_executor(
-            GlutinGuiExecutor {
-                tx: self.gui_tx.clone(),
-            },
-            move || {
-                let myself = Self::get().expect("to be called on gui thread");
-                let fonts = Rc::new(FontConfiguration::new(
-                    Arc::clone(&config),
-                    FontSystemSelection::get_default(),
-                ));
-                myself.do_spawn_new_window(&config, &fonts)
-            },
-        );
+        spawn_into_gui(move || {
+            let myself = Self::get().expect("to be called on gui thread");
+            myself.do_spawn_new_window(&config)
+        });
+    }
+
+    /// Create a new window to hold an existing tab that has been
+    /// detached from its previous window
+    pub fn schedule_window_for_tab(&self, config: &Arc<Config>, tab_id: TabId) {
+        let config = Arc::clone(config);
+        spawn_into_gui(move || {
+            let myself = Self::get().expect("to be called on gui thread");
+            let mux = Mux::get().unwrap();
+            let tab = mux
+                .get_tab(tab_id)
+                .ok_or_else(|| format_err!("no such tab {}", tab_id))?;
+            let window = GliumTerminalWindow::new(&myself, &config, &tab)?;
+            myself.add_window(window)
+        });
+    }
+
+    /// Have the window that hosts the mux window `window_id` adopt
+    /// a tab that was moved into it from another window
+    pub fn schedule_tab_moved(&self, window_id: MuxWindowId) {
+        spawn_into_gui(move || {
+            let myself = Self::get().expect("to be called on gui thread");
+            let mut windows = myself.windows.borrow_mut();
+            for window in windows.by_id.values_mut() {
+                if window.get_mux_window_id() == window_id {
+                    window.adopt_moved_tab()?;
+                }
+            }
+            Ok(())
+        });
     }
 
     pub fn with_window<F: Send + 'static + Fn(&mut TerminalWindow) -> Result<(), Error>>(
@@ -245,20 +259,15 @@ impl GuiEventLoop {
         window_id: WindowId,
         func: F,
     ) {
-        Future::with_executor(
-            GlutinGuiExecutor {
-                tx: self.gui_tx.clone(),
-            },
-            move || {
-                let myself = Self::get().expect("to be called on gui thread");
-                let mut windows = myself.windows.borrow_mut();
-                if let Some(window) = windows.by_id.get_mut(&window_id) {
-                    func(window)
-                } else {
-                    bail!("no such window {:?}", window_id);
-                }
-            },
-        );
+        spawn_into_gui(move || {
+            let myself = Self::get().expect("to be called on gui thread");
+            let mut windows = myself.windows.borrow_mut();
+            if let Some(window) = windows.by_id.get_mut(&window_id) {
+                func(window)
+            } else {
+                bail!("no such window {:?}", window_id);
+            }
+        });
     }
 
     /// Add a window to the event loop and run it.
@@ -278,6 +287,7 @@ impl GuiEventLoop {
         use glium::glutin::Event;
         let result = match *event {
             Event::WindowEvent { window_id, .. } => {
+                self.check_display_topology();
                 let dead = match self.windows.borrow_mut().by_id.get_mut(&window_id) {
                     Some(window) => match window.dispatch_event(event) {
                         Ok(_) => None,
@@ -300,27 +310,47 @@ impl GuiEventLoop {
         Ok(result)
     }
 
+    /// Samples the attached monitors, and if they have changed since they
+    /// were last sampled, lets each window adapt to the new arrangement
+    fn check_display_topology(&self) {
+        let now = Instant::now();
+        if now.duration_since(self.displays_sampled.get()) < DISPLAY_SAMPLE_INTERVAL {
+            return;
+        }
+        self.displays_sampled.set(now);
+
+        let mut windows = self.windows.borrow_mut();
+        let displays = match windows.by_id.values().next() {
+            Some(window) => window.displays(),
+            None => return,
+        };
+        let previous = self.displays.replace(displays.clone());
+        if previous.is_empty() || previous == displays {
+            return;
+        }
+        eprintln!("monitors changed: {:?}", displays);
+        for window in windows.by_id.values_mut() {
+            if let Err(err) = window.display_topology_changed(&displays) {
+                eprintln!("while adapting to the new monitors: {}", err);
+            }
+        }
+    }
+
     /// Spawns a future that will gracefully shut down the resources associated
     /// with the specified window.
     fn schedule_window_close(&self, window_id: WindowId) -> Result<(), Error> {
-        Future::with_executor(
-            GlutinGuiExecutor {
-                tx: self.gui_tx.clone(),
-            },
-            move || {
-                let events = Self::get().expect("to be called on gui thread");
-                let mut windows = events.windows.borrow_mut();
+        spawn_into_gui(move || {
+            let events = Self::get().expect("to be called on gui thread");
+            let mut windows = events.windows.borrow_mut();
 
-                windows.by_id.remove(&window_id);
-                Ok(())
-            },
-        );
+            windows.by_id.remove(&window_id);
+            Ok(())
+        });
 
         Ok(())
     }
 
     /// Run through all of the windows and cause them to paint if they need it.
-    /// This happens ~50ms or so.
     fn do_paint(&self) {
         for window in &mut self.windows.borrow_mut().by_id.values_mut() {
             window.paint_if_needed().unwrap();
@@ -351,19 +381,41 @@ impl GuiEventLoop {
         }
     }
 
-    fn process_tick(&self) -> Result<(), Error> {
-        loop {
-            match self.tick_rx.try_recv() {
-                Ok(_) => {
-                    self.test_for_child_exit();
-                    self.do_paint();
-                }
-                Err(TryRecvError::Empty) => return Ok(()),
-                Err(err) => bail!("tick_rx disconnected {:?}", err),
-            }
+    /// Called after the event loop wakes up to paint any output that
+    /// has arrived, paced by `max_fps`, and to arrange to be woken up
+    /// again when the next frame or blink is due.
+    fn process_frame(&self) {
+        self.test_for_child_exit();
+
+        if self.mux.take_output_pending() || self.needs_blink_repaint() {
+            self.pacer.request_frame();
+        }
+        if self.pacer.take_due_frame() {
+            self.do_paint();
+        }
+
+        let mut wakeup = self.pacer.time_until_frame();
+        for window in self.windows.borrow_mut().by_id.values_mut() {
+            wakeup = earliest(wakeup, window.time_until_blink_change());
+        }
+        if let Some(delay) = wakeup {
+            // Running anything via the gui executor wakes up the event
+            // loop, which will then call us again
+            self.wakeup_timer
+                .borrow_mut()
+                .schedule(self.gui_executor(), delay, || {});
         }
     }
 
+    /// Returns true if any window has blinking text that needs repainting
+    fn needs_blink_repaint(&self) -> bool {
+        self.windows
+            .borrow_mut()
+            .by_id
+            .values_mut()
+            .any(|window| window.needs_blink_repaint())
+    }
+
     fn test_for_child_exit(&self) {
         let window_ids: Vec<WindowId> = self
             .windows
@@ -2,19 +2,26 @@
 
 use crate::config::Config;
 use crate::failure::Error;
-use crate::font::FontConfiguration;
+use crate::font::{FontConfiguration, FontSystemSelection};
 use crate::frontend::glium::glutinloop::GuiEventLoop;
+use crate::frontend::guicommon::displays::{clamp_to_displays, Display};
 use crate::frontend::guicommon::host::{HostHelper, HostImpl, TabHost};
+use crate::frontend::guicommon::idle;
 use crate::frontend::guicommon::window::{Dimensions, TerminalWindow};
+use crate::keyassignment::KeyAssignment;
+use crate::keyremap::RemappedKey;
+use crate::mux::latency;
 use crate::mux::tab::{Tab, TabId};
 use crate::mux::window::WindowId;
 use crate::mux::{Mux, SessionTerminated};
 use crate::opengl::render::Renderer;
 use glium;
-use glium::glutin::dpi::{LogicalPosition, LogicalSize, PhysicalPosition, PhysicalSize};
-use glium::glutin::{self, ElementState, MouseCursor};
+use glium::glutin::dpi::{LogicalPosition, PhysicalPosition, PhysicalSize};
+use glium::glutin::{self, ElementState, GlContext, MouseCursor};
+use std::io::Write;
 use std::rc::Rc;
 use std::sync::Arc;
+use std::time::Instant;
 use term;
 use term::KeyCode;
 use term::KeyModifiers;
@@ -120,6 +127,23 @@ impl TerminalWindow for GliumTerminalWindow {
     fn deregister_tab(&mut self, _tab_id: TabId) -> Result<(), Error> {
         Ok(())
     }
+    fn spawn_window_for_tab(&mut self, tab_id: TabId) -> Result<(), Error> {
+        self.event_loop
+            .schedule_window_for_tab(&self.config, tab_id);
+        Ok(())
+    }
+    fn notify_tab_moved(&mut self, window_id: WindowId) -> Result<(), Error> {
+        self.event_loop.schedule_tab_moved(window_id);
+        Ok(())
+    }
+
+    fn perform_key_assignment(&mut self, assignment: &KeyAssignment) -> Result<(), Error> {
+        let mux = Mux::get().unwrap();
+        match mux.get_active_tab_for_window(self.mux_window_id) {
+            Some(tab) => self.host.perform_key_assignment(&*tab, assignment),
+            None => Ok(()),
+        }
+    }
 
     fn get_dimensions(&self) -> Dimensions {
         Dimensions {
@@ -174,13 +198,19 @@ impl TerminalWindow for GliumTerminalWindow {
             .gl_window()
             .get_inner_size()
             .ok_or_else(|| format_err!("failed to get inner window size"))?;
-        let dpi_scale = self.host.display.gl_window().get_hidpi_factor();
-        let (width, height): (u32, u32) = size.to_physical(dpi_scale).into();
+        let platform_scale = self.host.display.gl_window().get_hidpi_factor();
+        let (width, height): (u32, u32) = size.to_physical(platform_scale).into();
+        let dpi_scale = self.config.effective_dpi_scale(platform_scale);
         eprintln!(
             "resize {}x{}@{} -> {}x{}@{}",
             self.width, self.height, old_dpi_scale, width, height, dpi_scale
         );
         if (old_dpi_scale - dpi_scale).abs() >= std::f64::EPSILON {
+            // The window moved to a monitor with a different dpi.  The
+            // inner size is reported in logical units, so the physical
+            // size computed above already reflects the new dpi; rescale
+            // the fonts and resize the window so that it keeps the same
+            // number of rows and columns.
             self.scaling_changed(None, Some(dpi_scale), width as u16, height as u16)?;
         } else {
             self.resize_surfaces(width as u16, height as u16, false)?;
@@ -192,18 +222,31 @@ impl TerminalWindow for GliumTerminalWindow {
 impl GliumTerminalWindow {
     pub fn new(
         event_loop: &Rc<GuiEventLoop>,
-        fonts: &Rc<FontConfiguration>,
         config: &Arc<Config>,
         tab: &Rc<Tab>,
     ) -> Result<GliumTerminalWindow, Error> {
-        let palette = config
-            .colors
-            .as_ref()
-            .map(|p| p.clone().into())
-            .unwrap_or_else(term::color::ColorPalette::default);
-
         let (physical_rows, physical_cols) = tab.renderer().physical_dimensions();
 
+        // Each window has its own font configuration so that changing
+        // the font size in one window doesn't affect the others
+        let fonts = Rc::new(FontConfiguration::new(
+            Arc::clone(config),
+            FontSystemSelection::get_default(),
+        ));
+
+        // Start out with the scaling of the monitor that the window is
+        // most likely to be opened on; if it lands on a monitor with a
+        // different dpi then check_for_resize will adjust it.
+        // The platform scale is still needed to convert between the
+        // logical and physical sizes that glutin deals in.
+        let platform_scale = event_loop
+            .event_loop
+            .borrow()
+            .get_primary_monitor()
+            .get_hidpi_factor();
+        let dpi_scale = config.effective_dpi_scale(platform_scale);
+        fonts.change_scaling(1.0, dpi_scale);
+
         let metrics = fonts.default_font_metrics()?;
         let (cell_height, cell_width) = (
             metrics.cell_height.ceil() as usize,
@@ -213,15 +256,19 @@ impl GliumTerminalWindow {
         let width = cell_width * physical_cols;
         let height = cell_height * physical_rows;
 
-        let logical_size = LogicalSize::new(width as f64, height as f64);
-        eprintln!("make window with {}x{}", width, height);
+        let logical_size =
+            PhysicalSize::new(width as f64, height as f64).to_logical(platform_scale);
+        eprintln!("make window with {}x{}@{}", width, height, dpi_scale);
 
         let display = {
             let pref_context = glutin::ContextBuilder::new()
                 .with_vsync(true)
                 .with_pixel_format(24, 8);
             let window = glutin::WindowBuilder::new()
-                .with_min_dimensions(LogicalSize::new(cell_width as f64, cell_height as f64))
+                .with_min_dimensions(
+                    PhysicalSize::new(cell_width as f64, cell_height as f64)
+                        .to_logical(platform_scale),
+                )
                 .with_dimensions(logical_size)
                 .with_window_icon(Some(glutin::Icon::from_bytes(include_bytes!(
                     "../../../assets/icon/terminal.png"
@@ -233,6 +280,9 @@ impl GliumTerminalWindow {
             glium::Display::new(window, pref_context, &*mut_loop)
                 .map_err(|e| format_err!("{:?}", e))?
         };
+        // glium doesn't expose GL_MAX_TEXTURE_SIZE, so load the gl
+        // bindings that the texture atlas uses to query it
+        gl::load_with(|s| display.gl_window().get_proc_address(s) as *const _);
         let window_position = display.gl_window().get_position();
 
         let host = HostImpl::new(Host {
@@ -247,7 +297,7 @@ impl GliumTerminalWindow {
 
         let width = width as u16;
         let height = height as u16;
-        let renderer = Renderer::new(&host.display, width, height, fonts, palette)?;
+        let renderer = Renderer::new(&host.display, width, height, &fonts, config)?;
 
         let mux = Mux::get().unwrap();
         let mux_window_id = mux.add_new_window_with_tab(tab)?;
@@ -256,7 +306,7 @@ impl GliumTerminalWindow {
             host,
             event_loop: Rc::clone(event_loop),
             config: Arc::clone(config),
-            fonts: Rc::clone(fonts),
+            fonts: Rc::clone(&fonts),
             renderer,
             width,
             height,
@@ -274,6 +324,58 @@ impl GliumTerminalWindow {
         self.host.display.gl_window().id()
     }
 
+    /// Returns the monitors that are attached, ordered by position
+    pub fn displays(&self) -> Vec<Display> {
+        let mut displays: Vec<Display> = self
+            .host
+            .display
+            .gl_window()
+            .get_available_monitors()
+            .map(|monitor| {
+                let position = monitor.get_position();
+                let size = monitor.get_dimensions();
+                Display {
+                    x: position.x as i32,
+                    y: position.y as i32,
+                    width: size.width as u32,
+                    height: size.height as u32,
+                    scale: monitor.get_hidpi_factor(),
+                }
+            })
+            .collect();
+        displays.sort_by_key(|d| (d.x, d.y));
+        displays
+    }
+
+    /// Called when monitors have been attached or detached, or have
+    /// changed resolution.  If the window is no longer visible it is
+    /// moved back onto the nearest monitor, and its scaling is
+    /// re-evaluated in case the monitor that it is on has changed.
+    pub fn display_topology_changed(&mut self, displays: &[Display]) -> Result<(), Error> {
+        if self.host.is_fullscreen.is_none() {
+            let window = self.host.display.gl_window();
+            let platform_scale = window.get_hidpi_factor();
+            if let (Some(position), Some(size)) = (window.get_position(), window.get_outer_size()) {
+                let position = position.to_physical(platform_scale);
+                let size = size.to_physical(platform_scale);
+                if let Some((x, y)) = clamp_to_displays(
+                    displays,
+                    position.x as i32,
+                    position.y as i32,
+                    size.width as u32,
+                    size.height as u32,
+                ) {
+                    eprintln!("moving window back onto a visible monitor at {},{}", x, y);
+                    window.set_position(
+                        PhysicalPosition::new(f64::from(x), f64::from(y))
+                            .to_logical(platform_scale),
+                    );
+                }
+            }
+        }
+        self.check_for_resize()
+    }
+
     fn decode_modifiers(state: glium::glutin::ModifiersState) -> term::KeyModifiers {
         let mut mods = Default::default();
         if state.shift {
@@ -304,19 +406,27 @@ impl GliumTerminalWindow {
 
         self.last_mouse_coords = position;
         let (x, y): (i32, i32) = position.into();
-        tab.mouse_event(
-            term::MouseEvent {
-                kind: MouseEventKind::Move,
-                button: MouseButton::None,
-                x: (x as usize / self.cell_width) as usize,
-                y: (y as usize / self.cell_height) as i64,
-                modifiers: Self::decode_modifiers(modifiers),
-            },
-            &mut TabHost::new(&mut *tab.writer(), &mut self.host),
-        )?;
+        let event = term::MouseEvent {
+            kind: MouseEventKind::Move,
+            button: MouseButton::None,
+            x: (x as usize / self.cell_width) as usize,
+            y: (y as usize / self.cell_height) as i64,
+            x_pixel: x.max(0) as usize,
+            y_pixel: y.max(0) as usize,
+            modifiers: Self::decode_modifiers(modifiers),
+        };
+        let dims = self.get_dimensions();
+        if self
+            .host
+            .scrollbar_mouse_event(&*tab, &event, x as f32, y as f32, &dims)
+        {
+            return self.paint_if_needed();
+        }
+        tab.mouse_event(event, &mut TabHost::new(&mut *tab.writer(), &mut self.host))?;
         // Deliberately not forcing a paint on mouse move as it
-        // makes selection feel sluggish
-        // self.paint_if_needed()?;
+        // makes selection feel sluggish; schedule a paced frame
+        // for any change to the selection instead
+        self.schedule_paint_if_dirty();
 
         // When hovering over a hyperlink, show an appropriate
         // mouse cursor to give the cue that it is clickable
@@ -343,24 +453,31 @@ impl GliumTerminalWindow {
             None => return Ok(()),
         };
 
-        tab.mouse_event(
-            term::MouseEvent {
-                kind: match state {
-                    ElementState::Pressed => MouseEventKind::Press,
-                    ElementState::Released => MouseEventKind::Release,
-                },
-                button: match button {
-                    glutin::MouseButton::Left => MouseButton::Left,
-                    glutin::MouseButton::Right => MouseButton::Right,
-                    glutin::MouseButton::Middle => MouseButton::Middle,
-                    glutin::MouseButton::Other(_) => return Ok(()),
-                },
-                x: (self.last_mouse_coords.x as usize / self.cell_width) as usize,
-                y: (self.last_mouse_coords.y as usize / self.cell_height) as i64,
-                modifiers: Self::decode_modifiers(modifiers),
+        let event = term::MouseEvent {
+            kind: match state {
+                ElementState::Pressed => MouseEventKind::Press,
+                ElementState::Released => MouseEventKind::Release,
             },
-            &mut TabHost::new(&mut *tab.writer(), &mut self.host),
-        )?;
+            button: match button {
+                glutin::MouseButton::Left => MouseButton::Left,
+                glutin::MouseButton::Right => MouseButton::Right,
+                glutin::MouseButton::Middle => MouseButton::Middle,
+                glutin::MouseButton::Other(_) => return Ok(()),
+            },
+            x: (self.last_mouse_coords.x as usize / self.cell_width) as usize,
+            y: (self.last_mouse_coords.y as usize / self.cell_height) as i64,
+            x_pixel: self.last_mouse_coords.x.max(0.0) as usize,
+            y_pixel: self.last_mouse_coords.y.max(0.0) as usize,
+            modifiers: Self::decode_modifiers(modifiers),
+        };
+        let dims = self.get_dimensions();
+        let (x, y) = (
+            self.last_mouse_coords.x as f32,
+            self.last_mouse_coords.y as f32,
+        );
+        if !self.host.scrollbar_mouse_event(&*tab, &event, x, y, &dims) {
+            tab.mouse_event(event, &mut TabHost::new(&mut *tab.writer(), &mut self.host))?;
+        }
         self.paint_if_needed()?;
 
         Ok(())
@@ -415,6 +532,8 @@ impl GliumTerminalWindow {
                     button,
                     x: (self.last_mouse_coords.x as usize / self.cell_width) as usize,
                     y: (self.last_mouse_coords.y as usize / self.cell_height) as i64,
+                    x_pixel: self.last_mouse_coords.x.max(0.0) as usize,
+                    y_pixel: self.last_mouse_coords.y.max(0.0) as usize,
                     modifiers: Self::decode_modifiers(modifiers),
                 },
                 &mut TabHost::new(&mut *tab.writer(), &mut self.host),
@@ -594,6 +713,7 @@ impl GliumTerminalWindow {
             V::F13 => KeyCode::Function(13),
             V::F14 => KeyCode::Function(14),
             V::F15 => KeyCode::Function(15),
+            V::Capital => KeyCode::CapsLock,
             V::Insert => KeyCode::Insert,
             V::Home => KeyCode::Home,
             V::End => KeyCode::End,
@@ -623,6 +743,7 @@ impl GliumTerminalWindow {
     }
 
     fn key_event(&mut self, event: glium::glutin::KeyboardInput) -> Result<(), Error> {
+        let received = Instant::now();
         let mux = Mux::get().unwrap();
         let tab = match mux.get_active_tab_for_window(self.get_mux_window_id()) {
             Some(tab) => tab,
@@ -635,6 +756,15 @@ impl GliumTerminalWindow {
             // debug!("event {:?} -> {:?}", event, key);
             match event.state {
                 ElementState::Pressed => {
+                    let (key, mods) = match self.host.remapper().key_down(key, mods) {
+                        RemappedKey::Key(key, mods) => (key, mods),
+                        RemappedKey::Text(text) => {
+                            tab.send_text(&text)?;
+                            return self.paint_if_needed();
+                        }
+                        RemappedKey::Swallowed => return Ok(()),
+                    };
+
                     if mods == KeyModifiers::SUPER && key == KeyCode::Char('n') {
                         self.event_loop.schedule_spawn_new_window(&self.host.config);
                         return Ok(());
@@ -644,9 +774,15 @@ impl GliumTerminalWindow {
                         return Ok(());
                     }
 
+                    latency::key_received(received);
                     tab.key_down(key, mods)?;
+                    self.perform_pending_assignments()?;
+                }
+                ElementState::Released => {
+                    if let Some((key, mods)) = self.host.remapper().key_up(key, mods) {
+                        tab.key_up(key, mods)?;
+                    }
                 }
-                ElementState::Released => {}
             }
         } else {
             eprintln!("event {:?} with no mapping", event);
@@ -682,6 +818,20 @@ impl GliumTerminalWindow {
                 ..
             } => {
                 self.host.window_position = Some(position);
+                // The window may have moved to a monitor with a different
+                // dpi; not all systems deliver HiDpiFactorChanged when that
+                // happens, so re-check the scaling here too.
+                if !self.have_pending_resize_check {
+                    self.have_pending_resize_check = true;
+                    self.host.with_window(|win| win.check_for_resize());
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::Focused(focused),
+                ..
+            } => {
+                self.host.remapper().reset();
+                self.focus_changed(focused)?;
             }
             Event::WindowEvent {
                 event: WindowEvent::ReceivedCharacter(c),
@@ -706,6 +856,7 @@ impl GliumTerminalWindow {
                 event: WindowEvent::KeyboardInput { input, .. },
                 ..
             } => {
+                idle::note_activity();
                 self.key_event(input)?;
             }
             Event::WindowEvent {
@@ -717,6 +868,7 @@ impl GliumTerminalWindow {
                     },
                 ..
             } => {
+                idle::note_activity();
                 let dpi_scale = self.host.display.gl_window().get_hidpi_factor();
                 self.mouse_move(position.to_physical(dpi_scale), modifiers)?;
             }
@@ -730,6 +882,7 @@ impl GliumTerminalWindow {
                     },
                 ..
             } => {
+                idle::note_activity();
                 self.mouse_click(state, button, modifiers)?;
             }
             Event::WindowEvent {
@@ -739,6 +892,7 @@ impl GliumTerminalWindow {
                     },
                 ..
             } => {
+                idle::note_activity();
                 self.mouse_wheel(delta, modifiers)?;
             }
             Event::WindowEvent {
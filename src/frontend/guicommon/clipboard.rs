@@ -0,0 +1,185 @@
+//! Maintains a bounded history of the text that has been copied
+//! within wezterm, and provides an overlay that allows picking
+//! an older entry to paste.
+//! Also holds the internal registers, which allow copied text to be
+//! kept out of the system clipboard.
+use super::host::paste_to_tab;
+use super::overlay::{fuzzy_match, OverlayAction, OverlayDelegate};
+use crate::config::Config;
+use crate::mux::tab::TabId;
+use crate::mux::Mux;
+use failure::Error;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use term::{KeyCode, KeyModifiers};
+
+thread_local! {
+    static HISTORY: RefCell<VecDeque<String>> = RefCell::new(VecDeque::new());
+    static REGISTERS: RefCell<HashMap<String, String>> = RefCell::new(HashMap::new());
+}
+
+/// Store `text` in the named internal register
+pub fn set_register(name: &str, text: String) {
+    REGISTERS.with(|registers| registers.borrow_mut().insert(name.to_owned(), text));
+}
+
+/// Returns the content of the named internal register
+pub fn get_register(name: &str) -> Option<String> {
+    REGISTERS.with(|registers| registers.borrow().get(name).cloned())
+}
+
+/// Record `text` as the most recently copied item.
+/// If the same text was previously copied, it is moved to the front
+/// rather than being recorded twice.
+pub fn record_history(config: &Config, text: &str) {
+    if !config.enable_clipboard_history || text.is_empty() {
+        return;
+    }
+    HISTORY.with(|history| {
+        let mut history = history.borrow_mut();
+        history.retain(|item| item != text);
+        history.push_front(text.to_owned());
+        history.truncate(config.clipboard_history_size);
+    });
+}
+
+/// Returns a copy of the history, most recent item first
+pub fn get_history() -> Vec<String> {
+    HISTORY.with(|history| history.borrow().iter().cloned().collect())
+}
+
+pub struct ClipboardHistoryOverlay {
+    /// The tab into which the selected item will be pasted
+    tab_id: TabId,
+    items: Vec<String>,
+    filter: String,
+    selected: usize,
+}
+
+impl ClipboardHistoryOverlay {
+    pub fn new(tab_id: TabId) -> Self {
+        Self {
+            tab_id,
+            items: get_history(),
+            filter: String::new(),
+            selected: 0,
+        }
+    }
+
+    /// Returns the indices of the items that match the filter,
+    /// best match first
+    fn matches(&self) -> Vec<usize> {
+        let mut scored: Vec<(usize, usize)> = self
+            .items
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, item)| fuzzy_match(&self.filter, item).map(|score| (score, idx)))
+            .collect();
+        // The sort is stable, so equally scored items remain in
+        // most-recent-first order
+        scored.sort_by_key(|&(score, _)| score);
+        scored.into_iter().map(|(_, idx)| idx).collect()
+    }
+
+    fn paste_selected(&self) -> Result<(), Error> {
+        let matches = self.matches();
+        if let Some(&idx) = matches.get(self.selected) {
+            let mux = Mux::get().unwrap();
+            if let Some(tab) = mux.get_tab(self.tab_id) {
+                paste_to_tab(&*tab, self.items[idx].clone())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Produce a single line summary of `text` that fits in `width` columns
+fn summarize(text: &str, width: usize) -> String {
+    text.chars()
+        .map(|c| if c.is_control() { '\u{21b5}' } else { c })
+        .take(width)
+        .collect()
+}
+
+impl OverlayDelegate for ClipboardHistoryOverlay {
+    fn get_title(&self) -> String {
+        "Clipboard History".into()
+    }
+
+    fn render(&self, rows: usize, cols: usize) -> String {
+        let mut output = String::new();
+        output.push_str(&summarize(
+            "Type to filter, Enter to paste, Escape to cancel",
+            cols,
+        ));
+        output.push_str("\r\n");
+
+        if self.items.is_empty() {
+            output.push_str("\r\n");
+            output.push_str(&summarize("The clipboard history is empty", cols));
+        }
+
+        let matches = self.matches();
+        // Allow for the two header lines, and keep the selection visible
+        let avail = rows.saturating_sub(2).max(1);
+        let skip = if self.selected >= avail {
+            self.selected + 1 - avail
+        } else {
+            0
+        };
+
+        for (row, &idx) in matches.iter().enumerate().skip(skip).take(avail) {
+            output.push_str("\r\n");
+            let line = summarize(&self.items[idx], cols.saturating_sub(2));
+            if row == self.selected {
+                output.push_str(&format!("\x1b[7m> {}\x1b[0m", line));
+            } else {
+                output.push_str(&format!("  {}", line));
+            }
+        }
+
+        // Place the cursor at the end of the filter text on the
+        // bottom line, which serves as the prompt
+        let prompt = summarize(&format!("> {}", self.filter), cols.saturating_sub(1));
+        output.push_str(&format!("\x1b[{};1H\x1b[2K{}", rows, prompt));
+
+        output
+    }
+
+    fn key_down(&mut self, key: KeyCode, mods: KeyModifiers) -> Result<OverlayAction, Error> {
+        match (key, mods) {
+            (KeyCode::Escape, _) | (KeyCode::Char('g'), KeyModifiers::CTRL) => {
+                return Ok(OverlayAction::Close);
+            }
+            (KeyCode::Enter, _) | (KeyCode::Char('\r'), _) | (KeyCode::Char('\n'), _) => {
+                self.paste_selected()?;
+                return Ok(OverlayAction::Close);
+            }
+            (KeyCode::UpArrow, _) | (KeyCode::Char('p'), KeyModifiers::CTRL) => {
+                self.selected = self.selected.saturating_sub(1);
+            }
+            (KeyCode::DownArrow, _) | (KeyCode::Char('n'), KeyModifiers::CTRL) => {
+                let limit = self.matches().len().saturating_sub(1);
+                self.selected = (self.selected + 1).min(limit);
+            }
+            (KeyCode::Backspace, _) | (KeyCode::Char('\x08'), _) | (KeyCode::Char('\x7f'), _) => {
+                self.filter.pop();
+                self.selected = 0;
+            }
+            (KeyCode::Char(c), KeyModifiers::NONE) | (KeyCode::Char(c), KeyModifiers::SHIFT)
+                if !c.is_control() =>
+            {
+                self.filter.push(c);
+                self.selected = 0;
+            }
+            _ => {}
+        }
+        Ok(OverlayAction::Continue)
+    }
+
+    fn send_paste(&mut self, text: &str) -> Result<(), Error> {
+        self.filter.push_str(&summarize(text, 256));
+        self.selected = 0;
+        Ok(())
+    }
+}
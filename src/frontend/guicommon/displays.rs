@@ -0,0 +1,110 @@
+//! Keeps windows reachable when the arrangement of monitors changes.
+//! When a monitor is unplugged, or a monitor changes resolution, a
+//! window can be left entirely outside of the area that is visible.
+//! The frontends sample the set of monitors, and when it changes
+//! they re-evaluate the scaling of each window and use
+//! `clamp_to_displays` to move lost windows back onto a monitor.
+//! All coordinates here are in physical pixels.
+
+/// Describes the area of the desktop covered by a monitor
+#[derive(Debug, Clone, PartialEq)]
+pub struct Display {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub scale: f64,
+}
+
+/// The amount of a window, in each direction, that must overlap a
+/// display for the window to be considered reachable by the mouse
+const MIN_VISIBLE: i64 = 64;
+
+/// Returns the length of the overlap of the spans `a` and `b`,
+/// given as (start, length)
+fn overlap(a: (i64, i64), b: (i64, i64)) -> i64 {
+    ((a.0 + a.1).min(b.0 + b.1) - a.0.max(b.0)).max(0)
+}
+
+/// If the window whose outer bounds are `x`, `y`, `width`, `height` is
+/// not sufficiently visible on any of `displays`, returns the position
+/// to which it should be moved: the nearest position that lies on the
+/// display that is closest to it.  Returns None if the window can stay
+/// where it is.
+pub fn clamp_to_displays(
+    displays: &[Display],
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+) -> Option<(i32, i32)> {
+    let horizontal = (i64::from(x), i64::from(width));
+    let vertical = (i64::from(y), i64::from(height));
+    let need_width = MIN_VISIBLE.min(i64::from(width));
+    let need_height = MIN_VISIBLE.min(i64::from(height));
+
+    let span = |d: &Display| {
+        (
+            (i64::from(d.x), i64::from(d.width)),
+            (i64::from(d.y), i64::from(d.height)),
+        )
+    };
+    let visible = displays.iter().any(|d| {
+        let (dx, dy) = span(d);
+        overlap(horizontal, dx) >= need_width && overlap(vertical, dy) >= need_height
+    });
+    if visible {
+        return None;
+    }
+
+    let center = |(start, len): (i64, i64)| start + len / 2;
+    let (wx, wy) = (center(horizontal), center(vertical));
+    let nearest = displays.iter().min_by_key(|d| {
+        let (dx, dy) = span(d);
+        let (cx, cy) = (center(dx) - wx, center(dy) - wy);
+        cx * cx + cy * cy
+    })?;
+
+    // Keep the top left corner, which holds the title bar, on the
+    // display even if the window is larger than it
+    let clamp =
+        |(pos, len): (i64, i64), (start, dlen): (i64, i64)| pos.min(start + dlen - len).max(start);
+    let (dx, dy) = span(nearest);
+    Some((clamp(horizontal, dx) as i32, clamp(vertical, dy) as i32))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn clamp() {
+        let display = |x, width| Display {
+            x,
+            y: 0,
+            width,
+            height: 1000,
+            scale: 1.0,
+        };
+        let displays = vec![display(0, 1000), display(1000, 2000)];
+
+        // Fully or sufficiently visible windows stay where they are
+        assert_eq!(clamp_to_displays(&displays, 10, 10, 500, 500), None);
+        assert_eq!(clamp_to_displays(&displays, 2900, 10, 500, 500), None);
+        assert_eq!(clamp_to_displays(&displays, -400, 10, 500, 500), None);
+
+        // The second display was unplugged
+        let displays = vec![display(0, 1000)];
+        assert_eq!(
+            clamp_to_displays(&displays, 1500, 200, 500, 500),
+            Some((500, 200))
+        );
+        // Windows that are larger than the display keep their title
+        // bar visible
+        assert_eq!(
+            clamp_to_displays(&displays, 1500, -600, 1500, 1500),
+            Some((0, 0))
+        );
+        assert_eq!(clamp_to_displays(&[], 1500, 200, 500, 500), None);
+    }
+}
@@ -0,0 +1,95 @@
+//! Asks the user whether the files that tabs have offered to download
+//! should be saved, and saves those that are accepted.
+use super::notify::post_notification;
+use super::overlay::{OverlayAction, OverlayDelegate};
+use crate::mux::transfer::Download;
+use crate::mux::window::WindowId;
+use failure::Error;
+use std::path::PathBuf;
+use term::{KeyCode, KeyModifiers};
+
+pub struct DownloadOverlay {
+    window_id: WindowId,
+    dir: PathBuf,
+    /// The downloads that are yet to be decided, the current one first
+    offers: Vec<Download>,
+    /// Describes what happened to each decided download
+    outcomes: Vec<String>,
+}
+
+impl DownloadOverlay {
+    pub fn new(window_id: WindowId, dir: PathBuf, offers: Vec<Download>) -> Self {
+        Self {
+            window_id,
+            dir,
+            offers,
+            outcomes: vec![],
+        }
+    }
+
+    /// Moves on from the current download, closing the overlay and
+    /// reporting the outcomes once they have all been decided
+    fn next(&mut self) -> OverlayAction {
+        self.offers.remove(0);
+        if !self.offers.is_empty() {
+            return OverlayAction::Continue;
+        }
+        if !self.outcomes.is_empty() {
+            post_notification(self.window_id, self.outcomes.join("\n"));
+        }
+        OverlayAction::Close
+    }
+}
+
+impl OverlayDelegate for DownloadOverlay {
+    fn get_title(&self) -> String {
+        "Download".into()
+    }
+
+    fn render(&self, rows: usize, _cols: usize) -> String {
+        let download = match self.offers.first() {
+            Some(download) => download,
+            None => return String::new(),
+        };
+        let mut output = format!(
+            "A program in tab {} has sent a file to download\r\n\r\n\
+             \x20 name: {}\r\n\
+             \x20 size: {} bytes\r\n\
+             \x20 into: {}\r\n",
+            download.tab_id,
+            download.name,
+            download.data.len(),
+            self.dir.display()
+        );
+        if self.offers.len() > 1 {
+            output.push_str(&format!(
+                "\r\n{} more waiting after this one\r\n",
+                self.offers.len() - 1
+            ));
+        }
+        output.push_str(&format!(
+            "\x1b[{};1H\x1b[7mSave it? Press y to save, n or Escape to discard\x1b[0m",
+            rows
+        ));
+        output
+    }
+
+    fn key_down(&mut self, key: KeyCode, _mods: KeyModifiers) -> Result<OverlayAction, Error> {
+        match key {
+            KeyCode::Char('y') | KeyCode::Char('Y') => {
+                let outcome = match self.offers[0].save(&self.dir) {
+                    Ok(path) => format!("Saved {}", path.display()),
+                    Err(err) => format!("Failed to save {}: {}", self.offers[0].name, err),
+                };
+                self.outcomes.push(outcome);
+                Ok(self.next())
+            }
+            KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Escape => {
+                self.outcomes
+                    .push(format!("Discarded {}", self.offers[0].name));
+                Ok(self.next())
+            }
+            _ => Ok(OverlayAction::Continue),
+        }
+    }
+}
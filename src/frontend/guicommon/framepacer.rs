@@ -0,0 +1,90 @@
+//! Coalesces repaint requests so that output from the terminal is
+//! painted promptly, but no more than `max_fps` times per second.
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+pub struct FramePacer {
+    /// The minimum time between two paced frames
+    frame_interval: Duration,
+    /// When the most recent paced frame was painted
+    last_frame: Cell<Option<Instant>>,
+    /// Whether a frame has been requested but not yet painted
+    pending: Cell<bool>,
+}
+
+impl FramePacer {
+    pub fn new(max_fps: u8) -> Self {
+        let max_fps = u64::from(max_fps.max(1));
+        Self {
+            frame_interval: Duration::from_micros(1_000_000 / max_fps),
+            last_frame: Cell::new(None),
+            pending: Cell::new(false),
+        }
+    }
+
+    /// Note that something has changed and should be painted
+    pub fn request_frame(&self) {
+        self.pending.set(true);
+    }
+
+    /// Returns how long to wait before the requested frame is due,
+    /// or None if no frame has been requested
+    pub fn time_until_frame(&self) -> Option<Duration> {
+        if !self.pending.get() {
+            return None;
+        }
+        Some(match self.last_frame.get() {
+            Some(last) => self
+                .frame_interval
+                .checked_sub(last.elapsed())
+                .unwrap_or_default(),
+            None => Duration::default(),
+        })
+    }
+
+    /// Returns true if a frame has been requested and is now due.
+    /// The caller is expected to paint when this returns true.
+    pub fn take_due_frame(&self) -> bool {
+        match self.time_until_frame() {
+            Some(delay) if delay == Duration::default() => {
+                self.pending.set(false);
+                self.last_frame.set(Some(Instant::now()));
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Returns the earlier of two optional delays
+pub fn earliest(a: Option<Duration>, b: Option<Duration>) -> Option<Duration> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pacing() {
+        let pacer = FramePacer::new(10);
+        assert_eq!(pacer.time_until_frame(), None);
+        assert!(!pacer.take_due_frame());
+
+        pacer.request_frame();
+        assert_eq!(pacer.time_until_frame(), Some(Duration::default()));
+        assert!(pacer.take_due_frame());
+        assert_eq!(pacer.time_until_frame(), None);
+
+        // A second request arriving immediately must wait for the
+        // remainder of the frame interval
+        pacer.request_frame();
+        let delay = pacer.time_until_frame().unwrap();
+        assert!(delay > Duration::default());
+        assert!(delay <= Duration::from_millis(100));
+        assert!(!pacer.take_due_frame());
+    }
+}
@@ -0,0 +1,162 @@
+//! High contrast rendering.  When the operating system's high contrast
+//! or increased contrast accessibility setting is turned on, or when
+//! `high_contrast = "Always"` is configured, the palette is replaced
+//! by white on black with saturated cursor and selection colors, text
+//! colors are adjusted to meet the WCAG AAA contrast ratio and the
+//! underline and strikethrough strokes are drawn more thickly.
+use crate::config::{Config, HighContrast};
+use std::sync::atomic::{AtomicBool, Ordering};
+use term::color::{ColorPalette, RgbColor};
+
+/// The WCAG AAA contrast ratio for normal text
+pub const MIN_CONTRAST: f32 = 7.0;
+
+/// The result of `probe_system_setting`; false until it completes
+static SYSTEM_HIGH_CONTRAST: AtomicBool = AtomicBool::new(false);
+
+/// Query the system high contrast setting in the background, as
+/// doing so may run a helper program.  This is called early during
+/// startup so that the result is known before the first window is
+/// created; a window that is created before the probe completes is
+/// rendered without high contrast.
+pub fn probe_system_setting() {
+    let spawned = std::thread::Builder::new()
+        .name("high-contrast-probe".into())
+        .spawn(|| {
+            SYSTEM_HIGH_CONTRAST.store(system_prefers_high_contrast(), Ordering::SeqCst);
+        });
+    if let Err(err) = spawned {
+        eprintln!("unable to query the high contrast setting: {}", err);
+    }
+}
+
+#[cfg(windows)]
+fn system_prefers_high_contrast() -> bool {
+    use std::mem;
+    use winapi::um::winuser::{
+        SystemParametersInfoW, HCF_HIGHCONTRASTON, HIGHCONTRASTW, SPI_GETHIGHCONTRAST,
+    };
+    unsafe {
+        let mut hc: HIGHCONTRASTW = mem::zeroed();
+        hc.cbSize = mem::size_of::<HIGHCONTRASTW>() as u32;
+        let ok = SystemParametersInfoW(
+            SPI_GETHIGHCONTRAST,
+            hc.cbSize,
+            &mut hc as *mut HIGHCONTRASTW as *mut _,
+            0,
+        );
+        ok != 0 && (hc.dwFlags & HCF_HIGHCONTRASTON) != 0
+    }
+}
+
+/// Returns true if running `argv` succeeds and prints `expected`
+#[cfg(unix)]
+fn command_output_is(argv: &[&str], expected: &str) -> bool {
+    let output = std::process::Command::new(argv[0])
+        .args(&argv[1..])
+        .output();
+    match output {
+        Ok(output) => {
+            output.status.success() && String::from_utf8_lossy(&output.stdout).trim() == expected
+        }
+        Err(_) => false,
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn system_prefers_high_contrast() -> bool {
+    command_output_is(
+        &[
+            "defaults",
+            "read",
+            "com.apple.universalaccess",
+            "increaseContrast",
+        ],
+        "1",
+    )
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn system_prefers_high_contrast() -> bool {
+    if let Ok(theme) = std::env::var("GTK_THEME") {
+        if theme.to_lowercase().contains("highcontrast") {
+            return true;
+        }
+    }
+    command_output_is(
+        &[
+            "gsettings",
+            "get",
+            "org.gnome.desktop.a11y.interface",
+            "high-contrast",
+        ],
+        "true",
+    )
+}
+
+/// Returns true if windows should be rendered in high contrast
+pub fn is_enabled(config: &Config) -> bool {
+    resolve(
+        config.high_contrast,
+        SYSTEM_HIGH_CONTRAST.load(Ordering::SeqCst),
+    )
+}
+
+fn resolve(setting: HighContrast, system: bool) -> bool {
+    match setting {
+        HighContrast::Always => true,
+        HighContrast::Never => false,
+        HighContrast::FollowSystem => system,
+    }
+}
+
+/// Replace the default colors of `palette` with high contrast ones.
+/// The ANSI colors are retained; the renderer lightens them as needed
+/// to meet `MIN_CONTRAST` against the background.
+pub fn apply_to_palette(palette: &mut ColorPalette) {
+    let white = RgbColor::new(0xff, 0xff, 0xff);
+    let black = RgbColor::new(0, 0, 0);
+    let yellow = RgbColor::new(0xff, 0xff, 0);
+    let cyan = RgbColor::new(0, 0xff, 0xff);
+
+    palette.foreground = white;
+    palette.background = black;
+    palette.cursor_fg = black;
+    palette.cursor_bg = yellow;
+    palette.selection_fg = black;
+    palette.selection_bg = cyan;
+    palette.scrollbar_thumb = white;
+    // Black text would otherwise be invisible on the background
+    palette.colors.0[0] = RgbColor::new(0x80, 0x80, 0x80);
+}
+
+/// The thickness, in pixels, of underline and strikethrough strokes
+/// for cells that are `cell_height` pixels tall
+pub fn line_thickness(high_contrast: bool, cell_height: f64) -> usize {
+    if high_contrast {
+        ((cell_height / 10.0).ceil() as usize).max(2)
+    } else {
+        1
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn resolution() {
+        for &system in &[false, true] {
+            assert!(resolve(HighContrast::Always, system));
+            assert!(!resolve(HighContrast::Never, system));
+            assert_eq!(resolve(HighContrast::FollowSystem, system), system);
+        }
+    }
+
+    #[test]
+    fn thickness() {
+        assert_eq!(line_thickness(false, 40.0), 1);
+        assert_eq!(line_thickness(true, 12.0), 2);
+        assert_eq!(line_thickness(true, 41.0), 5);
+    }
+}
@@ -1,15 +1,76 @@
-use super::window::TerminalWindow;
-use crate::frontend::gui_executor;
+use super::clipboard::{get_register, record_history, set_register, ClipboardHistoryOverlay};
+use super::inspector::TabInspectorOverlay;
+use super::launcher::LauncherOverlay;
+use super::linknav::LinkNavigationOverlay;
+use super::linkresolve::resolve_link;
+use super::notify::post_notification;
+use super::quickselect::QuickSelectOverlay;
+use super::scrollbar::{ScrollDrag, ScrollbarArea};
+use super::window::{Dimensions, GuiWindow, TerminalWindow};
+use crate::config::{Config, PasteTransform};
+use crate::frontend::dispatch;
+use crate::keyassignment::{KeyAssignment, KeyMap};
+use crate::keyremap::KeyRemapper;
 use crate::mux::tab::{Tab, TabId};
+use crate::mux::window::WindowId;
 use crate::mux::Mux;
 use clipboard::{ClipboardContext, ClipboardProvider};
-use failure::Error;
-use promise::Future;
+use failure::{err_msg, Error};
+use promise::task::{gui_executor, spawn_background, spawn_into_gui};
+use promise::timer::{schedule_timer, TimerHandle};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::Write;
 use std::ops::{Deref, DerefMut};
+use std::process::{Command, Stdio};
 use std::sync::{Arc, Mutex};
-use term::{KeyCode, KeyModifiers};
+use std::time::{Duration, Instant};
+use term::{KeyCode, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
 use termwiz::hyperlink::Hyperlink;
 
+/// Open `uri` using `command`, which is a program and its arguments,
+/// or using the system default handler if there is no command
+fn open_hyperlink(command: Option<&Vec<String>>, uri: &str) -> Result<(), Error> {
+    let status = match command {
+        Some(command) => std::process::Command::new(&command[0])
+            .args(&command[1..])
+            .arg(uri)
+            .status()
+            .map_err(|e| format_err!("running {:?}: {}", command, e))?,
+        None => open::that(uri)?,
+    };
+    if !status.success() {
+        bail!("the opener exited with {}", status);
+    }
+    Ok(())
+}
+
+/// Open `uri` in the way that `config` specifies for its scheme.
+/// Links to files are resolved against `cwd`, the working directory
+/// of the tab that the link was followed in.
+/// Failures are reported as notifications in `window_id`.
+pub fn open_uri(window_id: WindowId, config: &Config, uri: &str, cwd: Option<&str>) {
+    let uri = if config.hyperlink_resolve_paths {
+        resolve_link(uri, cwd)
+    } else {
+        uri.to_owned()
+    };
+    let command = config.hyperlink_command(&uri);
+    // The opener may take a while to run, so wait for it in
+    // the background and report back only if it fails
+    spawn_background(move || {
+        if let Err(err) = open_hyperlink(command.as_ref(), &uri) {
+            let message = format!("Failed to open {}: {}", uri, err);
+            eprintln!("{}", message);
+            spawn_into_gui(move || {
+                post_notification(window_id, message);
+                Ok(())
+            });
+        }
+        Ok(())
+    });
+}
+
 pub trait HostHelper {
     fn with_window<F: Send + 'static + Fn(&mut TerminalWindow) -> Result<(), Error>>(
         &self,
@@ -23,45 +84,246 @@ pub struct HostImpl<H: HostHelper> {
     /// macOS gets unhappy if we set up the clipboard too early,
     /// so we use an Option to defer it until we use it
     clipboard: Option<ClipboardContext>,
+    keys: KeyMap,
+    remapper: KeyRemapper,
+    /// Set while the scrollbar thumb is being dragged
+    scroll_drag: Option<ScrollDrag>,
 }
 
-const PASTE_CHUNK_SIZE: usize = 1024;
-
 struct Paste {
     tab_id: TabId,
     text: String,
     offset: usize,
+    chunk_size: usize,
+    delay: Duration,
+    /// The timer that will send the next chunk, so that it can
+    /// be cancelled along with the paste
+    timer: Option<TimerHandle>,
+    started: Instant,
 }
 
-fn schedule_next_paste(paste: &Arc<Mutex<Paste>>) {
-    let paste = Arc::clone(paste);
-    Future::with_executor(gui_executor().unwrap(), move || {
-        let mut locked = paste.lock().unwrap();
-        let mux = Mux::get().unwrap();
-        let tab = mux.get_tab(locked.tab_id).unwrap();
+/// Progress is only shown for pastes that are still running after
+/// this long, so that moderately sized pastes don't flash the title
+const PASTE_PROGRESS_DELAY: Duration = Duration::from_millis(500);
+
+thread_local! {
+    /// The pastes that are currently being trickled into their tabs
+    static PASTES: RefCell<HashMap<TabId, Arc<Mutex<Paste>>>> = RefCell::new(HashMap::new());
+}
+
+/// Returns the offset of the end of the chunk starting at `offset`,
+/// taking care not to split a multi-byte sequence
+fn chunk_end(text: &str, offset: usize, chunk_size: usize) -> usize {
+    let mut end = (offset + chunk_size).min(text.len());
+    while !text.is_char_boundary(end) {
+        end += 1;
+    }
+    end
+}
 
-        let remain = locked.text.len() - locked.offset;
-        let chunk = remain.min(PASTE_CHUNK_SIZE);
-        let text_slice = &locked.text[locked.offset..locked.offset + chunk];
-        tab.send_paste(text_slice).unwrap();
+fn send_next_chunk(paste: &Arc<Mutex<Paste>>) {
+    let mut locked = paste.lock().unwrap();
+    let tab_id = locked.tab_id;
 
-        if chunk < remain {
-            // There is more to send
-            locked.offset += chunk;
-            schedule_next_paste(&paste);
+    let current = PASTES.with(|pastes| pastes.borrow().get(&tab_id).map(Arc::clone));
+    match current {
+        Some(ref current) if Arc::ptr_eq(current, paste) => {}
+        // The paste was cancelled or superseded
+        _ => return,
+    }
+
+    let mux = Mux::get().unwrap();
+    let tab = match mux.get_tab(tab_id) {
+        Some(tab) => tab,
+        None => {
+            // The tab went away before we finished
+            PASTES.with(|pastes| pastes.borrow_mut().remove(&tab_id));
+            return;
         }
+    };
 
-        Ok(())
-    });
+    let end = chunk_end(&locked.text, locked.offset, locked.chunk_size);
+    if let Err(err) = tab.send_paste(&locked.text[locked.offset..end]) {
+        eprintln!("paste into tab {} failed: {}", tab_id, err);
+        PASTES.with(|pastes| pastes.borrow_mut().remove(&tab_id));
+        return;
+    }
+    locked.offset = end;
+
+    if locked.offset < locked.text.len() {
+        // There is more to send
+        locked.timer = schedule_next_paste(paste, locked.delay);
+    } else {
+        PASTES.with(|pastes| pastes.borrow_mut().remove(&tab_id));
+    }
 }
 
-fn trickle_paste(tab_id: TabId, text: String) {
+/// Arrange for the next chunk of `paste` to be sent after `delay`.
+/// Returns the handle of the timer used to implement the delay.
+fn schedule_next_paste(paste: &Arc<Mutex<Paste>>, delay: Duration) -> Option<TimerHandle> {
+    let paste = Arc::clone(paste);
+    if delay == Duration::from_millis(0) {
+        spawn_into_gui(move || {
+            send_next_chunk(&paste);
+            Ok(())
+        });
+        None
+    } else {
+        Some(schedule_timer(gui_executor().unwrap(), delay, move || {
+            send_next_chunk(&paste)
+        }))
+    }
+}
+
+fn trickle_paste(tab_id: TabId, text: String, offset: usize, config: &Config) {
+    let delay = Duration::from_millis(config.paste_chunk_delay_ms);
     let paste = Arc::new(Mutex::new(Paste {
         tab_id,
         text,
-        offset: PASTE_CHUNK_SIZE,
+        offset,
+        chunk_size: config.paste_chunk_size.max(1),
+        delay,
+        timer: None,
+        started: Instant::now(),
     }));
-    schedule_next_paste(&paste);
+    // Starting a new paste into a tab replaces any that is already
+    // in progress there
+    if let Some(previous) =
+        PASTES.with(|pastes| pastes.borrow_mut().insert(tab_id, Arc::clone(&paste)))
+    {
+        cancel_paste_timer(&previous);
+    }
+    let timer = schedule_next_paste(&paste, delay);
+    paste.lock().unwrap().timer = timer;
+}
+
+/// Returns the progress of an in-flight paste into the specified tab,
+/// as a percentage.  Returns None until the paste has been running
+/// for `PASTE_PROGRESS_DELAY`.
+pub fn paste_progress(tab_id: TabId) -> Option<usize> {
+    PASTES.with(|pastes| {
+        let pastes = pastes.borrow();
+        let paste = pastes.get(&tab_id)?.lock().unwrap();
+        if paste.started.elapsed() < PASTE_PROGRESS_DELAY {
+            None
+        } else {
+            Some(paste.offset * 100 / paste.text.len().max(1))
+        }
+    })
+}
+
+/// Cancel an in-flight paste into the specified tab.
+/// Returns true if there was a paste to cancel.
+pub fn cancel_paste(tab_id: TabId) -> bool {
+    match PASTES.with(|pastes| pastes.borrow_mut().remove(&tab_id)) {
+        Some(paste) => {
+            cancel_paste_timer(&paste);
+            true
+        }
+        None => false,
+    }
+}
+
+fn cancel_paste_timer(paste: &Arc<Mutex<Paste>>) {
+    if let Some(timer) = paste.lock().unwrap().timer.take() {
+        timer.cancel();
+    }
+}
+
+/// Feed `text` to the stdin of the command described by `args`
+/// and return its output
+fn run_paste_command(args: &[String], text: String) -> Result<String, Error> {
+    let (prog, args) = args
+        .split_first()
+        .ok_or_else(|| err_msg("paste transform Command requires a program"))?;
+    let mut child = Command::new(prog)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| format_err!("failed to spawn paste transform {}: {}", prog, e))?;
+
+    // Write from a separate thread so that we can't deadlock if
+    // the command fills its output pipe before consuming its input
+    let mut stdin = child.stdin.take().unwrap();
+    let writer = std::thread::spawn(move || stdin.write_all(text.as_bytes()));
+
+    let output = child.wait_with_output()?;
+    writer
+        .join()
+        .map_err(|_| err_msg("paste transform writer panicked"))??;
+    if !output.status.success() {
+        bail!("paste transform {} failed: {:?}", prog, output.status);
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// Apply the configured `paste_transforms` to `text`
+pub fn transform_paste(transforms: &[PasteTransform], text: String) -> Result<String, Error> {
+    let mut text = text;
+    for transform in transforms {
+        text = match transform {
+            PasteTransform::StripTrailingNewline => {
+                text.trim_end_matches(|c| c == '\r' || c == '\n').to_owned()
+            }
+            PasteTransform::CrlfToLf => text.replace("\r\n", "\n"),
+            PasteTransform::CollapseWhitespace => {
+                text.split_whitespace().collect::<Vec<_>>().join(" ")
+            }
+            PasteTransform::Command(args) => run_paste_command(args, text)?,
+        };
+    }
+    Ok(text)
+}
+
+/// Send `text` to `tab` as a paste, after applying the configured
+/// `paste_transforms`.  Large pastes are trickled into the pty in
+/// chunks rather than sent all at once.
+pub fn paste_to_tab(tab: &Tab, text: String) -> Result<(), Error> {
+    let mux = Mux::get().unwrap();
+    let config = mux.config();
+    let runs_command = config.paste_transforms.iter().any(|t| match t {
+        PasteTransform::Command(_) => true,
+        _ => false,
+    });
+    if !runs_command {
+        let text = transform_paste(&config.paste_transforms, text)?;
+        return send_paste(tab, text, config);
+    }
+
+    // The command may take a while to run, so apply the transforms
+    // in the background and paste the result once they are done
+    let tab_id = tab.tab_id();
+    let transforms = config.paste_transforms.clone();
+    spawn_background(move || {
+        match transform_paste(&transforms, text) {
+            Ok(text) => {
+                spawn_into_gui(move || -> Result<(), Error> {
+                    let mux = Mux::get().unwrap();
+                    match mux.get_tab(tab_id) {
+                        Some(tab) => send_paste(&*tab, text, mux.config()),
+                        // The tab went away while the command ran
+                        None => Ok(()),
+                    }
+                });
+            }
+            Err(err) => eprintln!("paste into tab {} failed: {}", tab_id, err),
+        }
+        Ok(())
+    });
+    Ok(())
+}
+
+fn send_paste(tab: &Tab, text: String, config: &Config) -> Result<(), Error> {
+    if text.len() <= config.paste_chunk_size {
+        // Send it all now
+        return tab.send_paste(&text);
+    }
+    // It's pretty heavy, so we trickle it into the pty
+    let end = chunk_end(&text, 0, config.paste_chunk_size);
+    tab.send_paste(&text[0..end])?;
+    trickle_paste(tab.tab_id(), text, end, config);
+    Ok(())
 }
 
 impl<H: HostHelper> HostImpl<H> {
@@ -69,6 +331,9 @@ impl<H: HostHelper> HostImpl<H> {
         Self {
             helper,
             clipboard: None,
+            keys: KeyMap::new(Mux::get().unwrap().config()),
+            remapper: KeyRemapper::new(Mux::get().unwrap().config()),
+            scroll_drag: None,
         }
     }
 
@@ -86,6 +351,9 @@ impl<H: HostHelper> HostImpl<H> {
     }
 
     pub fn set_clipboard(&mut self, clip: Option<String>) -> Result<(), Error> {
+        if let Some(text) = clip.as_ref() {
+            record_history(Mux::get().unwrap().config(), text);
+        }
         self.clipboard()?
             .set_contents(clip.unwrap_or_else(|| "".into()))
             .map_err(|e| format_err!("{}", e))?;
@@ -95,92 +363,185 @@ impl<H: HostHelper> HostImpl<H> {
         self.get_clipboard().map(|_| ())
     }
 
+    /// Returns the text that was most recently copied from a
+    /// selection; this is the system clipboard unless the
+    /// `copy_register` option routes selections to a register
+    pub fn get_selection_text(&mut self) -> Result<String, Error> {
+        match Mux::get().unwrap().config().copy_register.as_ref() {
+            Some(name) => Ok(get_register(name).unwrap_or_else(String::new)),
+            None => self.get_clipboard(),
+        }
+    }
+
+    /// Store the text of a selection; see `get_selection_text`
+    pub fn set_selection_text(&mut self, clip: Option<String>) -> Result<(), Error> {
+        let mux = Mux::get().unwrap();
+        match mux.config().copy_register.as_ref() {
+            Some(name) => {
+                // Clearing the selection doesn't clear the register;
+                // it retains the most recent copy until replaced
+                if let Some(text) = clip {
+                    record_history(mux.config(), &text);
+                    set_register(name, text);
+                }
+                Ok(())
+            }
+            None => self.set_clipboard(clip),
+        }
+    }
+
+    /// The `key_remaps` are applied to key events before anything
+    /// else sees them
+    pub fn remapper(&mut self) -> &mut KeyRemapper {
+        &mut self.remapper
+    }
+
     pub fn process_gui_shortcuts(
         &mut self,
         tab: &Tab,
         mods: KeyModifiers,
         key: KeyCode,
     ) -> Result<bool, Error> {
-        if mods == KeyModifiers::SUPER && key == KeyCode::Char('t') {
-            self.with_window(|win| win.spawn_tab().map(|_| ()));
+        if key == KeyCode::Escape && mods == KeyModifiers::NONE && cancel_paste(tab.tab_id()) {
+            eprintln!("cancelled paste into tab {}", tab.tab_id());
             return Ok(true);
         }
 
-        if mods == KeyModifiers::ALT
-            && (key == KeyCode::Char('\r') || key == KeyCode::Char('\n') || key == KeyCode::Enter)
-        {
-            self.toggle_full_screen();
-            return Ok(true);
+        if let Some(assignment) = self.keys.lookup(key, mods) {
+            self.perform_key_assignment(tab, &assignment)?;
+            Ok(true)
+        } else {
+            Ok(false)
         }
+    }
 
-        if cfg!(target_os = "macos") && mods == KeyModifiers::SUPER && key == KeyCode::Char('c') {
-            // Nominally copy, but that is implicit, so NOP
-            return Ok(true);
-        }
-        if (cfg!(target_os = "macos") && mods == KeyModifiers::SUPER && key == KeyCode::Char('v'))
-            || (mods == KeyModifiers::SHIFT && key == KeyCode::Insert)
-        {
-            let text = self.get_clipboard()?;
-            if text.len() <= PASTE_CHUNK_SIZE {
-                // Send it all now
-                tab.send_paste(&text)?;
-                return Ok(true);
+    pub fn perform_key_assignment(
+        &mut self,
+        tab: &Tab,
+        assignment: &KeyAssignment,
+    ) -> Result<(), Error> {
+        use crate::keyassignment::KeyAssignment::*;
+        match assignment {
+            ToggleFullScreen => self.toggle_full_screen(),
+            Paste => {
+                let text = self.get_clipboard()?;
+                paste_to_tab(tab, text)?;
+            }
+            PasteFrom(name) => {
+                let text = if name == "Clipboard" {
+                    self.get_clipboard()?
+                } else {
+                    get_register(name).unwrap_or_else(String::new)
+                };
+                paste_to_tab(tab, text)?;
+            }
+            DecreaseFontSize => self.decrease_font_size(),
+            IncreaseFontSize => self.increase_font_size(),
+            ResetFontSize => self.reset_font_size(),
+            ShowClipboardHistory => {
+                let tab_id = tab.tab_id();
+                self.with_window(move |win| {
+                    win.show_overlay(Box::new(ClipboardHistoryOverlay::new(tab_id)))
+                });
+            }
+            ShowTabInspector => {
+                let tab_id = tab.tab_id();
+                self.with_window(move |win| {
+                    win.show_overlay(Box::new(TabInspectorOverlay::new(tab_id)))
+                });
+            }
+            ShowLauncher => self.with_window(move |win| {
+                let window_id = win.get_mux_window_id();
+                win.show_overlay(Box::new(LauncherOverlay::new(window_id)))
+            }),
+            QuickSelect => {
+                let tab_id = tab.tab_id();
+                self.with_window(move |win| {
+                    win.show_overlay(Box::new(QuickSelectOverlay::new(tab_id)))
+                });
+            }
+            NavigateLinks => {
+                let tab_id = tab.tab_id();
+                self.with_window(move |win| {
+                    let window_id = win.get_mux_window_id();
+                    win.show_overlay(Box::new(LinkNavigationOverlay::new(window_id, tab_id)))
+                });
+            }
+            MoveTabToNewWindow => {
+                let tab_id = tab.tab_id();
+                self.with_window(move |win| win.move_tab_to_new_window(tab_id));
+            }
+            MoveTabToWindowRelative(n) => {
+                let tab_id = tab.tab_id();
+                let n = *n;
+                self.with_window(move |win| win.move_tab_to_window_relative(tab_id, n));
+            }
+            SpawnCommandInNewWindow(spawn) => {
+                let spawn = spawn.clone();
+                self.with_window(move |win| {
+                    let config = Arc::clone(win.config());
+                    let cmd = config.build_spawn_command_prog(&spawn)?;
+                    let tab = crate::spawn_tab(&config, cmd, false)?;
+                    Mux::get().unwrap().add_tab(&tab)?;
+                    win.spawn_window_for_tab(tab.tab_id())
+                });
+            }
+            // The rest are the same for every frontend
+            _ => {
+                let assignment = assignment.clone();
+                self.with_window(move |win| {
+                    dispatch::perform_key_assignment(&mut GuiWindow(win), &assignment).map(|_| ())
+                });
             }
-            // It's pretty heavy, so we trickle it into the pty
-            tab.send_paste(&text[0..PASTE_CHUNK_SIZE])?;
-            trickle_paste(tab.tab_id(), text);
-            return Ok(true);
-        }
-        if mods == (KeyModifiers::SUPER | KeyModifiers::SHIFT)
-            && (key == KeyCode::Char('[') || key == KeyCode::Char('{'))
-        {
-            self.activate_tab_relative(-1);
-            return Ok(true);
-        }
-        if mods == (KeyModifiers::SUPER | KeyModifiers::SHIFT)
-            && (key == KeyCode::Char(']') || key == KeyCode::Char('}'))
-        {
-            self.activate_tab_relative(1);
-            return Ok(true);
         }
+        Ok(())
+    }
 
-        if (mods == KeyModifiers::SUPER || mods == KeyModifiers::CTRL) && key == KeyCode::Char('-')
-        {
-            self.decrease_font_size();
-            return Ok(true);
-        }
-        if (mods == KeyModifiers::SUPER || mods == KeyModifiers::CTRL) && key == KeyCode::Char('=')
-        {
-            self.increase_font_size();
-            return Ok(true);
-        }
-        if (mods == KeyModifiers::SUPER || mods == KeyModifiers::CTRL) && key == KeyCode::Char('0')
-        {
-            self.reset_font_size();
-            return Ok(true);
+    /// Give the scrollbar the first opportunity to handle a mouse
+    /// event.  `x` and `y` are the mouse position in physical pixels.
+    /// Returns true if the event was consumed by the scrollbar.
+    pub fn scrollbar_mouse_event(
+        &mut self,
+        tab: &Tab,
+        event: &MouseEvent,
+        x: f32,
+        y: f32,
+        dims: &Dimensions,
+    ) -> bool {
+        if !Mux::get().unwrap().config().enable_scroll_bar {
+            return false;
         }
-
-        if mods == KeyModifiers::SUPER {
-            if let KeyCode::Char(c) = key {
-                if c >= '0' && c <= '9' {
-                    let tab_number = c as u32 - 0x30;
-                    // Treat 0 as 10 as that is physically right of 9 on
-                    // a keyboard
-                    let tab_number = if tab_number == 0 { 10 } else { tab_number - 1 };
-                    self.activate_tab(tab_number as usize);
-                    return Ok(true);
-                }
+        let area = ScrollbarArea {
+            width: f32::from(dims.width),
+            height: f32::from(dims.height),
+            cell_width: dims.cell_width as f32,
+            cell_height: dims.cell_height as f32,
+        };
+        let mut term = tab.renderer();
+
+        match (event.kind, event.button, self.scroll_drag) {
+            (MouseEventKind::Press, MouseButton::Left, _) => {
+                self.scroll_drag = area.press(&mut *term, x, y);
+                self.scroll_drag.is_some()
+            }
+            (MouseEventKind::Move, _, Some(drag)) => {
+                area.drag(&mut *term, drag, y);
+                true
             }
+            (MouseEventKind::Release, MouseButton::Left, Some(_)) => {
+                self.scroll_drag = None;
+                true
+            }
+            _ => false,
         }
-        Ok(false)
     }
 
     pub fn activate_tab(&mut self, tab: usize) {
-        self.with_window(move |win| win.activate_tab(tab))
+        self.with_window(move |win| dispatch::activate_tab(&mut GuiWindow(win), tab))
     }
 
     pub fn activate_tab_relative(&mut self, tab: isize) {
-        self.with_window(move |win| win.activate_tab_relative(tab))
+        self.with_window(move |win| dispatch::activate_tab_relative(&mut GuiWindow(win), tab))
     }
 
     pub fn increase_font_size(&mut self) {
@@ -239,18 +600,28 @@ impl<'a, H: HostHelper> term::TerminalHost for TabHost<'a, H> {
     }
 
     fn click_link(&mut self, link: &Arc<Hyperlink>) {
-        match open::that(link.uri()) {
-            Ok(_) => {}
-            Err(err) => eprintln!("failed to open {}: {:?}", link.uri(), err),
-        }
+        let uri = link.uri().to_owned();
+        self.host.with_window(move |win| {
+            let window_id = win.get_mux_window_id();
+            let cwd = Mux::get()
+                .and_then(|mux| mux.get_active_tab_for_window(window_id))
+                .and_then(|tab| tab.get_current_dir());
+            open_uri(
+                window_id,
+                win.config(),
+                &uri,
+                cwd.as_ref().map(String::as_str),
+            );
+            Ok(())
+        })
     }
 
     fn get_clipboard(&mut self) -> Result<String, Error> {
-        self.host.get_clipboard()
+        self.host.get_selection_text()
     }
 
     fn set_clipboard(&mut self, clip: Option<String>) -> Result<(), Error> {
-        self.host.set_clipboard(clip)
+        self.host.set_selection_text(clip)
     }
 
     fn set_title(&mut self, _title: &str) {
@@ -280,3 +651,54 @@ impl<'a, H: HostHelper> term::TerminalHost for TabHost<'a, H> {
         self.host.reset_font_size()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn transform(transforms: &[PasteTransform], text: &str) -> String {
+        transform_paste(transforms, text.to_owned()).unwrap()
+    }
+
+    #[test]
+    fn builtin_transforms() {
+        use crate::config::PasteTransform::*;
+        assert_eq!(transform(&[], "a\r\nb\n"), "a\r\nb\n");
+        assert_eq!(transform(&[StripTrailingNewline], "ls\r\n\n"), "ls");
+        assert_eq!(transform(&[StripTrailingNewline], "ls\n  "), "ls\n  ");
+        assert_eq!(transform(&[CrlfToLf], "a\r\nb\rc\r\n"), "a\nb\rc\n");
+        assert_eq!(transform(&[CollapseWhitespace], "  a \t b\n\nc  "), "a b c");
+    }
+
+    #[test]
+    fn transforms_compose() {
+        use crate::config::PasteTransform::*;
+        assert_eq!(
+            transform(&[CrlfToLf, StripTrailingNewline], "a\r\nb\r\n"),
+            "a\nb"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn command_transform() {
+        use crate::config::PasteTransform::*;
+        let upper = Command(vec!["tr".to_owned(), "a-z".to_owned(), "A-Z".to_owned()]);
+        assert_eq!(
+            transform(&[upper, StripTrailingNewline], "hello\n"),
+            "HELLO"
+        );
+
+        let failing = Command(vec!["false".to_owned()]);
+        assert!(transform_paste(&[failing], "hello".to_owned()).is_err());
+    }
+
+    #[test]
+    fn command_transform_requires_program() {
+        let empty = PasteTransform::Command(vec![]);
+        assert!(transform_paste(&[empty], "hello".to_owned()).is_err());
+
+        let missing = PasteTransform::Command(vec!["/nonexistent/wezterm-paste".to_owned()]);
+        assert!(transform_paste(&[missing], "hello".to_owned()).is_err());
+    }
+}
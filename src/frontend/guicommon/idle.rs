@@ -0,0 +1,558 @@
+//! Notices when the user has stepped away, either because there has
+//! been no key press or mouse activity for `idle_timeout_minutes` or
+//! because the operating system has locked the session, and carries
+//! out the configured `idle_actions` and `lock_actions`.  Their effects
+//! are undone once there is activity again.
+//! The state is kept on the gui thread; the frontends report activity
+//! via `note_activity` and consult `is_blanked` when painting.
+use crate::config::IdleAction;
+use crate::mux::tab::TabId;
+use crate::mux::Mux;
+use promise::task::{gui_executor, spawn_background, spawn_into_gui};
+use promise::timer::schedule_timer;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+/// How often the lock state of the session is sampled on systems
+/// that don't announce changes to it
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+const LOCK_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+struct IdleState {
+    last_activity: Instant,
+    idle: bool,
+    locked: bool,
+    /// True while a timer is waiting to check for idleness
+    timer_scheduled: bool,
+    /// The actions that have been carried out since the session was
+    /// last active
+    applied: Vec<IdleAction>,
+    /// The tabs whose content is hidden until there is activity
+    blanked: HashSet<TabId>,
+}
+
+impl IdleState {
+    fn new(now: Instant) -> Self {
+        Self {
+            last_activity: now,
+            idle: false,
+            locked: false,
+            timer_scheduled: false,
+            applied: vec![],
+            blanked: HashSet::new(),
+        }
+    }
+
+    /// Returns how long to wait before checking for idleness, or None
+    /// if there is already a check pending or the session is idle
+    fn schedule_check(&mut self, now: Instant, timeout: Duration) -> Option<Duration> {
+        if self.timer_scheduled || self.idle {
+            return None;
+        }
+        self.timer_scheduled = true;
+        let elapsed = now.duration_since(self.last_activity);
+        Some(timeout - elapsed.min(timeout))
+    }
+
+    /// Called when the check scheduled by `schedule_check` is due.
+    /// Returns true if the session has just become idle.
+    fn check(&mut self, now: Instant, timeout: Duration) -> bool {
+        self.timer_scheduled = false;
+        if self.idle || now.duration_since(self.last_activity) < timeout {
+            return false;
+        }
+        self.idle = true;
+        true
+    }
+
+    /// Records activity at `now`.  Returns true if the user was away,
+    /// so the effects of the actions should be undone.
+    fn activity(&mut self, now: Instant) -> bool {
+        self.last_activity = now;
+        let was_away = self.idle && !self.locked;
+        self.idle = false;
+        was_away
+    }
+
+    /// Records the lock state of the session.  Returns Some(true) if
+    /// the `lock_actions` should be carried out, Some(false) if their
+    /// effects should be undone, or None if nothing should happen.
+    fn set_locked(&mut self, locked: bool) -> Option<bool> {
+        let changed = self.locked != locked;
+        self.locked = locked;
+        if changed && !self.idle {
+            Some(locked)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the actions that were applied and the tabs that were
+    /// blanked, forgetting them
+    fn take_applied(&mut self) -> (Vec<IdleAction>, HashSet<TabId>) {
+        (
+            std::mem::replace(&mut self.applied, vec![]),
+            std::mem::replace(&mut self.blanked, HashSet::new()),
+        )
+    }
+}
+
+thread_local! {
+    static STATE: RefCell<IdleState> = RefCell::new(IdleState::new(Instant::now()));
+}
+
+/// Starts watching for idleness and for the session being locked,
+/// as configured.  This must be called on the gui thread once the
+/// gui executor is running.
+pub fn start() {
+    let mux = Mux::get().unwrap();
+    schedule_idle_check(mux.config().idle_timeout_minutes);
+    if !mux.config().lock_actions.is_empty() {
+        let spawned = std::thread::Builder::new()
+            .name("session-lock".into())
+            .spawn(watch_session_lock);
+        if let Err(err) = spawned {
+            eprintln!("unable to watch for the session being locked: {}", err);
+        }
+    }
+}
+
+fn idle_timeout(minutes: Option<u64>) -> Option<Duration> {
+    minutes.map(|minutes| Duration::from_secs(minutes * 60))
+}
+
+/// Arranges to check for idleness once the timeout would next expire
+fn schedule_idle_check(minutes: Option<u64>) {
+    let timeout = match idle_timeout(minutes) {
+        Some(timeout) => timeout,
+        None => return,
+    };
+    let delay = STATE.with(|state| state.borrow_mut().schedule_check(Instant::now(), timeout));
+    if let (Some(delay), Some(executor)) = (delay, gui_executor()) {
+        schedule_timer(executor, delay, check_idle);
+    }
+}
+
+fn check_idle() {
+    let mux = match Mux::get() {
+        Some(mux) => mux,
+        None => return,
+    };
+    let config = mux.config();
+    let timeout = match idle_timeout(config.idle_timeout_minutes) {
+        Some(timeout) => timeout,
+        None => return,
+    };
+    let became_idle = STATE.with(|state| state.borrow_mut().check(Instant::now(), timeout));
+    if became_idle {
+        apply_actions(&config.idle_actions, "idle");
+    } else {
+        // There was activity since the timer was scheduled
+        schedule_idle_check(config.idle_timeout_minutes);
+    }
+}
+
+/// Called by the frontends when a key is pressed or the mouse is used
+pub fn note_activity() {
+    let was_away = STATE.with(|state| state.borrow_mut().activity(Instant::now()));
+    if was_away {
+        resume();
+    }
+    if let Some(mux) = Mux::get() {
+        schedule_idle_check(mux.config().idle_timeout_minutes);
+    }
+}
+
+fn lock_changed(locked: bool) {
+    let mux = match Mux::get() {
+        Some(mux) => mux,
+        None => return,
+    };
+    match STATE.with(|state| state.borrow_mut().set_locked(locked)) {
+        Some(true) => apply_actions(&mux.config().lock_actions, "locked"),
+        Some(false) => resume(),
+        None => {}
+    }
+}
+
+/// Returns true if the content of `tab_id` is hidden
+pub fn is_blanked(tab_id: TabId) -> bool {
+    STATE.with(|state| state.borrow().blanked.contains(&tab_id))
+}
+
+fn apply_actions(actions: &[IdleAction], state_name: &str) {
+    let mux = Mux::get().unwrap();
+    let config = mux.config();
+    for action in actions {
+        match action {
+            IdleAction::BlankTabs => {
+                for tab in mux.iter_tabs() {
+                    blank(tab.tab_id());
+                }
+            }
+            IdleAction::BlankSensitiveTabs => {
+                for tab in mux.iter_tabs() {
+                    let sensitive = tab
+                        .foreground_process()
+                        .and_then(|process| process.name)
+                        .map_or(false, |name| config.sensitive_processes.contains(&name));
+                    if sensitive {
+                        blank(tab.tab_id());
+                    }
+                }
+            }
+            IdleAction::RunCommand(args) => run_command(args, state_name),
+        }
+    }
+    STATE.with(|state| state.borrow_mut().applied.extend_from_slice(actions));
+    mux.notify_output();
+}
+
+/// Undoes the effects of the actions that were applied while the
+/// user was away
+fn resume() {
+    let (applied, blanked) = STATE.with(|state| state.borrow_mut().take_applied());
+    let mux = Mux::get().unwrap();
+    for tab_id in blanked {
+        if let Some(tab) = mux.get_tab(tab_id) {
+            tab.renderer().make_all_lines_dirty();
+        }
+    }
+    for action in &applied {
+        if let IdleAction::RunCommand(args) = action {
+            run_command(args, "active");
+        }
+    }
+    mux.notify_output();
+}
+
+fn blank(tab_id: TabId) {
+    STATE.with(|state| state.borrow_mut().blanked.insert(tab_id));
+    if let Some(tab) = Mux::get().unwrap().get_tab(tab_id) {
+        tab.renderer().make_all_lines_dirty();
+    }
+}
+
+fn run_command(args: &[String], state_name: &str) {
+    if args.is_empty() {
+        return;
+    }
+    let args = args.to_vec();
+    let state_name = state_name.to_owned();
+    spawn_background(move || {
+        let status = Command::new(&args[0])
+            .args(&args[1..])
+            .env("WEZTERM_SESSION_STATE", &state_name)
+            .status();
+        match status {
+            Ok(status) if !status.success() => {
+                eprintln!("{:?} exited with {}", args, status);
+            }
+            Err(err) => eprintln!("running {:?}: {}", args, err),
+            _ => {}
+        }
+        Ok(())
+    });
+}
+
+/// Reports the lock state of the session to the gui thread when it
+/// changes
+struct LockReporter {
+    last: bool,
+}
+
+impl LockReporter {
+    fn report(&mut self, locked: bool) {
+        if locked != self.last {
+            self.last = locked;
+            spawn_into_gui(move || {
+                lock_changed(locked);
+                Ok(())
+            });
+        }
+    }
+}
+
+/// Follows the lock state of the session via the signals that logind
+/// sends on the system bus, until they can no longer be received
+#[cfg(all(unix, not(target_os = "macos")))]
+fn watch_session_lock() {
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+
+    let mut reporter = LockReporter { last: false };
+    let session = match session_id() {
+        Some(session) => session,
+        None => {
+            eprintln!("unable to determine the logind session");
+            return;
+        }
+    };
+    // Subscribe before sampling the current state, so that a change
+    // in between can't be missed
+    let child = Command::new("gdbus")
+        .args(&[
+            "monitor",
+            "--system",
+            "--dest",
+            "org.freedesktop.login1",
+            "--object-path",
+            &session_object_path(&session),
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(err) => {
+            eprintln!(
+                "unable to watch for the session being locked: gdbus: {}",
+                err
+            );
+            return;
+        }
+    };
+    if let Some(locked) = session_locked(&session) {
+        reporter.report(locked);
+    }
+    if let Some(stdout) = child.stdout.take() {
+        for line in BufReader::new(stdout).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            if let Some(locked) = lock_event(&line) {
+                reporter.report(locked);
+            }
+        }
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+    eprintln!("stopped receiving the lock state of the session");
+}
+
+/// Returns the id of the logind session that we are part of
+#[cfg(all(unix, not(target_os = "macos")))]
+fn session_id() -> Option<String> {
+    if let Ok(id) = std::env::var("XDG_SESSION_ID") {
+        return Some(id);
+    }
+    let output = Command::new("loginctl")
+        .args(&["show-session", "self", "--property=Id", "--value"])
+        .output()
+        .ok()?;
+    let id = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+    if output.status.success() && !id.is_empty() {
+        Some(id)
+    } else {
+        None
+    }
+}
+
+/// Returns the D-Bus object path of the logind session `id`.  Bytes
+/// other than ASCII letters and digits, and a leading digit, are
+/// escaped as `_` followed by their value in hex.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn session_object_path(id: &str) -> String {
+    let mut path = "/org/freedesktop/login1/session/".to_owned();
+    for (idx, b) in id.bytes().enumerate() {
+        if b.is_ascii_alphabetic() || (idx > 0 && b.is_ascii_digit()) {
+            path.push(b as char);
+        } else {
+            path.push_str(&format!("_{:02x}", b));
+        }
+    }
+    path
+}
+
+/// Interprets a line printed by `gdbus monitor`, returning the lock
+/// state that it announces, if any.  Lock and Unlock ask the screen
+/// locker to act, while LockedHint reports what it did.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn lock_event(line: &str) -> Option<bool> {
+    if line.contains("org.freedesktop.login1.Session.Lock ") {
+        Some(true)
+    } else if line.contains("org.freedesktop.login1.Session.Unlock ") {
+        Some(false)
+    } else if line.contains("'LockedHint': <true>") {
+        Some(true)
+    } else if line.contains("'LockedHint': <false>") {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// Returns whether the session is locked, according to logind
+#[cfg(all(unix, not(target_os = "macos")))]
+fn session_locked(session: &str) -> Option<bool> {
+    let output = Command::new("loginctl")
+        .args(&["show-session", session, "--property=LockedHint", "--value"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    match String::from_utf8_lossy(&output.stdout).trim() {
+        "yes" => Some(true),
+        "no" => Some(false),
+        _ => None,
+    }
+}
+
+/// Samples the lock state of the session until it can't be
+/// determined, on systems that don't announce changes to it
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+fn watch_session_lock() {
+    let mut reporter = LockReporter { last: false };
+    while let Some(locked) = session_locked() {
+        reporter.report(locked);
+        std::thread::sleep(LOCK_POLL_INTERVAL);
+    }
+    eprintln!("unable to determine whether the session is locked");
+}
+
+/// Returns whether the session is locked.  The input desktop can't be
+/// opened while the lock screen is showing.  Windows only tells
+/// windows about the session being locked, not threads like ours.
+#[cfg(windows)]
+fn session_locked() -> Option<bool> {
+    use winapi::um::winuser::{CloseDesktop, OpenInputDesktop, DESKTOP_SWITCHDESKTOP};
+    let desktop = unsafe { OpenInputDesktop(0, 0, DESKTOP_SWITCHDESKTOP) };
+    if desktop.is_null() {
+        return Some(true);
+    }
+    unsafe { CloseDesktop(desktop) };
+    Some(false)
+}
+
+#[cfg(target_os = "macos")]
+fn session_locked() -> Option<bool> {
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn idle_after_timeout() {
+        let start = Instant::now();
+        let mut state = IdleState::new(start);
+        assert_eq!(state.schedule_check(start, TIMEOUT), Some(TIMEOUT));
+        // Only one check is pending at a time
+        assert_eq!(state.schedule_check(start, TIMEOUT), None);
+
+        // Activity since the check was scheduled postpones idleness
+        let later = start + Duration::from_secs(30);
+        assert!(!state.activity(later));
+        assert!(!state.check(start + TIMEOUT, TIMEOUT));
+        assert_eq!(
+            state.schedule_check(start + TIMEOUT, TIMEOUT),
+            Some(Duration::from_secs(30))
+        );
+
+        assert!(state.check(later + TIMEOUT, TIMEOUT));
+        assert!(!state.check(later + TIMEOUT, TIMEOUT));
+        // No more checks are needed until there is activity
+        assert_eq!(state.schedule_check(later + TIMEOUT, TIMEOUT), None);
+
+        assert!(state.activity(later + TIMEOUT * 2));
+        assert_eq!(
+            state.schedule_check(later + TIMEOUT * 2, TIMEOUT),
+            Some(TIMEOUT)
+        );
+    }
+
+    #[test]
+    fn lock_and_unlock() {
+        let start = Instant::now();
+        let mut state = IdleState::new(start);
+        assert_eq!(state.set_locked(false), None);
+        assert_eq!(state.set_locked(true), Some(true));
+        assert_eq!(state.set_locked(true), None);
+        // Activity while locked doesn't undo the lock actions
+        assert!(!state.activity(start));
+        assert_eq!(state.set_locked(false), Some(false));
+    }
+
+    #[test]
+    fn lock_while_idle() {
+        let start = Instant::now();
+        let mut state = IdleState::new(start);
+        state.schedule_check(start, TIMEOUT);
+        assert!(state.check(start + TIMEOUT, TIMEOUT));
+
+        // The idle actions are already in effect, and stay so until
+        // there is activity after the session is unlocked
+        assert_eq!(state.set_locked(true), None);
+        assert_eq!(state.set_locked(false), None);
+        assert!(state.activity(start + TIMEOUT));
+    }
+
+    #[test]
+    fn take_applied() {
+        let mut state = IdleState::new(Instant::now());
+        state.applied.push(IdleAction::BlankTabs);
+        state.blanked.insert(1);
+        let (applied, blanked) = state.take_applied();
+        assert_eq!(applied.len(), 1);
+        assert!(blanked.contains(&1));
+        assert!(state.applied.is_empty());
+        assert!(state.blanked.is_empty());
+    }
+
+    #[test]
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn object_path() {
+        assert_eq!(
+            session_object_path("2"),
+            "/org/freedesktop/login1/session/_32"
+        );
+        assert_eq!(
+            session_object_path("c12"),
+            "/org/freedesktop/login1/session/c12"
+        );
+        assert_eq!(
+            session_object_path("a-b"),
+            "/org/freedesktop/login1/session/a_2db"
+        );
+    }
+
+    #[test]
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn lock_events() {
+        let path = "/org/freedesktop/login1/session/_32";
+        assert_eq!(
+            lock_event(&format!("{}: org.freedesktop.login1.Session.Lock ()", path)),
+            Some(true)
+        );
+        assert_eq!(
+            lock_event(&format!(
+                "{}: org.freedesktop.login1.Session.Unlock ()",
+                path
+            )),
+            Some(false)
+        );
+        assert_eq!(
+            lock_event(&format!(
+                "{}: org.freedesktop.DBus.Properties.PropertiesChanged \
+                 ('org.freedesktop.login1.Session', {{'LockedHint': <true>}}, @as [])",
+                path
+            )),
+            Some(true)
+        );
+        assert_eq!(
+            lock_event(&format!(
+                "{}: org.freedesktop.DBus.Properties.PropertiesChanged \
+                 ('org.freedesktop.login1.Session', {{'IdleHint': <false>}}, @as [])",
+                path
+            )),
+            None
+        );
+    }
+}
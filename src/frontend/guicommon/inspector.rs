@@ -0,0 +1,137 @@
+//! An overlay that shows diagnostic information about a tab, such
+//! as how its process was spawned and which terminal modes are in
+//! effect, to help understand why an application misbehaves.
+use super::overlay::{OverlayAction, OverlayDelegate};
+use crate::mux::tab::TabId;
+use crate::mux::Mux;
+use failure::Error;
+use std::cell::Cell;
+use term::{KeyCode, KeyModifiers};
+
+pub struct TabInspectorOverlay {
+    /// The tab being inspected
+    tab_id: TabId,
+    /// The rendered lines of the report
+    lines: Vec<String>,
+    /// The index of the first line that is displayed
+    top: usize,
+    /// The number of lines shown by the most recent render
+    page_size: Cell<usize>,
+}
+
+impl TabInspectorOverlay {
+    pub fn new(tab_id: TabId) -> Self {
+        let mut overlay = Self {
+            tab_id,
+            lines: vec![],
+            top: 0,
+            page_size: Cell::new(1),
+        };
+        overlay.refresh();
+        overlay
+    }
+
+    /// Take a fresh snapshot of the state of the tab
+    fn refresh(&mut self) {
+        self.lines.clear();
+        let mux = Mux::get().unwrap();
+        let tab = match mux.get_tab(self.tab_id) {
+            Some(tab) => tab,
+            None => {
+                self.lines
+                    .push(format!("tab {} no longer exists", self.tab_id));
+                return;
+            }
+        };
+
+        self.lines
+            .push(format!("tab {}: {}", self.tab_id, tab.get_title()));
+        for section in tab.inspect() {
+            self.lines.push(String::new());
+            self.lines.push(format!("\x1b[1m{}\x1b[0m", section.title));
+            if section.lines.is_empty() {
+                self.lines.push("  (none)".to_string());
+            }
+            for line in section.lines {
+                self.lines.push(format!("  {}", line));
+            }
+        }
+    }
+
+    /// The largest value of `top` that still fills the page
+    fn max_top(&self) -> usize {
+        self.lines.len().saturating_sub(self.page_size.get())
+    }
+}
+
+/// Truncate `text` to `width` columns, replacing control characters
+/// other than escape so that they cannot disturb the layout
+fn fit(text: &str, width: usize) -> String {
+    let mut result = String::new();
+    let mut visible = 0;
+    let mut in_escape = false;
+    for c in text.chars() {
+        if in_escape {
+            result.push(c);
+            in_escape = c != 'm';
+        } else if c == '\x1b' {
+            result.push(c);
+            in_escape = true;
+        } else if visible < width {
+            result.push(if c.is_control() { '?' } else { c });
+            visible += 1;
+        }
+    }
+    result
+}
+
+impl OverlayDelegate for TabInspectorOverlay {
+    fn get_title(&self) -> String {
+        "Tab Inspector".into()
+    }
+
+    fn render(&self, rows: usize, cols: usize) -> String {
+        let mut output = String::new();
+        output.push_str(&fit(
+            "\x1b[7mArrows/PageUp/PageDown to scroll, r to refresh, Escape to close\x1b[0m",
+            cols,
+        ));
+
+        // Allow for the header line
+        let avail = rows.saturating_sub(1).max(1);
+        self.page_size.set(avail);
+        let top = self.top.min(self.max_top());
+        for line in self.lines.iter().skip(top).take(avail) {
+            output.push_str("\r\n");
+            output.push_str(&fit(line, cols));
+        }
+
+        output
+    }
+
+    fn key_down(&mut self, key: KeyCode, mods: KeyModifiers) -> Result<OverlayAction, Error> {
+        let page = self.page_size.get();
+        let max_top = self.max_top();
+        match (key, mods) {
+            (KeyCode::Escape, _)
+            | (KeyCode::Char('q'), KeyModifiers::NONE)
+            | (KeyCode::Char('g'), KeyModifiers::CTRL) => return Ok(OverlayAction::Close),
+            (KeyCode::Char('r'), KeyModifiers::NONE) => {
+                self.refresh();
+                self.top = self.top.min(self.max_top());
+            }
+            (KeyCode::UpArrow, _) | (KeyCode::Char('k'), KeyModifiers::NONE) => {
+                self.top = self.top.saturating_sub(1)
+            }
+            (KeyCode::DownArrow, _) | (KeyCode::Char('j'), KeyModifiers::NONE) => {
+                self.top = (self.top + 1).min(max_top)
+            }
+            (KeyCode::PageUp, _) => self.top = self.top.saturating_sub(page),
+            (KeyCode::PageDown, _) => self.top = (self.top + page).min(max_top),
+            (KeyCode::Home, _) => self.top = 0,
+            (KeyCode::End, _) => self.top = max_top,
+            _ => {}
+        }
+        Ok(OverlayAction::Continue)
+    }
+}
@@ -0,0 +1,327 @@
+//! The launcher is a fuzzy-searchable list of things that can be
+//! done from the keyboard: spawning tabs, including the entries from
+//! the `launch_menu` configuration, switching to an existing
+//! tab by its title, and invoking key assignments by name without
+//! having to remember the keys that are bound to them.
+use super::overlay::{fuzzy_match, OverlayAction, OverlayDelegate};
+use crate::keyassignment::KeyAssignment;
+use crate::mux::tab::TabId;
+use crate::mux::window::WindowId;
+use crate::mux::Mux;
+use failure::Error;
+use term::{KeyCode, KeyModifiers};
+
+enum Action {
+    /// Carry out a key assignment in the window that showed the launcher
+    Perform(KeyAssignment),
+    /// Activate a tab that lives in a different window
+    ActivateTab { window_id: WindowId, tab_id: TabId },
+}
+
+struct Entry {
+    label: String,
+    action: Action,
+}
+
+pub struct LauncherOverlay {
+    entries: Vec<Entry>,
+    filter: String,
+    selected: usize,
+}
+
+/// The key assignments that can be invoked by name.  Those that
+/// need a parameter are represented via the launch menu and tab
+/// entries instead.
+fn named_assignments() -> Vec<(&'static str, KeyAssignment)> {
+    use crate::keyassignment::KeyAssignment::*;
+    vec![
+        ("SpawnTab", SpawnTab),
+        ("ToggleFullScreen", ToggleFullScreen),
+        ("Paste", Paste),
+        ("IncreaseFontSize", IncreaseFontSize),
+        ("DecreaseFontSize", DecreaseFontSize),
+        ("ResetFontSize", ResetFontSize),
+        ("ShowClipboardHistory", ShowClipboardHistory),
+        ("ShowTabInspector", ShowTabInspector),
+        ("MoveTabToNewWindow", MoveTabToNewWindow),
+        ("QuickSelect", QuickSelect),
+        ("NavigateLinks", NavigateLinks),
+        ("ToggleRevealConcealed", ToggleRevealConcealed),
+        ("HighlightSelectionMatches", HighlightSelectionMatches),
+    ]
+}
+
+/// Produce a single line version of `text` that fits in `width` columns
+fn fit(text: &str, width: usize) -> String {
+    text.chars()
+        .map(|c| if c.is_control() { '?' } else { c })
+        .take(width)
+        .collect()
+}
+
+impl LauncherOverlay {
+    /// Create a launcher for the window `window_id`
+    pub fn new(window_id: WindowId) -> Self {
+        let mut entries = vec![Entry {
+            label: "Spawn a new tab".into(),
+            action: Action::Perform(KeyAssignment::SpawnTab),
+        }];
+
+        let mux = Mux::get().unwrap();
+        for (idx, item) in mux.config().launch_menu.iter().enumerate() {
+            entries.push(Entry {
+                label: format!("Launch {}", item.label),
+                action: Action::Perform(KeyAssignment::SpawnLaunchMenuItem(idx)),
+            });
+        }
+
+        for (window_number, id) in mux.window_ids().into_iter().enumerate() {
+            let window = match mux.get_window(id) {
+                Some(window) => window,
+                None => continue,
+            };
+            for idx in 0..window.len() {
+                let tab = match window.get_by_idx(idx) {
+                    Some(tab) => tab,
+                    None => continue,
+                };
+                let action = if id == window_id {
+                    Action::Perform(KeyAssignment::ActivateTab(idx))
+                } else {
+                    Action::ActivateTab {
+                        window_id: id,
+                        tab_id: tab.tab_id(),
+                    }
+                };
+                entries.push(Entry {
+                    label: format!(
+                        "Switch to tab {} of window {}: {}",
+                        idx + 1,
+                        window_number + 1,
+                        tab.get_title()
+                    ),
+                    action,
+                });
+            }
+        }
+
+        for (name, assignment) in named_assignments() {
+            entries.push(Entry {
+                label: name.to_string(),
+                action: Action::Perform(assignment),
+            });
+        }
+
+        Self {
+            entries,
+            filter: String::new(),
+            selected: 0,
+        }
+    }
+
+    /// Returns the indices of the entries that match the filter,
+    /// best match first
+    fn matches(&self) -> Vec<usize> {
+        let mut scored: Vec<(usize, usize)> = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, entry)| {
+                fuzzy_match(&self.filter, &entry.label).map(|score| (score, idx))
+            })
+            .collect();
+        scored.sort_by_key(|&(score, _)| score);
+        scored.into_iter().map(|(_, idx)| idx).collect()
+    }
+
+    fn launch_selected(&self) -> OverlayAction {
+        let matches = self.matches();
+        let entry = match matches.get(self.selected) {
+            Some(&idx) => &self.entries[idx],
+            None => return OverlayAction::Continue,
+        };
+        match &entry.action {
+            Action::Perform(assignment) => OverlayAction::Perform(assignment.clone()),
+            Action::ActivateTab { window_id, tab_id } => {
+                // There is no way to raise another window, so the best
+                // that we can do is make the tab active within it
+                let mux = Mux::get().unwrap();
+                if let Some(mut window) = mux.get_window_mut(*window_id) {
+                    if let Some(idx) = window.idx_by_id(*tab_id) {
+                        window.set_active(idx);
+                    }
+                }
+                if let Some(tab) = mux.get_tab(*tab_id) {
+                    tab.renderer().make_all_lines_dirty();
+                }
+                mux.notify_output();
+                OverlayAction::Close
+            }
+        }
+    }
+}
+
+impl OverlayDelegate for LauncherOverlay {
+    fn get_title(&self) -> String {
+        "Launcher".into()
+    }
+
+    fn render(&self, rows: usize, cols: usize) -> String {
+        let mut output = String::new();
+        output.push_str(&fit(
+            "Type to filter, Enter to launch, Escape to cancel",
+            cols,
+        ));
+        output.push_str("\r\n");
+
+        let matches = self.matches();
+        // Allow for the two header lines and the prompt, and keep the
+        // selection visible
+        let avail = rows.saturating_sub(3).max(1);
+        let skip = if self.selected >= avail {
+            self.selected + 1 - avail
+        } else {
+            0
+        };
+
+        for (row, &idx) in matches.iter().enumerate().skip(skip).take(avail) {
+            output.push_str("\r\n");
+            let line = fit(&self.entries[idx].label, cols.saturating_sub(2));
+            if row == self.selected {
+                output.push_str(&format!("\x1b[7m> {}\x1b[0m", line));
+            } else {
+                output.push_str(&format!("  {}", line));
+            }
+        }
+
+        let prompt = fit(&format!("> {}", self.filter), cols.saturating_sub(1));
+        output.push_str(&format!("\x1b[{};1H\x1b[2K{}", rows, prompt));
+
+        output
+    }
+
+    fn key_down(&mut self, key: KeyCode, mods: KeyModifiers) -> Result<OverlayAction, Error> {
+        match (key, mods) {
+            (KeyCode::Escape, _) | (KeyCode::Char('g'), KeyModifiers::CTRL) => {
+                return Ok(OverlayAction::Close);
+            }
+            (KeyCode::Enter, _) | (KeyCode::Char('\r'), _) | (KeyCode::Char('\n'), _) => {
+                return Ok(self.launch_selected());
+            }
+            (KeyCode::UpArrow, _) | (KeyCode::Char('p'), KeyModifiers::CTRL) => {
+                self.selected = self.selected.saturating_sub(1);
+            }
+            (KeyCode::DownArrow, _) | (KeyCode::Char('n'), KeyModifiers::CTRL) => {
+                let limit = self.matches().len().saturating_sub(1);
+                self.selected = (self.selected + 1).min(limit);
+            }
+            (KeyCode::Backspace, _) | (KeyCode::Char('\x08'), _) | (KeyCode::Char('\x7f'), _) => {
+                self.filter.pop();
+                self.selected = 0;
+            }
+            (KeyCode::Char(c), KeyModifiers::NONE) | (KeyCode::Char(c), KeyModifiers::SHIFT)
+                if !c.is_control() =>
+            {
+                self.filter.push(c);
+                self.selected = 0;
+            }
+            _ => {}
+        }
+        Ok(OverlayAction::Continue)
+    }
+
+    fn send_paste(&mut self, text: &str) -> Result<(), Error> {
+        self.filter.push_str(&fit(text, 256));
+        self.selected = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn launcher(labels: &[(&str, KeyAssignment)]) -> LauncherOverlay {
+        LauncherOverlay {
+            entries: labels
+                .iter()
+                .map(|(label, assignment)| Entry {
+                    label: label.to_string(),
+                    action: Action::Perform(assignment.clone()),
+                })
+                .collect(),
+            filter: String::new(),
+            selected: 0,
+        }
+    }
+
+    fn press(launcher: &mut LauncherOverlay, key: KeyCode) -> OverlayAction {
+        launcher.key_down(key, KeyModifiers::NONE).unwrap()
+    }
+
+    fn type_text(launcher: &mut LauncherOverlay, text: &str) {
+        for c in text.chars() {
+            assert_eq!(press(launcher, KeyCode::Char(c)), OverlayAction::Continue);
+        }
+    }
+
+    #[test]
+    fn filter_and_launch() {
+        let mut launcher = launcher(&[
+            ("Spawn a new tab", KeyAssignment::SpawnTab),
+            ("ToggleFullScreen", KeyAssignment::ToggleFullScreen),
+            ("ShowTabInspector", KeyAssignment::ShowTabInspector),
+        ]);
+        assert_eq!(launcher.matches(), vec![0, 1, 2]);
+
+        // The closest match is listed first
+        type_text(&mut launcher, "tab");
+        assert_eq!(launcher.matches(), vec![2, 0]);
+        assert_eq!(
+            press(&mut launcher, KeyCode::Enter),
+            OverlayAction::Perform(KeyAssignment::ShowTabInspector)
+        );
+
+        // The selection stops at the last match
+        for _ in 0..3 {
+            press(&mut launcher, KeyCode::DownArrow);
+        }
+        assert_eq!(
+            press(&mut launcher, KeyCode::Enter),
+            OverlayAction::Perform(KeyAssignment::SpawnTab)
+        );
+
+        // Editing the filter resets the selection
+        press(&mut launcher, KeyCode::Backspace);
+        assert_eq!(launcher.filter, "ta");
+        assert_eq!(launcher.selected, 0);
+
+        type_text(&mut launcher, "zzz");
+        assert!(launcher.matches().is_empty());
+        assert_eq!(
+            press(&mut launcher, KeyCode::Enter),
+            OverlayAction::Continue
+        );
+
+        assert_eq!(press(&mut launcher, KeyCode::Escape), OverlayAction::Close);
+    }
+
+    #[test]
+    fn pasted_filter() {
+        let mut launcher = launcher(&[
+            ("Spawn a new tab", KeyAssignment::SpawnTab),
+            ("ResetFontSize", KeyAssignment::ResetFontSize),
+        ]);
+        launcher.send_paste("font\r\n").unwrap();
+        assert_eq!(launcher.filter, "font??");
+        press(&mut launcher, KeyCode::Backspace);
+        press(&mut launcher, KeyCode::Backspace);
+        assert_eq!(launcher.matches(), vec![1]);
+    }
+
+    #[test]
+    fn fit_to_width() {
+        assert_eq!(fit("hello", 3), "hel");
+        assert_eq!(fit("a\tb\nc", 10), "a?b?c");
+    }
+}
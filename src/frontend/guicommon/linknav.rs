@@ -0,0 +1,143 @@
+//! Link navigation mode is a keyboard-only alternative to clicking on
+//! hyperlinks.  The focus starts on the hyperlink nearest the bottom of
+//! the tab; Tab and the arrow keys move it between the hyperlinks that
+//! are visible, including those matched by `hyperlink_rules`, Enter
+//! opens the focused link and Escape leaves the mode.
+use super::host::open_uri;
+use super::overlay::{OverlayAction, OverlayDelegate};
+use crate::mux::tab::TabId;
+use crate::mux::window::WindowId;
+use crate::mux::Mux;
+use failure::Error;
+use term::{KeyCode, KeyModifiers, VisibleHyperlink};
+
+pub struct LinkNavigationOverlay {
+    window_id: WindowId,
+    tab_id: TabId,
+    /// The text of the lines that were visible in the tab
+    lines: Vec<String>,
+    links: Vec<VisibleHyperlink>,
+    /// The index into `links` of the focused link
+    focused: usize,
+}
+
+impl LinkNavigationOverlay {
+    pub fn new(window_id: WindowId, tab_id: TabId) -> Self {
+        let mux = Mux::get().unwrap();
+        let (lines, links) = match mux.get_tab(tab_id) {
+            Some(tab) => {
+                let mut renderer = tab.renderer();
+                (
+                    renderer.get_viewport_text(),
+                    renderer.get_visible_hyperlinks(),
+                )
+            }
+            None => (vec![], vec![]),
+        };
+        // The most recent output is usually the most interesting
+        let focused = links.len().saturating_sub(1);
+        Self {
+            window_id,
+            tab_id,
+            lines,
+            links,
+            focused,
+        }
+    }
+
+    fn move_focus(&mut self, delta: isize) {
+        let len = self.links.len() as isize;
+        if len > 0 {
+            let mut focused = (self.focused as isize + delta) % len;
+            if focused < 0 {
+                focused += len;
+            }
+            self.focused = focused as usize;
+        }
+    }
+}
+
+impl OverlayDelegate for LinkNavigationOverlay {
+    fn get_title(&self) -> String {
+        match self.links.get(self.focused) {
+            Some(link) => format!(
+                "Link {} of {}: {}",
+                self.focused + 1,
+                self.links.len(),
+                link.link.uri()
+            ),
+            None => "No links".into(),
+        }
+    }
+
+    fn render(&self, rows: usize, _cols: usize) -> String {
+        let mut output = String::new();
+        for (row, line) in self.lines.iter().enumerate().take(rows) {
+            if row > 0 {
+                output.push_str("\r\n");
+            }
+            output.push_str(line.trim_end());
+        }
+
+        // Draw the links over the text; the focused link is shown in
+        // reverse video and the others are underlined
+        for (idx, link) in self.links.iter().enumerate() {
+            if link.row >= rows {
+                continue;
+            }
+            let style = if idx == self.focused { "1;7" } else { "4" };
+            output.push_str(&format!(
+                "\x1b[{};{}H\x1b[{}m{}\x1b[0m",
+                link.row + 1,
+                link.columns.start + 1,
+                style,
+                link.text
+            ));
+        }
+
+        if self.links.is_empty() && rows > 0 {
+            output.push_str(&format!(
+                "\x1b[{};1H\x1b[1;7mThere are no visible links; press Escape\x1b[0m",
+                rows
+            ));
+        }
+        output
+    }
+
+    fn key_down(&mut self, key: KeyCode, mods: KeyModifiers) -> Result<OverlayAction, Error> {
+        match (key, mods) {
+            (KeyCode::Escape, _) | (KeyCode::Char('g'), KeyModifiers::CTRL) => {
+                return Ok(OverlayAction::Close);
+            }
+            (KeyCode::Enter, _) | (KeyCode::Char('\r'), _) | (KeyCode::Char('\n'), _) => {
+                if let Some(link) = self.links.get(self.focused) {
+                    let mux = Mux::get().unwrap();
+                    let cwd = mux
+                        .get_tab(self.tab_id)
+                        .and_then(|tab| tab.get_current_dir());
+                    open_uri(
+                        self.window_id,
+                        mux.config(),
+                        link.link.uri(),
+                        cwd.as_ref().map(String::as_str),
+                    );
+                }
+                return Ok(OverlayAction::Close);
+            }
+            (KeyCode::Tab, KeyModifiers::SHIFT)
+            | (KeyCode::Char('\t'), KeyModifiers::SHIFT)
+            | (KeyCode::UpArrow, _)
+            | (KeyCode::LeftArrow, _)
+            | (KeyCode::Char('k'), KeyModifiers::NONE)
+            | (KeyCode::Char('p'), KeyModifiers::CTRL) => self.move_focus(-1),
+            (KeyCode::Tab, _)
+            | (KeyCode::Char('\t'), _)
+            | (KeyCode::DownArrow, _)
+            | (KeyCode::RightArrow, _)
+            | (KeyCode::Char('j'), KeyModifiers::NONE)
+            | (KeyCode::Char('n'), KeyModifiers::CTRL) => self.move_focus(1),
+            _ => {}
+        }
+        Ok(OverlayAction::Continue)
+    }
+}
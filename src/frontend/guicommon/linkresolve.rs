@@ -0,0 +1,243 @@
+//! Resolves the hyperlinks that refer to files before they are opened.
+//! Programs report their working directory as a `file://` url that
+//! names the host (OSC 7), which allows relative paths matched by the
+//! `hyperlink_rules` to be made absolute, and files on a remote host,
+//! such as one reached via ssh, to be opened as `scp://` urls rather
+//! than as a local path that doesn't exist.
+use std::path::Path;
+
+/// The location of a file, or directory, that was described by a
+/// `file://` url
+#[derive(Debug, PartialEq, Eq)]
+struct FileLocation {
+    /// The host that the file is on, or None if it is this host
+    host: Option<String>,
+    path: String,
+}
+
+impl FileLocation {
+    /// Parses a `file://` url, decoding the escaped characters of its path
+    fn parse(url: &str) -> Option<Self> {
+        let rest = strip_prefix_ignore_case(url, "file:")?;
+        let (host, path) = if rest.starts_with("//") {
+            let rest = &rest[2..];
+            match rest.find('/') {
+                Some(slash) => (&rest[..slash], &rest[slash..]),
+                None => (rest, "/"),
+            }
+        } else {
+            ("", rest)
+        };
+        let mut path = percent_decode(path);
+        // file:///C:/foo refers to C:/foo on Windows
+        if path.starts_with('/') && is_drive_path(&path[1..]) {
+            path.remove(0);
+        }
+        Some(Self {
+            host: if is_local_host(host) {
+                None
+            } else {
+                Some(host.to_owned())
+            },
+            path,
+        })
+    }
+
+    /// Returns the uri that is handed to the opener; local files are
+    /// opened by path, and remote ones via scp
+    fn into_uri(self) -> String {
+        match self.host {
+            Some(host) => format!("scp://{}{}", host, self.path),
+            None => self.path,
+        }
+    }
+}
+
+/// Returns a `file://` url that refers to `path` on this host
+pub fn path_to_file_url(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('%', "%25");
+    if path.starts_with('/') {
+        format!("file://{}", path)
+    } else {
+        format!("file:///{}", path)
+    }
+}
+
+/// Returns the uri that should be opened when the link to `uri` is
+/// followed in a tab whose working directory is the `file://` url
+/// `cwd`.  Links with any scheme other than `file` are unchanged.
+pub fn resolve_link(uri: &str, cwd: Option<&str>) -> String {
+    if let Some(location) = FileLocation::parse(uri) {
+        return location.into_uri();
+    }
+    if has_scheme(uri) {
+        return uri.to_owned();
+    }
+
+    // A path to a file; relative paths are relative to the cwd, and
+    // all of them are on the host that the cwd is on
+    let cwd = match cwd.and_then(FileLocation::parse) {
+        Some(cwd) => cwd,
+        None => return uri.to_owned(),
+    };
+    let path = if is_absolute(uri) {
+        uri.to_owned()
+    } else {
+        let separator = if cwd.path.ends_with('/') || cwd.path.ends_with('\\') {
+            ""
+        } else {
+            "/"
+        };
+        format!("{}{}{}", cwd.path, separator, uri)
+    };
+    FileLocation {
+        host: cwd.host,
+        path,
+    }
+    .into_uri()
+}
+
+fn strip_prefix_ignore_case<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+    if s.len() >= prefix.len()
+        && s.is_char_boundary(prefix.len())
+        && s[..prefix.len()].eq_ignore_ascii_case(prefix)
+    {
+        Some(&s[prefix.len()..])
+    } else {
+        None
+    }
+}
+
+/// Returns true if `uri` begins with a scheme such as `http:`.  A
+/// single letter is taken to be a Windows drive rather than a scheme.
+fn has_scheme(uri: &str) -> bool {
+    match uri.find(':') {
+        Some(colon) if colon > 1 => {
+            let scheme = &uri[..colon];
+            scheme
+                .chars()
+                .next()
+                .map_or(false, |c| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.')
+        }
+        _ => false,
+    }
+}
+
+/// Returns true if `path` begins with a Windows drive, such as `C:\`
+fn is_drive_path(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    bytes.len() >= 3
+        && bytes[0].is_ascii_alphabetic()
+        && bytes[1] == b':'
+        && (bytes[2] == b'/' || bytes[2] == b'\\')
+}
+
+fn is_absolute(path: &str) -> bool {
+    path.starts_with('/') || path.starts_with('\\') || is_drive_path(path)
+}
+
+/// Decodes the `%xx` escapes in `s`.  Invalid escapes are left as
+/// they are.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        if bytes[idx] == b'%'
+            && idx + 2 < bytes.len()
+            && bytes[idx + 1].is_ascii_hexdigit()
+            && bytes[idx + 2].is_ascii_hexdigit()
+        {
+            let hex = std::str::from_utf8(&bytes[idx + 1..idx + 3]).unwrap();
+            decoded.push(u8::from_str_radix(hex, 16).unwrap());
+            idx += 3;
+            continue;
+        }
+        decoded.push(bytes[idx]);
+        idx += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Returns true if `host` refers to this host.  Hosts are compared by
+/// their first label, as shells variously report the short and the
+/// fully qualified name.
+fn is_local_host(host: &str) -> bool {
+    if host.is_empty() || host.eq_ignore_ascii_case("localhost") {
+        return true;
+    }
+    let short = |name: &str| name.split('.').next().unwrap_or("").to_lowercase();
+    local_hostname().map_or(false, |local| short(&local) == short(host))
+}
+
+#[cfg(unix)]
+fn local_hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    let res = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+    if res != 0 {
+        return None;
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    Some(String::from_utf8_lossy(&buf[..len]).into_owned())
+}
+
+#[cfg(windows)]
+fn local_hostname() -> Option<String> {
+    std::env::var("COMPUTERNAME").ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn resolve() {
+        let local = local_hostname().unwrap();
+        let cwd = format!("file://{}/home/wez/my%20project", local);
+        let cwd = Some(cwd.as_str());
+
+        assert_eq!(
+            resolve_link("http://example.com/a", cwd),
+            "http://example.com/a"
+        );
+        assert_eq!(
+            resolve_link("src/main.rs", cwd),
+            "/home/wez/my project/src/main.rs"
+        );
+        assert_eq!(resolve_link("/etc/hosts", cwd), "/etc/hosts");
+        assert_eq!(resolve_link("src/main.rs", None), "src/main.rs");
+        assert_eq!(resolve_link("file:///etc/hosts", None), "/etc/hosts");
+        assert_eq!(
+            resolve_link("file://localhost/etc/hosts", None),
+            "/etc/hosts"
+        );
+        assert_eq!(resolve_link("file:///C:/Windows", None), "C:/Windows");
+        assert_eq!(resolve_link("C:\\Windows", None), "C:\\Windows");
+
+        let remote = Some("file://build-box.example.com/srv/app");
+        assert_eq!(
+            resolve_link("lib/x.py", remote),
+            "scp://build-box.example.com/srv/app/lib/x.py"
+        );
+        assert_eq!(
+            resolve_link("file://build-box/var/log/syslog", None),
+            "scp://build-box/var/log/syslog"
+        );
+    }
+
+    #[test]
+    fn file_urls() {
+        assert_eq!(
+            FileLocation::parse(&path_to_file_url(Path::new("/tmp/100%"))),
+            Some(FileLocation {
+                host: None,
+                path: "/tmp/100%".to_owned()
+            })
+        );
+        assert_eq!(percent_decode("a%2"), "a%2");
+        assert_eq!(percent_decode("%e2%9c%93"), "\u{2713}");
+    }
+}
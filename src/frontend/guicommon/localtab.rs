@@ -1,15 +1,74 @@
+use super::linkresolve::path_to_file_url;
+use super::notify::post_notification;
+use super::overlay::OverlayHost;
+use crate::config::ExitBehavior;
+use crate::mux::accessibility::AccessibleText;
+use crate::mux::latency;
 use crate::mux::renderable::Renderable;
-use crate::mux::tab::{alloc_tab_id, Tab, TabId};
-use crate::pty::{Child, MasterPty, PtySize};
+use crate::mux::renderfilter;
+use crate::mux::tab::{FailedSpawn, InspectSection, Tab, TabId};
+use crate::mux::Mux;
+use crate::pty::{
+    Child, CommandBuilder, ExitStatus, ForegroundProcess, MasterPty, NonBlockingReader, PtySize,
+};
 use failure::Error;
-use std::cell::{RefCell, RefMut};
-use term::{KeyCode, KeyModifiers, MouseEvent, Terminal, TerminalHost};
+use std::cell::{Cell, RefCell, RefMut};
+use std::path::PathBuf;
+use term::{KeyCode, KeyModifiers, MouseEvent, Terminal, TerminalHost, UnhandledSequence};
+
+/// Records how the process in a tab was spawned, so that it
+/// can be shown by the tab inspector
+#[derive(Debug, Clone)]
+pub struct SpawnInfo {
+    argv: Vec<String>,
+    envs: Vec<(String, String)>,
+    cwd: Option<PathBuf>,
+}
+
+impl SpawnInfo {
+    /// Capture the details of `cmd`; this must be called prior to
+    /// passing `cmd` to `spawn_command`.  The working directory is
+    /// only known if `cmd` specifies one.
+    pub fn new(cmd: &CommandBuilder) -> Self {
+        Self {
+            argv: cmd
+                .get_argv()
+                .iter()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect(),
+            envs: cmd
+                .get_envs()
+                .iter()
+                .map(|(key, val)| {
+                    (
+                        key.to_string_lossy().into_owned(),
+                        val.to_string_lossy().into_owned(),
+                    )
+                })
+                .collect(),
+            cwd: cmd.get_cwd().map(PathBuf::from),
+        }
+    }
+}
 
 pub struct LocalTab {
     tab_id: TabId,
     terminal: RefCell<Terminal>,
     process: RefCell<Box<Child>>,
     pty: RefCell<Box<MasterPty>>,
+    spawn_info: SpawnInfo,
+    /// Whether the tab remains open after its process has exited
+    /// until a key is pressed, so that its final output can be read
+    exit_behavior: ExitBehavior,
+    /// Set once the exit of a held tab has been announced in the tab
+    exit_announced: Cell<bool>,
+    /// Set once a key has been pressed to close a held tab
+    released: Cell<bool>,
+    /// The exit status of the process, once it has been noticed
+    exit_status: Cell<Option<ExitStatus>>,
+    /// Why the intended program couldn't be spawned, until the
+    /// window has explained it
+    spawn_failure: RefCell<Option<FailedSpawn>>,
 }
 
 impl Tab for LocalTab {
@@ -23,11 +82,39 @@ impl Tab for LocalTab {
     }
 
     fn is_dead(&self) -> bool {
-        if let Ok(None) = self.process.borrow_mut().try_wait() {
-            false
-        } else {
-            true
+        let status = match self.process.borrow_mut().try_wait() {
+            Ok(None) => return false,
+            Ok(status) => status,
+            Err(_) => None,
+        };
+        if let Some(status) = status {
+            self.exited(status);
+        }
+        if !self.holds_after(status) || self.released.get() {
+            return true;
         }
+        if !self.exit_announced.replace(true) {
+            let message = match status {
+                Some(status) => format!(
+                    "\r\n\x1b[0m[process exited with {}; press any key to close]",
+                    status
+                ),
+                None => "\r\n\x1b[0m[process exited; press any key to close]".to_string(),
+            };
+            self.terminal
+                .borrow_mut()
+                .advance_bytes(message.as_bytes(), &mut OverlayHost::default());
+            Mux::get().unwrap().notify_output();
+        }
+        false
+    }
+
+    fn exit_status(&self) -> Option<ExitStatus> {
+        self.process
+            .borrow_mut()
+            .try_wait()
+            .ok()
+            .and_then(|status| status)
     }
 
     fn advance_bytes(&self, buf: &[u8], host: &mut TerminalHost) {
@@ -39,9 +126,38 @@ impl Tab for LocalTab {
     }
 
     fn key_down(&self, key: KeyCode, mods: KeyModifiers) -> Result<(), Error> {
+        if self.exit_announced.get() {
+            // The process is gone; this key press closes the tab
+            self.released.set(true);
+            Mux::get().unwrap().notify_output();
+            return Ok(());
+        }
+        self.terminal
+            .borrow_mut()
+            .key_down(key, mods, &mut *self.pty.borrow_mut())?;
+        latency::key_written(self.tab_id);
+        Ok(())
+    }
+
+    fn send_text(&self, text: &str) -> Result<(), Error> {
+        self.terminal
+            .borrow_mut()
+            .send_text(text, &mut *self.pty.borrow_mut())
+    }
+
+    fn key_up(&self, key: KeyCode, mods: KeyModifiers) -> Result<(), Error> {
+        if self.exit_announced.get() {
+            return Ok(());
+        }
         self.terminal
             .borrow_mut()
-            .key_down(key, mods, &mut *self.pty.borrow_mut())
+            .key_up(key, mods, &mut *self.pty.borrow_mut())
+    }
+
+    fn focus_changed(&self, focused: bool) -> Result<(), Error> {
+        self.terminal
+            .borrow_mut()
+            .focus_changed(focused, &mut *self.pty.borrow_mut())
     }
 
     fn resize(
@@ -71,6 +187,10 @@ impl Tab for LocalTab {
         self.pty.borrow_mut().try_clone_reader()
     }
 
+    fn nonblocking_reader(&self) -> Result<Box<NonBlockingReader>, Error> {
+        self.pty.borrow_mut().try_clone_nonblocking_reader()
+    }
+
     fn send_paste(&self, text: &str) -> Result<(), Error> {
         self.terminal
             .borrow_mut()
@@ -78,18 +198,218 @@ impl Tab for LocalTab {
     }
 
     fn get_title(&self) -> String {
-        self.terminal.borrow_mut().get_title().to_string()
+        let title = self.terminal.borrow_mut().get_title().to_string();
+        match self.exit_status.get() {
+            Some(status) if !status.success() => format!("[{}] {}", status, title),
+            _ => title,
+        }
+    }
+
+    fn unhandled_sequences(&self) -> (usize, Vec<UnhandledSequence>) {
+        let terminal = self.terminal.borrow();
+        (
+            terminal.unhandled_count(),
+            terminal.recent_unhandled().cloned().collect(),
+        )
+    }
+
+    fn foreground_process(&self) -> Option<ForegroundProcess> {
+        self.pty.borrow().foreground_process()
+    }
+
+    fn take_spawn_failure(&self) -> Option<FailedSpawn> {
+        self.spawn_failure.borrow_mut().take()
+    }
+
+    fn kill(&self) -> Result<(), Error> {
+        // Don't hold the tab open once the process has gone
+        self.released.set(true);
+        self.process.borrow_mut().kill()?;
+        Ok(())
+    }
+
+    /// Prefers the directory that the program has reported, as it may
+    /// be running on another host, then that of the foreground process,
+    /// as it may have changed directory since it was spawned
+    fn get_current_dir(&self) -> Option<String> {
+        self.terminal
+            .borrow()
+            .get_current_dir()
+            .map(str::to_owned)
+            .or_else(|| {
+                self.foreground_process()
+                    .and_then(|process| process.cwd)
+                    .map(|cwd| path_to_file_url(&cwd))
+            })
+            .or_else(|| {
+                self.spawn_info
+                    .cwd
+                    .as_ref()
+                    .map(|cwd| path_to_file_url(cwd))
+            })
+    }
+
+    fn inspect(&self) -> Vec<InspectSection> {
+        let info = &self.spawn_info;
+        let terminal = self.terminal.borrow();
+        let pid = match self.process.borrow().process_id() {
+            Some(pid) => pid.to_string(),
+            None => "unknown".to_string(),
+        };
+        let foreground = match self.foreground_process() {
+            Some(process) => format!(
+                "{} (pid {}) in {}",
+                process
+                    .name
+                    .as_ref()
+                    .map(String::as_str)
+                    .unwrap_or("unknown"),
+                process.pid,
+                process
+                    .cwd
+                    .as_ref()
+                    .map(|cwd| cwd.display().to_string())
+                    .unwrap_or_else(|| "unknown".to_string())
+            ),
+            None => "unknown".to_string(),
+        };
+
+        let mut envs: Vec<String> = info
+            .envs
+            .iter()
+            .map(|(key, val)| format!("{}={}", key, val))
+            .collect();
+        if envs.is_empty() {
+            envs.push("(none)".to_string());
+        }
+
+        vec![
+            InspectSection {
+                title: "Process".to_string(),
+                lines: vec![
+                    format!("command: {}", info.argv.join(" ")),
+                    format!(
+                        "cwd: {}",
+                        info.cwd
+                            .as_ref()
+                            .map(|cwd| cwd.display().to_string())
+                            .unwrap_or_else(|| "unknown".to_string())
+                    ),
+                    format!(
+                        "reported cwd: {}",
+                        terminal.get_current_dir().unwrap_or("unknown")
+                    ),
+                    format!("pid: {}", pid),
+                    format!("foreground: {}", foreground),
+                    "domain: local".to_string(),
+                ],
+            },
+            InspectSection {
+                title: "Environment overrides".to_string(),
+                lines: envs,
+            },
+            InspectSection {
+                title: "Terminal modes".to_string(),
+                lines: terminal
+                    .get_modes()
+                    .into_iter()
+                    .map(|(name, value)| format!("{}: {}", name, value))
+                    .collect(),
+            },
+            InspectSection {
+                title: format!(
+                    "Ignored escape sequences ({} in total, most recent last)",
+                    terminal.unhandled_count()
+                ),
+                lines: terminal
+                    .recent_unhandled()
+                    .flat_map(|seq| {
+                        Some(seq.description.clone())
+                            .into_iter()
+                            .chain(seq.hex_dump().into_iter().map(|line| format!("  {}", line)))
+                    })
+                    .collect(),
+            },
+            InspectSection {
+                title: "Input latency".to_string(),
+                lines: latency::report(self.tab_id),
+            },
+            InspectSection {
+                title: "Screen reader announcements (most recent last)".to_string(),
+                lines: renderfilter::report(self.tab_id),
+            },
+            InspectSection {
+                title: "Accessible text".to_string(),
+                lines: AccessibleText::from_renderable(&*terminal).report(),
+            },
+        ]
     }
 }
 
 impl LocalTab {
-    pub fn new(terminal: Terminal, process: Box<Child>, pty: Box<MasterPty>) -> Self {
-        let tab_id = alloc_tab_id();
+    pub fn new(
+        tab_id: TabId,
+        terminal: Terminal,
+        process: Box<Child>,
+        pty: Box<MasterPty>,
+        spawn_info: SpawnInfo,
+    ) -> Self {
         Self {
             tab_id,
             terminal: RefCell::new(terminal),
             process: RefCell::new(process),
             pty: RefCell::new(pty),
+            spawn_info,
+            exit_behavior: ExitBehavior::Close,
+            exit_announced: Cell::new(false),
+            released: Cell::new(false),
+            exit_status: Cell::new(None),
+            spawn_failure: RefCell::new(None),
+        }
+    }
+
+    /// Records that the process has exited.  The first time that an
+    /// unsuccessful exit is noticed, it is reported to the window.
+    fn exited(&self, status: ExitStatus) {
+        if self.exit_status.replace(Some(status)).is_some() || status.success() {
+            return;
+        }
+        if self.holds_after(Some(status)) {
+            // The status is shown in the tab itself
+            return;
+        }
+        let mux = Mux::get().unwrap();
+        if !mux.config().notify_on_failed_exit {
+            return;
+        }
+        if let Some(window_id) = mux.window_containing_tab(self.tab_id) {
+            let title = self.terminal.borrow_mut().get_title().to_string();
+            post_notification(
+                window_id,
+                format!("The process in tab \"{}\" exited with {}", title, status),
+            );
+        }
+    }
+
+    /// Controls whether the tab is kept open after its process exits
+    /// until a key is pressed, rather than closing it straight away
+    pub fn set_exit_behavior(&mut self, behavior: ExitBehavior) {
+        self.exit_behavior = behavior;
+    }
+
+    /// Records that the process is the `fallback_prog`, which was run
+    /// because the intended program couldn't be spawned
+    pub fn set_spawn_failure(&mut self, failure: FailedSpawn) {
+        self.spawn_failure = RefCell::new(Some(failure));
+    }
+
+    /// Returns true if the tab remains open after its process exited
+    /// with `status`, which is None if it couldn't be determined
+    fn holds_after(&self, status: Option<ExitStatus>) -> bool {
+        match self.exit_behavior {
+            ExitBehavior::Close => false,
+            ExitBehavior::CloseOnCleanExit => !status.map_or(false, |status| status.success()),
+            ExitBehavior::Hold => true,
         }
     }
 }
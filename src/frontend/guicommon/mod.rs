@@ -1,3 +1,19 @@
+pub mod clipboard;
+pub mod displays;
+pub mod download;
+pub mod framepacer;
+pub mod highcontrast;
 pub mod host;
+pub mod idle;
+pub mod inspector;
+pub mod launcher;
+pub mod linknav;
+pub mod linkresolve;
 pub mod localtab;
+pub mod notify;
+pub mod overlay;
+pub mod quickselect;
+pub mod scrollbar;
+pub mod spawnfailure;
+pub mod spawnqueue;
 pub mod window;
@@ -0,0 +1,88 @@
+//! Notifications are short messages, such as a report that a
+//! hyperlink could not be opened, that need to be seen by the user
+//! rather than only written to the log.  They are displayed in an
+//! overlay the next time that the window is painted.
+use super::overlay::{OverlayAction, OverlayDelegate};
+use crate::mux::window::WindowId;
+use crate::mux::Mux;
+use failure::Error;
+use std::cell::RefCell;
+use term::{KeyCode, KeyModifiers};
+
+thread_local! {
+    static PENDING: RefCell<Vec<(WindowId, String)>> = RefCell::new(vec![]);
+}
+
+/// Queue `message` to be shown in the window `window_id`.
+/// This must be called on the gui thread.
+pub fn post_notification(window_id: WindowId, message: String) {
+    PENDING.with(|pending| pending.borrow_mut().push((window_id, message)));
+    // Ensure that a frame is scheduled so that it is seen promptly
+    Mux::get().unwrap().notify_output();
+}
+
+/// Removes and returns the messages queued for the window `window_id`
+pub fn take_notifications(window_id: WindowId) -> Vec<String> {
+    PENDING.with(|pending| {
+        let mut pending = pending.borrow_mut();
+        let (mine, others): (Vec<_>, Vec<_>) =
+            pending.drain(..).partition(|(id, _)| *id == window_id);
+        *pending = others;
+        mine.into_iter().map(|(_, message)| message).collect()
+    })
+}
+
+pub struct NotificationOverlay {
+    messages: Vec<String>,
+}
+
+impl NotificationOverlay {
+    pub fn new(messages: Vec<String>) -> Self {
+        Self { messages }
+    }
+}
+
+impl OverlayDelegate for NotificationOverlay {
+    fn get_title(&self) -> String {
+        "Notification".into()
+    }
+
+    fn render(&self, rows: usize, cols: usize) -> String {
+        let mut lines = vec![];
+        for message in &self.messages {
+            for line in message.lines() {
+                let chars: Vec<char> = line
+                    .chars()
+                    .map(|c| if c.is_control() { '?' } else { c })
+                    .collect();
+                // Wrap long lines rather than letting them be truncated
+                for chunk in chars.chunks(cols.max(1)) {
+                    lines.push(chunk.iter().collect::<String>());
+                }
+            }
+            lines.push(String::new());
+        }
+
+        let mut output = String::new();
+        for line in lines.iter().take(rows.saturating_sub(1)) {
+            output.push_str(line);
+            output.push_str("\r\n");
+        }
+        output.push_str(&format!(
+            "\x1b[{};1H\x1b[7mPress Escape or Enter to dismiss\x1b[0m",
+            rows
+        ));
+        output
+    }
+
+    fn key_down(&mut self, key: KeyCode, _mods: KeyModifiers) -> Result<OverlayAction, Error> {
+        match key {
+            KeyCode::Escape
+            | KeyCode::Enter
+            | KeyCode::Char('\r')
+            | KeyCode::Char('\n')
+            | KeyCode::Char('q') => Ok(OverlayAction::Close),
+            _ => Ok(OverlayAction::Continue),
+        }
+    }
+}
@@ -0,0 +1,252 @@
+//! Overlays are transient, keyboard driven tabs that temporarily take
+//! the place of the active tab in a window.  They don't have a pty;
+//! instead an `OverlayDelegate` renders its content as text and
+//! escape sequences into an in-memory terminal model, which allows
+//! the usual rendering machinery to display it.
+use crate::keyassignment::KeyAssignment;
+use crate::mux::renderable::Renderable;
+use crate::mux::tab::{alloc_tab_id, Tab, TabId};
+use crate::mux::window::WindowId;
+use crate::mux::Mux;
+use failure::{err_msg, Error};
+use std::cell::{RefCell, RefMut};
+use std::sync::Arc;
+use term::{KeyCode, KeyModifiers, MouseEvent, Terminal, TerminalHost};
+use termwiz::hyperlink::Hyperlink;
+
+thread_local! {
+    /// Key assignments chosen in overlays that are waiting to be
+    /// carried out by the window that showed the overlay
+    static PENDING: RefCell<Vec<(WindowId, KeyAssignment)>> = RefCell::new(vec![]);
+}
+
+/// Removes and returns the key assignments that overlays in the
+/// specified window have asked to be performed
+pub fn take_pending_assignments(window_id: WindowId) -> Vec<KeyAssignment> {
+    PENDING.with(|pending| {
+        let mut pending = pending.borrow_mut();
+        let (mine, others): (Vec<_>, Vec<_>) =
+            pending.drain(..).partition(|(id, _)| *id == window_id);
+        *pending = others;
+        mine.into_iter().map(|(_, assignment)| assignment).collect()
+    })
+}
+
+/// Returned from `OverlayDelegate::key_down` to indicate what
+/// should happen to the overlay
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OverlayAction {
+    /// Keep the overlay active and re-render it
+    Continue,
+    /// Remove the overlay and reveal the tab underneath
+    Close,
+    /// Remove the overlay, then carry out the assignment as though
+    /// its key had been pressed in the tab underneath
+    Perform(KeyAssignment),
+}
+
+pub trait OverlayDelegate {
+    /// Returns the title to show while the overlay is active
+    fn get_title(&self) -> String;
+
+    /// Returns the content of the overlay as text, possibly
+    /// including escape sequences, sized for the supplied
+    /// dimensions.  The screen is cleared and the cursor homed
+    /// prior to rendering the returned text.
+    fn render(&self, rows: usize, cols: usize) -> String;
+
+    fn key_down(&mut self, key: KeyCode, mods: KeyModifiers) -> Result<OverlayAction, Error>;
+
+    fn send_paste(&mut self, _text: &str) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// The overlay renders into its own terminal model; nothing that
+/// it writes can elicit a response that needs to go anywhere, so
+/// this host swallows everything.  It is also useful for writing
+/// informational text into a tab whose process has exited.
+pub struct OverlayHost {
+    writer: std::io::Sink,
+}
+
+impl Default for OverlayHost {
+    fn default() -> Self {
+        Self {
+            writer: std::io::sink(),
+        }
+    }
+}
+
+impl TerminalHost for OverlayHost {
+    fn writer(&mut self) -> &mut std::io::Write {
+        &mut self.writer
+    }
+
+    fn click_link(&mut self, _link: &Arc<Hyperlink>) {}
+
+    fn get_clipboard(&mut self) -> Result<String, Error> {
+        Ok("".into())
+    }
+
+    fn set_clipboard(&mut self, _clip: Option<String>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn set_title(&mut self, _title: &str) {}
+}
+
+pub struct OverlayTab {
+    tab_id: TabId,
+    window_id: WindowId,
+    terminal: RefCell<Terminal>,
+    delegate: RefCell<Box<OverlayDelegate>>,
+    writer: RefCell<std::io::Sink>,
+}
+
+impl OverlayTab {
+    pub fn new(
+        window_id: WindowId,
+        rows: usize,
+        cols: usize,
+        delegate: Box<OverlayDelegate>,
+    ) -> Self {
+        let overlay = Self {
+            tab_id: alloc_tab_id(),
+            window_id,
+            terminal: RefCell::new(Terminal::new(rows, cols, 0, vec![])),
+            delegate: RefCell::new(delegate),
+            writer: RefCell::new(std::io::sink()),
+        };
+        overlay.render();
+        overlay
+    }
+
+    fn render(&self) {
+        let mut terminal = self.terminal.borrow_mut();
+        let (rows, cols) = terminal.physical_dimensions();
+        let text = self.delegate.borrow().render(rows, cols);
+        let mut host = OverlayHost::default();
+        terminal.advance_bytes(b"\x1b[0m\x1b[2J\x1b[H", &mut host);
+        terminal.advance_bytes(text.as_bytes(), &mut host);
+        terminal.make_all_lines_dirty();
+    }
+
+    fn close(&self) {
+        let mux = Mux::get().unwrap();
+        if let Some(mut window) = mux.get_window_mut(self.window_id) {
+            window.clear_overlay();
+        }
+    }
+}
+
+impl Tab for OverlayTab {
+    #[inline]
+    fn tab_id(&self) -> TabId {
+        self.tab_id
+    }
+
+    fn renderer(&self) -> RefMut<Renderable> {
+        RefMut::map(self.terminal.borrow_mut(), |t| &mut *t)
+    }
+
+    fn get_title(&self) -> String {
+        self.delegate.borrow().get_title()
+    }
+
+    fn send_paste(&self, text: &str) -> Result<(), Error> {
+        self.delegate.borrow_mut().send_paste(text)?;
+        self.render();
+        Ok(())
+    }
+
+    fn reader(&self) -> Result<Box<std::io::Read + Send>, Error> {
+        Err(err_msg("overlays have no pty to read from"))
+    }
+
+    fn writer(&self) -> RefMut<std::io::Write> {
+        self.writer.borrow_mut()
+    }
+
+    fn resize(
+        &self,
+        rows: u16,
+        cols: u16,
+        _pixel_width: u16,
+        _pixel_height: u16,
+    ) -> Result<(), Error> {
+        self.terminal
+            .borrow_mut()
+            .resize(rows as usize, cols as usize);
+        self.render();
+        Ok(())
+    }
+
+    fn key_down(&self, key: KeyCode, mods: KeyModifiers) -> Result<(), Error> {
+        let action = self.delegate.borrow_mut().key_down(key, mods)?;
+        match action {
+            OverlayAction::Continue => self.render(),
+            OverlayAction::Close => self.close(),
+            OverlayAction::Perform(assignment) => {
+                self.close();
+                PENDING.with(|pending| {
+                    pending.borrow_mut().push((self.window_id, assignment));
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn mouse_event(&self, _event: MouseEvent, _host: &mut TerminalHost) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn advance_bytes(&self, _buf: &[u8], _host: &mut TerminalHost) {}
+
+    fn is_dead(&self) -> bool {
+        false
+    }
+}
+
+/// Performs a simple fuzzy match of `pattern` against `candidate`.
+/// Each character of `pattern` must appear in `candidate` in the same
+/// order, ignoring case.  Returns a score for which lower values
+/// indicate a better match, or None if `candidate` doesn't match.
+pub fn fuzzy_match(pattern: &str, candidate: &str) -> Option<usize> {
+    let mut score = 0;
+    let mut last_match = None;
+    let mut chars = candidate.chars().flat_map(char::to_lowercase).enumerate();
+
+    for p in pattern.chars().flat_map(char::to_lowercase) {
+        loop {
+            let (idx, c) = chars.next()?;
+            if c == p {
+                // Penalize the distance from the start for the first
+                // character and the gaps between subsequent matches
+                score += match last_match {
+                    None => idx,
+                    Some(last) => idx - last - 1,
+                };
+                last_match = Some(idx);
+                break;
+            }
+        }
+    }
+
+    Some(score)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fuzzy() {
+        assert_eq!(fuzzy_match("", "anything"), Some(0));
+        assert_eq!(fuzzy_match("abc", "abc"), Some(0));
+        assert_eq!(fuzzy_match("ABC", "xabc"), Some(1));
+        assert_eq!(fuzzy_match("ac", "abc"), Some(1));
+        assert_eq!(fuzzy_match("ca", "abc"), None);
+        assert_eq!(fuzzy_match("abcd", "abc"), None);
+    }
+}
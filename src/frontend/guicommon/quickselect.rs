@@ -0,0 +1,279 @@
+//! Quick select mode scans the visible portion of a tab for text that
+//! is commonly copied, such as URLs, paths and hashes, and labels each
+//! match with a short hint.  Typing a hint copies the matching text;
+//! typing it in uppercase opens it instead.
+use super::clipboard::{record_history, set_register};
+use super::overlay::{OverlayAction, OverlayDelegate};
+use crate::mux::tab::TabId;
+use crate::mux::Mux;
+use clipboard::{ClipboardContext, ClipboardProvider};
+use failure::Error;
+use regex::Regex;
+use std::ops::Range;
+use term::{KeyCode, KeyModifiers};
+
+/// The characters used to build hint labels, ordered so that the
+/// easiest keys to reach are used first
+const ALPHABET: &str = "asdfqwerzxcvjklmiuopghtybn";
+
+struct Match {
+    /// The row in the viewport on which the match was found
+    row: usize,
+    /// The byte range of the match within the text of that row
+    range: Range<usize>,
+    label: String,
+}
+
+pub struct QuickSelectOverlay {
+    /// The text of the lines that were visible in the tab
+    lines: Vec<String>,
+    matches: Vec<Match>,
+    /// The portion of a label that has been typed so far
+    typed: String,
+}
+
+impl QuickSelectOverlay {
+    pub fn new(tab_id: TabId) -> Self {
+        let mux = Mux::get().unwrap();
+        let lines = match mux.get_tab(tab_id) {
+            Some(tab) => tab.renderer().get_viewport_text(),
+            None => vec![],
+        };
+
+        let patterns: Vec<Regex> = mux
+            .config()
+            .quick_select_patterns
+            .iter()
+            .filter_map(|pattern| match Regex::new(pattern) {
+                Ok(re) => Some(re),
+                Err(err) => {
+                    eprintln!("invalid quick_select_patterns entry {}: {}", pattern, err);
+                    None
+                }
+            })
+            .collect();
+
+        let matches = find_matches(&lines, &patterns);
+        Self {
+            lines,
+            matches,
+            typed: String::new(),
+        }
+    }
+
+    fn text_of(&self, m: &Match) -> String {
+        self.lines[m.row][m.range.clone()].to_owned()
+    }
+}
+
+/// Finds the non-overlapping matches of `patterns` in `lines`.
+/// Where matches overlap, the one that starts first wins, and the
+/// longer of those that start at the same position.
+/// Identical text is given the same label.
+fn find_matches(lines: &[String], patterns: &[Regex]) -> Vec<Match> {
+    let mut found = vec![];
+    for (row, line) in lines.iter().enumerate() {
+        let mut ranges: Vec<Range<usize>> = patterns
+            .iter()
+            .flat_map(|re| re.find_iter(line).map(|m| m.start()..m.end()))
+            .filter(|range| !range.is_empty())
+            .collect();
+        ranges.sort_by_key(|range| (range.start, std::cmp::Reverse(range.end)));
+
+        let mut end = 0;
+        for range in ranges {
+            if range.start >= end {
+                end = range.end;
+                found.push((row, range));
+            }
+        }
+    }
+
+    let mut unique: Vec<&str> = vec![];
+    for (row, range) in &found {
+        let text = &lines[*row][range.clone()];
+        if !unique.contains(&text) {
+            unique.push(text);
+        }
+    }
+    let labels = compute_labels(unique.len());
+
+    found
+        .iter()
+        .filter_map(|(row, range)| {
+            let text = &lines[*row][range.clone()];
+            let idx = unique.iter().position(|t| *t == text)?;
+            let label = labels.get(idx)?.clone();
+            Some(Match {
+                row: *row,
+                range: range.clone(),
+                label,
+            })
+        })
+        .collect()
+}
+
+/// Produces up to `count` labels, none of which is a prefix of
+/// another.  Single characters are used when they suffice, otherwise
+/// all labels are two characters long.
+fn compute_labels(count: usize) -> Vec<String> {
+    let chars: Vec<char> = ALPHABET.chars().collect();
+    if count <= chars.len() {
+        return chars.iter().take(count).map(|c| c.to_string()).collect();
+    }
+    let mut labels = vec![];
+    for a in &chars {
+        for b in &chars {
+            if labels.len() == count {
+                return labels;
+            }
+            labels.push(format!("{}{}", a, b));
+        }
+    }
+    labels
+}
+
+/// Copy `text` to wherever selections are copied to; see
+/// `HostImpl::set_selection_text`
+fn copy_text(text: String) -> Result<(), Error> {
+    let mux = Mux::get().unwrap();
+    record_history(mux.config(), &text);
+    match mux.config().copy_register.as_ref() {
+        Some(name) => set_register(name, text),
+        None => {
+            let mut clipboard = ClipboardContext::new().map_err(|e| format_err!("{}", e))?;
+            clipboard
+                .set_contents(text)
+                .map_err(|e| format_err!("{}", e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Produce a copy of `line` with the text of its matches highlighted
+/// and their labels written over the start of the matched text
+fn render_line(line: &str, matches: &[&Match], typed: &str) -> String {
+    let mut output = String::new();
+    let mut pos = 0;
+    for m in matches {
+        output.push_str(&line[pos..m.range.start]);
+        let text = &line[m.range.clone()];
+        pos = m.range.end;
+
+        if !m.label.starts_with(typed) {
+            output.push_str(text);
+            continue;
+        }
+
+        let label_len = m.label.chars().count();
+        let rest: String = text.chars().skip(label_len).collect();
+        output.push_str(&format!(
+            "\x1b[1;30;43m{}\x1b[0;33m{}\x1b[0m",
+            m.label, rest
+        ));
+    }
+    output.push_str(&line[pos..]);
+    output
+}
+
+impl OverlayDelegate for QuickSelectOverlay {
+    fn get_title(&self) -> String {
+        "Quick Select".into()
+    }
+
+    fn render(&self, rows: usize, _cols: usize) -> String {
+        let mut output = String::new();
+        for (row, line) in self.lines.iter().enumerate().take(rows) {
+            if row > 0 {
+                output.push_str("\r\n");
+            }
+            let matches: Vec<&Match> = self.matches.iter().filter(|m| m.row == row).collect();
+            output.push_str(&render_line(line.trim_end(), &matches, &self.typed));
+        }
+        output
+    }
+
+    fn key_down(&mut self, key: KeyCode, mods: KeyModifiers) -> Result<OverlayAction, Error> {
+        let (c, open) = match (key, mods) {
+            (KeyCode::Escape, _) | (KeyCode::Char('g'), KeyModifiers::CTRL) => {
+                return Ok(OverlayAction::Close);
+            }
+            (KeyCode::Backspace, _) | (KeyCode::Char('\x08'), _) | (KeyCode::Char('\x7f'), _) => {
+                self.typed.pop();
+                return Ok(OverlayAction::Continue);
+            }
+            (KeyCode::Char(c), KeyModifiers::NONE) if c.is_ascii_lowercase() => (c, false),
+            (KeyCode::Char(c), KeyModifiers::SHIFT) | (KeyCode::Char(c), KeyModifiers::NONE)
+                if c.is_ascii_alphabetic() =>
+            {
+                (c.to_ascii_lowercase(), true)
+            }
+            _ => return Ok(OverlayAction::Continue),
+        };
+
+        self.typed.push(c);
+        if let Some(m) = self.matches.iter().find(|m| m.label == self.typed) {
+            let text = self.text_of(m);
+            if open {
+                if let Err(err) = open::that(&text) {
+                    eprintln!("failed to open {}: {:?}", text, err);
+                }
+            } else {
+                copy_text(text)?;
+            }
+            return Ok(OverlayAction::Close);
+        }
+
+        if !self
+            .matches
+            .iter()
+            .any(|m| m.label.starts_with(&self.typed))
+        {
+            // Nothing has this prefix; start over rather than leaving
+            // the user stuck
+            self.typed.clear();
+        }
+        Ok(OverlayAction::Continue)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn labels() {
+        assert_eq!(compute_labels(3), vec!["a", "s", "d"]);
+        let labels = compute_labels(ALPHABET.len() + 1);
+        assert_eq!(labels.len(), ALPHABET.len() + 1);
+        assert_eq!(labels[0], "aa");
+        assert_eq!(labels[ALPHABET.len()], "sa");
+    }
+
+    #[test]
+    fn matching() {
+        let patterns = vec![
+            Regex::new(r"\b\w+://[\w.-]+\S*").unwrap(),
+            Regex::new(r"[\w.~-]*/[\w.@~/-]+").unwrap(),
+            Regex::new(r"\b[0-9a-f]{7,64}\b").unwrap(),
+        ];
+        let lines = vec![
+            "see https://example.com/foo for cac9681".to_owned(),
+            "cac9681 touched src/main.rs".to_owned(),
+        ];
+        let matches = find_matches(&lines, &patterns);
+        let found: Vec<(usize, &str, &str)> = matches
+            .iter()
+            .map(|m| (m.row, &lines[m.row][m.range.clone()], m.label.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (0, "https://example.com/foo", "a"),
+                (0, "cac9681", "s"),
+                (1, "cac9681", "s"),
+                (1, "src/main.rs", "d"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,206 @@
+//! Computes the geometry of the scrollbar and maps mouse
+//! interaction with it onto the viewport of the terminal.
+use crate::mux::renderable::Renderable;
+
+/// The position of the scrollbar thumb, in physical pixels
+/// relative to the top of the window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScrollThumb {
+    pub top: f32,
+    pub height: f32,
+}
+
+/// Compute the thumb position for the given viewport offset and
+/// total number of rows.  Returns None when the viewport is at the
+/// bottom, as the scrollbar is hidden in that state.
+pub fn compute_thumb(
+    viewport_offset: usize,
+    total_rows: usize,
+    physical_rows: usize,
+    height: f32,
+    min_thumb_height: f32,
+) -> Option<ScrollThumb> {
+    if viewport_offset == 0 || total_rows == 0 {
+        return None;
+    }
+    let top_row = total_rows.saturating_sub(physical_rows + viewport_offset);
+
+    let thumb_height = thumb_height(total_rows, physical_rows, height, min_thumb_height);
+    // When the thumb is inflated to the minimum height, the travel
+    // range is reduced accordingly
+    let travel = height - thumb_height;
+    let max_top_row = total_rows.saturating_sub(physical_rows).max(1);
+    let top = travel * top_row as f32 / max_top_row as f32;
+
+    Some(ScrollThumb {
+        top,
+        height: thumb_height,
+    })
+}
+
+/// The height of the thumb is proportional to the fraction of the
+/// scrollback that is visible in the viewport
+fn thumb_height(
+    total_rows: usize,
+    physical_rows: usize,
+    height: f32,
+    min_thumb_height: f32,
+) -> f32 {
+    (height * physical_rows as f32 / total_rows.max(1) as f32)
+        .max(min_thumb_height)
+        .min(height)
+}
+
+/// The inverse of `compute_thumb`: given the desired top of the
+/// thumb, compute the corresponding viewport offset
+pub fn offset_for_thumb_top(
+    thumb: ScrollThumb,
+    total_rows: usize,
+    physical_rows: usize,
+    height: f32,
+) -> usize {
+    let travel = (height - thumb.height).max(1.0);
+    let max_top_row = total_rows.saturating_sub(physical_rows);
+    let frac = (thumb.top / travel).max(0.0).min(1.0);
+    let top_row = (frac * max_top_row as f32).round() as usize;
+    max_top_row.saturating_sub(top_row)
+}
+
+/// The number of columns available to the terminal in a window that
+/// is `width` pixels wide.  When the scrollbar is enabled the rightmost
+/// column is reserved for it, so that the thumb doesn't obscure text.
+pub fn terminal_cols(enable_scroll_bar: bool, width: u16, cell_width: usize) -> u16 {
+    // The +1 allows a column that is one pixel short of fitting;
+    // see the comment on the equivalent row computation in
+    // TerminalWindow::resize_surfaces
+    let cols = ((width as usize + 1) / cell_width) as u16;
+    if enable_scroll_bar {
+        cols.saturating_sub(1).max(1)
+    } else {
+        cols
+    }
+}
+
+/// Tracks an in-progress drag of the scrollbar thumb
+#[derive(Debug, Clone, Copy)]
+pub struct ScrollDrag {
+    /// The distance from the top of the thumb to the mouse
+    /// position at the start of the drag
+    grab_offset: f32,
+}
+
+/// The geometry of the window, used to interpret mouse positions
+pub struct ScrollbarArea {
+    pub width: f32,
+    pub height: f32,
+    pub cell_width: f32,
+    pub cell_height: f32,
+}
+
+impl ScrollbarArea {
+    /// The scrollbar occupies a column along the right edge of
+    /// the window
+    pub fn bar_width(&self) -> f32 {
+        self.cell_width
+    }
+
+    fn thumb(&self, term: &Renderable) -> Option<ScrollThumb> {
+        let (offset, total) = term.get_scrollbar_info();
+        let (rows, _cols) = term.physical_dimensions();
+        compute_thumb(offset, total, rows, self.height, self.cell_height)
+    }
+
+    /// Handle a left button press at the given pixel position.
+    /// Returns Some if the press started a drag of the scrollbar.
+    /// Pressing outside of the thumb jumps to that position.
+    pub fn press(&self, term: &mut Renderable, x: f32, y: f32) -> Option<ScrollDrag> {
+        if x < self.width - self.bar_width() {
+            return None;
+        }
+        let thumb = self.thumb(term)?;
+        let grab_offset = if y >= thumb.top && y < thumb.top + thumb.height {
+            y - thumb.top
+        } else {
+            // Center the thumb on the mouse position
+            let grab_offset = thumb.height / 2.0;
+            self.drag(term, ScrollDrag { grab_offset }, y);
+            grab_offset
+        };
+        Some(ScrollDrag { grab_offset })
+    }
+
+    /// Handle mouse motion while dragging the thumb.
+    /// The thumb is hidden once it reaches the bottom, so its
+    /// height is recomputed here rather than taken from `thumb`
+    /// in order that the drag can continue back up.
+    pub fn drag(&self, term: &mut Renderable, drag: ScrollDrag, y: f32) {
+        let (_, total) = term.get_scrollbar_info();
+        let (rows, _cols) = term.physical_dimensions();
+        let thumb = ScrollThumb {
+            top: y - drag.grab_offset,
+            height: thumb_height(total, rows, self.height, self.cell_height),
+        };
+        term.set_viewport_offset(offset_for_thumb_top(thumb, total, rows, self.height));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn thumb() {
+        // Hidden at the bottom of the scrollback
+        assert_eq!(compute_thumb(0, 200, 50, 1000.0, 10.0), None);
+
+        // At the top of 200 rows with 50 visible
+        assert_eq!(
+            compute_thumb(150, 200, 50, 1000.0, 10.0),
+            Some(ScrollThumb {
+                top: 0.0,
+                height: 250.0
+            })
+        );
+        // One screen up from the bottom
+        assert_eq!(
+            compute_thumb(50, 200, 50, 1000.0, 10.0),
+            Some(ScrollThumb {
+                top: 500.0,
+                height: 250.0
+            })
+        );
+        // The thumb is inflated to the minimum height
+        assert_eq!(
+            compute_thumb(9950, 10000, 50, 1000.0, 100.0),
+            Some(ScrollThumb {
+                top: 0.0,
+                height: 100.0
+            })
+        );
+    }
+
+    #[test]
+    fn reserves_column() {
+        assert_eq!(terminal_cols(false, 800, 10), 80);
+        assert_eq!(terminal_cols(true, 800, 10), 79);
+        assert_eq!(terminal_cols(true, 799, 10), 79);
+        assert_eq!(terminal_cols(true, 5, 10), 1);
+    }
+
+    #[test]
+    fn thumb_top_to_offset() {
+        let thumb = |top| ScrollThumb { top, height: 250.0 };
+        assert_eq!(offset_for_thumb_top(thumb(0.0), 200, 50, 1000.0), 150);
+        assert_eq!(offset_for_thumb_top(thumb(500.0), 200, 50, 1000.0), 50);
+        // Dragging to or beyond the bottom reaches the bottom
+        assert_eq!(offset_for_thumb_top(thumb(750.0), 200, 50, 1000.0), 0);
+        assert_eq!(offset_for_thumb_top(thumb(900.0), 200, 50, 1000.0), 0);
+        assert_eq!(offset_for_thumb_top(thumb(-20.0), 200, 50, 1000.0), 150);
+
+        // Round trips through compute_thumb
+        for offset in 1..=150 {
+            let thumb = compute_thumb(offset, 200, 50, 1000.0, 10.0).unwrap();
+            assert_eq!(offset_for_thumb_top(thumb, 200, 50, 1000.0), offset);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! Explains why the program of a tab couldn't be spawned the first
+//! time that the tab is shown, and lets the user choose between
+//! carrying on with the `fallback_prog` that was started in its place
+//! and closing the tab.
+use super::overlay::{OverlayAction, OverlayDelegate};
+use crate::mux::tab::{FailedSpawn, TabId};
+use crate::mux::Mux;
+use failure::Error;
+use term::{KeyCode, KeyModifiers};
+
+pub struct SpawnFailureOverlay {
+    tab_id: TabId,
+    failure: FailedSpawn,
+}
+
+impl SpawnFailureOverlay {
+    pub fn new(tab_id: TabId, failure: FailedSpawn) -> Self {
+        Self { tab_id, failure }
+    }
+}
+
+impl OverlayDelegate for SpawnFailureOverlay {
+    fn get_title(&self) -> String {
+        "Unable to spawn".into()
+    }
+
+    fn render(&self, rows: usize, _cols: usize) -> String {
+        let fallback = self.failure.fallback.join(" ");
+        format!(
+            "The program of this tab couldn't be started:\r\n\r\n\
+             \x20 {}\r\n\r\n\
+             {} has been started in its place, so that the problem can\r\n\
+             be investigated, for example by checking `default_prog`.\r\n\
+             \x1b[{};1H\x1b[7mPress Enter to use {}, or c to close the tab\x1b[0m",
+            self.failure.error.replace('\n', "\r\n  "),
+            fallback,
+            rows,
+            fallback
+        )
+    }
+
+    fn key_down(&mut self, key: KeyCode, _mods: KeyModifiers) -> Result<OverlayAction, Error> {
+        match key {
+            KeyCode::Enter | KeyCode::Escape => Ok(OverlayAction::Close),
+            KeyCode::Char('c') | KeyCode::Char('C') => {
+                if let Some(tab) = Mux::get().unwrap().get_tab(self.tab_id) {
+                    tab.kill()?;
+                }
+                Ok(OverlayAction::Close)
+            }
+            _ => Ok(OverlayAction::Continue),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Config;
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    fn overlay() -> SpawnFailureOverlay {
+        SpawnFailureOverlay::new(
+            1,
+            FailedSpawn {
+                error: "unable to spawn [\"nosuchshell\"]: not found".into(),
+                fallback: vec!["/bin/sh".into()],
+            },
+        )
+    }
+
+    #[test]
+    fn render() {
+        let text = overlay().render(10, 80);
+        assert!(text.contains("nosuchshell"));
+        assert!(text.contains("Press Enter to use /bin/sh"));
+    }
+
+    #[test]
+    fn choices() {
+        let mux = Rc::new(Mux::new(&Arc::new(Config::default())));
+        Mux::set_mux(&mux);
+        let mut overlay = overlay();
+        let none = KeyModifiers::default();
+        assert_eq!(
+            overlay.key_down(KeyCode::Char('x'), none).unwrap(),
+            OverlayAction::Continue
+        );
+        assert_eq!(
+            overlay.key_down(KeyCode::Enter, none).unwrap(),
+            OverlayAction::Close
+        );
+        // The tab may already have gone
+        assert_eq!(
+            overlay.key_down(KeyCode::Char('c'), none).unwrap(),
+            OverlayAction::Close
+        );
+    }
+}
@@ -0,0 +1,31 @@
+//! Requests to spawn tabs that arrive from outside of a window, such
+//! as `wezterm start --cwd DIR` being routed to the running instance,
+//! are queued here until the window that is to hold them next paints.
+//! Only the window knows its dimensions, so only it can size the new
+//! tab correctly.
+use crate::mux::window::WindowId;
+use crate::mux::Mux;
+use crate::pty::CommandBuilder;
+use std::cell::RefCell;
+
+thread_local! {
+    static PENDING: RefCell<Vec<(WindowId, CommandBuilder)>> = RefCell::new(vec![]);
+}
+
+/// Queue `cmd` to be spawned into a new tab in the window `window_id`.
+/// This must be called on the gui thread.
+pub fn queue_spawn(window_id: WindowId, cmd: CommandBuilder) {
+    PENDING.with(|pending| pending.borrow_mut().push((window_id, cmd)));
+    Mux::get().unwrap().notify_output();
+}
+
+/// Removes and returns the commands queued for the window `window_id`
+pub fn take_queued_spawns(window_id: WindowId) -> Vec<CommandBuilder> {
+    PENDING.with(|pending| {
+        let mut pending = pending.borrow_mut();
+        let (mine, others): (Vec<_>, Vec<_>) =
+            pending.drain(..).partition(|(id, _)| *id == window_id);
+        *pending = others;
+        mine.into_iter().map(|(_, cmd)| cmd).collect()
+    })
+}
@@ -1,16 +1,33 @@
 use crate::config::Config;
 use crate::font::FontConfiguration;
-use crate::frontend::guicommon::localtab::LocalTab;
+use crate::frontend::dispatch::{self, FrontEndWindow};
+use crate::frontend::guicommon::download::DownloadOverlay;
+use crate::frontend::guicommon::framepacer::earliest;
+use crate::frontend::guicommon::host::paste_progress;
+use crate::frontend::guicommon::idle;
+use crate::frontend::guicommon::notify::{take_notifications, NotificationOverlay};
+use crate::frontend::guicommon::overlay::{take_pending_assignments, OverlayDelegate, OverlayTab};
+use crate::frontend::guicommon::scrollbar::terminal_cols;
+use crate::frontend::guicommon::spawnfailure::SpawnFailureOverlay;
+use crate::frontend::guicommon::spawnqueue::take_queued_spawns;
+use crate::keyassignment::KeyAssignment;
+use crate::mux::bell;
+use crate::mux::latency;
+use crate::mux::renderfilter;
 use crate::mux::tab::{Tab, TabId};
+use crate::mux::transfer;
 use crate::mux::window::WindowId;
+use crate::mux::zmodem;
 use crate::mux::Mux;
 use crate::opengl::render::Renderer;
-use crate::opengl::textureatlas::OutOfTextureSpace;
-use crate::pty::{PtySize, PtySystemSelection};
+use crate::opengl::textureatlas::{max_texture_size, OutOfTextureSpace};
+use crate::pty::{CommandBuilder, PtySize};
 use failure::Error;
 use glium;
+use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::Arc;
+use std::time::Duration;
 
 /// Reports the currently configured physical size of the display
 /// surface (physical pixels, not adjusted for dpi) and the current
@@ -40,6 +57,15 @@ pub trait TerminalWindow {
     fn advise_renderer_of_resize(&mut self, width: u16, height: u16) -> Result<(), Error>;
     fn tab_was_created(&mut self, tab: &Rc<Tab>) -> Result<(), Error>;
     fn deregister_tab(&mut self, tab_id: TabId) -> Result<(), Error>;
+    /// Arrange for a new window to be created to hold a tab that
+    /// has been detached from this window
+    fn spawn_window_for_tab(&mut self, tab_id: TabId) -> Result<(), Error>;
+    /// Arrange for the gui window that hosts the mux window `window_id`
+    /// to adopt a tab that has been moved into it
+    fn notify_tab_moved(&mut self, window_id: WindowId) -> Result<(), Error>;
+    /// Carry out `assignment` against the active tab, as though
+    /// its key had been pressed
+    fn perform_key_assignment(&mut self, assignment: &KeyAssignment) -> Result<(), Error>;
     fn config(&self) -> &Arc<Config>;
     fn fonts(&self) -> &Rc<FontConfiguration>;
     fn get_dimensions(&self) -> Dimensions;
@@ -48,34 +74,69 @@ pub trait TerminalWindow {
         Ok(())
     }
 
-    fn activate_tab(&mut self, tab_idx: usize) -> Result<(), Error> {
+    /// Called when the window gains or loses the keyboard focus
+    fn focus_changed(&mut self, focused: bool) -> Result<(), Error> {
         let mux = Mux::get().unwrap();
-        let mut window = mux
-            .get_window_mut(self.get_mux_window_id())
-            .ok_or_else(|| format_err!("no such window"))?;
+        mux.set_window_focused(self.get_mux_window_id(), focused);
+        match mux.get_active_tab_for_window(self.get_mux_window_id()) {
+            Some(tab) => tab.focus_changed(focused),
+            None => Ok(()),
+        }
+    }
 
-        let max = window.len();
-        if tab_idx < max {
-            window.set_active(tab_idx);
+    fn activate_tab(&mut self, tab_idx: usize) -> Result<(), Error> {
+        dispatch::activate_tab(&mut GuiWindow(self), tab_idx)
+    }
 
-            drop(window);
-            self.update_title();
+    fn activate_tab_relative(&mut self, delta: isize) -> Result<(), Error> {
+        dispatch::activate_tab_relative(&mut GuiWindow(self), delta)
+    }
+
+    /// Tear the tab off into a window of its own
+    fn move_tab_to_new_window(&mut self, tab_id: TabId) -> Result<(), Error> {
+        let mux = Mux::get().unwrap();
+        let num_tabs = match mux.get_window(self.get_mux_window_id()) {
+            Some(window) => window.len(),
+            None => return Ok(()),
+        };
+        if num_tabs < 2 {
+            // It already has a window of its own
+            return Ok(());
         }
-        Ok(())
+        mux.detach_tab(tab_id)?;
+        self.paint_if_needed()?;
+        self.spawn_window_for_tab(tab_id)
     }
 
-    fn activate_tab_relative(&mut self, delta: isize) -> Result<(), Error> {
+    /// Move the tab to the window `delta` windows away from this one,
+    /// in the order that the windows were created.  If this was the
+    /// only tab then this window will be closed.
+    fn move_tab_to_window_relative(&mut self, tab_id: TabId, delta: isize) -> Result<(), Error> {
         let mux = Mux::get().unwrap();
-        let window = mux
-            .get_window(self.get_mux_window_id())
-            .ok_or_else(|| format_err!("no such window"))?;
-
-        let max = window.len();
-        let active = window.get_active_idx() as isize;
-        let tab = active + delta;
-        let tab = if tab < 0 { max as isize + tab } else { tab };
-        drop(window);
-        self.activate_tab(tab as usize % max)
+        let window_ids = mux.window_ids();
+        let max = window_ids.len();
+        if max < 2 {
+            return Ok(());
+        }
+        let current = window_ids
+            .iter()
+            .position(|&id| id == self.get_mux_window_id())
+            .ok_or_else(|| format_err!("no such window"))? as isize;
+        let dest = (current + delta) % max as isize;
+        let dest = if dest < 0 { max as isize + dest } else { dest };
+        let dest = window_ids[dest as usize];
+
+        mux.move_tab(tab_id, dest)?;
+        self.paint_if_needed()?;
+        self.notify_tab_moved(dest)
+    }
+
+    /// Called when a tab has been moved into this window; ensures that
+    /// it is sized to match the window and shown
+    fn adopt_moved_tab(&mut self) -> Result<(), Error> {
+        let dims = self.get_dimensions();
+        self.resize_surfaces(dims.width, dims.height, true)?;
+        self.paint_if_needed()
     }
 
     fn update_title(&mut self) {
@@ -91,7 +152,15 @@ pub trait TerminalWindow {
         }
         let tab_no = window.get_active_idx();
 
-        let title = window.get_active().unwrap().get_title();
+        let tab = window.get_active().unwrap();
+        let title = match paste_progress(tab.tab_id()) {
+            Some(percent) => format!(
+                "[Pasting {}%, Escape to cancel] {}",
+                percent,
+                tab.get_title()
+            ),
+            None => tab.get_title(),
+        };
 
         drop(window);
 
@@ -103,12 +172,64 @@ pub trait TerminalWindow {
         }
     }
 
+    /// Returns true if blinking text in this window has changed
+    /// visibility since it was last painted, or if a synchronized
+    /// update in the active tab has taken too long to complete
+    fn needs_blink_repaint(&mut self) -> bool {
+        self.renderer().needs_blink_repaint()
+            || self.synchronized_output_remaining() == Some(Duration::from_secs(0))
+    }
+
+    /// Returns how long until blinking text in this window next
+    /// needs to be repainted, or until a synchronized update in the
+    /// active tab is abandoned
+    fn time_until_blink_change(&mut self) -> Option<Duration> {
+        earliest(
+            self.renderer().time_until_blink_change(),
+            self.synchronized_output_remaining(),
+        )
+    }
+
+    /// Schedule a repaint if handling an input event changed the
+    /// active tab without it producing any output
+    fn schedule_paint_if_dirty(&self) {
+        let mux = Mux::get().unwrap();
+        if let Some(tab) = mux.get_active_tab_for_window(self.get_mux_window_id()) {
+            mux.notify_if_dirty(&*tab.renderer());
+        }
+    }
+
+    /// Returns how long painting of the active tab may be deferred
+    /// while its application completes a synchronized update
+    fn synchronized_output_remaining(&mut self) -> Option<Duration> {
+        let mux = Mux::get().unwrap();
+        match mux.get_active_tab_for_window(self.get_mux_window_id()) {
+            Some(tab) => tab.renderer().synchronized_output_remaining(),
+            None => None,
+        }
+    }
+
     fn paint_if_needed(&mut self) -> Result<(), Error> {
+        self.show_pending_notifications()?;
+        self.show_pending_downloads()?;
+        self.show_spawn_failure()?;
+        self.spawn_queued_tabs()?;
         let mux = Mux::get().unwrap();
         let tab = match mux.get_active_tab_for_window(self.get_mux_window_id()) {
             Some(tab) => tab,
             None => return Ok(()),
         };
+        if self.renderer().needs_blink_repaint()
+            || bell::take_visual_bell_change(self.get_mux_window_id())
+        {
+            tab.renderer().make_all_lines_dirty();
+        }
+        if tab.renderer().is_output_synchronized() {
+            // The application hasn't finished updating the screen; it
+            // will be painted when it has
+            self.update_title();
+            return Ok(());
+        }
         if tab.renderer().has_dirty_lines() {
             self.paint()?;
         }
@@ -125,11 +246,21 @@ pub trait TerminalWindow {
             None => return Ok(()),
         };
 
+        if idle::is_blanked(tab.tab_id()) {
+            return self.paint_blanked(target, &tab);
+        }
+
+        renderfilter::observe(tab.tab_id(), &*tab.renderer());
+
         let res = {
             let renderer = self.renderer();
             renderer.paint(&mut target, &mut *tab.renderer())
         };
 
+        if bell::visual_bell_active(self.get_mux_window_id()) {
+            self.paint_visual_bell(&mut target);
+        }
+
         // Ensure that we finish() the target before we let the
         // error bubble up, otherwise we lose the context.
         target
@@ -142,6 +273,16 @@ pub trait TerminalWindow {
         match res {
             Err(err) => {
                 if let Some(&OutOfTextureSpace { size }) = err.downcast_ref::<OutOfTextureSpace>() {
+                    // Each retry asks for a larger atlas than the last,
+                    // so this bounds the recursion below
+                    let limit = max_texture_size();
+                    if size > limit {
+                        bail!(
+                            "out of texture space; {} exceeds the limit of {}",
+                            size,
+                            limit
+                        );
+                    }
                     eprintln!("out of texture space, allocating {}", size);
                     self.recreate_texture_atlas(size)?;
                     tab.renderer().make_all_lines_dirty();
@@ -150,54 +291,154 @@ pub trait TerminalWindow {
                 }
                 Err(err)
             }
-            Ok(_) => Ok(()),
+            Ok(_) => {
+                latency::painted(tab.tab_id());
+                Ok(())
+            }
         }
     }
 
     fn spawn_tab(&mut self) -> Result<TabId, Error> {
-        let config = self.config();
+        let cmd = self.config().build_prog(None)?;
+        self.spawn_tab_with_command(cmd)
+    }
 
-        let dims = self.get_dimensions();
+    /// Spawn the `idx`th entry of the `launch_menu` into a new tab
+    fn spawn_launch_menu_item(&mut self, idx: usize) -> Result<TabId, Error> {
+        dispatch::spawn_launch_menu_item(&mut GuiWindow(self), idx)
+    }
 
-        let rows = (dims.height as usize + 1) / dims.cell_height;
-        let cols = (dims.width as usize + 1) / dims.cell_width;
+    fn spawn_tab_with_command(&mut self, cmd: CommandBuilder) -> Result<TabId, Error> {
+        dispatch::spawn_tab(&mut GuiWindow(self), cmd)
+    }
 
-        let pty_sys = PtySystemSelection::default().get()?;
-        let (pty, slave) = pty_sys.openpty(PtySize {
-            rows: rows as u16,
-            cols: cols as u16,
-            pixel_width: dims.width,
-            pixel_height: dims.height,
-        })?;
-        let cmd = config.build_prog(None)?;
+    /// Paint the window without the content of `tab`, which has been
+    /// hidden until the user returns
+    fn paint_blanked(&mut self, mut target: glium::Frame, tab: &Rc<Tab>) -> Result<(), Error> {
+        use glium::Surface;
 
-        let process = slave.spawn_command(cmd)?;
-        eprintln!("spawned: {:?}", process);
+        let color = self.renderer().palette().background.to_tuple_rgba();
+        target.clear_color(color.0, color.1, color.2, color.3);
+        target
+            .finish()
+            .expect("target.finish failed and we don't know how to recover");
+        tab.renderer().clean_dirty_lines();
+        Ok(())
+    }
+
+    /// Flash the edges of the window to show the visual bell
+    fn paint_visual_bell(&mut self, target: &mut glium::Frame) {
+        use glium::Surface;
+
+        let (width, height) = target.get_dimensions();
+        let dims = self.get_dimensions();
+        // The border is half of a cell wide
+        let size = ((dims.cell_width / 2).max(1) as u32).min(width / 2);
+        let color = self.renderer().palette().foreground.to_tuple_rgba();
+        let edges = [
+            (0, 0, width, size),
+            (0, height.saturating_sub(size), width, size),
+            (0, 0, size, height),
+            (width.saturating_sub(size), 0, size, height),
+        ];
+        for &(left, bottom, width, height) in &edges {
+            let rect = glium::Rect {
+                left,
+                bottom,
+                width,
+                height,
+            };
+            target.clear(Some(&rect), Some(color), false, None, None);
+        }
+    }
 
+    /// Show an overlay in place of the active tab until the
+    /// delegate asks for it to be closed
+    fn show_overlay(&mut self, delegate: Box<OverlayDelegate>) -> Result<(), Error> {
         let mux = Mux::get().unwrap();
+        let window_id = self.get_mux_window_id();
+        let (rows, cols) = match mux.get_active_tab_for_window(window_id) {
+            Some(tab) => tab.renderer().physical_dimensions(),
+            None => return Ok(()),
+        };
 
-        let terminal = term::Terminal::new(
-            rows,
-            cols,
-            config.scrollback_lines.unwrap_or(3500),
-            config.hyperlink_rules.clone(),
-        );
+        let overlay: Rc<Tab> = Rc::new(OverlayTab::new(window_id, rows, cols, delegate));
+        mux.get_window_mut(window_id)
+            .ok_or_else(|| format_err!("no such window!?"))?
+            .set_overlay(&overlay);
+        self.update_title();
+        Ok(())
+    }
 
-        let tab: Rc<Tab> = Rc::new(LocalTab::new(terminal, process, pty));
-        let tab_id = tab.tab_id();
+    /// Show an overlay holding any notifications that have been
+    /// posted for this window since the last call
+    fn show_pending_notifications(&mut self) -> Result<(), Error> {
+        let mut messages = take_notifications(self.get_mux_window_id());
+        messages.extend(bell::take_notifications(self.get_mux_window_id()));
+        messages.extend(zmodem::take_notifications(self.get_mux_window_id()));
+        if messages.is_empty() {
+            return Ok(());
+        }
+        self.show_overlay(Box::new(NotificationOverlay::new(messages)))
+    }
 
-        let len = {
-            let mut window = mux
-                .get_window_mut(self.get_mux_window_id())
-                .ok_or_else(|| format_err!("no such window!?"))?;
-            window.push(&tab);
-            window.len()
+    /// Ask whether to save the files that tabs in this window have
+    /// offered to download.  This waits until no other overlay is
+    /// shown, so that the downloads aren't lost by replacing it.
+    fn show_pending_downloads(&mut self) -> Result<(), Error> {
+        let window_id = self.get_mux_window_id();
+        let mux = Mux::get().unwrap();
+        let has_overlay = mux
+            .get_window(window_id)
+            .map(|window| window.get_overlay().is_some())
+            .unwrap_or(true);
+        if has_overlay {
+            return Ok(());
+        }
+        let offers = transfer::take_offers(window_id);
+        if offers.is_empty() {
+            return Ok(());
+        }
+        let dir = PathBuf::from(self.config().download_dir.clone().unwrap_or_default());
+        self.show_overlay(Box::new(DownloadOverlay::new(window_id, dir, offers)))
+    }
+
+    /// Explain why the program of the active tab couldn't be spawned
+    /// the first time that the tab is shown, once no other overlay is
+    fn show_spawn_failure(&mut self) -> Result<(), Error> {
+        let mux = Mux::get().unwrap();
+        let tab = match mux.get_window(self.get_mux_window_id()) {
+            Some(ref window) if window.get_overlay().is_none() => match window.get_active() {
+                Some(tab) => Rc::clone(tab),
+                None => return Ok(()),
+            },
+            _ => return Ok(()),
         };
-        self.activate_tab(len - 1)?;
+        match tab.take_spawn_failure() {
+            Some(failure) => {
+                self.show_overlay(Box::new(SpawnFailureOverlay::new(tab.tab_id(), failure)))
+            }
+            None => Ok(()),
+        }
+    }
 
-        self.tab_was_created(&tab)?;
+    /// Spawn the tabs that were requested for this window from
+    /// outside of it since the last call
+    fn spawn_queued_tabs(&mut self) -> Result<(), Error> {
+        for cmd in take_queued_spawns(self.get_mux_window_id()) {
+            self.spawn_tab_with_command(cmd)?;
+        }
+        Ok(())
+    }
 
-        Ok(tab_id)
+    /// Carry out any key assignments that were chosen in an overlay
+    /// since the last call.  This is called after passing a key
+    /// press to the active tab.
+    fn perform_pending_assignments(&mut self) -> Result<(), Error> {
+        for assignment in take_pending_assignments(self.get_mux_window_id()) {
+            self.perform_key_assignment(&assignment)?;
+        }
+        Ok(())
     }
 
     fn resize_surfaces(&mut self, width: u16, height: u16, force: bool) -> Result<bool, Error> {
@@ -213,7 +454,7 @@ pub trait TerminalWindow {
             // the bottom, we can usually squeeze that extra row in there,
             // so optimistically pretend that we have that extra pixel!
             let rows = ((height as usize + 1) / dims.cell_height) as u16;
-            let cols = ((width as usize + 1) / dims.cell_width) as u16;
+            let cols = terminal_cols(self.config().enable_scroll_bar, width, dims.cell_width);
 
             let mux = Mux::get().unwrap();
             let window = mux
@@ -222,6 +463,9 @@ pub trait TerminalWindow {
             for tab in window.iter() {
                 tab.resize(rows, cols, width as u16, height as u16)?;
             }
+            if let Some(overlay) = window.get_overlay() {
+                overlay.resize(rows, cols, width as u16, height as u16)?;
+            }
 
             Ok(true)
         } else {
@@ -308,6 +552,7 @@ pub trait TerminalWindow {
             .collect();
         drop(window);
         for tab in dead_tabs {
+            mux.record_exit_status(&*tab);
             self.tab_did_terminate(tab.tab_id());
         }
         let empty = match mux.get_window(self.get_mux_window_id()) {
@@ -317,3 +562,38 @@ pub trait TerminalWindow {
         empty
     }
 }
+
+/// Presents a `TerminalWindow` to the key assignment dispatcher
+pub struct GuiWindow<'a, W: TerminalWindow + ?Sized>(pub &'a mut W);
+
+impl<'a, W: TerminalWindow + ?Sized> FrontEndWindow for GuiWindow<'a, W> {
+    fn mux_window_id(&self) -> WindowId {
+        self.0.get_mux_window_id()
+    }
+
+    fn config(&self) -> Arc<Config> {
+        Arc::clone(self.0.config())
+    }
+
+    fn tab_size(&self) -> PtySize {
+        let dims = self.0.get_dimensions();
+        PtySize {
+            rows: ((dims.height as usize + 1) / dims.cell_height) as u16,
+            cols: terminal_cols(
+                self.0.config().enable_scroll_bar,
+                dims.width,
+                dims.cell_width,
+            ),
+            pixel_width: dims.width,
+            pixel_height: dims.height,
+        }
+    }
+
+    fn tab_was_spawned(&mut self, tab: &Rc<Tab>) -> Result<(), Error> {
+        self.0.tab_was_created(tab)
+    }
+
+    fn active_tab_changed(&mut self) {
+        self.0.update_title();
+    }
+}
@@ -1,88 +1,148 @@
 use crate::config::Config;
-use crate::font::FontConfiguration;
 use crate::mux::tab::Tab;
 use crate::mux::Mux;
+use crate::server::listener::spawn_listener_at;
 use failure::Error;
 use promise::Executor;
-use serde_derive::*;
+use serde::{Deserialize, Deserializer};
 use std::cell::RefCell;
 use std::rc::Rc;
 use std::sync::Arc;
 
+pub mod dispatch;
 pub mod glium;
 pub mod guicommon;
 pub mod muxserver;
+pub mod tui;
 #[cfg(all(unix, not(feature = "force-glutin"), not(target_os = "macos")))]
 pub mod xwindows;
 
-#[derive(Debug, Deserialize, Clone, Copy)]
-pub enum FrontEndSelection {
-    Glutin,
-    X11,
-    MuxServer,
-    Null,
+/// Constructs a frontend that will manage the windows of the mux
+pub type FrontEndFactory = fn(&Rc<Mux>) -> Result<Rc<FrontEnd>, Error>;
+
+/// Describes a frontend that can be chosen with the `front_end`
+/// configuration option or the `--front-end` command line option
+#[derive(Clone, Copy)]
+pub struct FrontEndRegistration {
+    /// The name by which the frontend is selected; names are matched
+    /// without regard to case
+    pub name: &'static str,
+    pub factory: FrontEndFactory,
+    /// If true, the frontend shows windows of its own on the local
+    /// display, and other invocations of wezterm are directed to it
+    /// via the `single_instance_socket_path`
+    pub accepts_other_instances: bool,
 }
 
+fn builtin_front_ends() -> Vec<FrontEndRegistration> {
+    let mut front_ends = vec![FrontEndRegistration {
+        name: "Glutin",
+        factory: glium::glutinloop::GlutinFrontEnd::try_new,
+        accepts_other_instances: true,
+    }];
+    #[cfg(all(unix, not(feature = "force-glutin"), not(target_os = "macos")))]
+    front_ends.push(FrontEndRegistration {
+        name: "X11",
+        factory: xwindows::x11loop::X11FrontEnd::try_new,
+        accepts_other_instances: true,
+    });
+    front_ends.extend(vec![
+        FrontEndRegistration {
+            name: "MuxServer",
+            factory: muxserver::MuxServerFrontEnd::try_new,
+            accepts_other_instances: false,
+        },
+        FrontEndRegistration {
+            name: "Null",
+            factory: muxserver::MuxServerFrontEnd::new_null,
+            accepts_other_instances: false,
+        },
+        FrontEndRegistration {
+            name: "Tui",
+            factory: tui::TuiFrontEnd::try_new,
+            accepts_other_instances: false,
+        },
+    ]);
+    front_ends
+}
+
+thread_local! {
+    static REGISTRY: RefCell<Vec<FrontEndRegistration>> = RefCell::new(builtin_front_ends());
+}
+
+/// Make a frontend available for selection by name.  A registration
+/// with the same name as an existing one replaces it.  This must be
+/// called before the command line is parsed for the frontend to be
+/// accepted by `--front-end`.
+#[allow(dead_code)]
+pub fn register_front_end(registration: FrontEndRegistration) {
+    REGISTRY.with(|registry| {
+        let mut registry = registry.borrow_mut();
+        registry.retain(|r| !r.name.eq_ignore_ascii_case(registration.name));
+        registry.push(registration);
+    });
+}
+
+fn lookup_front_end(name: &str) -> Option<FrontEndRegistration> {
+    REGISTRY.with(|registry| {
+        registry
+            .borrow()
+            .iter()
+            .find(|r| r.name.eq_ignore_ascii_case(name))
+            .cloned()
+    })
+}
+
+/// Identifies one of the registered frontends by its name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrontEndSelection(&'static str);
+
 impl Default for FrontEndSelection {
     fn default() -> Self {
         if cfg!(feature = "force-glutin") {
-            FrontEndSelection::Glutin
+            FrontEndSelection("Glutin")
         } else if cfg!(all(unix, not(target_os = "macos"))) {
-            FrontEndSelection::X11
+            FrontEndSelection("X11")
         } else {
-            FrontEndSelection::Glutin
+            FrontEndSelection("Glutin")
         }
     }
 }
 
-thread_local! {
-    static EXECUTOR: RefCell<Option<Box<Executor>>> = RefCell::new(None);
-}
-
-pub fn gui_executor() -> Option<Box<Executor>> {
-    let mut res = None;
-    EXECUTOR.with(|exec| {
-        if let Some(exec) = &*exec.borrow() {
-            res = Some(exec.clone_executor());
-        }
-    });
-    res
-}
-
 impl FrontEndSelection {
     pub fn try_new(self, mux: &Rc<Mux>) -> Result<Rc<FrontEnd>, Error> {
-        let front_end = match self {
-            FrontEndSelection::Glutin => glium::glutinloop::GlutinFrontEnd::try_new(mux),
-            #[cfg(all(unix, not(target_os = "macos")))]
-            FrontEndSelection::X11 => xwindows::x11loop::X11FrontEnd::try_new(mux),
-            #[cfg(not(all(unix, not(target_os = "macos"))))]
-            FrontEndSelection::X11 => bail!("X11 not compiled in"),
-            FrontEndSelection::MuxServer => muxserver::MuxServerFrontEnd::try_new(mux),
-            FrontEndSelection::Null => muxserver::MuxServerFrontEnd::new_null(mux),
-        }?;
-
-        EXECUTOR.with(|exec| {
-            *exec.borrow_mut() = Some(front_end.gui_executor());
-        });
+        let registration = lookup_front_end(self.0)
+            .ok_or_else(|| format_err!("{} frontend is not compiled in", self.0))?;
+        let front_end = (registration.factory)(mux)?;
+
+        promise::task::set_gui_executor(front_end.gui_executor());
+        spawn_signal_handler()?;
+
+        if registration.accepts_other_instances {
+            // Failing to listen only means that other invocations
+            // will start a new instance, so it isn't fatal
+            if let Some(path) = mux.config().single_instance_socket_path.as_ref() {
+                if let Err(err) = spawn_listener_at(path, mux, front_end.gui_executor()) {
+                    eprintln!("not listening for other instances: {}", err);
+                }
+            }
+        }
 
         Ok(front_end)
     }
 
-    // TODO: find or build a proc macro for this
+    /// Returns the names of the registered frontends
     pub fn variants() -> Vec<&'static str> {
-        vec!["Glutin", "X11", "MuxServer", "Null"]
+        REGISTRY.with(|registry| registry.borrow().iter().map(|r| r.name).collect())
     }
 }
 
 impl std::str::FromStr for FrontEndSelection {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_lowercase().as_ref() {
-            "glutin" => Ok(FrontEndSelection::Glutin),
-            "x11" => Ok(FrontEndSelection::X11),
-            "muxserver" => Ok(FrontEndSelection::MuxServer),
-            "null" => Ok(FrontEndSelection::Null),
-            _ => Err(format_err!(
+        match lookup_front_end(s) {
+            Some(registration) => Ok(FrontEndSelection(registration.name)),
+            None => Err(format_err!(
                 "{} is not a valid FrontEndSelection variant, possible values are {:?}",
                 s,
                 FrontEndSelection::variants()
@@ -91,17 +151,54 @@ impl std::str::FromStr for FrontEndSelection {
     }
 }
 
+impl<'de> Deserialize<'de> for FrontEndSelection {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Arrange for the mux to be shut down in an orderly fashion when
+/// we are asked to terminate, rather than dying without running the
+/// shutdown hooks
+#[cfg(unix)]
+fn spawn_signal_handler() -> Result<(), Error> {
+    use promise::task::spawn_into_gui;
+    use signal_hook::iterator::Signals;
+
+    let signals = Signals::new(&[libc::SIGTERM, libc::SIGINT, libc::SIGHUP])?;
+    std::thread::spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            eprintln!("shutting down due to signal {}", signal);
+            spawn_into_gui(move || -> Result<(), Error> {
+                Mux::get().unwrap().shutdown();
+                std::process::exit(128 + signal);
+            });
+        }
+    });
+    Ok(())
+}
+
+/// There is no equivalent of SIGTERM for a gui process on Windows;
+/// the session ending is delivered as WM_ENDSESSION to the window
+/// procedure, which winit doesn't expose to us.
+#[cfg(not(unix))]
+fn spawn_signal_handler() -> Result<(), Error> {
+    Ok(())
+}
+
 pub trait FrontEnd {
     /// Run the event loop.  Does not return until there is either a fatal
     /// error, or until there are no more windows left to manage.
     fn run_forever(&self) -> Result<(), Error>;
 
-    fn spawn_new_window(
-        &self,
-        config: &Arc<Config>,
-        fontconfig: &Rc<FontConfiguration>,
-        tab: &Rc<Tab>,
-    ) -> Result<(), Error>;
+    /// Create a new window to show `tab`.  Each window has its own
+    /// font configuration, so that its font size can be changed
+    /// independently of the other windows.
+    fn spawn_new_window(&self, config: &Arc<Config>, tab: &Rc<Tab>) -> Result<(), Error>;
 
     fn gui_executor(&self) -> Box<Executor>;
 }
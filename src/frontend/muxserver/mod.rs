@@ -1,6 +1,5 @@
 //! Implements the multiplexer server frontend
 use crate::config::Config;
-use crate::font::FontConfiguration;
 use crate::frontend::FrontEnd;
 use crate::mux::tab::Tab;
 use crate::mux::Mux;
@@ -12,11 +11,19 @@ use std::rc::Rc;
 use std::sync::mpsc::{self, Receiver, SyncSender};
 use std::sync::Arc;
 
+/// Runs functions on the thread that is blocked in the `run_forever`
+/// method of a frontend that drains the paired `Receiver`
 #[derive(Clone)]
-struct MuxExecutor {
+pub struct MuxExecutor {
     tx: SyncSender<SpawnFunc>,
 }
 
+impl MuxExecutor {
+    pub fn new(tx: SyncSender<SpawnFunc>) -> Self {
+        Self { tx }
+    }
+}
+
 impl Executor for MuxExecutor {
     fn execute(&self, f: SpawnFunc) {
         self.tx.send(f).expect("MuxExecutor execute failed");
@@ -38,7 +45,7 @@ impl MuxServerFrontEnd {
         let (tx, rx) = mpsc::sync_channel(4);
 
         if start_listener {
-            spawn_listener(mux.config(), Box::new(MuxExecutor { tx: tx.clone() }))?;
+            spawn_listener(mux, Box::new(MuxExecutor { tx: tx.clone() }))?;
         }
         Ok(Rc::new(Self { tx, rx }))
     }
@@ -73,12 +80,7 @@ impl FrontEnd for MuxServerFrontEnd {
         }
     }
 
-    fn spawn_new_window(
-        &self,
-        _config: &Arc<Config>,
-        _fontconfig: &Rc<FontConfiguration>,
-        _tab: &Rc<Tab>,
-    ) -> Result<(), Error> {
+    fn spawn_new_window(&self, _config: &Arc<Config>, _tab: &Rc<Tab>) -> Result<(), Error> {
         // The tab was already added to the mux, so we are a NOP
         Ok(())
     }
@@ -0,0 +1,621 @@
+//! Implements a frontend that shows the mux in the terminal that
+//! wezterm was started from, rather than in a window of its own, so
+//! that the multiplexer can be used over a plain ssh session.
+//!
+//! The active tab of the first window is rendered into the hosting
+//! terminal via termwiz, with a tab bar on the bottom row, and
+//! keyboard input is routed to that tab.  In the style of tmux,
+//! `CTRL-B` is a prefix key that is followed by a command:
+//!
+//! * `c` spawns a new tab
+//! * `n` and `p` activate the next and previous tabs
+//! * `0` through `9` activate the tab with that index
+//! * `CTRL-B` sends `CTRL-B` to the active tab
+use crate::config::Config;
+use crate::frontend::dispatch::{self, FrontEndWindow};
+use crate::frontend::muxserver::MuxExecutor;
+use crate::frontend::FrontEnd;
+use crate::keyassignment::KeyAssignment;
+use crate::mux::tab::Tab;
+use crate::mux::window::WindowId;
+use crate::mux::Mux;
+use crate::pty::PtySize;
+use failure::Error;
+use promise::Executor;
+use promise::SpawnFunc;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender};
+use std::sync::Arc;
+use std::time::Duration;
+use termwiz::caps::Capabilities;
+use termwiz::cell::CellAttributes;
+use termwiz::input::{InputEvent, KeyCode, KeyEvent, Modifiers};
+use termwiz::surface::{Change, Position};
+use termwiz::terminal::buffered::BufferedTerminal;
+use termwiz::terminal::{Blocking, SystemTerminal, Terminal};
+use unicode_width::UnicodeWidthChar;
+
+/// Where there is no way to be woken when the hosting terminal has
+/// input, how long to wait for work from other threads before
+/// checking for it, in milliseconds
+const POLL_INTERVAL_MS: u64 = 10;
+
+/// The key that introduces a frontend command
+const PREFIX_KEY: KeyCode = KeyCode::Char('B');
+
+pub struct TuiFrontEnd {
+    tx: SyncSender<SpawnFunc>,
+    rx: Receiver<SpawnFunc>,
+    terminal: RefCell<BufferedTerminal<SystemTerminal>>,
+    /// The mux window that is shown in the terminal
+    window_id: RefCell<Option<WindowId>>,
+    /// True if the prefix key was pressed and we are waiting
+    /// for the command that follows it
+    prefix_pending: Cell<bool>,
+    /// The text of the tab bar as it was last painted
+    tab_bar: RefCell<String>,
+    /// Allows the input watcher to wait for more input, if there is
+    /// one; see `spawn_input_watcher`
+    input_watcher: Option<SyncSender<()>>,
+}
+
+/// What to do with the key that follows the prefix key
+#[derive(Debug, PartialEq, Eq)]
+enum PrefixCommand {
+    /// Send the prefix key itself to the tab
+    SendPrefix,
+    Perform(KeyAssignment),
+}
+
+impl PrefixCommand {
+    fn for_key(event: &KeyEvent) -> Option<Self> {
+        let assignment = match (event.key, event.modifiers) {
+            (PREFIX_KEY, Modifiers::CTRL) => return Some(PrefixCommand::SendPrefix),
+            (KeyCode::Char('c'), Modifiers::NONE) => KeyAssignment::SpawnTab,
+            (KeyCode::Char('n'), Modifiers::NONE) => KeyAssignment::ActivateTabRelative(1),
+            (KeyCode::Char('p'), Modifiers::NONE) => KeyAssignment::ActivateTabRelative(-1),
+            (KeyCode::Char(c), Modifiers::NONE) if c.is_ascii_digit() => {
+                KeyAssignment::ActivateTab(c as usize - '0' as usize)
+            }
+            _ => return None,
+        };
+        Some(PrefixCommand::Perform(assignment))
+    }
+}
+
+impl TuiFrontEnd {
+    pub fn try_new(_mux: &Rc<Mux>) -> Result<Rc<FrontEnd>, Error> {
+        let (tx, rx) = mpsc::sync_channel(4);
+
+        let caps = Capabilities::new_from_env()?;
+        let mut terminal = SystemTerminal::new(caps)?;
+        terminal.set_raw_mode()?;
+        let mut terminal = BufferedTerminal::new(terminal)?;
+        terminal.add_change(Change::ClearScreen(Default::default()));
+
+        redirect_stderr();
+        let input_watcher = spawn_input_watcher(tx.clone())?;
+
+        Ok(Rc::new(Self {
+            tx,
+            rx,
+            terminal: RefCell::new(terminal),
+            window_id: RefCell::new(None),
+            prefix_pending: Cell::new(false),
+            tab_bar: RefCell::new(String::new()),
+            input_watcher,
+        }))
+    }
+
+    /// Returns the active tab of the window that we are showing,
+    /// first removing any tabs whose process has terminated.
+    /// Once that window is empty, we move on to show the next one.
+    fn active_tab(&self) -> Option<Rc<Tab>> {
+        if let Some(tab) = self.active_tab_for_window() {
+            return Some(tab);
+        }
+        let mux = Mux::get().unwrap();
+        for window_id in mux.window_ids() {
+            *self.window_id.borrow_mut() = Some(window_id);
+            if let Some(tab) = self.active_tab_for_window() {
+                self.resize_tab(&tab).ok();
+                return Some(tab);
+            }
+        }
+        None
+    }
+
+    fn active_tab_for_window(&self) -> Option<Rc<Tab>> {
+        let mux = Mux::get().unwrap();
+        let window_id = (*self.window_id.borrow())?;
+        let mut window = mux.get_window_mut(window_id)?;
+
+        let dead_tabs: Vec<Rc<Tab>> = window
+            .iter()
+            .filter(|tab| tab.is_dead())
+            .map(Rc::clone)
+            .collect();
+        for tab in dead_tabs {
+            mux.record_exit_status(&*tab);
+            window.remove_by_id(tab.tab_id());
+            if let Some(active) = window.get_active() {
+                active.renderer().make_all_lines_dirty();
+            }
+        }
+
+        window.get_active().map(Rc::clone)
+    }
+
+    /// Returns the size of the area in which the tab is shown;
+    /// the bottom row is reserved for the tab bar
+    fn tab_dimensions(&self) -> (usize, usize) {
+        let (cols, rows) = self.terminal.borrow().dimensions();
+        (cols, rows.saturating_sub(1).max(1))
+    }
+
+    /// Resize `tab` to match the size of the hosting terminal
+    fn resize_tab(&self, tab: &Rc<Tab>) -> Result<(), Error> {
+        let (cols, rows) = self.tab_dimensions();
+        tab.resize(rows as u16, cols as u16, 0, 0)?;
+        tab.renderer().make_all_lines_dirty();
+        Ok(())
+    }
+
+    /// Carries out the command that follows the prefix key.
+    /// Returns true if the key is to be sent to the tab.
+    fn perform_command(&self, event: &KeyEvent) -> Result<bool, Error> {
+        let assignment = match PrefixCommand::for_key(event) {
+            Some(PrefixCommand::SendPrefix) => return Ok(true),
+            Some(PrefixCommand::Perform(assignment)) => assignment,
+            None => return Ok(false),
+        };
+        if self.window_id.borrow().is_some() {
+            dispatch::perform_key_assignment(&mut TuiWindow(self), &assignment)?;
+        }
+        Ok(false)
+    }
+
+    /// Routes the input from the hosting terminal to `tab`, returning
+    /// true if there was any
+    fn process_input(&self, tab: &Rc<Tab>) -> Result<bool, Error> {
+        let mut resized = self.terminal.borrow_mut().check_for_resize()?;
+        let mut had_input = false;
+        loop {
+            let event = self
+                .terminal
+                .borrow_mut()
+                .terminal()
+                .poll_input(Blocking::DoNotWait)?;
+            had_input |= event.is_some();
+            match event {
+                Some(InputEvent::Key(event)) => {
+                    if self.prefix_pending.replace(false) {
+                        if !self.perform_command(&event)? {
+                            // The active tab may have changed; pick
+                            // up the new one on the next iteration
+                            break;
+                        }
+                    } else if event.key == PREFIX_KEY && event.modifiers == Modifiers::CTRL {
+                        self.prefix_pending.set(true);
+                        continue;
+                    }
+                    tab.key_down(event.key, event.modifiers)?;
+                }
+                Some(InputEvent::Paste(text)) => tab.send_paste(&text)?,
+                Some(InputEvent::Resized { .. }) => {
+                    resized |= self.terminal.borrow_mut().check_for_resize()?;
+                }
+                Some(_) => {}
+                None => break,
+            }
+        }
+
+        if resized {
+            self.terminal
+                .borrow_mut()
+                .add_change(Change::ClearScreen(Default::default()));
+            self.tab_bar.borrow_mut().clear();
+            let mux = Mux::get().unwrap();
+            let window_id = *self.window_id.borrow();
+            if let Some(window) = window_id.and_then(|id| mux.get_window(id)) {
+                for tab in window.iter() {
+                    self.resize_tab(tab)?;
+                }
+            }
+        }
+        Ok(had_input || resized)
+    }
+
+    /// Computes the text of the tab bar, which lists the tabs of
+    /// the window with the active tab marked by an asterisk
+    fn compute_tab_bar(&self) -> String {
+        let mux = Mux::get().unwrap();
+        let window_id = *self.window_id.borrow();
+        let window = match window_id.and_then(|id| mux.get_window(id)) {
+            Some(window) => window,
+            None => return String::new(),
+        };
+        let titles: Vec<String> = window.iter().map(|tab| tab.get_title()).collect();
+        format_tab_bar(&titles, window.get_active_idx(), self.prefix_pending.get())
+    }
+
+    /// Copy the dirty lines of the tab into the terminal surface
+    /// and send the changes to the hosting terminal
+    fn paint(&self, tab: &Rc<Tab>) -> Result<(), Error> {
+        let tab_bar = self.compute_tab_bar();
+        let tab_bar_changed = *self.tab_bar.borrow() != tab_bar;
+        let mut renderer = tab.renderer();
+        if !renderer.has_dirty_lines() && !tab_bar_changed {
+            return Ok(());
+        }
+
+        let (cols, rows) = self.tab_dimensions();
+        let mut terminal = self.terminal.borrow_mut();
+
+        for (line_idx, line, _selection) in renderer.get_dirty_lines() {
+            if line_idx >= rows {
+                continue;
+            }
+            terminal.add_change(Change::CursorPosition {
+                x: Position::Absolute(0),
+                y: Position::Absolute(line_idx),
+            });
+
+            let mut attrs = None;
+            let mut text = String::new();
+            let mut x = 0;
+            for (_, cell) in line.visible_cells() {
+                // Don't allow the text to wrap onto the next line
+                let width = cell.width().max(1);
+                if x + width > cols {
+                    break;
+                }
+                x += width;
+
+                if attrs.as_ref() != Some(cell.attrs()) {
+                    if !text.is_empty() {
+                        terminal.add_change(text.split_off(0));
+                    }
+                    attrs = Some(cell.attrs().clone());
+                    terminal.add_change(Change::AllAttributes(cell.attrs().clone()));
+                }
+                text.push_str(cell.str());
+            }
+            if !text.is_empty() {
+                terminal.add_change(text);
+            }
+            if x < cols {
+                terminal.add_change(Change::AllAttributes(Default::default()));
+                terminal.add_change(" ".repeat(cols - x));
+            }
+        }
+        renderer.clean_dirty_lines();
+
+        if tab_bar_changed && rows < terminal.dimensions().1 {
+            terminal.add_change(Change::CursorPosition {
+                x: Position::Absolute(0),
+                y: Position::Absolute(rows),
+            });
+            let mut attrs = CellAttributes::default();
+            attrs.set_reverse(true);
+            terminal.add_change(Change::AllAttributes(attrs));
+            terminal.add_change(pad_to_width(&tab_bar, cols));
+            terminal.add_change(Change::AllAttributes(Default::default()));
+            *self.tab_bar.borrow_mut() = tab_bar;
+        }
+
+        let cursor = renderer.get_cursor_position();
+        terminal.add_change(Change::CursorPosition {
+            x: Position::Absolute(cursor.x.min(cols.saturating_sub(1))),
+            y: Position::Absolute((cursor.y.max(0) as usize).min(rows.saturating_sub(1))),
+        });
+
+        terminal.flush()?;
+        terminal.terminal().flush()
+    }
+
+    fn run_loop(&self) -> Result<(), Error> {
+        let mux = Mux::get().unwrap();
+        loop {
+            let received = match self.input_watcher {
+                Some(_) => self.rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                None => self
+                    .rx
+                    .recv_timeout(Duration::from_millis(POLL_INTERVAL_MS)),
+            };
+            match received {
+                Ok(func) => func.call(),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(err) => bail!("while waiting for events: {:?}", err),
+            }
+            while let Ok(func) = self.rx.try_recv() {
+                func.call();
+            }
+
+            if mux.is_empty() {
+                return Ok(());
+            }
+
+            let tab = match self.active_tab() {
+                Some(tab) => tab,
+                None => continue,
+            };
+            // The screen is only examined for changes once it has been
+            // reported to have changed, or input may have changed it
+            let had_input = self.process_input(&tab)?;
+            if let Some(resume) = self.input_watcher.as_ref() {
+                // The pending input has been read, so the watcher
+                // can wait for more
+                resume.try_send(()).ok();
+            }
+            if mux.take_output_pending() || had_input {
+                if let Some(tab) = self.active_tab() {
+                    self.paint(&tab)?;
+                }
+            }
+        }
+    }
+}
+
+impl FrontEnd for TuiFrontEnd {
+    fn gui_executor(&self) -> Box<Executor> {
+        Box::new(MuxExecutor::new(self.tx.clone()))
+    }
+
+    fn run_forever(&self) -> Result<(), Error> {
+        let result = self.run_loop();
+
+        let mut terminal = self.terminal.borrow_mut();
+        terminal.add_change(Change::AllAttributes(Default::default()));
+        terminal.add_change(Change::ClearScreen(Default::default()));
+        terminal.flush()?;
+        terminal.terminal().flush()?;
+
+        result
+    }
+
+    fn spawn_new_window(&self, _config: &Arc<Config>, tab: &Rc<Tab>) -> Result<(), Error> {
+        let mux = Mux::get().unwrap();
+        let window_id = mux.add_new_window_with_tab(tab)?;
+        {
+            let mut shown = self.window_id.borrow_mut();
+            if shown.is_none() {
+                *shown = Some(window_id);
+            }
+        }
+        self.resize_tab(tab)
+    }
+}
+
+/// Presents the window shown by the `TuiFrontEnd` to the key
+/// assignment dispatcher
+struct TuiWindow<'a>(&'a TuiFrontEnd);
+
+impl<'a> FrontEndWindow for TuiWindow<'a> {
+    fn mux_window_id(&self) -> WindowId {
+        self.0.window_id.borrow().unwrap_or(0)
+    }
+
+    fn config(&self) -> Arc<Config> {
+        Arc::clone(Mux::get().unwrap().config())
+    }
+
+    fn tab_size(&self) -> PtySize {
+        let (cols, rows) = self.0.tab_dimensions();
+        PtySize {
+            rows: rows as u16,
+            cols: cols as u16,
+            pixel_width: 0,
+            pixel_height: 0,
+        }
+    }
+
+    fn tab_was_spawned(&mut self, tab: &Rc<Tab>) -> Result<(), Error> {
+        Mux::get().unwrap().add_tab(tab)
+    }
+
+    fn active_tab_changed(&mut self) {}
+}
+
+/// Computes the text of the tab bar from the titles of the tabs,
+/// marking the active tab with an asterisk
+fn format_tab_bar(titles: &[String], active: usize, prefix_pending: bool) -> String {
+    let mut bar = String::new();
+    for (idx, title) in titles.iter().enumerate() {
+        bar.push_str(&format!(
+            "[{}{}] {}  ",
+            idx,
+            if idx == active { "*" } else { "" },
+            title
+        ));
+    }
+    if prefix_pending {
+        bar.push_str("(prefix)");
+    }
+    bar
+}
+
+/// Truncates or pads `text` with spaces so that it occupies exactly
+/// `cols` columns of the terminal.  A double width character that
+/// would straddle the last column is replaced by a space.
+fn pad_to_width(text: &str, cols: usize) -> String {
+    let mut result = String::new();
+    let mut width = 0;
+    for c in text.chars() {
+        let c_width = c.width().unwrap_or(0);
+        if width + c_width > cols {
+            break;
+        }
+        result.push(c);
+        width += c_width;
+    }
+    result.extend(std::iter::repeat(' ').take(cols - width));
+    result
+}
+
+/// Wakes the run loop via `tx` when the hosting terminal has input or
+/// is resized, so that the loop can block until there is work to do.
+/// After each wakeup for input, the watcher waits until the loop has
+/// signalled via the returned sender that it has read the input.
+#[cfg(unix)]
+fn spawn_input_watcher(tx: SyncSender<SpawnFunc>) -> Result<Option<SyncSender<()>>, Error> {
+    use signal_hook::iterator::Signals;
+    use std::os::unix::io::AsRawFd;
+
+    let signals = Signals::new(&[libc::SIGWINCH])?;
+    let resize_tx = tx.clone();
+    std::thread::spawn(move || {
+        for _ in signals.forever() {
+            if resize_tx.send(SpawnFunc::from(|| {})).is_err() {
+                break;
+            }
+        }
+    });
+
+    let tty = std::fs::File::open("/dev/tty")?;
+    let (resume_tx, resume_rx) = mpsc::sync_channel(1);
+    std::thread::spawn(move || loop {
+        let mut pfd = libc::pollfd {
+            fd: tty.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        if unsafe { libc::poll(&mut pfd, 1, -1) } < 0 {
+            if std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            break;
+        }
+        if pfd.revents & (libc::POLLERR | libc::POLLHUP | libc::POLLNVAL) != 0 {
+            // The terminal has gone away; the loop will find out
+            // when it next tries to read from it
+            tx.send(SpawnFunc::from(|| {})).ok();
+            break;
+        }
+        if tx.send(SpawnFunc::from(|| {})).is_err() || resume_rx.recv().is_err() {
+            break;
+        }
+    });
+    Ok(Some(resume_tx))
+}
+
+#[cfg(not(unix))]
+fn spawn_input_watcher(_tx: SyncSender<SpawnFunc>) -> Result<Option<SyncSender<()>>, Error> {
+    Ok(None)
+}
+
+/// Anything written to stderr would corrupt the display, so send
+/// our diagnostics to a log file in the runtime directory instead
+#[cfg(unix)]
+fn redirect_stderr() {
+    use std::os::unix::io::AsRawFd;
+
+    let path = crate::config::runtime_dir().join("tui.log");
+    match open_log(&path) {
+        Ok(file) => unsafe {
+            libc::dup2(file.as_raw_fd(), libc::STDERR_FILENO);
+        },
+        Err(err) => eprintln!("unable to create {}: {}", path.display(), err),
+    }
+}
+
+/// Creates or truncates the log file at `path`, which is only
+/// accessible to the user.  A symlink in place of the file is not
+/// followed, so that our output can't be directed elsewhere.
+#[cfg(unix)]
+fn open_log(path: &std::path::Path) -> std::io::Result<std::fs::File> {
+    use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+
+    if let Some(dir) = path.parent() {
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)?;
+    }
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path)
+}
+
+#[cfg(not(unix))]
+fn redirect_stderr() {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key(key: KeyCode, modifiers: Modifiers) -> KeyEvent {
+        KeyEvent { key, modifiers }
+    }
+
+    #[test]
+    fn prefix_commands() {
+        let command = |k, mods| PrefixCommand::for_key(&key(k, mods));
+        assert_eq!(
+            command(PREFIX_KEY, Modifiers::CTRL),
+            Some(PrefixCommand::SendPrefix)
+        );
+        assert_eq!(
+            command(KeyCode::Char('c'), Modifiers::NONE),
+            Some(PrefixCommand::Perform(KeyAssignment::SpawnTab))
+        );
+        assert_eq!(
+            command(KeyCode::Char('p'), Modifiers::NONE),
+            Some(PrefixCommand::Perform(KeyAssignment::ActivateTabRelative(
+                -1
+            )))
+        );
+        assert_eq!(
+            command(KeyCode::Char('3'), Modifiers::NONE),
+            Some(PrefixCommand::Perform(KeyAssignment::ActivateTab(3)))
+        );
+        assert_eq!(command(KeyCode::Char('c'), Modifiers::CTRL), None);
+        assert_eq!(command(KeyCode::Char('x'), Modifiers::NONE), None);
+    }
+
+    #[test]
+    fn tab_bar() {
+        let titles = vec!["bash".to_string(), "vim".to_string()];
+        assert_eq!(format_tab_bar(&titles, 1, false), "[0] bash  [1*] vim  ");
+        assert_eq!(
+            format_tab_bar(&titles, 0, true),
+            "[0*] bash  [1] vim  (prefix)"
+        );
+        assert_eq!(format_tab_bar(&[], 0, false), "");
+    }
+
+    #[test]
+    fn tab_bar_width() {
+        assert_eq!(pad_to_width("[0*] bash", 6), "[0*] b");
+        assert_eq!(pad_to_width("[0*] bash", 12), "[0*] bash   ");
+        // Titles are measured in columns rather than characters
+        assert_eq!(pad_to_width("[0*] 日本語", 9), "[0*] 日本");
+        assert_eq!(pad_to_width("[0*] 日本語", 10), "[0*] 日本 ");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn log_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("wezterm-tui-{}", std::process::id()));
+        let path = dir.join("tui.log");
+        open_log(&path).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        // A symlink planted in place of the log is not followed
+        let target = dir.join("target");
+        std::fs::remove_file(&path).unwrap();
+        std::os::unix::fs::symlink(&target, &path).unwrap();
+        assert!(open_log(&path).is_err());
+        assert!(!target.exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -134,22 +134,39 @@ impl Keyboard {
             }
         };
 
+        let kc = Self::keysym_to_key(ksym, xsym)?;
+        Some((kc, self.get_key_modifiers()))
+    }
+
+    /// Decodes a key release.  Releases don't take part in composing,
+    /// so this reports the key that was released as it appears in the
+    /// keymap.
+    pub fn process_key_release(
+        &self,
+        xcb_ev: &xcb::KeyReleaseEvent,
+    ) -> Option<(KeyCode, KeyModifiers)> {
+        let xcode = xkb::Keycode::from(xcb_ev.detail());
+        let xsym = self.state.borrow().key_get_one_sym(xcode);
+        let kc = Self::keysym_to_key(xsym, xsym)?;
+        Some((kc, self.get_key_modifiers()))
+    }
+
+    /// `ksym` is the possibly composed keysym and `xsym` is the keysym
+    /// of the key itself
+    fn keysym_to_key(ksym: xkb::Keysym, xsym: xkb::Keysym) -> Option<KeyCode> {
         // could be from_u32_unchecked
         let ks_char = std::char::from_u32(xkb::keysym_to_utf32(ksym));
 
-        let kc = match ks_char {
-            Some(c) if (c as u32) >= 0x20 && (c as u32) != 0x7f => KeyCode::Char(c),
+        match ks_char {
+            Some(c) if (c as u32) >= 0x20 && (c as u32) != 0x7f => Some(KeyCode::Char(c)),
             _ => {
-                if let Some(key) = keysym_to_keycode(xsym) {
-                    key
-                } else {
+                let key = keysym_to_keycode(xsym);
+                if key.is_none() {
                     debug!("xkbc:Missing xcb keysym {} definition", xsym);
-                    return None;
                 }
+                key
             }
-        };
-
-        Some((kc, self.get_key_modifiers()))
+        }
     }
 
     fn mod_is_active(&self, modifier: &str) -> bool {
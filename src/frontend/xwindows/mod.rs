@@ -179,6 +179,13 @@ impl Connection {
     pub fn xkb_lookup_keysym(&self, event: &xcb::KeyPressEvent) -> Option<(KeyCode, KeyModifiers)> {
         self.keyboard.process_key_event(event)
     }
+
+    pub fn xkb_lookup_released_keysym(
+        &self,
+        event: &xcb::KeyReleaseEvent,
+    ) -> Option<(KeyCode, KeyModifiers)> {
+        self.keyboard.process_key_release(event)
+    }
 }
 
 impl Drop for Connection {
@@ -254,6 +261,7 @@ impl Window {
                         | xcb::EVENT_MASK_POINTER_MOTION
                         | xcb::EVENT_MASK_BUTTON_MOTION
                         | xcb::EVENT_MASK_KEY_RELEASE
+                        | xcb::EVENT_MASK_FOCUS_CHANGE
                         | xcb::EVENT_MASK_STRUCTURE_NOTIFY,
                 )],
             )
@@ -1,22 +1,25 @@
 use crate::config::Config;
-use crate::font::{FontConfiguration, FontSystemSelection};
+use crate::frontend::guicommon::framepacer::{earliest, FramePacer};
+use crate::frontend::guicommon::idle;
 use crate::frontend::guicommon::window::TerminalWindow;
 use crate::frontend::xwindows::xwin::X11TerminalWindow;
 use crate::frontend::xwindows::Connection;
 use crate::frontend::FrontEnd;
-use crate::mux::tab::Tab;
+use crate::mux::tab::{Tab, TabId};
+use crate::mux::window::WindowId as MuxWindowId;
 use crate::mux::Mux;
 use crate::spawn_tab;
 use failure::Error;
 use mio::{Events, Poll, PollOpt, Ready, Token};
 use mio_extras::channel::{channel, Receiver as GuiReceiver, Sender as GuiSender};
-use promise::{Executor, Future, SpawnFunc};
+use promise::task::spawn_into_gui;
+use promise::{Executor, SpawnFunc};
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
 use std::sync::mpsc::TryRecvError;
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 use xcb;
 
 #[cfg(all(unix, not(target_os = "macos")))]
@@ -46,7 +49,7 @@ pub struct GuiEventLoop {
     poll: Poll,
     pub conn: Rc<Connection>,
     windows: Rc<RefCell<Windows>>,
-    interval: Duration,
+    pacer: FramePacer,
     gui_rx: GuiReceiver<SpawnFunc>,
     gui_tx: GuiSender<SpawnFunc>,
     mux: Rc<Mux>,
@@ -76,15 +79,11 @@ impl FrontEnd for X11FrontEnd {
     }
 
     fn run_forever(&self) -> Result<(), Error> {
+        idle::start();
         self.event_loop.run()
     }
-    fn spawn_new_window(
-        &self,
-        config: &Arc<Config>,
-        fontconfig: &Rc<FontConfiguration>,
-        tab: &Rc<Tab>,
-    ) -> Result<(), Error> {
-        let window = X11TerminalWindow::new(&self.event_loop, fontconfig, config, tab)?;
+    fn spawn_new_window(&self, config: &Arc<Config>, tab: &Rc<Tab>) -> Result<(), Error> {
+        let window = X11TerminalWindow::new(&self.event_loop, config, tab)?;
 
         self.event_loop.add_window(window)
     }
@@ -111,7 +110,7 @@ impl GuiEventLoop {
             poll,
             gui_tx,
             gui_rx,
-            interval: Duration::from_millis(50),
+            pacer: FramePacer::new(mux.config().max_fps),
             windows: Rc::new(RefCell::new(Default::default())),
             mux: Rc::clone(mux),
         })
@@ -134,7 +133,7 @@ impl GuiEventLoop {
     }
 
     pub fn register_tab(&self, tab: &Rc<Tab>) -> Result<(), Error> {
-        self.mux.add_tab(self.gui_executor(), tab)
+        self.mux.add_tab(tab)
     }
 
     fn run(&self) -> Result<(), Error> {
@@ -144,20 +143,18 @@ impl GuiEventLoop {
         let tok_gui = Token(TOK_GUI_EXEC);
 
         self.conn.flush();
-        let mut last_interval = Instant::now();
 
         loop {
-            let now = Instant::now();
-            let diff = now - last_interval;
-            let period = if diff >= self.interval {
+            if self.needs_blink_repaint() {
+                self.pacer.request_frame();
+            }
+            if self.pacer.take_due_frame() {
                 self.do_paint();
-                last_interval = now;
-                self.interval
-            } else {
-                self.interval - diff
-            };
+            }
 
-            match self.poll.poll(&mut events, Some(period)) {
+            // Sleep until there is something to do; when idle this
+            // blocks until the next event arrives.
+            match self.poll.poll(&mut events, self.next_wakeup()) {
                 Ok(_) => {
                     for event in &events {
                         let t = event.token();
@@ -168,6 +165,9 @@ impl GuiEventLoop {
                         } else {
                         }
                     }
+                    if self.mux.take_output_pending() {
+                        self.pacer.request_frame();
+                    }
                     self.process_sigchld();
                     // Check the window count; if after processing the futures there
                     // are no windows left, then we are done.
@@ -191,50 +191,62 @@ impl GuiEventLoop {
         window_id: WindowId,
         func: F,
     ) -> Result<(), Error> {
-        Future::with_executor(
-            X11GuiExecutor {
-                tx: self.gui_tx.clone(),
-            },
-            move || {
-                let myself = Self::get().expect("to be called on gui thread");
-                let mut windows = myself.windows.borrow_mut();
-                if let Some(window) = windows.by_id.get_mut(&window_id) {
-                    func(window)
-                } else {
-                    bail!("no such window {:?}", window_id);
-                }
-            },
-        );
+        spawn_into_gui(move || {
+            let myself = Self::get().expect("to be called on gui thread");
+            let mut windows = myself.windows.borrow_mut();
+            if let Some(window) = windows.by_id.get_mut(&window_id) {
+                func(window)
+            } else {
+                bail!("no such window {:?}", window_id);
+            }
+        });
         Ok(())
     }
 
-    fn do_spawn_new_window(
-        &self,
-        config: &Arc<Config>,
-        fonts: &Rc<FontConfiguration>,
-    ) -> Result<(), Error> {
-        let tab = spawn_tab(&config, None)?;
-        self.mux.add_tab(self.gui_executor(), &tab)?;
+    fn do_spawn_new_window(&self, config: &Arc<Config>) -> Result<(), Error> {
+        let tab = spawn_tab(&config, config.build_prog(None)?, false)?;
+        self.mux.add_tab(&tab)?;
         let events = Self::get().expect("to be called on gui thread");
-        let window = X11TerminalWindow::new(&events, &fonts, &config, &tab)?;
+        let window = X11TerminalWindow::new(&events, &config, &tab)?;
         events.add_window(window)
     }
 
     pub fn schedule_spawn_new_window(&self, config: &Arc<Config>) {
         let config = Arc::clone(config);
-        Future::with_executor(
-            X11GuiExecutor {
-                tx: self.gui_tx.clone(),
-            },
-            move || {
-                let myself = Self::get().expect("to be called on gui thread");
-                let fonts = Rc::new(FontConfiguration::new(
-                    Arc::clone(&config),
-                    FontSystemSelection::get_default(),
-                ));
-                myself.do_spawn_new_window(&config, &fonts)
-            },
-        );
+        spawn_into_gui(move || {
+            let myself = Self::get().expect("to be called on gui thread");
+            myself.do_spawn_new_window(&config)
+        });
+    }
+
+    /// Create a new window to hold an existing tab that has been
+    /// detached from its previous window
+    pub fn schedule_window_for_tab(&self, config: &Arc<Config>, tab_id: TabId) {
+        let config = Arc::clone(config);
+        spawn_into_gui(move || {
+            let myself = Self::get().expect("to be called on gui thread");
+            let mux = Mux::get().unwrap();
+            let tab = mux
+                .get_tab(tab_id)
+                .ok_or_else(|| format_err!("no such tab {}", tab_id))?;
+            let window = X11TerminalWindow::new(&myself, &config, &tab)?;
+            myself.add_window(window)
+        });
+    }
+
+    /// Have the window that hosts the mux window `window_id` adopt
+    /// a tab that was moved into it from another window
+    pub fn schedule_tab_moved(&self, window_id: MuxWindowId) {
+        spawn_into_gui(move || {
+            let myself = Self::get().expect("to be called on gui thread");
+            let mut windows = myself.windows.borrow_mut();
+            for window in windows.by_id.values_mut() {
+                if window.get_mux_window_id() == window_id {
+                    window.adopt_moved_tab()?;
+                }
+            }
+            Ok(())
+        });
     }
 
     pub fn add_window(&self, window: X11TerminalWindow) -> Result<(), Error> {
@@ -271,7 +283,12 @@ impl GuiEventLoop {
     ) -> Result<(), Error> {
         let mut windows = self.windows.borrow_mut();
         if let Some(window) = windows.by_id.get_mut(&window_id) {
-            window.dispatch_event(event)?;
+            let res = window.dispatch_event(event);
+            // Mouse and key events may have changed the selection or
+            // scrolled the viewport without the tab producing output;
+            // `run` will pick this up and schedule a frame
+            window.schedule_paint_if_dirty();
+            res?;
         }
         Ok(())
     }
@@ -302,6 +319,10 @@ impl GuiEventLoop {
                 let msg: &xcb::ClientMessageEvent = unsafe { xcb::cast_event(event) };
                 Some(msg.window())
             }
+            xcb::FOCUS_IN | xcb::FOCUS_OUT => {
+                let focus: &xcb::FocusInEvent = unsafe { xcb::cast_event(event) };
+                Some(focus.event())
+            }
             _ => None,
         }
     }
@@ -346,8 +367,27 @@ impl GuiEventLoop {
         }
     }
 
+    /// Returns true if any window has blinking text that needs repainting
+    fn needs_blink_repaint(&self) -> bool {
+        self.windows
+            .borrow_mut()
+            .by_id
+            .values_mut()
+            .any(|window| window.needs_blink_repaint())
+    }
+
+    /// Returns how long the event loop may sleep before it needs to
+    /// paint, or None if it can wait for the next event
+    fn next_wakeup(&self) -> Option<Duration> {
+        let mut wakeup = self.pacer.time_until_frame();
+        for window in self.windows.borrow_mut().by_id.values_mut() {
+            wakeup = earliest(wakeup, window.time_until_blink_change());
+        }
+        wakeup
+    }
+
     /// Run through all of the windows and cause them to paint if they need it.
-    /// This happens ~50ms or so.
+    /// This is paced by `max_fps` when output arrives from a tab.
     fn do_paint(&self) {
         for window in &mut self.windows.borrow_mut().by_id.values_mut() {
             window.paint_if_needed().unwrap();
@@ -79,6 +79,18 @@ pub fn keysym_to_keycode(keysym: u32) -> Option<KeyCode> {
         KEY_F10 => KeyCode::Function(10),
         KEY_F11 => KeyCode::Function(11),
         KEY_F12 => KeyCode::Function(12),
+        KEY_F13 => KeyCode::Function(13),
+        KEY_F14 => KeyCode::Function(14),
+        KEY_F15 => KeyCode::Function(15),
+        KEY_F16 => KeyCode::Function(16),
+        KEY_F17 => KeyCode::Function(17),
+        KEY_F18 => KeyCode::Function(18),
+        KEY_F19 => KeyCode::Function(19),
+        KEY_F20 => KeyCode::Function(20),
+        KEY_F21 => KeyCode::Function(21),
+        KEY_F22 => KeyCode::Function(22),
+        KEY_F23 => KeyCode::Function(23),
+        KEY_F24 => KeyCode::Function(24),
 
         // numeric and function keypad keys
         KEY_KP_Enter => KeyCode::Char(0xdu8 as char),
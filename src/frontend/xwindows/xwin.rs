@@ -1,17 +1,23 @@
 use super::xkeysyms;
 use super::{Connection, Window};
 use crate::config::Config;
-use crate::font::FontConfiguration;
+use crate::font::{FontConfiguration, FontSystemSelection};
 use crate::frontend::guicommon::host::{HostHelper, HostImpl, TabHost};
+use crate::frontend::guicommon::idle;
 use crate::frontend::guicommon::window::{Dimensions, TerminalWindow};
 use crate::frontend::xwindows::x11loop::{GuiEventLoop, WindowId as X11WindowId};
+use crate::keyassignment::KeyAssignment;
+use crate::keyremap::RemappedKey;
+use crate::mux::latency;
 use crate::mux::tab::{Tab, TabId};
 use crate::mux::window::WindowId;
 use crate::mux::{Mux, SessionTerminated};
 use crate::opengl::render::Renderer;
 use failure::Error;
+use std::io::Write;
 use std::rc::Rc;
 use std::sync::Arc;
+use std::time::Instant;
 use term::{self, KeyCode, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
 use xcb;
 
@@ -78,6 +84,24 @@ impl TerminalWindow for X11TerminalWindow {
     fn deregister_tab(&mut self, _tab_id: TabId) -> Result<(), Error> {
         Ok(())
     }
+    fn spawn_window_for_tab(&mut self, tab_id: TabId) -> Result<(), Error> {
+        self.host
+            .event_loop
+            .schedule_window_for_tab(&self.host.config, tab_id);
+        Ok(())
+    }
+    fn notify_tab_moved(&mut self, window_id: WindowId) -> Result<(), Error> {
+        self.host.event_loop.schedule_tab_moved(window_id);
+        Ok(())
+    }
+
+    fn perform_key_assignment(&mut self, assignment: &KeyAssignment) -> Result<(), Error> {
+        let mux = Mux::get().unwrap();
+        match mux.get_active_tab_for_window(self.mux_window_id) {
+            Some(tab) => self.host.perform_key_assignment(&*tab, assignment),
+            None => Ok(()),
+        }
+    }
     fn get_dimensions(&self) -> Dimensions {
         Dimensions {
             width: self.width,
@@ -118,18 +142,20 @@ impl TerminalWindow for X11TerminalWindow {
 impl X11TerminalWindow {
     pub fn new(
         event_loop: &Rc<GuiEventLoop>,
-        fonts: &Rc<FontConfiguration>,
         config: &Arc<Config>,
         tab: &Rc<Tab>,
     ) -> Result<X11TerminalWindow, Error> {
-        let palette = config
-            .colors
-            .as_ref()
-            .map(|p| p.clone().into())
-            .unwrap_or_else(term::color::ColorPalette::default);
-
         let (physical_rows, physical_cols) = tab.renderer().physical_dimensions();
 
+        // Each window has its own font configuration so that changing
+        // the font size in one window doesn't affect the others
+        let fonts = Rc::new(FontConfiguration::new(
+            Arc::clone(config),
+            FontSystemSelection::get_default(),
+        ));
+        // The fonts are otherwise rendered at the configured dpi
+        // without any scaling
+        fonts.change_scaling(1.0, config.effective_dpi_scale(1.0));
         let metrics = fonts.default_font_metrics()?;
         let (cell_height, cell_width) = (
             metrics.cell_height.ceil() as usize,
@@ -148,10 +174,10 @@ impl X11TerminalWindow {
             window,
             event_loop: Rc::clone(event_loop),
             config: Arc::clone(config),
-            fonts: Rc::clone(fonts),
+            fonts: Rc::clone(&fonts),
         });
 
-        let renderer = Renderer::new(&host.window, width, height, fonts, palette)?;
+        let renderer = Renderer::new(&host.window, width, height, &fonts, config)?;
         let mux = Mux::get().unwrap();
         let mux_window_id = mux.add_new_window_with_tab(tab)?;
         host.window.show();
@@ -181,12 +207,21 @@ impl X11TerminalWindow {
         self.conn.xkb_lookup_keysym(event)
     }
 
-    fn mouse_event(&mut self, event: MouseEvent) -> Result<(), Error> {
+    /// `x` and `y` are the position of the mouse in physical pixels
+    fn mouse_event(&mut self, event: MouseEvent, x: i16, y: i16) -> Result<(), Error> {
+        idle::note_activity();
         let mux = Mux::get().unwrap();
         let tab = match mux.get_active_tab_for_window(self.get_mux_window_id()) {
             Some(tab) => tab,
             None => return Ok(()),
         };
+        let dims = self.get_dimensions();
+        if self
+            .host
+            .scrollbar_mouse_event(&*tab, &event, f32::from(x), f32::from(y), &dims)
+        {
+            return self.paint_if_needed();
+        }
         tab.mouse_event(event, &mut TabHost::new(&mut *tab.writer(), &mut self.host))?;
         Ok(())
     }
@@ -208,12 +243,23 @@ impl X11TerminalWindow {
             }
             xcb::KEY_PRESS => {
                 let key_press: &xcb::KeyPressEvent = unsafe { xcb::cast_event(event) };
+                let received = Instant::now();
+                idle::note_activity();
                 let mux = Mux::get().unwrap();
                 let tab = match mux.get_active_tab_for_window(self.get_mux_window_id()) {
                     Some(tab) => tab,
                     None => return Ok(()),
                 };
                 if let Some((code, mods)) = self.decode_key(key_press) {
+                    let (code, mods) = match self.host.remapper().key_down(code, mods) {
+                        RemappedKey::Key(code, mods) => (code, mods),
+                        RemappedKey::Text(text) => {
+                            tab.send_text(&text)?;
+                            return Ok(());
+                        }
+                        RemappedKey::Swallowed => return Ok(()),
+                    };
+
                     if mods == KeyModifiers::SUPER && code == KeyCode::Char('n') {
                         self.host
                             .event_loop
@@ -225,7 +271,22 @@ impl X11TerminalWindow {
                         return Ok(());
                     }
 
+                    latency::key_received(received);
                     tab.key_down(code, mods)?;
+                    self.perform_pending_assignments()?;
+                }
+            }
+            xcb::KEY_RELEASE => {
+                let key_release: &xcb::KeyReleaseEvent = unsafe { xcb::cast_event(event) };
+                let mux = Mux::get().unwrap();
+                let tab = match mux.get_active_tab_for_window(self.get_mux_window_id()) {
+                    Some(tab) => tab,
+                    None => return Ok(()),
+                };
+                if let Some((code, mods)) = self.conn.xkb_lookup_released_keysym(key_release) {
+                    if let Some((code, mods)) = self.host.remapper().key_up(code, mods) {
+                        tab.key_up(code, mods)?;
+                    }
                 }
             }
             xcb::MOTION_NOTIFY => {
@@ -236,9 +297,11 @@ impl X11TerminalWindow {
                     button: MouseButton::None,
                     x: (motion.event_x() as usize / self.cell_width) as usize,
                     y: (motion.event_y() as usize / self.cell_height) as i64,
+                    x_pixel: motion.event_x().max(0) as usize,
+                    y_pixel: motion.event_y().max(0) as usize,
                     modifiers: xkeysyms::modifiers_from_state(motion.state()),
                 };
-                self.mouse_event(event)?;
+                self.mouse_event(event, motion.event_x(), motion.event_y())?;
             }
             xcb::BUTTON_PRESS | xcb::BUTTON_RELEASE => {
                 let button_press: &xcb::ButtonPressEvent = unsafe { xcb::cast_event(event) };
@@ -251,6 +314,8 @@ impl X11TerminalWindow {
                     },
                     x: (button_press.event_x() as usize / self.cell_width) as usize,
                     y: (button_press.event_y() as usize / self.cell_height) as i64,
+                    x_pixel: button_press.event_x().max(0) as usize,
+                    y_pixel: button_press.event_y().max(0) as usize,
                     button: match button_press.detail() {
                         1 => MouseButton::Left,
                         2 => MouseButton::Middle,
@@ -265,7 +330,19 @@ impl X11TerminalWindow {
                     modifiers: xkeysyms::modifiers_from_state(button_press.state()),
                 };
 
-                self.mouse_event(event)?;
+                self.mouse_event(event, button_press.event_x(), button_press.event_y())?;
+            }
+            xcb::FOCUS_IN | xcb::FOCUS_OUT => {
+                let focus: &xcb::FocusInEvent = unsafe { xcb::cast_event(event) };
+                // Keyboard grabs, such as those taken by the window
+                // manager while switching windows, also produce focus
+                // events; only the final change is interesting
+                if focus.mode() as u32 == xcb::NOTIFY_MODE_NORMAL
+                    || focus.mode() as u32 == xcb::NOTIFY_MODE_WHILE_GRABBED
+                {
+                    self.host.remapper().reset();
+                    self.focus_changed(r == xcb::FOCUS_IN)?;
+                }
             }
             xcb::CLIENT_MESSAGE => {
                 let msg: &xcb::ClientMessageEvent = unsafe { xcb::cast_event(event) };
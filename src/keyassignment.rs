@@ -0,0 +1,295 @@
+//! Maps key presses to actions that are carried out by the gui
+//! rather than being passed through to the terminal.
+use crate::config::Config;
+use failure::Error;
+use serde::{Deserialize, Deserializer};
+use serde_derive::*;
+use std::collections::HashMap;
+use term::{KeyCode, KeyModifiers};
+
+/// An action that can be bound to a key
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub enum KeyAssignment {
+    SpawnTab,
+    /// Spawn the numbered entry of the `launch_menu` in a new tab
+    SpawnLaunchMenuItem(usize),
+    /// Spawn the described program in a new tab of the window
+    SpawnCommandInNewTab(SpawnCommand),
+    /// Spawn the described program in a new window
+    SpawnCommandInNewWindow(SpawnCommand),
+    ToggleFullScreen,
+    Copy,
+    Paste,
+    /// Paste the content of the named internal register, or the
+    /// system clipboard if the name is "Clipboard"
+    PasteFrom(String),
+    ActivateTabRelative(isize),
+    IncreaseFontSize,
+    DecreaseFontSize,
+    ResetFontSize,
+    ActivateTab(usize),
+    /// Show an overlay listing recently copied text.  This has no
+    /// default binding; add one via `keys` or pick it from the launcher.
+    ShowClipboardHistory,
+    /// Show diagnostic information about the active tab
+    ShowTabInspector,
+    /// Tear the active tab off into a new window of its own
+    MoveTabToNewWindow,
+    /// Move the active tab into another window; the argument is
+    /// relative to the current window, in the order that the windows
+    /// were opened
+    MoveTabToWindowRelative(isize),
+    /// Label the URLs, paths and other interesting text on the screen
+    /// so that they can be copied or opened by typing their label
+    QuickSelect,
+    /// Move the focus between the visible hyperlinks with the
+    /// keyboard and open the focused one with Enter
+    NavigateLinks,
+    /// Show a searchable list of tabs and actions to choose from
+    ShowLauncher,
+    /// Show or hide the text in the active tab that was concealed
+    /// with SGR 8.  Revealed text is included when copying.
+    ToggleRevealConcealed,
+    /// Highlight every occurrence of the selected text in the active
+    /// tab and its scrollback, or remove those highlights if they are
+    /// already shown.  Only selections within a single line are
+    /// searched for.
+    HighlightSelectionMatches,
+    Nop,
+}
+
+/// A program to be spawned by the `SpawnCommandInNewTab` and
+/// `SpawnCommandInNewWindow` assignments.  For example, to run htop
+/// in a new tab when pressing CTRL-SHIFT-T:
+///
+/// ```toml
+/// [[keys]]
+/// key = "T"
+/// mods = "CTRL|SHIFT"
+/// action = { SpawnCommandInNewTab = { args = ["htop"] } }
+/// ```
+#[derive(Debug, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct SpawnCommand {
+    /// The program to run and its arguments, in the same form as
+    /// `default_prog`.  The default program is run if omitted.
+    pub args: Option<Vec<String>>,
+    /// The directory in which to start the program.  If omitted, it
+    /// starts in the current directory of wezterm.
+    pub cwd: Option<String>,
+    /// Variables to set in the environment of the program, in
+    /// addition to those that it inherits from wezterm
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+}
+
+/// Binds a key and modifier combination to a `KeyAssignment`.
+/// For example, to show the clipboard history when pressing
+/// CTRL-SHIFT-H:
+///
+/// ```toml
+/// [[keys]]
+/// key = "H"
+/// mods = "CTRL|SHIFT"
+/// action = "ShowClipboardHistory"
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+pub struct Key {
+    #[serde(deserialize_with = "de_keycode")]
+    pub key: KeyCode,
+    #[serde(deserialize_with = "de_modifiers", default)]
+    pub mods: KeyModifiers,
+    pub action: KeyAssignment,
+}
+
+/// Parses a key name such as "a", "Enter" or "F5" into a `KeyCode`
+pub fn parse_keycode(s: &str) -> Result<KeyCode, Error> {
+    macro_rules! m {
+        ($($val:ident),* $(,)*) => {
+            match s {
+            $(
+                stringify!($val) => return Ok(KeyCode::$val),
+            )*
+                _ => {}
+            }
+        }
+    }
+
+    m!(
+        Hyper,
+        Super,
+        Meta,
+        Cancel,
+        Backspace,
+        Tab,
+        Clear,
+        Enter,
+        Shift,
+        LeftShift,
+        RightShift,
+        Control,
+        LeftControl,
+        RightControl,
+        Alt,
+        LeftAlt,
+        RightAlt,
+        LeftWindows,
+        RightWindows,
+        Escape,
+        Menu,
+        Pause,
+        CapsLock,
+        PageUp,
+        PageDown,
+        End,
+        Home,
+        LeftArrow,
+        RightArrow,
+        UpArrow,
+        DownArrow,
+        Select,
+        Print,
+        Execute,
+        PrintScreen,
+        Insert,
+        Delete,
+        Help,
+        Applications,
+        Sleep,
+        Numpad0,
+        Numpad1,
+        Numpad2,
+        Numpad3,
+        Numpad4,
+        Numpad5,
+        Numpad6,
+        Numpad7,
+        Numpad8,
+        Numpad9,
+        Multiply,
+        Add,
+        Separator,
+        Subtract,
+        Decimal,
+        Divide,
+        NumLock,
+        ScrollLock,
+    );
+
+    if s.len() > 1 && s.starts_with('F') {
+        if let Ok(n) = s[1..].parse::<u8>() {
+            if n >= 1 && n <= 24 {
+                return Ok(KeyCode::Function(n));
+            }
+        }
+    }
+
+    let mut chars = s.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Ok(KeyCode::Char(c));
+    }
+
+    bail!("invalid key name {}", s);
+}
+
+/// Parses a modifier specification such as "CTRL|SHIFT" into
+/// `KeyModifiers`
+pub fn parse_modifiers(s: &str) -> Result<KeyModifiers, Error> {
+    let mut mods = KeyModifiers::NONE;
+    for m in s.split('|') {
+        let m = m.trim();
+        mods |= match m {
+            "" | "NONE" => KeyModifiers::NONE,
+            "SHIFT" => KeyModifiers::SHIFT,
+            "ALT" | "OPT" | "META" => KeyModifiers::ALT,
+            "CTRL" => KeyModifiers::CTRL,
+            "SUPER" | "CMD" | "WIN" => KeyModifiers::SUPER,
+            _ => bail!("invalid modifier name {} in {}", m, s),
+        };
+    }
+    Ok(mods)
+}
+
+pub fn de_keycode<'de, D>(deserializer: D) -> Result<KeyCode, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    parse_keycode(&s).map_err(serde::de::Error::custom)
+}
+
+pub fn de_modifiers<'de, D>(deserializer: D) -> Result<KeyModifiers, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    parse_modifiers(&s).map_err(serde::de::Error::custom)
+}
+
+pub struct KeyMap(HashMap<(KeyCode, KeyModifiers), KeyAssignment>);
+
+impl KeyMap {
+    /// Build the key map from the default assignments, overlaid
+    /// with any `keys` from the configuration.
+    pub fn new(config: &Config) -> Self {
+        use self::KeyAssignment::*;
+        let mut map = HashMap::new();
+
+        macro_rules! k {
+            ($([$mod:expr, $code:expr, $action:expr]),* $(,)*) => {
+                $(
+                map.insert(($code, $mod), $action);
+                )*
+            };
+        };
+
+        let ctrl_shift = KeyModifiers::CTRL | KeyModifiers::SHIFT;
+        let super_shift = KeyModifiers::SUPER | KeyModifiers::SHIFT;
+
+        if cfg!(target_os = "macos") {
+            k!(
+                // Nominally copy, but that is implicit, so NOP
+                [KeyModifiers::SUPER, KeyCode::Char('c'), Copy],
+                [KeyModifiers::SUPER, KeyCode::Char('v'), Paste],
+            );
+        }
+
+        k!(
+            [KeyModifiers::SHIFT, KeyCode::Insert, Paste],
+            [KeyModifiers::SUPER, KeyCode::Char('t'), SpawnTab],
+            [KeyModifiers::ALT, KeyCode::Char('\r'), ToggleFullScreen],
+            [KeyModifiers::ALT, KeyCode::Char('\n'), ToggleFullScreen],
+            [KeyModifiers::ALT, KeyCode::Enter, ToggleFullScreen],
+            [super_shift, KeyCode::Char('['), ActivateTabRelative(-1)],
+            [super_shift, KeyCode::Char('{'), ActivateTabRelative(-1)],
+            [super_shift, KeyCode::Char(']'), ActivateTabRelative(1)],
+            [super_shift, KeyCode::Char('}'), ActivateTabRelative(1)],
+            [KeyModifiers::SUPER, KeyCode::Char('-'), DecreaseFontSize],
+            [KeyModifiers::CTRL, KeyCode::Char('-'), DecreaseFontSize],
+            [KeyModifiers::SUPER, KeyCode::Char('='), IncreaseFontSize],
+            [KeyModifiers::CTRL, KeyCode::Char('='), IncreaseFontSize],
+            [KeyModifiers::SUPER, KeyCode::Char('0'), ResetFontSize],
+            [KeyModifiers::CTRL, KeyCode::Char('0'), ResetFontSize],
+            [ctrl_shift, KeyCode::Char('I'), ShowTabInspector],
+            [ctrl_shift, KeyCode::Char('i'), ShowTabInspector],
+            [ctrl_shift, KeyCode::Char(' '), QuickSelect],
+            [ctrl_shift, KeyCode::Char('L'), NavigateLinks],
+            [ctrl_shift, KeyCode::Char('l'), NavigateLinks],
+            [ctrl_shift, KeyCode::Char('P'), ShowLauncher],
+            [ctrl_shift, KeyCode::Char('p'), ShowLauncher],
+        );
+
+        for (idx, c) in "123456789".chars().enumerate() {
+            k!([KeyModifiers::SUPER, KeyCode::Char(c), ActivateTab(idx)]);
+        }
+
+        for key in &config.keys {
+            map.insert((key.key, key.mods), key.action.clone());
+        }
+
+        KeyMap(map)
+    }
+
+    pub fn lookup(&self, key: KeyCode, mods: KeyModifiers) -> Option<KeyAssignment> {
+        self.0.get(&(key, mods)).cloned()
+    }
+}
@@ -0,0 +1,290 @@
+//! Rewrites key presses into other keys or text before they are
+//! encoded for the terminal, so that keys can be rearranged within
+//! wezterm without changing the keyboard layout of the whole system.
+//! Remapping happens before the key assignments are consulted, so a
+//! remapped key triggers the assignment of the key that it became.
+use crate::config::Config;
+use crate::keyassignment::{de_keycode, de_modifiers, parse_keycode, parse_modifiers};
+use failure::Error;
+use serde::{Deserialize, Deserializer};
+use serde_derive::*;
+use std::collections::HashMap;
+use term::{KeyCode, KeyModifiers};
+
+/// A key together with the modifiers that are pressed with it,
+/// written as "F1", "CTRL|F1" or "CTRL|SHIFT|a"
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyCombo {
+    pub key: KeyCode,
+    pub mods: KeyModifiers,
+}
+
+impl KeyCombo {
+    pub fn parse(s: &str) -> Result<Self, Error> {
+        // The key is the part after the last separator, unless the key
+        // is itself the separator
+        let (mods, key) = if s == "|" {
+            ("", s)
+        } else if s.ends_with("||") {
+            (&s[..s.len() - 2], "|")
+        } else {
+            match s.rfind('|') {
+                Some(idx) => (&s[..idx], &s[idx + 1..]),
+                None => ("", s),
+            }
+        };
+        Ok(Self {
+            key: parse_keycode(key.trim())?,
+            mods: parse_modifiers(mods)?,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyCombo {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Self::parse(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+/// What a remapped key becomes
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub enum RemapAction {
+    /// Send this key and modifiers instead
+    Key(KeyCombo),
+    /// Send this text to the terminal as though it had been typed
+    Text(String),
+    /// The key acts as these modifiers for as long as it is held down,
+    /// whatever modifiers are pressed with it.  When the key is itself
+    /// a modifier key, such as "Control", the modifier that it
+    /// normally applies is suppressed, which allows swapping keys.
+    Modifier(#[serde(deserialize_with = "de_modifiers")] KeyModifiers),
+}
+
+/// Remaps a key and modifier combination.  For example, to make
+/// CapsLock behave as CTRL and F13 send SHIFT-F1:
+///
+/// ```toml
+/// [[key_remaps]]
+/// key = "CapsLock"
+/// action = { Modifier = "CTRL" }
+///
+/// [[key_remaps]]
+/// key = "F13"
+/// action = { Key = "SHIFT|F1" }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+pub struct KeyRemap {
+    #[serde(deserialize_with = "de_keycode")]
+    pub key: KeyCode,
+    #[serde(deserialize_with = "de_modifiers", default)]
+    pub mods: KeyModifiers,
+    pub action: RemapAction,
+}
+
+/// The outcome of remapping a key press
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemappedKey {
+    Key(KeyCode, KeyModifiers),
+    Text(String),
+    /// The key has no effect of its own, as is the case for keys
+    /// that have been made to act as modifiers
+    Swallowed,
+}
+
+/// A key that has been remapped to act as a modifier and that is
+/// currently held down
+#[derive(Debug)]
+struct HeldModifier {
+    key: KeyCode,
+    added: KeyModifiers,
+    suppressed: KeyModifiers,
+}
+
+/// Returns the modifier that the operating system applies while `key`
+/// is held down
+fn native_modifier(key: KeyCode) -> KeyModifiers {
+    match key {
+        KeyCode::Control | KeyCode::LeftControl | KeyCode::RightControl => KeyModifiers::CTRL,
+        KeyCode::Shift | KeyCode::LeftShift | KeyCode::RightShift => KeyModifiers::SHIFT,
+        KeyCode::Alt | KeyCode::LeftAlt | KeyCode::RightAlt => KeyModifiers::ALT,
+        KeyCode::Super | KeyCode::LeftWindows | KeyCode::RightWindows => KeyModifiers::SUPER,
+        _ => KeyModifiers::NONE,
+    }
+}
+
+/// Applies the `key_remaps` from the configuration to the key events
+/// of a window
+#[derive(Debug, Default)]
+pub struct KeyRemapper {
+    remaps: HashMap<(KeyCode, KeyModifiers), RemapAction>,
+    modifiers: HashMap<KeyCode, KeyModifiers>,
+    held: Vec<HeldModifier>,
+}
+
+impl KeyRemapper {
+    pub fn new(config: &Config) -> Self {
+        let mut remapper = Self::default();
+        for remap in &config.key_remaps {
+            match &remap.action {
+                RemapAction::Modifier(mods) => {
+                    remapper.modifiers.insert(remap.key, *mods);
+                }
+                action => {
+                    remapper
+                        .remaps
+                        .insert((remap.key, remap.mods), action.clone());
+                }
+            }
+        }
+        remapper
+    }
+
+    /// Applies the modifiers of the held keys to `mods`
+    fn effective_modifiers(&self, mods: KeyModifiers) -> KeyModifiers {
+        self.held
+            .iter()
+            .fold(mods, |mods, held| (mods - held.suppressed) | held.added)
+    }
+
+    pub fn key_down(&mut self, key: KeyCode, mods: KeyModifiers) -> RemappedKey {
+        if let Some(added) = self.modifiers.get(&key).cloned() {
+            // Auto-repeat delivers further presses while it is held
+            if !self.held.iter().any(|held| held.key == key) {
+                self.held.push(HeldModifier {
+                    key,
+                    added,
+                    suppressed: native_modifier(key),
+                });
+            }
+            return RemappedKey::Swallowed;
+        }
+
+        let mods = self.effective_modifiers(mods);
+        match self.remaps.get(&(key, mods)) {
+            Some(RemapAction::Key(combo)) => RemappedKey::Key(combo.key, combo.mods),
+            Some(RemapAction::Text(text)) => RemappedKey::Text(text.clone()),
+            Some(RemapAction::Modifier(_)) | None => RemappedKey::Key(key, mods),
+        }
+    }
+
+    /// Returns the key that should be reported as released, or None
+    /// if the release should not be reported
+    pub fn key_up(&mut self, key: KeyCode, mods: KeyModifiers) -> Option<(KeyCode, KeyModifiers)> {
+        if let Some(idx) = self.held.iter().position(|held| held.key == key) {
+            self.held.remove(idx);
+            return None;
+        }
+        if self.modifiers.contains_key(&key) {
+            return None;
+        }
+
+        let mods = self.effective_modifiers(mods);
+        match self.remaps.get(&(key, mods)) {
+            Some(RemapAction::Key(combo)) => Some((combo.key, combo.mods)),
+            Some(RemapAction::Text(_)) => None,
+            Some(RemapAction::Modifier(_)) | None => Some((key, mods)),
+        }
+    }
+
+    /// Forgets the held modifiers; the release of a key isn't seen
+    /// if the window loses the focus while it is held
+    pub fn reset(&mut self) {
+        self.held.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_combo() {
+        let combo = |key, mods| KeyCombo { key, mods };
+        assert_eq!(
+            KeyCombo::parse("F1").unwrap(),
+            combo(KeyCode::Function(1), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            KeyCombo::parse("CTRL|SHIFT|a").unwrap(),
+            combo(KeyCode::Char('a'), KeyModifiers::CTRL | KeyModifiers::SHIFT)
+        );
+        assert_eq!(
+            KeyCombo::parse("|").unwrap(),
+            combo(KeyCode::Char('|'), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            KeyCombo::parse("ALT||").unwrap(),
+            combo(KeyCode::Char('|'), KeyModifiers::ALT)
+        );
+        assert!(KeyCombo::parse("HYPER|a").is_err());
+    }
+
+    #[test]
+    fn remap() {
+        let mut remapper = KeyRemapper::default();
+        remapper
+            .modifiers
+            .insert(KeyCode::CapsLock, KeyModifiers::CTRL);
+        remapper
+            .modifiers
+            .insert(KeyCode::Control, KeyModifiers::NONE);
+        remapper.remaps.insert(
+            (KeyCode::Function(13), KeyModifiers::NONE),
+            RemapAction::Key(KeyCombo {
+                key: KeyCode::Function(1),
+                mods: KeyModifiers::SHIFT,
+            }),
+        );
+        remapper.remaps.insert(
+            (KeyCode::Char('x'), KeyModifiers::CTRL),
+            RemapAction::Text("hello".to_owned()),
+        );
+
+        assert_eq!(
+            remapper.key_down(KeyCode::Function(13), KeyModifiers::NONE),
+            RemappedKey::Key(KeyCode::Function(1), KeyModifiers::SHIFT)
+        );
+        assert_eq!(
+            remapper.key_up(KeyCode::Function(13), KeyModifiers::NONE),
+            Some((KeyCode::Function(1), KeyModifiers::SHIFT))
+        );
+
+        // CapsLock acts as CTRL while held
+        assert_eq!(
+            remapper.key_down(KeyCode::CapsLock, KeyModifiers::NONE),
+            RemappedKey::Swallowed
+        );
+        assert_eq!(
+            remapper.key_down(KeyCode::Char('a'), KeyModifiers::NONE),
+            RemappedKey::Key(KeyCode::Char('a'), KeyModifiers::CTRL)
+        );
+        assert_eq!(
+            remapper.key_down(KeyCode::Char('x'), KeyModifiers::NONE),
+            RemappedKey::Text("hello".to_owned())
+        );
+        assert_eq!(remapper.key_up(KeyCode::CapsLock, KeyModifiers::NONE), None);
+        assert_eq!(
+            remapper.key_down(KeyCode::Char('a'), KeyModifiers::NONE),
+            RemappedKey::Key(KeyCode::Char('a'), KeyModifiers::NONE)
+        );
+
+        // Control no longer applies CTRL while held
+        assert_eq!(
+            remapper.key_down(KeyCode::Control, KeyModifiers::CTRL),
+            RemappedKey::Swallowed
+        );
+        assert_eq!(
+            remapper.key_down(KeyCode::Char('a'), KeyModifiers::CTRL),
+            RemappedKey::Key(KeyCode::Char('a'), KeyModifiers::NONE)
+        );
+        remapper.reset();
+        assert_eq!(
+            remapper.key_down(KeyCode::Char('a'), KeyModifiers::CTRL),
+            RemappedKey::Key(KeyCode::Char('a'), KeyModifiers::CTRL)
+        );
+    }
+}
@@ -0,0 +1,115 @@
+//! Reports the fonts that are selected by the configuration and,
+//! when given some text, which of them would be used to render each
+//! portion of it.  This helps to explain why a glyph is drawn using an
+//! unexpected font, or is missing altogether.
+use crate::config::{Config, TextStyle};
+use crate::font::{FallbackIdx, FontConfiguration, NamedFont};
+use failure::Error;
+use std::sync::Arc;
+use term::CellAttributes;
+
+/// Produce a one line summary of the fallback font `idx` of `font`
+fn describe_fallback(font: &mut NamedFont, idx: FallbackIdx) -> Result<String, Error> {
+    let fallback = font.get_fallback(idx)?;
+    Ok(format!(
+        "{} ({}){}",
+        fallback.description(),
+        fallback
+            .path()
+            .unwrap_or_else(|| "path unknown".to_string()),
+        if fallback.has_color() {
+            ", has color glyphs"
+        } else {
+            ""
+        }
+    ))
+}
+
+fn families(style: &TextStyle) -> String {
+    style
+        .font_with_fallback()
+        .iter()
+        .map(|attr| {
+            let mut family = attr.family.clone();
+            if attr.bold.unwrap_or(false) {
+                family.push_str(" bold");
+            }
+            if attr.italic.unwrap_or(false) {
+                family.push_str(" italic");
+            }
+            family
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Print the fonts that the base font and each of the `font_rules`
+/// resolve to
+fn list_styles(config: &Config, fonts: &FontConfiguration) -> Result<(), Error> {
+    let mut styles = vec![("font".to_string(), &config.font)];
+    for (idx, rule) in config.font_rules.iter().enumerate() {
+        styles.push((format!("font_rules[{}]", idx), &rule.font));
+    }
+
+    for (label, style) in styles {
+        println!("{}: {}", label, families(style));
+        let font = fonts.cached_font(style)?;
+        let mut font = font.borrow_mut();
+        // Only the fallbacks that correspond to the configured families
+        // are shown; some font systems have many more after those.
+        for idx in 0..style.font_with_fallback().len() {
+            match describe_fallback(&mut **font, idx) {
+                Ok(desc) => println!("  {}: {}", idx, desc),
+                Err(err) => {
+                    println!("  {}: {}", idx, err);
+                    break;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Shape `text` using the style for unattributed text and print
+/// the font that was selected for each glyph
+fn list_text(fonts: &FontConfiguration, text: &str) -> Result<(), Error> {
+    let style = fonts.match_style(&CellAttributes::default()).clone();
+    let font = fonts.cached_font(&style)?;
+    let mut font = font.borrow_mut();
+    let glyphs = font.shape(text)?;
+
+    for glyph in &glyphs {
+        // The text of a glyph runs up to the start of the next cluster
+        let start = glyph.cluster as usize;
+        let end = glyphs
+            .iter()
+            .map(|g| g.cluster as usize)
+            .filter(|&pos| pos > start)
+            .min()
+            .unwrap_or_else(|| text.len());
+        let fragment = &text[start..end];
+        let codepoints = fragment
+            .chars()
+            .map(|c| format!("U+{:04X}", c as u32))
+            .collect::<Vec<_>>()
+            .join(" ");
+        println!(
+            "{:?} {} -> {}: {}",
+            fragment,
+            codepoints,
+            glyph.font_idx,
+            describe_fallback(&mut **font, glyph.font_idx)?
+        );
+    }
+    Ok(())
+}
+
+/// Report on the configured fonts, or on the fonts used for `text`
+/// if it is specified
+pub fn ls_fonts(config: &Arc<Config>, text: Option<&str>) -> Result<(), Error> {
+    let fonts = FontConfiguration::new(Arc::clone(config), config.font_system);
+    match text {
+        Some(text) => list_text(&fonts, text),
+        None => list_styles(config, &fonts),
+    }
+}
@@ -8,27 +8,34 @@ extern crate failure_derive;
 #[macro_use]
 pub mod log;
 use failure::Error;
-use std::ffi::OsString;
+use std::ffi::{OsStr, OsString};
 use structopt::StructOpt;
 
 use std::rc::Rc;
 use std::sync::Arc;
 
+mod colorpreview;
 mod config;
 mod frontend;
+mod keyassignment;
+mod keyremap;
+mod lsfonts;
 mod mux;
 mod opengl;
 mod server;
-use crate::frontend::guicommon::localtab::LocalTab;
+mod terminfo;
+use crate::frontend::guicommon::localtab::{LocalTab, SpawnInfo};
+use crate::frontend::guicommon::overlay::OverlayHost;
 use crate::frontend::{FrontEnd, FrontEndSelection};
-use crate::mux::tab::Tab;
+use crate::mux::tab::{alloc_tab_id, FailedSpawn, Tab};
+use crate::mux::window::WindowId;
 use crate::mux::Mux;
 
 mod font;
-use crate::font::{FontConfiguration, FontSystemSelection};
+use crate::font::FontSystemSelection;
 
 mod pty;
-use pty::PtySize;
+use pty::{CommandBuilder, PtySize};
 use std::env;
 
 /// Determine which shell to run.
@@ -94,11 +101,28 @@ struct StartCommand {
     )]
     font_system: Option<FontSystemSelection>,
 
+    /// Use SCALE as the scale factor for the fonts in each window,
+    /// rather than the one reported for the monitor.  This overrides
+    /// the `dpi_scale` configuration option.
+    #[structopt(long = "dpi-scale")]
+    dpi_scale: Option<f64>,
+
     /// Instead of executing your shell, run PROG.
     /// For example: `wezterm start -- bash -l` will spawn bash
     /// as if it were a login shell.
     #[structopt(parse(from_os_str))]
     prog: Vec<OsString>,
+
+    /// Keep the window open after PROG exits, until a key is
+    /// pressed, so that its output can be read
+    #[structopt(long = "hold")]
+    hold: bool,
+
+    /// Start in the directory CWD rather than the current directory.
+    /// If wezterm is already running, and neither PROG nor --hold
+    /// were given, a new tab is opened in the running instance.
+    #[structopt(long = "cwd", parse(from_os_str))]
+    cwd: Option<OsString>,
 }
 
 #[derive(Debug, StructOpt, Clone)]
@@ -110,22 +134,140 @@ enum SubCommand {
     #[structopt(name = "cli", about = "Interact with experimental mux server")]
     #[structopt(raw(setting = "structopt::clap::AppSettings::ColoredHelp"))]
     Cli(CliCommand),
+
+    #[structopt(
+        name = "preview-colors",
+        about = "Show the colors of a color scheme in this terminal"
+    )]
+    #[structopt(raw(setting = "structopt::clap::AppSettings::ColoredHelp"))]
+    PreviewColors(PreviewColorsCommand),
+
+    #[structopt(
+        name = "ls-fonts",
+        about = "Show which fonts are used to render the configured styles or some text"
+    )]
+    #[structopt(raw(setting = "structopt::clap::AppSettings::ColoredHelp"))]
+    LsFonts(LsFontsCommand),
+
+    #[structopt(
+        name = "terminfo",
+        about = "Print or install the terminfo entry that describes wezterm"
+    )]
+    #[structopt(raw(setting = "structopt::clap::AppSettings::ColoredHelp"))]
+    Terminfo(TerminfoCommand),
 }
 
 #[derive(Debug, StructOpt, Clone)]
-struct CliCommand {}
+struct PreviewColorsCommand {
+    /// Preview the scheme in SCHEME, a TOML file holding the same
+    /// keys as the `[colors]` section of the configuration, rather
+    /// than the configured colors
+    #[structopt(long = "scheme", parse(from_os_str))]
+    scheme: Option<std::path::PathBuf>,
+}
+
+#[derive(Debug, StructOpt, Clone)]
+struct LsFontsCommand {
+    /// Rather than listing the configured fonts, report the font
+    /// that is used for each glyph of TEXT
+    #[structopt(long = "text")]
+    text: Option<String>,
+}
+
+#[derive(Debug, StructOpt, Clone)]
+struct TerminfoCommand {
+    /// Compile the entry using `tic` rather than printing its source,
+    /// and show how to make use of it
+    #[structopt(long = "install")]
+    install: bool,
+
+    /// Install into DIR rather than ~/.terminfo
+    #[structopt(long = "dir", parse(from_os_str))]
+    dir: Option<std::path::PathBuf>,
+}
+
+#[derive(Debug, StructOpt, Clone)]
+struct CliCommand {
+    #[structopt(subcommand)]
+    sub: Option<CliSubCommand>,
+}
+
+#[derive(Debug, StructOpt, Clone)]
+enum CliSubCommand {
+    #[structopt(name = "list", about = "list tabs")]
+    List,
+
+    #[structopt(
+        name = "unhandled-escapes",
+        about = "show the escape sequences that were ignored by a tab"
+    )]
+    UnhandledEscapes(UnhandledEscapesCommand),
+
+    #[structopt(
+        name = "test-hyperlink-rules",
+        about = "show how the hyperlink_rules match text read from stdin"
+    )]
+    TestHyperlinkRules,
+
+    #[structopt(
+        name = "get-semantic-object",
+        about = "print the hyperlink or word displayed at a position in a tab"
+    )]
+    GetSemanticObject(GetSemanticObjectCommand),
+}
+
+#[derive(Debug, StructOpt, Clone)]
+struct UnhandledEscapesCommand {
+    /// The tab to query.  All tabs are reported if omitted.
+    #[structopt(long = "tab")]
+    tab_id: Option<usize>,
+}
+
+#[derive(Debug, StructOpt, Clone)]
+struct GetSemanticObjectCommand {
+    /// The tab to query, as shown by `wezterm cli list`.  Defaults to
+    /// the tab that the command is run in.
+    #[structopt(long = "tab-id", env = "WEZTERM_TAB")]
+    tab_id: usize,
+    /// The row of the viewport, counting from 0 at the top
+    #[structopt(long = "row")]
+    row: i64,
+    /// The column, counting from 0 at the left
+    #[structopt(long = "col")]
+    col: usize,
+}
 
 fn run_terminal_gui(config: Arc<config::Config>, opts: &StartCommand) -> Result<(), Error> {
+    let config = match opts.dpi_scale {
+        Some(scale) if scale > 0.0 => Arc::new(config::Config {
+            dpi_scale: Some(scale),
+            ..(*config).clone()
+        }),
+        Some(scale) => bail!("--dpi-scale must be greater than zero, not {}", scale),
+        None => config,
+    };
+
+    frontend::guicommon::highcontrast::probe_system_setting();
+
     let font_system = opts.font_system.unwrap_or(config.font_system);
     font_system.set_default();
 
-    let fontconfig = Rc::new(FontConfiguration::new(Arc::clone(&config), font_system));
+    let one_shot = !opts.prog.is_empty();
 
-    let cmd = if !opts.prog.is_empty() {
+    if let Some(cwd) = opts.cwd.as_ref() {
+        if !one_shot && !opts.hold && spawn_in_running_instance(&config, cwd) {
+            return Ok(());
+        }
+    }
+
+    let mut cmd = config.build_prog(if one_shot {
         Some(opts.prog.iter().map(|x| x.as_os_str()).collect())
     } else {
         None
-    };
+    })?;
+    if let Some(cwd) = opts.cwd.as_ref() {
+        cmd.cwd(cwd);
+    }
 
     let mux = Rc::new(mux::Mux::new(&config));
     Mux::set_mux(&mux);
@@ -133,8 +275,27 @@ fn run_terminal_gui(config: Arc<config::Config>, opts: &StartCommand) -> Result<
     let front_end = opts.front_end.unwrap_or(config.front_end);
     let gui = front_end.try_new(&mux)?;
 
-    spawn_window(&mux, &*gui, cmd, &fontconfig)?;
-    gui.run_forever()
+    // A program that was explicitly requested must not be silently
+    // replaced by the fallback; failing to spawn it is an error that
+    // scripts running `wezterm start -- command` need to see
+    let spawn_failure = if one_shot {
+        config::SpawnFailure::Fail
+    } else {
+        config.spawn_failure
+    };
+    spawn_window(&mux, &*gui, cmd, opts.hold, spawn_failure)?;
+    let result = gui.run_forever();
+    mux.shutdown();
+    result?;
+
+    if one_shot {
+        // Propagate the status of the command so that
+        // `wezterm start -- command` can be used in scripts
+        if let Some(status) = mux.last_exit_status() {
+            std::process::exit(status.exit_code() as i32);
+        }
+    }
+    Ok(())
 }
 
 fn main() -> Result<(), Error> {
@@ -171,64 +332,392 @@ fn main() -> Result<(), Error> {
             println!("Using configuration: {:#?}\nopts: {:#?}", config, opts);
             run_terminal_gui(config, &start)
         }
-        SubCommand::Cli(_) => {
+        SubCommand::PreviewColors(cmd) => {
+            let palette = match cmd.scheme {
+                Some(path) => colorpreview::load_scheme(&path)?,
+                None => colorpreview::configured_scheme(&config),
+            };
+            colorpreview::show_preview(&palette)
+        }
+        SubCommand::LsFonts(cmd) => {
+            lsfonts::ls_fonts(&config, cmd.text.as_ref().map(String::as_str))
+        }
+        SubCommand::Terminfo(cmd) => {
+            if !cmd.install {
+                print!("{}", terminfo::TERMINFO_SOURCE);
+                return Ok(());
+            }
+            let dir = terminfo::install(cmd.dir.as_ref().map(|d| d.as_path()))?;
+            println!(
+                "Installed the {} terminfo entry into {}\n",
+                terminfo::TERM_NAME,
+                dir.display()
+            );
+            print!("{}", terminfo::shell_snippets(&dir));
+            Ok(())
+        }
+        SubCommand::Cli(cli) => {
             use crate::server::client::Client;
             use crate::server::codec::*;
-            let mut client = Client::new(&config)?;
-            eprintln!("ping: {:?}", client.ping()?);
-            let tabs = client.list_tabs()?;
-            for (tab_id, title) in tabs.tabs.iter() {
-                eprintln!("tab {}: {}", tab_id, title);
-                let _data = client.get_coarse_tab_renderable_data(GetCoarseTabRenderableData {
-                    tab_id: *tab_id,
-                })?;
-                // eprintln!("coarse: {:?}", data);
+            use term::SemanticObject;
+            match cli.sub.unwrap_or(CliSubCommand::List) {
+                CliSubCommand::List => {
+                    let mut client = Client::new(&config)?;
+                    eprintln!("ping: {:?}", client.ping()?);
+                    let tabs = client.list_tabs()?;
+                    for (tab_id, title) in tabs.tabs.iter() {
+                        eprintln!("tab {}: {}", tab_id, title);
+                        let _data =
+                            client.get_coarse_tab_renderable_data(GetCoarseTabRenderableData {
+                                tab_id: *tab_id,
+                            })?;
+                        // eprintln!("coarse: {:?}", data);
+                    }
+                }
+                CliSubCommand::UnhandledEscapes(cmd) => {
+                    let mut client = Client::new(&config)?;
+                    let tab_ids: Vec<usize> = match cmd.tab_id {
+                        Some(tab_id) => vec![tab_id],
+                        None => {
+                            let mut tab_ids: Vec<usize> =
+                                client.list_tabs()?.tabs.keys().cloned().collect();
+                            tab_ids.sort();
+                            tab_ids
+                        }
+                    };
+                    for tab_id in tab_ids {
+                        let result = client
+                            .get_tab_unhandled_sequences(GetTabUnhandledSequences { tab_id })?;
+                        println!(
+                            "tab {}: {} ignored escape sequences, most recent last",
+                            tab_id, result.count
+                        );
+                        for seq in result.recent {
+                            println!("  {}", seq.description);
+                            for line in seq.hex_dump() {
+                                println!("    {}", line);
+                            }
+                        }
+                    }
+                }
+                CliSubCommand::TestHyperlinkRules => test_hyperlink_rules(&config)?,
+                CliSubCommand::GetSemanticObject(cmd) => {
+                    let mut client = Client::new(&config)?;
+                    let result = client.get_semantic_object(GetSemanticObject {
+                        tab_id: cmd.tab_id,
+                        row: cmd.row,
+                        col: cmd.col,
+                    })?;
+                    // The kind is printed first so that scripts can
+                    // tell links from words
+                    match result.object {
+                        Some(SemanticObject::Hyperlink { uri, text }) => {
+                            println!("hyperlink\t{}\t{}", uri, text)
+                        }
+                        Some(SemanticObject::Word(word)) => println!("word\t{}", word),
+                        None => bail!(
+                            "nothing at row {} col {} of tab {}",
+                            cmd.row,
+                            cmd.col,
+                            cmd.tab_id
+                        ),
+                    }
+                }
             }
             Ok(())
         }
     }
 }
 
+/// Report the matches that each of the hyperlink rules makes against
+/// the lines of stdin, so that rules can be developed without having
+/// to restart the gui to try them out
+fn test_hyperlink_rules(config: &config::Config) -> Result<(), Error> {
+    use std::io::BufRead;
+    use termwiz::hyperlink::Rule;
+
+    for (idx, rule) in config.hyperlink_rules.iter().enumerate() {
+        println!("rule {}: {} -> {}", idx, rule.pattern(), rule.format());
+    }
+
+    let stdin = std::io::stdin();
+    for (line_idx, line) in stdin.lock().lines().enumerate() {
+        let line = line?;
+        let mut matches = vec![];
+        for (idx, rule) in config.hyperlink_rules.iter().enumerate() {
+            for m in Rule::match_hyperlinks(&line, std::slice::from_ref(rule)) {
+                matches.push((idx, m));
+            }
+        }
+        if matches.is_empty() {
+            continue;
+        }
+        matches.sort_by_key(|(idx, m)| (m.range.start, *idx));
+
+        println!("line {}: {}", line_idx + 1, line);
+        for (idx, m) in matches {
+            println!(
+                "  rule {} matched {:?} at bytes {}..{} -> {}",
+                idx,
+                &line[m.range.clone()],
+                m.range.start,
+                m.range.end,
+                m.link.uri()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Ask the running gui instance, if any, to open a tab in `cwd`.
+/// Returns true if it agreed to do so.
+fn spawn_in_running_instance(config: &Arc<config::Config>, cwd: &OsString) -> bool {
+    use crate::server::client::Client;
+    use crate::server::codec::SpawnTab;
+
+    let sock_path = match config.single_instance_socket_path.as_ref() {
+        Some(path) => std::path::Path::new(path),
+        None => return false,
+    };
+    let cwd = match cwd.to_str() {
+        Some(cwd) => cwd.to_owned(),
+        // Can't be sent over the wire; start a new instance instead
+        None => return false,
+    };
+    match Client::with_path(sock_path).and_then(|mut client| {
+        client.spawn_tab(SpawnTab {
+            args: None,
+            cwd: Some(cwd),
+        })
+    }) {
+        Ok(_) => true,
+        Err(err) => {
+            eprintln!("no running instance to open a tab in: {}", err);
+            false
+        }
+    }
+}
+
+/// The size of tabs that are spawned before there is a window
+/// whose size they could adopt
+const DEFAULT_TAB_SIZE: PtySize = PtySize {
+    rows: 24,
+    cols: 80,
+    pixel_width: 0,
+    pixel_height: 0,
+};
+
 fn spawn_tab(
     config: &Arc<config::Config>,
-    cmd: Option<Vec<&std::ffi::OsStr>>,
+    cmd: CommandBuilder,
+    hold: bool,
 ) -> Result<Rc<Tab>, Error> {
-    let cmd = config.build_prog(cmd)?;
+    spawn_tab_with_size(
+        config,
+        cmd,
+        DEFAULT_TAB_SIZE,
+        None,
+        hold,
+        config.spawn_failure,
+    )
+}
 
-    let initial_cols = 80u16;
-    let initial_rows = 24u16;
-    let initial_pixel_width = 0;
-    let initial_pixel_height = 0;
+/// Spawn `cmd` into a new tab whose terminal has the specified size.
+/// `window_id` is the window that the tab is going to be added to,
+/// or None if a new window is going to be created for it.
+/// The ids of the tab and window, and the path of the socket that
+/// the mux is listening on, are exported in the environment so that
+/// the programs in the tab can identify it to `wezterm cli`.
+/// If `cmd` can't be spawned then `spawn_failure` decides whether the
+/// `fallback_prog` is run in its place, below a description of the error.
+fn spawn_tab_with_size(
+    config: &Arc<config::Config>,
+    mut cmd: CommandBuilder,
+    size: PtySize,
+    window_id: Option<WindowId>,
+    hold: bool,
+    spawn_failure: config::SpawnFailure,
+) -> Result<Rc<Tab>, Error> {
+    let mux = Mux::get().unwrap();
+    let tab_id = alloc_tab_id();
+    let window_id = window_id.unwrap_or_else(|| mux.reserve_window_id(tab_id));
+    cmd.env("WEZTERM_TAB", tab_id.to_string());
+    cmd.env("WEZTERM_WINDOW", window_id.to_string());
+    if let Some(path) = mux.socket_path() {
+        cmd.env("WEZTERM_UNIX_SOCKET", path);
+    }
 
     let pty_sys = config.pty.get()?;
-    let (master, slave) = pty_sys.openpty(PtySize {
-        rows: initial_rows,
-        cols: initial_cols,
-        pixel_width: initial_pixel_width,
-        pixel_height: initial_pixel_height,
-    })?;
+    let (mut master, slave) = pty_sys.openpty(size)?;
 
-    let child = slave.spawn_command(cmd)?;
+    let fallback = match spawn_failure {
+        config::SpawnFailure::RunFallbackProg if !config.fallback_prog.is_empty() => {
+            Some(fallback_command(config, &cmd)?)
+        }
+        _ => None,
+    };
+    let mut spawn_info = SpawnInfo::new(&cmd);
+    let argv = cmd.get_argv().to_vec();
+    let mut spawn_error = None;
+    let mut failed_spawn = None;
+    let child = match (slave.spawn_command(cmd), fallback) {
+        (Ok(child), _) => child,
+        (Err(err), Some(fallback)) => {
+            eprintln!("unable to spawn {:?}: {}; running fallback_prog", argv, err);
+            spawn_error = Some(format!(
+                "\x1b[1mwezterm: unable to spawn {:?}: {}\r\n\
+                 Running {:?} instead.\x1b[0m\r\n\r\n",
+                argv,
+                err,
+                fallback.get_argv()
+            ));
+            failed_spawn = Some(FailedSpawn {
+                error: format!("unable to spawn {:?}: {}", argv, err),
+                fallback: fallback
+                    .get_argv()
+                    .iter()
+                    .map(|arg| arg.to_string_lossy().into_owned())
+                    .collect(),
+            });
+            spawn_info = SpawnInfo::new(&fallback);
+            slave.spawn_command(fallback)?
+        }
+        (Err(err), None) => return Err(err),
+    };
     eprintln!("spawned: {:?}", child);
+    if config.register_utmp {
+        if let Err(err) = master.register_utmp() {
+            eprintln!("unable to register the session in utmp: {}", err);
+        }
+    }
 
-    let terminal = term::Terminal::new(
-        initial_rows as usize,
-        initial_cols as usize,
-        config.scrollback_lines.unwrap_or(3500),
+    let mut terminal = term::Terminal::new(
+        size.rows as usize,
+        size.cols as usize,
+        config.scrollback_lines,
         config.hyperlink_rules.clone(),
     );
+    terminal.set_log_unhandled(config.log_unhandled_escapes);
+    terminal.set_scroll_to_bottom_on_input(config.scroll_to_bottom_on_input);
+    terminal.set_scroll_to_bottom_on_output(config.scroll_to_bottom_on_output);
+    terminal.set_alt_screen_wheel_scroll_lines(config.alt_screen_wheel_scroll_lines);
+    terminal.set_device_identity(config.device_identity());
+    terminal.set_csi_u_key_encoding(config.enable_csi_u_key_encoding);
+    terminal.set_sgr_21_is_bold_off(config.sgr_21_is_bold_off);
+    terminal.set_copy_concealed_text(config.copy_concealed_text);
+    terminal.set_normalize_input(config.normalize_input_to_nfc);
+    terminal.set_normalize_output(config.normalize_output_to_nfc);
+    if let Some(message) = spawn_error {
+        terminal.advance_bytes(message, &mut OverlayHost::default());
+    }
+
+    let mut tab = LocalTab::new(tab_id, terminal, child, master, spawn_info);
+    tab.set_exit_behavior(if hold {
+        config::ExitBehavior::Hold
+    } else {
+        config.exit_behavior
+    });
+    if let Some(failure) = failed_spawn {
+        tab.set_spawn_failure(failure);
+    }
+    Ok(Rc::new(tab))
+}
 
-    Ok(Rc::new(LocalTab::new(terminal, child, master)))
+/// Returns the command that runs the `fallback_prog` in place of
+/// `cmd`, in the same directory and with the same environment
+fn fallback_command(
+    config: &config::Config,
+    cmd: &CommandBuilder,
+) -> Result<CommandBuilder, Error> {
+    let mut fallback =
+        config.build_prog(Some(config.fallback_prog.iter().map(OsStr::new).collect()))?;
+    for (key, val) in cmd.get_envs() {
+        fallback.env(key, val);
+    }
+    if let Some(cwd) = cmd.get_cwd() {
+        fallback.cwd(cwd);
+    }
+    Ok(fallback)
 }
 
 fn spawn_window(
     mux: &Rc<Mux>,
     gui: &FrontEnd,
-    cmd: Option<Vec<&std::ffi::OsStr>>,
-    fontconfig: &Rc<FontConfiguration>,
+    cmd: CommandBuilder,
+    hold: bool,
+    spawn_failure: config::SpawnFailure,
 ) -> Result<(), Error> {
-    let tab = spawn_tab(mux.config(), cmd)?;
-    mux.add_tab(gui.gui_executor(), &tab)?;
+    let tab = spawn_tab_with_size(
+        mux.config(),
+        cmd,
+        DEFAULT_TAB_SIZE,
+        None,
+        hold,
+        spawn_failure,
+    )?;
+    mux.add_tab(&tab)?;
+
+    gui.spawn_new_window(mux.config(), &tab)
+}
 
-    gui.spawn_new_window(mux.config(), &fontconfig, &tab)
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fallback_keeps_env_and_cwd() {
+        let config = config::Config::default();
+        let mut cmd = CommandBuilder::new("nosuchshell");
+        cmd.env("WEZTERM_TEST", "1");
+        cmd.cwd("/tmp");
+        let fallback = fallback_command(&config, &cmd).unwrap();
+        assert_eq!(
+            fallback.get_argv()[0],
+            OsString::from(&config.fallback_prog[0])
+        );
+        assert!(fallback
+            .get_envs()
+            .iter()
+            .any(|(key, val)| key == "WEZTERM_TEST" && val == "1"));
+        assert_eq!(fallback.get_cwd(), cmd.get_cwd());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn spawn_failure_runs_fallback() {
+        let config = Arc::new(config::Config::default());
+        let mux = Rc::new(Mux::new(&config));
+        Mux::set_mux(&mux);
+        let missing = "/nonexistent/wezterm-test-program";
+
+        let result = spawn_tab_with_size(
+            &config,
+            CommandBuilder::new(missing),
+            DEFAULT_TAB_SIZE,
+            None,
+            false,
+            config::SpawnFailure::Fail,
+        );
+        assert!(result.is_err());
+
+        let tab = spawn_tab_with_size(
+            &config,
+            CommandBuilder::new(missing),
+            DEFAULT_TAB_SIZE,
+            None,
+            false,
+            config::SpawnFailure::RunFallbackProg,
+        )
+        .unwrap();
+        let text = tab.renderer().get_viewport_text();
+        assert!(text[0].contains("unable to spawn"));
+
+        let failure = tab.take_spawn_failure().unwrap();
+        assert!(failure.error.contains(missing));
+        assert_eq!(failure.fallback, config.fallback_prog);
+        // It is only explained once
+        assert_eq!(tab.take_spawn_failure(), None);
+
+        tab.kill().unwrap();
+    }
 }
@@ -0,0 +1,158 @@
+//! Describes the content of a tab in the shape that the platform
+//! accessibility APIs expect: the visible lines as a single document
+//! of text, along with the caret as a character offset into it.
+//! UI Automation's TextPattern, the AT-SPI Text interface and the
+//! NSAccessibility text attributes all model text this way, so this
+//! is the common ground for exposing the terminal to screen readers;
+//! the lines announced by `renderfilter` complement it as the live
+//! region.  The frontends don't yet have bindings for any of those
+//! APIs, so for now the document is shown by the tab inspector, which
+//! allows checking what a screen reader would be told.
+use crate::mux::renderable::Renderable;
+use unicode_width::UnicodeWidthChar;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessibleText {
+    /// The visible lines, without trailing whitespace, joined by
+    /// newlines
+    pub text: String,
+    /// The character offset of the cursor into `text`, or None
+    /// if the cursor is not in the viewport
+    pub caret: Option<usize>,
+}
+
+impl AccessibleText {
+    /// Build the document from the `lines` of the viewport, with the
+    /// cursor at cell `(row, col)` of the viewport.  If the cursor is
+    /// beyond the end of its line, the line is padded with spaces so
+    /// that the caret remains where it is displayed.
+    pub fn new(lines: &[String], cursor: Option<(usize, usize)>) -> Self {
+        let mut text = String::new();
+        let mut caret = None;
+        let mut offset = 0;
+        for (row, line) in lines.iter().enumerate() {
+            if row > 0 {
+                text.push('\n');
+                offset += 1;
+            }
+            let line = line.trim_end();
+            text.push_str(line);
+            let chars = line.chars().count();
+
+            match cursor {
+                Some((cursor_row, cursor_col)) if cursor_row == row => {
+                    let (idx, col) = char_index_for_column(line, cursor_col);
+                    if idx == chars && col < cursor_col {
+                        for _ in col..cursor_col {
+                            text.push(' ');
+                        }
+                        offset += cursor_col - col;
+                    }
+                    caret = Some(offset + idx);
+                }
+                _ => {}
+            }
+            offset += chars;
+        }
+        Self { text, caret }
+    }
+
+    pub fn from_renderable(renderer: &Renderable) -> Self {
+        let lines = renderer.get_viewport_text();
+        let cursor = renderer.get_cursor_position();
+        let (scroll_offset, _) = renderer.get_scrollbar_info();
+        let row = cursor.y + scroll_offset as i64;
+        let cursor = if row >= 0 && (row as usize) < lines.len() {
+            Some((row as usize, cursor.x))
+        } else {
+            None
+        };
+        Self::new(&lines, cursor)
+    }
+
+    /// Returns the line and the column within that line of the caret,
+    /// both counted in characters
+    pub fn caret_line_and_column(&self) -> Option<(usize, usize)> {
+        let caret = self.caret?;
+        let mut line = 0;
+        let mut column = 0;
+        for c in self.text.chars().take(caret) {
+            if c == '\n' {
+                line += 1;
+                column = 0;
+            } else {
+                column += 1;
+            }
+        }
+        Some((line, column))
+    }
+
+    /// Produce a summary suitable for the tab inspector
+    pub fn report(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "{} lines, {} characters",
+            self.text.lines().count(),
+            self.text.chars().count()
+        )];
+        lines.push(match self.caret_line_and_column() {
+            Some((line, column)) => format!(
+                "caret at offset {}: line {} column {}",
+                self.caret.unwrap(),
+                line,
+                column
+            ),
+            None => "caret is not visible".to_string(),
+        });
+        lines
+    }
+}
+
+/// Returns the index of the character that is displayed in cell `col`
+/// of `line`, along with the cell at which it starts.  If the line is
+/// shorter than `col`, returns the number of characters and the number
+/// of cells that the line occupies.
+fn char_index_for_column(line: &str, col: usize) -> (usize, usize) {
+    let mut cells = 0;
+    for (idx, c) in line.chars().enumerate() {
+        let width = c.width().unwrap_or(0);
+        // Combining characters have no width and belong to the
+        // preceding character
+        if width > 0 && cells + width > col {
+            return (idx, cells);
+        }
+        cells += width;
+    }
+    (line.chars().count(), cells)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn caret_offsets() {
+        let lines = vec![
+            "$ ls     ".to_string(),
+            "日本 x".to_string(),
+            "$ ".to_string(),
+        ];
+
+        let doc = AccessibleText::new(&lines, Some((2, 2)));
+        assert_eq!(doc.text, "$ ls\n日本 x\n$ ");
+        assert_eq!(doc.caret, Some(12));
+        assert_eq!(doc.caret_line_and_column(), Some((2, 2)));
+
+        // The second cell of a double width character maps to it
+        let doc = AccessibleText::new(&lines, Some((1, 3)));
+        assert_eq!(doc.caret, Some(6));
+
+        // The cursor beyond the end of a line is preserved by padding
+        let doc = AccessibleText::new(&lines, Some((0, 6)));
+        assert_eq!(doc.text, "$ ls  \n日本 x\n$");
+        assert_eq!(doc.caret, Some(6));
+
+        let doc = AccessibleText::new(&lines, None);
+        assert_eq!(doc.caret, None);
+        assert_eq!(doc.report()[1], "caret is not visible");
+    }
+}
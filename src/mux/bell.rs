@@ -0,0 +1,191 @@
+//! Decides what happens when a tab rings the bell.  The action depends
+//! upon whether the tab is the active tab of the focused window, and
+//! a tab that rings the bell again within `bell_debounce_ms` of its
+//! last bell is ignored, as some programs ring it constantly.
+//! The frontends query the outcome when they next paint a window.
+use crate::config::BellAction;
+use crate::mux::tab::TabId;
+use crate::mux::window::WindowId;
+use crate::mux::Mux;
+use promise::task::gui_executor;
+use promise::timer::schedule_timer;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// How long the visual bell is shown for, in milliseconds
+const VISUAL_BELL_DURATION_MS: u64 = 150;
+
+fn visual_bell_duration() -> Duration {
+    Duration::from_millis(VISUAL_BELL_DURATION_MS)
+}
+
+/// Remembers when each tab last rang the bell
+#[derive(Default)]
+struct Debouncer {
+    last_rung: HashMap<TabId, Instant>,
+}
+
+impl Debouncer {
+    /// Returns true if a bell rung by `tab_id` at `now` should take
+    /// effect, in which case it becomes the last bell of that tab
+    fn accept(&mut self, tab_id: TabId, now: Instant, interval: Duration) -> bool {
+        match self.last_rung.get(&tab_id) {
+            Some(last) if now.duration_since(*last) < interval => false,
+            _ => {
+                self.last_rung.insert(tab_id, now);
+                true
+            }
+        }
+    }
+}
+
+/// The state of the visual bell of a window
+struct VisualBell {
+    started: Instant,
+    /// True once a frame has been painted showing the bell
+    painted: bool,
+}
+
+thread_local! {
+    static DEBOUNCER: RefCell<Debouncer> = RefCell::new(Debouncer::default());
+    static VISUAL: RefCell<HashMap<WindowId, VisualBell>> = RefCell::new(HashMap::new());
+    static NOTIFICATIONS: RefCell<Vec<(WindowId, String)>> = RefCell::new(vec![]);
+}
+
+/// Called when `tab_id` rings the bell.  This must be called on the
+/// gui thread.
+pub fn ring(tab_id: TabId) {
+    let mux = Mux::get().unwrap();
+    let config = mux.config();
+
+    let interval = Duration::from_millis(config.bell_debounce_ms);
+    if !DEBOUNCER.with(|d| d.borrow_mut().accept(tab_id, Instant::now(), interval)) {
+        return;
+    }
+
+    let window_id = match mux.window_containing_tab(tab_id) {
+        Some(window_id) => window_id,
+        None => return,
+    };
+    let is_active = mux
+        .get_active_tab_for_window(window_id)
+        .map(|tab| tab.tab_id() == tab_id)
+        .unwrap_or(false);
+    let focused = is_active && mux.focused_window() == Some(window_id);
+
+    let action = if focused {
+        config.bell_focused
+    } else {
+        config.bell_unfocused
+    };
+
+    match action {
+        BellAction::Ignore => return,
+        BellAction::Visual => {
+            VISUAL.with(|visual| {
+                visual.borrow_mut().insert(
+                    window_id,
+                    VisualBell {
+                        started: Instant::now(),
+                        painted: false,
+                    },
+                )
+            });
+            // Arrange to paint again once the bell has finished,
+            // so that it is removed from the display
+            if let Some(executor) = gui_executor() {
+                schedule_timer(executor, visual_bell_duration(), || {
+                    if let Some(mux) = Mux::get() {
+                        mux.notify_output();
+                    }
+                });
+            }
+        }
+        BellAction::Notify => {
+            let idx = mux
+                .get_window(window_id)
+                .and_then(|window| window.idx_by_id(tab_id))
+                .unwrap_or(0);
+            let title = mux
+                .get_tab(tab_id)
+                .map(|tab| tab.get_title())
+                .unwrap_or_default();
+            NOTIFICATIONS.with(|n| {
+                n.borrow_mut()
+                    .push((window_id, format!("Tab {} ({}) rang the bell", idx, title)))
+            });
+        }
+    }
+    mux.notify_output();
+}
+
+/// Returns true if the visual bell of `window_id` should be shown
+pub fn visual_bell_active(window_id: WindowId) -> bool {
+    VISUAL.with(|visual| match visual.borrow().get(&window_id) {
+        Some(bell) => bell.started.elapsed() < visual_bell_duration(),
+        None => false,
+    })
+}
+
+/// Returns true if the window needs to be painted because its visual
+/// bell has started or finished since the last call
+pub fn take_visual_bell_change(window_id: WindowId) -> bool {
+    VISUAL.with(|visual| {
+        let mut visual = visual.borrow_mut();
+        let expired = match visual.get_mut(&window_id) {
+            None => return false,
+            Some(bell) if bell.started.elapsed() >= visual_bell_duration() => true,
+            Some(bell) => {
+                let changed = !bell.painted;
+                bell.painted = true;
+                return changed;
+            }
+        };
+        if expired {
+            visual.remove(&window_id);
+        }
+        expired
+    })
+}
+
+/// Removes and returns the bell notifications for `window_id`
+pub fn take_notifications(window_id: WindowId) -> Vec<String> {
+    NOTIFICATIONS.with(|n| {
+        let mut n = n.borrow_mut();
+        let (mine, others): (Vec<_>, Vec<_>) = n.drain(..).partition(|(id, _)| *id == window_id);
+        *n = others;
+        mine.into_iter().map(|(_, message)| message).collect()
+    })
+}
+
+/// Discards the bell state of a tab that has been removed
+pub fn forget(tab_id: TabId) {
+    DEBOUNCER.with(|d| {
+        d.borrow_mut().last_rung.remove(&tab_id);
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn debounce() {
+        let mut debouncer = Debouncer::default();
+        let interval = Duration::from_millis(100);
+        let start = Instant::now();
+
+        assert!(debouncer.accept(1, start, interval));
+        assert!(!debouncer.accept(1, start + Duration::from_millis(50), interval));
+        // Each tab is debounced separately
+        assert!(debouncer.accept(2, start + Duration::from_millis(50), interval));
+        // The interval is measured from the last bell that took effect
+        assert!(debouncer.accept(1, start + Duration::from_millis(100), interval));
+        assert!(!debouncer.accept(1, start + Duration::from_millis(150), interval));
+
+        // A zero interval allows every bell
+        assert!(debouncer.accept(3, start, Duration::from_millis(0)));
+        assert!(debouncer.accept(3, start, Duration::from_millis(0)));
+    }
+}
@@ -0,0 +1,251 @@
+//! Optional instrumentation of keyboard latency.  When the
+//! `input_latency_instrumentation` configuration is enabled, a key
+//! press is timestamped as it is received from the windowing system,
+//! when it has been written to the pty, when the first output that
+//! follows it is read back from the pty, when that output has been
+//! parsed and finally when it has been painted.  The durations of
+//! each of those stages are accumulated into per-tab histograms that
+//! are shown by the tab inspector, so that regressions in latency
+//! can be measured across changes to the renderer.
+use crate::mux::tab::TabId;
+use crate::mux::Mux;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// The upper bounds of the histogram buckets, in milliseconds.
+/// Samples larger than the last bound are counted in an extra bucket.
+const BUCKETS: [u64; 8] = [1, 2, 4, 8, 16, 32, 64, 128];
+const NUM_BUCKETS: usize = 9;
+
+/// A key press that doesn't produce any output within this time is
+/// abandoned rather than being matched up with unrelated output
+const STALE_SECS: u64 = 1;
+
+#[derive(Default, Clone)]
+struct Histogram {
+    counts: [usize; NUM_BUCKETS],
+    total: Duration,
+    max: Duration,
+}
+
+fn as_millis(d: Duration) -> f64 {
+    d.as_secs() as f64 * 1000.0 + f64::from(d.subsec_nanos()) / 1_000_000.0
+}
+
+impl Histogram {
+    fn record(&mut self, d: Duration) {
+        let ms = as_millis(d);
+        let idx = BUCKETS
+            .iter()
+            .position(|&bound| ms < bound as f64)
+            .unwrap_or(BUCKETS.len());
+        self.counts[idx] += 1;
+        self.total += d;
+        self.max = self.max.max(d);
+    }
+
+    fn count(&self) -> usize {
+        self.counts.iter().sum()
+    }
+
+    fn summarize(&self, label: &str) -> Vec<String> {
+        let count = self.count();
+        if count == 0 {
+            return vec![format!("{}: no samples", label)];
+        }
+        let mut lines = vec![format!(
+            "{}: {} samples, mean {:.2}ms, max {:.2}ms",
+            label,
+            count,
+            as_millis(self.total) / count as f64,
+            as_millis(self.max)
+        )];
+        let widest = *self.counts.iter().max().unwrap_or(&1);
+        let mut lower = 0;
+        for (idx, &n) in self.counts.iter().enumerate() {
+            let range = match BUCKETS.get(idx) {
+                Some(&upper) => format!("{:>3}-{:<3}ms", lower, upper),
+                None => format!("{:>3}+     ", lower),
+            };
+            lower = BUCKETS.get(idx).cloned().unwrap_or(lower);
+            if n == 0 {
+                continue;
+            }
+            let bar = "#".repeat((n * 40 + widest - 1) / widest);
+            lines.push(format!("  {} {:>6} {}", range, n, bar));
+        }
+        lines
+    }
+}
+
+/// The timestamps of a key press that is in flight
+struct Sample {
+    received: Instant,
+    written: Instant,
+    echoed: Option<Instant>,
+    parsed: Option<Instant>,
+}
+
+#[derive(Default)]
+struct TabLatency {
+    pending: Option<Sample>,
+    write: Histogram,
+    echo: Histogram,
+    parse: Histogram,
+    paint: Histogram,
+    total: Histogram,
+}
+
+thread_local! {
+    static RECEIVED: RefCell<Option<Instant>> = RefCell::new(None);
+    static TABS: RefCell<HashMap<TabId, TabLatency>> = RefCell::new(HashMap::new());
+}
+
+fn enabled() -> bool {
+    Mux::get()
+        .map(|mux| mux.config().input_latency_instrumentation)
+        .unwrap_or(false)
+}
+
+/// Called by the frontend just before a key event that was received
+/// from the windowing system at `at` is passed to the tab.  Events
+/// that are consumed by the gui, such as shortcuts and key releases,
+/// are not recorded, so that they cannot be paired with a later write.
+pub fn key_received(at: Instant) {
+    if enabled() {
+        RECEIVED.with(|received| *received.borrow_mut() = Some(at));
+    }
+}
+
+/// Called once the key that was most recently passed to
+/// `key_received` has been written to the pty of `tab_id`
+pub fn key_written(tab_id: TabId) {
+    let received = match RECEIVED.with(|received| received.borrow_mut().take()) {
+        Some(received) => received,
+        None => return,
+    };
+    let now = Instant::now();
+    TABS.with(|tabs| {
+        let mut tabs = tabs.borrow_mut();
+        let tab = tabs.entry(tab_id).or_insert_with(TabLatency::default);
+        // While typing quickly, only the first key of a burst is
+        // measured; its echo is the first output to arrive
+        let busy = match tab.pending.as_ref() {
+            Some(sample) => now.duration_since(sample.written) < Duration::from_secs(STALE_SECS),
+            None => false,
+        };
+        if !busy {
+            tab.pending = Some(Sample {
+                received,
+                written: now,
+                echoed: None,
+                parsed: None,
+            });
+        }
+    });
+}
+
+/// Called when output that was read from the pty of `tab_id` at
+/// `read_at` is about to be parsed
+pub fn output_received(tab_id: TabId, read_at: Instant) {
+    TABS.with(|tabs| {
+        if let Some(tab) = tabs.borrow_mut().get_mut(&tab_id) {
+            if let Some(sample) = tab.pending.as_mut() {
+                if sample.echoed.is_none() && read_at >= sample.written {
+                    sample.echoed = Some(read_at);
+                }
+            }
+        }
+    });
+}
+
+/// Called after output for `tab_id` has been parsed
+pub fn output_parsed(tab_id: TabId) {
+    TABS.with(|tabs| {
+        if let Some(tab) = tabs.borrow_mut().get_mut(&tab_id) {
+            if let Some(sample) = tab.pending.as_mut() {
+                if sample.echoed.is_some() && sample.parsed.is_none() {
+                    sample.parsed = Some(Instant::now());
+                }
+            }
+        }
+    });
+}
+
+/// Called after `tab_id` has been painted; completes the measurement
+/// of a key press whose echo has been parsed
+pub fn painted(tab_id: TabId) {
+    TABS.with(|tabs| {
+        let mut tabs = tabs.borrow_mut();
+        let tab = match tabs.get_mut(&tab_id) {
+            Some(tab) => tab,
+            None => return,
+        };
+        let (echoed, parsed) = match tab.pending.as_ref() {
+            Some(Sample {
+                echoed: Some(echoed),
+                parsed: Some(parsed),
+                ..
+            }) => (*echoed, *parsed),
+            _ => return,
+        };
+        let sample = tab.pending.take().unwrap();
+        let now = Instant::now();
+        tab.write
+            .record(sample.written.duration_since(sample.received));
+        tab.echo.record(echoed.duration_since(sample.written));
+        tab.parse.record(parsed.duration_since(echoed));
+        tab.paint.record(now.duration_since(parsed));
+        tab.total.record(now.duration_since(sample.received));
+    });
+}
+
+/// Discard the measurements for a tab that has been closed
+pub fn forget(tab_id: TabId) {
+    TABS.with(|tabs| {
+        tabs.borrow_mut().remove(&tab_id);
+    });
+}
+
+/// Produce a report of the measurements for `tab_id`, suitable for
+/// the tab inspector
+pub fn report(tab_id: TabId) -> Vec<String> {
+    if !enabled() {
+        return vec!["Set input_latency_instrumentation = true to measure".to_string()];
+    }
+    TABS.with(|tabs| {
+        let tabs = tabs.borrow();
+        let tab = match tabs.get(&tab_id) {
+            Some(tab) => tab,
+            None => return vec!["no key presses have been measured".to_string()],
+        };
+        let mut lines = vec![];
+        lines.append(&mut tab.write.summarize("key event to pty write"));
+        lines.append(&mut tab.echo.summarize("pty write to echo"));
+        lines.append(&mut tab.parse.summarize("echo to parsed"));
+        lines.append(&mut tab.paint.summarize("parsed to painted"));
+        lines.append(&mut tab.total.summarize("key event to painted"));
+        lines
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn histogram() {
+        let mut hist = Histogram::default();
+        hist.record(Duration::from_micros(400));
+        hist.record(Duration::from_millis(3));
+        hist.record(Duration::from_millis(3));
+        hist.record(Duration::from_millis(500));
+        assert_eq!(hist.counts, [1, 0, 2, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(hist.max, Duration::from_millis(500));
+
+        let lines = hist.summarize("test");
+        assert_eq!(lines[0], "test: 4 samples, mean 126.60ms, max 500.00ms");
+        assert_eq!(lines.len(), 4);
+    }
+}
@@ -1,30 +1,84 @@
 use crate::config::Config;
 use crate::pty::ExitStatus;
+use boxfnonce::BoxFnOnce;
 use failure::Error;
-use promise::{Executor, Future};
-use std::cell::{Ref, RefCell, RefMut};
+use promise::task::spawn_into_gui;
+use std::cell::{Cell, Ref, RefCell, RefMut};
 use std::collections::HashMap;
-use std::io::Read;
+use std::io::{Read, Write};
 use std::rc::Rc;
 use std::sync::Arc;
 use std::thread;
+use std::time::Instant;
 use term::TerminalHost;
 use termwiz::hyperlink::Hyperlink;
 
+pub mod accessibility;
+pub mod bell;
+pub mod latency;
+#[cfg(unix)]
+pub mod ptypoll;
 pub mod renderable;
+pub mod renderfilter;
 pub mod tab;
+pub mod transfer;
 pub mod window;
+pub mod zmodem;
 
+use crate::mux::renderable::Renderable;
 use crate::mux::tab::{Tab, TabId};
-use crate::mux::window::{Window, WindowId};
+use crate::mux::window::{alloc_window_id, Window, WindowId};
 
 pub struct Mux {
     tabs: RefCell<HashMap<TabId, Rc<Tab>>>,
     windows: RefCell<HashMap<WindowId, Window>>,
     config: Arc<Config>,
+    /// Set when a tab has parsed output that has yet to be painted
+    output_pending: Cell<bool>,
+    /// The exit status of the most recently terminated tab
+    last_exit_status: Cell<Option<ExitStatus>>,
+    /// The window that has the keyboard focus, if any
+    focused_window: Cell<Option<WindowId>>,
+    /// Called by `shutdown`
+    shutdown_hooks: RefCell<Vec<BoxFnOnce<'static, ()>>>,
+    /// The ids of the windows that tabs were spawned to be shown in,
+    /// for the tabs whose windows have yet to be created
+    reserved_windows: RefCell<HashMap<TabId, WindowId>>,
+    /// The path of the socket that the mux accepts requests on
+    socket_path: RefCell<Option<String>>,
 }
 
-fn read_from_tab_pty(executor: Box<Executor>, tab_id: TabId, mut reader: Box<std::io::Read>) {
+/// Called on the thread that read `data` from the pty of `tab_id`,
+/// to have it parsed on the gui thread
+fn output_received(tab_id: TabId, data: Vec<u8>, read_at: Instant) {
+    spawn_into_gui(move || {
+        let mux = Mux::get().unwrap();
+        if let Some(tab) = mux.get_tab(tab_id) {
+            latency::output_received(tab_id, read_at);
+            let data = zmodem::filter_output(tab_id, data, &mut *tab.writer());
+            tab.advance_bytes(
+                &data,
+                &mut Host {
+                    writer: &mut *tab.writer(),
+                    tab_id,
+                },
+            );
+            latency::output_parsed(tab_id);
+        }
+        Ok(())
+    });
+}
+
+/// Called when the output of `tab_id` reaches EOF, or fails
+fn output_closed(tab_id: TabId) {
+    spawn_into_gui(move || {
+        let mux = Mux::get().unwrap();
+        mux.remove_tab(tab_id);
+        Ok(())
+    });
+}
+
+fn read_from_tab_pty<R: std::io::Read>(tab_id: TabId, mut reader: R) {
     const BUFSIZE: usize = 32 * 1024;
     let mut buf = [0; BUFSIZE];
     loop {
@@ -37,28 +91,10 @@ fn read_from_tab_pty(executor: Box<Executor>, tab_id: TabId, mut reader: Box<std
                 eprintln!("read_pty failed: tab {} {:?}", tab_id, err);
                 break;
             }
-            Ok(size) => {
-                let data = buf[0..size].to_vec();
-                Future::with_executor(executor.clone_executor(), move || {
-                    let mux = Mux::get().unwrap();
-                    if let Some(tab) = mux.get_tab(tab_id) {
-                        tab.advance_bytes(
-                            &data,
-                            &mut Host {
-                                writer: &mut *tab.writer(),
-                            },
-                        );
-                    }
-                    Ok(())
-                });
-            }
+            Ok(size) => output_received(tab_id, buf[0..size].to_vec(), Instant::now()),
         }
     }
-    Future::with_executor(executor.clone_executor(), move || {
-        let mux = Mux::get().unwrap();
-        mux.remove_tab(tab_id);
-        Ok(())
-    });
+    output_closed(tab_id);
 }
 
 /// This is just a stub impl of TerminalHost; it really only exists
@@ -67,6 +103,7 @@ fn read_from_tab_pty(executor: Box<Executor>, tab_id: TabId, mut reader: Box<std
 /// The GUI driven flows provide their own impl of TerminalHost.
 struct Host<'a> {
     writer: &'a mut std::io::Write,
+    tab_id: TabId,
 }
 
 impl<'a> TerminalHost for Host<'a> {
@@ -90,7 +127,24 @@ impl<'a> TerminalHost for Host<'a> {
         Ok(())
     }
 
-    fn set_title(&mut self, _title: &str) {}
+    fn set_title(&mut self, _title: &str) {
+        // The title is shown in the tab bar and window title
+        self.screen_changed();
+    }
+
+    fn ring_bell(&mut self) {
+        bell::ring(self.tab_id);
+    }
+
+    fn save_file(&mut self, name: Option<&str>, data: &[u8]) {
+        transfer::offer(self.tab_id, name, data);
+    }
+
+    fn screen_changed(&mut self) {
+        if let Some(mux) = Mux::get() {
+            mux.notify_output();
+        }
+    }
 }
 
 thread_local! {
@@ -103,6 +157,12 @@ impl Mux {
             tabs: RefCell::new(HashMap::new()),
             windows: RefCell::new(HashMap::new()),
             config: Arc::clone(config),
+            output_pending: Cell::new(false),
+            last_exit_status: Cell::new(None),
+            focused_window: Cell::new(None),
+            shutdown_hooks: RefCell::new(vec![]),
+            reserved_windows: RefCell::new(HashMap::new()),
+            socket_path: RefCell::new(None),
         }
     }
 
@@ -110,6 +170,17 @@ impl Mux {
         &self.config
     }
 
+    /// Records that requests are accepted on the socket at `path`
+    pub fn set_socket_path(&self, path: &str) {
+        *self.socket_path.borrow_mut() = Some(path.to_owned());
+    }
+
+    /// Returns the path of the socket that requests are accepted on,
+    /// if the mux is listening
+    pub fn socket_path(&self) -> Option<String> {
+        self.socket_path.borrow().clone()
+    }
+
     pub fn set_mux(mux: &Rc<Mux>) {
         MUX.with(|m| {
             *m.borrow_mut() = Some(Rc::clone(mux));
@@ -126,23 +197,99 @@ impl Mux {
         res
     }
 
+    /// Called when something that is shown has changed, such as the
+    /// screen of a tab after its output has been parsed, so that the
+    /// frontend knows to schedule a repaint
+    pub fn notify_output(&self) {
+        self.output_pending.set(true);
+    }
+
+    /// Input events can change what is shown without producing any
+    /// output, for example by changing the selection or scrolling the
+    /// viewport.  Frontends call this after handling such events to
+    /// schedule a repaint if `renderer` has changed.
+    pub fn notify_if_dirty(&self, renderer: &Renderable) {
+        if renderer.has_dirty_lines() {
+            self.notify_output();
+        }
+    }
+
+    /// Returns true if output has arrived since the last call
+    pub fn take_output_pending(&self) -> bool {
+        self.output_pending.replace(false)
+    }
+
     pub fn get_tab(&self, tab_id: TabId) -> Option<Rc<Tab>> {
         self.tabs.borrow().get(&tab_id).map(Rc::clone)
     }
 
-    pub fn add_tab(&self, executor: Box<Executor>, tab: &Rc<Tab>) -> Result<(), Error> {
+    pub fn add_tab(&self, tab: &Rc<Tab>) -> Result<(), Error> {
         self.tabs.borrow_mut().insert(tab.tab_id(), Rc::clone(tab));
 
-        let reader = tab.reader()?;
         let tab_id = tab.tab_id();
-        thread::spawn(move || read_from_tab_pty(executor, tab_id, reader));
+
+        // Prefer to have the shared poller read the output, falling
+        // back to a thread of its own for tabs that can't be polled
+        #[cfg(unix)]
+        {
+            if let Ok(reader) = tab.nonblocking_reader() {
+                match ptypoll::register(tab_id, reader) {
+                    Ok(()) => return Ok(()),
+                    Err(err) => eprintln!("unable to poll tab {}: {}", tab_id, err),
+                }
+            }
+        }
+
+        let reader = tab.reader()?;
+        thread::spawn(move || read_from_tab_pty(tab_id, reader));
 
         Ok(())
     }
 
     pub fn remove_tab(&self, tab_id: TabId) {
         eprintln!("removing tab {}", tab_id);
-        self.tabs.borrow_mut().remove(&tab_id);
+        let tab = self.tabs.borrow_mut().remove(&tab_id);
+        self.reserved_windows.borrow_mut().remove(&tab_id);
+        latency::forget(tab_id);
+        renderfilter::forget(tab_id);
+        bell::forget(tab_id);
+        transfer::forget(tab_id);
+        zmodem::forget(tab_id);
+        if let Some(tab) = tab {
+            self.record_exit_status(&*tab);
+        }
+        // Another tab is shown in its place
+        self.notify_output();
+    }
+
+    /// Remember the exit status of the process in `tab`, if it has
+    /// exited, so that it can be reported when wezterm exits.
+    /// The output of a tab can reach EOF slightly before its process
+    /// can be reaped, so this is also called when a window notices
+    /// that a tab is dead.
+    pub fn record_exit_status(&self, tab: &Tab) {
+        if let Some(status) = tab.exit_status() {
+            self.last_exit_status.set(Some(status));
+        }
+    }
+
+    /// Returns the exit status of the most recently terminated tab
+    pub fn last_exit_status(&self) -> Option<ExitStatus> {
+        self.last_exit_status.get()
+    }
+
+    /// Records that `window_id` has gained or lost the keyboard focus
+    pub fn set_window_focused(&self, window_id: WindowId, focused: bool) {
+        if focused {
+            self.focused_window.set(Some(window_id));
+        } else if self.focused_window.get() == Some(window_id) {
+            self.focused_window.set(None);
+        }
+    }
+
+    /// Returns the window that has the keyboard focus, if any
+    pub fn focused_window(&self) -> Option<WindowId> {
+        self.focused_window.get()
     }
 
     pub fn get_window(&self, window_id: WindowId) -> Option<Ref<Window>> {
@@ -168,13 +315,81 @@ impl Mux {
         window.get_active().map(Rc::clone)
     }
 
+    /// Allocates the id of the window that is going to be created to
+    /// show `tab_id`, so that it is known before the tab is spawned
+    pub fn reserve_window_id(&self, tab_id: TabId) -> WindowId {
+        let window_id = alloc_window_id();
+        self.reserved_windows.borrow_mut().insert(tab_id, window_id);
+        window_id
+    }
+
+    /// Forgets the window id reserved for `tab_id`, for a tab that
+    /// isn't going to be given a window of its own after all
+    pub fn release_window_id(&self, tab_id: TabId) {
+        self.reserved_windows.borrow_mut().remove(&tab_id);
+    }
+
     pub fn add_new_window_with_tab(&self, tab: &Rc<Tab>) -> Result<WindowId, Error> {
-        let window = Window::new(tab);
+        let window_id = self
+            .reserved_windows
+            .borrow_mut()
+            .remove(&tab.tab_id())
+            .unwrap_or_else(alloc_window_id);
+        let window = Window::new(window_id, tab);
         let window_id = window.window_id();
         self.windows.borrow_mut().insert(window_id, window);
         Ok(window_id)
     }
 
+    /// Returns the ids of all windows, in the order that they were created
+    pub fn window_ids(&self) -> Vec<WindowId> {
+        let mut ids: Vec<WindowId> = self.windows.borrow().keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    /// Returns the id of the window that holds the specified tab
+    pub fn window_containing_tab(&self, tab_id: TabId) -> Option<WindowId> {
+        self.windows
+            .borrow()
+            .iter()
+            .find(|(_, window)| window.idx_by_id(tab_id).is_some())
+            .map(|(window_id, _)| *window_id)
+    }
+
+    /// Removes a tab from the window that holds it without terminating
+    /// it, so that it can be placed into a different window
+    pub fn detach_tab(&self, tab_id: TabId) -> Result<(), Error> {
+        let window_id = self
+            .window_containing_tab(tab_id)
+            .ok_or_else(|| format_err!("tab {} is not in a window", tab_id))?;
+        let mut window = self
+            .get_window_mut(window_id)
+            .ok_or_else(|| format_err!("no such window {}", window_id))?;
+        window.remove_by_id(tab_id);
+        if let Some(active) = window.get_active() {
+            active.renderer().make_all_lines_dirty();
+        }
+        Ok(())
+    }
+
+    /// Moves a tab into the specified window and makes it the active
+    /// tab of that window
+    pub fn move_tab(&self, tab_id: TabId, dest: WindowId) -> Result<(), Error> {
+        let tab = self
+            .get_tab(tab_id)
+            .ok_or_else(|| format_err!("no such tab {}", tab_id))?;
+        if !self.windows.borrow().contains_key(&dest) {
+            bail!("no such window {}", dest);
+        }
+        self.detach_tab(tab_id)?;
+        let mut window = self.get_window_mut(dest).unwrap();
+        window.push(&tab);
+        let idx = window.len() - 1;
+        window.set_active(idx);
+        Ok(())
+    }
+
     #[allow(dead_code)]
     pub fn is_empty(&self) -> bool {
         self.tabs.borrow().is_empty()
@@ -187,6 +402,30 @@ impl Mux {
             .map(|(_, v)| Rc::clone(v))
             .collect()
     }
+
+    /// Arrange for `func` to be called when wezterm shuts down, so
+    /// that it can flush or close resources that would otherwise be
+    /// left in an inconsistent state.  Hooks are called in the reverse
+    /// of the order in which they were added.
+    pub fn add_shutdown_hook<F: FnOnce() + 'static>(&self, func: F) {
+        self.shutdown_hooks.borrow_mut().push(BoxFnOnce::from(func));
+    }
+
+    /// Flush the input that is pending for each tab and run the
+    /// shutdown hooks.  This is called when the frontend has finished
+    /// running and when a termination signal is received; calling it
+    /// more than once is harmless.
+    pub fn shutdown(&self) {
+        for tab in self.iter_tabs() {
+            if let Err(err) = tab.writer().flush() {
+                eprintln!("failed to flush tab {}: {}", tab.tab_id(), err);
+            }
+        }
+        let hooks: Vec<_> = self.shutdown_hooks.borrow_mut().drain(..).collect();
+        for hook in hooks.into_iter().rev() {
+            hook.call();
+        }
+    }
 }
 
 #[derive(Debug, Fail)]
@@ -199,3 +438,67 @@ pub enum SessionTerminated {
     #[fail(display = "Window Closed")]
     WindowClosed,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::frontend::guicommon::framepacer::FramePacer;
+    use crate::frontend::guicommon::overlay::OverlayHost;
+    use term::{KeyModifiers, MouseButton, MouseEvent, MouseEventKind, Terminal};
+
+    #[test]
+    fn selection_schedules_frame() {
+        let mux = Mux::new(&Arc::new(Config::default()));
+        let pacer = FramePacer::new(10);
+        let mut terminal = Terminal::new(3, 10, 0, vec![]);
+        let mut host = OverlayHost::default();
+        terminal.advance_bytes(b"hello", &mut host);
+        terminal.clean_dirty_lines();
+        mux.take_output_pending();
+
+        let event = |kind, x, button| MouseEvent {
+            kind,
+            x,
+            y: 0,
+            x_pixel: 0,
+            y_pixel: 0,
+            button,
+            modifiers: KeyModifiers::default(),
+        };
+
+        // Moving the mouse over plain text changes nothing
+        terminal
+            .mouse_event(event(MouseEventKind::Move, 1, MouseButton::None), &mut host)
+            .unwrap();
+        mux.notify_if_dirty(&terminal);
+        assert!(!mux.take_output_pending());
+
+        // Dragging out a selection must be painted
+        terminal
+            .mouse_event(
+                event(MouseEventKind::Press, 0, MouseButton::Left),
+                &mut host,
+            )
+            .unwrap();
+        terminal
+            .mouse_event(event(MouseEventKind::Move, 3, MouseButton::None), &mut host)
+            .unwrap();
+        mux.notify_if_dirty(&terminal);
+        if mux.take_output_pending() {
+            pacer.request_frame();
+        }
+        assert!(pacer.take_due_frame());
+    }
+
+    #[test]
+    fn release_reserved_window() {
+        let mux = Mux::new(&Arc::new(Config::default()));
+        let reserved = mux.reserve_window_id(1);
+        assert_eq!(mux.reserved_windows.borrow().get(&1), Some(&reserved));
+
+        mux.release_window_id(1);
+        assert!(mux.reserved_windows.borrow().is_empty());
+        // The id isn't handed out again
+        assert_ne!(mux.reserve_window_id(2), reserved);
+    }
+}
@@ -0,0 +1,333 @@
+//! Waits upon the output of every tab that can provide a non-blocking
+//! reader from a single thread, rather than dedicating a thread that
+//! blocks in read to each tab, which adds up when many tabs are open.
+use crate::mux::tab::TabId;
+use crate::pty::unix::{clear_nonblocking, cloexec, set_nonblocking};
+use crate::pty::NonBlockingReader;
+use failure::Error;
+use lazy_static::lazy_static;
+use std::fs::File;
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// The amount that is read from a tab at once
+const BUFSIZE: usize = 32 * 1024;
+/// The number of times that a tab is read from before moving on to
+/// the others, so that a tab producing a flood of output can't keep
+/// the rest waiting
+const MAX_READS_PER_WAKE: usize = 4;
+
+/// What the poller observed when reading from a tab
+enum PollEvent {
+    Output(Vec<u8>),
+    Closed,
+    /// The tab's descriptor can't be polled (macOS reports ptys as
+    /// `POLLNVAL`), so its reader is handed back to be read from a
+    /// thread of its own
+    Unpollable(Box<NonBlockingReader>),
+}
+
+struct PollerHandle {
+    sender: Sender<(TabId, Box<NonBlockingReader>)>,
+    /// Written to in order to wake the poller when a tab is added
+    wake: File,
+}
+
+lazy_static! {
+    static ref POLLER: Mutex<Option<PollerHandle>> = Mutex::new(None);
+}
+
+/// Arranges for the output of `tab_id` to be read from `reader` by
+/// the poller thread, starting the thread if it isn't yet running.
+pub fn register(tab_id: TabId, reader: Box<NonBlockingReader>) -> Result<(), Error> {
+    let mut poller = POLLER.lock().unwrap();
+    if poller.is_none() {
+        *poller = Some(spawn_poller()?);
+    }
+    let handle = poller.as_mut().unwrap();
+    handle
+        .sender
+        .send((tab_id, reader))
+        .map_err(|_| format_err!("the pty poller has stopped"))?;
+    handle.wake.write_all(b"x")?;
+    Ok(())
+}
+
+/// Returns the read and write ends of a pipe; the read end is
+/// non-blocking
+fn pipe() -> Result<(File, File), Error> {
+    let mut fds = [-1; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        bail!("pipe failed: {:?}", std::io::Error::last_os_error());
+    }
+    let (read, write) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+    cloexec(read.as_raw_fd())?;
+    cloexec(write.as_raw_fd())?;
+    set_nonblocking(read.as_raw_fd())?;
+    Ok((read, write))
+}
+
+fn spawn_poller() -> Result<PollerHandle, Error> {
+    let (wake_read, wake) = pipe()?;
+    let (sender, receiver) = channel();
+    std::thread::Builder::new()
+        .name("pty-poller".into())
+        .spawn(move || {
+            poll_tabs(wake_read, receiver, |tab_id, event| match event {
+                PollEvent::Output(data) => super::output_received(tab_id, data, Instant::now()),
+                PollEvent::Closed => super::output_closed(tab_id),
+                PollEvent::Unpollable(reader) => read_from_thread(tab_id, reader),
+            })
+        })?;
+    Ok(PollerHandle { sender, wake })
+}
+
+/// Falls back to reading `reader` from a thread that blocks in read,
+/// as is done for tabs that have no non-blocking reader
+fn read_from_thread(tab_id: TabId, reader: Box<NonBlockingReader>) {
+    eprintln!("tab {} can't be polled; reading it from a thread", tab_id);
+    if let Err(err) = clear_nonblocking(reader.pollable_fd()) {
+        eprintln!("read_pty failed: tab {} {:?}", tab_id, err);
+        super::output_closed(tab_id);
+        return;
+    }
+    std::thread::spawn(move || super::read_from_tab_pty(tab_id, reader));
+}
+
+/// Reads from the tabs that are sent via `receiver`, passing what
+/// is read to `deliver`.  Writing to the other end of `wake` causes
+/// newly sent tabs to be picked up.  Returns once the other end of
+/// `wake` has been closed and all of the tabs have been closed.
+fn poll_tabs<F: FnMut(TabId, PollEvent)>(
+    mut wake: File,
+    receiver: Receiver<(TabId, Box<NonBlockingReader>)>,
+    mut deliver: F,
+) {
+    let mut tabs: Vec<(TabId, Box<NonBlockingReader>)> = vec![];
+    let mut buf = [0; BUFSIZE];
+    // Whether more tabs may yet be sent to us
+    let mut accepting = true;
+    loop {
+        if !accepting && tabs.is_empty() {
+            return;
+        }
+        let first_tab = if accepting { 1 } else { 0 };
+        let mut pfds: Vec<libc::pollfd> = Some(wake.as_raw_fd())
+            .filter(|_| accepting)
+            .into_iter()
+            .chain(tabs.iter().map(|(_, reader)| reader.pollable_fd()))
+            .map(|fd| libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0,
+            })
+            .collect();
+
+        if unsafe { libc::poll(pfds.as_mut_ptr(), pfds.len() as _, -1) } == -1 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == ErrorKind::Interrupted {
+                continue;
+            }
+            eprintln!("pty poller failed: {:?}", err);
+            return;
+        }
+
+        // Service the tabs before adding new ones, so that the
+        // indices of `pfds` and `tabs` still correspond
+        let mut closed = vec![];
+        let mut unpollable = vec![];
+        for (idx, (tab_id, reader)) in tabs.iter_mut().enumerate() {
+            let revents = pfds[idx + first_tab].revents;
+            if revents == 0 {
+                continue;
+            }
+            if revents & libc::POLLNVAL != 0 {
+                unpollable.push(idx);
+                continue;
+            }
+            // Once the other end has hung up, running out of data
+            // means that there will be no more
+            let hangup = revents & (libc::POLLHUP | libc::POLLERR) != 0;
+            for _ in 0..MAX_READS_PER_WAKE {
+                match reader.read(&mut buf) {
+                    Ok(size) if size == 0 => {
+                        eprintln!("read_pty EOF: tab_id {}", tab_id);
+                        closed.push(idx);
+                        break;
+                    }
+                    Ok(size) => deliver(*tab_id, PollEvent::Output(buf[0..size].to_vec())),
+                    Err(ref err) if err.kind() == ErrorKind::WouldBlock => {
+                        if hangup {
+                            eprintln!("read_pty hangup: tab_id {}", tab_id);
+                            closed.push(idx);
+                        }
+                        break;
+                    }
+                    Err(ref err) if err.kind() == ErrorKind::Interrupted => {}
+                    Err(err) => {
+                        eprintln!("read_pty failed: tab {} {:?}", tab_id, err);
+                        closed.push(idx);
+                        break;
+                    }
+                }
+            }
+        }
+        let mut removed: Vec<(usize, bool)> = closed
+            .into_iter()
+            .map(|idx| (idx, true))
+            .chain(unpollable.into_iter().map(|idx| (idx, false)))
+            .collect();
+        removed.sort();
+        for (idx, closed) in removed.into_iter().rev() {
+            let (tab_id, reader) = tabs.remove(idx);
+            if closed {
+                deliver(tab_id, PollEvent::Closed);
+            } else {
+                deliver(tab_id, PollEvent::Unpollable(reader));
+            }
+        }
+
+        if accepting && pfds[0].revents != 0 {
+            let mut drain = [0u8; 64];
+            while let Ok(size) = wake.read(&mut drain) {
+                if size == 0 {
+                    accepting = false;
+                    break;
+                }
+            }
+            tabs.extend(receiver.try_iter());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::os::unix::io::RawFd;
+    use std::sync::mpsc::RecvTimeoutError;
+    use std::time::Duration;
+
+    /// A descriptor far beyond any that the tests have open, which
+    /// poll reports as `POLLNVAL`
+    const UNOPENED_FD: RawFd = 100_000;
+
+    struct PipeReader {
+        file: File,
+        pollable: bool,
+    }
+
+    impl Read for PipeReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.file.read(buf)
+        }
+    }
+
+    impl NonBlockingReader for PipeReader {
+        fn pollable_fd(&self) -> RawFd {
+            if self.pollable {
+                self.file.as_raw_fd()
+            } else {
+                UNOPENED_FD
+            }
+        }
+    }
+
+    /// What the tests saw delivered; the readers of unpollable tabs
+    /// are read to the end so that they can be compared
+    #[derive(Debug, PartialEq)]
+    enum Seen {
+        Output(Vec<u8>),
+        Closed,
+        Unpollable(Vec<u8>),
+    }
+
+    struct Poller {
+        wake: File,
+        sender: Sender<(TabId, Box<NonBlockingReader>)>,
+        events: Receiver<(TabId, Seen)>,
+        thread: std::thread::JoinHandle<()>,
+    }
+
+    impl Poller {
+        fn start() -> Self {
+            let (wake_read, wake) = pipe().unwrap();
+            let (sender, receiver) = channel();
+            let (events_tx, events) = channel();
+            let thread = std::thread::spawn(move || {
+                poll_tabs(wake_read, receiver, |tab_id, event| {
+                    let seen = match event {
+                        PollEvent::Output(data) => Seen::Output(data),
+                        PollEvent::Closed => Seen::Closed,
+                        PollEvent::Unpollable(mut reader) => {
+                            let mut data = vec![];
+                            reader.read_to_end(&mut data).unwrap();
+                            Seen::Unpollable(data)
+                        }
+                    };
+                    events_tx.send((tab_id, seen)).unwrap()
+                })
+            });
+            Self {
+                wake,
+                sender,
+                events,
+                thread,
+            }
+        }
+
+        fn add(&mut self, tab_id: TabId, file: File, pollable: bool) {
+            let reader: Box<NonBlockingReader> = Box::new(PipeReader { file, pollable });
+            self.sender.send((tab_id, reader)).unwrap();
+            self.wake.write_all(b"x").unwrap();
+        }
+
+        fn next(&self) -> Result<(TabId, Seen), RecvTimeoutError> {
+            self.events.recv_timeout(Duration::from_secs(10))
+        }
+
+        /// With no tabs left and no way to send more, the poller stops
+        fn stop(self) {
+            drop(self.wake);
+            self.thread.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn output_and_eof() {
+        let mut poller = Poller::start();
+        let (read, mut write) = pipe().unwrap();
+        poller.add(1, read, true);
+
+        write.write_all(b"hello").unwrap();
+        assert_eq!(poller.next().unwrap(), (1, Seen::Output(b"hello".to_vec())));
+
+        drop(write);
+        assert_eq!(poller.next().unwrap(), (1, Seen::Closed));
+
+        poller.stop();
+    }
+
+    #[test]
+    fn unpollable_is_handed_back() {
+        let mut poller = Poller::start();
+        let (read, mut write) = pipe().unwrap();
+        write.write_all(b"hello").unwrap();
+        drop(write);
+        poller.add(1, read, false);
+
+        assert_eq!(
+            poller.next().unwrap(),
+            (1, Seen::Unpollable(b"hello".to_vec()))
+        );
+        // Having been handed back, nothing more is read from it
+        assert_eq!(
+            poller.events.try_recv(),
+            Err(std::sync::mpsc::TryRecvError::Empty)
+        );
+
+        poller.stop();
+    }
+}
@@ -1,6 +1,11 @@
+use failure::Error;
 use std::ops::Range;
 use std::sync::Arc;
-use term::{CursorPosition, Line, Terminal, TerminalState};
+use std::time::Duration;
+use term::{
+    CursorPosition, HighlightSet, Line, LineHighlight, Pattern, SearchResult, SemanticObject,
+    StableRowIndex, Terminal, TerminalState, VisibleHyperlink, VisibleRowIndex,
+};
 use termwiz::hyperlink::Hyperlink;
 
 /// Renderable allows passing something that isn't an actual term::Terminal
@@ -18,6 +23,23 @@ pub trait Renderable {
     /// columns on this line.
     fn get_dirty_lines(&self) -> Vec<(usize, &Line, Range<usize>)>;
 
+    /// Returns the highlighted cells on the line `line_idx` of the
+    /// viewport, with those that are drawn on top last
+    fn highlights_for_line(&self, line_idx: usize) -> Vec<LineHighlight>;
+
+    /// Highlights the cells of `set`, replacing the set with the
+    /// same `name`
+    fn set_highlights(&mut self, name: &str, set: HighlightSet);
+
+    /// Removes the set of highlights named `name`
+    fn clear_highlights(&mut self, name: &str);
+
+    /// Returns true if there is a set of highlights named `name`
+    fn has_highlights(&self, name: &str) -> bool;
+
+    /// Returns the text of the selection
+    fn get_selection_text(&self) -> String;
+
     fn has_dirty_lines(&self) -> bool;
 
     fn make_all_lines_dirty(&mut self);
@@ -31,6 +53,50 @@ pub trait Renderable {
     /// Returns physical, non-scrollback (rows, cols) for the
     /// terminal screen
     fn physical_dimensions(&self) -> (usize, usize);
+
+    /// Returns the number of rows that the viewport is scrolled back
+    /// from the bottom, along with the total number of rows including
+    /// the scrollback
+    fn get_scrollbar_info(&self) -> (usize, usize);
+
+    /// Scroll the viewport so that it is `offset` rows back from the
+    /// bottom
+    fn set_viewport_offset(&mut self, offset: usize);
+
+    /// Returns the text of each of the lines in the viewport,
+    /// from top to bottom
+    fn get_viewport_text(&self) -> Vec<String>;
+
+    /// Search the screen and its scrollback for `pattern`
+    fn search(&self, pattern: &Pattern) -> Result<Vec<SearchResult>, Error>;
+
+    /// Scroll the viewport so that the line `row`, as returned by
+    /// `search`, is visible.  Returns false if it no longer exists.
+    fn scroll_to_stable_row(&mut self, row: StableRowIndex) -> bool;
+
+    /// Returns the stable index of the line at the top of the viewport
+    fn get_stable_viewport_top(&self) -> StableRowIndex;
+
+    /// Returns the hyperlinks that are visible in the viewport
+    fn get_visible_hyperlinks(&mut self) -> Vec<VisibleHyperlink>;
+
+    /// Returns the hyperlink or word displayed at `col` of the
+    /// viewport row `row`
+    fn semantic_object_at(&mut self, row: VisibleRowIndex, col: usize) -> Option<SemanticObject>;
+
+    /// Returns true if the application is part way through updating
+    /// the screen, and painting should be deferred until it is done
+    fn is_output_synchronized(&mut self) -> bool;
+
+    /// Returns how long painting may be deferred for the update that
+    /// is in progress, or None if there isn't one
+    fn synchronized_output_remaining(&self) -> Option<Duration>;
+
+    /// Returns true if text with the conceal attribute should be shown
+    fn is_concealed_revealed(&self) -> bool;
+
+    /// Show or hide the text that has the conceal attribute
+    fn set_reveal_concealed(&mut self, reveal: bool);
 }
 
 impl Renderable for Terminal {
@@ -42,6 +108,26 @@ impl Renderable for Terminal {
         TerminalState::get_dirty_lines(self)
     }
 
+    fn highlights_for_line(&self, line_idx: usize) -> Vec<LineHighlight> {
+        TerminalState::highlights_for_line(self, line_idx)
+    }
+
+    fn set_highlights(&mut self, name: &str, set: HighlightSet) {
+        TerminalState::set_highlights(self, name, set)
+    }
+
+    fn clear_highlights(&mut self, name: &str) {
+        TerminalState::clear_highlights(self, name)
+    }
+
+    fn has_highlights(&self, name: &str) -> bool {
+        self.get_highlights(name).is_some()
+    }
+
+    fn get_selection_text(&self) -> String {
+        TerminalState::get_selection_text(self)
+    }
+
     fn clean_dirty_lines(&mut self) {
         TerminalState::clean_dirty_lines(self)
     }
@@ -55,11 +141,61 @@ impl Renderable for Terminal {
     }
 
     fn physical_dimensions(&self) -> (usize, usize) {
-        let screen = self.screen();
-        (screen.physical_rows, screen.physical_cols)
+        TerminalState::physical_dimensions(self)
     }
 
     fn has_dirty_lines(&self) -> bool {
         TerminalState::has_dirty_lines(self)
     }
+
+    fn get_scrollbar_info(&self) -> (usize, usize) {
+        (self.get_viewport_offset() as usize, self.screen().len())
+    }
+
+    fn set_viewport_offset(&mut self, offset: usize) {
+        self.set_scroll_viewport(offset as VisibleRowIndex)
+    }
+
+    fn get_viewport_text(&self) -> Vec<String> {
+        let screen = self.screen();
+        self.viewport_range()
+            .map(|idx| screen.line(idx).as_str())
+            .collect()
+    }
+
+    fn search(&self, pattern: &Pattern) -> Result<Vec<SearchResult>, Error> {
+        TerminalState::search(self, pattern)
+    }
+
+    fn scroll_to_stable_row(&mut self, row: StableRowIndex) -> bool {
+        TerminalState::scroll_to_stable_row(self, row)
+    }
+
+    fn get_stable_viewport_top(&self) -> StableRowIndex {
+        TerminalState::stable_viewport_top(self)
+    }
+
+    fn get_visible_hyperlinks(&mut self) -> Vec<VisibleHyperlink> {
+        TerminalState::visible_hyperlinks(self)
+    }
+
+    fn semantic_object_at(&mut self, row: VisibleRowIndex, col: usize) -> Option<SemanticObject> {
+        TerminalState::semantic_object_at(self, row, col)
+    }
+
+    fn is_output_synchronized(&mut self) -> bool {
+        TerminalState::is_output_synchronized(self)
+    }
+
+    fn synchronized_output_remaining(&self) -> Option<Duration> {
+        TerminalState::synchronized_output_remaining(self)
+    }
+
+    fn is_concealed_revealed(&self) -> bool {
+        TerminalState::is_concealed_revealed(self)
+    }
+
+    fn set_reveal_concealed(&mut self, reveal: bool) {
+        TerminalState::set_reveal_concealed(self, reveal)
+    }
 }
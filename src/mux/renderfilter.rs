@@ -0,0 +1,191 @@
+//! Filters the lines of output that are announced to screen readers.
+//! Programs that draw spinners or progress bars rewrite the same line
+//! many times per second, and announcing each of those versions would
+//! drown out everything else.  When `screen_reader_filter` is enabled,
+//! the text of each line that is painted is observed, and a line that
+//! changes is only announced once it has stopped changing for
+//! `screen_reader_coalesce_ms`; a spinner that keeps spinning is never
+//! announced and a progress bar is announced once, when it completes.
+//! Lines are identified by their `StableRowIndex` so that scrolling
+//! doesn't cause lines that were already announced to be repeated.
+//! Until a frontend has an accessibility binding to deliver them to,
+//! the most recent announcements are only shown by the tab inspector.
+use crate::mux::renderable::Renderable;
+use crate::mux::tab::TabId;
+use crate::mux::Mux;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::time::{Duration, Instant};
+use term::StableRowIndex;
+
+/// The text of rows that are further than this above the most recently
+/// announced row is forgotten
+const MAX_TRACKED_ROWS: usize = 1000;
+/// The number of announcements that are shown by the tab inspector
+const MAX_RECENT: usize = 20;
+
+pub struct RenderFilter {
+    coalesce: Duration,
+    /// The text that was most recently announced for each row
+    announced: BTreeMap<StableRowIndex, String>,
+    /// Rows whose text differs from what was announced, along with
+    /// the time that the text last changed
+    pending: BTreeMap<StableRowIndex, (String, Instant)>,
+}
+
+impl RenderFilter {
+    pub fn new(coalesce: Duration) -> Self {
+        Self {
+            coalesce,
+            announced: BTreeMap::new(),
+            pending: BTreeMap::new(),
+        }
+    }
+
+    /// Record that `row` currently holds `text`
+    pub fn update(&mut self, row: StableRowIndex, text: String, now: Instant) {
+        let text = text.trim_end().to_string();
+        if self.announced.get(&row) == Some(&text) {
+            // It was changed back again before it settled
+            self.pending.remove(&row);
+            return;
+        }
+        match self.pending.get(&row) {
+            Some((pending, _)) if *pending == text => {}
+            _ => {
+                self.pending.insert(row, (text, now));
+            }
+        }
+    }
+
+    /// Returns the text of the rows that haven't changed for the
+    /// coalescing interval as of `now`, from the top down.  Blank
+    /// rows are not announced.
+    pub fn take_settled(&mut self, now: Instant) -> Vec<String> {
+        let settled: Vec<StableRowIndex> = self
+            .pending
+            .iter()
+            .filter(|(_, (_, changed))| now.duration_since(*changed) >= self.coalesce)
+            .map(|(row, _)| *row)
+            .collect();
+
+        let mut result = vec![];
+        for row in settled {
+            let (text, _) = self.pending.remove(&row).unwrap();
+            if !text.is_empty() {
+                result.push(text.clone());
+            }
+            self.announced.insert(row, text);
+        }
+
+        if let Some(&newest) = self.announced.keys().next_back() {
+            let oldest = newest.saturating_sub(MAX_TRACKED_ROWS);
+            self.announced = self.announced.split_off(&oldest);
+        }
+        result
+    }
+}
+
+struct TabFilter {
+    filter: RenderFilter,
+    recent: VecDeque<String>,
+}
+
+impl TabFilter {
+    fn settle(&mut self, now: Instant) {
+        for text in self.filter.take_settled(now) {
+            if self.recent.len() == MAX_RECENT {
+                self.recent.pop_front();
+            }
+            self.recent.push_back(text);
+        }
+    }
+}
+
+thread_local! {
+    static TABS: RefCell<HashMap<TabId, TabFilter>> = RefCell::new(HashMap::new());
+}
+
+fn coalesce_interval() -> Option<Duration> {
+    let mux = Mux::get()?;
+    let config = mux.config();
+    if config.screen_reader_filter {
+        Some(Duration::from_millis(config.screen_reader_coalesce_ms))
+    } else {
+        None
+    }
+}
+
+/// Called prior to painting `tab_id` to observe the text of the
+/// lines that have changed since it was last painted
+pub fn observe(tab_id: TabId, renderer: &Renderable) {
+    let coalesce = match coalesce_interval() {
+        Some(coalesce) => coalesce,
+        None => return,
+    };
+    let now = Instant::now();
+    let top = renderer.get_stable_viewport_top();
+    TABS.with(|tabs| {
+        let mut tabs = tabs.borrow_mut();
+        let tab = tabs.entry(tab_id).or_insert_with(|| TabFilter {
+            filter: RenderFilter::new(coalesce),
+            recent: VecDeque::new(),
+        });
+        for (row, line, _) in renderer.get_dirty_lines() {
+            tab.filter.update(top + row, line.as_str(), now);
+        }
+        tab.settle(now);
+    });
+}
+
+/// Discard the state for a tab that has been closed
+pub fn forget(tab_id: TabId) {
+    TABS.with(|tabs| {
+        tabs.borrow_mut().remove(&tab_id);
+    });
+}
+
+/// Produce a report of the most recent announcements for `tab_id`,
+/// suitable for the tab inspector
+pub fn report(tab_id: TabId) -> Vec<String> {
+    if coalesce_interval().is_none() {
+        return vec!["Set screen_reader_filter = true to enable".to_string()];
+    }
+    TABS.with(|tabs| match tabs.borrow_mut().get_mut(&tab_id) {
+        Some(tab) => {
+            tab.settle(Instant::now());
+            tab.recent.iter().cloned().collect()
+        }
+        None => vec![],
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn coalesce() {
+        let start = Instant::now();
+        let ms = |n| start + Duration::from_millis(n);
+        let mut filter = RenderFilter::new(Duration::from_millis(100));
+
+        filter.update(0, "$ make".to_string(), ms(0));
+        filter.update(1, "building |".to_string(), ms(0));
+        filter.update(1, "building /".to_string(), ms(50));
+        assert_eq!(filter.take_settled(ms(100)), vec!["$ make".to_string()]);
+
+        // The spinner is still spinning, so isn't announced
+        filter.update(1, "building -".to_string(), ms(120));
+        assert!(filter.take_settled(ms(200)).is_empty());
+
+        filter.update(1, "done      ".to_string(), ms(210));
+        assert_eq!(filter.take_settled(ms(310)), vec!["done".to_string()]);
+
+        // Repainting unchanged text, such as after scrolling, is not
+        // announced again
+        filter.update(0, "$ make".to_string(), ms(400));
+        filter.update(1, "done".to_string(), ms(400));
+        assert!(filter.take_settled(ms(1000)).is_empty());
+    }
+}
@@ -1,7 +1,8 @@
 use crate::mux::renderable::Renderable;
+use crate::pty::{ExitStatus, ForegroundProcess, NonBlockingReader};
 use failure::Error;
 use std::cell::RefMut;
-use term::{KeyCode, KeyModifiers, MouseEvent, TerminalHost};
+use term::{KeyCode, KeyModifiers, MouseEvent, TerminalHost, UnhandledSequence};
 
 static TAB_ID: ::std::sync::atomic::AtomicUsize = ::std::sync::atomic::ATOMIC_USIZE_INIT;
 pub type TabId = usize;
@@ -10,6 +11,23 @@ pub fn alloc_tab_id() -> TabId {
     TAB_ID.fetch_add(1, ::std::sync::atomic::Ordering::Relaxed)
 }
 
+/// A titled group of diagnostic information about a tab, as shown
+/// by the tab inspector
+#[derive(Debug, Clone)]
+pub struct InspectSection {
+    pub title: String,
+    pub lines: Vec<String>,
+}
+
+/// Describes why the program of a tab couldn't be spawned, and what
+/// was run in its place
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailedSpawn {
+    pub error: String,
+    /// The argv of the `fallback_prog` that is running in the tab
+    pub fallback: Vec<String>,
+}
+
 pub trait Tab {
     fn tab_id(&self) -> TabId;
     fn renderer(&self) -> RefMut<Renderable>;
@@ -28,4 +46,70 @@ pub trait Tab {
     fn mouse_event(&self, event: MouseEvent, host: &mut TerminalHost) -> Result<(), Error>;
     fn advance_bytes(&self, buf: &[u8], host: &mut TerminalHost);
     fn is_dead(&self) -> bool;
+
+    /// Returns a reader for the output of the tab that doesn't block,
+    /// so that it can be serviced by the shared pty poller rather than
+    /// by a thread of its own
+    fn nonblocking_reader(&self) -> Result<Box<NonBlockingReader>, Error> {
+        bail!("tab {} has no non-blocking reader", self.tab_id());
+    }
+
+    /// Sends text that was entered as a whole, such as that committed
+    /// by an input method or produced by a key remapping
+    fn send_text(&self, text: &str) -> Result<(), Error> {
+        self.writer().write_all(text.as_bytes())?;
+        Ok(())
+    }
+
+    /// Returns why the program of the tab couldn't be spawned, if it
+    /// was replaced by the `fallback_prog`.  This is only returned by
+    /// the first call, so that it is only explained once.
+    fn take_spawn_failure(&self) -> Option<FailedSpawn> {
+        None
+    }
+
+    /// Ends the process in the tab, so that the tab closes
+    fn kill(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Called when the window containing the tab gains or loses
+    /// the focus
+    fn focus_changed(&self, _focused: bool) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Called when a key is released.  This is only of interest to
+    /// applications that have asked for key releases to be reported.
+    fn key_up(&self, _key: KeyCode, _mods: KeyModifiers) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Returns the exit status of the process in the tab, if it
+    /// has exited
+    fn exit_status(&self) -> Option<ExitStatus> {
+        None
+    }
+
+    /// Returns the process in the foreground of the tab, if known
+    fn foreground_process(&self) -> Option<ForegroundProcess> {
+        None
+    }
+
+    /// Returns the working directory of the program in the tab as a
+    /// `file://` url that names its host, if it is known
+    fn get_current_dir(&self) -> Option<String> {
+        None
+    }
+
+    /// Returns diagnostic information about the tab
+    fn inspect(&self) -> Vec<InspectSection> {
+        vec![]
+    }
+
+    /// Returns the total number of escape sequences that were ignored
+    /// by the terminal, along with the most recent of them
+    fn unhandled_sequences(&self) -> (usize, Vec<UnhandledSequence>) {
+        (0, vec![])
+    }
 }
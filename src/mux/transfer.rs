@@ -0,0 +1,161 @@
+//! Receives the files that programs send to the terminal to be
+//! downloaded, using the iTerm2 file transfer protocol that `it2dl`
+//! speaks.  The file travels through the output of the tab, so this
+//! works just as well for a program running on a remote host over
+//! ssh as for a local one.
+//! A download is held until the user has agreed to save it, as
+//! otherwise any program could write files on the local machine.
+use crate::mux::tab::TabId;
+use crate::mux::window::WindowId;
+use crate::mux::Mux;
+use failure::Error;
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+
+/// How many numbered variations of a name are tried when saving a
+/// download before giving up
+const MAX_SAVE_ATTEMPTS: usize = 1000;
+
+/// A file that a tab has offered to download
+pub struct Download {
+    pub tab_id: TabId,
+    /// The name to save the file as; this is a plain file name that
+    /// cannot refer to other directories
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+impl Download {
+    /// Saves the file into `dir`, choosing a name that doesn't clash
+    /// with an existing file.  Returns the path that it was saved to.
+    pub fn save(&self, dir: &Path) -> Result<PathBuf, Error> {
+        std::fs::create_dir_all(dir)?;
+        let mut attempt = 0;
+        loop {
+            if attempt == MAX_SAVE_ATTEMPTS {
+                bail!(
+                    "unable to find an unused name for {} in {}",
+                    self.name,
+                    dir.display()
+                );
+            }
+            let path = dir.join(numbered_name(&self.name, attempt));
+            attempt += 1;
+            let mut file = match std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(file) => file,
+                Err(ref err) if err.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(err) => bail!("unable to create {}: {}", path.display(), err),
+            };
+            std::io::Write::write_all(&mut file, &self.data)?;
+            return Ok(path);
+        }
+    }
+}
+
+thread_local! {
+    static OFFERS: RefCell<Vec<(WindowId, Download)>> = RefCell::new(vec![]);
+}
+
+/// Called when `tab_id` sends a file to be downloaded.  This must be
+/// called on the gui thread.
+pub fn offer(tab_id: TabId, name: Option<&str>, data: &[u8]) {
+    let mux = Mux::get().unwrap();
+    let window_id = match mux.window_containing_tab(tab_id) {
+        Some(window_id) => window_id,
+        None => {
+            eprintln!("Ignoring download from tab {} as it has no window", tab_id);
+            return;
+        }
+    };
+    let download = Download {
+        tab_id,
+        name: sanitize_name(name),
+        data: data.to_vec(),
+    };
+    OFFERS.with(|offers| offers.borrow_mut().push((window_id, download)));
+    mux.notify_output();
+}
+
+/// Removes and returns the downloads offered by the tabs of `window_id`
+pub fn take_offers(window_id: WindowId) -> Vec<Download> {
+    OFFERS.with(|offers| {
+        let mut offers = offers.borrow_mut();
+        let (mine, others): (Vec<_>, Vec<_>) =
+            offers.drain(..).partition(|(id, _)| *id == window_id);
+        *offers = others;
+        mine.into_iter().map(|(_, download)| download).collect()
+    })
+}
+
+/// Discards the downloads offered by a tab that has been removed
+pub fn forget(tab_id: TabId) {
+    OFFERS.with(|offers| {
+        offers
+            .borrow_mut()
+            .retain(|(_, download)| download.tab_id != tab_id)
+    });
+}
+
+/// Reduces the name suggested by the program to its final component,
+/// so that it can't place the file outside of the download directory
+fn sanitize_name(name: Option<&str>) -> String {
+    let name = name
+        .and_then(|name| name.rsplit(|c| c == '/' || c == '\\').next())
+        .unwrap_or("")
+        .trim();
+    let name: String = name.chars().filter(|c| !c.is_control()).collect();
+    match name.as_str() {
+        "" | "." | ".." => "download".to_owned(),
+        _ => name,
+    }
+}
+
+/// Returns `name` for the first attempt, then inserts a number ahead
+/// of its extension for subsequent attempts
+fn numbered_name(name: &str, attempt: usize) -> String {
+    if attempt == 0 {
+        return name.to_owned();
+    }
+    match name.rfind('.') {
+        Some(dot) if dot > 0 => format!("{}-{}{}", &name[..dot], attempt, &name[dot..]),
+        _ => format!("{}-{}", name, attempt),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn names() {
+        assert_eq!(sanitize_name(Some("report.pdf")), "report.pdf");
+        assert_eq!(sanitize_name(Some("../../.bashrc")), ".bashrc");
+        assert_eq!(sanitize_name(Some("C:\\Windows\\evil.dll")), "evil.dll");
+        assert_eq!(sanitize_name(Some("dir/..")), "download");
+        assert_eq!(sanitize_name(Some("a\x1b[2Jb")), "a[2Jb");
+        assert_eq!(sanitize_name(None), "download");
+
+        assert_eq!(numbered_name("report.pdf", 0), "report.pdf");
+        assert_eq!(numbered_name("report.pdf", 2), "report-2.pdf");
+        assert_eq!(numbered_name(".bashrc", 1), ".bashrc-1");
+        assert_eq!(numbered_name("download", 1), "download-1");
+    }
+
+    #[test]
+    fn save_avoids_clashes() {
+        let dir = std::env::temp_dir().join(format!("wezterm-transfer-{}", std::process::id()));
+        let download = Download {
+            tab_id: 0,
+            name: "report.pdf".to_string(),
+            data: b"hello".to_vec(),
+        };
+        assert_eq!(download.save(&dir).unwrap(), dir.join("report.pdf"));
+        assert_eq!(download.save(&dir).unwrap(), dir.join("report-1.pdf"));
+        assert_eq!(std::fs::read(dir.join("report-1.pdf")).unwrap(), b"hello");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
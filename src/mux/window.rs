@@ -4,18 +4,26 @@ use std::rc::Rc;
 static WIN_ID: ::std::sync::atomic::AtomicUsize = ::std::sync::atomic::ATOMIC_USIZE_INIT;
 pub type WindowId = usize;
 
+pub fn alloc_window_id() -> WindowId {
+    WIN_ID.fetch_add(1, ::std::sync::atomic::Ordering::Relaxed)
+}
+
 pub struct Window {
     id: WindowId,
     tabs: Vec<Rc<Tab>>,
     active: usize,
+    /// When set, the overlay takes the place of the active tab
+    /// for the purposes of input and rendering
+    overlay: Option<Rc<Tab>>,
 }
 
 impl Window {
-    pub fn new(tab: &Rc<Tab>) -> Self {
+    pub fn new(id: WindowId, tab: &Rc<Tab>) -> Self {
         Self {
-            id: WIN_ID.fetch_add(1, ::std::sync::atomic::Ordering::Relaxed),
+            id,
             tabs: vec![Rc::clone(tab)],
             active: 0,
+            overlay: None,
         }
     }
 
@@ -52,14 +60,36 @@ impl Window {
         if let Some(idx) = self.idx_by_id(id) {
             self.tabs.remove(idx);
             let len = self.tabs.len();
-            if len > 0 && self.active == idx && idx >= len {
+            if idx < self.active {
+                // Keep the same tab active
+                self.active -= 1;
+            } else if len > 0 && self.active == idx && idx >= len {
                 self.set_active(len - 1);
             }
         }
     }
 
     pub fn get_active(&self) -> Option<&Rc<Tab>> {
-        self.get_by_idx(self.active)
+        self.overlay
+            .as_ref()
+            .or_else(|| self.get_by_idx(self.active))
+    }
+
+    pub fn get_overlay(&self) -> Option<&Rc<Tab>> {
+        self.overlay.as_ref()
+    }
+
+    pub fn set_overlay(&mut self, overlay: &Rc<Tab>) {
+        overlay.renderer().make_all_lines_dirty();
+        self.overlay = Some(Rc::clone(overlay));
+    }
+
+    pub fn clear_overlay(&mut self) {
+        if self.overlay.take().is_some() {
+            if let Some(tab) = self.get_by_idx(self.active) {
+                tab.renderer().make_all_lines_dirty();
+            }
+        }
     }
 
     #[inline]
@@ -69,6 +99,7 @@ impl Window {
 
     pub fn set_active(&mut self, idx: usize) {
         assert!(idx < self.tabs.len());
+        self.overlay = None;
         self.active = idx;
         self.get_by_idx(idx)
             .unwrap()
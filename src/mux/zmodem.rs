@@ -0,0 +1,1067 @@
+//! Detects the ZMODEM file transfers that are started by running `sz`
+//! or `rz` in a tab, whose binary data would otherwise be displayed
+//! as garbage.  Depending on `zmodem_policy`, the files that are sent
+//! with `sz` are received and then offered for download in the same
+//! way as those sent with the iTerm2 protocol, or the transfer is
+//! cancelled.  Sending files to `rz` isn't supported, so those
+//! transfers are always cancelled.
+//! Only the parts of the protocol that `sz` needs from a receiver
+//! are implemented; as the data travels over a pty rather than a
+//! noisy line, a corrupt frame cancels the transfer rather than
+//! being retried.
+use crate::config::ZmodemPolicy;
+use crate::mux::tab::TabId;
+use crate::mux::transfer;
+use crate::mux::window::WindowId;
+use crate::mux::Mux;
+use promise::task::gui_executor;
+use promise::timer::schedule_timer;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+const ZPAD: u8 = b'*';
+const ZDLE: u8 = 0x18;
+const ZBIN: u8 = b'A';
+const ZHEX: u8 = b'B';
+const ZBIN32: u8 = b'C';
+const XON: u8 = 0x11;
+const XOFF: u8 = 0x13;
+
+// Frame types
+const ZRQINIT: u8 = 0;
+const ZRINIT: u8 = 1;
+const ZSINIT: u8 = 2;
+const ZACK: u8 = 3;
+const ZFILE: u8 = 4;
+const ZABORT: u8 = 7;
+const ZFIN: u8 = 8;
+const ZRPOS: u8 = 9;
+const ZDATA: u8 = 10;
+const ZEOF: u8 = 11;
+const ZFERR: u8 = 12;
+const ZCAN: u8 = 16;
+const ZCOMMAND: u8 = 18;
+
+// The escaped bytes that end a data subpacket
+const ZCRCE: u8 = b'h';
+const ZCRCG: u8 = b'i';
+const ZCRCQ: u8 = b'j';
+const ZCRCW: u8 = b'k';
+// Escapes for DEL with and without the high bit set
+const ZRUB0: u8 = b'l';
+const ZRUB1: u8 = b'm';
+
+/// The ZRINIT flags: full duplex, can receive while saving,
+/// and understands 32 bit CRCs
+const RECEIVER_FLAGS: u8 = 0x01 | 0x02 | 0x20;
+
+/// Makes the program at the other end abandon the transfer
+const CANCEL_SEQUENCE: &[u8] =
+    b"\x18\x18\x18\x18\x18\x18\x18\x18\x18\x18\x08\x08\x08\x08\x08\x08\x08\x08\x08\x08";
+
+/// The start of the hex header that begins a transfer; the second
+/// digit of the frame type follows it
+const HEX_HEADER_START: &[u8] = &[ZPAD, ZPAD, ZDLE, ZHEX, b'0'];
+
+/// A transfer is abandoned if no frame arrives for this many seconds
+const STALL_TIMEOUT_SECS: u64 = 30;
+
+/// `sz` sends subpackets of at most 8KiB; anything longer is corrupt
+const MAX_SUBPACKET_SIZE: usize = 16 * 1024;
+
+/// Received files are held in memory until they are saved, so larger
+/// files than this are refused
+const MAX_FILE_SIZE: usize = 256 * 1024 * 1024;
+
+fn stall_timeout() -> Duration {
+    Duration::from_secs(STALL_TIMEOUT_SECS)
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &byte in data {
+        crc ^= u16::from(byte) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Returns true if `crc` is the checksum of `data`, in the byte
+/// order that it is sent in for the width of the checksum
+fn crc_matches(data: &[u8], crc: &[u8]) -> bool {
+    if crc.len() == 4 {
+        let expected = crc32(data);
+        crc == [
+            expected as u8,
+            (expected >> 8) as u8,
+            (expected >> 16) as u8,
+            (expected >> 24) as u8,
+        ]
+    } else {
+        let expected = crc16(data);
+        crc == [(expected >> 8) as u8, expected as u8]
+    }
+}
+
+/// Returns true for the bytes that may be sent between frames: the
+/// line ending and XON that follow a hex header, flow control, and
+/// the CANs that cancel the transfer
+fn between_frames(byte: u8) -> bool {
+    match byte & 0x7f {
+        b'\r' | b'\n' | XON | XOFF | ZDLE => true,
+        _ => false,
+    }
+}
+
+/// Returns the unescaped value of the byte that follows ZDLE
+fn unescape(byte: u8) -> Option<u8> {
+    match byte {
+        ZRUB0 => Some(0x7f),
+        ZRUB1 => Some(0xff),
+        byte if byte & 0x60 == 0x40 => Some(byte ^ 0x40),
+        _ => None,
+    }
+}
+
+/// Encodes the header data of a position, least significant byte first
+fn position_data(pos: usize) -> [u8; 4] {
+    let pos = pos as u32;
+    [
+        pos as u8,
+        (pos >> 8) as u8,
+        (pos >> 16) as u8,
+        (pos >> 24) as u8,
+    ]
+}
+
+fn data_position(data: [u8; 4]) -> usize {
+    (u32::from(data[0])
+        | u32::from(data[1]) << 8
+        | u32::from(data[2]) << 16
+        | u32::from(data[3]) << 24) as usize
+}
+
+/// Encodes a hex header, which is the form that receivers send
+fn hex_header(kind: u8, data: [u8; 4]) -> Vec<u8> {
+    let mut bytes = vec![kind];
+    bytes.extend_from_slice(&data);
+    let crc = crc16(&bytes);
+    bytes.push((crc >> 8) as u8);
+    bytes.push(crc as u8);
+
+    let mut header = vec![ZPAD, ZPAD, ZDLE, ZHEX];
+    for byte in bytes {
+        header.extend_from_slice(format!("{:02x}", byte).as_bytes());
+    }
+    header.extend_from_slice(b"\r\x8a");
+    // The sender doesn't expect XON after these
+    if kind != ZACK && kind != ZFIN {
+        header.push(XON);
+    }
+    header
+}
+
+/// A unit of the protocol decoded from the bytes sent by the sender
+#[derive(Debug, PartialEq)]
+enum Frame {
+    Header {
+        kind: u8,
+        data: [u8; 4],
+    },
+    Subpacket {
+        data: Vec<u8>,
+        end: u8,
+    },
+    /// The sender cancelled the transfer
+    Cancelled,
+    /// A frame failed its CRC check or was malformed
+    Corrupt,
+    /// A byte that isn't part of the protocol arrived between frames,
+    /// so the sender is no longer running
+    Stray,
+}
+
+enum DecoderState {
+    /// Looking for the start of a header; the number of bytes of
+    /// that start that have been seen so far
+    Seek(usize),
+    /// Collecting the hex digits of a hex header
+    Hex(Vec<u8>),
+    /// Collecting the bytes of a binary header
+    Binary { crc32: bool, bytes: Vec<u8> },
+    /// Collecting the data and checksum of a data subpacket
+    Subpacket {
+        crc32: bool,
+        data: Vec<u8>,
+        end: Option<u8>,
+        crc: Vec<u8>,
+    },
+}
+
+/// Splits the bytes sent by the sender into frames
+struct Decoder {
+    state: DecoderState,
+    /// True if the previous byte was ZDLE
+    escaped: bool,
+    /// The number of consecutive CAN bytes, five of which cancel
+    cancels: usize,
+}
+
+impl Decoder {
+    fn new(state: DecoderState) -> Self {
+        Self {
+            state,
+            escaped: false,
+            cancels: 0,
+        }
+    }
+
+    fn advance(&mut self, byte: u8) -> Option<Frame> {
+        // ZDLE is also CAN
+        if byte == ZDLE {
+            self.cancels += 1;
+            if self.cancels >= 5 {
+                return Some(Frame::Cancelled);
+            }
+        } else {
+            self.cancels = 0;
+        }
+
+        if let DecoderState::Seek(seen) = self.state {
+            self.state = match (seen, byte) {
+                (_, ZPAD) if seen < 2 => DecoderState::Seek(1),
+                (1, ZDLE) => DecoderState::Seek(2),
+                (2, ZHEX) => DecoderState::Hex(vec![]),
+                (2, ZBIN) => DecoderState::Binary {
+                    crc32: false,
+                    bytes: vec![],
+                },
+                (2, ZBIN32) => DecoderState::Binary {
+                    crc32: true,
+                    bytes: vec![],
+                },
+                (0, byte) if between_frames(byte) => DecoderState::Seek(0),
+                (0, _) | (1, _) => return Some(Frame::Stray),
+                _ => return Some(Frame::Corrupt),
+            };
+            return None;
+        }
+
+        if let DecoderState::Hex(ref mut digits) = self.state {
+            match byte {
+                b'0'..=b'9' | b'a'..=b'f' => digits.push(byte),
+                _ => return Some(Frame::Corrupt),
+            }
+            if digits.len() < 14 {
+                return None;
+            }
+            let bytes: Option<Vec<u8>> = digits
+                .chunks(2)
+                .map(|pair| {
+                    std::str::from_utf8(pair)
+                        .ok()
+                        .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                })
+                .collect();
+            return match bytes {
+                Some(ref bytes) if crc_matches(&bytes[..5], &bytes[5..]) => {
+                    Some(self.header(false, bytes))
+                }
+                _ => Some(Frame::Corrupt),
+            };
+        }
+
+        // Binary headers and subpackets are escaped, and flow control
+        // characters that aren't escaped are to be ignored
+        if byte == ZDLE {
+            self.escaped = true;
+            return None;
+        }
+        if !self.escaped && (byte & 0x7f == XON || byte & 0x7f == XOFF) {
+            return None;
+        }
+        let escaped = self.escaped;
+        self.escaped = false;
+
+        let frame = match self.state {
+            DecoderState::Binary {
+                crc32,
+                ref mut bytes,
+            } => {
+                let byte = if escaped {
+                    match unescape(byte) {
+                        Some(byte) => byte,
+                        None => return Some(Frame::Corrupt),
+                    }
+                } else {
+                    byte
+                };
+                bytes.push(byte);
+                if bytes.len() < if crc32 { 9 } else { 7 } {
+                    return None;
+                }
+                if !crc_matches(&bytes[..5], &bytes[5..]) {
+                    return Some(Frame::Corrupt);
+                }
+                let bytes = bytes.clone();
+                return Some(self.header(crc32, &bytes));
+            }
+            DecoderState::Subpacket {
+                crc32,
+                ref mut data,
+                ref mut end,
+                ref mut crc,
+            } => {
+                match (escaped, *end) {
+                    (true, None) if byte >= ZCRCE && byte <= ZCRCW => {
+                        *end = Some(byte);
+                        return None;
+                    }
+                    _ => {}
+                }
+                let byte = if escaped {
+                    match unescape(byte) {
+                        Some(byte) => byte,
+                        None => return Some(Frame::Corrupt),
+                    }
+                } else {
+                    byte
+                };
+                let end = match *end {
+                    None if data.len() == MAX_SUBPACKET_SIZE => return Some(Frame::Corrupt),
+                    None => {
+                        data.push(byte);
+                        return None;
+                    }
+                    Some(end) => end,
+                };
+                crc.push(byte);
+                if crc.len() < if crc32 { 4 } else { 2 } {
+                    return None;
+                }
+                let mut checked = data.clone();
+                checked.push(end);
+                if !crc_matches(&checked, crc) {
+                    return Some(Frame::Corrupt);
+                }
+                // More subpackets follow unless this one ends the frame
+                let next = if end == ZCRCG || end == ZCRCQ {
+                    DecoderState::Subpacket {
+                        crc32,
+                        data: vec![],
+                        end: None,
+                        crc: vec![],
+                    }
+                } else {
+                    DecoderState::Seek(0)
+                };
+                let data = std::mem::replace(data, vec![]);
+                self.state = next;
+                Frame::Subpacket { data, end }
+            }
+            DecoderState::Seek(_) | DecoderState::Hex(_) => unreachable!(),
+        };
+        Some(frame)
+    }
+
+    /// Decodes the type, data and checksum of a header, and
+    /// prepares for the subpackets that follow it, if any
+    fn header(&mut self, crc32: bool, bytes: &[u8]) -> Frame {
+        let kind = bytes[0];
+        let data = [bytes[1], bytes[2], bytes[3], bytes[4]];
+        self.state = match kind {
+            ZSINIT | ZFILE | ZDATA | ZCOMMAND => DecoderState::Subpacket {
+                crc32,
+                data: vec![],
+                end: None,
+                crc: vec![],
+            },
+            _ => DecoderState::Seek(0),
+        };
+        Frame::Header { kind, data }
+    }
+}
+
+/// Something that happened during a transfer that the user needs
+/// to know about
+#[derive(Debug, PartialEq)]
+pub enum Event {
+    /// A file was received in full
+    Received { name: String, data: Vec<u8> },
+    /// A program tried to send a file with `sz`, and the transfer
+    /// was cancelled as receiving files is disabled
+    SendCancelled,
+    /// A program tried to receive a file with `rz`
+    ReceiveCancelled,
+    /// A transfer failed for the given reason
+    Failed(String),
+}
+
+enum Step {
+    Continue,
+    /// The transfer is over; the byte is passed on to the terminal
+    /// unless it was consumed
+    Finished {
+        consumed: bool,
+    },
+}
+
+/// Receives the files sent by `sz`
+struct Receiver {
+    decoder: Decoder,
+    /// The type of the most recent header, which gives the meaning
+    /// of the subpackets that follow it
+    header: u8,
+    /// The name and data of the file being received
+    file: Option<(String, Vec<u8>)>,
+    /// Once ZFIN has been answered, the sender ends the session by
+    /// sending "OO"; this is the number of those that have arrived
+    over_and_out: Option<usize>,
+}
+
+impl Receiver {
+    /// Creates a receiver for a transfer whose ZRQINIT header has
+    /// been seen up to the end of its type
+    fn new() -> Self {
+        Self {
+            decoder: Decoder::new(DecoderState::Hex(vec![b'0', b'0'])),
+            header: ZRQINIT,
+            file: None,
+            over_and_out: None,
+        }
+    }
+
+    fn fail(&mut self, reason: &str, replies: &mut Vec<u8>, events: &mut Vec<Event>) -> Step {
+        replies.extend_from_slice(CANCEL_SEQUENCE);
+        events.push(Event::Failed(reason.to_owned()));
+        Step::Finished { consumed: true }
+    }
+
+    fn advance(&mut self, byte: u8, replies: &mut Vec<u8>, events: &mut Vec<Event>) -> Step {
+        if let Some(count) = self.over_and_out {
+            // The line ending of the sender's ZFIN comes first
+            if count == 0 && (byte == b'\r' || byte & 0x7f == b'\n' || byte & 0x7f == XON) {
+                return Step::Continue;
+            }
+            if byte == b'O' && count < 2 {
+                self.over_and_out = Some(count + 1);
+                if count + 1 < 2 {
+                    return Step::Continue;
+                }
+                return Step::Finished { consumed: true };
+            }
+            return Step::Finished { consumed: false };
+        }
+
+        match self.decoder.advance(byte) {
+            None => Step::Continue,
+            Some(Frame::Cancelled) => {
+                events.push(Event::Failed("the sender cancelled it".to_owned()));
+                Step::Finished { consumed: true }
+            }
+            Some(Frame::Corrupt) => self.fail("the data was corrupted", replies, events),
+            Some(Frame::Stray) => {
+                // Let the terminal have the output that follows
+                events.push(Event::Failed("it was interrupted".to_owned()));
+                Step::Finished { consumed: false }
+            }
+            Some(Frame::Header { kind, data }) => {
+                self.header = kind;
+                self.process_header(kind, data, replies, events)
+            }
+            Some(Frame::Subpacket { data, end }) => {
+                self.process_subpacket(data, end, replies, events)
+            }
+        }
+    }
+
+    fn process_header(
+        &mut self,
+        kind: u8,
+        data: [u8; 4],
+        replies: &mut Vec<u8>,
+        events: &mut Vec<Event>,
+    ) -> Step {
+        match kind {
+            ZRQINIT => replies.extend(hex_header(ZRINIT, [0, 0, 0, RECEIVER_FLAGS])),
+            ZDATA => {
+                let received = self.file.as_ref().map(|(_, data)| data.len());
+                if received != Some(data_position(data)) {
+                    return self.fail("the data was out of order", replies, events);
+                }
+            }
+            ZEOF => match self.file.take() {
+                Some((name, file_data)) if file_data.len() == data_position(data) => {
+                    events.push(Event::Received {
+                        name,
+                        data: file_data,
+                    });
+                    replies.extend(hex_header(ZRINIT, [0, 0, 0, RECEIVER_FLAGS]));
+                }
+                _ => return self.fail("the file was incomplete", replies, events),
+            },
+            ZFIN => {
+                replies.extend(hex_header(ZFIN, [0; 4]));
+                self.over_and_out = Some(0);
+            }
+            ZCAN | ZABORT | ZFERR => {
+                events.push(Event::Failed("the sender cancelled it".to_owned()));
+                return Step::Finished { consumed: true };
+            }
+            ZCOMMAND => {
+                return self.fail("the sender asked to run a command", replies, events);
+            }
+            // The others either have subpackets that follow, or are
+            // of no consequence to a receiver
+            _ => {}
+        }
+        Step::Continue
+    }
+
+    fn process_subpacket(
+        &mut self,
+        data: Vec<u8>,
+        end: u8,
+        replies: &mut Vec<u8>,
+        events: &mut Vec<Event>,
+    ) -> Step {
+        match self.header {
+            ZSINIT => replies.extend(hex_header(ZACK, [0; 4])),
+            ZFILE => {
+                // The name is followed by a NUL and then its size and
+                // other details, which aren't needed
+                let name = data.split(|&b| b == 0).next().unwrap_or(&[]);
+                let name = String::from_utf8_lossy(name).into_owned();
+                self.file = Some((name, vec![]));
+                replies.extend(hex_header(ZRPOS, position_data(0)));
+            }
+            ZDATA => {
+                let received = match self.file.as_mut() {
+                    Some((_, file_data)) if file_data.len() + data.len() > MAX_FILE_SIZE => {
+                        return self.fail("the file is too large", replies, events);
+                    }
+                    Some((_, file_data)) => {
+                        file_data.extend_from_slice(&data);
+                        file_data.len()
+                    }
+                    None => return self.fail("data arrived before the file", replies, events),
+                };
+                if end == ZCRCQ || end == ZCRCW {
+                    replies.extend(hex_header(ZACK, position_data(received)));
+                }
+            }
+            ZCOMMAND => {
+                return self.fail("the sender asked to run a command", replies, events);
+            }
+            _ => {}
+        }
+        Step::Continue
+    }
+}
+
+enum Session {
+    Receiving(Receiver),
+    /// The transfer was cancelled; the rest of the header that started
+    /// it is discarded, up to the end of its line and a following XON
+    Discarding {
+        seen_line_end: bool,
+        count: usize,
+    },
+}
+
+impl Session {
+    fn advance(&mut self, byte: u8, replies: &mut Vec<u8>, events: &mut Vec<Event>) -> Step {
+        match self {
+            Session::Receiving(receiver) => receiver.advance(byte, replies, events),
+            Session::Discarding {
+                seen_line_end: true,
+                ..
+            } => Step::Finished {
+                consumed: byte & 0x7f == XON,
+            },
+            Session::Discarding {
+                seen_line_end,
+                count,
+            } => {
+                *count += 1;
+                if byte & 0x7f == b'\n' {
+                    *seen_line_end = true;
+                } else if *count > 32 {
+                    // That wasn't a header after all
+                    return Step::Finished { consumed: false };
+                }
+                Step::Continue
+            }
+        }
+    }
+}
+
+/// Watches the output of a tab for the start of transfers, and
+/// takes the output of the transfer away from the terminal
+#[derive(Default)]
+pub struct ZmodemFilter {
+    /// The number of bytes of `HEX_HEADER_START` that have been seen
+    matched: usize,
+    session: Option<Session>,
+    /// The matched part of a header that hasn't been passed on yet.
+    /// Once the match includes ZDLE it can't be ordinary output, so
+    /// it is held back in case the rest of the header follows.
+    held: Vec<u8>,
+    /// When the session last made progress
+    last_progress: Option<Instant>,
+    /// True while a timer is scheduled to check for the session
+    /// stalling
+    stall_check_pending: bool,
+}
+
+impl ZmodemFilter {
+    /// Processes `data` that was output by the tab, returning the part
+    /// of it that should be passed on to the terminal.  Replies for
+    /// the program at the other end are appended to `replies`.
+    pub fn filter(
+        &mut self,
+        data: Vec<u8>,
+        policy: ZmodemPolicy,
+        replies: &mut Vec<u8>,
+        events: &mut Vec<Event>,
+    ) -> Vec<u8> {
+        if policy == ZmodemPolicy::Ignore {
+            return data;
+        }
+        if self.session.is_none() && self.held.is_empty() && !data.contains(&ZDLE) {
+            // A transfer can't start without ZDLE, but the header could
+            // begin at the end of this output
+            let stars = data.iter().rev().take_while(|&&b| b == ZPAD).count();
+            self.matched = stars.min(2);
+            return data;
+        }
+
+        let mut output = std::mem::replace(&mut self.held, vec![]);
+        output.reserve(data.len());
+        for byte in data {
+            if let Some(session) = self.session.as_mut() {
+                self.last_progress = Some(Instant::now());
+                if let Step::Finished { consumed } = session.advance(byte, replies, events) {
+                    self.session = None;
+                    self.last_progress = None;
+                    if !consumed {
+                        output.push(byte);
+                    }
+                }
+                continue;
+            }
+
+            output.push(byte);
+            if let Some(kind) = self.detect(byte) {
+                // Hide as much of the header as is in this output
+                let start = output.len().saturating_sub(HEX_HEADER_START.len() + 1);
+                output.truncate(start);
+                self.session = Some(self.start(kind, policy, replies, events));
+                self.last_progress = Some(Instant::now());
+            }
+        }
+
+        if self.session.is_none() && self.matched >= 3 {
+            let start = output.len() - self.matched;
+            self.held = output.split_off(start);
+        }
+        output
+    }
+
+    /// Returns true if a session is in progress and there is no timer
+    /// to check for it stalling; the caller is to schedule one
+    fn needs_stall_check(&mut self) -> bool {
+        if self.session.is_some() && !self.stall_check_pending {
+            self.stall_check_pending = true;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Abandons the session if it hasn't made progress for the stall
+    /// timeout as of `now`.  Returns how much longer it has left to
+    /// make progress if it is still going.
+    fn check_stalled(&mut self, now: Instant, events: &mut Vec<Event>) -> Option<Duration> {
+        let remaining = match (self.session.as_ref(), self.last_progress) {
+            (Some(_), Some(last)) => stall_timeout().checked_sub(now.duration_since(last)),
+            _ => None,
+        };
+        if remaining.is_none() && self.session.is_some() {
+            events.push(Event::Failed("it stalled".to_owned()));
+            self.session = None;
+            self.last_progress = None;
+        }
+        self.stall_check_pending = remaining.is_some();
+        remaining
+    }
+
+    /// Advances the match of the header that starts a transfer,
+    /// returning the frame type once the header is recognized
+    fn detect(&mut self, byte: u8) -> Option<u8> {
+        if self.matched == HEX_HEADER_START.len() {
+            self.matched = 0;
+            return match byte {
+                b'0' => Some(ZRQINIT),
+                b'1' => Some(ZRINIT),
+                _ => None,
+            };
+        }
+        self.matched = if byte == HEX_HEADER_START[self.matched] {
+            self.matched + 1
+        } else if byte == ZPAD {
+            // Any number of ZPADs may precede the header
+            if self.matched == 2 {
+                2
+            } else {
+                1
+            }
+        } else {
+            0
+        };
+        None
+    }
+
+    fn start(
+        &mut self,
+        kind: u8,
+        policy: ZmodemPolicy,
+        replies: &mut Vec<u8>,
+        events: &mut Vec<Event>,
+    ) -> Session {
+        if kind == ZRQINIT && policy == ZmodemPolicy::Receive {
+            return Session::Receiving(Receiver::new());
+        }
+        replies.extend_from_slice(CANCEL_SEQUENCE);
+        events.push(if kind == ZRQINIT {
+            Event::SendCancelled
+        } else {
+            Event::ReceiveCancelled
+        });
+        Session::Discarding {
+            seen_line_end: false,
+            count: 0,
+        }
+    }
+}
+
+thread_local! {
+    static FILTERS: RefCell<HashMap<TabId, ZmodemFilter>> = RefCell::new(HashMap::new());
+    static NOTIFICATIONS: RefCell<Vec<(WindowId, String)>> = RefCell::new(vec![]);
+}
+
+/// Called with the output of `tab_id` before it is parsed, returning
+/// the part of it that should be parsed.  Replies to the program
+/// running in the tab are written to `writer`.
+/// This must be called on the gui thread.
+pub fn filter_output(tab_id: TabId, data: Vec<u8>, writer: &mut Write) -> Vec<u8> {
+    let mux = Mux::get().unwrap();
+    let policy = mux.config().zmodem_policy;
+
+    let mut replies = vec![];
+    let mut events = vec![];
+    let (output, needs_stall_check) = FILTERS.with(|filters| {
+        let mut filters = filters.borrow_mut();
+        let filter = filters.entry(tab_id).or_insert_with(ZmodemFilter::default);
+        let output = filter.filter(data, policy, &mut replies, &mut events);
+        (output, filter.needs_stall_check())
+    });
+
+    if !replies.is_empty() {
+        if let Err(err) = writer.write_all(&replies).and_then(|_| writer.flush()) {
+            eprintln!("failed to reply to zmodem in tab {}: {}", tab_id, err);
+        }
+    }
+    if needs_stall_check {
+        schedule_stall_check(tab_id, stall_timeout());
+    }
+    report_events(tab_id, events);
+
+    output
+}
+
+/// Arranges for the session of `tab_id` to be checked for stalling
+/// once `delay` has elapsed; the sender may have gone away without
+/// producing any more output that would let us notice
+fn schedule_stall_check(tab_id: TabId, delay: Duration) {
+    if let Some(executor) = gui_executor() {
+        schedule_timer(executor, delay, move || {
+            let mut events = vec![];
+            let remaining = FILTERS.with(|filters| {
+                filters
+                    .borrow_mut()
+                    .get_mut(&tab_id)
+                    .and_then(|filter| filter.check_stalled(Instant::now(), &mut events))
+            });
+            if let Some(remaining) = remaining {
+                schedule_stall_check(tab_id, remaining);
+            }
+            report_events(tab_id, events);
+        });
+    }
+}
+
+/// Offers the received files for download and tells the user about
+/// the other events
+fn report_events(tab_id: TabId, events: Vec<Event>) {
+    let mux = Mux::get().unwrap();
+    for event in events {
+        let message = match event {
+            Event::Received { name, data } => {
+                transfer::offer(tab_id, Some(&name), &data);
+                continue;
+            }
+            Event::SendCancelled => format!(
+                "Cancelled a file that tab {} tried to send with ZMODEM, \
+                 as zmodem_policy is Cancel",
+                tab_id
+            ),
+            Event::ReceiveCancelled => format!(
+                "Cancelled the ZMODEM transfer that tab {} started with rz; \
+                 sending files to rz isn't supported",
+                tab_id
+            ),
+            Event::Failed(reason) => {
+                format!("A ZMODEM transfer in tab {} failed: {}", tab_id, reason)
+            }
+        };
+        eprintln!("{}", message);
+        if let Some(window_id) = mux.window_containing_tab(tab_id) {
+            NOTIFICATIONS.with(|n| n.borrow_mut().push((window_id, message)));
+            mux.notify_output();
+        }
+    }
+}
+
+/// Removes and returns the transfer notifications for `window_id`
+pub fn take_notifications(window_id: WindowId) -> Vec<String> {
+    NOTIFICATIONS.with(|n| {
+        let mut n = n.borrow_mut();
+        let (mine, others): (Vec<_>, Vec<_>) = n.drain(..).partition(|(id, _)| *id == window_id);
+        *n = others;
+        mine.into_iter().map(|(_, message)| message).collect()
+    })
+}
+
+/// Discards the transfer state of a tab that has been removed
+pub fn forget(tab_id: TabId) {
+    FILTERS.with(|filters| {
+        filters.borrow_mut().remove(&tab_id);
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Escapes `bytes` in the way that `sz` does
+    fn escape(bytes: &[u8]) -> Vec<u8> {
+        let mut out = vec![];
+        for &b in bytes {
+            match b {
+                ZDLE | XON | XOFF | 0x91 | 0x93 | 0x10 | 0x90 => {
+                    out.push(ZDLE);
+                    out.push(b ^ 0x40);
+                }
+                _ => out.push(b),
+            }
+        }
+        out
+    }
+
+    fn binary32_header(kind: u8, data: [u8; 4]) -> Vec<u8> {
+        let mut bytes = vec![kind];
+        bytes.extend_from_slice(&data);
+        let crc = crc32(&bytes);
+        bytes.extend_from_slice(&[
+            crc as u8,
+            (crc >> 8) as u8,
+            (crc >> 16) as u8,
+            (crc >> 24) as u8,
+        ]);
+        let mut out = vec![ZPAD, ZDLE, ZBIN32];
+        out.extend(escape(&bytes));
+        out
+    }
+
+    fn subpacket32(data: &[u8], end: u8) -> Vec<u8> {
+        let mut checked = data.to_vec();
+        checked.push(end);
+        let crc = crc32(&checked);
+        let mut out = escape(data);
+        out.push(ZDLE);
+        out.push(end);
+        out.extend(escape(&[
+            crc as u8,
+            (crc >> 8) as u8,
+            (crc >> 16) as u8,
+            (crc >> 24) as u8,
+        ]));
+        out
+    }
+
+    #[test]
+    fn receive() {
+        let contents = b"hello\x18world\x11\n".to_vec();
+        let mut stream = b"before".to_vec();
+        stream.extend(b"rz\r");
+        stream.extend(hex_header(ZRQINIT, [0; 4]));
+        stream.extend(binary32_header(ZFILE, [0; 4]));
+        stream.extend(subpacket32(b"greeting.txt\x0012 0 100644\x00", ZCRCW));
+        stream.extend(binary32_header(ZDATA, position_data(0)));
+        stream.extend(subpacket32(&contents[..6], ZCRCG));
+        stream.extend(subpacket32(&contents[6..], ZCRCE));
+        stream.extend(binary32_header(ZEOF, position_data(contents.len())));
+        stream.extend(hex_header(ZFIN, [0; 4]));
+        stream.extend(b"OOafter");
+
+        // Deliver it in small pieces to show that frames may be split
+        let mut filter = ZmodemFilter::default();
+        let mut output = vec![];
+        let mut replies = vec![];
+        let mut events = vec![];
+        for chunk in stream.chunks(7) {
+            output.extend(filter.filter(
+                chunk.to_vec(),
+                ZmodemPolicy::Receive,
+                &mut replies,
+                &mut events,
+            ));
+        }
+
+        assert_eq!(String::from_utf8_lossy(&output), "beforerz\rafter");
+        assert_eq!(
+            events,
+            vec![Event::Received {
+                name: "greeting.txt".to_owned(),
+                data: contents,
+            }]
+        );
+
+        let mut expected = hex_header(ZRINIT, [0, 0, 0, RECEIVER_FLAGS]);
+        expected.extend(hex_header(ZRPOS, position_data(0)));
+        expected.extend(hex_header(ZRINIT, [0, 0, 0, RECEIVER_FLAGS]));
+        expected.extend(hex_header(ZFIN, [0; 4]));
+        assert_eq!(replies, expected);
+    }
+
+    #[test]
+    fn cancel() {
+        let mut stream = hex_header(ZRINIT, [0, 0, 0, RECEIVER_FLAGS]);
+        stream.extend(b"$ ");
+
+        let mut filter = ZmodemFilter::default();
+        let mut replies = vec![];
+        let mut events = vec![];
+        let output = filter.filter(stream, ZmodemPolicy::Receive, &mut replies, &mut events);
+
+        assert_eq!(output, b"$ ");
+        assert_eq!(replies, CANCEL_SEQUENCE);
+        assert_eq!(events, vec![Event::ReceiveCancelled]);
+    }
+
+    #[test]
+    fn corrupt() {
+        let mut stream = hex_header(ZRQINIT, [0; 4]);
+        let mut header = binary32_header(ZFILE, [0; 4]);
+        // Change its type from ZFILE to ZSKIP
+        header[3] ^= 1;
+        stream.extend(header);
+        stream.extend(b"$ ");
+
+        let mut filter = ZmodemFilter::default();
+        let mut replies = vec![];
+        let mut events = vec![];
+        let output = filter.filter(stream, ZmodemPolicy::Receive, &mut replies, &mut events);
+
+        assert_eq!(output, b"$ ");
+        assert!(replies.ends_with(CANCEL_SEQUENCE));
+        assert_eq!(
+            events,
+            vec![Event::Failed("the data was corrupted".to_owned())]
+        );
+    }
+
+    #[test]
+    fn interrupted() {
+        // The sender went away, and the shell printed its prompt
+        let mut stream = hex_header(ZRQINIT, [0; 4]);
+        stream.extend(b"$ ");
+
+        let mut filter = ZmodemFilter::default();
+        let mut replies = vec![];
+        let mut events = vec![];
+        let output = filter.filter(stream, ZmodemPolicy::Receive, &mut replies, &mut events);
+
+        assert_eq!(output, b"$ ");
+        assert_eq!(replies, hex_header(ZRINIT, [0, 0, 0, RECEIVER_FLAGS]));
+        assert_eq!(events, vec![Event::Failed("it was interrupted".to_owned())]);
+    }
+
+    #[test]
+    fn oversized_subpacket() {
+        let mut stream = hex_header(ZRQINIT, [0; 4]);
+        stream.extend(binary32_header(ZFILE, [0; 4]));
+        stream.extend(vec![b'x'; MAX_SUBPACKET_SIZE + 1]);
+
+        let mut filter = ZmodemFilter::default();
+        let mut replies = vec![];
+        let mut events = vec![];
+        let output = filter.filter(stream, ZmodemPolicy::Receive, &mut replies, &mut events);
+
+        assert!(output.is_empty());
+        assert!(replies.ends_with(CANCEL_SEQUENCE));
+        assert_eq!(
+            events,
+            vec![Event::Failed("the data was corrupted".to_owned())]
+        );
+    }
+
+    #[test]
+    fn stalled() {
+        let mut filter = ZmodemFilter::default();
+        let mut replies = vec![];
+        let mut events = vec![];
+        filter.filter(
+            hex_header(ZRQINIT, [0; 4]),
+            ZmodemPolicy::Receive,
+            &mut replies,
+            &mut events,
+        );
+        let start = Instant::now();
+        assert!(filter.needs_stall_check());
+        assert!(!filter.needs_stall_check());
+
+        let remaining = filter.check_stalled(start + Duration::from_secs(10), &mut events);
+        assert!(remaining.unwrap() <= Duration::from_secs(STALL_TIMEOUT_SECS - 10));
+        assert!(events.is_empty());
+
+        let later = start + Duration::from_secs(STALL_TIMEOUT_SECS + 1);
+        assert_eq!(filter.check_stalled(later, &mut events), None);
+        assert_eq!(events, vec![Event::Failed("it stalled".to_owned())]);
+        assert!(!filter.needs_stall_check());
+    }
+}
@@ -1,8 +1,10 @@
 //! This module is responsible for rendering a terminal to an OpenGL context
 
-use super::textureatlas::{Atlas, Sprite, SpriteSlice, TEX_SIZE};
-use crate::config::TextStyle;
-use crate::font::{FontConfiguration, GlyphInfo};
+use super::textureatlas::{AtlasPages, Sprite, SpriteSlice, TEX_SIZE};
+use crate::config::{Config, HsbTransform, TextBlink, TextStyle};
+use crate::font::{FontConfiguration, GlyphInfo, StyleId};
+use crate::frontend::guicommon::highcontrast;
+use crate::frontend::guicommon::scrollbar::compute_thumb;
 use crate::mux::renderable::Renderable;
 use euclid;
 use failure::{err_msg, Error};
@@ -15,21 +17,29 @@ use std::collections::HashMap;
 use std::mem;
 use std::ops::{Deref, Range};
 use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use term::color::RgbaTuple;
-use term::{self, CursorPosition, Line, Underline};
+use term::highlight::highlight_colors;
+use term::{self, Blink, CursorPosition, Line, LineHighlight, Underline};
+use termwiz::cellcluster::CellCluster;
 
 type Transform3D = euclid::Transform3D<f32>;
 
 #[derive(Copy, Clone, Debug)]
 struct Point(euclid::Point2D<f32>);
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 struct GlyphKey {
     font_idx: usize,
     glyph_pos: u32,
-    style: TextStyle,
+    style: StyleId,
 }
 
+/// The maximum number of shaped clusters that are retained
+/// in the shape cache
+const SHAPE_CACHE_SIZE: usize = 4096;
+
 /// Caches a rendered glyph.
 /// The image data may be None for whitespace glyphs.
 #[derive(Debug)]
@@ -43,6 +53,13 @@ struct CachedGlyph {
     scale: f64,
 }
 
+impl CachedGlyph {
+    /// Returns the atlas page that holds the glyph image, if any
+    fn page(&self) -> Option<usize> {
+        self.texture.as_ref().map(|sprite| sprite.page)
+    }
+}
+
 impl Default for Point {
     fn default() -> Point {
         Point::new(0.0, 0.0)
@@ -79,6 +96,10 @@ const V_TOP_RIGHT: usize = 1;
 const V_BOT_LEFT: usize = 2;
 const V_BOT_RIGHT: usize = 3;
 
+/// The opacity of the column guides and the cursor crosshair, which are
+/// drawn in the foreground color; faint enough not to hide the text
+const GUIDE_ALPHA: f32 = 0.08;
+
 #[derive(Copy, Clone, Debug, Default)]
 struct Vertex {
     // pre-computed by compute_vertices and changed only on resize
@@ -96,6 +117,9 @@ struct Vertex {
     underline: f32,
     strikethrough: f32,
     v_idx: f32,
+    /// The atlas page that holds the glyph image, or -1.0 if
+    /// the cell has no glyph to draw
+    tex_page: f32,
 }
 
 implement_vertex!(
@@ -109,6 +133,7 @@ implement_vertex!(
     underline,
     strikethrough,
     v_idx,
+    tex_page,
 );
 
 struct ShaderSource {
@@ -144,10 +169,12 @@ in vec4 bg_color;
 in float has_color;
 in float underline;
 in float v_idx;
+in float tex_page;
 
 uniform mat4 projection;
 uniform mat4 translation;
 uniform bool bg_and_line_layer;
+uniform float glyph_page;
 
 out vec2 tex_coords;
 out vec2 underline_coords;
@@ -159,7 +186,7 @@ out float o_underline;
 // Offset from the RHS texture coordinate to the LHS.
 // This is an underestimation to avoid the shader interpolating
 // the underline gylph into its neighbor.
-const float underline_offset = (1.0 / 5.0);
+const float underline_offset = (1.0 / {u_cols:.1});
 
 void main() {{
     o_fg_color = fg_color;
@@ -188,18 +215,25 @@ void main() {{
             }}
         }}
 
+    }} else if (tex_page != glyph_page) {{
+        // The glyph is held in a different atlas page and will be
+        // drawn by the pass for that page.  Collapse the quad so
+        // that nothing is rasterized for it in this pass.
+        gl_Position = vec4(-2.0, -2.0, 0.0, 1.0);
+        tex_coords = vec2(0.0, 0.0);
     }} else {{
         gl_Position = projection * vec4(position + adjust, 0.0, 1.0);
         tex_coords = tex;
     }}
 }}
     "#,
-        version = src.version
+        version = src.version,
+        u_cols = U_COLS
     )
 }
 
 /// How many columns the underline texture has
-const U_COLS: f32 = 5.0;
+const U_COLS: f32 = 11.0;
 /// The glyph has no underline or strikethrough
 const U_NONE: f32 = 0.0;
 /// The glyph has a single underline.  This value is actually the texture
@@ -213,6 +247,8 @@ const U_STRIKE: f32 = 3.0 / U_COLS;
 const U_STRIKE_ONE: f32 = 4.0 / U_COLS;
 /// Texture coord for the RHS of the strikethrough + double underline glyph
 const U_STRIKE_TWO: f32 = 5.0 / U_COLS;
+/// Added to any of the above to select the same glyph with an overline
+const U_OVERLINE: f32 = 6.0 / U_COLS;
 
 fn fragment_shader() -> String {
     let src = ShaderSource::new();
@@ -280,6 +316,106 @@ void main() {{
     )
 }
 
+/// Linearly interpolate from color `a` towards color `b`; an `amount`
+/// of 0.0 yields `a` and 1.0 yields `b`.  The alpha of `a` is preserved.
+fn mix_colors(a: RgbaTuple, b: RgbaTuple, amount: f32) -> RgbaTuple {
+    let mix = |x: f32, y: f32| x + (y - x) * amount;
+    (mix(a.0, b.0), mix(a.1, b.1), mix(a.2, b.2), a.3)
+}
+
+/// Compute the relative luminance of an sRGB color, as defined by WCAG
+fn relative_luminance(color: RgbaTuple) -> f32 {
+    fn linear(c: f32) -> f32 {
+        if c <= 0.039_28 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    }
+    let (r, g, b, _) = color;
+    0.2126 * linear(r) + 0.7152 * linear(g) + 0.0722 * linear(b)
+}
+
+/// Compute the WCAG contrast ratio between two colors
+fn contrast_ratio(a: RgbaTuple, b: RgbaTuple) -> f32 {
+    let a = relative_luminance(a);
+    let b = relative_luminance(b);
+    let (lighter, darker) = if a > b { (a, b) } else { (b, a) };
+    (lighter + 0.05) / (darker + 0.05)
+}
+
+/// Scale the hue, saturation and brightness of `color` by the
+/// multipliers in `transform`.  The alpha of `color` is preserved.
+fn apply_hsb(color: RgbaTuple, transform: &HsbTransform) -> RgbaTuple {
+    let (r, g, b, a) = color;
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+
+    let hue = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / delta)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    let saturation = if max == 0.0 { 0.0 } else { delta / max };
+
+    let mut hue = (hue * transform.hue) % 360.0;
+    if hue < 0.0 {
+        hue += 360.0;
+    }
+    let saturation = (saturation * transform.saturation).max(0.0).min(1.0);
+    let value = (max * transform.brightness).max(0.0).min(1.0);
+
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - ((hue / 60.0) % 2.0 - 1.0).abs());
+    let (r, g, b) = match (hue / 60.0) as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = value - chroma;
+    (r + m, g + m, b + m, a)
+}
+
+/// Adjust `fg` so that it has at least `min_contrast` against `bg`.
+/// The foreground is blended towards white or black, whichever is
+/// further from the background, by the smallest amount that satisfies
+/// the constraint.
+fn ensure_contrast(fg: RgbaTuple, bg: RgbaTuple, min_contrast: f32) -> RgbaTuple {
+    if contrast_ratio(fg, bg) >= min_contrast {
+        return fg;
+    }
+
+    let target = if relative_luminance(bg) < 0.18 {
+        (1.0, 1.0, 1.0, 1.0)
+    } else {
+        (0.0, 0.0, 0.0, 1.0)
+    };
+    let blend = |amount: f32| mix_colors(fg, target, amount);
+
+    // Binary search for the smallest blend amount that meets the
+    // desired contrast; if even the extreme doesn't meet it, we'll
+    // end up with pure white or black.
+    let mut low = 0.0;
+    let mut high = 1.0;
+    for _ in 0..8 {
+        let mid = (low + high) / 2.0;
+        if contrast_ratio(blend(mid), bg) >= min_contrast {
+            high = mid;
+        } else {
+            low = mid;
+        }
+    }
+    blend(high)
+}
+
 pub struct Renderer {
     width: u16,
     height: u16,
@@ -288,13 +424,50 @@ pub struct Renderer {
     cell_width: f64,
     descender: f64,
     glyph_cache: RefCell<HashMap<GlyphKey, Rc<CachedGlyph>>>,
+    /// Caches the result of shaping the text of a cluster, so that
+    /// repainting an unchanged line doesn't need to consult harfbuzz.
+    /// This is indexed by `StyleId` so that it can be probed using
+    /// the text of the cluster without copying it.
+    shape_cache: RefCell<Vec<HashMap<String, Rc<Vec<GlyphInfo>>>>>,
+    /// Scratch space for the clusters of the line being rendered,
+    /// which is retained to avoid allocating it for every line
+    cluster_scratch: RefCell<Vec<CellCluster>>,
     program: glium::Program,
     glyph_vertex_buffer: RefCell<VertexBuffer<Vertex>>,
     glyph_index_buffer: IndexBuffer<u32>,
     projection: Transform3D,
-    atlas: RefCell<Atlas>,
+    atlas: RefCell<AtlasPages>,
+    /// The atlas page used by each cell of the viewport, indexed by
+    /// `line_idx * num_cols + cell_idx`.  This is used to maintain the
+    /// reference counts that prevent evicting a page that is in use.
+    cell_pages: RefCell<Vec<Option<usize>>>,
     underline_tex: SrgbTexture2d,
     palette: term::color::ColorPalette,
+    config: Arc<Config>,
+    /// Whether to render in high contrast; see `highcontrast`
+    high_contrast: bool,
+    /// The minimum contrast ratio of text against its background
+    min_contrast: f32,
+    scrollbar_vertex_buffer: RefCell<VertexBuffer<Vertex>>,
+    scrollbar_index_buffer: IndexBuffer<u32>,
+    /// The column guides and cursor crosshair; see `update_guides`
+    guide_vertex_buffer: RefCell<VertexBuffer<Vertex>>,
+    guide_index_buffer: IndexBuffer<u32>,
+    /// Blinking text is timed relative to this
+    blink_epoch: Instant,
+    /// The (slow, rapid) visibility of blinking text in the most
+    /// recent paint
+    painted_blink_phase: (bool, bool),
+    /// Whether each line of the viewport held blinking text when
+    /// it was last rendered
+    blinking_lines: RefCell<Vec<bool>>,
+    /// The cursor position in the most recent paint.  The cell it
+    /// occupied needs to be redrawn when the cursor moves.
+    painted_cursor: Option<CursorPosition>,
+    /// Set when the vertex buffer or glyph textures have been
+    /// replaced, which means that dirty lines must be redrawn in
+    /// their entirety rather than just their damaged cells.
+    full_damage: bool,
 }
 
 impl Renderer {
@@ -303,8 +476,21 @@ impl Renderer {
         width: u16,
         height: u16,
         fonts: &Rc<FontConfiguration>,
-        palette: term::color::ColorPalette,
+        config: &Arc<Config>,
     ) -> Result<Self, Error> {
+        let mut palette = config
+            .colors
+            .as_ref()
+            .map(|p| p.clone().into())
+            .unwrap_or_else(term::color::ColorPalette::default);
+        let high_contrast = highcontrast::is_enabled(config);
+        let min_contrast = if high_contrast {
+            highcontrast::apply_to_palette(&mut palette);
+            config.min_contrast.max(highcontrast::MIN_CONTRAST)
+        } else {
+            config.min_contrast
+        };
+
         let metrics = fonts.default_font_metrics()?;
         let (cell_height, cell_width, descender) =
             (metrics.cell_height, metrics.cell_width, metrics.descender);
@@ -313,7 +499,13 @@ impl Renderer {
             cell_height, cell_width, descender
         );
 
-        let underline_tex = Self::compute_underlines(facade, cell_width, cell_height, descender)?;
+        let underline_tex = Self::compute_underlines(
+            facade,
+            cell_width,
+            cell_height,
+            descender,
+            highcontrast::line_thickness(high_contrast, cell_height),
+        )?;
 
         let (glyph_vertex_buffer, glyph_index_buffer) = Self::compute_vertices(
             facade,
@@ -335,12 +527,30 @@ impl Renderer {
         };
         let program = glium::Program::new(facade, source)?;
 
-        let atlas = RefCell::new(Atlas::new(facade, TEX_SIZE)?);
+        let atlas = RefCell::new(AtlasPages::new(facade, TEX_SIZE)?);
+
+        let (scrollbar_vertex_buffer, scrollbar_index_buffer) =
+            Self::compute_quad_vertices(facade, 1)?;
+        let (guide_vertex_buffer, guide_index_buffer) =
+            Self::compute_quad_vertices(facade, Self::num_guides(config).max(1))?;
 
         Ok(Self {
             atlas,
+            cell_pages: RefCell::new(vec![]),
             program,
             palette,
+            config: Arc::clone(config),
+            high_contrast,
+            min_contrast,
+            scrollbar_vertex_buffer: RefCell::new(scrollbar_vertex_buffer),
+            scrollbar_index_buffer,
+            guide_vertex_buffer: RefCell::new(guide_vertex_buffer),
+            guide_index_buffer,
+            blink_epoch: Instant::now(),
+            painted_blink_phase: (true, true),
+            blinking_lines: RefCell::new(vec![]),
+            painted_cursor: None,
+            full_damage: true,
             glyph_vertex_buffer: RefCell::new(glyph_vertex_buffer),
             glyph_index_buffer,
             width,
@@ -350,6 +560,8 @@ impl Renderer {
             cell_width,
             descender,
             glyph_cache: RefCell::new(HashMap::new()),
+            shape_cache: RefCell::new(vec![]),
+            cluster_scratch: RefCell::new(vec![]),
             projection: Self::compute_projection(f32::from(width), f32::from(height)),
             underline_tex,
         })
@@ -357,12 +569,14 @@ impl Renderer {
 
     /// Create the texture atlas for the line decoration layer.
     /// This is a bitmap with columns to accomodate the U_XXX
-    /// constants defined above.
+    /// constants defined above.  Each line is drawn `thickness`
+    /// pixels tall.
     fn compute_underlines<F: Facade>(
         facade: &F,
         cell_width: f64,
         cell_height: f64,
         descender: f64,
+        thickness: usize,
     ) -> Result<SrgbTexture2d, glium::texture::TextureCreationError> {
         let cell_width = cell_width.ceil() as usize;
         let cell_height = cell_height.ceil() as usize;
@@ -372,63 +586,51 @@ impl Renderer {
             (descender / 64.0).floor() as isize
         };
 
-        let width = 5 * cell_width;
+        let width = U_COLS as usize * cell_width;
         let mut underline_data = vec![0u8; width * cell_height * 4];
 
         let descender_row = (cell_height as isize + descender) as usize;
         let descender_plus_one = (1 + descender_row).min(cell_height - 1);
-        let descender_plus_two = (2 + descender_row).min(cell_height - 1);
+        // The gap between the lines of a double underline is the
+        // same as their thickness
+        let descender_plus_two = (2 * thickness + descender_row).min(cell_height - 1);
         let strike_row = descender_row / 2;
 
-        // First, the single underline.
-        // We place this just under the descender position.
-        {
-            let col = 0;
-            let offset = ((width * 4) * descender_plus_one) + (col * 4 * cell_width);
-            for i in 0..4 * cell_width {
-                underline_data[offset + i] = 0xff;
-            }
-        }
-        // Double underline,
-        // We place this at and just below the descender
-        {
-            let col = 1;
-            let offset_one = ((width * 4) * (descender_row)) + (col * 4 * cell_width);
-            let offset_two = ((width * 4) * (descender_plus_two)) + (col * 4 * cell_width);
-            for i in 0..4 * cell_width {
-                underline_data[offset_one + i] = 0xff;
-                underline_data[offset_two + i] = 0xff;
-            }
-        }
-        // Strikethrough
-        {
-            let col = 2;
-            let offset = (width * 4) * strike_row + (col * 4 * cell_width);
-            for i in 0..4 * cell_width {
-                underline_data[offset + i] = 0xff;
-            }
-        }
-        // Strikethrough and single underline
-        {
-            let col = 3;
-            let offset_one = ((width * 4) * descender_plus_one) + (col * 4 * cell_width);
-            let offset_two = ((width * 4) * strike_row) + (col * 4 * cell_width);
-            for i in 0..4 * cell_width {
-                underline_data[offset_one + i] = 0xff;
-                underline_data[offset_two + i] = 0xff;
+        // Set the pixels of the line that starts at `row` in column `col`,
+        // extending it upwards if it would otherwise overflow the cell
+        let mut draw_line = |col: usize, row: usize| {
+            let row = row.min(cell_height.saturating_sub(thickness));
+            for row in row..(row + thickness).min(cell_height) {
+                let offset = ((width * 4) * row) + (col * 4 * cell_width);
+                for i in 0..4 * cell_width {
+                    underline_data[offset + i] = 0xff;
+                }
             }
-        }
-        // Strikethrough and double underline
-        {
-            let col = 4;
-            let offset_one = ((width * 4) * (descender_row)) + (col * 4 * cell_width);
-            let offset_two = ((width * 4) * strike_row) + (col * 4 * cell_width);
-            let offset_three = ((width * 4) * (descender_plus_two)) + (col * 4 * cell_width);
-            for i in 0..4 * cell_width {
-                underline_data[offset_one + i] = 0xff;
-                underline_data[offset_two + i] = 0xff;
-                underline_data[offset_three + i] = 0xff;
+        };
+
+        let columns: [&[usize]; 5] = [
+            // First, the single underline.
+            // We place this just under the descender position.
+            &[descender_plus_one],
+            // Double underline,
+            // We place this at and just below the descender
+            &[descender_row, descender_plus_two],
+            // Strikethrough
+            &[strike_row],
+            // Strikethrough and single underline
+            &[descender_plus_one, strike_row],
+            // Strikethrough and double underline
+            &[descender_row, strike_row, descender_plus_two],
+        ];
+        // The overline is placed at the top of the cell; the column
+        // for it alone is followed by the columns above combined with it
+        draw_line(5, 0);
+        for (col, rows) in columns.iter().enumerate() {
+            for &row in rows.iter() {
+                draw_line(col, row);
+                draw_line(col + 6, row);
             }
+            draw_line(col + 6, 0);
         }
 
         glium::texture::SrgbTexture2d::new(
@@ -447,16 +649,30 @@ impl Renderer {
         self.descender = metrics.descender;
 
         self.glyph_cache.borrow_mut().clear();
-        self.atlas = RefCell::new(Atlas::new(facade, TEX_SIZE)?);
-        self.underline_tex =
-            Self::compute_underlines(facade, self.cell_width, self.cell_height, self.descender)?;
+        self.shape_cache.borrow_mut().clear();
+        self.atlas = RefCell::new(AtlasPages::new(facade, TEX_SIZE)?);
+        self.cell_pages.borrow_mut().clear();
+        self.full_damage = true;
+        self.underline_tex = Self::compute_underlines(
+            facade,
+            self.cell_width,
+            self.cell_height,
+            self.descender,
+            highcontrast::line_thickness(self.high_contrast, self.cell_height),
+        )?;
         Ok(())
     }
 
+    /// Discard all of the atlas pages and the glyphs they hold.
+    /// This is the fallback for when a glyph is too large to fit in
+    /// a page of the current size, or when every page is in use by
+    /// the content on screen; the caller must repaint everything.
     pub fn recreate_atlas<F: Facade>(&mut self, facade: &F, size: u32) -> Result<(), Error> {
-        let atlas = RefCell::new(Atlas::new(facade, size)?);
+        let atlas = RefCell::new(AtlasPages::new(facade, size)?);
         self.atlas = atlas;
         self.glyph_cache.borrow_mut().clear();
+        self.cell_pages.borrow_mut().clear();
+        self.full_damage = true;
         Ok(())
     }
 
@@ -477,31 +693,86 @@ impl Renderer {
         self.glyph_vertex_buffer = RefCell::new(glyph_vertex_buffer);
         self.glyph_index_buffer = glyph_index_buffer;
 
+        // The new vertex buffer doesn't reference any glyphs until
+        // it is repainted
+        self.cell_pages.borrow_mut().clear();
+        self.atlas.borrow_mut().clear_refs();
+        self.full_damage = true;
+
         Ok(())
     }
 
+    /// Shape `text`, returning the cached result if the same text
+    /// has previously been shaped with the same style.
+    fn cached_shape(
+        &self,
+        text: &str,
+        style_id: StyleId,
+        style: &TextStyle,
+    ) -> Result<Rc<Vec<GlyphInfo>>, Error> {
+        if let Some(entry) = self
+            .shape_cache
+            .borrow()
+            .get(style_id)
+            .and_then(|cache| cache.get(text))
+        {
+            return Ok(Rc::clone(entry));
+        }
+
+        let glyph_info = Rc::new(self.fonts.shape(style, text)?);
+
+        let mut cache = self.shape_cache.borrow_mut();
+        if cache.iter().map(HashMap::len).sum::<usize>() >= SHAPE_CACHE_SIZE {
+            // Rather than tracking usage, start afresh; the content
+            // that is currently on screen will quickly repopulate it
+            cache.clear();
+        }
+        if cache.len() <= style_id {
+            cache.resize(style_id + 1, HashMap::new());
+        }
+        cache[style_id].insert(text.to_owned(), Rc::clone(&glyph_info));
+        Ok(glyph_info)
+    }
+
     /// Resolve a glyph from the cache, rendering the glyph on-demand if
     /// the cache doesn't already hold the desired glyph.
-    fn cached_glyph(&self, info: &GlyphInfo, style: &TextStyle) -> Result<Rc<CachedGlyph>, Error> {
+    fn cached_glyph(
+        &self,
+        info: &GlyphInfo,
+        style_id: StyleId,
+        style: &TextStyle,
+    ) -> Result<Rc<CachedGlyph>, Error> {
         let key = GlyphKey {
             font_idx: info.font_idx,
             glyph_pos: info.glyph_pos,
-            style: style.clone(),
+            style: style_id,
         };
 
-        let mut cache = self.glyph_cache.borrow_mut();
-
-        if let Some(entry) = cache.get(&key) {
+        if let Some(entry) = self.glyph_cache.borrow().get(&key) {
+            if let Some(page) = entry.page() {
+                self.atlas.borrow_mut().touch(page);
+            }
             return Ok(Rc::clone(entry));
         }
 
-        let glyph = self.load_glyph(info, style)?;
+        let (glyph, evicted) = self.load_glyph(info, style)?;
+        let mut cache = self.glyph_cache.borrow_mut();
+        if let Some(evicted) = evicted {
+            // The glyphs held in the evicted page are no longer valid
+            cache.retain(|_, glyph| glyph.page() != Some(evicted));
+        }
         cache.insert(key, Rc::clone(&glyph));
         Ok(glyph)
     }
 
-    /// Perform the load and render of a glyph
-    fn load_glyph(&self, info: &GlyphInfo, style: &TextStyle) -> Result<Rc<CachedGlyph>, Error> {
+    /// Perform the load and render of a glyph.
+    /// Also returns the atlas page that was evicted to make room
+    /// for the glyph, if any.
+    fn load_glyph(
+        &self,
+        info: &GlyphInfo,
+        style: &TextStyle,
+    ) -> Result<(Rc<CachedGlyph>, Option<usize>), Error> {
         let (has_color, glyph, cell_width, cell_height) = {
             let font = self.fonts.cached_font(style)?;
             let mut font = font.borrow_mut();
@@ -526,6 +797,7 @@ impl Renderer {
             (info.x_offset, info.y_offset)
         };
 
+        let mut evicted = None;
         let glyph = if glyph.width == 0 || glyph.height == 0 {
             // a whitespace glyph
             CachedGlyph {
@@ -543,10 +815,11 @@ impl Renderer {
                 (glyph.width as u32, glyph.height as u32),
             );
 
-            let tex = self
-                .atlas
-                .borrow_mut()
-                .allocate(raw_im.width, raw_im.height, raw_im)?;
+            let (tex, evicted_page) =
+                self.atlas
+                    .borrow_mut()
+                    .allocate(raw_im.width, raw_im.height, raw_im)?;
+            evicted = evicted_page;
 
             let bearing_x = glyph.bearing_x * scale;
             let bearing_y = glyph.bearing_y * scale;
@@ -562,7 +835,33 @@ impl Renderer {
             }
         };
 
-        Ok(Rc::new(glyph))
+        Ok((Rc::new(glyph), evicted))
+    }
+
+    /// Record the atlas page used by a cell of the viewport, adjusting
+    /// the reference counts of the pages that it used before and after
+    fn set_cell_page(
+        &self,
+        line_idx: usize,
+        cell_idx: usize,
+        num_cols: usize,
+        page: Option<usize>,
+    ) {
+        let idx = line_idx * num_cols + cell_idx;
+        let mut cell_pages = self.cell_pages.borrow_mut();
+        if cell_pages.len() <= idx {
+            cell_pages.resize(idx + 1, None);
+        }
+        if cell_pages[idx] != page {
+            let mut atlas = self.atlas.borrow_mut();
+            if let Some(old) = cell_pages[idx] {
+                atlas.release(old);
+            }
+            if let Some(new) = page {
+                atlas.add_ref(new);
+            }
+            cell_pages[idx] = page;
+        }
     }
 
     /// Compute a vertex buffer to hold the quads that comprise the visible
@@ -633,6 +932,162 @@ impl Renderer {
         ))
     }
 
+    /// Creates `num_quads` quads whose positions are updated on each
+    /// paint, such as the scrollbar thumb
+    fn compute_quad_vertices<F: Facade>(
+        facade: &F,
+        num_quads: usize,
+    ) -> Result<(VertexBuffer<Vertex>, IndexBuffer<u32>), Error> {
+        let mut verts = Vec::new();
+        let mut indices = Vec::new();
+        for _ in 0..num_quads {
+            let idx = verts.len() as u32;
+            for &v_idx in &[V_TOP_LEFT, V_TOP_RIGHT, V_BOT_LEFT, V_BOT_RIGHT] {
+                verts.push(Vertex {
+                    v_idx: v_idx as f32,
+                    ..Default::default()
+                });
+            }
+            indices.extend_from_slice(&[idx, idx + 1, idx + 2, idx + 1, idx + 2, idx + 3]);
+        }
+        Ok((
+            VertexBuffer::dynamic(facade, &verts)?,
+            IndexBuffer::new(facade, glium::index::PrimitiveType::TrianglesList, &indices)?,
+        ))
+    }
+
+    /// Position the scrollbar thumb according to the viewport.
+    /// Returns false if the scrollbar should not be drawn.
+    fn update_scrollbar(&self, term: &Renderable) -> bool {
+        if !self.config.enable_scroll_bar {
+            return false;
+        }
+        let (offset, total) = term.get_scrollbar_info();
+        let (rows, _cols) = term.physical_dimensions();
+        let height = f32::from(self.height);
+        let thumb = match compute_thumb(offset, total, rows, height, self.cell_height as f32) {
+            Some(thumb) => thumb,
+            None => return false,
+        };
+
+        let width = f32::from(self.width);
+        let right = width / 2.0;
+        let left = right - self.cell_width as f32;
+        let top = (height / -2.0) + thumb.top;
+        let bottom = top + thumb.height;
+        let color = self.palette.scrollbar_thumb.to_tuple_rgba();
+
+        let mut vb = self.scrollbar_vertex_buffer.borrow_mut();
+        let mut vert = vb.map();
+        vert[V_TOP_LEFT].position = Point::new(left, top);
+        vert[V_TOP_RIGHT].position = Point::new(right, top);
+        vert[V_BOT_LEFT].position = Point::new(left, bottom);
+        vert[V_BOT_RIGHT].position = Point::new(right, bottom);
+        for v in vert.iter_mut() {
+            v.bg_color = color;
+        }
+        true
+    }
+
+    /// Returns the number of quads needed to draw the guides
+    fn num_guides(config: &Config) -> usize {
+        config.column_guides.len() + if config.cursor_crosshair { 2 } else { 0 }
+    }
+
+    /// Position the column guides and the cursor crosshair, which are
+    /// translucent bands that are drawn between the backgrounds and the
+    /// text of the cells.  Returns false if there are none to draw.
+    fn update_guides(&self, term: &Renderable, cursor: &CursorPosition) -> bool {
+        let num_guides = Self::num_guides(&self.config);
+        if num_guides == 0 {
+            return false;
+        }
+        let (rows, cols) = term.physical_dimensions();
+        let cell_width = (self.cell_width as f32).ceil();
+        let cell_height = (self.cell_height as f32).ceil();
+        let left = f32::from(self.width) / -2.0;
+        let top = f32::from(self.height) / -2.0;
+        let right = left + cols as f32 * cell_width;
+        let bottom = top + rows as f32 * cell_height;
+
+        // (left, top, right, bottom) of each guide
+        let mut rects = vec![];
+        for &col in &self.config.column_guides {
+            if col >= 1 && col <= cols {
+                let x = left + (col - 1) as f32 * cell_width;
+                rects.push((x, top, x + cell_width, bottom));
+            }
+        }
+        if self.config.cursor_crosshair && cursor.y >= 0 && (cursor.y as usize) < rows {
+            let x = left + cursor.x as f32 * cell_width;
+            let y = top + cursor.y as f32 * cell_height;
+            rects.push((left, y, right, y + cell_height));
+            rects.push((x, top, x + cell_width, bottom));
+        }
+
+        let (r, g, b, _) = self.palette.foreground.to_tuple_rgba();
+        let color = (r, g, b, GUIDE_ALPHA);
+
+        let mut vb = self.guide_vertex_buffer.borrow_mut();
+        let mut vert = vb.map();
+        for idx in 0..num_guides {
+            // Guides that aren't in use are collapsed to nothing
+            let (l, t, r, b) = rects.get(idx).cloned().unwrap_or_default();
+            let quad = &mut vert[idx * VERTICES_PER_CELL..(idx + 1) * VERTICES_PER_CELL];
+            quad[V_TOP_LEFT].position = Point::new(l, t);
+            quad[V_TOP_RIGHT].position = Point::new(r, t);
+            quad[V_BOT_LEFT].position = Point::new(l, b);
+            quad[V_BOT_RIGHT].position = Point::new(r, b);
+            for v in quad.iter_mut() {
+                v.bg_color = color;
+            }
+        }
+        !rects.is_empty()
+    }
+
+    /// Returns whether (slow, rapid) blinking text is currently visible
+    fn blink_phase(&self) -> (bool, bool) {
+        let elapsed = self.blink_epoch.elapsed();
+        let elapsed_ms = elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis());
+        let visible = |rate: u64| (elapsed_ms / rate.max(1)) % 2 == 0;
+        (
+            visible(self.config.text_blink_rate_ms),
+            visible(self.config.text_blink_rate_rapid_ms),
+        )
+    }
+
+    pub fn palette(&self) -> &term::color::ColorPalette {
+        &self.palette
+    }
+
+    /// Returns true if there is blinking text on screen whose
+    /// visibility has changed since it was last painted
+    pub fn needs_blink_repaint(&self) -> bool {
+        self.config.text_blink == TextBlink::Blink
+            && self.blink_phase() != self.painted_blink_phase
+            && self.blinking_lines.borrow().iter().any(|&b| b)
+    }
+
+    /// Returns how long until the visibility of blinking text on
+    /// screen next changes, or None if there is no blinking text
+    pub fn time_until_blink_change(&self) -> Option<Duration> {
+        if self.config.text_blink != TextBlink::Blink
+            || !self.blinking_lines.borrow().iter().any(|&b| b)
+        {
+            return None;
+        }
+        let elapsed = self.blink_epoch.elapsed();
+        let elapsed_ms = elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis());
+        let remaining = |rate: u64| {
+            let rate = rate.max(1);
+            rate - elapsed_ms % rate
+        };
+        Some(Duration::from_millis(
+            remaining(self.config.text_blink_rate_ms)
+                .min(remaining(self.config.text_blink_rate_rapid_ms)),
+        ))
+    }
+
     /// The projection corrects for the aspect ratio and flips the y-axis
     fn compute_projection(width: f32, height: f32) -> Transform3D {
         Transform3D::ortho(
@@ -649,10 +1104,13 @@ impl Renderer {
     /// This is nominally a matter of setting the fg/bg color and the
     /// texture coordinates for a given glyph.  There's a little bit
     /// of extra complexity to deal with multi-cell glyphs.
+    /// Only the cells in the `damage` range are updated; the vertices
+    /// for the other cells are left as they were in the prior paint.
     fn render_screen_line(
         &self,
         line_idx: usize,
         line: &Line,
+        damage: Range<usize>,
         selection: Range<usize>,
         cursor: &CursorPosition,
         terminal: &Renderable,
@@ -668,17 +1126,44 @@ impl Renderer {
         };
 
         let current_highlight = terminal.current_highlight();
+        let highlights = terminal.highlights_for_line(line_idx);
+        let reveal_concealed = terminal.is_concealed_revealed();
 
         // Break the line into clusters of cells with the same attributes
-        let cell_clusters = line.cluster();
+        let mut cell_clusters = self.cluster_scratch.borrow_mut();
+        line.cluster_into(&mut cell_clusters);
         let mut last_cell_idx = 0;
-        for cluster in cell_clusters {
-            let attrs = &cluster.attrs;
+        let mut line_has_blink = false;
+        for cluster in cell_clusters.iter() {
+            let blink = cluster.attrs.blink();
+            line_has_blink |= blink != Blink::None;
+
+            // Skip shaping clusters that are entirely outside of the
+            // damaged region.  The cluster may end with a double-wide
+            // cell, so allow for that when testing for an intersection.
+            let first_cell_idx = cluster.byte_to_cell_idx[0];
+            let last_cell_idx_in_cluster = cluster.byte_to_cell_idx[cluster.text.len() - 1];
+            if last_cell_idx_in_cluster + 2 <= damage.start || first_cell_idx >= damage.end {
+                last_cell_idx = last_cell_idx_in_cluster.min(num_cols.saturating_sub(1));
+                continue;
+            }
+
+            let italic_attrs;
+            let attrs = if blink != Blink::None && self.config.text_blink == TextBlink::Italic {
+                italic_attrs = {
+                    let mut attrs = cluster.attrs.clone();
+                    attrs.set_italic(true);
+                    attrs
+                };
+                &italic_attrs
+            } else {
+                &cluster.attrs
+            };
             let is_highlited_hyperlink = match (&attrs.hyperlink, &current_highlight) {
                 (&Some(ref this), &Some(ref highlight)) => this == highlight,
                 _ => false,
             };
-            let style = self.fonts.match_style(attrs);
+            let (style_id, style) = self.fonts.match_style_and_id(attrs);
 
             let bg_color = self.palette.resolve_bg(attrs.background);
             let fg_color = match attrs.foreground {
@@ -689,7 +1174,9 @@ impl Renderer {
                         self.palette.resolve_fg(attrs.foreground)
                     }
                 }
-                term::color::ColorAttribute::PaletteIndex(idx) if idx < 8 => {
+                term::color::ColorAttribute::PaletteIndex(idx)
+                    if idx < 8 && self.config.bold_brightens_ansi_colors =>
+                {
                     // For compatibility purposes, switch to a brighter version
                     // of one of the standard ANSI colors when Bold is enabled.
                     // This lifts black to dark grey.
@@ -718,16 +1205,32 @@ impl Renderer {
             let glyph_color = fg_color.to_tuple_rgba();
             let bg_color = bg_color.to_tuple_rgba();
 
-            // Shape the printable text from this cluster
-            let glyph_info = {
-                let font = self.fonts.cached_font(style)?;
-                let mut font = font.borrow_mut();
-                font.shape(&cluster.text)?
+            // Faint text is rendered by blending the foreground color
+            // part way towards the background color
+            let glyph_color = if attrs.intensity() == term::Intensity::Half {
+                mix_colors(glyph_color, bg_color, 0.4)
+            } else {
+                glyph_color
+            };
+
+            // Blinking text is hidden by drawing it in the background color
+            let blink_visible = match (self.config.text_blink, blink) {
+                (TextBlink::Blink, Blink::Slow) => self.painted_blink_phase.0,
+                (TextBlink::Blink, Blink::Rapid) => self.painted_blink_phase.1,
+                _ => true,
             };
+            let glyph_color = if blink_visible { glyph_color } else { bg_color };
+
+            // Concealed text is drawn as though it were whitespace, so
+            // that color glyphs are hidden too
+            let concealed = attrs.invisible() && !reveal_concealed;
+
+            // Shape the printable text from this cluster
+            let glyph_info = self.cached_shape(&cluster.text, style_id, style)?;
 
-            for info in &glyph_info {
+            for info in glyph_info.iter() {
                 let cell_idx = cluster.byte_to_cell_idx[info.cluster as usize];
-                let glyph = self.cached_glyph(info, style)?;
+                let glyph = self.cached_glyph(info, style_id, style)?;
 
                 let left = (glyph.x_offset + glyph.bearing_x) as f32;
                 let top = ((self.cell_height + self.descender) - (glyph.y_offset + glyph.bearing_y))
@@ -756,6 +1259,11 @@ impl Renderer {
                     (false, true, Underline::Single) => U_STRIKE_ONE,
                     (false, true, Underline::Double) => U_STRIKE_TWO,
                 };
+                let underline = if attrs.overline() {
+                    underline + U_OVERLINE
+                } else {
+                    underline
+                };
 
                 // Iterate each cell that comprises this glyph.  There is usually
                 // a single cell per glyph but combining characters, ligatures
@@ -776,6 +1284,7 @@ impl Renderer {
                         cell_idx,
                         cursor,
                         &selection,
+                        &highlights,
                         glyph_color,
                         bg_color,
                     );
@@ -799,7 +1308,7 @@ impl Renderer {
                     vert[V_BOT_RIGHT].underline = underline;
 
                     match glyph.texture {
-                        Some(ref texture) => {
+                        Some(ref texture) if !concealed => {
                             let slice = SpriteSlice {
                                 cell_idx: glyph_idx,
                                 num_cells: info.num_cells as usize,
@@ -834,9 +1343,16 @@ impl Renderer {
                             vert[V_TOP_RIGHT].has_color = has_color;
                             vert[V_BOT_LEFT].has_color = has_color;
                             vert[V_BOT_RIGHT].has_color = has_color;
+
+                            let tex_page = texture.page as f32;
+                            vert[V_TOP_LEFT].tex_page = tex_page;
+                            vert[V_TOP_RIGHT].tex_page = tex_page;
+                            vert[V_BOT_LEFT].tex_page = tex_page;
+                            vert[V_BOT_RIGHT].tex_page = tex_page;
+                            self.set_cell_page(line_idx, cell_idx, num_cols, Some(texture.page));
                         }
-                        None => {
-                            // Whitespace; no texture to render
+                        _ => {
+                            // Whitespace or concealed; no texture to render
                             let zero = (0.0, 0.0f32);
 
                             // Note: these 0 coords refer to the blank pixel
@@ -855,12 +1371,26 @@ impl Renderer {
                             vert[V_TOP_RIGHT].has_color = 0.0;
                             vert[V_BOT_LEFT].has_color = 0.0;
                             vert[V_BOT_RIGHT].has_color = 0.0;
+
+                            vert[V_TOP_LEFT].tex_page = -1.0;
+                            vert[V_TOP_RIGHT].tex_page = -1.0;
+                            vert[V_BOT_LEFT].tex_page = -1.0;
+                            vert[V_BOT_RIGHT].tex_page = -1.0;
+                            self.set_cell_page(line_idx, cell_idx, num_cols, None);
                         }
                     }
                 }
             }
         }
 
+        {
+            let mut blinking_lines = self.blinking_lines.borrow_mut();
+            if blinking_lines.len() <= line_idx {
+                blinking_lines.resize(line_idx + 1, false);
+            }
+            blinking_lines[line_idx] = line_has_blink;
+        }
+
         // Clear any remaining cells to the right of the clusters we
         // found above, otherwise we leave artifacts behind.  The easiest
         // reproduction for the artifacts is to maximize the window and
@@ -868,7 +1398,9 @@ impl Renderer {
         // the right pane with its prior contents instead of showing the
         // cleared lines from the shell in the main screen.
 
-        for cell_idx in last_cell_idx + 1..num_cols {
+        let clear_start = (last_cell_idx + 1).max(damage.start);
+        let clear_end = num_cols.min(damage.end);
+        for cell_idx in clear_start..clear_end {
             let vert_idx = cell_idx * VERTICES_PER_CELL;
             let vert_slice = &mut vertices[vert_idx..vert_idx + 4];
 
@@ -880,6 +1412,7 @@ impl Renderer {
                 cell_idx,
                 cursor,
                 &selection,
+                &highlights,
                 self.palette.foreground.to_tuple_rgba(),
                 self.palette.background.to_tuple_rgba(),
             );
@@ -893,7 +1426,9 @@ impl Renderer {
                 vert.tex = (0.0, 0.0);
                 vert.adjust = Default::default();
                 vert.has_color = 0.0;
+                vert.tex_page = -1.0;
             }
+            self.set_cell_page(line_idx, cell_idx, num_cols, None);
         }
 
         Ok(())
@@ -905,15 +1440,39 @@ impl Renderer {
         cell_idx: usize,
         cursor: &CursorPosition,
         selection: &Range<usize>,
+        highlights: &[LineHighlight],
         fg_color: RgbaTuple,
         bg_color: RgbaTuple,
     ) -> (RgbaTuple, RgbaTuple) {
         let selected = term::in_range(cell_idx, selection);
         let is_cursor = line_idx as i64 == cursor.y && cursor.x == cell_idx;
 
+        // Highlighted cells are shown in the colors of their highlight,
+        // unless they are also selected or hold the cursor
+        let (fg_color, bg_color) = match highlight_colors(highlights, cell_idx) {
+            Some((fg, bg)) if !selected && !is_cursor => (fg.to_tuple_rgba(), bg.to_tuple_rgba()),
+            _ => (fg_color, bg_color),
+        };
+
         let (fg_color, bg_color) = match (selected, is_cursor) {
-            // Normally, render the cell as configured
-            (false, false) => (fg_color, bg_color),
+            // Normally, render the cell as configured, but keep the
+            // text legible against its background
+            (false, false) => {
+                let hsb = &self.config.foreground_text_hsb;
+                let fg_color = if *hsb != HsbTransform::default() {
+                    apply_hsb(fg_color, hsb)
+                } else {
+                    fg_color
+                };
+                if self.min_contrast > 1.0 {
+                    (
+                        ensure_contrast(fg_color, bg_color, self.min_contrast),
+                        bg_color,
+                    )
+                } else {
+                    (fg_color, bg_color)
+                }
+            }
             // Cursor cell overrides colors
             (_, true) => (
                 self.palette.cursor_fg.to_tuple_rgba(),
@@ -936,16 +1495,42 @@ impl Renderer {
         let (r, g, b, a) = background_color.to_tuple_rgba();
         target.clear_color(r, g, b, a);
 
+        self.painted_blink_phase = self.blink_phase();
+        self.atlas.borrow_mut().unpin_all();
+
+        let (num_rows, _num_cols) = term.physical_dimensions();
+        self.blinking_lines.borrow_mut().truncate(num_rows);
+
         let cursor = term.get_cursor_position();
+        let full_damage = self.full_damage;
         {
             let dirty_lines = term.get_dirty_lines();
 
             for (line_idx, line, selrange) in dirty_lines {
-                self.render_screen_line(line_idx, line, selrange, &cursor, term)?;
+                let mut damage = if full_damage {
+                    0..usize::max_value()
+                } else {
+                    line.damaged_columns()
+                };
+                // The cursor may have moved without the cells that it
+                // left or entered being marked as changed
+                for pos in self.painted_cursor.iter().chain(Some(&cursor)) {
+                    if pos.y == line_idx as i64 {
+                        damage = if damage.start < damage.end {
+                            damage.start.min(pos.x)..damage.end.max(pos.x + 1)
+                        } else {
+                            pos.x..pos.x + 1
+                        };
+                    }
+                }
+                self.render_screen_line(line_idx, line, damage, selrange, &cursor, term)?;
             }
         }
+        self.full_damage = false;
+        self.painted_cursor = Some(cursor);
 
-        let tex = self.atlas.borrow().texture();
+        let atlas = self.atlas.borrow();
+        let tex = atlas.texture(0);
 
         // Pass 1: Draw backgrounds, strikethrough and underline
         target.draw(
@@ -964,23 +1549,142 @@ impl Renderer {
             },
         )?;
 
-        // Pass 2: Draw glyphs
-        target.draw(
-            &*self.glyph_vertex_buffer.borrow(),
-            &self.glyph_index_buffer,
-            &self.program,
-            &uniform! {
-                projection: self.projection.to_column_arrays(),
-                glyph_tex: &*tex,
-                bg_and_line_layer: false,
-            },
-            &glium::DrawParameters {
-                blend: glium::Blend::alpha_blending(),
-                ..Default::default()
-            },
-        )?;
+        // Pass 2: Draw the guides over the backgrounds, so that they
+        // don't obscure the text
+        if self.update_guides(term, &cursor) {
+            target.draw(
+                &*self.guide_vertex_buffer.borrow(),
+                &self.guide_index_buffer,
+                &self.program,
+                &uniform! {
+                    projection: self.projection.to_column_arrays(),
+                    glyph_tex: &*tex,
+                    bg_and_line_layer: true,
+                    underline_tex: &self.underline_tex,
+                },
+                &glium::DrawParameters {
+                    blend: glium::Blend::alpha_blending(),
+                    ..Default::default()
+                },
+            )?;
+        }
+
+        // Pass 3: Draw glyphs, one pass for each atlas page
+        for page in 0..atlas.num_pages() {
+            let page_tex = atlas.texture(page);
+            target.draw(
+                &*self.glyph_vertex_buffer.borrow(),
+                &self.glyph_index_buffer,
+                &self.program,
+                &uniform! {
+                    projection: self.projection.to_column_arrays(),
+                    glyph_tex: &*page_tex,
+                    glyph_page: page as f32,
+                    bg_and_line_layer: false,
+                },
+                &glium::DrawParameters {
+                    blend: glium::Blend::alpha_blending(),
+                    ..Default::default()
+                },
+            )?;
+        }
+
+        // Pass 4: Draw the scrollbar thumb over the top of the
+        // rightmost column
+        if self.update_scrollbar(term) {
+            target.draw(
+                &*self.scrollbar_vertex_buffer.borrow(),
+                &self.scrollbar_index_buffer,
+                &self.program,
+                &uniform! {
+                    projection: self.projection.to_column_arrays(),
+                    glyph_tex: &*tex,
+                    bg_and_line_layer: true,
+                    underline_tex: &self.underline_tex,
+                },
+                &glium::DrawParameters {
+                    blend: glium::Blend::alpha_blending(),
+                    ..Default::default()
+                },
+            )?;
+        }
 
         term.clean_dirty_lines();
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const BLACK: RgbaTuple = (0.0, 0.0, 0.0, 1.0);
+    const WHITE: RgbaTuple = (1.0, 1.0, 1.0, 1.0);
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 0.001, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn contrast() {
+        assert_close(contrast_ratio(BLACK, WHITE), 21.0);
+        assert_close(contrast_ratio(WHITE, BLACK), 21.0);
+        assert_close(contrast_ratio(WHITE, WHITE), 1.0);
+    }
+
+    #[test]
+    fn ensure_min_contrast() {
+        let dark_blue = (0.0, 0.0, 0.5, 1.0);
+        assert!(contrast_ratio(dark_blue, BLACK) < 4.5);
+
+        // Lightened against a dark background
+        let fg = ensure_contrast(dark_blue, BLACK, 4.5);
+        assert!(contrast_ratio(fg, BLACK) >= 4.5);
+        assert!(relative_luminance(fg) > relative_luminance(dark_blue));
+
+        // Darkened against a light background
+        let yellow = (1.0, 1.0, 0.5, 1.0);
+        let fg = ensure_contrast(yellow, WHITE, 7.0);
+        assert!(contrast_ratio(fg, WHITE) >= 7.0);
+
+        // Colors with sufficient contrast are left alone
+        assert_eq!(ensure_contrast(WHITE, BLACK, 4.5), WHITE);
+
+        // Unattainable ratios end up at the extreme
+        assert_eq!(ensure_contrast(dark_blue, BLACK, 30.0), WHITE);
+    }
+
+    #[test]
+    fn hsb() {
+        let color = (0.2, 0.4, 0.6, 0.5);
+        let same = apply_hsb(color, &HsbTransform::default());
+        assert_close(same.0, color.0);
+        assert_close(same.1, color.1);
+        assert_close(same.2, color.2);
+        assert_close(same.3, color.3);
+
+        let brighter = apply_hsb(
+            color,
+            &HsbTransform {
+                hue: 1.0,
+                saturation: 1.0,
+                brightness: 1.5,
+            },
+        );
+        assert_close(brighter.0, 0.3);
+        assert_close(brighter.1, 0.6);
+        assert_close(brighter.2, 0.9);
+
+        let gray = apply_hsb(
+            color,
+            &HsbTransform {
+                hue: 1.0,
+                saturation: 0.0,
+                brightness: 1.0,
+            },
+        );
+        assert_close(gray.0, 0.6);
+        assert_close(gray.1, 0.6);
+        assert_close(gray.2, 0.6);
+    }
+}
@@ -1,29 +1,69 @@
 //! Keeping track of sprite textures
 
 use failure::Error;
-use glium::backend::Facade;
+use glium::backend::{Context, Facade};
 use glium::texture::{SrgbTexture2d, Texture2dDataSource};
-use glium::{self, Rect};
+use glium::{self, Rect, Surface};
 use std::rc::Rc;
 
+/// The initial side length of each atlas page
 pub const TEX_SIZE: u32 = 4096;
 
+/// The largest side length that the atlas pages will be grown to
+/// in response to `OutOfTextureSpace`.  A single page of this size
+/// occupies the whole of `ATLAS_MEMORY_BUDGET`.
+pub const MAX_TEX_SIZE: u32 = 8192;
+
+/// The maximum number of atlas pages that will be allocated before
+/// the least recently used page is evicted to make room
+pub const MAX_ATLAS_PAGES: usize = 4;
+
+/// The most video memory, in bytes, that the atlas pages may occupy
+pub const ATLAS_MEMORY_BUDGET: usize = 256 * 1024 * 1024;
+
+/// Returns how many pages with the given side length can be
+/// allocated within `ATLAS_MEMORY_BUDGET`, up to `MAX_ATLAS_PAGES`.
+/// At least one page is always allowed.
+pub fn max_pages_for_side(side: u32) -> usize {
+    let page_bytes = side as usize * side as usize * 4;
+    (ATLAS_MEMORY_BUDGET / page_bytes.max(1))
+        .max(1)
+        .min(MAX_ATLAS_PAGES)
+}
+
+/// Returns the largest side length that the atlas pages may be grown
+/// to: `MAX_TEX_SIZE`, or less if the GL implementation can't create
+/// textures that large.  This must be called while the GL context
+/// is current.
+pub fn max_texture_size() -> u32 {
+    if !gl::GetIntegerv::is_loaded() {
+        return MAX_TEX_SIZE;
+    }
+    let mut size: gl::types::GLint = 0;
+    unsafe {
+        gl::GetIntegerv(gl::MAX_TEXTURE_SIZE, &mut size);
+    }
+    if size <= 0 {
+        MAX_TEX_SIZE
+    } else {
+        (size as u32).min(MAX_TEX_SIZE)
+    }
+}
+
 #[derive(Debug, Fail)]
 #[fail(display = "Texture Size exceeded, need {}", size)]
 pub struct OutOfTextureSpace {
     pub size: u32,
 }
 
-/// Atlases are bitmaps of srgba data that are sized as a power of 2.
-/// We allocate sprites out of the available space, starting from the
-/// bottom left corner and working to the right until we run out of
-/// space, then we move up to the logical row above.  Since sprites can
-/// have varying height the height of the rows can also vary.
+/// Keeps track of the space used in a square region, allocating
+/// rectangles starting from the bottom left corner and working to
+/// the right until we run out of space, then we move up to the
+/// logical row above.  Since sprites can have varying height the
+/// height of the rows can also vary.
 #[derive(Debug)]
-pub struct Atlas {
-    texture: Rc<SrgbTexture2d>,
-
-    // Dimensions of the texture
+struct Packer {
+    // Dimensions of the region
     side: u32,
 
     /// The bottom of the available space.
@@ -36,36 +76,25 @@ pub struct Atlas {
     left: u32,
 }
 
-impl Atlas {
-    pub fn new<F: Facade>(facade: &F, side: u32) -> Result<Self, Error> {
-        let texture = Rc::new(SrgbTexture2d::empty_with_format(
-            facade,
-            glium::texture::SrgbFormat::U8U8U8U8,
-            glium::texture::MipmapsOption::NoMipmap,
-            side,
-            side,
-        )?);
-        Ok(Self {
-            texture,
+impl Packer {
+    fn new(side: u32) -> Self {
+        Self {
             side,
             bottom: 0,
             tallest: 0,
             left: 0,
-        })
+        }
     }
 
-    #[inline]
-    pub fn texture(&self) -> Rc<SrgbTexture2d> {
-        Rc::clone(&self.texture)
+    fn clear(&mut self) {
+        self.bottom = 0;
+        self.tallest = 0;
+        self.left = 0;
     }
 
-    /// Reserve space for a sprite of the given size
-    pub fn allocate<'a, T: Texture2dDataSource<'a>>(
-        &mut self,
-        width: u32,
-        height: u32,
-        data: T,
-    ) -> Result<Sprite, OutOfTextureSpace> {
+    /// Reserve space for a sprite of the given size, returning the
+    /// rectangle that it occupies in the region
+    fn reserve(&mut self, width: u32, height: u32) -> Result<Rect, OutOfTextureSpace> {
         // We pad each sprite reservation with blank space to avoid
         // surprising and unexpected artifacts when the texture is
         // interpolated on to the render surface.
@@ -104,22 +133,278 @@ impl Atlas {
             height,
         };
 
-        self.texture.write(rect, data);
-
         self.left += reserve_width;
         self.tallest = self.tallest.max(reserve_height);
 
-        Ok(Sprite {
-            texture: Rc::clone(&self.texture),
-            coords: rect,
+        Ok(rect)
+    }
+}
+
+/// Atlases are bitmaps of srgba data that are sized as a power of 2.
+/// Sprites are allocated out of the available space by a `Packer`.
+#[derive(Debug)]
+pub struct Atlas {
+    texture: Rc<SrgbTexture2d>,
+    packer: Packer,
+}
+
+impl Atlas {
+    pub fn new<F: Facade>(facade: &F, side: u32) -> Result<Self, Error> {
+        let texture = Rc::new(SrgbTexture2d::empty_with_format(
+            facade,
+            glium::texture::SrgbFormat::U8U8U8U8,
+            glium::texture::MipmapsOption::NoMipmap,
+            side,
+            side,
+        )?);
+        Ok(Self {
+            texture,
+            packer: Packer::new(side),
         })
     }
+
+    #[inline]
+    pub fn texture(&self) -> Rc<SrgbTexture2d> {
+        Rc::clone(&self.texture)
+    }
+
+    /// Discard all of the allocated sprites, making the full
+    /// texture available for new allocations
+    pub fn clear(&mut self) {
+        self.texture.as_surface().clear_color(0.0, 0.0, 0.0, 0.0);
+        self.packer.clear();
+    }
+
+    /// Reserve space for a sprite of the given size, returning the
+    /// rectangle that it occupies in the texture
+    fn reserve(&mut self, width: u32, height: u32) -> Result<Rect, OutOfTextureSpace> {
+        self.packer.reserve(width, height)
+    }
+}
+
+/// The bookkeeping needed to decide whether an atlas page
+/// can be evicted
+#[derive(Debug, Default)]
+struct PageUsage {
+    /// The value of the `PageTracker` clock when a sprite from
+    /// this page was last used
+    last_used: u64,
+    /// How many cells on screen are currently showing a sprite
+    /// from this page
+    refs: usize,
+    /// Set when a sprite from this page has been used by the frame
+    /// that is being painted.  Those sprites may not have been
+    /// recorded in `refs` yet, for example while the glyphs of a
+    /// cluster are being gathered, so the page must not be evicted
+    /// until the frame is complete.
+    pinned: bool,
+}
+
+/// Tracks the usage of each of the atlas pages
+#[derive(Debug, Default)]
+struct PageTracker {
+    pages: Vec<PageUsage>,
+    clock: u64,
+}
+
+impl PageTracker {
+    fn add_page(&mut self) -> usize {
+        self.pages.push(PageUsage::default());
+        self.pages.len() - 1
+    }
+
+    fn touch(&mut self, page: usize) {
+        self.clock += 1;
+        if let Some(page) = self.pages.get_mut(page) {
+            page.last_used = self.clock;
+            page.pinned = true;
+        }
+    }
+
+    fn add_ref(&mut self, page: usize) {
+        if let Some(page) = self.pages.get_mut(page) {
+            page.refs += 1;
+        }
+    }
+
+    fn release(&mut self, page: usize) {
+        if let Some(page) = self.pages.get_mut(page) {
+            page.refs = page.refs.saturating_sub(1);
+        }
+    }
+
+    fn clear_refs(&mut self) {
+        for page in &mut self.pages {
+            page.refs = 0;
+        }
+    }
+
+    fn unpin_all(&mut self) {
+        for page in &mut self.pages {
+            page.pinned = false;
+        }
+    }
+
+    /// Returns the least recently used page that is neither shown
+    /// on screen nor pinned by the frame that is being painted
+    fn victim(&self) -> Option<usize> {
+        self.pages
+            .iter()
+            .enumerate()
+            .filter(|(_, page)| page.refs == 0 && !page.pinned)
+            .min_by_key(|(_, page)| page.last_used)
+            .map(|(idx, _)| idx)
+    }
+}
+
+/// Manages a set of fixed size atlas pages.  Sprites are allocated
+/// from the existing pages while they have room, and new pages are
+/// added as needed up to a limit that depends on the page size.
+/// Once that limit is reached, the least recently used page that
+/// isn't referenced by anything on screen is cleared and reused,
+/// so that only the sprites from that page need to be rendered again.
+#[derive(Debug)]
+pub struct AtlasPages {
+    context: Rc<Context>,
+    side: u32,
+    max_pages: usize,
+    pages: Vec<Atlas>,
+    usage: PageTracker,
+}
+
+impl AtlasPages {
+    pub fn new<F: Facade>(facade: &F, side: u32) -> Result<Self, Error> {
+        let context = Rc::clone(facade.get_context());
+        let atlas = Atlas::new(&context, side)?;
+        let mut usage = PageTracker::default();
+        usage.add_page();
+        Ok(Self {
+            context,
+            side,
+            max_pages: max_pages_for_side(side),
+            pages: vec![atlas],
+            usage,
+        })
+    }
+
+    #[inline]
+    pub fn side(&self) -> u32 {
+        self.side
+    }
+
+    #[inline]
+    pub fn num_pages(&self) -> usize {
+        self.pages.len()
+    }
+
+    #[inline]
+    pub fn texture(&self, page: usize) -> Rc<SrgbTexture2d> {
+        self.pages[page].texture()
+    }
+
+    /// Record that a sprite from `page` was just used.  The page is
+    /// pinned until `unpin_all` is called.
+    pub fn touch(&mut self, page: usize) {
+        self.usage.touch(page);
+    }
+
+    /// Record that a cell on screen now shows a sprite from `page`
+    pub fn add_ref(&mut self, page: usize) {
+        self.usage.add_ref(page);
+    }
+
+    /// Record that a cell on screen no longer shows a sprite from `page`
+    pub fn release(&mut self, page: usize) {
+        self.usage.release(page);
+    }
+
+    /// Forget all references; used when the screen content is
+    /// about to be rebuilt from scratch
+    pub fn clear_refs(&mut self) {
+        self.usage.clear_refs();
+    }
+
+    /// Release the pages pinned by the previous frame; called as
+    /// painting of a new frame begins
+    pub fn unpin_all(&mut self) {
+        self.usage.unpin_all();
+    }
+
+    /// Allocate a sprite from the pages.  In addition to the sprite,
+    /// returns the index of the page that was evicted to make room for
+    /// it, if any; sprites that were previously allocated from that
+    /// page are no longer valid.
+    /// Returns `OutOfTextureSpace` if the sprite is larger than a page,
+    /// or if every page is in use by the content on screen or by the
+    /// frame being painted.  The size in the error is always larger
+    /// than the current page size, so that recreating the pages at
+    /// that size makes progress.
+    pub fn allocate<'a, T: Texture2dDataSource<'a>>(
+        &mut self,
+        width: u32,
+        height: u32,
+        data: T,
+    ) -> Result<(Sprite, Option<usize>), Error> {
+        // Allow for the padding that `Atlas::reserve` adds
+        if width + 2 > self.side || height + 2 > self.side {
+            return Err(OutOfTextureSpace {
+                size: (width.max(height) + 2).next_power_of_two(),
+            }
+            .into());
+        }
+
+        let mut evicted = None;
+
+        // Prefer the most recently added pages, as the older pages
+        // are likely to be full already
+        let mut found = None;
+        for idx in (0..self.pages.len()).rev() {
+            if let Ok(rect) = self.pages[idx].reserve(width, height) {
+                found = Some((idx, rect));
+                break;
+            }
+        }
+
+        let (idx, rect) = match found {
+            Some(found) => found,
+            None => {
+                let idx = if self.pages.len() < self.max_pages {
+                    let atlas = Atlas::new(&self.context, self.side)?;
+                    self.pages.push(atlas);
+                    self.usage.add_page()
+                } else {
+                    let idx = self.usage.victim().ok_or_else(|| OutOfTextureSpace {
+                        size: self.side * 2,
+                    })?;
+                    self.pages[idx].clear();
+                    evicted = Some(idx);
+                    idx
+                };
+                (idx, self.pages[idx].reserve(width, height)?)
+            }
+        };
+
+        let page = &self.pages[idx];
+        page.texture.write(rect, data);
+        self.usage.touch(idx);
+
+        Ok((
+            Sprite {
+                texture: page.texture(),
+                coords: rect,
+                page: idx,
+            },
+            evicted,
+        ))
+    }
 }
 
 #[derive(Debug)]
 pub struct Sprite {
     pub texture: Rc<SrgbTexture2d>,
     pub coords: Rect,
+    /// The index of the `AtlasPages` page that holds this sprite
+    pub page: usize,
 }
 
 /// Represents a vertical slice through a sprite.
@@ -241,3 +526,93 @@ impl Sprite {
         (self.right(slice), self.top(slice))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pages_within_budget() {
+        assert_eq!(max_pages_for_side(1024), MAX_ATLAS_PAGES);
+        assert_eq!(max_pages_for_side(TEX_SIZE), 4);
+        assert_eq!(max_pages_for_side(MAX_TEX_SIZE), 1);
+        // A page larger than the budget is still allowed
+        assert_eq!(max_pages_for_side(16384), 1);
+    }
+
+    #[test]
+    fn packing() {
+        let mut packer = Packer::new(16);
+        // Each reservation is padded by a pixel on every side
+        assert_eq!(
+            packer.reserve(4, 4).unwrap(),
+            Rect {
+                left: 1,
+                bottom: 1,
+                width: 4,
+                height: 4
+            }
+        );
+        assert_eq!(
+            packer.reserve(4, 2).unwrap(),
+            Rect {
+                left: 7,
+                bottom: 1,
+                width: 4,
+                height: 2
+            }
+        );
+        // Doesn't fit on the current row, so starts a new one above
+        // the tallest sprite on this row
+        assert_eq!(
+            packer.reserve(8, 8).unwrap(),
+            Rect {
+                left: 1,
+                bottom: 7,
+                width: 8,
+                height: 8
+            }
+        );
+        // No room above; the suggested size must make progress
+        let err = packer.reserve(8, 8).unwrap_err();
+        assert_eq!(err.size, 32);
+
+        packer.clear();
+        assert_eq!(packer.reserve(8, 8).unwrap().bottom, 1);
+
+        // Too big for any region of this size
+        assert_eq!(packer.reserve(20, 2).unwrap_err().size, 32);
+    }
+
+    #[test]
+    fn eviction() {
+        let mut usage = PageTracker::default();
+        for _ in 0..3 {
+            usage.add_page();
+        }
+        usage.touch(0);
+        usage.touch(1);
+        usage.touch(2);
+        // Everything was used by the frame being painted
+        assert_eq!(usage.victim(), None);
+
+        usage.unpin_all();
+        assert_eq!(usage.victim(), Some(0));
+
+        // Pages that are on screen are never evicted
+        usage.add_ref(0);
+        assert_eq!(usage.victim(), Some(1));
+
+        // Neither are pages that the current frame has used
+        usage.touch(1);
+        assert_eq!(usage.victim(), Some(2));
+
+        usage.unpin_all();
+        usage.release(0);
+        assert_eq!(usage.victim(), Some(0));
+
+        usage.add_ref(0);
+        usage.clear_refs();
+        assert_eq!(usage.victim(), Some(0));
+    }
+}
@@ -8,6 +8,7 @@ use std::os::windows::ffi::OsStrExt;
 pub struct CommandBuilder {
     args: Vec<OsString>,
     envs: Vec<(OsString, OsString)>,
+    cwd: Option<OsString>,
 }
 
 impl CommandBuilder {
@@ -15,6 +16,7 @@ impl CommandBuilder {
         Self {
             args: vec![program.as_ref().to_owned()],
             envs: vec![],
+            cwd: None,
         }
     }
 
@@ -46,6 +48,28 @@ impl CommandBuilder {
             val.as_ref()
         );
     }
+
+    /// Set the directory in which the program will be started
+    pub fn cwd<D: AsRef<OsStr>>(&mut self, dir: D) {
+        self.cwd = Some(dir.as_ref().to_owned());
+    }
+
+    /// Returns the directory in which the program will be started,
+    /// if it has been set
+    pub fn get_cwd(&self) -> Option<&OsString> {
+        self.cwd.as_ref()
+    }
+
+    /// Returns the program and its arguments
+    pub fn get_argv(&self) -> &[OsString] {
+        &self.args
+    }
+
+    /// Returns the environment variables that will be set in
+    /// addition to those inherited from this process
+    pub fn get_envs(&self) -> &[(OsString, OsString)] {
+        &self.envs
+    }
 }
 
 #[cfg(unix)]
@@ -56,6 +80,9 @@ impl CommandBuilder {
         for (key, val) in &self.envs {
             cmd.env(key, val);
         }
+        if let Some(dir) = self.cwd.as_ref() {
+            cmd.current_dir(dir);
+        }
 
         cmd
     }
@@ -116,6 +143,16 @@ impl CommandBuilder {
         Ok((exe, cmdline))
     }
 
+    /// Returns the nul terminated working directory for the child,
+    /// if one has been set
+    pub fn current_directory(&self) -> Option<Vec<u16>> {
+        self.cwd.as_ref().map(|dir| {
+            let mut wide: Vec<u16> = dir.encode_wide().collect();
+            wide.push(0);
+            wide
+        })
+    }
+
     // Borrowed from https://github.com/hniksic/rust-subprocess/blob/873dfed165173e52907beb87118b2c0c05d8b8a1/src/popen.rs#L1117
     // which in turn was translated from ArgvQuote at http://tinyurl.com/zmgtnls
     fn append_quoted(arg: &OsStr, cmdline: &mut Vec<u16>) {
@@ -1,12 +1,15 @@
 use failure::Error;
 use serde_derive::*;
 use std::io::Result as IoResult;
+use std::path::PathBuf;
 
 pub mod cmdbuilder;
 pub use cmdbuilder::CommandBuilder;
 
 #[cfg(unix)]
 pub mod unix;
+#[cfg(unix)]
+pub mod utmp;
 #[cfg(windows)]
 pub mod win;
 
@@ -25,36 +28,147 @@ pub trait MasterPty: std::io::Write {
     fn resize(&self, size: PtySize) -> Result<(), Error>;
     fn get_size(&self) -> Result<PtySize, Error>;
     fn try_clone_reader(&self) -> Result<Box<std::io::Read + Send>, Error>;
+
+    /// Returns a reader that doesn't block, so that the output of many
+    /// ptys can be waited upon by a single thread.  Putting the pty
+    /// into non-blocking mode also affects the master itself, whose
+    /// writes then wait for the pty to drain rather than failing.
+    ///
+    /// This is not supported on Windows: the output of a ConPTY is an
+    /// anonymous pipe from `CreatePipe`, which can't be used for
+    /// overlapped I/O and so has no event handle that could be waited
+    /// upon alongside those of other tabs.  The output of Windows tabs
+    /// is instead read by a thread per tab; see `Mux::add_tab`.
+    fn try_clone_nonblocking_reader(&self) -> Result<Box<NonBlockingReader>, Error> {
+        bail!("non-blocking reads are not supported by this pty");
+    }
+
+    /// Returns the process that is in the foreground of the pty, if it
+    /// can be determined.  This doesn't rely on the cooperation of the
+    /// shell, so it can be used whatever the user is running.
+    fn foreground_process(&self) -> Option<ForegroundProcess> {
+        None
+    }
+
+    /// Records the session in utmp and wtmp until the pty is closed,
+    /// so that it is listed by `who` and receives `wall` messages
+    fn register_utmp(&mut self) -> Result<(), Error> {
+        bail!("utmp is not supported by this pty");
+    }
+}
+
+/// The process that is in the foreground of a pty: the shell while it
+/// is waiting for a command to be entered, or the command that it is
+/// running
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForegroundProcess {
+    pub pid: u32,
+    /// The name of its executable, if it can be determined
+    pub name: Option<String>,
+    /// Its working directory, if it can be determined
+    pub cwd: Option<PathBuf>,
+}
+
+/// A reader whose reads fail with `ErrorKind::WouldBlock` rather than
+/// blocking when there is no data available
+pub trait NonBlockingReader: std::io::Read + Send {
+    /// Returns the descriptor that becomes readable when there is data
+    /// to be read, or when the pty has been closed
+    #[cfg(unix)]
+    fn pollable_fd(&self) -> std::os::unix::io::RawFd;
 }
 
 pub trait Child: std::fmt::Debug {
     fn try_wait(&mut self) -> IoResult<Option<ExitStatus>>;
     fn kill(&mut self) -> IoResult<()>;
     fn wait(&mut self) -> IoResult<ExitStatus>;
+    /// Returns the process identifier of the child, if known
+    fn process_id(&self) -> Option<u32>;
 }
 
 pub trait SlavePty {
     fn spawn_command(&self, cmd: CommandBuilder) -> Result<Box<Child>, Error>;
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ExitStatus {
-    successful: bool,
+    code: u32,
+    /// The signal that terminated the process, if any
+    signal: Option<u32>,
 }
 
-#[cfg(windows)]
 impl ExitStatus {
     pub fn with_exit_code(code: u32) -> Self {
+        Self { code, signal: None }
+    }
+
+    /// The status of a process that was terminated by `signal`
+    pub fn with_signal(signal: u32) -> Self {
         Self {
-            successful: if code == 0 { true } else { false },
+            code: 128 + signal,
+            signal: Some(signal),
+        }
+    }
+
+    pub fn success(&self) -> bool {
+        self.code == 0
+    }
+
+    /// Returns the exit code of the process.  For a process that was
+    /// terminated by a signal, this follows the shell convention of
+    /// 128 plus the signal number.
+    pub fn exit_code(&self) -> u32 {
+        self.code
+    }
+
+    /// Returns the signal that terminated the process, which is
+    /// always None on Windows
+    pub fn signal(&self) -> Option<u32> {
+        self.signal
+    }
+}
+
+impl std::fmt::Display for ExitStatus {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.signal {
+            Some(signal) => match signal_description(signal) {
+                Some(desc) => write!(fmt, "signal {} ({})", signal, desc),
+                None => write!(fmt, "signal {}", signal),
+            },
+            None => write!(fmt, "exit code {}", self.code),
         }
     }
 }
 
+/// Returns the description of `signal`, such as "Killed"
+#[cfg(unix)]
+fn signal_description(signal: u32) -> Option<String> {
+    let desc = unsafe { libc::strsignal(signal as libc::c_int) };
+    if desc.is_null() {
+        return None;
+    }
+    let desc = unsafe { std::ffi::CStr::from_ptr(desc) };
+    Some(desc.to_string_lossy().into_owned())
+}
+
+#[cfg(windows)]
+fn signal_description(_signal: u32) -> Option<String> {
+    None
+}
+
 impl From<std::process::ExitStatus> for ExitStatus {
-    fn from(_status: std::process::ExitStatus) -> ExitStatus {
-        // FIXME: properly fill
-        ExitStatus { successful: true }
+    fn from(status: std::process::ExitStatus) -> ExitStatus {
+        if let Some(code) = status.code() {
+            return ExitStatus::with_exit_code(code as u32);
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+            if let Some(signal) = status.signal() {
+                return ExitStatus::with_signal(signal as u32);
+            }
+        }
+        ExitStatus::with_exit_code(1)
     }
 }
 
@@ -80,11 +194,18 @@ impl Child for std::process::Child {
     fn wait(&mut self) -> IoResult<ExitStatus> {
         std::process::Child::wait(self).map(Into::into)
     }
+
+    fn process_id(&self) -> Option<u32> {
+        Some(self.id())
+    }
 }
 
 #[allow(dead_code)]
 #[derive(Debug, Clone, Copy, Deserialize)]
 pub enum PtySystemSelection {
+    /// Use the native pty of the system.  On Windows, this is conpty
+    /// when it is available and works well, and winpty otherwise.
+    Auto,
     Unix,
     ConPty,
     WinPty,
@@ -94,13 +215,23 @@ impl PtySystemSelection {
     #[cfg(unix)]
     pub fn get(&self) -> Result<Box<PtySystem>, Error> {
         match self {
-            PtySystemSelection::Unix => Ok(Box::new(unix::UnixPtySystem {})),
+            PtySystemSelection::Auto | PtySystemSelection::Unix => {
+                Ok(Box::new(unix::UnixPtySystem {}))
+            }
             _ => bail!("{:?} not available on unix", self),
         }
     }
     #[cfg(windows)]
     pub fn get(&self) -> Result<Box<PtySystem>, Error> {
         match self {
+            PtySystemSelection::Auto => {
+                if win::conpty::is_usable() {
+                    PtySystemSelection::ConPty.get()
+                } else {
+                    eprintln!("conpty is unavailable on this version of Windows; using winpty");
+                    PtySystemSelection::WinPty.get()
+                }
+            }
             PtySystemSelection::ConPty => Ok(Box::new(win::conpty::ConPtySystem {})),
             PtySystemSelection::WinPty => Ok(Box::new(win::winpty::WinPtySystem {})),
             _ => bail!("{:?} not available on Windows", self),
@@ -108,7 +239,7 @@ impl PtySystemSelection {
     }
 
     pub fn variants() -> Vec<&'static str> {
-        vec!["Unix", "ConPty", "WinPty"]
+        vec!["Auto", "Unix", "ConPty", "WinPty"]
     }
 }
 
@@ -116,6 +247,7 @@ impl std::str::FromStr for PtySystemSelection {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.to_lowercase().as_ref() {
+            "auto" => Ok(PtySystemSelection::Auto),
             "unix" => Ok(PtySystemSelection::Unix),
             "winpty" => Ok(PtySystemSelection::WinPty),
             "conpty" => Ok(PtySystemSelection::ConPty),
@@ -133,6 +265,48 @@ impl Default for PtySystemSelection {
         #[cfg(unix)]
         return PtySystemSelection::Unix;
         #[cfg(windows)]
-        return PtySystemSelection::ConPty;
+        return PtySystemSelection::Auto;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn exit_status() {
+        let status = ExitStatus::with_exit_code(0);
+        assert!(status.success());
+        assert_eq!(status.signal(), None);
+        assert_eq!(status.to_string(), "exit code 0");
+
+        let status = ExitStatus::with_exit_code(3);
+        assert!(!status.success());
+        assert_eq!(status.exit_code(), 3);
+        assert_eq!(status.to_string(), "exit code 3");
+
+        let status = ExitStatus::with_signal(9);
+        assert!(!status.success());
+        assert_eq!(status.exit_code(), 137);
+        assert_eq!(status.signal(), Some(9));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn from_std_exit_status() {
+        use std::os::unix::process::ExitStatusExt;
+
+        // The raw values are wait(2) statuses; the exit code is held
+        // in the second byte and the signal in the low bits
+        let status: ExitStatus = std::process::ExitStatus::from_raw(0).into();
+        assert_eq!(status, ExitStatus::with_exit_code(0));
+
+        let status: ExitStatus = std::process::ExitStatus::from_raw(42 << 8).into();
+        assert_eq!(status, ExitStatus::with_exit_code(42));
+
+        let status: ExitStatus = std::process::ExitStatus::from_raw(libc::SIGKILL).into();
+        assert_eq!(status, ExitStatus::with_signal(libc::SIGKILL as u32));
+        assert_eq!(status.exit_code(), 128 + libc::SIGKILL as u32);
+        assert!(status.to_string().starts_with("signal 9"));
     }
 }
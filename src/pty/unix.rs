@@ -1,12 +1,17 @@
 //! Working with pseudo-terminals
 
-use crate::pty::{Child, CommandBuilder, MasterPty, PtySize, PtySystem, SlavePty};
+use crate::pty::utmp::UtmpRecord;
+use crate::pty::{
+    Child, CommandBuilder, ForegroundProcess, MasterPty, NonBlockingReader, PtySize, PtySystem,
+    SlavePty,
+};
 use failure::Error;
 use libc::{self, winsize};
 use std::io;
 use std::mem;
 use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
 use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
 use std::process::Stdio;
 use std::ptr;
 
@@ -40,6 +45,7 @@ impl PtySystem for UnixPtySystem {
         }
 
         let master = UnixMasterPty {
+            utmp: None,
             fd: OwnedFd { fd: master },
         };
         let slave = UnixSlavePty {
@@ -134,6 +140,9 @@ impl io::Write for OwnedFd {
 /// Represents the master end of a pty.
 /// The file descriptor will be closed when the Pty is dropped.
 pub struct UnixMasterPty {
+    /// Declared ahead of `fd` so that the entry is removed before
+    /// the fd is closed
+    utmp: Option<UtmpRecord>,
     fd: OwnedFd,
 }
 
@@ -144,7 +153,7 @@ pub struct UnixSlavePty {
 }
 
 /// Helper function to set the close-on-exec flag for a raw descriptor
-fn cloexec(fd: RawFd) -> Result<(), Error> {
+pub fn cloexec(fd: RawFd) -> Result<(), Error> {
     let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
     if flags == -1 {
         bail!(
@@ -162,8 +171,7 @@ fn cloexec(fd: RawFd) -> Result<(), Error> {
     Ok(())
 }
 
-#[allow(dead_code)]
-fn clear_nonblocking(fd: RawFd) -> Result<(), Error> {
+pub fn clear_nonblocking(fd: RawFd) -> Result<(), Error> {
     let flags = unsafe { libc::fcntl(fd, libc::F_GETFL, 0) };
     if flags == -1 {
         bail!(
@@ -181,8 +189,7 @@ fn clear_nonblocking(fd: RawFd) -> Result<(), Error> {
     Ok(())
 }
 
-#[allow(dead_code)]
-fn set_nonblocking(fd: RawFd) -> Result<(), Error> {
+pub fn set_nonblocking(fd: RawFd) -> Result<(), Error> {
     let flags = unsafe { libc::fcntl(fd, libc::F_GETFL, 0) };
     if flags == -1 {
         bail!(
@@ -307,15 +314,160 @@ impl MasterPty for UnixMasterPty {
 
     fn try_clone_reader(&self) -> Result<Box<std::io::Read + Send>, Error> {
         let fd = self.fd.try_clone()?;
+        // The flag is shared with any non-blocking reader that was
+        // cloned earlier; this reader replaces it
+        clear_nonblocking(fd.as_raw_fd())?;
         Ok(Box::new(fd))
     }
+
+    fn try_clone_nonblocking_reader(&self) -> Result<Box<NonBlockingReader>, Error> {
+        let fd = self.fd.try_clone()?;
+        set_nonblocking(fd.as_raw_fd())?;
+        Ok(Box::new(fd))
+    }
+
+    fn foreground_process(&self) -> Option<ForegroundProcess> {
+        // The foreground process group is led by the process that
+        // the shell started for the foreground job, or by the shell
+        let pid = unsafe { libc::tcgetpgrp(self.fd.as_raw_fd()) };
+        if pid <= 0 {
+            return None;
+        }
+        Some(ForegroundProcess {
+            pid: pid as u32,
+            name: process_name(pid),
+            cwd: process_cwd(pid),
+        })
+    }
+
+    fn register_utmp(&mut self) -> Result<(), Error> {
+        if self.utmp.is_none() {
+            let host = std::env::var("DISPLAY").unwrap_or_default();
+            self.utmp = Some(UtmpRecord::add(self.fd.as_raw_fd(), &host)?);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn process_name(pid: libc::pid_t) -> Option<String> {
+    let comm = std::fs::read_to_string(format!("/proc/{}/comm", pid)).ok()?;
+    Some(comm.trim_end().to_owned())
+}
+
+#[cfg(target_os = "macos")]
+fn process_name(pid: libc::pid_t) -> Option<String> {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+    // PROC_PIDPATHINFO_MAXSIZE
+    let mut buf = vec![0u8; 4096];
+    let len = unsafe { libc::proc_pidpath(pid, buf.as_mut_ptr() as *mut _, buf.len() as u32) };
+    if len <= 0 {
+        return None;
+    }
+    buf.truncate(len as usize);
+    let path = PathBuf::from(OsString::from_vec(buf));
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn process_name(_pid: libc::pid_t) -> Option<String> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn process_cwd(pid: libc::pid_t) -> Option<PathBuf> {
+    std::fs::read_link(format!("/proc/{}/cwd", pid)).ok()
+}
+
+#[cfg(target_os = "macos")]
+fn process_cwd(pid: libc::pid_t) -> Option<PathBuf> {
+    use std::ffi::{CStr, OsStr};
+    use std::os::unix::ffi::OsStrExt;
+    let mut info: libc::proc_vnodepathinfo = unsafe { mem::zeroed() };
+    let size = mem::size_of::<libc::proc_vnodepathinfo>() as libc::c_int;
+    let len = unsafe {
+        libc::proc_pidinfo(
+            pid,
+            libc::PROC_PIDVNODEPATHINFO,
+            0,
+            &mut info as *mut _ as *mut _,
+            size,
+        )
+    };
+    if len != size {
+        return None;
+    }
+    // The path is a nul terminated MAXPATHLEN buffer, which libc
+    // declares as nested arrays
+    let path = unsafe { CStr::from_ptr(info.pvi_cdir.vip_path.as_ptr() as *const libc::c_char) };
+    let path = path.to_bytes();
+    if path.is_empty() {
+        return None;
+    }
+    Some(PathBuf::from(OsStr::from_bytes(path)))
+}
+
+/// Other systems don't make this available in a way that is
+/// both cheap and reliable
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn process_cwd(_pid: libc::pid_t) -> Option<PathBuf> {
+    None
+}
+
+impl NonBlockingReader for OwnedFd {
+    fn pollable_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+/// Waits until `fd` is ready for the `events` described by the
+/// poll(2) flags, retrying if interrupted by a signal
+fn wait_for_fd(fd: RawFd, events: libc::c_short) -> Result<(), io::Error> {
+    let mut pfd = libc::pollfd {
+        fd,
+        events,
+        revents: 0,
+    };
+    loop {
+        if unsafe { libc::poll(&mut pfd, 1, -1) } != -1 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
 }
 
 impl io::Write for UnixMasterPty {
     fn write(&mut self, buf: &[u8]) -> Result<usize, io::Error> {
-        self.fd.write(buf)
+        // The descriptor is non-blocking if a non-blocking reader has
+        // been cloned from it, as the flag is shared between duplicates,
+        // so wait for the pty to drain when it is full
+        loop {
+            match self.fd.write(buf) {
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    wait_for_fd(self.fd.as_raw_fd(), libc::POLLOUT)?
+                }
+                result => return result,
+            }
+        }
     }
     fn flush(&mut self) -> Result<(), io::Error> {
         self.fd.flush()
     }
 }
+
+#[cfg(all(test, target_os = "linux"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn own_process() {
+        let pid = unsafe { libc::getpid() };
+        assert_eq!(process_cwd(pid), std::env::current_dir().ok());
+        assert!(process_name(pid).is_some());
+    }
+}
@@ -0,0 +1,84 @@
+//! Registers pty sessions in utmp and wtmp, so that `who`, `w` and
+//! `wall` know about them.  Those files are only writable by
+//! privileged programs, so this is done with the setgid helper of
+//! libutempter, as xterm does.  The library is loaded when it is first
+//! needed so that wezterm doesn't depend upon it being installed.
+use failure::Error;
+use lazy_static::lazy_static;
+use libc::{c_char, c_int, c_void};
+use std::ffi::CString;
+use std::os::unix::io::RawFd;
+
+type AddRecord = unsafe extern "C" fn(master_fd: c_int, hostname: *const c_char) -> c_int;
+type RemoveRecord = unsafe extern "C" fn(master_fd: c_int) -> c_int;
+
+struct Utempter {
+    add_record: AddRecord,
+    remove_record: RemoveRecord,
+}
+
+lazy_static! {
+    static ref UTEMPTER: Option<Utempter> = load_utempter();
+}
+
+fn load_utempter() -> Option<Utempter> {
+    for name in &["libutempter.so.0", "libutempter.so"] {
+        let name = CString::new(*name).unwrap();
+        let lib = unsafe { libc::dlopen(name.as_ptr(), libc::RTLD_NOW) };
+        if lib.is_null() {
+            continue;
+        }
+        let add = symbol(lib, "utempter_add_record");
+        let remove = symbol(lib, "utempter_remove_record");
+        if let (Some(add), Some(remove)) = (add, remove) {
+            return Some(unsafe {
+                Utempter {
+                    add_record: std::mem::transmute::<*mut c_void, AddRecord>(add),
+                    remove_record: std::mem::transmute::<*mut c_void, RemoveRecord>(remove),
+                }
+            });
+        }
+    }
+    None
+}
+
+fn symbol(lib: *mut c_void, name: &str) -> Option<*mut c_void> {
+    let name = CString::new(name).unwrap();
+    let sym = unsafe { libc::dlsym(lib, name.as_ptr()) };
+    if sym.is_null() {
+        None
+    } else {
+        Some(sym)
+    }
+}
+
+/// The utmp entry of a pty, which is removed when this is dropped.
+/// It must be dropped before the master fd is closed.
+#[derive(Debug)]
+pub struct UtmpRecord {
+    master_fd: RawFd,
+}
+
+impl UtmpRecord {
+    /// Adds an entry for the pty whose master is `master_fd`.  `host`
+    /// is recorded as the origin of the session; local sessions are
+    /// conventionally recorded with the X display that they are on.
+    pub fn add(master_fd: RawFd, host: &str) -> Result<Self, Error> {
+        let utempter = UTEMPTER
+            .as_ref()
+            .ok_or_else(|| format_err!("libutempter is not installed"))?;
+        let host = CString::new(host)?;
+        if unsafe { (utempter.add_record)(master_fd, host.as_ptr()) } == 0 {
+            bail!("utempter_add_record failed");
+        }
+        Ok(Self { master_fd })
+    }
+}
+
+impl Drop for UtmpRecord {
+    fn drop(&mut self) {
+        if let Some(utempter) = UTEMPTER.as_ref() {
+            unsafe { (utempter.remove_record)(self.master_fd) };
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use super::job::job_for_process;
 use super::ownedhandle::OwnedHandle;
 use super::WinChild;
 use crate::pty::cmdbuilder::CommandBuilder;
@@ -18,11 +19,16 @@ use winapi::shared::winerror::{HRESULT, S_OK};
 use winapi::um::handleapi::*;
 use winapi::um::namedpipeapi::CreatePipe;
 use winapi::um::processthreadsapi::*;
-use winapi::um::winbase::EXTENDED_STARTUPINFO_PRESENT;
 use winapi::um::winbase::STARTUPINFOEXW;
+use winapi::um::winbase::{CREATE_SUSPENDED, EXTENDED_STARTUPINFO_PRESENT};
 use winapi::um::wincon::COORD;
+use winapi::um::winnt::OSVERSIONINFOW;
 
 const PROC_THREAD_ATTRIBUTE_PSEUDOCONSOLE: usize = 0x00020016;
+/// Asks the pseudo console to request win32-input-mode, so that key
+/// releases and modifier keys reach console applications.  Versions
+/// of Windows that don't know of this flag ignore it.
+const PSEUDOCONSOLE_WIN32_INPUT_MODE: DWORD = 0x4;
 
 pub struct ConPtySystem {}
 impl PtySystem for ConPtySystem {
@@ -134,6 +140,50 @@ shared_library!(ConPtyFuncs,
     pub fn ClosePseudoConsole(hpc: HPCON),
 );
 
+shared_library!(NtDllFuncs,
+    pub fn RtlGetVersion(info: *mut OSVERSIONINFOW) -> i32,
+);
+
+/// The first build of Windows 10 that provides the pseudo console api
+const MIN_CONPTY_BUILD: DWORD = 17763;
+
+/// Ranges of builds whose pseudo console is too buggy to be used
+/// by default.  The conpty of the October 2018 update loses output
+/// and mangles the screen when resized; these were fixed in 18309.
+const BROKEN_CONPTY_BUILDS: &[(DWORD, DWORD)] = &[(17763, 18309)];
+
+/// Returns the build number of the running version of Windows.
+/// GetVersionEx reports the version that the executable is
+/// manifested for rather than the real one, so this asks ntdll.
+fn windows_build_number() -> Option<DWORD> {
+    let ntdll = NtDllFuncs::open(Path::new("ntdll.dll")).ok()?;
+    let mut info: OSVERSIONINFOW = unsafe { mem::zeroed() };
+    info.dwOSVersionInfoSize = mem::size_of::<OSVERSIONINFOW>() as DWORD;
+    if unsafe { (ntdll.RtlGetVersion)(&mut info) } != 0 {
+        return None;
+    }
+    Some(info.dwBuildNumber)
+}
+
+/// Returns true if the pseudo console api is available and is not
+/// known to be broken on this version of Windows
+pub fn is_usable() -> bool {
+    let build = match windows_build_number() {
+        Some(build) => build,
+        None => return false,
+    };
+    if build < MIN_CONPTY_BUILD {
+        return false;
+    }
+    if BROKEN_CONPTY_BUILDS
+        .iter()
+        .any(|&(first, fixed)| build >= first && build < fixed)
+    {
+        return false;
+    }
+    ConPtyFuncs::open(Path::new("kernel32.dll")).is_ok()
+}
+
 lazy_static! {
     static ref CONPTY: ConPtyFuncs = ConPtyFuncs::open(Path::new("kernel32.dll")).expect(
         "this system does not support conpty.  Windows 10 October 2018 or newer is required"
@@ -153,8 +203,15 @@ impl Drop for PsuedoCon {
 impl PsuedoCon {
     fn new(size: COORD, input: &OwnedHandle, output: &OwnedHandle) -> Result<Self, Error> {
         let mut con: HPCON = INVALID_HANDLE_VALUE;
-        let result =
-            unsafe { (CONPTY.CreatePseudoConsole)(size, input.handle, output.handle, 0, &mut con) };
+        let result = unsafe {
+            (CONPTY.CreatePseudoConsole)(
+                size,
+                input.handle,
+                output.handle,
+                PSEUDOCONSOLE_WIN32_INPUT_MODE,
+                &mut con,
+            )
+        };
         ensure!(
             result == S_OK,
             "failed to create psuedo console: HRESULT {}",
@@ -252,6 +309,7 @@ impl SlavePty for ConPtySlavePty {
         let mut pi: PROCESS_INFORMATION = unsafe { mem::zeroed() };
 
         let (mut exe, mut cmdline) = cmd.cmdline()?;
+        let cwd = cmd.current_directory();
         let cmd_os = OsString::from_wide(&cmdline);
         eprintln!(
             "Running: module: {} {:?}",
@@ -265,9 +323,11 @@ impl SlavePty for ConPtySlavePty {
                 ptr::null_mut(),
                 ptr::null_mut(),
                 0,
-                EXTENDED_STARTUPINFO_PRESENT,
+                // Suspended until it is in its job, so that nothing
+                // that it spawns can escape the job
+                EXTENDED_STARTUPINFO_PRESENT | CREATE_SUSPENDED,
                 ptr::null_mut(), // FIXME: env
-                ptr::null_mut(),
+                cwd.as_ref().map_or(ptr::null(), |dir| dir.as_ptr()),
                 &mut si.StartupInfo,
                 &mut pi,
             )
@@ -279,10 +339,17 @@ impl SlavePty for ConPtySlavePty {
 
         // Make sure we close out the thread handle so we don't leak it;
         // we do this simply by making it owned
-        let _main_thread = OwnedHandle::new(pi.hThread);
+        let main_thread = OwnedHandle::new(pi.hThread);
         let proc = OwnedHandle::new(pi.hProcess);
 
-        Ok(Box::new(WinChild { proc }))
+        let job = job_for_process(proc.handle);
+        if unsafe { ResumeThread(main_thread.handle) } == DWORD::max_value() {
+            let err = IoError::last_os_error();
+            unsafe { TerminateProcess(proc.handle, 1) };
+            bail!("ResumeThread `{:?}` failed: {}", cmd_os, err);
+        }
+
+        Ok(Box::new(WinChild { proc, job }))
     }
 }
 
@@ -0,0 +1,85 @@
+//! Job Objects group a process together with all of the processes that
+//! it goes on to spawn, so that the whole tree can be terminated at once.
+//! Terminating just the process that we spawned would leave behind
+//! anything that it started, such as the python run from a cmd prompt.
+use super::ownedhandle::OwnedHandle;
+use failure::Error;
+use std::io::Error as IoError;
+use std::mem;
+use std::os::windows::raw::HANDLE;
+use std::ptr;
+use winapi::um::jobapi2::{
+    AssignProcessToJobObject, CreateJobObjectW, SetInformationJobObject, TerminateJobObject,
+};
+use winapi::um::winnt::{
+    JobObjectExtendedLimitInformation, JOBOBJECT_EXTENDED_LIMIT_INFORMATION,
+    JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+};
+
+/// A job whose processes are all terminated when it is dropped
+#[derive(Debug)]
+pub struct JobObject {
+    job: OwnedHandle,
+}
+
+impl JobObject {
+    pub fn new() -> Result<Self, Error> {
+        let handle = unsafe { CreateJobObjectW(ptr::null_mut(), ptr::null()) };
+        if handle.is_null() {
+            bail!("CreateJobObjectW failed: {}", IoError::last_os_error());
+        }
+        let job = OwnedHandle::new(handle);
+
+        let mut limits: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = unsafe { mem::zeroed() };
+        limits.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+        let res = unsafe {
+            SetInformationJobObject(
+                job.handle as *mut _,
+                JobObjectExtendedLimitInformation,
+                &mut limits as *mut _ as *mut _,
+                mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+            )
+        };
+        if res == 0 {
+            bail!(
+                "SetInformationJobObject failed: {}",
+                IoError::last_os_error()
+            );
+        }
+        Ok(Self { job })
+    }
+
+    /// Adds `process` to the job.  The processes that it spawns from
+    /// then on are added to the job too.
+    pub fn assign(&self, process: HANDLE) -> Result<(), Error> {
+        if unsafe { AssignProcessToJobObject(self.job.handle as *mut _, process as *mut _) } == 0 {
+            bail!(
+                "AssignProcessToJobObject failed: {}",
+                IoError::last_os_error()
+            );
+        }
+        Ok(())
+    }
+
+    /// Terminates every process in the job
+    pub fn terminate(&self, exit_code: u32) -> Result<(), IoError> {
+        if unsafe { TerminateJobObject(self.job.handle as *mut _, exit_code) } == 0 {
+            return Err(IoError::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+/// Returns a job that holds `process`, or None if it couldn't be
+/// assigned to one, which happens on versions of Windows prior to 8
+/// when wezterm is itself running in a job that doesn't allow nesting.
+/// The process can still be terminated, but not its descendants.
+pub fn job_for_process(process: HANDLE) -> Option<JobObject> {
+    match JobObject::new().and_then(|job| job.assign(process).map(|_| job)) {
+        Ok(job) => Some(job),
+        Err(err) => {
+            eprintln!("unable to place the child in a job: {}", err);
+            None
+        }
+    }
+}
@@ -7,15 +7,20 @@ use winapi::um::synchapi::WaitForSingleObject;
 use winapi::um::winbase::INFINITE;
 
 pub mod conpty;
+pub mod job;
 pub mod winpty;
 
 pub mod ownedhandle;
 
+use job::JobObject;
 use ownedhandle::OwnedHandle;
 
 #[derive(Debug)]
 pub struct WinChild {
     proc: OwnedHandle,
+    /// The job holding the process and its descendants, which are
+    /// terminated along with it when the job is dropped
+    job: Option<JobObject>,
 }
 
 impl Child for WinChild {
@@ -34,8 +39,11 @@ impl Child for WinChild {
     }
 
     fn kill(&mut self) -> IoResult<()> {
-        unsafe {
-            TerminateProcess(self.proc.handle, 1);
+        match &self.job {
+            Some(job) => job.terminate(1)?,
+            None => unsafe {
+                TerminateProcess(self.proc.handle, 1);
+            },
         }
         self.wait()?;
         Ok(())
@@ -56,4 +64,13 @@ impl Child for WinChild {
             Err(IoError::last_os_error())
         }
     }
+
+    fn process_id(&self) -> Option<u32> {
+        let pid = unsafe { GetProcessId(self.proc.handle) };
+        if pid == 0 {
+            None
+        } else {
+            Some(pid)
+        }
+    }
 }
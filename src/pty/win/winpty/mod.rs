@@ -1,3 +1,4 @@
+use super::job::job_for_process;
 use super::ownedhandle::OwnedHandle;
 use super::WinChild;
 use crate::pty::cmdbuilder::CommandBuilder;
@@ -71,15 +72,20 @@ impl SlavePty for WinPtySlavePty {
             SpawnFlags::AUTO_SHUTDOWN | SpawnFlags::EXIT_AFTER_SHUTDOWN,
             Some(exe),
             Some(cmdline),
-            None, // cwd
+            cmd.current_directory(),
             None, // env
         )?;
 
         let mut inner = self.inner.lock().unwrap();
         let spawned = inner.pty.spawn(&spawn_config)?;
 
+        // winpty spawns the process itself, so it is placed in the job
+        // once it is running; anything that it spawns in the meantime
+        // isn't included
+        let job = job_for_process(spawned.process_handle.handle);
         let child = WinChild {
             proc: spawned.process_handle,
+            job,
         };
 
         Ok(Box::new(child))
@@ -36,13 +36,22 @@ macro_rules! rpc {
 }
 
 impl Client {
+    /// Connect to the mux server, or to the instance of wezterm that
+    /// the command is run in if it is run in a tab
     pub fn new(config: &Arc<Config>) -> Result<Self, Error> {
-        let sock_path = Path::new(
+        if let Some(path) = std::env::var_os("WEZTERM_UNIX_SOCKET") {
+            return Self::with_path(Path::new(&path));
+        }
+        Self::with_path(Path::new(
             config
                 .mux_server_unix_domain_socket_path
                 .as_ref()
                 .ok_or_else(|| err_msg("no mux_server_unix_domain_socket_path"))?,
-        );
+        ))
+    }
+
+    /// Connect to the listener on `sock_path`
+    pub fn with_path(sock_path: &Path) -> Result<Self, Error> {
         eprintln!("connect to {}", sock_path.display());
         let stream = UnixStream::connect(sock_path)?;
         Ok(Self { stream, serial: 0 })
@@ -69,4 +78,15 @@ impl Client {
         GetCoarseTabRenderableData,
         GetCoarseTabRenderableDataResponse
     );
+    rpc!(
+        get_tab_unhandled_sequences,
+        GetTabUnhandledSequences,
+        GetTabUnhandledSequencesResponse
+    );
+    rpc!(spawn_tab, SpawnTab, SpawnTabResponse);
+    rpc!(
+        get_semantic_object,
+        GetSemanticObject,
+        GetSemanticObjectResponse
+    );
 }
@@ -16,7 +16,7 @@ use leb128;
 use serde_derive::*;
 use std::collections::HashMap;
 use std::sync::Arc;
-use term::{CursorPosition, Line};
+use term::{CursorPosition, Line, SemanticObject, UnhandledSequence};
 use termwiz::hyperlink::Hyperlink;
 use varbincode;
 
@@ -210,6 +210,12 @@ pdu! {
     ListTabsResponse: 4,
     GetCoarseTabRenderableData: 5,
     GetCoarseTabRenderableDataResponse: 6,
+    GetTabUnhandledSequences: 7,
+    GetTabUnhandledSequencesResponse: 8,
+    SpawnTab: 9,
+    SpawnTabResponse: 10,
+    GetSemanticObject: 11,
+    GetSemanticObjectResponse: 12,
 }
 
 #[derive(Deserialize, Serialize, PartialEq, Debug)]
@@ -251,6 +257,46 @@ pub struct GetCoarseTabRenderableDataResponse {
     pub dirty_lines: Vec<DirtyLine>,
 }
 
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct GetTabUnhandledSequences {
+    pub tab_id: TabId,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct GetTabUnhandledSequencesResponse {
+    /// The total number of sequences that were ignored
+    pub count: usize,
+    /// The most recently ignored sequences, oldest first
+    pub recent: Vec<UnhandledSequence>,
+}
+
+/// Spawn a new tab.  A gui instance opens it in its most recently
+/// created window.
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SpawnTab {
+    /// The command to run; the default program if None
+    pub args: Option<Vec<String>>,
+    /// The directory to run it in; inherited from the server if None
+    pub cwd: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SpawnTabResponse {}
+
+/// Ask what is displayed at a position in the viewport of a tab
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct GetSemanticObject {
+    pub tab_id: TabId,
+    pub row: i64,
+    pub col: usize,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct GetSemanticObjectResponse {
+    /// None if there is nothing of interest at that position
+    pub object: Option<SemanticObject>,
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
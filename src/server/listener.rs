@@ -1,12 +1,14 @@
-use crate::config::Config;
+use crate::frontend::guicommon::spawnqueue::queue_spawn;
 use crate::mux::Mux;
 use crate::server::codec::*;
 use crate::server::{UnixListener, UnixStream};
 use failure::{err_msg, Error};
 #[cfg(unix)]
 use libc::{mode_t, umask};
-use promise::{Executor, Future};
+use promise::task::spawn_with;
+use promise::Executor;
 use std::collections::HashMap;
+use std::ffi::OsStr;
 use std::fs::{remove_file, DirBuilder};
 #[cfg(unix)]
 use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
@@ -60,7 +62,7 @@ impl ClientSession {
                     Pdu::Pong(Pong {}).encode(&mut self.stream, decoded.serial)?;
                 }
                 Pdu::ListTabs(ListTabs {}) => {
-                    let result = Future::with_executor(self.executor.clone_executor(), move || {
+                    let result = spawn_with(self.executor.clone_executor(), move || {
                         let mut tabs = HashMap::new();
                         let mux = Mux::get().unwrap();
                         for tab in mux.iter_tabs() {
@@ -72,7 +74,7 @@ impl ClientSession {
                     Pdu::ListTabsResponse(result).encode(&mut self.stream, decoded.serial)?;
                 }
                 Pdu::GetCoarseTabRenderableData(GetCoarseTabRenderableData { tab_id }) => {
-                    let result = Future::with_executor(self.executor.clone_executor(), move || {
+                    let result = spawn_with(self.executor.clone_executor(), move || {
                         let mux = Mux::get().unwrap();
                         let tab = mux
                             .get_tab(tab_id)
@@ -104,9 +106,70 @@ impl ClientSession {
                         .encode(&mut self.stream, decoded.serial)?;
                 }
 
+                Pdu::GetTabUnhandledSequences(GetTabUnhandledSequences { tab_id }) => {
+                    let result = spawn_with(self.executor.clone_executor(), move || {
+                        let mux = Mux::get().unwrap();
+                        let tab = mux
+                            .get_tab(tab_id)
+                            .ok_or_else(|| format_err!("no such tab {}", tab_id))?;
+                        let (count, recent) = tab.unhandled_sequences();
+                        Ok(GetTabUnhandledSequencesResponse { count, recent })
+                    })
+                    .wait()?;
+                    Pdu::GetTabUnhandledSequencesResponse(result)
+                        .encode(&mut self.stream, decoded.serial)?;
+                }
+
+                Pdu::SpawnTab(SpawnTab { args, cwd }) => {
+                    let result = spawn_with(self.executor.clone_executor(), move || {
+                        let mux = Mux::get().unwrap();
+                        let config = Arc::clone(mux.config());
+                        let mut cmd = config.build_prog(
+                            args.as_ref()
+                                .map(|args| args.iter().map(OsStr::new).collect()),
+                        )?;
+                        if let Some(cwd) = cwd {
+                            cmd.cwd(cwd);
+                        }
+                        match mux.window_ids().last() {
+                            // Let the window spawn it, so that the tab
+                            // is sized to match
+                            Some(&window_id) => queue_spawn(window_id, cmd),
+                            None => {
+                                let tab = crate::spawn_tab(&config, cmd, false)?;
+                                mux.add_tab(&tab)?;
+                                // There is no frontend to make a window
+                                // for it, so nothing will use the id
+                                // that was reserved while spawning it
+                                mux.release_window_id(tab.tab_id());
+                            }
+                        }
+                        Ok(SpawnTabResponse {})
+                    })
+                    .wait()?;
+                    Pdu::SpawnTabResponse(result).encode(&mut self.stream, decoded.serial)?;
+                }
+
+                Pdu::GetSemanticObject(GetSemanticObject { tab_id, row, col }) => {
+                    let result = spawn_with(self.executor.clone_executor(), move || {
+                        let mux = Mux::get().unwrap();
+                        let tab = mux
+                            .get_tab(tab_id)
+                            .ok_or_else(|| format_err!("no such tab {}", tab_id))?;
+                        let object = tab.renderer().semantic_object_at(row, col);
+                        Ok(GetSemanticObjectResponse { object })
+                    })
+                    .wait()?;
+                    Pdu::GetSemanticObjectResponse(result)
+                        .encode(&mut self.stream, decoded.serial)?;
+                }
+
                 Pdu::Pong { .. }
                 | Pdu::ListTabsResponse { .. }
                 | Pdu::GetCoarseTabRenderableDataResponse { .. }
+                | Pdu::GetTabUnhandledSequencesResponse { .. }
+                | Pdu::SpawnTabResponse { .. }
+                | Pdu::GetSemanticObjectResponse { .. }
                 | Pdu::Invalid { .. } => {}
             }
         }
@@ -188,6 +251,14 @@ fn safely_create_sock_path(sock_path: &String) -> Result<UnixListener, Error> {
     }
 
     if sock_path.exists() {
+        // Only replace the socket if nothing is listening on it;
+        // otherwise we'd steal it from a live instance
+        if UnixStream::connect(sock_path).is_ok() {
+            bail!(
+                "{} is in use by another process that is still running",
+                sock_path.display()
+            );
+        }
         remove_file(sock_path)?;
     }
 
@@ -195,14 +266,115 @@ fn safely_create_sock_path(sock_path: &String) -> Result<UnixListener, Error> {
         .map_err(|e| format_err!("Failed to bind to {}: {}", sock_path.display(), e))
 }
 
-pub fn spawn_listener(config: &Arc<Config>, executor: Box<Executor>) -> Result<(), Error> {
-    let sock_path = config
+/// Identifies the socket that we created, so that we don't remove
+/// one that another process has since bound in its place
+#[derive(Debug, PartialEq, Eq)]
+struct SocketIdentity {
+    #[cfg(unix)]
+    dev: u64,
+    #[cfg(unix)]
+    ino: u64,
+    /// Guards against the inode being reused for a new socket
+    #[cfg(unix)]
+    ctime: (i64, i64),
+    #[cfg(windows)]
+    created: u64,
+}
+
+impl SocketIdentity {
+    fn of(path: &Path) -> Option<Self> {
+        let meta = path.symlink_metadata().ok()?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            Some(Self {
+                dev: meta.dev(),
+                ino: meta.ino(),
+                ctime: (meta.ctime(), meta.ctime_nsec()),
+            })
+        }
+        #[cfg(windows)]
+        {
+            use std::os::windows::fs::MetadataExt;
+            Some(Self {
+                created: meta.creation_time(),
+            })
+        }
+    }
+}
+
+/// Remove the socket at `sock_path` if it is still the one
+/// described by `identity`
+fn remove_own_socket(sock_path: &Path, identity: &Option<SocketIdentity>) {
+    if identity.is_none() || SocketIdentity::of(sock_path) != *identity {
+        return;
+    }
+    if let Err(err) = remove_file(sock_path) {
+        eprintln!("failed to remove {}: {}", sock_path.display(), err);
+    }
+}
+
+pub fn spawn_listener(mux: &Mux, executor: Box<Executor>) -> Result<(), Error> {
+    let sock_path = mux
+        .config()
         .mux_server_unix_domain_socket_path
         .as_ref()
         .ok_or_else(|| err_msg("no mux_server_unix_domain_socket_path"))?;
+    spawn_listener_at(sock_path, mux, executor)
+}
+
+/// Listen on `sock_path` rather than the configured mux server path.
+/// This is used by the gui to accept requests from other invocations
+/// of wezterm.
+pub fn spawn_listener_at(
+    sock_path: &String,
+    mux: &Mux,
+    executor: Box<Executor>,
+) -> Result<(), Error> {
     let mut listener = Listener::new(safely_create_sock_path(sock_path)?, executor);
+    mux.set_socket_path(sock_path);
+
+    // Don't leave a stale socket behind for clients to find, but
+    // leave alone any socket that another instance has since bound
+    let sock_path = Path::new(sock_path).to_path_buf();
+    let identity = SocketIdentity::of(&sock_path);
+    mux.add_shutdown_hook(move || remove_own_socket(&sock_path, &identity));
+
     thread::spawn(move || {
         listener.run();
     });
     Ok(())
 }
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn socket_ownership() {
+        let dir = std::env::temp_dir().join(format!("wezterm-listener-{}", std::process::id()));
+        let sock_path = dir.join("sock").to_str().unwrap().to_owned();
+        let path = Path::new(&sock_path);
+
+        let first = safely_create_sock_path(&sock_path).unwrap();
+        // A socket that is still being listened on is not replaced
+        assert!(safely_create_sock_path(&sock_path).is_err());
+        assert!(UnixStream::connect(path).is_ok());
+
+        // but a stale one is
+        drop(first);
+        let second = safely_create_sock_path(&sock_path).unwrap();
+        assert!(UnixStream::connect(path).is_ok());
+
+        // Only the socket that we created is removed
+        let other = dir.join("other");
+        std::fs::write(&other, b"").unwrap();
+        remove_own_socket(path, &SocketIdentity::of(&other));
+        assert!(path.exists());
+        remove_own_socket(path, &SocketIdentity::of(path));
+        assert!(!path.exists());
+
+        drop(second);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
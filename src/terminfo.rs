@@ -0,0 +1,204 @@
+//! Provides the `wezterm` terminfo entry, which describes the
+//! capabilities that the terminal has beyond those of xterm-256color,
+//! and helps to install it so that the programs running in it, both
+//! locally and on remote hosts, can make use of them.
+use failure::Error;
+use lazy_static::lazy_static;
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// The name of the terminfo entry, and so the value of TERM that
+/// selects it
+pub const TERM_NAME: &str = "wezterm";
+
+/// The value of TERM that is used in place of `TERM_NAME` when its
+/// entry isn't installed
+pub const FALLBACK_TERM: &str = "xterm-256color";
+
+/// The source of the terminfo entry, in the form accepted by `tic`
+pub const TERMINFO_SOURCE: &str = include_str!("../assets/terminfo/wezterm.terminfo");
+
+/// Returns the directory that the entry is installed into by default
+pub fn user_terminfo_dir() -> Result<PathBuf, Error> {
+    let home = dirs::home_dir().ok_or_else(|| format_err!("can't find home dir"))?;
+    Ok(home.join(".terminfo"))
+}
+
+/// Returns the directories that are searched for terminfo entries,
+/// in the same order as ncurses searches them
+fn search_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![];
+    if let Some(dir) = std::env::var_os("TERMINFO") {
+        dirs.push(PathBuf::from(dir));
+    }
+    if let Ok(dir) = user_terminfo_dir() {
+        dirs.push(dir);
+    }
+    if let Some(list) = std::env::var_os("TERMINFO_DIRS") {
+        dirs.extend(std::env::split_paths(&list).filter(|p| !p.as_os_str().is_empty()));
+    }
+    for dir in &[
+        "/etc/terminfo",
+        "/lib/terminfo",
+        "/usr/share/terminfo",
+        "/usr/lib/terminfo",
+        "/usr/share/lib/terminfo",
+    ] {
+        dirs.push(PathBuf::from(dir));
+    }
+    dirs
+}
+
+/// Returns the path to the compiled entry within `dir`, if present.
+/// Entries are filed under their first letter, or under its hex
+/// value on filesystems that are case insensitive.
+fn find_in_dir(dir: &Path, name: &str) -> Option<PathBuf> {
+    let first = name.chars().next()?;
+    [first.to_string(), format!("{:x}", first as u32)]
+        .iter()
+        .map(|sub| dir.join(sub).join(name))
+        .find(|path| path.is_file())
+}
+
+/// Returns the location of the compiled entry, if it is installed
+pub fn find_installed() -> Option<PathBuf> {
+    search_dirs()
+        .iter()
+        .filter_map(|dir| find_in_dir(dir, TERM_NAME))
+        .next()
+}
+
+lazy_static! {
+    /// Whether the entry is installed, as of the first time that it
+    /// is needed; this saves searching for it each time a tab is spawned
+    static ref INSTALLED: bool = find_installed().is_some();
+}
+
+/// Returns the value of TERM to advertise when `configured` is the
+/// configured value.  Programs can't make use of the terminal if TERM
+/// names an entry that they can't find, so the wezterm entry is only
+/// advertised once it is installed.
+pub fn resolve_term(configured: &str) -> &str {
+    resolve(configured, *INSTALLED)
+}
+
+fn resolve(configured: &str, installed: bool) -> &str {
+    if configured == TERM_NAME && !installed {
+        FALLBACK_TERM
+    } else {
+        configured
+    }
+}
+
+/// Compiles the entry into `dir`, or into `~/.terminfo` if `dir` is
+/// None, using `tic`.  Returns the directory that it was installed into.
+pub fn install(dir: Option<&Path>) -> Result<PathBuf, Error> {
+    let dir = match dir {
+        Some(dir) => dir.to_path_buf(),
+        None => user_terminfo_dir()?,
+    };
+    std::fs::create_dir_all(&dir)?;
+
+    // The source is piped to tic rather than written to a file,
+    // so that there is no temporary file for anyone to tamper with
+    let mut out_dir = OsString::from("-o");
+    out_dir.push(&dir);
+    let mut child = match Command::new("tic")
+        .arg("-x")
+        .arg(&out_dir)
+        .arg("-")
+        .stdin(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(err) => bail!(
+            "unable to run tic, which is usually provided by ncurses: {}",
+            err
+        ),
+    };
+    let written = child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(TERMINFO_SOURCE.as_bytes());
+    let status = child.wait()?;
+    if !status.success() {
+        bail!("tic failed: {}", status);
+    }
+    written?;
+    Ok(dir)
+}
+
+/// Returns shell commands that make use of the entry installed in `dir`.
+/// If it isn't found there when searching the usual locations, they
+/// include adding `dir` to the search path.
+pub fn shell_snippets(dir: &Path) -> String {
+    let found = find_installed()
+        .map(|path| path.starts_with(dir))
+        .unwrap_or(false);
+    let dir = dir.display();
+    let search_path = if found {
+        String::new()
+    } else {
+        format!(
+            "# {dir} isn't searched by default; add it to the search path\n\
+             # bash, zsh:\n\
+             export TERMINFO_DIRS=\"{dir}:$TERMINFO_DIRS\"\n\
+             # fish:\n\
+             set -gx TERMINFO_DIRS {dir} $TERMINFO_DIRS\n\n",
+            dir = dir
+        )
+    };
+    format!(
+        "{search_path}\
+         # To install the entry on a remote HOST:\n\
+         wezterm terminfo | ssh HOST tic -x -\n\
+         \n\
+         # ssh passes TERM on to the remote host; on hosts that may lack\n\
+         # the entry, add this to the shell startup files to fall back\n\
+         # to {fallback} there.\n\
+         # bash, zsh:\n\
+         [ \"$TERM\" = {name} ] && ! infocmp {name} >/dev/null 2>&1 && \
+         export TERM={fallback}\n\
+         # fish:\n\
+         test \"$TERM\" = {name}; and not infocmp {name} >/dev/null 2>&1; \
+         and set -gx TERM {fallback}\n",
+        search_path = search_path,
+        name = TERM_NAME,
+        fallback = FALLBACK_TERM,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn resolution() {
+        assert_eq!(resolve(TERM_NAME, true), TERM_NAME);
+        assert_eq!(resolve(TERM_NAME, false), FALLBACK_TERM);
+        assert_eq!(resolve("screen-256color", false), "screen-256color");
+        assert_eq!(resolve("screen-256color", true), "screen-256color");
+
+        // The cached answer is stable
+        assert_eq!(resolve_term(TERM_NAME), resolve_term(TERM_NAME));
+        assert_eq!(resolve_term("xterm"), "xterm");
+    }
+
+    #[test]
+    fn find_in_dir_layouts() {
+        let dir = std::env::temp_dir().join(format!("wezterm-terminfo-{}", std::process::id()));
+        assert_eq!(find_in_dir(&dir, TERM_NAME), None);
+
+        // The hex layout used by macOS
+        std::fs::create_dir_all(dir.join("77")).unwrap();
+        std::fs::write(dir.join("77").join(TERM_NAME), b"").unwrap();
+        assert_eq!(
+            find_in_dir(&dir, TERM_NAME),
+            Some(dir.join("77").join(TERM_NAME))
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
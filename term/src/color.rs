@@ -16,6 +16,7 @@ pub struct ColorPalette {
     pub cursor_bg: RgbColor,
     pub selection_fg: RgbColor,
     pub selection_bg: RgbColor,
+    pub scrollbar_thumb: RgbColor,
 }
 
 impl fmt::Debug for Palette256 {
@@ -185,6 +186,8 @@ impl Default for ColorPalette {
         let selection_fg = colors[AnsiColor::Black as usize];
         let selection_bg = RgbColor::new(0xff, 0xfa, 0xcd);
 
+        let scrollbar_thumb = colors[240]; // Grey35
+
         ColorPalette {
             colors: Palette256(colors),
             foreground,
@@ -193,6 +196,7 @@ impl Default for ColorPalette {
             cursor_bg,
             selection_fg,
             selection_bg,
+            scrollbar_thumb,
         }
     }
 }
@@ -0,0 +1,80 @@
+//! Sets of highlighted cells, such as the matches of a search, which
+//! are shown in colors of their own.  Any number of sets can be shown
+//! at once, each identified by a name chosen by whoever sets it, and
+//! they are kept apart from the selection, which is drawn over them.
+use super::*;
+use crate::color::RgbColor;
+
+/// A set of cells that are highlighted in the same colors
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HighlightSet {
+    /// The highlighted cells.  The rows are stable indices, so the
+    /// highlights stay with the text as it scrolls.
+    pub ranges: Vec<SearchResult>,
+    pub fg: RgbColor,
+    pub bg: RgbColor,
+}
+
+/// The part of a `HighlightSet` that falls on a line of the viewport
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineHighlight {
+    pub columns: Range<usize>,
+    pub fg: RgbColor,
+    pub bg: RgbColor,
+}
+
+/// Returns the colors of the last of `highlights` that includes the
+/// cell at `col`, as (fg, bg)
+pub fn highlight_colors(highlights: &[LineHighlight], col: usize) -> Option<(RgbColor, RgbColor)> {
+    highlights
+        .iter()
+        .rev()
+        .find(|h| in_range(col, &h.columns))
+        .map(|h| (h.fg, h.bg))
+}
+
+/// The highlight sets of a terminal, in the order that they were set
+#[derive(Debug, Default)]
+pub struct Highlights {
+    sets: Vec<(String, HighlightSet)>,
+}
+
+impl Highlights {
+    /// Replaces the set named `name`, returning the set that it
+    /// replaced, if any.  Sets that are set later are drawn over
+    /// those that were set earlier.
+    pub fn set(&mut self, name: &str, set: HighlightSet) -> Option<HighlightSet> {
+        let prior = self.remove(name);
+        self.sets.push((name.to_owned(), set));
+        prior
+    }
+
+    /// Removes the set named `name`, returning it
+    pub fn remove(&mut self, name: &str) -> Option<HighlightSet> {
+        let idx = self.sets.iter().position(|(n, _)| n == name)?;
+        Some(self.sets.remove(idx).1)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&HighlightSet> {
+        self.sets
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, set)| set)
+    }
+
+    /// Returns the highlights of the line `row`, with those that are
+    /// drawn on top last
+    pub fn for_row(&self, row: StableRowIndex) -> Vec<LineHighlight> {
+        let mut result = vec![];
+        for (_, set) in &self.sets {
+            for range in set.ranges.iter().filter(|r| r.row == row) {
+                result.push(LineHighlight {
+                    columns: range.columns.clone(),
+                    fg: set.fg,
+                    bg: set.bg,
+                });
+            }
+        }
+        result
+    }
+}
@@ -30,6 +30,10 @@ pub struct MouseEvent {
     pub kind: MouseEventKind,
     pub x: usize,
     pub y: VisibleRowIndex,
+    /// The position of the mouse in pixels, relative to the top left
+    /// of the terminal.  This is used by the SGR-Pixels reporting mode.
+    pub x_pixel: usize,
+    pub y_pixel: usize,
     pub button: MouseButton,
     pub modifiers: KeyModifiers,
 }
@@ -74,3 +78,146 @@ impl LastMouseClick {
         }
     }
 }
+
+/// The bits of the control key state of a win32 key event
+const LEFT_ALT_PRESSED: u32 = 0x2;
+const LEFT_CTRL_PRESSED: u32 = 0x8;
+const SHIFT_PRESSED: u32 = 0x10;
+const ENHANCED_KEY: u32 = 0x100;
+
+/// Returns the win32 virtual key code of `key`, or 0 for characters
+/// that don't have their own key.  Returns None for keys that can't
+/// be represented.
+fn win32_virtual_key(key: KeyCode) -> Option<u32> {
+    use crate::KeyCode::*;
+    Some(match key {
+        Char(c) if c.is_ascii_lowercase() => c.to_ascii_uppercase() as u32,
+        Char(c) if c.is_ascii_uppercase() || c.is_ascii_digit() || c == ' ' => c as u32,
+        Char(_) => 0,
+        Cancel => 0x03,
+        Backspace => 0x08,
+        Tab => 0x09,
+        Clear => 0x0c,
+        Enter => 0x0d,
+        Shift | LeftShift | RightShift => 0x10,
+        Control | LeftControl | RightControl => 0x11,
+        Alt | LeftAlt | RightAlt | Menu | LeftMenu | RightMenu | Meta => 0x12,
+        Pause => 0x13,
+        CapsLock => 0x14,
+        Escape => 0x1b,
+        PageUp => 0x21,
+        PageDown => 0x22,
+        End => 0x23,
+        Home => 0x24,
+        LeftArrow => 0x25,
+        UpArrow => 0x26,
+        RightArrow => 0x27,
+        DownArrow => 0x28,
+        Select => 0x29,
+        Print => 0x2a,
+        Execute => 0x2b,
+        PrintScreen => 0x2c,
+        Insert => 0x2d,
+        Delete => 0x2e,
+        Help => 0x2f,
+        LeftWindows | Super => 0x5b,
+        RightWindows => 0x5c,
+        Applications => 0x5d,
+        Sleep => 0x5f,
+        Numpad0 => 0x60,
+        Numpad1 => 0x61,
+        Numpad2 => 0x62,
+        Numpad3 => 0x63,
+        Numpad4 => 0x64,
+        Numpad5 => 0x65,
+        Numpad6 => 0x66,
+        Numpad7 => 0x67,
+        Numpad8 => 0x68,
+        Numpad9 => 0x69,
+        Multiply => 0x6a,
+        Add => 0x6b,
+        Separator => 0x6c,
+        Subtract => 0x6d,
+        Decimal => 0x6e,
+        Divide => 0x6f,
+        Function(n) if n >= 1 && n <= 24 => 0x6f + u32::from(n),
+        NumLock => 0x90,
+        ScrollLock => 0x91,
+        BrowserBack => 0xa6,
+        BrowserForward => 0xa7,
+        BrowserRefresh => 0xa8,
+        BrowserStop => 0xa9,
+        BrowserSearch => 0xaa,
+        BrowserFavorites => 0xab,
+        BrowserHome => 0xac,
+        VolumeMute => 0xad,
+        VolumeDown => 0xae,
+        VolumeUp => 0xaf,
+        MediaNextTrack => 0xb0,
+        MediaPrevTrack => 0xb1,
+        MediaStop => 0xb2,
+        MediaPlayPause => 0xb3,
+        Function(_) | Hyper | InternalPasteStart | InternalPasteEnd => return None,
+    })
+}
+
+/// Returns the encoding of a key being pressed or released that is
+/// used once an application, in practice ConPTY, has enabled
+/// win32-input-mode (DEC private mode 9001):
+/// `CSI Vk ; Sc ; Uc ; Kd ; Cs ; Rc _`, which holds the fields of a
+/// win32 `KEY_EVENT_RECORD`: the virtual key code, scan code, unicode
+/// character, whether the key is down, the control key state and the
+/// repeat count.  The scan code isn't known, so it is left as 0 for
+/// the console to derive from the virtual key code.
+/// Returns None for keys that can't be represented.
+pub fn encode_win32_input(key: KeyCode, mods: KeyModifiers, key_down: bool) -> Option<String> {
+    let vk = win32_virtual_key(key)?;
+    let ctrl = mods.contains(KeyModifiers::CTRL);
+    let unicode = match key {
+        // The console reports the control code that the key produces
+        KeyCode::Char(c) if ctrl && c.is_ascii_alphabetic() => c.to_ascii_uppercase() as u32 & 0x1f,
+        // Characters outside of the BMP don't fit in a single UTF-16 unit
+        KeyCode::Char(c) if c as u32 > 0xffff => return None,
+        KeyCode::Char(c) => c as u32,
+        KeyCode::Backspace => 0x08,
+        KeyCode::Tab => 0x09,
+        KeyCode::Enter => 0x0d,
+        KeyCode::Escape => 0x1b,
+        _ => 0,
+    };
+
+    let mut state = 0;
+    if mods.contains(KeyModifiers::ALT) {
+        state |= LEFT_ALT_PRESSED;
+    }
+    if ctrl {
+        state |= LEFT_CTRL_PRESSED;
+    }
+    if mods.contains(KeyModifiers::SHIFT) {
+        state |= SHIFT_PRESSED;
+    }
+    match key {
+        KeyCode::Insert
+        | KeyCode::Delete
+        | KeyCode::Home
+        | KeyCode::End
+        | KeyCode::PageUp
+        | KeyCode::PageDown
+        | KeyCode::LeftArrow
+        | KeyCode::RightArrow
+        | KeyCode::UpArrow
+        | KeyCode::DownArrow
+        | KeyCode::Divide
+        | KeyCode::RightControl
+        | KeyCode::RightAlt => state |= ENHANCED_KEY,
+        _ => {}
+    }
+
+    Some(format!(
+        "\x1b[{};0;{};{};{};1_",
+        vk,
+        unicode,
+        if key_down { 1 } else { 0 },
+        state
+    ))
+}
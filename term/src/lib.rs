@@ -25,6 +25,12 @@ pub use crate::screen::*;
 pub mod selection;
 use crate::selection::{SelectionCoordinate, SelectionRange};
 
+pub mod search;
+pub use crate::search::*;
+
+pub mod highlight;
+pub use crate::highlight::{HighlightSet, LineHighlight};
+
 use termwiz::hyperlink::Hyperlink;
 
 pub mod terminal;
@@ -38,6 +44,13 @@ pub use crate::terminalstate::*;
 /// depends on the terminal dimensions and the scrollback size.
 pub type PhysRowIndex = usize;
 
+/// Identifies a line in a way that remains valid as lines scroll
+/// into the scrollback and are eventually discarded from the top of it.
+/// The topmost line of the scrollback has a `StableRowIndex` equal to
+/// the number of lines that have been discarded so far.
+/// Use `Screen::stable_row_to_phys` to resolve it to a `PhysRowIndex`.
+pub type StableRowIndex = usize;
+
 /// Represents an index into the visible portion of the screen.
 /// Value 0 is the first visible row.  `VisibleRowIndex` needs to be
 /// resolved into a `PhysRowIndex` to obtain an actual row.  It is not
@@ -96,11 +109,37 @@ pub mod color;
 #[cfg(test)]
 mod test;
 
-/// The response we given when queries for device attributes.
-/// This particular string says "we are a VT102".
-/// TODO: Consider VT220 extended response which can advertise
-/// certain feature sets.
-pub const DEVICE_IDENT: &[u8] = b"\x1b[?6c";
+/// How the terminal identifies itself to programs that ask.
+/// Programs such as vim and tmux use these responses to decide which
+/// features to use, so they should describe the terminal accurately.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceIdentity {
+    /// The parameters of the response to a primary device attributes
+    /// request (`CSI c`), which is sent as `CSI ? {} c`
+    pub primary_attributes: String,
+    /// The parameters of the response to a secondary device attributes
+    /// request (`CSI > c`), which is sent as `CSI > {} c`
+    pub secondary_attributes: String,
+    /// The name and version of the terminal, sent in response to
+    /// XTVERSION (`CSI > q`) as `DCS > | {} ST`
+    pub name_and_version: String,
+    /// The terminfo name of the terminal, reported as the `TN`
+    /// capability in response to XTGETTCAP
+    pub term: String,
+}
+
+impl Default for DeviceIdentity {
+    fn default() -> Self {
+        Self {
+            // A VT220 with ANSI color
+            primary_attributes: "62;22".to_string(),
+            // A VT220 with firmware version 0 and no ROM cartridge
+            secondary_attributes: "1;0;0".to_string(),
+            name_and_version: "wezterm".to_string(),
+            term: "xterm-256color".to_string(),
+        }
+    }
+}
 
 pub const CSI: &[u8] = b"\x1b[";
 pub const OSC: &[u8] = b"\x1b]";
@@ -1,6 +1,20 @@
 use super::*;
+use std::borrow::Cow;
 use std::collections::VecDeque;
 
+/// The number of lines of scrollback immediately above the visible
+/// portion of the screen that are kept uncompressed, so that scrolling
+/// back a short distance doesn't require decompressing anything
+const UNCOMPRESSED_SCROLLBACK: usize = 100;
+
+/// A line of the screen, which is compressed once it has scrolled
+/// far enough back into the scrollback
+#[derive(Debug, Clone)]
+enum StoredLine {
+    Live(Line),
+    Compressed(CompressedLine),
+}
+
 /// Holds the model of a screen.  This can either be the primary screen
 /// which includes lines of scrollback text, or the alternate screen
 /// which holds no scrollback.  The intent is to have one instance of
@@ -15,7 +29,17 @@ pub struct Screen {
     /// on the current window size) and will be the first line to be
     /// popped off the front of the screen when a new line is added that
     /// would otherwise have exceeded the line capacity
-    pub lines: VecDeque<Line>,
+    lines: VecDeque<StoredLine>,
+
+    /// The range of lines that were decompressed by the most recent
+    /// call to `thaw`; they are compressed again by the next call to
+    /// `thaw` if they are no longer in use.
+    thawed: Range<PhysRowIndex>,
+
+    /// The number of lines that have been discarded from the top of
+    /// the scrollback; this relates a `PhysRowIndex` to its
+    /// `StableRowIndex`
+    stable_row_offset: StableRowIndex,
 
     /// Maximum number of lines of scrollback
     pub scrollback_size: usize,
@@ -33,11 +57,13 @@ impl Screen {
     pub fn new(physical_rows: usize, physical_cols: usize, scrollback_size: usize) -> Screen {
         let mut lines = VecDeque::with_capacity(physical_rows + scrollback_size);
         for _ in 0..physical_rows {
-            lines.push_back(Line::with_width(physical_cols));
+            lines.push_back(StoredLine::Live(Line::with_width(physical_cols)));
         }
 
         Screen {
             lines,
+            thawed: 0..0,
+            stable_row_offset: 0,
             scrollback_size,
             physical_rows,
             physical_cols,
@@ -52,20 +78,316 @@ impl Screen {
             self.lines.reserve(capacity - current_capacity);
         }
 
+        let prior_threshold = self.compression_threshold();
         if physical_rows > self.physical_rows {
             // Enlarging the viewable portion?  Add more lines at the bottom
             for _ in self.physical_rows..physical_rows {
-                self.lines.push_back(Line::with_width(physical_cols));
+                self.lines
+                    .push_back(StoredLine::Live(Line::with_width(physical_cols)));
             }
         }
         self.physical_rows = physical_rows;
         self.physical_cols = physical_cols;
+
+        // Shrinking moves lines into the scrollback
+        let threshold = self.compression_threshold();
+        self.compress_range(prior_threshold..threshold);
+    }
+
+    /// Rewrap the lines of the screen and its scrollback to fit
+    /// `physical_cols` columns.  This must be called before `resize`,
+    /// while `physical_rows` still reflects the prior size of the screen.
+    /// Physical lines that are joined by `Line::is_wrapped` are treated as
+    /// a single logical line, so that text that was wrapped when printed
+    /// flows to the new width rather than being truncated.  Returns the
+    /// adjusted position of `cursor` so that it remains at the same
+    /// position in its logical line.
+    /// The stable indices of the lines change as a result.
+    pub fn rewrap(&mut self, physical_cols: usize, cursor: CursorPosition) -> CursorPosition {
+        let cursor_phys = self.phys_row(cursor.y);
+        let blank = Cell::default();
+
+        let mut rewrapped: VecDeque<StoredLine> = VecDeque::with_capacity(self.lines.capacity());
+        let mut logical: Option<Line> = None;
+        // The number of physical lines joined into `logical` so far
+        let mut joined = 0;
+        // The offset of the cursor within the logical line that holds
+        // it, and the row of that logical line that it was on
+        let mut logical_cursor = None;
+        let mut new_cursor = (cursor_phys, cursor.x);
+        // How many more rows of the cursor's logical line are above
+        // the cursor than before
+        let mut grown = 0;
+
+        let lines = std::mem::replace(&mut self.lines, VecDeque::new());
+        let num_lines = lines.len();
+        for (idx, stored) in lines.into_iter().enumerate() {
+            let line = match stored {
+                StoredLine::Live(line) => line,
+                StoredLine::Compressed(compressed) => compressed.decompress(),
+            };
+            let mut line = match logical.take() {
+                Some(mut prior) => {
+                    joined += 1;
+                    if idx == cursor_phys {
+                        logical_cursor = Some((prior.cells().len() + cursor.x, joined));
+                    }
+                    prior.append_line(line);
+                    prior
+                }
+                None => {
+                    joined = 0;
+                    if idx == cursor_phys {
+                        logical_cursor = Some((cursor.x, 0));
+                    }
+                    line
+                }
+            };
+            if line.is_wrapped() {
+                if idx + 1 < num_lines {
+                    logical = Some(line);
+                    continue;
+                }
+                // There is no line for it to continue onto
+                line.set_wrapped(false);
+            }
+
+            // Trailing blank cells are not worth wrapping, but the
+            // cells up to the cursor must be kept in place
+            let keep = line
+                .cells()
+                .iter()
+                .rposition(|cell| *cell != blank)
+                .map(|idx| idx + 1)
+                .unwrap_or(0)
+                .max(logical_cursor.map(|(x, _)| x + 1).unwrap_or(0));
+            line.resize(keep);
+
+            let mut start = 0;
+            for (row, mut part) in line.wrap(physical_cols).into_iter().enumerate() {
+                let len = part.cells().len();
+                if let Some((x, old_row)) = logical_cursor {
+                    if x >= start && x < start + len {
+                        new_cursor = (rewrapped.len(), x - start);
+                        grown = row.saturating_sub(old_row);
+                        logical_cursor = None;
+                    }
+                }
+                start += len;
+                if len < physical_cols {
+                    part.resize(physical_cols);
+                }
+                rewrapped.push_back(StoredLine::Live(part));
+            }
+        }
+        self.lines = rewrapped;
+
+        // Keep the same number of lines below the cursor as before, so
+        // that the cursor keeps its row on the screen, unless narrowing
+        // pushed the start of its logical line upwards; in that case
+        // blank lines below the cursor are discarded to keep the whole
+        // logical line on the screen.  Then pad so that the screen is full.
+        let below_cursor = self
+            .physical_rows
+            .saturating_sub(cursor.y as usize + 1)
+            .saturating_sub(grown);
+        while self.lines.len() > new_cursor.0 + below_cursor + 1 {
+            match self.lines.back() {
+                Some(StoredLine::Live(line)) if line.cells().iter().all(|c| *c == blank) => {
+                    self.lines.pop_back();
+                }
+                _ => break,
+            }
+        }
+        while self.lines.len() < (new_cursor.0 + below_cursor + 1).max(self.physical_rows) {
+            self.lines
+                .push_back(StoredLine::Live(Line::with_width(physical_cols)));
+        }
+
+        // Discard lines that no longer fit in the scrollback
+        let max_allowed = self.physical_rows + self.scrollback_size;
+        let excess = self.lines.len().saturating_sub(max_allowed);
+        for _ in 0..excess {
+            self.lines.pop_front();
+        }
+        self.stable_row_offset += excess;
+        let cursor_phys = new_cursor.0.saturating_sub(excess);
+
+        self.thawed = 0..0;
+        let threshold = self.compression_threshold();
+        self.compress_range(0..threshold);
+
+        let top = self.lines.len() - self.physical_rows;
+        CursorPosition {
+            x: new_cursor.1,
+            y: cursor_phys.saturating_sub(top) as VisibleRowIndex,
+        }
+    }
+
+    /// Returns the number of lines, including the scrollback
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Returns true if there are no lines at all, which is only the
+    /// case for a screen with no rows
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// Returns the stable index of the line at `idx`
+    #[inline]
+    pub fn phys_to_stable_row_index(&self, idx: PhysRowIndex) -> StableRowIndex {
+        idx + self.stable_row_offset
+    }
+
+    /// Resolves a stable index into the physical index of that line,
+    /// or returns None if the line has been discarded from the
+    /// scrollback or doesn't exist yet
+    pub fn stable_row_to_phys(&self, row: StableRowIndex) -> Option<PhysRowIndex> {
+        if row < self.stable_row_offset {
+            return None;
+        }
+        let idx = row - self.stable_row_offset;
+        if idx < self.lines.len() {
+            Some(idx)
+        } else {
+            None
+        }
+    }
+
+    /// Get a line, relative to start of scrollback.
+    /// Lines in the scrollback may need to be decompressed, in which
+    /// case a copy of the line is returned.
+    pub fn line(&self, idx: PhysRowIndex) -> Cow<Line> {
+        match &self.lines[idx] {
+            StoredLine::Live(line) => Cow::Borrowed(line),
+            StoredLine::Compressed(compressed) => Cow::Owned(compressed.decompress()),
+        }
+    }
+
+    /// Get a line, relative to start of scrollback, only if it
+    /// hasn't been compressed.  Compressed lines cannot be dirty, so
+    /// this is useful when looking for changes.
+    #[inline]
+    pub fn live_line(&self, idx: PhysRowIndex) -> Option<&Line> {
+        match self.lines.get(idx) {
+            Some(StoredLine::Live(line)) => Some(line),
+            _ => None,
+        }
+    }
+
+    /// Like `live_line`, but mutable
+    #[inline]
+    pub fn live_line_mut(&mut self, idx: PhysRowIndex) -> Option<&mut Line> {
+        match self.lines.get_mut(idx) {
+            Some(StoredLine::Live(line)) => Some(line),
+            _ => None,
+        }
+    }
+
+    /// Iterates over the lines that are not compressed
+    pub fn live_lines_mut(&mut self) -> impl Iterator<Item = &mut Line> {
+        self.lines.iter_mut().filter_map(|line| match line {
+            StoredLine::Live(line) => Some(line),
+            StoredLine::Compressed(_) => None,
+        })
     }
 
     /// Get mutable reference to a line, relative to start of scrollback.
+    /// The line is decompressed if necessary.
     #[inline]
     pub fn line_mut(&mut self, idx: PhysRowIndex) -> &mut Line {
-        &mut self.lines[idx]
+        if let StoredLine::Compressed(_) = self.lines[idx] {
+            let start = self.thawed.start.min(idx);
+            let end = self.thawed.end.max(idx + 1);
+            self.decompress_line(idx);
+            self.thawed = start..end;
+        }
+        match &mut self.lines[idx] {
+            StoredLine::Live(line) => line,
+            StoredLine::Compressed(_) => unreachable!(),
+        }
+    }
+
+    /// Ensure that the lines in `range`, typically the viewport, are
+    /// decompressed.  Lines that were decompressed by a prior call and
+    /// that are outside of `range` are compressed again.
+    pub fn thaw(&mut self, range: Range<PhysRowIndex>) {
+        let range = range.start.min(self.lines.len())..range.end.min(self.lines.len());
+        let prior = self.thawed.clone();
+        let threshold = self.compression_threshold();
+        for idx in prior {
+            if !in_range(idx, &range) && idx < threshold {
+                self.compress_line(idx);
+            }
+        }
+        for idx in range.clone() {
+            self.decompress_line(idx);
+        }
+        self.thawed = range;
+    }
+
+    /// Lines above this index are eligible to be compressed
+    fn compression_threshold(&self) -> PhysRowIndex {
+        self.lines
+            .len()
+            .saturating_sub(self.physical_rows + UNCOMPRESSED_SCROLLBACK)
+    }
+
+    fn compress_line(&mut self, idx: PhysRowIndex) {
+        let compressed = match self.lines.get(idx) {
+            Some(StoredLine::Live(line)) => CompressedLine::new(line),
+            _ => return,
+        };
+        self.lines[idx] = StoredLine::Compressed(compressed);
+    }
+
+    fn decompress_line(&mut self, idx: PhysRowIndex) {
+        let line = match self.lines.get(idx) {
+            Some(StoredLine::Compressed(compressed)) => compressed.decompress(),
+            _ => return,
+        };
+        self.lines[idx] = StoredLine::Live(line);
+    }
+
+    /// Compress the lines in `range`, other than those that are
+    /// in use per `thaw`
+    fn compress_range(&mut self, range: Range<PhysRowIndex>) {
+        for idx in range {
+            if !in_range(idx, &self.thawed) {
+                self.compress_line(idx);
+            }
+        }
+    }
+
+    /// Adjust `thawed` to account for the removal of `count` lines
+    /// starting at `idx`
+    fn lines_removed(&mut self, idx: PhysRowIndex, count: usize) {
+        let adjust = |row: PhysRowIndex| {
+            if row >= idx + count {
+                row - count
+            } else {
+                row.min(idx)
+            }
+        };
+        self.thawed = adjust(self.thawed.start)..adjust(self.thawed.end);
+    }
+
+    /// Adjust `thawed` to account for the insertion of `count` lines
+    /// at `idx`
+    fn lines_inserted(&mut self, idx: PhysRowIndex, count: usize) {
+        if self.thawed.start >= self.thawed.end {
+            return;
+        }
+        if self.thawed.start >= idx {
+            self.thawed.start += count;
+        }
+        if self.thawed.end > idx {
+            self.thawed.end += count;
+        }
     }
 
     /// Sets a line dirty.  The line is relative to the visible origin.
@@ -73,7 +395,17 @@ impl Screen {
     pub fn dirty_line(&mut self, idx: VisibleRowIndex) {
         let line_idx = self.phys_row(idx);
         if line_idx < self.lines.len() {
-            self.lines[line_idx].set_dirty();
+            self.line_mut(line_idx).set_dirty();
+        }
+    }
+
+    /// Marks a range of cells within a line as dirty, without dirtying
+    /// the remainder of the line.  The line is relative to the visible origin.
+    #[inline]
+    pub fn dirty_cells(&mut self, idx: VisibleRowIndex, cols: Range<usize>) {
+        let line_idx = self.phys_row(idx);
+        if line_idx < self.lines.len() {
+            self.line_mut(line_idx).damage_columns(cols);
         }
     }
 
@@ -82,11 +414,11 @@ impl Screen {
     pub fn visible_lines(&self) -> Vec<Line> {
         let line_idx = self.lines.len() - self.physical_rows;
         let mut lines = Vec::new();
-        for line in self.lines.iter().skip(line_idx) {
+        for idx in line_idx..self.lines.len() {
             if lines.len() >= self.physical_rows {
                 break;
             }
-            lines.push(line.clone());
+            lines.push(self.line(idx).into_owned());
         }
         lines
     }
@@ -94,24 +426,40 @@ impl Screen {
     /// Returns a copy of the lines in the screen (including scrollback)
     #[cfg(test)]
     pub fn all_lines(&self) -> Vec<Line> {
-        self.lines.iter().map(|l| l.clone()).collect()
+        (0..self.lines.len())
+            .map(|idx| self.line(idx).into_owned())
+            .collect()
     }
 
-    pub fn insert_cell(&mut self, x: usize, y: VisibleRowIndex) {
+    /// Insert a blank cell at `x`, shifting the cells from there up
+    /// to `right_margin` (exclusive) to the right.  The cell that is
+    /// shifted beyond the margin is lost.
+    pub fn insert_cell(&mut self, x: usize, y: VisibleRowIndex, right_margin: usize) {
         let phys_cols = self.physical_cols;
 
         let line_idx = self.phys_row(y);
         let line = self.line_mut(line_idx);
         line.insert_cell(x, Cell::default());
+        if right_margin < phys_cols && right_margin < line.cells().len() {
+            line.erase_cell(right_margin);
+        }
         if line.cells().len() > phys_cols {
             line.resize(phys_cols);
         }
     }
 
-    pub fn erase_cell(&mut self, x: usize, y: VisibleRowIndex) {
+    /// Remove the cell at `x`, shifting the cells after it up to
+    /// `right_margin` (exclusive) to the left, and leaving a blank
+    /// cell just inside the margin.
+    pub fn erase_cell(&mut self, x: usize, y: VisibleRowIndex, right_margin: usize) {
         let line_idx = self.phys_row(y);
         let line = self.line_mut(line_idx);
+        let len = line.cells().len();
         line.erase_cell(x);
+        if right_margin < len {
+            line.insert_cell(right_margin - 1, Cell::default());
+            line.resize(len);
+        }
     }
 
     /// Set a cell.  the x and y coordinates are relative to the visible screeen
@@ -221,13 +569,21 @@ impl Screen {
         let to_move = lines_removed.min(num_rows);
         let (to_remove, to_add) = {
             for _ in 0..to_move {
-                let mut line = self.lines.remove(remove_idx).unwrap();
-                // Make the line like a new one of the appropriate width
-                line.resize_and_clear(self.physical_cols);
+                let line = match self.lines.remove(remove_idx).unwrap() {
+                    StoredLine::Live(mut line) => {
+                        // Make the line like a new one of the appropriate width
+                        line.resize_and_clear(self.physical_cols);
+                        line
+                    }
+                    StoredLine::Compressed(_) => Line::with_width(self.physical_cols),
+                };
+                self.lines_removed(remove_idx, 1);
                 if scroll_region.end as usize == self.physical_rows {
-                    self.lines.push_back(line);
+                    self.lines.push_back(StoredLine::Live(line));
                 } else {
-                    self.lines.insert(phys_scroll.end - 1, line);
+                    self.lines
+                        .insert(phys_scroll.end - 1, StoredLine::Live(line));
+                    self.lines_inserted(phys_scroll.end - 1, 1);
                 }
             }
             // We may still have some lines to add at the bottom, so
@@ -239,17 +595,30 @@ impl Screen {
         for _ in 0..to_remove {
             self.lines.remove(remove_idx);
         }
+        self.lines_removed(remove_idx, to_remove);
 
         if scroll_region.end as usize == self.physical_rows {
             // It's cheaper to push() than it is insert() at the end
             for _ in 0..to_add {
-                self.lines.push_back(Line::with_width(self.physical_cols));
+                self.lines
+                    .push_back(StoredLine::Live(Line::with_width(self.physical_cols)));
             }
         } else {
             for _ in 0..to_add {
-                self.lines
-                    .insert(phys_scroll.end, Line::with_width(self.physical_cols));
+                self.lines.insert(
+                    phys_scroll.end,
+                    StoredLine::Live(Line::with_width(self.physical_cols)),
+                );
             }
+            self.lines_inserted(phys_scroll.end, to_add);
+        }
+
+        if scroll_region.start == 0 {
+            // Lines were removed from the front of the scrollback
+            self.stable_row_offset += lines_removed;
+            // Compress the lines that have now scrolled far enough back
+            let threshold = self.compression_threshold();
+            self.compress_range(threshold.saturating_sub(num_rows)..threshold);
         }
     }
 
@@ -278,10 +647,82 @@ impl Screen {
         for _ in 0..num_rows {
             self.lines.remove(middle);
         }
+        self.lines_removed(middle, num_rows);
 
         for _ in 0..num_rows {
-            self.lines
-                .insert(phys_scroll.start, Line::with_width(self.physical_cols));
+            self.lines.insert(
+                phys_scroll.start,
+                StoredLine::Live(Line::with_width(self.physical_cols)),
+            );
+        }
+        self.lines_inserted(phys_scroll.start, num_rows);
+    }
+
+    /// Copy the cells in `cols` of the visible row `src` over those
+    /// of the visible row `dest`, or blank them if `src` is None
+    fn copy_columns(
+        &mut self,
+        src: Option<VisibleRowIndex>,
+        dest: VisibleRowIndex,
+        cols: &Range<usize>,
+    ) {
+        let cells: Vec<Cell> = match src {
+            Some(src) => {
+                let line = self.line(self.phys_row(src));
+                cols.clone()
+                    .map(|x| line.cells().get(x).cloned().unwrap_or_default())
+                    .collect()
+            }
+            None => cols.clone().map(|_| Cell::default()).collect(),
+        };
+        let line_idx = self.phys_row(dest);
+        let line = self.line_mut(line_idx);
+        for (x, cell) in cols.clone().zip(cells) {
+            line.set_cell(x, cell);
+        }
+    }
+
+    /// Scroll the rectangle formed by `scroll_region` and the
+    /// columns `margins` up by num_rows.  This is used instead of
+    /// `scroll_up` when left and right margins are in effect; the
+    /// cells outside of the margins are not moved, and nothing is
+    /// added to the scrollback.
+    pub fn scroll_up_within_margins(
+        &mut self,
+        scroll_region: &Range<VisibleRowIndex>,
+        margins: &Range<usize>,
+        num_rows: usize,
+    ) {
+        let num_rows = (num_rows as VisibleRowIndex).min(scroll_region.end - scroll_region.start);
+        for y in scroll_region.clone() {
+            let src = y + num_rows;
+            let src = if src < scroll_region.end {
+                Some(src)
+            } else {
+                None
+            };
+            self.copy_columns(src, y, margins);
+        }
+    }
+
+    /// Scroll the rectangle formed by `scroll_region` and the
+    /// columns `margins` down by num_rows.  The counterpart of
+    /// `scroll_up_within_margins`.
+    pub fn scroll_down_within_margins(
+        &mut self,
+        scroll_region: &Range<VisibleRowIndex>,
+        margins: &Range<usize>,
+        num_rows: usize,
+    ) {
+        let num_rows = (num_rows as VisibleRowIndex).min(scroll_region.end - scroll_region.start);
+        for y in scroll_region.clone().rev() {
+            let src = y - num_rows;
+            let src = if src >= scroll_region.start {
+                Some(src)
+            } else {
+                None
+            };
+            self.copy_columns(src, y, margins);
         }
     }
 }
@@ -0,0 +1,80 @@
+//! Searching the screen and its scrollback for text.
+//! This is independent of any UI so that the gui, the mux protocol
+//! and the cli can all share it.
+use super::*;
+use regex::Regex;
+
+/// Describes what to search for
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Pattern {
+    /// Match this text exactly
+    CaseSensitiveString(String),
+    /// Match this text, ignoring case
+    CaseInsensitiveString(String),
+    /// Match this regular expression
+    Regex(String),
+}
+
+impl Pattern {
+    fn compile(&self) -> Result<Regex, Error> {
+        let re = match self {
+            Pattern::CaseSensitiveString(s) => regex::escape(s),
+            Pattern::CaseInsensitiveString(s) => format!("(?i){}", regex::escape(s)),
+            Pattern::Regex(re) => re.clone(),
+        };
+        Regex::new(&re).map_err(|e| format_err!("invalid search pattern {:?}: {}", self, e))
+    }
+}
+
+/// A match returned by `Screen::search`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchResult {
+    /// The line on which the match was found
+    pub row: StableRowIndex,
+    /// The cells that hold the matching text
+    pub columns: Range<usize>,
+}
+
+/// Find the matches of `re` in `line`, returning the cell ranges
+fn search_line(line: &Line, re: &Regex) -> Vec<Range<usize>> {
+    // Record the cell index and width of the cell that starts at
+    // each byte offset of the text, so that byte ranges can be
+    // mapped back to cells
+    let mut text = String::new();
+    let mut starts = vec![];
+    for (idx, cell) in line.visible_cells() {
+        starts.push((text.len(), idx, cell.width().max(1)));
+        text.push_str(cell.str());
+    }
+
+    let cell_at = |byte: usize| match starts.binary_search_by_key(&byte, |&(start, ..)| start) {
+        Ok(i) => starts[i],
+        Err(i) => starts[i - 1],
+    };
+
+    re.find_iter(&text)
+        .filter(|m| m.start() != m.end())
+        .map(|m| {
+            let (_, first, _) = cell_at(m.start());
+            let (_, last, width) = cell_at(m.end() - 1);
+            first..last + width
+        })
+        .collect()
+}
+
+impl Screen {
+    /// Search the lines of the screen, including the scrollback, for
+    /// `pattern`.  Matches are returned from the top of the scrollback
+    /// downwards.  A match cannot span multiple lines.
+    pub fn search(&self, pattern: &Pattern) -> Result<Vec<SearchResult>, Error> {
+        let re = pattern.compile()?;
+        let mut results = vec![];
+        for idx in 0..self.len() {
+            let row = self.phys_to_stable_row_index(idx);
+            for columns in search_line(&self.line(idx), &re) {
+                results.push(SearchResult { row, columns });
+            }
+        }
+        Ok(results)
+    }
+}
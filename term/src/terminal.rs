@@ -40,6 +40,18 @@ pub trait TerminalHost {
 
     /// Reset font size
     fn reset_font_size(&mut self) {}
+
+    /// Called when the bell control code is received
+    fn ring_bell(&mut self) {}
+
+    /// Called when the application sends a file to be saved, rather
+    /// than displayed, using the iTerm2 file transfer protocol.
+    /// `name` is the name suggested by the application, if any.
+    fn save_file(&mut self, _name: Option<&str>, _data: &[u8]) {}
+
+    /// Called after output has changed what is shown on the screen,
+    /// so that it can be repainted without having to poll for changes
+    fn screen_changed(&mut self) {}
 }
 
 pub struct Terminal {
@@ -88,5 +100,14 @@ impl Terminal {
         let mut performer = Performer::new(&mut self.state, host);
 
         self.parser.parse(bytes, |action| performer.perform(action));
+        drop(performer);
+
+        if !bytes.is_empty() {
+            self.state.output_received();
+        }
+
+        if self.state.has_dirty_lines() {
+            host.screen_changed();
+        }
     }
 }
@@ -2,18 +2,258 @@
 // and inclusive range
 #![cfg_attr(feature = "cargo-clippy", allow(clippy::range_plus_one))]
 use super::*;
+use crate::highlight::Highlights;
 use image::{self, GenericImage};
 use ordered_float::NotNaN;
+use std::collections::VecDeque;
 use std::fmt::Write;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use termwiz::color::{ColorAttribute, ColorSpec};
 use termwiz::escape::csi::{
-    Cursor, DecPrivateMode, DecPrivateModeCode, Device, Edit, EraseInDisplay, EraseInLine, Mode,
-    Sgr, TerminalMode, TerminalModeCode, Window,
+    ActiveStatusDisplay, Cursor, CursorStyle, DecPrivateMode, DecPrivateModeCode, Device, Edit,
+    EraseInDisplay, EraseInLine, Mode, Sgr, StatusDisplayType, TerminalMode, TerminalModeCode,
+    Window, XtermKeyModifierResource,
 };
 use termwiz::escape::osc::{ITermFileData, ITermProprietary};
-use termwiz::escape::{Action, ControlCode, Esc, EscCode, OneBased, OperatingSystemCommand, CSI};
-use termwiz::hyperlink::Rule as HyperlinkRule;
+use termwiz::escape::{
+    Action, ControlCode, DeviceControlMode, Esc, EscCode, OneBased, OperatingSystemCommand, CSI,
+};
+use termwiz::hyperlink::{HyperlinkInterner, Rule as HyperlinkRule};
 use termwiz::image::{ImageCell, ImageData, TextureCoordinate};
+use unicode_normalization::UnicodeNormalization;
+
+/// A hyperlink that is displayed in the viewport
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VisibleHyperlink {
+    /// The row of the viewport that displays the link
+    pub row: usize,
+    /// The cells that display the link
+    pub columns: Range<usize>,
+    /// The text of those cells
+    pub text: String,
+    pub link: Arc<Hyperlink>,
+}
+
+/// Something that is displayed at a position on the screen, which
+/// scripts can ask about in order to act upon it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SemanticObject {
+    /// A hyperlink, either explicit or created by the hyperlink rules
+    Hyperlink { uri: String, text: String },
+    /// A word, as selected by double clicking
+    Word(String),
+}
+
+/// Returns true if the cell text `s` is part of a word for the
+/// purposes of double click selection
+fn is_word_char(s: &str) -> bool {
+    // TODO: add configuration for this
+    if s.len() > 1 {
+        true
+    } else if s.len() == 1 {
+        match s.chars().nth(0).unwrap() {
+            ' ' | '\t' | '\n' | '{' | '[' | '}' | ']' | '(' | ')' | '"' | '\'' => false,
+            _ => true,
+        }
+    } else {
+        false
+    }
+}
+
+/// How many of the most recently ignored escape sequences are
+/// retained for diagnostic purposes
+const MAX_RECENT_UNHANDLED: usize = 32;
+
+/// How long the display of a synchronized update is deferred before
+/// the screen is shown regardless, in case the application never
+/// finishes the update
+const SYNCHRONIZED_OUTPUT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The longest payload of a DCS request that will be accumulated;
+/// the rest of a longer payload is ignored
+const MAX_DEVICE_CONTROL_LEN: usize = 1024;
+
+/// A DCS request whose payload is accumulated until the sequence
+/// is terminated
+#[derive(Debug)]
+enum DeviceControlRequest {
+    /// DECRQSS - request the value of a setting
+    RequestSetting(Vec<u8>),
+    /// XTGETTCAP - request the values of terminfo capabilities
+    RequestTermcap(Vec<u8>),
+}
+
+impl DeviceControlRequest {
+    /// Recognize the request that is introduced by `enter`.
+    /// The parser doesn't report the final character of the DCS, so
+    /// requests are distinguished by their intermediates alone;
+    /// `DCS $ q` and `DCS + q` have no parameters, unlike the other
+    /// sequences that share those intermediates.
+    fn new(enter: &DeviceControlMode) -> Option<Self> {
+        match enter {
+            DeviceControlMode::Enter {
+                params,
+                intermediates,
+                ignored_extra_intermediates: false,
+            } if params.is_empty() => match intermediates.as_slice() {
+                b"$" => Some(DeviceControlRequest::RequestSetting(vec![])),
+                b"+" => Some(DeviceControlRequest::RequestTermcap(vec![])),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn push(&mut self, data: u8) {
+        let payload = match self {
+            DeviceControlRequest::RequestSetting(payload)
+            | DeviceControlRequest::RequestTermcap(payload) => payload,
+        };
+        if payload.len() < MAX_DEVICE_CONTROL_LEN {
+            payload.push(data);
+        }
+    }
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+/// The longest string that is reflected back to the application in
+/// the response to a query
+const MAX_REFLECTED_LEN: usize = 256;
+
+/// Makes `s` safe to include in a response to the application.
+/// Responses are read by the application as though they were typed,
+/// so control characters are removed to prevent a string that was
+/// chosen by whoever wrote to the terminal from ending the response
+/// early and entering commands of its own.
+fn sanitize_reflected(s: &str) -> String {
+    s.chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_REFLECTED_LEN)
+        .collect()
+}
+
+fn hex_decode(s: &[u8]) -> Option<String> {
+    if s.len() % 2 != 0 {
+        return None;
+    }
+    let bytes = s
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    String::from_utf8(bytes).ok()
+}
+
+/// Returns the text of the columns `cols` of `line`.  Concealed cells
+/// are returned as spaces unless `include_concealed` is true.
+fn columns_text(line: &Line, cols: Range<usize>, include_concealed: bool) -> String {
+    let mut s = String::new();
+    for (_, cell) in line
+        .visible_cells()
+        .skip_while(|(n, _)| *n < cols.start)
+        .take_while(|(n, _)| *n < cols.end)
+    {
+        if cell.attrs().invisible() && !include_concealed {
+            for _ in 0..cell.width() {
+                s.push(' ');
+            }
+        } else {
+            s.push_str(cell.str());
+        }
+    }
+    s
+}
+
+/// Returns the SGR parameters that select `attr`, as reported
+/// in response to DECRQSS
+fn sgr_report(attr: &CellAttributes) -> String {
+    fn color(color: ColorAttribute) -> Option<ColorSpec> {
+        match color {
+            ColorAttribute::TrueColorWithPaletteFallback(rgb, _)
+            | ColorAttribute::TrueColorWithDefaultFallback(rgb) => Some(ColorSpec::TrueColor(rgb)),
+            ColorAttribute::PaletteIndex(idx) => Some(ColorSpec::PaletteIndex(idx)),
+            ColorAttribute::Default => None,
+        }
+    }
+
+    let mut sgr = vec![Sgr::Reset];
+    if attr.intensity() != Intensity::Normal {
+        sgr.push(Sgr::Intensity(attr.intensity()));
+    }
+    if attr.underline() != Underline::None {
+        sgr.push(Sgr::Underline(attr.underline()));
+    }
+    if attr.blink() != Blink::None {
+        sgr.push(Sgr::Blink(attr.blink()));
+    }
+    if attr.italic() {
+        sgr.push(Sgr::Italic(true));
+    }
+    if attr.reverse() {
+        sgr.push(Sgr::Inverse(true));
+    }
+    if attr.invisible() {
+        sgr.push(Sgr::Invisible(true));
+    }
+    if attr.strikethrough() {
+        sgr.push(Sgr::StrikeThrough(true));
+    }
+    if attr.overline() {
+        sgr.push(Sgr::Overline(true));
+    }
+    if let Some(fg) = color(attr.foreground) {
+        sgr.push(Sgr::Foreground(fg));
+    }
+    if let Some(bg) = color(attr.background) {
+        sgr.push(Sgr::Background(bg));
+    }
+
+    // Each Sgr is displayed with its own trailing `m`
+    sgr.iter()
+        .map(|sgr| sgr.to_string().trim_end_matches('m').to_string())
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+/// Describes an escape sequence or control code that the terminal
+/// ignored, so that compatibility problems can be reported precisely
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnhandledSequence {
+    /// A description of the sequence as it was parsed
+    pub description: String,
+    /// The sequence re-encoded from its parsed form.  This is empty
+    /// if the sequence cannot be re-encoded.
+    pub bytes: Vec<u8>,
+}
+
+impl UnhandledSequence {
+    /// Format the bytes as a hex dump, with up to 16 bytes per line
+    /// followed by their printable representation
+    pub fn hex_dump(&self) -> Vec<String> {
+        self.bytes
+            .chunks(16)
+            .map(|chunk| {
+                let mut line = String::new();
+                for b in chunk {
+                    write!(&mut line, "{:02x} ", b).ok();
+                }
+                for _ in chunk.len()..16 {
+                    line.push_str("   ");
+                }
+                line.push('|');
+                for &b in chunk {
+                    let printable = b >= 0x20 && b < 0x7f;
+                    line.push(if printable { b as char } else { '.' });
+                }
+                line.push('|');
+                line
+            })
+            .collect()
+    }
+}
 
 struct TabStop {
     tabs: Vec<bool>,
@@ -72,13 +312,22 @@ struct ScreenOrAlt {
     alt_screen_is_active: bool,
     saved_cursor: Option<SavedCursor>,
     alt_saved_cursor: Option<SavedCursor>,
+    /// The host writable status line, which takes the bottom row of
+    /// the display when it is enabled by DECSSDT
+    status_line: Screen,
+    status_line_is_enabled: bool,
+    /// Tells us whether output is directed to the status line by
+    /// DECSASD rather than to the primary or alternate screen
+    status_line_is_active: bool,
 }
 
 impl Deref for ScreenOrAlt {
     type Target = Screen;
 
     fn deref(&self) -> &Screen {
-        if self.alt_screen_is_active {
+        if self.status_line_is_active {
+            &self.status_line
+        } else if self.alt_screen_is_active {
             &self.alt_screen
         } else {
             &self.screen
@@ -88,7 +337,9 @@ impl Deref for ScreenOrAlt {
 
 impl DerefMut for ScreenOrAlt {
     fn deref_mut(&mut self) -> &mut Screen {
-        if self.alt_screen_is_active {
+        if self.status_line_is_active {
+            &mut self.status_line
+        } else if self.alt_screen_is_active {
             &mut self.alt_screen
         } else {
             &mut self.screen
@@ -100,6 +351,7 @@ impl ScreenOrAlt {
     pub fn new(physical_rows: usize, physical_cols: usize, scrollback_size: usize) -> Self {
         let screen = Screen::new(physical_rows, physical_cols, scrollback_size);
         let alt_screen = Screen::new(physical_rows, physical_cols, 0);
+        let status_line = Screen::new(1, physical_cols, 0);
 
         Self {
             screen,
@@ -107,12 +359,101 @@ impl ScreenOrAlt {
             alt_screen_is_active: false,
             saved_cursor: None,
             alt_saved_cursor: None,
+            status_line,
+            status_line_is_enabled: false,
+            status_line_is_active: false,
         }
     }
 
-    pub fn resize(&mut self, physical_rows: usize, physical_cols: usize) {
+    /// Resize both screens.  The primary screen is rewrapped to the new
+    /// width; the alternate screen is expected to be redrawn by the
+    /// application that is using it.  `cursor` is the position of the
+    /// cursor on the active screen and its adjusted position is returned.
+    pub fn resize(
+        &mut self,
+        physical_rows: usize,
+        physical_cols: usize,
+        cursor: CursorPosition,
+    ) -> CursorPosition {
+        let mut cursor = cursor;
+        if physical_cols != self.screen.physical_cols {
+            if self.alt_screen_is_active {
+                // The cursor position on the primary screen is the one
+                // that was saved when the alternate screen was entered
+                if let Some(saved) = self.saved_cursor.as_mut() {
+                    saved.position = self.screen.rewrap(physical_cols, saved.position);
+                } else {
+                    self.screen.rewrap(physical_cols, CursorPosition::default());
+                }
+            } else {
+                cursor = self.screen.rewrap(physical_cols, cursor);
+            }
+        }
+        let physical_rows = physical_rows.saturating_sub(self.status_line_rows()).max(1);
+        self.screen.resize(physical_rows, physical_cols);
+        self.alt_screen.resize(physical_rows, physical_cols);
+        self.status_line.resize(1, physical_cols);
+        cursor
+    }
+
+    /// Returns the number of rows of the display that are taken by
+    /// the status line
+    pub fn status_line_rows(&self) -> usize {
+        if self.status_line_is_enabled {
+            1
+        } else {
+            0
+        }
+    }
+
+    /// Enable or remove the status line.  Its row is taken from, or
+    /// given back to, the bottom of the screens, and it starts out
+    /// blank.  Output must be directed to the main display first.
+    pub fn enable_status_line(&mut self, enable: bool) {
+        if enable == self.status_line_is_enabled {
+            return;
+        }
+        let physical_rows = self.screen.physical_rows + self.status_line_rows();
+        let physical_cols = self.screen.physical_cols;
+        self.status_line_is_enabled = enable;
+        let physical_rows = physical_rows - self.status_line_rows();
         self.screen.resize(physical_rows, physical_cols);
         self.alt_screen.resize(physical_rows, physical_cols);
+        self.status_line = Screen::new(1, physical_cols, 0);
+    }
+
+    pub fn is_status_line_enabled(&self) -> bool {
+        self.status_line_is_enabled
+    }
+
+    pub fn activate_status_line(&mut self) {
+        self.status_line_is_active = self.status_line_is_enabled;
+    }
+
+    pub fn activate_main_display(&mut self) {
+        self.status_line_is_active = false;
+    }
+
+    pub fn is_status_line_active(&self) -> bool {
+        self.status_line_is_active
+    }
+
+    /// Returns the status line, if it is enabled
+    pub fn status_line(&self) -> Option<&Line> {
+        if self.status_line_is_enabled {
+            self.status_line.live_line(self.status_line.phys_row(0))
+        } else {
+            None
+        }
+    }
+
+    pub fn status_line_mut(&mut self) -> Option<&mut Line> {
+        if self.status_line_is_enabled {
+            let idx = self.status_line.phys_row(0);
+            self.status_line.live_line_mut(idx)
+        } else {
+            None
+        }
     }
 
     pub fn activate_alt_screen(&mut self) {
@@ -154,6 +495,15 @@ pub struct TerminalState {
 
     /// The scroll region
     scroll_region: Range<VisibleRowIndex>,
+    /// The columns between the left and right margins, as set by
+    /// DECSLRM.  Together with `scroll_region` this forms the
+    /// rectangle that is affected by scrolling.
+    left_and_right_margins: Range<usize>,
+    /// DECLRMM: whether DECSLRM may be used to set the margins
+    left_and_right_margin_mode: bool,
+    /// While output is directed to the status line, holds the cursor
+    /// position and scroll region of the main display
+    main_display_state: Option<(CursorPosition, Range<VisibleRowIndex>)>,
 
     /// When set, modifies the sequence of bytes sent for keys
     /// designated as cursor keys.  This includes various navigation
@@ -164,11 +514,37 @@ pub struct TerminalState {
     /// in the numeric keypad portion of the keyboard.
     application_keypad: bool,
 
+    /// The level of xterm's modifyOtherKeys that was selected by the
+    /// application with XTMODKEYS.  At level 1 modified keys that
+    /// would otherwise be indistinguishable are reported using
+    /// `CSI 27 ; mod ; code ~`; at level 2 all modified keys are.
+    modify_other_keys: i64,
+    /// When set, modified keys are reported using `CSI code ; mod u`
+    /// regardless of `modify_other_keys`
+    csi_u_key_encoding: bool,
+
     /// When set, pasting the clipboard should bracket the data with
     /// designated marker characters.
     bracketed_paste: bool,
 
+    /// When set, the window gaining and losing focus is reported
+    focus_tracking: bool,
+
+    /// When set, keys are reported as they are pressed and released
+    /// in the form of win32 key events, which ConPTY requests so that
+    /// console applications receive the events that they expect
+    win32_input_mode: bool,
+
+    /// The time at which the application began a synchronized update
+    /// with mode 2026, if it hasn't yet finished it.  The equivalent
+    /// DCS sequences aren't supported as the parser doesn't provide
+    /// the final byte of a DCS.
+    synchronized_output: Option<Instant>,
+
     sgr_mouse: bool,
+    /// When set, mouse reports use the SGR encoding with pixel
+    /// rather than cell coordinates
+    sgr_pixels_mouse: bool,
     button_event_mouse: bool,
     current_mouse_button: MouseButton,
     mouse_position: CursorPosition,
@@ -197,13 +573,62 @@ pub struct TerminalState {
     selection_start: Option<SelectionCoordinate>,
     /// Holds the not-normalized selection range.
     selection_range: Option<SelectionRange>,
+    /// Sets of highlighted cells, such as search matches, which are
+    /// drawn beneath the selection
+    highlights: Highlights,
 
     tabs: TabStop,
 
     hyperlink_rules: Vec<HyperlinkRule>,
+    /// Shares the storage of identical explicit hyperlinks between the
+    /// cells of the screen and scrollback
+    hyperlinks: HyperlinkInterner,
 
     /// The terminal title string
     title: String,
+    /// The working directory reported by the program, as a `file://`
+    /// url that includes the host name
+    current_dir: Option<String>,
+
+    /// The escape sequences that were most recently ignored,
+    /// oldest first
+    recent_unhandled: VecDeque<UnhandledSequence>,
+    /// The total number of escape sequences that were ignored
+    unhandled_count: usize,
+    /// Whether to log ignored escape sequences to stderr
+    log_unhandled: bool,
+    /// Whether sending input resets the viewport to the bottom
+    scroll_to_bottom_on_input: bool,
+    /// Whether receiving output resets the viewport to the bottom
+    scroll_to_bottom_on_output: bool,
+    /// How many arrow key presses are sent for each tick of the mouse
+    /// wheel while the alternate screen is active and the mouse is not
+    /// being reported
+    alt_screen_wheel_scroll_lines: usize,
+    /// Whether SGR 21 turns off bold rather than selecting a double
+    /// underline
+    sgr_21_is_bold_off: bool,
+    /// Whether pasted and typed text is converted to Unicode NFC before
+    /// it is sent to the application
+    normalize_input: bool,
+    /// Whether printed text is converted to Unicode NFC before it is
+    /// placed in the cells
+    normalize_output: bool,
+    /// Whether text with the conceal attribute is included when the
+    /// selection is copied
+    copy_concealed_text: bool,
+    /// Whether text with the conceal attribute is currently shown;
+    /// revealed text is also included when the selection is copied
+    reveal_concealed: bool,
+    /// The responses to device attributes and XTVERSION requests
+    device_identity: DeviceIdentity,
+    /// The DCS request that is being received, if it is one that
+    /// we respond to
+    device_control: Option<DeviceControlRequest>,
+    /// The cursor style most recently selected by DECSCUSR.  It is
+    /// reported by DECRQSS but doesn't yet change how the cursor
+    /// is drawn.
+    cursor_style: CursorStyle,
 }
 
 /// Like Write::write_all except that we keep looping
@@ -241,12 +666,21 @@ impl TerminalState {
             pen: CellAttributes::default(),
             cursor: CursorPosition::default(),
             scroll_region: 0..physical_rows as VisibleRowIndex,
+            left_and_right_margins: 0..physical_cols,
+            left_and_right_margin_mode: false,
+            main_display_state: None,
             wrap_next: false,
             insert: false,
             application_cursor_keys: false,
             application_keypad: false,
+            modify_other_keys: 0,
+            csi_u_key_encoding: false,
             bracketed_paste: false,
+            focus_tracking: false,
+            win32_input_mode: false,
+            synchronized_output: None,
             sgr_mouse: false,
+            sgr_pixels_mouse: false,
             button_event_mouse: false,
             cursor_visible: true,
             dec_line_drawing_mode: false,
@@ -257,16 +691,223 @@ impl TerminalState {
             viewport_offset: 0,
             selection_range: None,
             selection_start: None,
+            highlights: Highlights::default(),
             tabs: TabStop::new(physical_cols, 8),
             hyperlink_rules,
+            hyperlinks: HyperlinkInterner::new(),
             title: "wezterm".to_string(),
+            current_dir: None,
+            recent_unhandled: VecDeque::new(),
+            unhandled_count: 0,
+            log_unhandled: true,
+            scroll_to_bottom_on_input: true,
+            scroll_to_bottom_on_output: false,
+            alt_screen_wheel_scroll_lines: 1,
+            sgr_21_is_bold_off: false,
+            normalize_input: false,
+            normalize_output: false,
+            copy_concealed_text: false,
+            reveal_concealed: false,
+            device_identity: DeviceIdentity::default(),
+            device_control: None,
+            cursor_style: CursorStyle::default(),
+        }
+    }
+
+    /// Record that an escape sequence or control code was ignored.
+    /// `encoded` is the sequence as it would be sent to the terminal.
+    fn unhandled(&mut self, description: String, encoded: &str) {
+        if self.log_unhandled {
+            eprintln!("unhandled {}", description);
+        }
+        self.unhandled_count += 1;
+        if self.recent_unhandled.len() == MAX_RECENT_UNHANDLED {
+            self.recent_unhandled.pop_front();
+        }
+        self.recent_unhandled.push_back(UnhandledSequence {
+            description,
+            bytes: encoded.as_bytes().to_vec(),
+        });
+    }
+
+    /// Controls whether ignored escape sequences are logged to stderr.
+    /// They are counted and retained regardless of this setting.
+    pub fn set_log_unhandled(&mut self, log: bool) {
+        self.log_unhandled = log;
+    }
+
+    /// Controls whether typing or pasting while scrolled back
+    /// returns the viewport to the bottom.  Enabled by default.
+    pub fn set_scroll_to_bottom_on_input(&mut self, enable: bool) {
+        self.scroll_to_bottom_on_input = enable;
+    }
+
+    /// Controls whether output from the program while scrolled back
+    /// returns the viewport to the bottom.  Disabled by default.
+    pub fn set_scroll_to_bottom_on_output(&mut self, enable: bool) {
+        self.scroll_to_bottom_on_output = enable;
+    }
+
+    /// Controls how many Up or Down arrow key presses are sent for each
+    /// tick of the mouse wheel while the alternate screen is active and
+    /// the application hasn't enabled mouse reporting.  This allows
+    /// programs such as `less` to be scrolled with the wheel.
+    /// The default is 1; 0 disables the translation.
+    pub fn set_alt_screen_wheel_scroll_lines(&mut self, lines: usize) {
+        self.alt_screen_wheel_scroll_lines = lines;
+    }
+
+    /// ECMA-48 defines SGR 21 as double underline, which is what xterm
+    /// and most modern terminals do, but some older terminals and the
+    /// Linux console treat it as turning off bold.  Enabling this
+    /// selects the latter, for programs that expect it.  Disabled by
+    /// default.
+    pub fn set_sgr_21_is_bold_off(&mut self, enable: bool) {
+        self.sgr_21_is_bold_off = enable;
+    }
+
+    /// Controls whether pasted and typed text is converted to Unicode
+    /// Normalization Form C, so that characters that were composed from a base and
+    /// combining marks, as macOS produces, reach the application as the
+    /// precomposed characters that it most likely expects.  Disabled by
+    /// default.
+    pub fn set_normalize_input(&mut self, enable: bool) {
+        self.normalize_input = enable;
+    }
+
+    /// Controls whether printed text is converted to Unicode
+    /// Normalization Form C, so that decomposed sequences are shown,
+    /// searched and copied as their precomposed equivalents.
+    /// Disabled by default.
+    pub fn set_normalize_output(&mut self, enable: bool) {
+        self.normalize_output = enable;
+    }
+
+    /// Controls whether text that was concealed with SGR 8 is included
+    /// when copying the selection.  Disabled by default, so that the
+    /// text remains hidden unless it has been revealed.
+    pub fn set_copy_concealed_text(&mut self, enable: bool) {
+        self.copy_concealed_text = enable;
+    }
+
+    /// Show or hide the text that was concealed with SGR 8
+    pub fn set_reveal_concealed(&mut self, reveal: bool) {
+        if reveal != self.reveal_concealed {
+            self.reveal_concealed = reveal;
+            self.make_all_lines_dirty();
+        }
+    }
+
+    pub fn is_concealed_revealed(&self) -> bool {
+        self.reveal_concealed
+    }
+
+    /// Controls whether keys pressed with modifiers are always reported
+    /// using the `CSI u` encoding.  Disabled by default.
+    pub fn set_csi_u_key_encoding(&mut self, enable: bool) {
+        self.csi_u_key_encoding = enable;
+    }
+
+    /// Sets the responses that are given to programs that ask the
+    /// terminal to identify itself.  Characters that are not valid
+    /// in the responses are removed.
+    pub fn set_device_identity(&mut self, identity: DeviceIdentity) {
+        fn params(s: &str) -> String {
+            s.chars()
+                .filter(|&c| c.is_ascii_digit() || c == ';')
+                .take(MAX_REFLECTED_LEN)
+                .collect()
+        }
+        self.device_identity = DeviceIdentity {
+            primary_attributes: params(&identity.primary_attributes),
+            secondary_attributes: params(&identity.secondary_attributes),
+            name_and_version: sanitize_reflected(&identity.name_and_version),
+            term: sanitize_reflected(&identity.term),
+        };
+    }
+
+    /// Called after output has been parsed
+    pub(crate) fn output_received(&mut self) {
+        if self.scroll_to_bottom_on_output && self.viewport_offset != 0 {
+            self.set_scroll_viewport(0);
+        }
+    }
+
+    /// Called after input has been sent to the program
+    fn input_sent(&mut self) {
+        if self.scroll_to_bottom_on_input && self.viewport_offset != 0 {
+            self.set_scroll_viewport(0);
         }
     }
 
+    /// Returns the total number of escape sequences that were ignored
+    pub fn unhandled_count(&self) -> usize {
+        self.unhandled_count
+    }
+
+    /// Returns the escape sequences that were most recently ignored,
+    /// oldest first
+    pub fn recent_unhandled(&self) -> impl Iterator<Item = &UnhandledSequence> {
+        self.recent_unhandled.iter()
+    }
+
+    /// Returns the names and current values of the terminal modes,
+    /// for diagnostic purposes
+    pub fn get_modes(&self) -> Vec<(&'static str, String)> {
+        let flag = |b: bool| if b { "on" } else { "off" }.to_string();
+        vec![
+            ("alternate screen", flag(self.screen.is_alt_screen_active())),
+            ("insert", flag(self.insert)),
+            (
+                "application cursor keys",
+                flag(self.application_cursor_keys),
+            ),
+            ("application keypad", flag(self.application_keypad)),
+            ("bracketed paste", flag(self.bracketed_paste)),
+            ("focus tracking", flag(self.focus_tracking)),
+            ("win32 input mode", flag(self.win32_input_mode)),
+            (
+                "synchronized output",
+                flag(self.synchronized_output.is_some()),
+            ),
+            ("sgr mouse", flag(self.sgr_mouse)),
+            ("sgr pixels mouse", flag(self.sgr_pixels_mouse)),
+            ("button event mouse", flag(self.button_event_mouse)),
+            ("cursor visible", flag(self.cursor_visible)),
+            ("dec line drawing", flag(self.dec_line_drawing_mode)),
+            (
+                "scroll region",
+                format!("{}..{}", self.scroll_region.start, self.scroll_region.end),
+            ),
+            (
+                "left and right margin mode",
+                flag(self.left_and_right_margin_mode),
+            ),
+            (
+                "left and right margins",
+                format!(
+                    "{}..{}",
+                    self.left_and_right_margins.start, self.left_and_right_margins.end
+                ),
+            ),
+            (
+                "cursor position",
+                format!("{},{}", self.cursor.x, self.cursor.y),
+            ),
+        ]
+    }
+
     pub fn get_title(&self) -> &str {
         &self.title
     }
 
+    /// Returns the working directory most recently reported by the
+    /// program using OSC 7, or by the iTerm2 `CurrentDir` sequence,
+    /// as a `file://` url
+    pub fn get_current_dir(&self) -> Option<&str> {
+        self.current_dir.as_ref().map(String::as_str)
+    }
+
     pub fn screen(&self) -> &Screen {
         &self.screen
     }
@@ -286,23 +927,73 @@ impl TerminalState {
                 if !s.is_empty() {
                     s.push('\n');
                 }
-                s.push_str(screen.lines[idx].columns_as_str(cols).trim_end());
+                let line = screen.line(idx);
+                let include_concealed = self.copy_concealed_text || self.reveal_concealed;
+                s.push_str(columns_text(&line, cols, include_concealed).trim_end());
             }
         }
 
         s
     }
 
-    /// Dirty the lines in the current selection range
+    /// Dirty the cells in the current selection range
     fn dirty_selection_lines(&mut self) {
         if let Some(sel) = self.selection_range.as_ref().map(|r| r.normalize()) {
             let screen = self.screen_mut();
-            for y in screen.scrollback_or_visible_range(&sel.rows()) {
-                screen.line_mut(y).set_dirty();
+            for row in sel.rows() {
+                let y = screen.scrollback_or_visible_row(row);
+                // Compressed lines aren't being displayed
+                if let Some(line) = screen.live_line_mut(y) {
+                    line.damage_columns(sel.cols_for_row(row));
+                }
+            }
+        }
+    }
+
+    /// Dirty the cells of the lines that `set` highlights
+    fn dirty_highlight_lines(&mut self, set: &HighlightSet) {
+        let screen = self.screen_mut();
+        for range in &set.ranges {
+            if let Some(idx) = screen.stable_row_to_phys(range.row) {
+                if let Some(line) = screen.live_line_mut(idx) {
+                    line.damage_columns(range.columns.clone());
+                }
             }
         }
     }
 
+    /// Highlights the cells of `set`, replacing the set with the same
+    /// `name`.  The rows refer to the screen that is currently shown.
+    pub fn set_highlights(&mut self, name: &str, set: HighlightSet) {
+        self.dirty_highlight_lines(&set);
+        if let Some(prior) = self.highlights.set(name, set) {
+            self.dirty_highlight_lines(&prior);
+        }
+    }
+
+    /// Removes the set of highlights named `name`
+    pub fn clear_highlights(&mut self, name: &str) {
+        if let Some(prior) = self.highlights.remove(name) {
+            self.dirty_highlight_lines(&prior);
+        }
+    }
+
+    /// Returns the set of highlights named `name`
+    pub fn get_highlights(&self, name: &str) -> Option<&HighlightSet> {
+        self.highlights.get(name)
+    }
+
+    /// Returns the highlights on the line `line_idx` of the viewport,
+    /// with those that are drawn on top last
+    pub fn highlights_for_line(&self, line_idx: usize) -> Vec<LineHighlight> {
+        if line_idx >= self.screen().physical_rows {
+            // The status line
+            return vec![];
+        }
+        self.highlights
+            .for_row(self.stable_viewport_top() + line_idx)
+    }
+
     pub fn clear_selection(&mut self) {
         self.dirty_selection_lines();
         self.selection_range = None;
@@ -370,22 +1061,97 @@ impl TerminalState {
         let rules = &self.hyperlink_rules;
 
         let idx = self.screen.scrollback_or_visible_row(y);
-        match self.screen.lines.get_mut(idx) {
-            Some(ref mut line) => {
-                line.scan_and_create_hyperlinks(rules);
-                match line.cells().get(x) {
-                    Some(cell) => cell.attrs().hyperlink.as_ref().cloned(),
-                    None => None,
-                }
-            }
+        if idx >= self.screen.len() {
+            return None;
+        }
+        let line = self.screen.line_mut(idx);
+        line.scan_and_create_hyperlinks(rules);
+        match line.cells().get(x) {
+            Some(cell) => cell.attrs().hyperlink.as_ref().cloned(),
             None => None,
         }
     }
 
+    /// Returns the hyperlinks in the viewport, including those matched
+    /// by the hyperlink rules, from the top left to the bottom right.
+    /// Adjacent cells that share a hyperlink are reported together.
+    pub fn visible_hyperlinks(&mut self) -> Vec<VisibleHyperlink> {
+        let rules = &self.hyperlink_rules;
+        let mut links = vec![];
+        for (row, idx) in self.viewport_range().enumerate() {
+            let line = self.screen.line_mut(idx);
+            line.scan_and_create_hyperlinks(rules);
+
+            let mut current: Option<VisibleHyperlink> = None;
+            for (col, cell) in line.visible_cells() {
+                let width = cell.width().max(1);
+                let link = match cell.attrs().hyperlink.as_ref() {
+                    Some(link) => link,
+                    None => {
+                        links.extend(current.take());
+                        continue;
+                    }
+                };
+                if let Some(current) = current.as_mut() {
+                    if current.link == *link && current.columns.end == col {
+                        current.columns.end = col + width;
+                        current.text.push_str(cell.str());
+                        continue;
+                    }
+                }
+                links.extend(current.take());
+                current = Some(VisibleHyperlink {
+                    row,
+                    columns: col..col + width,
+                    text: cell.str().to_string(),
+                    link: Arc::clone(link),
+                });
+            }
+            links.extend(current);
+        }
+        links
+    }
+
+    /// Returns the hyperlink or word that is displayed in the cell at
+    /// `col` of the viewport row `row`, if any
+    pub fn semantic_object_at(
+        &mut self,
+        row: VisibleRowIndex,
+        col: usize,
+    ) -> Option<SemanticObject> {
+        if row < 0 || row as usize >= self.screen().physical_rows {
+            return None;
+        }
+        if let Some(link) = self
+            .visible_hyperlinks()
+            .into_iter()
+            .find(|link| link.row == row as usize && in_range(col, &link.columns))
+        {
+            return Some(SemanticObject::Hyperlink {
+                uri: link.link.uri().to_owned(),
+                text: link.text,
+            });
+        }
+
+        let idx = self.screen().scrollback_or_visible_row(
+            row as ScrollbackOrVisibleRowIndex
+                - self.viewport_offset as ScrollbackOrVisibleRowIndex,
+        );
+        let line = self.screen().line(idx);
+        let cell = line.cells().get(col)?;
+        if !is_word_char(cell.str()) {
+            return None;
+        }
+        let range = line.compute_double_click_range(col, is_word_char);
+        Some(SemanticObject::Word(
+            line.columns_as_str(range.start..range.end + 1),
+        ))
+    }
+
     /// Invalidate rows that have hyperlinks
     fn invalidate_hyperlinks(&mut self) {
         let screen = self.screen_mut();
-        for line in &mut screen.lines {
+        for line in screen.live_lines_mut() {
             if line.has_hyperlink() {
                 line.set_dirty();
             }
@@ -428,19 +1194,9 @@ impl TerminalState {
         let y = event.y as ScrollbackOrVisibleRowIndex
             - self.viewport_offset as ScrollbackOrVisibleRowIndex;
         let idx = self.screen().scrollback_or_visible_row(y);
-        let click_range = self.screen().lines[idx].compute_double_click_range(event.x, |s| {
-            // TODO: add configuration for this
-            if s.len() > 1 {
-                true
-            } else if s.len() == 1 {
-                match s.chars().nth(0).unwrap() {
-                    ' ' | '\t' | '\n' | '{' | '[' | '}' | ']' | '(' | ')' | '"' | '\'' => false,
-                    _ => true,
-                }
-            } else {
-                false
-            }
-        });
+        let line = self.screen().line(idx);
+        let click_range = line.compute_double_click_range(event.x, is_word_char);
+        drop(line);
 
         self.selection_start = Some(SelectionCoordinate {
             x: click_range.start,
@@ -565,6 +1321,22 @@ impl TerminalState {
         Ok(())
     }
 
+    /// Returns true if mouse events are to be reported to the application
+    fn reporting_mouse(&self) -> bool {
+        self.sgr_mouse || self.sgr_pixels_mouse
+    }
+
+    /// Returns the 1-based coordinates to report for a mouse event.
+    /// These are pixel coordinates in SGR-Pixels mode, otherwise
+    /// they are cell coordinates.
+    fn mouse_report_position(&self, event: &MouseEvent) -> (usize, i64) {
+        if self.sgr_pixels_mouse {
+            (event.x_pixel + 1, event.y_pixel as i64 + 1)
+        } else {
+            (event.x + 1, event.y + 1)
+        }
+    }
+
     fn mouse_wheel(&mut self, event: MouseEvent, writer: &mut std::io::Write) -> Result<(), Error> {
         let (report_button, scroll_delta, key) = if event.button == MouseButton::WheelUp {
             (64, -1, KeyCode::UpArrow)
@@ -572,14 +1344,17 @@ impl TerminalState {
             (65, 1, KeyCode::DownArrow)
         };
 
-        if self.sgr_mouse {
+        if self.reporting_mouse() {
+            let (x, y) = self.mouse_report_position(&event);
             write_all(
                 writer,
-                format!("\x1b[<{};{};{}M", report_button, event.x + 1, event.y + 1).as_bytes(),
+                format!("\x1b[<{};{};{}M", report_button, x, y).as_bytes(),
             )?;
-        } else if self.screen.is_alt_screen_active() {
+        } else if self.screen.is_alt_screen_active() && self.alt_screen_wheel_scroll_lines > 0 {
             // Send cursor keys instead (equivalent to xterm's alternateScroll mode)
-            self.key_down(key, KeyModifiers::default(), writer)?;
+            for _ in 0..self.alt_screen_wheel_scroll_lines {
+                self.key_down(key, KeyModifiers::default(), writer)?;
+            }
         } else {
             self.scroll_viewport(scroll_delta)
         }
@@ -598,10 +1373,11 @@ impl TerminalState {
             MouseButton::Right => Some(2),
             _ => None,
         } {
-            if self.sgr_mouse {
+            if self.reporting_mouse() {
+                let (x, y) = self.mouse_report_position(&event);
                 write_all(
                     host.writer(),
-                    format!("\x1b[<{};{};{}M", button, event.x + 1, event.y + 1).as_bytes(),
+                    format!("\x1b[<{};{};{}M", button, x, y).as_bytes(),
                 )?;
             } else if event.button == MouseButton::Middle {
                 let clip = host.get_clipboard()?;
@@ -619,8 +1395,9 @@ impl TerminalState {
     ) -> Result<(), Error> {
         if self.current_mouse_button != MouseButton::None {
             self.current_mouse_button = MouseButton::None;
-            if self.sgr_mouse {
-                write!(writer, "\x1b[<3;{};{}m", event.x + 1, event.y + 1)?;
+            if self.reporting_mouse() {
+                let (x, y) = self.mouse_report_position(&event);
+                write!(writer, "\x1b[<3;{};{}m", x, y)?;
             }
         }
 
@@ -634,8 +1411,9 @@ impl TerminalState {
             (MouseButton::Right, true) => Some(34),
             (..) => None,
         } {
-            if self.sgr_mouse {
-                write!(writer, "\x1b[<{};{};{}M", button, event.x + 1, event.y + 1)?;
+            if self.reporting_mouse() {
+                let (x, y) = self.mouse_report_position(&event);
+                write!(writer, "\x1b[<{};{};{}M", button, x, y)?;
             }
         }
         Ok(())
@@ -668,7 +1446,7 @@ impl TerminalState {
         }
 
         // First pass to figure out if we're messing with the selection
-        let send_event = self.sgr_mouse && !event.modifiers.contains(KeyModifiers::SHIFT);
+        let send_event = self.reporting_mouse() && !event.modifiers.contains(KeyModifiers::SHIFT);
 
         // Perform click counting
         if event.kind == MouseEventKind::Press {
@@ -748,15 +1526,52 @@ impl TerminalState {
     /// If bracketed paste mode is enabled, the paste is enclosed
     /// in the bracketing, otherwise it is fed to the pty as-is.
     pub fn send_paste(&mut self, text: &str, writer: &mut std::io::Write) -> Result<(), Error> {
+        let normalized;
+        let text = if self.normalize_input {
+            normalized = text.nfc().collect::<String>();
+            normalized.as_str()
+        } else {
+            text
+        };
         if self.bracketed_paste {
             let buf = format!("\x1b[200~{}\x1b[201~", text);
             write_all(writer, buf.as_bytes())?;
         } else {
             write_all(writer, text.as_bytes())?;
         }
+        if !text.is_empty() {
+            self.input_sent();
+        }
         Ok(())
     }
 
+    /// Sends text that was entered as a whole rather than key by key,
+    /// such as that committed by an input method or produced by a key
+    /// remapping.  Unlike a paste, it is never bracketed.
+    pub fn send_text(&mut self, text: &str, writer: &mut std::io::Write) -> Result<(), Error> {
+        if self.normalize_input {
+            let normalized: String = text.nfc().collect();
+            write_all(writer, normalized.as_bytes())?;
+        } else {
+            write_all(writer, text.as_bytes())?;
+        }
+        if !text.is_empty() {
+            self.input_sent();
+        }
+        Ok(())
+    }
+
+    /// Appends the typed character `c` to `buf`, normalized if enabled.
+    /// Some characters, such as U+212B ANGSTROM SIGN, have a different
+    /// canonical form even on their own.
+    fn push_typed_char(&self, buf: &mut String, c: char) {
+        if self.normalize_input {
+            buf.extend(std::iter::once(c).nfc());
+        } else {
+            buf.push(c);
+        }
+    }
+
     /// Processes a key_down event generated by the gui/render layer
     /// that is embedding the Terminal.  This method translates the
     /// keycode into a sequence of bytes to send to the slave end
@@ -778,6 +1593,22 @@ impl TerminalState {
         let shift = mods & SHIFT;
         let alt = mods & ALT;
 
+        // Shift with PageUp and PageDown scrolls the viewport below
+        let scrolls = shift == SHIFT && (key == PageUp || key == PageDown);
+        if self.win32_input_mode && !scrolls {
+            if let Some(encoded) = encode_win32_input(key, mods, true) {
+                write_all(writer, encoded.as_bytes())?;
+                self.input_sent();
+                return Ok(());
+            }
+        }
+
+        if let Some(encoded) = self.encode_modified_key(key, mods) {
+            write_all(writer, encoded.as_bytes())?;
+            self.input_sent();
+            return Ok(());
+        }
+
         let mut buf = String::new();
 
         // TODO: also respect self.application_keypad
@@ -805,11 +1636,11 @@ impl TerminalState {
             }
             (Char(c), _, ALT, ..) => {
                 buf.push(0x1b as char);
-                buf.push(c);
+                self.push_typed_char(&mut buf, c);
                 buf.as_str()
             }
             (Char(c), ..) => {
-                buf.push(c);
+                self.push_typed_char(&mut buf, c);
                 buf.as_str()
             }
 
@@ -929,37 +1760,175 @@ impl TerminalState {
         // eprintln!("sending {:?}", to_send);
         write_all(writer, to_send.as_bytes())?;
 
-        // Reset the viewport if we sent data to the parser
-        if !to_send.is_empty() && self.viewport_offset != 0 {
-            // TODO: some folks like to configure this behavior.
-            self.set_scroll_viewport(0);
+        if !to_send.is_empty() {
+            self.input_sent();
+        }
+
+        Ok(())
+    }
+
+    /// Returns true if the application is part way through a
+    /// synchronized update, in which case the screen should not be
+    /// painted until it has finished.  An update that has taken
+    /// too long is abandoned.
+    pub fn is_output_synchronized(&mut self) -> bool {
+        match self.synchronized_output {
+            Some(start) if start.elapsed() < SYNCHRONIZED_OUTPUT_TIMEOUT => true,
+            Some(_) => {
+                self.synchronized_output = None;
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Returns how long remains until a synchronized update that is in
+    /// progress is abandoned, or None if there isn't one
+    pub fn synchronized_output_remaining(&self) -> Option<Duration> {
+        self.synchronized_output.map(|start| {
+            let elapsed = start.elapsed();
+            if elapsed < SYNCHRONIZED_OUTPUT_TIMEOUT {
+                SYNCHRONIZED_OUTPUT_TIMEOUT - elapsed
+            } else {
+                Duration::from_secs(0)
+            }
+        })
+    }
+
+    /// Processes a key_up event generated by the gui/render layer.
+    /// Releasing a key is only reported to applications that have
+    /// enabled win32-input-mode.
+    pub fn key_up(
+        &mut self,
+        key: KeyCode,
+        mods: KeyModifiers,
+        writer: &mut std::io::Write,
+    ) -> Result<(), Error> {
+        if self.win32_input_mode {
+            if let Some(encoded) = encode_win32_input(key, mods, false) {
+                write_all(writer, encoded.as_bytes())?;
+            }
         }
+        Ok(())
+    }
 
+    /// Informs the terminal that the window containing it has gained
+    /// or lost the focus.  If the application has enabled focus
+    /// tracking, `CSI I` or `CSI O` is sent to it.
+    pub fn focus_changed(
+        &mut self,
+        focused: bool,
+        writer: &mut std::io::Write,
+    ) -> Result<(), Error> {
+        if self.focus_tracking {
+            write_all(writer, if focused { b"\x1b[I" } else { b"\x1b[O" })?;
+        }
         Ok(())
     }
 
+    /// Returns the modifyOtherKeys or `CSI u` encoding of `key` if the
+    /// current mode calls for the key to be reported that way, or None
+    /// if the usual encoding should be used
+    fn encode_modified_key(&self, key: KeyCode, mods: KeyModifiers) -> Option<String> {
+        let level = if self.csi_u_key_encoding {
+            2
+        } else {
+            self.modify_other_keys
+        };
+        if level <= 0 {
+            return None;
+        }
+
+        let code = match key {
+            KeyCode::Char(c) => c as u32,
+            KeyCode::Tab => 0x09,
+            KeyCode::Enter => 0x0d,
+            KeyCode::Escape => 0x1b,
+            KeyCode::Backspace => 0x7f,
+            _ => return None,
+        };
+        let ctrl = mods.contains(KeyModifiers::CTRL);
+        let shift = mods.contains(KeyModifiers::SHIFT);
+        let alt = mods.contains(KeyModifiers::ALT);
+        let printable = code >= 0x20 && code != 0x7f;
+
+        let report = if printable {
+            if level == 1 {
+                // Ctrl with a lowercase letter produces a distinct
+                // control code and Alt is sent as an ESC prefix; it
+                // is the other Ctrl combinations that are ambiguous
+                let lowercase = match key {
+                    KeyCode::Char(c) => c.is_ascii_lowercase(),
+                    _ => false,
+                };
+                ctrl && (shift || !lowercase)
+            } else {
+                // Shift alone is reflected in the character itself
+                ctrl || alt
+            }
+        } else {
+            ctrl || alt || shift
+        };
+        if !report {
+            return None;
+        }
+
+        let modifier =
+            1 + if shift { 1 } else { 0 } + if alt { 2 } else { 0 } + if ctrl { 4 } else { 0 };
+        Some(if self.csi_u_key_encoding {
+            format!("\x1b[{};{}u", code, modifier)
+        } else {
+            format!("\x1b[27;{};{}~", modifier, code)
+        })
+    }
+
     pub fn resize(&mut self, physical_rows: usize, physical_cols: usize) {
-        self.screen.resize(physical_rows, physical_cols);
-        self.scroll_region = 0..physical_rows as i64;
+        if physical_cols != self.screen().physical_cols {
+            // Rewrapping moves text, so the selection no longer applies
+            self.clear_selection();
+        }
+        self.select_active_status_display(ActiveStatusDisplay::MainDisplay);
+        let mut cursor = self.cursor;
+        if self.wrap_next
+            && !self.screen.is_alt_screen_active()
+            && physical_cols != self.screen().physical_cols
+        {
+            // The pending wrap is resolved by rewrapping; the cursor
+            // belongs after the last printed cell
+            cursor.x += 1;
+            self.wrap_next = false;
+        }
+        self.cursor = self.screen.resize(physical_rows, physical_cols, cursor);
+        self.scroll_region = 0..self.screen().physical_rows as i64;
+        self.left_and_right_margins = 0..physical_cols;
         self.tabs.resize(physical_cols);
         self.set_scroll_viewport(0);
         // Ensure that the cursor is within the new bounds of the screen
         self.set_cursor_pos(&Position::Relative(0), &Position::Relative(0));
     }
 
-    /// Returns true if any of the visible lines are marked dirty
-    pub fn has_dirty_lines(&self) -> bool {
+    /// Returns the number of rows and columns of the display, including
+    /// the row that is taken by the status line
+    pub fn physical_dimensions(&self) -> (usize, usize) {
         let screen = self.screen();
-        let height = screen.physical_rows;
-        let len = screen.lines.len() - self.viewport_offset as usize;
+        (
+            screen.physical_rows + self.screen.status_line_rows(),
+            screen.physical_cols,
+        )
+    }
 
-        for line in screen.lines.iter().skip(len - height) {
-            if line.is_dirty() {
-                return true;
-            }
-        }
+    /// Returns the host writable status line, if it is enabled
+    pub fn status_line(&self) -> Option<&Line> {
+        self.screen.status_line()
+    }
 
-        false
+    /// Returns true if any of the visible lines are marked dirty
+    pub fn has_dirty_lines(&self) -> bool {
+        let screen = self.screen();
+        self.viewport_range()
+            .filter_map(|idx| screen.live_line(idx))
+            .chain(self.screen.status_line())
+            .any(Line::is_dirty)
     }
 
     /// Returns the set of visible lines that are dirty.
@@ -971,17 +1940,14 @@ impl TerminalState {
         let mut res = Vec::new();
 
         let screen = self.screen();
-        let height = screen.physical_rows;
-        let len = screen.lines.len() - self.viewport_offset as usize;
-
         let selection = self.selection_range.map(|r| r.normalize());
 
-        for (i, line) in screen.lines.iter().skip(len - height).enumerate() {
-            if i >= height {
-                // When scrolling back, make sure we don't emit lines that
-                // are below the bottom of the viewport
-                break;
-            }
+        for (i, idx) in self.viewport_range().enumerate() {
+            // Compressed lines are never dirty
+            let line = match screen.live_line(idx) {
+                Some(line) => line,
+                None => continue,
+            };
             if line.is_dirty() {
                 let selrange = match selection {
                     None => 0..0,
@@ -993,7 +1959,14 @@ impl TerminalState {
                         sel.cols_for_row(row)
                     }
                 };
-                res.push((i, &*line, selrange));
+                res.push((i, line, selrange));
+            }
+        }
+
+        // The status line is shown on the row below the screen
+        if let Some(line) = self.screen.status_line() {
+            if line.is_dirty() {
+                res.push((screen.physical_rows, line, 0..0));
             }
         }
 
@@ -1004,18 +1977,66 @@ impl TerminalState {
         self.viewport_offset
     }
 
+    /// Returns the range of physical rows that are in the viewport
+    pub fn viewport_range(&self) -> Range<PhysRowIndex> {
+        let screen = self.screen();
+        let bottom = screen.len() - self.viewport_offset as usize;
+        bottom - screen.physical_rows..bottom
+    }
+
+    /// Returns the stable index of the line at the top of the viewport
+    pub fn stable_viewport_top(&self) -> StableRowIndex {
+        self.screen()
+            .phys_to_stable_row_index(self.viewport_range().start)
+    }
+
+    /// Search the current screen and its scrollback for `pattern`
+    pub fn search(&self, pattern: &Pattern) -> Result<Vec<SearchResult>, Error> {
+        self.screen().search(pattern)
+    }
+
+    /// Scroll the viewport so that the line `row` is visible.
+    /// If it is not already visible it is placed at the top of the
+    /// viewport, or as close to the top as the scrollback allows.
+    /// Returns false if the line no longer exists.
+    pub fn scroll_to_stable_row(&mut self, row: StableRowIndex) -> bool {
+        let idx = match self.screen().stable_row_to_phys(row) {
+            Some(idx) => idx,
+            None => return false,
+        };
+        let viewport = self.viewport_range();
+        if !in_range(idx, &viewport) {
+            let rows = self.screen().physical_rows;
+            let position = self.screen().len() as VisibleRowIndex
+                - idx as VisibleRowIndex
+                - rows as VisibleRowIndex;
+            self.set_scroll_viewport(position);
+        }
+        true
+    }
+
     /// Clear the dirty flag for all dirty lines
     pub fn clean_dirty_lines(&mut self) {
         let screen = self.screen_mut();
-        for line in &mut screen.lines {
+        for line in screen.live_lines_mut() {
+            line.clear_dirty();
+        }
+        if let Some(line) = self.screen.status_line_mut() {
             line.clear_dirty();
         }
     }
 
     /// When dealing with selection, mark a range of lines as dirty
     pub fn make_all_lines_dirty(&mut self) {
+        // Everything in the viewport is about to be repainted, so
+        // it needs to be decompressed
+        let viewport = self.viewport_range();
         let screen = self.screen_mut();
-        for line in &mut screen.lines {
+        screen.thaw(viewport);
+        for line in screen.live_lines_mut() {
+            line.set_dirty();
+        }
+        if let Some(line) = self.screen.status_line_mut() {
             line.set_dirty();
         }
     }
@@ -1050,32 +2071,39 @@ impl TerminalState {
 
         let rows = self.screen().physical_rows;
         let cols = self.screen().physical_cols;
+        let old_x = self.cursor.x;
         let old_y = self.cursor.y;
+        let new_x = x.min(cols as i64 - 1) as usize;
         let new_y = y.min(rows as i64 - 1);
 
-        self.cursor.x = x.min(cols as i64 - 1) as usize;
+        self.cursor.x = new_x;
         self.cursor.y = new_y;
         self.wrap_next = false;
 
+        // Only the cells under the old and new cursor positions
+        // need to be redrawn
         let screen = self.screen_mut();
-        screen.dirty_line(old_y);
-        screen.dirty_line(new_y);
+        screen.dirty_cells(old_y, old_x..old_x + 1);
+        screen.dirty_cells(new_y, new_x..new_x + 1);
     }
 
-    fn set_scroll_viewport(&mut self, position: VisibleRowIndex) {
+    /// Set the viewport so that it is `position` rows back from the
+    /// bottom of the scrollback.  Dirties the lines that are now in view.
+    pub fn set_scroll_viewport(&mut self, position: VisibleRowIndex) {
         self.clear_selection();
         let position = position.max(0);
 
         let rows = self.screen().physical_rows;
-        let avail_scrollback = self.screen().lines.len() - rows;
+        let avail_scrollback = self.screen().len() - rows;
 
         let position = position.min(avail_scrollback as i64);
 
         self.viewport_offset = position;
-        let top = self.screen().lines.len() - (rows + position as usize);
+        let viewport = self.viewport_range();
         {
             let screen = self.screen_mut();
-            for y in top..top + rows {
+            screen.thaw(viewport.clone());
+            for y in viewport {
                 screen.line_mut(y).set_dirty();
             }
         }
@@ -1089,21 +2117,67 @@ impl TerminalState {
         self.set_scroll_viewport(position);
     }
 
+    /// Returns true if the left and right margins span the screen
+    fn margins_are_full_width(&self) -> bool {
+        self.left_and_right_margins == (0..self.screen().physical_cols)
+    }
+
     fn scroll_up(&mut self, num_rows: usize) {
-        self.clear_selection();
         let scroll_region = self.scroll_region.clone();
-        self.screen_mut().scroll_up(&scroll_region, num_rows)
+        self.scroll_up_within(scroll_region, num_rows);
     }
 
     fn scroll_down(&mut self, num_rows: usize) {
-        self.clear_selection();
         let scroll_region = self.scroll_region.clone();
-        self.screen_mut().scroll_down(&scroll_region, num_rows)
+        self.scroll_down_within(scroll_region, num_rows);
+    }
+
+    /// Scroll `scroll_region` up, respecting the left and right margins
+    fn scroll_up_within(&mut self, scroll_region: Range<VisibleRowIndex>, num_rows: usize) {
+        self.clear_selection();
+        if self.margins_are_full_width() {
+            self.screen_mut().scroll_up(&scroll_region, num_rows)
+        } else {
+            let margins = self.left_and_right_margins.clone();
+            self.screen_mut()
+                .scroll_up_within_margins(&scroll_region, &margins, num_rows)
+        }
+    }
+
+    /// Scroll `scroll_region` down, respecting the left and right margins
+    fn scroll_down_within(&mut self, scroll_region: Range<VisibleRowIndex>, num_rows: usize) {
+        self.clear_selection();
+        if self.margins_are_full_width() {
+            self.screen_mut().scroll_down(&scroll_region, num_rows)
+        } else {
+            let margins = self.left_and_right_margins.clone();
+            self.screen_mut()
+                .scroll_down_within_margins(&scroll_region, &margins, num_rows)
+        }
+    }
+
+    /// The column that a carriage return moves the cursor to
+    fn left_margin_for_cursor(&self) -> usize {
+        if self.cursor.x >= self.left_and_right_margins.start {
+            self.left_and_right_margins.start
+        } else {
+            0
+        }
+    }
+
+    /// The rightmost column that the cursor can reach by moving or
+    /// printing from its current position
+    fn right_margin_for_cursor(&self) -> usize {
+        if self.cursor.x < self.left_and_right_margins.end {
+            self.left_and_right_margins.end - 1
+        } else {
+            self.screen().physical_cols - 1
+        }
     }
 
     fn new_line(&mut self, move_to_first_column: bool) {
         let x = if move_to_first_column {
-            0
+            self.left_margin_for_cursor()
         } else {
             self.cursor.x
         };
@@ -1167,21 +2241,12 @@ impl TerminalState {
 
     fn set_hyperlink(&mut self, link: Option<Hyperlink>) {
         self.pen.hyperlink = match link {
-            Some(hyperlink) => Some(Arc::new(hyperlink)),
+            Some(hyperlink) => Some(self.hyperlinks.intern(hyperlink)),
             None => None,
         }
     }
 
     fn set_image(&mut self, image: ITermFileData) {
-        if !image.inline {
-            eprintln!(
-                "Ignoring file download request name={:?} size={}",
-                image.name,
-                image.data.len()
-            );
-            return;
-        }
-
         // Decode the image data
         let decoded_image = match image::load_from_memory(&image.data) {
             Ok(im) => im,
@@ -1310,20 +2375,165 @@ impl TerminalState {
 
     fn perform_device(&mut self, dev: Device, host: &mut TerminalHost) {
         match dev {
-            Device::DeviceAttributes(a) => eprintln!("unhandled: {:?}", a),
+            Device::DeviceAttributes(a) => {
+                let description = format!("{:?}", a);
+                let encoded = CSI::Device(Box::new(Device::DeviceAttributes(a))).to_string();
+                self.unhandled(description, &encoded);
+            }
             Device::SoftReset => {
                 self.pen = CellAttributes::default();
                 // TODO: see https://vt100.net/docs/vt510-rm/DECSTR.html
             }
             Device::RequestPrimaryDeviceAttributes => {
-                host.writer().write(DEVICE_IDENT).ok();
+                write!(
+                    host.writer(),
+                    "\x1b[?{}c",
+                    self.device_identity.primary_attributes
+                )
+                .ok();
             }
             Device::RequestSecondaryDeviceAttributes => {
-                host.writer().write(b"\x1b[>0;0;0c").ok();
+                write!(
+                    host.writer(),
+                    "\x1b[>{}c",
+                    self.device_identity.secondary_attributes
+                )
+                .ok();
+            }
+            Device::RequestTerminalNameAndVersion => {
+                write!(
+                    host.writer(),
+                    "\x1bP>|{}\x1b\\",
+                    self.device_identity.name_and_version
+                )
+                .ok();
             }
             Device::StatusReport => {
                 host.writer().write(b"\x1b[0n").ok();
             }
+            Device::SelectStatusDisplayType(display_type) => {
+                self.select_status_display_type(display_type)
+            }
+            Device::SelectActiveStatusDisplay(display) => {
+                self.select_active_status_display(display)
+            }
+        }
+    }
+
+    /// DECSSDT: only the host writable status line is supported; the
+    /// other types remove it.  The status line takes the bottom row of
+    /// the display, so the screen loses a row while it is shown.
+    fn select_status_display_type(&mut self, display_type: StatusDisplayType) {
+        let enable = display_type == StatusDisplayType::HostWritable;
+        if enable == self.screen.is_status_line_enabled() {
+            return;
+        }
+        self.select_active_status_display(ActiveStatusDisplay::MainDisplay);
+        self.screen.enable_status_line(enable);
+        if enable {
+            // The top row moved into the scrollback; keep the cursor
+            // on the same line of text
+            self.cursor.y = (self.cursor.y - 1).max(0);
+        }
+        self.scroll_region = 0..self.screen().physical_rows as VisibleRowIndex;
+        self.set_cursor_pos(&Position::Relative(0), &Position::Relative(0));
+        self.make_all_lines_dirty();
+    }
+
+    /// DECSASD: direct output to the status line or back to the main
+    /// display.  Each display keeps its own cursor position.
+    fn select_active_status_display(&mut self, display: ActiveStatusDisplay) {
+        match display {
+            ActiveStatusDisplay::StatusLine => {
+                if self.screen.is_status_line_enabled() && !self.screen.is_status_line_active() {
+                    self.main_display_state = Some((self.cursor, self.scroll_region.clone()));
+                    self.screen.activate_status_line();
+                    self.cursor = CursorPosition::default();
+                    self.scroll_region = 0..1;
+                    self.wrap_next = false;
+                }
+            }
+            ActiveStatusDisplay::MainDisplay => {
+                if let Some((cursor, scroll_region)) = self.main_display_state.take() {
+                    self.screen.activate_main_display();
+                    self.cursor = cursor;
+                    self.scroll_region = scroll_region;
+                    self.wrap_next = false;
+                }
+            }
+        }
+    }
+
+    /// Returns the value of a setting requested by DECRQSS, in the
+    /// form of the control sequence that would select it
+    fn setting_report(&self, setting: &[u8]) -> Option<String> {
+        match setting {
+            b"m" => Some(format!("{}m", sgr_report(&self.pen))),
+            b" q" => Some(format!("{} q", self.cursor_style as u8)),
+            b"r" => Some(format!(
+                "{};{}r",
+                self.scroll_region.start + 1,
+                self.scroll_region.end
+            )),
+            b"s" => Some(format!(
+                "{};{}s",
+                self.left_and_right_margins.start + 1,
+                self.left_and_right_margins.end
+            )),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of the terminfo capability `name` requested
+    /// by XTGETTCAP.  Boolean capabilities have no value.
+    fn termcap(&self, name: &str) -> Option<Option<String>> {
+        let value = match name {
+            "TN" | "name" => self.device_identity.term.clone(),
+            "Co" | "colors" => "256".to_string(),
+            "RGB" => "8".to_string(),
+            "Tc" => return Some(None),
+            "setrgbf" => "\x1b[38;2;%p1%d;%p2%d;%p3%dm".to_string(),
+            "setrgbb" => "\x1b[48;2;%p1%d;%p2%d;%p3%dm".to_string(),
+            "smxx" => "\x1b[9m".to_string(),
+            "rmxx" => "\x1b[29m".to_string(),
+            _ => return None,
+        };
+        Some(Some(value))
+    }
+
+    fn perform_device_control(&mut self, request: DeviceControlRequest, host: &mut TerminalHost) {
+        match request {
+            DeviceControlRequest::RequestSetting(setting) => {
+                let response = match self.setting_report(&setting) {
+                    Some(report) => format!("\x1bP1$r{}\x1b\\", report),
+                    None => "\x1bP0$r\x1b\\".to_string(),
+                };
+                host.writer().write(response.as_bytes()).ok();
+            }
+            DeviceControlRequest::RequestTermcap(names) => {
+                for hex_name in names.split(|&b| b == b';') {
+                    let name = hex_decode(hex_name);
+                    let value = name.as_ref().and_then(|name| self.termcap(name));
+                    // The name is only echoed if it is valid hex, so
+                    // that arbitrary text can't be reflected
+                    let hex_name = match name {
+                        Some(_) if hex_name.len() <= MAX_REFLECTED_LEN => {
+                            std::str::from_utf8(hex_name).unwrap_or("")
+                        }
+                        _ => "",
+                    };
+                    let response = match value {
+                        Some(Some(value)) => format!(
+                            "\x1bP1+r{}={}\x1b\\",
+                            hex_name,
+                            hex_encode(value.as_bytes())
+                        ),
+                        Some(None) => format!("\x1bP1+r{}\x1b\\", hex_name),
+                        None => format!("\x1bP0+r{}\x1b\\", hex_name),
+                    };
+                    host.writer().write(response.as_bytes()).ok();
+                }
+            }
         }
     }
 
@@ -1350,6 +2560,33 @@ impl TerminalState {
                 self.bracketed_paste = false;
             }
 
+            Mode::SetDecPrivateMode(DecPrivateMode::Code(
+                DecPrivateModeCode::SynchronizedOutput,
+            )) => {
+                if self.synchronized_output.is_none() {
+                    self.synchronized_output = Some(Instant::now());
+                }
+            }
+            Mode::ResetDecPrivateMode(DecPrivateMode::Code(
+                DecPrivateModeCode::SynchronizedOutput,
+            )) => {
+                self.synchronized_output = None;
+            }
+
+            Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::FocusTracking)) => {
+                self.focus_tracking = true;
+            }
+            Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::FocusTracking)) => {
+                self.focus_tracking = false;
+            }
+
+            Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::Win32InputMode)) => {
+                self.win32_input_mode = true;
+            }
+            Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::Win32InputMode)) => {
+                self.win32_input_mode = false;
+            }
+
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::EnableAlternateScreen,
             )) => {
@@ -1378,6 +2615,18 @@ impl TerminalState {
                 self.application_cursor_keys = false;
             }
 
+            Mode::SetDecPrivateMode(DecPrivateMode::Code(
+                DecPrivateModeCode::LeftRightMarginMode,
+            )) => {
+                self.left_and_right_margin_mode = true;
+            }
+            Mode::ResetDecPrivateMode(DecPrivateMode::Code(
+                DecPrivateModeCode::LeftRightMarginMode,
+            )) => {
+                self.left_and_right_margin_mode = false;
+                self.left_and_right_margins = 0..self.screen().physical_cols;
+            }
+
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::ShowCursor)) => {
                 self.cursor_visible = true;
             }
@@ -1416,6 +2665,13 @@ impl TerminalState {
                 self.sgr_mouse = false;
             }
 
+            Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::SGRPixelsMouse)) => {
+                self.sgr_pixels_mouse = true;
+            }
+            Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::SGRPixelsMouse)) => {
+                self.sgr_pixels_mouse = false;
+            }
+
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::ClearAndEnableAlternateScreen,
             )) => {
@@ -1438,23 +2694,38 @@ impl TerminalState {
             }
             Mode::SaveDecPrivateMode(DecPrivateMode::Code(_))
             | Mode::RestoreDecPrivateMode(DecPrivateMode::Code(_)) => {
-                eprintln!("save/restore dec mode unimplemented")
+                let description = format!("save/restore dec mode {:?}", mode);
+                self.unhandled(description, &CSI::Mode(mode).to_string());
             }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Unspecified(n))
             | Mode::ResetDecPrivateMode(DecPrivateMode::Unspecified(n))
             | Mode::SaveDecPrivateMode(DecPrivateMode::Unspecified(n))
             | Mode::RestoreDecPrivateMode(DecPrivateMode::Unspecified(n)) => {
-                eprintln!("unhandled DecPrivateMode {}", n);
+                let description = format!("DecPrivateMode {}", n);
+                self.unhandled(description, &CSI::Mode(mode).to_string());
+            }
+
+            Mode::XtermKeyMode {
+                resource: XtermKeyModifierResource::OtherKeys,
+                value,
+            } => {
+                self.modify_other_keys = value.unwrap_or(0);
+            }
+            Mode::XtermKeyMode { .. } => {
+                let description = format!("{:?}", mode);
+                self.unhandled(description, &CSI::Mode(mode).to_string());
             }
 
             Mode::SetMode(TerminalMode::Unspecified(n))
             | Mode::ResetMode(TerminalMode::Unspecified(n)) => {
-                eprintln!("unhandled TerminalMode {}", n);
+                let description = format!("TerminalMode {}", n);
+                self.unhandled(description, &CSI::Mode(mode).to_string());
             }
 
-            Mode::SetMode(m) | Mode::ResetMode(m) => {
-                eprintln!("unhandled TerminalMode {:?}", m);
+            Mode::SetMode(ref m) | Mode::ResetMode(ref m) => {
+                let description = format!("TerminalMode {:?}", m);
+                self.unhandled(description, &CSI::Mode(mode.clone()).to_string());
             }
         }
     }
@@ -1516,7 +2787,10 @@ impl TerminalState {
             | Window::PushIconAndWindowTitle
             | Window::PushIconTitle
             | Window::PushWindowTitle => {}
-            _ => eprintln!("unhandled Window CSI {:?}", window),
+            _ => {
+                let description = format!("Window CSI {:?}", window);
+                self.unhandled(description, &CSI::Window(window).to_string());
+            }
         }
     }
 
@@ -1562,19 +2836,22 @@ impl TerminalState {
             Edit::DeleteCharacter(n) => {
                 let y = self.cursor.y;
                 let x = self.cursor.x;
-                let limit = (x + n as usize).min(self.screen().physical_cols);
+                let right_margin = self.right_margin_for_cursor() + 1;
+                let limit = (x + n as usize).min(right_margin);
                 {
                     let screen = self.screen_mut();
                     for _ in x..limit as usize {
-                        screen.erase_cell(x, y);
+                        screen.erase_cell(x, y, right_margin);
                     }
                 }
                 self.clear_selection_if_intersects(x..limit, y as ScrollbackOrVisibleRowIndex);
             }
             Edit::DeleteLine(n) => {
-                if in_range(self.cursor.y, &self.scroll_region) {
+                if in_range(self.cursor.y, &self.scroll_region)
+                    && in_range(self.cursor.x, &self.left_and_right_margins)
+                {
                     let scroll_region = self.cursor.y..self.scroll_region.end;
-                    self.screen_mut().scroll_up(&scroll_region, n as usize);
+                    self.scroll_up_within(scroll_region, n as usize);
 
                     let scrollback_region = self.cursor.y as ScrollbackOrVisibleRowIndex
                         ..self.scroll_region.end as ScrollbackOrVisibleRowIndex;
@@ -1614,19 +2891,22 @@ impl TerminalState {
                 let x = self.cursor.x;
                 // TODO: this limiting behavior may not be correct.  There's also a
                 // SEM sequence that impacts the scope of ICH and ECH to consider.
-                let limit = (x + n as usize).min(self.screen().physical_cols);
+                let right_margin = self.right_margin_for_cursor() + 1;
+                let limit = (x + n as usize).min(right_margin);
                 {
                     let screen = self.screen_mut();
                     for x in x..limit as usize {
-                        screen.insert_cell(x, y);
+                        screen.insert_cell(x, y, right_margin);
                     }
                 }
                 self.clear_selection_if_intersects(x..limit, y as ScrollbackOrVisibleRowIndex);
             }
             Edit::InsertLine(n) => {
-                if in_range(self.cursor.y, &self.scroll_region) {
+                if in_range(self.cursor.y, &self.scroll_region)
+                    && in_range(self.cursor.x, &self.left_and_right_margins)
+                {
                     let scroll_region = self.cursor.y..self.scroll_region.end;
-                    self.screen_mut().scroll_down(&scroll_region, n as usize);
+                    self.scroll_down_within(scroll_region, n as usize);
 
                     let scrollback_region = self.cursor.y as ScrollbackOrVisibleRowIndex
                         ..self.scroll_region.end as ScrollbackOrVisibleRowIndex;
@@ -1664,6 +2944,11 @@ impl TerminalState {
                 }
                 self.scroll_region = top..bottom + 1;
             }
+            Cursor::SetLeftAndRightMargins { left, right } => {
+                if self.left_and_right_margin_mode {
+                    self.set_left_and_right_margins(left.as_zero_based(), right.as_zero_based());
+                }
+            }
             Cursor::ForwardTabulation(n) => {
                 for _ in 0..n {
                     self.c0_horizontal_tab();
@@ -1674,17 +2959,32 @@ impl TerminalState {
             Cursor::TabulationControl(_) => {}
             Cursor::LineTabulation(_) => {}
 
-            Cursor::Left(n) => {
-                self.set_cursor_pos(&Position::Relative(-(i64::from(n))), &Position::Relative(0))
-            }
+            Cursor::Left(n) => self.cursor_left(n),
             Cursor::Right(n) => {
-                self.set_cursor_pos(&Position::Relative(i64::from(n)), &Position::Relative(0))
+                // The cursor stops at the right margin if it starts inside it
+                let x = (self.cursor.x as i64 + i64::from(n))
+                    .min(self.right_margin_for_cursor() as i64);
+                self.set_cursor_pos(&Position::Absolute(x), &Position::Relative(0))
             }
             Cursor::Up(n) => {
-                self.set_cursor_pos(&Position::Relative(0), &Position::Relative(-(i64::from(n))))
+                // The cursor stops at the top margin if it starts below it
+                let top = if self.cursor.y >= self.scroll_region.start {
+                    self.scroll_region.start
+                } else {
+                    0
+                };
+                let y = (self.cursor.y - i64::from(n)).max(top);
+                self.set_cursor_pos(&Position::Relative(0), &Position::Absolute(y))
             }
             Cursor::Down(n) => {
-                self.set_cursor_pos(&Position::Relative(0), &Position::Relative(i64::from(n)))
+                // The cursor stops at the bottom margin if it starts above it
+                let bottom = if self.cursor.y < self.scroll_region.end {
+                    self.scroll_region.end
+                } else {
+                    self.screen().physical_rows as VisibleRowIndex
+                };
+                let y = (self.cursor.y + i64::from(n)).min(bottom - 1);
+                self.set_cursor_pos(&Position::Relative(0), &Position::Absolute(y))
             }
             Cursor::CharacterAndLinePosition { line, col } | Cursor::Position { line, col } => self
                 .set_cursor_pos(
@@ -1732,9 +3032,36 @@ impl TerminalState {
                 let report = CSI::Cursor(Cursor::ActivePositionReport { line, col });
                 write!(host.writer(), "{}", report).ok();
             }
-            Cursor::SaveCursor => self.save_cursor(),
+            Cursor::SaveCursor => {
+                // With DECLRMM enabled, CSI s is DECSLRM with default
+                // parameters rather than SCP
+                if self.left_and_right_margin_mode {
+                    let cols = self.screen().physical_cols;
+                    self.set_left_and_right_margins(0, (cols as u32).saturating_sub(1));
+                } else {
+                    self.save_cursor();
+                }
+            }
             Cursor::RestoreCursor => self.restore_cursor(),
-            Cursor::CursorStyle(style) => eprintln!("unhandled: CursorStyle {:?}", style),
+            Cursor::CursorStyle(style) => self.cursor_style = style,
+        }
+    }
+
+    /// Moves the cursor left, stopping at the left margin if it starts
+    /// inside it
+    fn cursor_left(&mut self, n: u32) {
+        let x = (self.cursor.x as i64 - i64::from(n)).max(self.left_margin_for_cursor() as i64);
+        self.set_cursor_pos(&Position::Absolute(x), &Position::Relative(0))
+    }
+
+    /// Apply DECSLRM; `left` and `right` are the 0-based inclusive
+    /// columns of the margins.  Invalid margins are ignored.
+    fn set_left_and_right_margins(&mut self, left: u32, right: u32) {
+        let cols = self.screen().physical_cols;
+        let right = (right as usize).min(cols.saturating_sub(1));
+        let left = left as usize;
+        if left < right {
+            self.left_and_right_margins = left..right + 1;
         }
     }
 
@@ -1780,6 +3107,10 @@ impl TerminalState {
             Sgr::Intensity(intensity) => {
                 self.pen.set_intensity(intensity);
             }
+            // SGR 21 is the only way to select a double underline
+            Sgr::Underline(Underline::Double) if self.sgr_21_is_bold_off => {
+                self.pen.set_intensity(Intensity::Normal);
+            }
             Sgr::Underline(underline) => {
                 self.pen.set_underline(underline);
             }
@@ -1798,6 +3129,9 @@ impl TerminalState {
             Sgr::StrikeThrough(strike) => {
                 self.pen.set_strikethrough(strike);
             }
+            Sgr::Overline(overline) => {
+                self.pen.set_overline(overline);
+            }
             Sgr::Foreground(col) => {
                 self.pen.set_foreground(col);
             }
@@ -1851,6 +3185,11 @@ impl<'a> Performer<'a> {
             Some(s) => s,
             None => return,
         };
+        let p = if self.normalize_output {
+            p.nfc().collect()
+        } else {
+            p
+        };
 
         let mut x_offset = 0;
 
@@ -1875,12 +3214,19 @@ impl<'a> Performer<'a> {
             };
 
             if !self.insert && self.wrap_next {
+                // Remember that the text continues on the next line so
+                // that it can be rewrapped if the screen is resized
+                let y = self.cursor.y;
+                let screen = self.screen_mut();
+                let line_idx = screen.phys_row(y);
+                screen.line_mut(line_idx).set_wrapped(true);
                 self.new_line(true);
             }
 
             let x = self.cursor.x;
             let y = self.cursor.y;
-            let width = self.screen().physical_cols;
+            // Printing wraps at the right margin if the cursor is inside it
+            let width = self.right_margin_for_cursor() + 1;
 
             let pen = self.pen.clone();
 
@@ -1895,7 +3241,7 @@ impl<'a> Performer<'a> {
             if self.insert {
                 let screen = self.screen_mut();
                 for _ in x..x + print_width as usize {
-                    screen.insert_cell(x + x_offset, y);
+                    screen.insert_cell(x + x_offset, y, width);
                 }
             }
 
@@ -1925,7 +3271,26 @@ impl<'a> Performer<'a> {
         match action {
             Action::Print(c) => self.print(c),
             Action::Control(code) => self.control(code),
-            Action::DeviceControl(ctrl) => eprintln!("Unhandled {:?}", ctrl),
+            Action::DeviceControl(ctrl) => match *ctrl {
+                DeviceControlMode::Data(data) => {
+                    if let Some(request) = self.device_control.as_mut() {
+                        request.push(data);
+                    }
+                }
+                DeviceControlMode::Exit => {
+                    if let Some(request) = self.state.device_control.take() {
+                        self.state.perform_device_control(request, self.host);
+                    }
+                }
+                enter => {
+                    self.device_control = DeviceControlRequest::new(&enter);
+                    if self.device_control.is_none() {
+                        // The data and exit are part of the sequence
+                        // that is recorded here
+                        self.unhandled(format!("{:?}", enter), "");
+                    }
+                }
+            },
             Action::OperatingSystemCommand(osc) => self.osc_dispatch(*osc),
             Action::Esc(esc) => self.esc_dispatch(esc),
             Action::CSI(csi) => self.csi_dispatch(csi),
@@ -1945,14 +3310,16 @@ impl<'a> Performer<'a> {
                 self.new_line(false)
             }
             ControlCode::CarriageReturn => {
-                self.set_cursor_pos(&Position::Absolute(0), &Position::Relative(0));
-            }
-            ControlCode::Backspace => {
-                self.set_cursor_pos(&Position::Relative(-1), &Position::Relative(0));
+                let x = self.left_margin_for_cursor();
+                self.set_cursor_pos(&Position::Absolute(x as i64), &Position::Relative(0));
             }
+            ControlCode::Backspace => self.cursor_left(1),
             ControlCode::HorizontalTab => self.c0_horizontal_tab(),
-            ControlCode::Bell => eprintln!("Ding! (this is the bell)"),
-            _ => println!("unhandled ControlCode {:?}", control),
+            ControlCode::Bell => self.host.ring_bell(),
+            _ => {
+                let encoded = (control as u8 as char).to_string();
+                self.unhandled(format!("ControlCode {:?}", control), &encoded);
+            }
         }
     }
 
@@ -1964,10 +3331,14 @@ impl<'a> Performer<'a> {
             CSI::Edit(edit) => self.state.perform_csi_edit(edit),
             CSI::Mode(mode) => self.state.perform_csi_mode(mode),
             CSI::Device(dev) => self.state.perform_device(*dev, self.host),
-            CSI::Mouse(mouse) => eprintln!("mouse report sent by app? {:?}", mouse),
+            CSI::Mouse(mouse) => {
+                let description = format!("mouse report sent by app {:?}", mouse);
+                self.unhandled(description, &CSI::Mouse(mouse).to_string());
+            }
             CSI::Window(window) => self.state.perform_csi_window(window, self.host),
             CSI::Unspecified(unspec) => {
-                eprintln!("unknown unspecified CSI: {:?}", format!("{}", unspec))
+                let encoded = CSI::Unspecified(unspec).to_string();
+                self.unhandled(format!("CSI {:?}", &encoded[2..]), &encoded);
             }
         };
     }
@@ -1999,7 +3370,10 @@ impl<'a> Performer<'a> {
             }
             Esc::Code(EscCode::DecSaveCursorPosition) => self.save_cursor(),
             Esc::Code(EscCode::DecRestoreCursorPosition) => self.restore_cursor(),
-            _ => println!("ESC: unhandled {:?}", esc),
+            _ => {
+                let description = format!("ESC {:?}", esc);
+                self.unhandled(description, &esc.to_string());
+            }
         }
     }
 
@@ -2016,11 +3390,12 @@ impl<'a> Performer<'a> {
                 self.set_hyperlink(link);
             }
             OperatingSystemCommand::Unspecified(unspec) => {
-                eprint!("Unhandled OSC ");
-                for item in unspec {
-                    eprint!(" {}", String::from_utf8_lossy(&item));
+                let mut description = "OSC".to_string();
+                for item in &unspec {
+                    write!(&mut description, " {}", String::from_utf8_lossy(item)).ok();
                 }
-                eprintln!("");
+                let encoded = OperatingSystemCommand::Unspecified(unspec).to_string();
+                self.unhandled(description, &encoded);
             }
 
             OperatingSystemCommand::ClearSelection(_) => {
@@ -2035,12 +3410,27 @@ impl<'a> Performer<'a> {
                 Err(err) => eprintln!("failed to set clipboard in response to OSC 52: {:?}", err),
             },
             OperatingSystemCommand::ITermProprietary(iterm) => match iterm {
+                ITermProprietary::File(ref file) if !file.inline => self
+                    .host
+                    .save_file(file.name.as_ref().map(String::as_str), &file.data),
                 ITermProprietary::File(image) => self.set_image(*image),
-                _ => eprintln!("unhandled iterm2: {:?}", iterm),
+                // This doesn't identify the host, so it is assumed to
+                // be the local one
+                ITermProprietary::CurrentDir(dir) => {
+                    self.current_dir = Some(format!("file://{}", dir.replace('%', "%25")));
+                }
+                _ => {
+                    let description = format!("iterm2 {:?}", iterm);
+                    let encoded = OperatingSystemCommand::ITermProprietary(iterm).to_string();
+                    self.unhandled(description, &encoded);
+                }
             },
             OperatingSystemCommand::SystemNotification(message) => {
                 eprintln!("Application sends SystemNotification: {}", message);
             }
+            OperatingSystemCommand::CurrentWorkingDirectory(url) => {
+                self.current_dir = Some(url);
+            }
         }
     }
 }
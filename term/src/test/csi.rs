@@ -176,3 +176,307 @@ fn test_ed() {
         Compare::TEXT | Compare::ATTRS,
     );
 }
+
+#[test]
+fn test_decslrm() {
+    let mut term = TestTerm::new(4, 6, 0);
+    term.print("abcdef\r\nghijkl\r\nmnopqr\r\nstuvwx");
+
+    // Margins are ignored unless DECLRMM is enabled
+    term.print("\x1b[2;4s");
+    term.print("\x1b[?69h\x1b[2;4s");
+
+    // Scrolling only moves the cells between the margins
+    term.cup(1, 3);
+    term.print("\n");
+    assert_visible_contents(&term, &["ahijef", "gnopkl", "mtuvqr", "s   wx"]);
+    term.assert_cursor_pos(1, 3, None);
+
+    // Printing wraps at the right margin, to the left margin
+    term.print("XYZW");
+    assert_visible_contents(&term, &["anopef", "gtuvkl", "mXYZqr", "sW  wx"]);
+    term.assert_cursor_pos(2, 3, None);
+    term.print("\r");
+    term.assert_cursor_pos(1, 3, None);
+
+    // Cursor movement stops at the margins
+    term.print("\x1b[10C");
+    term.assert_cursor_pos(3, 3, None);
+    term.print("\x1b[10D");
+    term.assert_cursor_pos(1, 3, None);
+
+    // Deleting and inserting characters only shifts cells up to
+    // the right margin
+    term.cup(1, 0);
+    term.print("\x1b[P");
+    assert_visible_contents(&term, &["aop ef", "gtuvkl", "mXYZqr", "sW  wx"]);
+    term.print("\x1b[2@");
+    assert_visible_contents(&term, &["a  oef", "gtuvkl", "mXYZqr", "sW  wx"]);
+
+    // With DECLRMM enabled, CSI s resets the margins
+    term.print("\x1b[s");
+    term.cup(3, 0);
+    term.print("\r");
+    term.assert_cursor_pos(0, 0, None);
+}
+
+/// Send `seq` to the terminal and return its response
+fn query(term: &mut TestTerm, seq: &str) -> String {
+    let mut host = RecordingHost::default();
+    term.term.advance_bytes(seq, &mut host);
+    String::from_utf8(host.output).unwrap()
+}
+
+#[test]
+fn test_device_identity() {
+    let mut term = TestTerm::new(3, 4, 0);
+
+    assert_eq!(query(&mut term, "\x1b[c"), "\x1b[?62;22c");
+    assert_eq!(query(&mut term, "\x1b[>c"), "\x1b[>1;0;0c");
+    assert_eq!(query(&mut term, "\x1b[>q"), "\x1bP>|wezterm\x1b\\");
+    assert_eq!(query(&mut term, "\x1b[5n"), "\x1b[0n");
+    term.print("ab\r\n");
+    assert_eq!(query(&mut term, "\x1b[6n"), "\x1b[2;1R");
+
+    term.set_device_identity(DeviceIdentity {
+        primary_attributes: "64;\x1b22".to_string(),
+        secondary_attributes: "41;330;0".to_string(),
+        name_and_version: "fancyterm\x1b\\ 1.0".to_string(),
+        term: "fancyterm".to_string(),
+    });
+    assert_eq!(query(&mut term, "\x1b[0c"), "\x1b[?64;22c");
+    assert_eq!(query(&mut term, "\x1b[>0c"), "\x1b[>41;330;0c");
+    assert_eq!(query(&mut term, "\x1b[>0q"), "\x1bP>|fancyterm\\ 1.0\x1b\\");
+}
+
+#[test]
+fn test_decrqss() {
+    let mut term = TestTerm::new(5, 10, 0);
+    assert_eq!(query(&mut term, "\x1bP$qm\x1b\\"), "\x1bP1$r0m\x1b\\");
+
+    term.print("\x1b[1;4;31;48;2;1;2;3m");
+    assert_eq!(
+        query(&mut term, "\x1bP$qm\x1b\\"),
+        "\x1bP1$r0;1;4;31;48;2;1;2;3m\x1b\\"
+    );
+
+    term.print("\x1b[5 q\x1b[2;4r\x1b[?69h\x1b[3;8s");
+    assert_eq!(query(&mut term, "\x1bP$q q\x1b\\"), "\x1bP1$r5 q\x1b\\");
+    assert_eq!(query(&mut term, "\x1bP$qr\x1b\\"), "\x1bP1$r2;4r\x1b\\");
+    assert_eq!(query(&mut term, "\x1bP$qs\x1b\\"), "\x1bP1$r3;8s\x1b\\");
+
+    assert_eq!(query(&mut term, "\x1bP$qz\x1b\\"), "\x1bP0$r\x1b\\");
+}
+
+#[test]
+fn test_xtgettcap() {
+    let mut term = TestTerm::new(5, 10, 0);
+    // TN;Tc;bogus
+    assert_eq!(
+        query(&mut term, "\x1bP+q544E;5463;626F677573\x1b\\"),
+        "\x1bP1+r544E=787465726D2D323536636F6C6F72\x1b\\\
+         \x1bP1+r5463\x1b\\\
+         \x1bP0+r626F677573\x1b\\"
+    );
+    // colors, and a name that isn't valid hex, which isn't echoed
+    assert_eq!(
+        query(&mut term, "\x1bP+q636F6C6F7273;zz\x1b\\"),
+        "\x1bP1+r636F6C6F7273=323536\x1b\\\x1bP0+r\x1b\\"
+    );
+    // text that would run a command if it were echoed
+    assert_eq!(query(&mut term, "\x1bP+qls\r\x1b\\"), "\x1bP0+r\x1b\\");
+}
+
+/// Press `key` and return what is sent to the program
+fn press(term: &mut TestTerm, key: KeyCode, mods: KeyModifiers) -> String {
+    let mut sink = vec![];
+    term.key_down(key, mods, &mut sink).unwrap();
+    String::from_utf8(sink).unwrap()
+}
+
+#[test]
+fn test_modify_other_keys() {
+    let mut term = TestTerm::new(3, 4, 0);
+    let ctrl_shift = KeyModifiers::CTRL | KeyModifiers::SHIFT;
+
+    assert_eq!(
+        press(&mut term, KeyCode::Char('i'), KeyModifiers::CTRL),
+        "\t"
+    );
+    assert_eq!(press(&mut term, KeyCode::Char('A'), ctrl_shift), "\x01");
+
+    // Level 1 only reports the combinations that are ambiguous
+    term.print("\x1b[>4;1m");
+    assert_eq!(
+        press(&mut term, KeyCode::Char('i'), KeyModifiers::CTRL),
+        "\t"
+    );
+    assert_eq!(
+        press(&mut term, KeyCode::Char('A'), ctrl_shift),
+        "\x1b[27;6;65~"
+    );
+    assert_eq!(
+        press(&mut term, KeyCode::Char('1'), KeyModifiers::CTRL),
+        "\x1b[27;5;49~"
+    );
+    assert_eq!(
+        press(&mut term, KeyCode::Tab, KeyModifiers::SHIFT),
+        "\x1b[27;2;9~"
+    );
+    assert_eq!(
+        press(&mut term, KeyCode::Char('a'), KeyModifiers::ALT),
+        "\x1ba"
+    );
+
+    // Level 2 reports all modified keys
+    term.print("\x1b[>4;2m");
+    assert_eq!(
+        press(&mut term, KeyCode::Char('i'), KeyModifiers::CTRL),
+        "\x1b[27;5;105~"
+    );
+    assert_eq!(
+        press(&mut term, KeyCode::Char('a'), KeyModifiers::ALT),
+        "\x1b[27;3;97~"
+    );
+    assert_eq!(
+        press(&mut term, KeyCode::Char('A'), KeyModifiers::SHIFT),
+        "A"
+    );
+    assert_eq!(press(&mut term, KeyCode::Tab, KeyModifiers::NONE), "\t");
+
+    // Omitting the value restores the default
+    term.print("\x1b[>4m");
+    assert_eq!(
+        press(&mut term, KeyCode::Char('i'), KeyModifiers::CTRL),
+        "\t"
+    );
+
+    term.set_csi_u_key_encoding(true);
+    assert_eq!(
+        press(&mut term, KeyCode::Char('i'), KeyModifiers::CTRL),
+        "\x1b[105;5u"
+    );
+    assert_eq!(
+        press(&mut term, KeyCode::Enter, KeyModifiers::SHIFT),
+        "\x1b[13;2u"
+    );
+    assert_eq!(
+        press(&mut term, KeyCode::Char('i'), KeyModifiers::NONE),
+        "i"
+    );
+}
+
+#[test]
+fn test_focus_tracking() {
+    let mut term = TestTerm::new(3, 4, 0);
+    let mut sink = vec![];
+    term.focus_changed(true, &mut sink).unwrap();
+    assert!(sink.is_empty());
+
+    term.print("\x1b[?1004h");
+    term.focus_changed(false, &mut sink).unwrap();
+    term.focus_changed(true, &mut sink).unwrap();
+    assert_eq!(sink, b"\x1b[O\x1b[I");
+
+    term.print("\x1b[?1004l");
+    sink.clear();
+    term.focus_changed(false, &mut sink).unwrap();
+    assert!(sink.is_empty());
+}
+
+#[test]
+fn test_win32_input_mode() {
+    let mut term = TestTerm::new(3, 4, 0);
+    let mut sink = vec![];
+    term.key_up(KeyCode::Char('a'), KeyModifiers::NONE, &mut sink)
+        .unwrap();
+    assert!(sink.is_empty());
+
+    term.print("\x1b[?9001h");
+    assert_eq!(
+        press(&mut term, KeyCode::Char('a'), KeyModifiers::NONE),
+        "\x1b[65;0;97;1;0;1_"
+    );
+    assert_eq!(
+        press(&mut term, KeyCode::Char('c'), KeyModifiers::CTRL),
+        "\x1b[67;0;3;1;8;1_"
+    );
+    assert_eq!(
+        press(&mut term, KeyCode::UpArrow, KeyModifiers::NONE),
+        "\x1b[38;0;0;1;256;1_"
+    );
+    term.key_up(KeyCode::Shift, KeyModifiers::NONE, &mut sink)
+        .unwrap();
+    assert_eq!(sink, b"\x1b[16;0;0;0;0;1_");
+
+    term.print("\x1b[?9001l");
+    assert_eq!(
+        press(&mut term, KeyCode::Char('a'), KeyModifiers::NONE),
+        "a"
+    );
+}
+
+#[test]
+fn test_synchronized_output() {
+    let mut term = TestTerm::new(3, 4, 0);
+    assert!(!term.is_output_synchronized());
+    assert_eq!(term.synchronized_output_remaining(), None);
+
+    term.print("\x1b[?2026h");
+    assert!(term.is_output_synchronized());
+    assert!(term.synchronized_output_remaining().is_some());
+    term.print("hello");
+    assert!(term.is_output_synchronized());
+
+    term.print("\x1b[?2026l");
+    assert!(!term.is_output_synchronized());
+    assert!(term.has_dirty_lines());
+}
+
+#[test]
+fn test_sgr_lines() {
+    let mut term = TestTerm::new(3, 4, 0);
+    term.print("\x1b[1;21;9;53m");
+    assert_eq!(
+        query(&mut term, "\x1bP$qm\x1b\\"),
+        "\x1bP1$r0;1;21;9;53m\x1b\\"
+    );
+    term.print("\x1b[24;55m");
+    assert_eq!(query(&mut term, "\x1bP$qm\x1b\\"), "\x1bP1$r0;1;9m\x1b\\");
+
+    term.set_sgr_21_is_bold_off(true);
+    term.print("\x1b[0;1;4;21m");
+    assert_eq!(query(&mut term, "\x1bP$qm\x1b\\"), "\x1bP1$r0;4m\x1b\\");
+}
+
+#[test]
+fn test_status_line() {
+    let mut term = TestTerm::new(3, 4, 0);
+    term.print("a\r\nb\r\nc");
+
+    // Enabling the status line takes the bottom row from the screen
+    term.print("\x1b[2$~");
+    assert_eq!(term.physical_dimensions(), (3, 4));
+    term.assert_viewport_contents(&["b", "c"]);
+    term.assert_cursor_pos(1, 1, None);
+    assert_eq!(
+        term.status_line().map(Line::as_str),
+        Some("    ".to_owned())
+    );
+
+    // Output is directed to the status line and back again, with each
+    // display keeping its own cursor position
+    term.clean_dirty_lines();
+    term.print("\x1b[1$}st\x1b[0$}d");
+    assert_eq!(
+        term.status_line().map(Line::as_str),
+        Some("st  ".to_owned())
+    );
+    term.assert_viewport_contents(&["b", "cd"]);
+    term.assert_dirty_lines(&[1, 2], None);
+
+    term.print("\x1b[0$~");
+    assert_eq!(term.physical_dimensions(), (3, 4));
+    assert!(term.status_line().is_none());
+    term.assert_viewport_contents(&["b", "cd", ""]);
+}
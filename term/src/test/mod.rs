@@ -5,6 +5,7 @@ use super::*;
 mod c0;
 mod c1;
 mod csi;
+mod search;
 mod selection;
 use std::sync::Arc;
 use termwiz::escape::csi::{Edit, EraseInDisplay, EraseInLine};
@@ -14,6 +15,9 @@ use termwiz::escape::{OneBased, OperatingSystemCommand, CSI};
 struct TestHost {
     title: String,
     clip: Option<String>,
+    bells: usize,
+    saved_files: Vec<(Option<String>, Vec<u8>)>,
+    screen_changes: usize,
 }
 
 impl TestHost {
@@ -53,6 +57,19 @@ impl TerminalHost for TestHost {
     }
 
     fn click_link(&mut self, _link: &Arc<Hyperlink>) {}
+
+    fn ring_bell(&mut self) {
+        self.bells += 1;
+    }
+
+    fn save_file(&mut self, name: Option<&str>, data: &[u8]) {
+        self.saved_files
+            .push((name.map(str::to_owned), data.to_vec()));
+    }
+
+    fn screen_changed(&mut self) {
+        self.screen_changes += 1;
+    }
 }
 
 struct TestTerm {
@@ -139,6 +156,8 @@ impl TestTerm {
                 kind: MouseEventKind::Press,
                 x,
                 y,
+                x_pixel: 0,
+                y_pixel: 0,
                 button,
                 modifiers: KeyModifiers::default(),
             })
@@ -147,6 +166,8 @@ impl TestTerm {
                 kind: MouseEventKind::Release,
                 x,
                 y,
+                x_pixel: 0,
+                y_pixel: 0,
                 button,
                 modifiers: KeyModifiers::default(),
             })
@@ -166,6 +187,8 @@ impl TestTerm {
             kind: MouseEventKind::Press,
             x: start_x,
             y: start_y,
+            x_pixel: 0,
+            y_pixel: 0,
             button: MouseButton::Left,
             modifiers: KeyModifiers::default(),
         })
@@ -176,6 +199,8 @@ impl TestTerm {
             kind: MouseEventKind::Move,
             x: end_x,
             y: end_y,
+            x_pixel: 0,
+            y_pixel: 0,
             button: MouseButton::None,
             modifiers: KeyModifiers::default(),
         })
@@ -186,6 +211,8 @@ impl TestTerm {
             kind: MouseEventKind::Release,
             x: end_x,
             y: end_y,
+            x_pixel: 0,
+            y_pixel: 0,
             button: MouseButton::Left,
             modifiers: KeyModifiers::default(),
         })
@@ -213,7 +240,7 @@ impl TestTerm {
 
     fn viewport_lines(&self) -> Vec<Line> {
         let screen = self.screen();
-        let line_count = screen.lines.len();
+        let line_count = screen.len();
         let viewport = self.viewport_offset;
         let phs_rows = screen.physical_rows;
         screen
@@ -302,7 +329,7 @@ fn print_all_lines(term: &Terminal) {
     let screen = term.screen();
 
     println!("whole screen contents are:");
-    for line in screen.lines.iter() {
+    for line in screen.all_lines() {
         println!("[{}]", line.as_str());
     }
 }
@@ -417,6 +444,58 @@ fn cursor_movement_damage() {
     term.assert_dirty_lines(&[0, 1], Some("cursor movement dirties old and new lines"));
 }
 
+/// Ensure that only the cells that changed are reported as damaged,
+/// so that the renderer can avoid redrawing the rest of the line
+#[test]
+fn cell_damage() {
+    let mut term = TestTerm::new(2, 10, 0);
+    term.clean_dirty_lines();
+
+    term.cup(3, 0);
+    term.print("ab");
+    let damage: Vec<_> = term
+        .get_dirty_lines()
+        .iter()
+        .map(|&(i, line, _)| (i, line.damaged_columns()))
+        .collect();
+    assert_eq!(damage, vec![(0, 0..5)]);
+    term.clean_dirty_lines();
+
+    term.cup(1, 1);
+    let damage: Vec<_> = term
+        .get_dirty_lines()
+        .iter()
+        .map(|&(i, line, _)| (i, line.damaged_columns()))
+        .collect();
+    assert_eq!(
+        damage,
+        vec![(0, 5..6), (1, 1..2)],
+        "cursor movement damages only the old and new cells"
+    );
+}
+
+#[test]
+fn unhandled_sequences() {
+    let mut term = TestTerm::new(2, 10, 0);
+    term.set_log_unhandled(false);
+    assert_eq!(term.unhandled_count(), 0);
+
+    term.print("a\x1b[?9999hb");
+    assert_visible_contents(&term, &["ab        ", "          "]);
+    assert_eq!(term.unhandled_count(), 1);
+
+    let recent: Vec<UnhandledSequence> = term.recent_unhandled().cloned().collect();
+    assert_eq!(recent.len(), 1);
+    assert_eq!(recent[0].bytes, b"\x1b[?9999h".to_vec());
+    assert_eq!(
+        recent[0].hex_dump(),
+        vec![format!(
+            "1b 5b 3f 39 39 39 39 68 {}|.[?9999h|",
+            " ".repeat(24)
+        )]
+    );
+}
+
 /// Replicates a bug I initially found via:
 /// $ vim
 /// :help
@@ -481,6 +560,221 @@ fn test_scrollup() {
     assert_all_contents(&term, &["4", "5", "6", "7", "8", " "]);
 }
 
+#[test]
+fn test_scroll_to_bottom() {
+    let mut term = TestTerm::new(2, 1, 4);
+    let mut sink = vec![];
+    term.print("1\n2\n3\n4");
+
+    term.scroll_viewport(-1);
+    term.assert_viewport_contents(&["2", "3"]);
+    term.print("\x1b[m");
+    term.assert_viewport_contents(&["2", "3"]);
+    term.key_down(KeyCode::Char('a'), KeyModifiers::NONE, &mut sink)
+        .unwrap();
+    term.assert_viewport_contents(&["3", "4"]);
+
+    term.set_scroll_to_bottom_on_input(false);
+    term.set_scroll_to_bottom_on_output(true);
+    term.scroll_viewport(-1);
+    term.key_down(KeyCode::Char('a'), KeyModifiers::NONE, &mut sink)
+        .unwrap();
+    term.assert_viewport_contents(&["2", "3"]);
+    term.print("\x1b[m");
+    term.assert_viewport_contents(&["3", "4"]);
+}
+
+#[test]
+fn test_compressed_scrollback() {
+    let mut term = TestTerm::new(2, 3, 300);
+    for i in 0..300 {
+        term.print(format!("{:03}\r\n", i));
+    }
+    term.print("\x1b[1mb");
+
+    let lines = term.screen().all_lines();
+    assert_eq!(lines.len(), 301);
+    assert_eq!(lines[0].as_str(), "000");
+    assert_eq!(lines[299].as_str(), "299");
+    assert_eq!(lines[300].as_str(), "b  ");
+
+    // The lines near the top of the scrollback are held compressed;
+    // scrolling to them must produce the same content
+    term.scroll_viewport(-299);
+    term.assert_viewport_contents(&["000", "001"]);
+    term.scroll_viewport(250);
+    term.assert_viewport_contents(&["250", "251"]);
+    term.scroll_viewport(49);
+    term.assert_viewport_contents(&["299", "b  "]);
+}
+
+#[test]
+fn test_rewrap() {
+    let mut term = TestTerm::new(3, 5, 10);
+    term.print("hello world");
+    assert_visible_contents(&term, &["hello", " worl", "d    "]);
+    term.assert_cursor_pos(1, 2, None);
+
+    // Widening joins the wrapped lines back together
+    term.resize(3, 12);
+    assert_visible_contents(&term, &["hello world ", "            ", "            "]);
+    term.assert_cursor_pos(11, 0, None);
+
+    // Narrowing wraps them again, keeping the cursor after the text
+    term.resize(3, 5);
+    assert_visible_contents(&term, &["hello", " worl", "d    "]);
+    term.assert_cursor_pos(1, 2, None);
+    term.print("!");
+    assert_visible_contents(&term, &["hello", " worl", "d!   "]);
+
+    // Lines ended by an explicit newline are not joined, and a
+    // pending wrap at the right margin is resolved
+    let mut term = TestTerm::new(3, 4, 10);
+    term.print("ab\r\nwxyz");
+    term.resize(3, 6);
+    assert_visible_contents(&term, &["ab    ", "wxyz  ", "      "]);
+    term.assert_cursor_pos(4, 1, None);
+    term.print("!");
+    assert_visible_contents(&term, &["ab    ", "wxyz! ", "      "]);
+}
+
+/// A host that records what the terminal sends to the application
+#[derive(Default)]
+struct RecordingHost {
+    output: Vec<u8>,
+}
+
+impl TerminalHost for RecordingHost {
+    fn set_title(&mut self, _title: &str) {}
+
+    fn set_clipboard(&mut self, _clip: Option<String>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn get_clipboard(&mut self) -> Result<String, Error> {
+        Err(failure::err_msg("no clipboard"))
+    }
+
+    fn writer(&mut self) -> &mut std::io::Write {
+        &mut self.output
+    }
+
+    fn click_link(&mut self, _link: &Arc<Hyperlink>) {}
+}
+
+#[test]
+fn test_alt_screen_wheel() {
+    let mut term = TestTerm::new(3, 5, 10);
+    let wheel_up = MouseEvent {
+        kind: MouseEventKind::Press,
+        x: 0,
+        y: 0,
+        x_pixel: 0,
+        y_pixel: 0,
+        button: MouseButton::WheelUp,
+        modifiers: KeyModifiers::default(),
+    };
+
+    // The primary screen scrolls the viewport rather than sending keys
+    term.print("1\n2\n3\n4");
+    let mut host = RecordingHost::default();
+    term.mouse_event(wheel_up, &mut host).unwrap();
+    assert_eq!(host.output, b"");
+    assert_eq!(term.get_viewport_offset(), 1);
+
+    term.set_mode("?1049", true);
+    let mut host = RecordingHost::default();
+    term.mouse_event(wheel_up, &mut host).unwrap();
+    assert_eq!(host.output, b"\x1b[A");
+
+    term.set_alt_screen_wheel_scroll_lines(3);
+    let mut host = RecordingHost::default();
+    term.mouse_event(wheel_up, &mut host).unwrap();
+    assert_eq!(host.output, b"\x1b[A\x1b[A\x1b[A");
+
+    term.set_alt_screen_wheel_scroll_lines(0);
+    let mut host = RecordingHost::default();
+    term.mouse_event(wheel_up, &mut host).unwrap();
+    assert_eq!(host.output, b"");
+}
+
+#[test]
+fn test_sgr_pixels_mouse() {
+    let mut term = TestTerm::new(3, 5, 0);
+    term.set_mode("?1002", true);
+    term.set_mode("?1016", true);
+
+    // Press in cell (2, 1) at pixel (17, 20)
+    let mut host = RecordingHost::default();
+    term.mouse_event(
+        MouseEvent {
+            kind: MouseEventKind::Press,
+            x: 2,
+            y: 1,
+            x_pixel: 17,
+            y_pixel: 20,
+            button: MouseButton::Left,
+            modifiers: KeyModifiers::default(),
+        },
+        &mut host,
+    )
+    .unwrap();
+    assert_eq!(host.output, b"\x1b[<0;18;21M");
+
+    // Drag within the same cell; only the pixel position changes
+    let mut host = RecordingHost::default();
+    term.mouse_event(
+        MouseEvent {
+            kind: MouseEventKind::Move,
+            x: 2,
+            y: 1,
+            x_pixel: 19,
+            y_pixel: 23,
+            button: MouseButton::None,
+            modifiers: KeyModifiers::default(),
+        },
+        &mut host,
+    )
+    .unwrap();
+    assert_eq!(host.output, b"\x1b[<32;20;24M");
+
+    // Release in cell (4, 2) at pixel (41, 35)
+    let mut host = RecordingHost::default();
+    term.mouse_event(
+        MouseEvent {
+            kind: MouseEventKind::Release,
+            x: 4,
+            y: 2,
+            x_pixel: 41,
+            y_pixel: 35,
+            button: MouseButton::Left,
+            modifiers: KeyModifiers::default(),
+        },
+        &mut host,
+    )
+    .unwrap();
+    assert_eq!(host.output, b"\x1b[<3;42;36m");
+
+    // Cell coordinates are reported once the mode is reset
+    term.set_mode("?1016", false);
+    term.set_mode("?1006", true);
+    let mut host = RecordingHost::default();
+    term.mouse_event(
+        MouseEvent {
+            kind: MouseEventKind::Press,
+            x: 2,
+            y: 1,
+            x_pixel: 17,
+            y_pixel: 20,
+            button: MouseButton::Left,
+            modifiers: KeyModifiers::default(),
+        },
+        &mut host,
+    )
+    .unwrap();
+    assert_eq!(host.output, b"\x1b[<0;3;2M");
+}
+
 #[test]
 fn test_scroll_margins() {
     let mut term = TestTerm::new(3, 1, 10);
@@ -580,3 +874,177 @@ fn test_hyperlinks() {
         Compare::TEXT | Compare::ATTRS,
     );
 }
+
+#[test]
+fn test_hyperlinks_are_interned() {
+    let mut term = TestTerm::new(3, 5, 0);
+    let link = Arc::new(Hyperlink::new("http://example.com"));
+    term.hyperlink(&link);
+    term.print("ab");
+    term.hyperlink_off();
+    term.print("\r\n");
+    term.hyperlink(&link);
+    term.print("cd");
+    term.hyperlink_off();
+
+    let lines = term.screen().visible_lines();
+    let first = lines[0].cells()[0].attrs().hyperlink.clone().unwrap();
+    let second = lines[1].cells()[1].attrs().hyperlink.clone().unwrap();
+    assert_eq!(*first, *link);
+    assert!(Arc::ptr_eq(&first, &second));
+}
+
+#[test]
+fn test_visible_hyperlinks() {
+    let mut term = TestTerm::new(3, 10, 0);
+    let first = Arc::new(Hyperlink::new("http://example.com"));
+    let second = Arc::new(Hyperlink::new("http://example.org"));
+    term.print("x ");
+    term.hyperlink(&first);
+    term.print("ab");
+    term.hyperlink(&second);
+    term.print("cd");
+    term.hyperlink_off();
+    term.print(" y\r\n");
+    term.hyperlink(&first);
+    term.print("e");
+    term.hyperlink_off();
+
+    let links: Vec<(usize, Range<usize>, String, String)> = term
+        .visible_hyperlinks()
+        .into_iter()
+        .map(|l| (l.row, l.columns, l.text, l.link.uri().to_string()))
+        .collect();
+    assert_eq!(
+        links,
+        vec![
+            (0, 2..4, "ab".to_string(), "http://example.com".to_string()),
+            (0, 4..6, "cd".to_string(), "http://example.org".to_string()),
+            (1, 0..1, "e".to_string(), "http://example.com".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_semantic_object_at() {
+    let mut term = TestTerm::new(2, 20, 0);
+    let link = Arc::new(Hyperlink::new("http://example.com"));
+    term.print("echo (hello) ");
+    term.hyperlink(&link);
+    term.print("site");
+    term.hyperlink_off();
+
+    assert_eq!(
+        term.semantic_object_at(0, 1),
+        Some(SemanticObject::Word("echo".to_string()))
+    );
+    assert_eq!(
+        term.semantic_object_at(0, 8),
+        Some(SemanticObject::Word("hello".to_string()))
+    );
+    assert_eq!(
+        term.semantic_object_at(0, 14),
+        Some(SemanticObject::Hyperlink {
+            uri: "http://example.com".to_string(),
+            text: "site".to_string(),
+        })
+    );
+    assert_eq!(term.semantic_object_at(0, 4), None);
+    assert_eq!(term.semantic_object_at(1, 0), None);
+    assert_eq!(term.semantic_object_at(2, 0), None);
+}
+
+#[test]
+fn test_file_download() {
+    let mut term = TestTerm::new(3, 10, 0);
+    // name is "a.txt" and the content is "hello", both base64 encoded
+    term.print("\x1b]1337;File=name=YS50eHQ=;size=5:aGVsbG8=\x07");
+    assert_eq!(
+        term.host.saved_files,
+        vec![(Some("a.txt".to_owned()), b"hello".to_vec())]
+    );
+    // Nothing is displayed
+    assert_visible_contents(&term, &["          ", "          ", "          "]);
+}
+
+#[test]
+fn test_current_dir() {
+    let mut term = TestTerm::new(3, 10, 0);
+    assert_eq!(term.get_current_dir(), None);
+    term.print("\x1b]7;file://host/home/wez\x07");
+    assert_eq!(term.get_current_dir(), Some("file://host/home/wez"));
+    term.print("\x1b]1337;CurrentDir=/tmp/100%\x07");
+    assert_eq!(term.get_current_dir(), Some("file:///tmp/100%25"));
+    assert_eq!(term.unhandled_count(), 0);
+}
+
+#[test]
+fn test_unicode_normalization() {
+    let mut term = TestTerm::new(1, 3, 0);
+    term.print("e\u{301}");
+    assert_visible_contents(&term, &["e\u{301}  "]);
+
+    term.set_normalize_output(true);
+    term.cup(0, 0);
+    term.print("e\u{301}");
+    assert_visible_contents(&term, &["\u{e9}  "]);
+
+    let mut sent = vec![];
+    term.send_paste("e\u{301}", &mut sent).unwrap();
+    assert_eq!(sent, "e\u{301}".as_bytes());
+
+    term.set_normalize_input(true);
+    let mut sent = vec![];
+    term.send_paste("e\u{301}", &mut sent).unwrap();
+    assert_eq!(sent, "\u{e9}".as_bytes());
+
+    let mut sent = vec![];
+    term.send_text("e\u{301}", &mut sent).unwrap();
+    assert_eq!(sent, "\u{e9}".as_bytes());
+
+    // ANGSTROM SIGN is typed as the letter that it is equivalent to
+    let mut sent = vec![];
+    term.key_down(KeyCode::Char('\u{212b}'), KeyModifiers::NONE, &mut sent)
+        .unwrap();
+    assert_eq!(sent, "\u{c5}".as_bytes());
+}
+
+#[test]
+fn test_screen_changed() {
+    let mut term = TestTerm::new(2, 3, 0);
+    term.print("a");
+    assert_eq!(term.host.screen_changes, 1);
+
+    // Output that only changes modes doesn't need a repaint
+    term.clean_dirty_lines();
+    term.set_mode("?1000", true);
+    assert_eq!(term.host.screen_changes, 1);
+
+    term.print("b");
+    assert_eq!(term.host.screen_changes, 2);
+}
+
+#[test]
+fn test_thawed_lines_follow_scrolling() {
+    use crate::screen::Screen;
+
+    let mut screen = Screen::new(2, 4, 200);
+    let region = 0..2;
+    for _ in 0..200 {
+        screen.scroll_up(&region, 1);
+    }
+    assert!(screen.live_line(10).is_none());
+
+    screen.thaw(10..20);
+    assert!((10..20).all(|idx| screen.live_line(idx).is_some()));
+
+    // Scrollback is full, so this drops the oldest line and the
+    // thawed lines move up by one
+    screen.scroll_up(&region, 1);
+    assert!((9..19).all(|idx| screen.live_line(idx).is_some()));
+
+    // Moving the viewport away compresses the lines again
+    screen.thaw(50..60);
+    assert!((0..50).all(|idx| screen.live_line(idx).is_none()));
+    assert!((50..60).all(|idx| screen.live_line(idx).is_some()));
+}
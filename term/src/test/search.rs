@@ -0,0 +1,108 @@
+use super::*;
+use crate::color::RgbColor;
+
+fn found(term: &TestTerm, pattern: Pattern) -> Vec<(StableRowIndex, Range<usize>)> {
+    term.search(&pattern)
+        .unwrap()
+        .into_iter()
+        .map(|result| (result.row, result.columns))
+        .collect()
+}
+
+#[test]
+fn search_scrollback() {
+    let mut term = TestTerm::new(3, 10, 2);
+    term.print("foo bar\r\nFOO\r\n\u{1F480}foo\r\nbaz\r\nfoo");
+
+    assert_eq!(
+        found(&term, Pattern::CaseSensitiveString("foo".into())),
+        vec![(0, 0..3), (2, 2..5), (4, 0..3)]
+    );
+    assert_eq!(
+        found(&term, Pattern::CaseInsensitiveString("foo".into())),
+        vec![(0, 0..3), (1, 0..3), (2, 2..5), (4, 0..3)]
+    );
+    assert_eq!(
+        found(&term, Pattern::Regex("ba.".into())),
+        vec![(0, 4..7), (3, 0..3)]
+    );
+    assert_eq!(
+        found(&term, Pattern::Regex("\u{1F480}f".into())),
+        vec![(2, 0..3)]
+    );
+    assert!(term.search(&Pattern::Regex("(".into())).is_err());
+
+    // Discard the first line; the rows of the others don't change
+    term.print("\r\nqux");
+    assert_eq!(
+        found(&term, Pattern::CaseSensitiveString("foo".into())),
+        vec![(2, 2..5), (4, 0..3)]
+    );
+    assert_eq!(
+        found(&term, Pattern::CaseSensitiveString("qux".into())),
+        vec![(5, 0..3)]
+    );
+    assert_eq!(term.screen().stable_row_to_phys(0), None);
+    assert_eq!(term.screen().stable_row_to_phys(5), Some(4));
+
+    assert!(!term.scroll_to_stable_row(0));
+    assert!(term.scroll_to_stable_row(1));
+    term.assert_viewport_contents(&["FOO       ", "\u{1F480}foo     ", "baz       "]);
+}
+
+#[test]
+fn highlight_search_results() {
+    let mut term = TestTerm::new(3, 10, 2);
+    term.print("foo bar\r\nbaz foo\r\nqux");
+    let red = RgbColor::new(0xff, 0, 0);
+    let blue = RgbColor::new(0, 0, 0xff);
+
+    let matches = term
+        .search(&Pattern::CaseSensitiveString("foo".into()))
+        .unwrap();
+    term.clean_dirty_lines();
+    term.set_highlights(
+        "search",
+        HighlightSet {
+            ranges: matches,
+            fg: red,
+            bg: blue,
+        },
+    );
+    term.assert_dirty_lines(&[0, 1], None);
+    assert_eq!(
+        term.highlights_for_line(1),
+        vec![LineHighlight {
+            columns: 4..7,
+            fg: red,
+            bg: blue,
+        }]
+    );
+
+    // A later set is drawn over the earlier one
+    let current = term.get_highlights("search").unwrap().ranges[1].clone();
+    term.set_highlights(
+        "current",
+        HighlightSet {
+            ranges: vec![current],
+            fg: blue,
+            bg: red,
+        },
+    );
+    let highlights = term.highlights_for_line(1);
+    assert_eq!(
+        highlight::highlight_colors(&highlights, 5),
+        Some((blue, red))
+    );
+    assert_eq!(highlight::highlight_colors(&highlights, 3), None);
+
+    // Highlights stay with their text as it scrolls
+    term.print("\r\nquux");
+    assert_eq!(term.highlights_for_line(0).len(), 2);
+
+    term.clean_dirty_lines();
+    term.clear_highlights("search");
+    term.assert_dirty_lines(&[0], None);
+    assert_eq!(term.highlights_for_line(0).len(), 1);
+    assert_eq!(term.get_highlights("search"), None);
+}
@@ -41,6 +41,27 @@ fn drag_selection() {
     assert_eq!(term.get_clipboard().unwrap(), "world\n\u{1F480}sk");
 }
 
+/// Test that concealed text is only copied when allowed
+#[test]
+fn concealed_selection() {
+    let mut term = TestTerm::new(3, 12, 0);
+    term.print("ans \x1b[8msecret\x1b[28m!");
+
+    term.drag_select(0, 0, 10, 0);
+    assert_eq!(term.get_clipboard().unwrap(), "ans       !");
+
+    term.clean_dirty_lines();
+    term.set_reveal_concealed(true);
+    assert!(term.has_dirty_lines());
+    term.drag_select(0, 0, 10, 0);
+    assert_eq!(term.get_clipboard().unwrap(), "ans secret!");
+
+    term.set_reveal_concealed(false);
+    term.set_copy_concealed_text(true);
+    term.drag_select(0, 0, 10, 0);
+    assert_eq!(term.get_clipboard().unwrap(), "ans secret!");
+}
+
 /// Test double click to select a word
 #[test]
 fn double_click_selection() {
@@ -0,0 +1,59 @@
+//! Compares building the clusters of each line into a fresh vector
+//! with reusing a scratch vector, as the renderer does.
+use criterion::{criterion_group, criterion_main, Criterion};
+use termwiz::cell::{Cell, CellAttributes, Intensity};
+use termwiz::color::ColorAttribute;
+use termwiz::surface::Line;
+
+const WIDTH: usize = 300;
+const HEIGHT: usize = 100;
+
+/// Produce lines of text whose attributes vary along each line,
+/// so that every line has several clusters
+fn make_lines() -> Vec<Line> {
+    (0..HEIGHT)
+        .map(|row| {
+            let mut line = Line::with_width(WIDTH);
+            for col in 0..WIDTH / 10 {
+                let n = row * WIDTH + col;
+                let mut attrs = CellAttributes::default();
+                attrs
+                    .set_intensity(if n % 3 == 0 {
+                        Intensity::Bold
+                    } else {
+                        Intensity::Normal
+                    })
+                    .set_foreground(ColorAttribute::PaletteIndex((n % 8) as u8));
+                for (i, c) in format!("{:<10}", n).chars().enumerate() {
+                    line.set_cell(col * 10 + i, Cell::new(c, attrs.clone()));
+                }
+            }
+            line
+        })
+        .collect()
+}
+
+fn cluster(c: &mut Criterion) {
+    let lines = make_lines();
+    c.bench_function("cluster 300x100", move |bench| {
+        bench.iter(|| {
+            for line in &lines {
+                criterion::black_box(line.cluster());
+            }
+        })
+    });
+
+    let lines = make_lines();
+    c.bench_function("cluster_into 300x100", move |bench| {
+        let mut clusters = vec![];
+        bench.iter(|| {
+            for line in &lines {
+                line.cluster_into(&mut clusters);
+                criterion::black_box(&clusters);
+            }
+        })
+    });
+}
+
+criterion_group!(benches, cluster);
+criterion_main!(benches);
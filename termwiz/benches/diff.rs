@@ -0,0 +1,52 @@
+//! Compares the serial and parallel implementations of surface
+//! differencing on a large surface.
+use criterion::{criterion_group, criterion_main, Criterion};
+use termwiz::cell::{AttributeChange, Intensity};
+use termwiz::color::ColorAttribute;
+use termwiz::surface::{Change, Position, Surface};
+
+const WIDTH: usize = 300;
+const HEIGHT: usize = 100;
+
+/// Fill a surface with text, varying the attributes along each
+/// line so that the diff has to emit attribute changes too
+fn make_surface(seed: usize) -> Surface {
+    let mut s = Surface::new(WIDTH, HEIGHT);
+    for row in 0..HEIGHT {
+        s.add_change(Change::CursorPosition {
+            x: Position::Absolute(0),
+            y: Position::Absolute(row),
+        });
+        for col in 0..WIDTH / 10 {
+            let n = row * WIDTH + col + seed;
+            s.add_change(AttributeChange::Intensity(if n % 3 == 0 {
+                Intensity::Bold
+            } else {
+                Intensity::Normal
+            }));
+            s.add_change(AttributeChange::Foreground(ColorAttribute::PaletteIndex(
+                (n % 8) as u8,
+            )));
+            s.add_change(format!("{:<10}", n));
+        }
+    }
+    s
+}
+
+fn diff(c: &mut Criterion) {
+    let a = make_surface(0);
+    let b = make_surface(7);
+
+    c.bench_function("diff_rows serial 300x100", move |bench| {
+        bench.iter(|| a.diff_region_with_parallelism(0, 0, WIDTH, HEIGHT, &b, 0, 0, false))
+    });
+
+    let a = make_surface(0);
+    let b = make_surface(7);
+    c.bench_function("diff_rows parallel 300x100", move |bench| {
+        bench.iter(|| a.diff_region_with_parallelism(0, 0, WIDTH, HEIGHT, &b, 0, 0, true))
+    });
+}
+
+criterion_group!(benches, diff);
+criterion_main!(benches);
@@ -0,0 +1,24 @@
+//! This example shows how to read lines of input with `LineEditor`.
+//! Previously entered lines can be recalled with the up arrow, and
+//! CTRL-R searches back through them.
+extern crate failure;
+extern crate termwiz;
+
+use failure::Error;
+use termwiz::caps::Capabilities;
+use termwiz::lineedit::{LineEditor, LineEditorHost, NopLineEditorHost};
+use termwiz::terminal::new_terminal;
+
+fn main() -> Result<(), Error> {
+    let caps = Capabilities::new_from_env()?;
+    let terminal = new_terminal(caps)?;
+    let mut editor = LineEditor::new(terminal);
+    let mut host = NopLineEditorHost::default();
+
+    while let Some(line) = editor.read_line(&mut host)? {
+        println!("read line: {:?}\r", line);
+        host.history().add(&line);
+    }
+
+    Ok(())
+}
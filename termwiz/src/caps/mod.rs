@@ -107,6 +107,13 @@ pub struct ProbeHints {
 
     /// Whether mouse support is present and should be used
     mouse_reporting: Option<bool>,
+
+    /// Whether key release events should be reported.  The default
+    /// is to report them when the terminal is known to be able to
+    /// provide them, which is only the case for the Windows console.
+    /// On unix, enabling this requests the kitty keyboard protocol,
+    /// which must be supported by the terminal.
+    key_up_events: Option<bool>,
 }
 
 /// Describes the level of color support available
@@ -142,6 +149,7 @@ pub struct Capabilities {
     terminfo_db: Option<terminfo::Database>,
     bracketed_paste: bool,
     mouse_reporting: bool,
+    key_up_events: bool,
 }
 
 impl Capabilities {
@@ -249,6 +257,7 @@ impl Capabilities {
 
         let bracketed_paste = hints.bracketed_paste.unwrap_or(true);
         let mouse_reporting = hints.mouse_reporting.unwrap_or(true);
+        let key_up_events = hints.key_up_events.unwrap_or(cfg!(windows));
 
         Ok(Self {
             color_level,
@@ -259,6 +268,7 @@ impl Capabilities {
             terminfo_db: hints.terminfo_db,
             bracketed_paste,
             mouse_reporting,
+            key_up_events,
         })
     }
 
@@ -304,6 +314,14 @@ impl Capabilities {
     pub fn mouse_reporting(&self) -> bool {
         self.mouse_reporting
     }
+
+    /// Whether `InputEvent::KeyUp` events will be reported when keys
+    /// are released.  Terminals that communicate via escape sequences
+    /// only report key releases via the kitty keyboard protocol, so
+    /// this is false for those unless enabled via `ProbeHints`.
+    pub fn key_up_events(&self) -> bool {
+        self.key_up_events
+    }
 }
 
 #[cfg(test)]
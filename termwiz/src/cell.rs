@@ -12,9 +12,15 @@ use unicode_width::UnicodeWidthStr;
 /// Holds the attributes for a cell.
 /// Most style attributes are stored internally as part of a bitfield
 /// to reduce per-cell overhead.
+/// Only hyperlinks are shared between cells (see `HyperlinkInterner`);
+/// the bitfield and colors are small enough to be kept inline rather
+/// than referenced by an id into an interning table.  Such an id would
+/// require that table to be reachable from everything that consumes a
+/// `Cell` via `Cell::attrs`, including lines that are serialized on
+/// their own by the mux protocol.
 /// The setter methods return a mutable self reference so that they can
 /// be chained together.
-#[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Eq, Serialize, Deserialize)]
 pub struct CellAttributes {
     attributes: u16,
     /// The foreground color
@@ -27,6 +33,24 @@ pub struct CellAttributes {
     pub image: Option<Box<ImageCell>>,
 }
 
+/// Comparing attributes is on the hot path of clustering and rendering
+/// lines.  Hyperlinks are typically shared via `HyperlinkInterner`, so
+/// they are compared by pointer before falling back to comparing the
+/// link contents.
+impl PartialEq for CellAttributes {
+    fn eq(&self, other: &Self) -> bool {
+        self.attributes == other.attributes
+            && self.foreground == other.foreground
+            && self.background == other.background
+            && match (&self.hyperlink, &other.hyperlink) {
+                (None, None) => true,
+                (Some(a), Some(b)) => Arc::ptr_eq(a, b) || **a == **b,
+                _ => false,
+            }
+            && self.image == other.image
+    }
+}
+
 /// Define getter and setter for the attributes bitfield.
 /// The first form is for a simple boolean value stored in
 /// a single bit.  The $bitnum parameter specifies which bit.
@@ -140,6 +164,7 @@ impl CellAttributes {
     bitfield!(reverse, set_reverse, 7);
     bitfield!(strikethrough, set_strikethrough, 8);
     bitfield!(invisible, set_invisible, 9);
+    bitfield!(overline, set_overline, 10);
 
     /// Returns true if the attribute bits in both objects are equal.
     /// This can be used to cheaply test whether the styles of the two
@@ -307,6 +332,7 @@ pub enum AttributeChange {
     Reverse(bool),
     StrikeThrough(bool),
     Invisible(bool),
+    Overline(bool),
     Foreground(ColorAttribute),
     Background(ColorAttribute),
     Hyperlink(Option<Arc<Hyperlink>>),
@@ -16,37 +16,40 @@ impl CellCluster {
     /// Compute the list of CellClusters from a set of visible cells.
     /// The input is typically the result of calling `Line::visible_cells()`.
     pub fn make_cluster<'a>(iter: impl Iterator<Item = (usize, &'a Cell)>) -> Vec<CellCluster> {
-        let mut last_cluster = None;
         let mut clusters = Vec::new();
+        Self::make_cluster_into(iter, &mut clusters);
+        clusters
+    }
+
+    /// Like `make_cluster`, but replaces the contents of `clusters`,
+    /// reusing the memory held by the clusters already in it.  This
+    /// avoids heap traffic when the clusters of many lines are computed
+    /// in succession, as happens when rendering.
+    pub fn make_cluster_into<'a>(
+        iter: impl Iterator<Item = (usize, &'a Cell)>,
+        clusters: &mut Vec<CellCluster>,
+    ) {
+        let mut count = 0;
 
         for (cell_idx, c) in iter {
             let cell_str = c.str();
 
-            last_cluster = match last_cluster.take() {
-                None => {
-                    // Start new cluster
-                    Some(CellCluster::new(c.attrs().clone(), cell_str, cell_idx))
-                }
-                Some(mut last) => {
-                    if last.attrs != *c.attrs() {
-                        // Flush pending cluster and start a new one
-                        clusters.push(last);
-                        Some(CellCluster::new(c.attrs().clone(), cell_str, cell_idx))
-                    } else {
-                        // Add to current cluster
-                        last.add(cell_str, cell_idx);
-                        Some(last)
-                    }
-                }
-            };
-        }
+            if count > 0 && clusters[count - 1].attrs == *c.attrs() {
+                // Add to current cluster
+                clusters[count - 1].add(cell_str, cell_idx);
+                continue;
+            }
 
-        if let Some(cluster) = last_cluster {
-            // Don't forget to include any pending cluster on the final step!
-            clusters.push(cluster);
+            // Start new cluster
+            if count < clusters.len() {
+                clusters[count].reset(c.attrs(), cell_str, cell_idx);
+            } else {
+                clusters.push(CellCluster::new(c.attrs().clone(), cell_str, cell_idx));
+            }
+            count += 1;
         }
 
-        clusters
+        clusters.truncate(count);
     }
 
     /// Start off a new cluster with some initial data
@@ -62,6 +65,15 @@ impl CellCluster {
         }
     }
 
+    /// Make this cluster hold only the supplied data, retaining
+    /// its allocations
+    fn reset(&mut self, attrs: &CellAttributes, text: &str, cell_idx: usize) {
+        self.attrs = attrs.clone();
+        self.text.clear();
+        self.byte_to_cell_idx.clear();
+        self.add(text, cell_idx);
+    }
+
     /// Add to this cluster
     fn add(&mut self, text: &str, cell_idx: usize) {
         for _ in 0..text.len() {
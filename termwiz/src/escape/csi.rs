@@ -163,6 +163,22 @@ pub enum DeviceAttributes {
     Vt420(DeviceAttributeFlags),
 }
 
+/// DECSSDT - https://vt100.net/docs/vt510-rm/DECSSDT.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+pub enum StatusDisplayType {
+    None = 0,
+    Indicator = 1,
+    /// A status line that the host can write to with DECSASD
+    HostWritable = 2,
+}
+
+/// DECSASD - https://vt100.net/docs/vt510-rm/DECSASD.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+pub enum ActiveStatusDisplay {
+    MainDisplay = 0,
+    StatusLine = 1,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Device {
     DeviceAttributes(DeviceAttributes),
@@ -170,7 +186,15 @@ pub enum Device {
     SoftReset,
     RequestPrimaryDeviceAttributes,
     RequestSecondaryDeviceAttributes,
+    /// XTVERSION - asks for the name and version of the terminal
+    RequestTerminalNameAndVersion,
     StatusReport,
+    /// DECSSDT - selects the kind of status line shown below the
+    /// main display
+    SelectStatusDisplayType(StatusDisplayType),
+    /// DECSASD - selects whether output is sent to the main display
+    /// or to the host writable status line
+    SelectActiveStatusDisplay(ActiveStatusDisplay),
 }
 
 impl Display for Device {
@@ -187,7 +211,14 @@ impl Display for Device {
             Device::SoftReset => write!(f, "!p")?,
             Device::RequestPrimaryDeviceAttributes => write!(f, "c")?,
             Device::RequestSecondaryDeviceAttributes => write!(f, ">c")?,
+            Device::RequestTerminalNameAndVersion => write!(f, ">q")?,
             Device::StatusReport => write!(f, "5n")?,
+            Device::SelectStatusDisplayType(t) => {
+                write!(f, "{}$~", t.to_u8().ok_or_else(|| FmtError)?)?
+            }
+            Device::SelectActiveStatusDisplay(d) => {
+                write!(f, "{}$}}", d.to_u8().ok_or_else(|| FmtError)?)?
+            }
         };
         Ok(())
     }
@@ -412,6 +443,12 @@ pub enum Mode {
     RestoreDecPrivateMode(DecPrivateMode),
     SetMode(TerminalMode),
     ResetMode(TerminalMode),
+    /// XTMODKEYS - sets how keys with modifiers are reported.
+    /// A value of None restores the initial value of the resource.
+    XtermKeyMode {
+        resource: XtermKeyModifierResource,
+        value: Option<i64>,
+    },
 }
 
 impl Display for Mode {
@@ -441,6 +478,13 @@ impl Display for Mode {
             Mode::RestoreDecPrivateMode(mode) => emit!("r", mode),
             Mode::SetMode(mode) => emit_mode!("h", mode),
             Mode::ResetMode(mode) => emit_mode!("l", mode),
+            Mode::XtermKeyMode { resource, value } => {
+                let resource = resource.to_u8().ok_or_else(|| FmtError)?;
+                match value {
+                    Some(value) => write!(f, ">{};{}m", resource, value),
+                    None => write!(f, ">{}m", resource),
+                }
+            }
         }
     }
 }
@@ -456,6 +500,8 @@ pub enum DecPrivateModeCode {
     ApplicationCursorKeys = 1,
     StartBlinkingCursor = 12,
     ShowCursor = 25,
+    /// DECLRMM - enables DECSLRM, which sets the left and right margins
+    LeftRightMarginMode = 69,
     /// Enable mouse button press/release reporting
     MouseTracking = 1000,
     /// Warning: this requires a cooperative and timely response from
@@ -465,13 +511,33 @@ pub enum DecPrivateModeCode {
     ButtonEventMouse = 1002,
     /// Enable mouse motion, button press/release and drag reporting
     AnyEventMouse = 1003,
+    /// Report focus in and out events
+    FocusTracking = 1004,
     /// Use extended coordinate system in mouse reporting.  Does not
     /// enable mouse reporting itself, it just controls how reports
     /// will be encoded.
     SGRMouse = 1006,
+    /// Like SGRMouse, but the coordinates are reported in pixels
+    /// rather than character cells
+    SGRPixelsMouse = 1016,
     ClearAndEnableAlternateScreen = 1049,
     EnableAlternateScreen = 47,
     BracketedPaste = 2004,
+    /// The application is updating the screen and would like it to
+    /// be displayed only once the update is complete
+    SynchronizedOutput = 2026,
+    /// Report key presses and releases as win32 key events; this is
+    /// requested by ConPTY
+    Win32InputMode = 9001,
+}
+
+/// The keyboard resources that may be adjusted by XTMODKEYS
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+pub enum XtermKeyModifierResource {
+    Keyboard = 0,
+    CursorKeys = 1,
+    FunctionKeys = 2,
+    OtherKeys = 4,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -617,6 +683,15 @@ pub enum Cursor {
         bottom: OneBased,
     },
 
+    /// DECSLRM - Set left and right margins.
+    /// Only works when DECLRMM is enabled.  With no parameters this is
+    /// indistinguishable from SCP, so it is parsed as `SaveCursor` and
+    /// the terminal must interpret it according to DECLRMM.
+    SetLeftAndRightMargins {
+        left: OneBased,
+        right: OneBased,
+    },
+
     CursorStyle(CursorStyle),
 }
 
@@ -807,6 +882,9 @@ impl Display for Cursor {
                     write!(f, "{};{}r", top, bottom)?;
                 }
             }
+            Cursor::SetLeftAndRightMargins { left, right } => {
+                write!(f, "{};{}s", left, right)?;
+            }
             Cursor::RequestActivePositionReport => write!(f, "6n")?,
             Cursor::SaveCursor => write!(f, "s")?,
             Cursor::RestoreCursor => write!(f, "u")?,
@@ -969,6 +1047,7 @@ pub enum Sgr {
     Inverse(bool),
     Invisible(bool),
     StrikeThrough(bool),
+    Overline(bool),
     Font(Font),
     Foreground(ColorSpec),
     Background(ColorSpec),
@@ -1013,6 +1092,8 @@ impl Display for Sgr {
             Sgr::Invisible(false) => code!(InvisibleOff),
             Sgr::StrikeThrough(true) => code!(StrikeThroughOn),
             Sgr::StrikeThrough(false) => code!(StrikeThroughOff),
+            Sgr::Overline(true) => code!(OverlineOn),
+            Sgr::Overline(false) => code!(OverlineOff),
             Sgr::Font(Font::Default) => code!(DefaultFont),
             Sgr::Font(Font::Alternate(1)) => code!(AltFont1),
             Sgr::Font(Font::Alternate(2)) => code!(AltFont2),
@@ -1241,8 +1322,11 @@ impl<'a> CSIParser<'a> {
             ('m', &[]) => self.sgr(params).map(CSI::Sgr),
             ('n', &[]) => self.dsr(params),
             ('q', &[b' ']) => self.cursor_style(params),
+            ('q', &[b'>']) => self
+                .req_terminal_name_and_version(params)
+                .map(|dev| CSI::Device(Box::new(dev))),
             ('r', &[]) => self.decstbm(params),
-            ('s', &[]) => noparams!(Cursor, SaveCursor, params),
+            ('s', &[]) => self.decslrm(params),
             ('t', &[]) => self.window(params).map(CSI::Window),
             ('u', &[]) => noparams!(Cursor, RestoreCursor, params),
             ('y', &[b'*']) => {
@@ -1266,6 +1350,12 @@ impl<'a> CSIParser<'a> {
             }
 
             ('p', &[b'!']) => Ok(CSI::Device(Box::new(Device::SoftReset))),
+            ('~', &[b'$']) => self
+                .status_display(params)
+                .map(|t| CSI::Device(Box::new(Device::SelectStatusDisplayType(t)))),
+            ('}', &[b'$']) => self
+                .status_display(params)
+                .map(|d| CSI::Device(Box::new(Device::SelectActiveStatusDisplay(d)))),
 
             ('h', &[b'?']) => self
                 .dec(params)
@@ -1281,6 +1371,7 @@ impl<'a> CSIParser<'a> {
                 .map(|mode| CSI::Mode(Mode::SaveDecPrivateMode(mode))),
 
             ('m', &[b'<']) | ('M', &[b'<']) => self.mouse_sgr1006(params).map(CSI::Mouse),
+            ('m', &[b'>']) => self.xterm_key_modifier(params).map(CSI::Mode),
 
             ('c', &[]) => self
                 .req_primary_device_attributes(params)
@@ -1321,6 +1412,16 @@ impl<'a> CSIParser<'a> {
         }
     }
 
+    /// Parses the single parameter of DECSSDT and DECSASD, which
+    /// defaults to 0
+    fn status_display<T: num::FromPrimitive>(&mut self, params: &'a [i64]) -> Result<T, ()> {
+        match params {
+            [] => num::FromPrimitive::from_i64(0).ok_or(()),
+            [p] => num::FromPrimitive::from_i64(*p).ok_or(()),
+            _ => Err(()),
+        }
+    }
+
     fn dsr(&mut self, params: &'a [i64]) -> Result<CSI, ()> {
         if params == [5] {
             Ok(self.advance_by(1, params, CSI::Device(Box::new(Device::StatusReport))))
@@ -1351,6 +1452,23 @@ impl<'a> CSIParser<'a> {
         }
     }
 
+    fn decslrm(&mut self, params: &'a [i64]) -> Result<CSI, ()> {
+        if params.is_empty() {
+            Ok(CSI::Cursor(Cursor::SaveCursor))
+        } else if params.len() == 2 {
+            Ok(self.advance_by(
+                2,
+                params,
+                CSI::Cursor(Cursor::SetLeftAndRightMargins {
+                    left: OneBased::from_esc_param(params[0])?,
+                    right: OneBased::from_esc_param(params[1])?,
+                }),
+            ))
+        } else {
+            Err(())
+        }
+    }
+
     fn req_primary_device_attributes(&mut self, params: &'a [i64]) -> Result<Device, ()> {
         if params == [] {
             Ok(Device::RequestPrimaryDeviceAttributes)
@@ -1371,6 +1489,16 @@ impl<'a> CSIParser<'a> {
         }
     }
 
+    fn req_terminal_name_and_version(&mut self, params: &'a [i64]) -> Result<Device, ()> {
+        if params == [] {
+            Ok(Device::RequestTerminalNameAndVersion)
+        } else if params == [0] {
+            Ok(self.advance_by(1, params, Device::RequestTerminalNameAndVersion))
+        } else {
+            Err(())
+        }
+    }
+
     fn secondary_device_attributes(&mut self, params: &'a [i64]) -> Result<Device, ()> {
         if params == [1, 0] {
             Ok(self.advance_by(
@@ -1487,6 +1615,15 @@ impl<'a> CSIParser<'a> {
         }
     }
 
+    fn xterm_key_modifier(&mut self, params: &'a [i64]) -> Result<Mode, ()> {
+        if params.is_empty() || params.len() > 2 {
+            return Err(());
+        }
+        let resource = num::FromPrimitive::from_i64(params[0]).ok_or(())?;
+        let value = params.get(1).cloned();
+        Ok(self.advance_by(params.len(), params, Mode::XtermKeyMode { resource, value }))
+    }
+
     fn parse_sgr_color(&mut self, params: &'a [i64]) -> Result<ColorSpec, ()> {
         if params.len() >= 5 && params[1] == 2 {
             let red = to_u8(params[2])?;
@@ -1656,6 +1793,8 @@ impl<'a> CSIParser<'a> {
                     SgrCode::InvisibleOff => one!(Sgr::Invisible(false)),
                     SgrCode::StrikeThroughOn => one!(Sgr::StrikeThrough(true)),
                     SgrCode::StrikeThroughOff => one!(Sgr::StrikeThrough(false)),
+                    SgrCode::OverlineOn => one!(Sgr::Overline(true)),
+                    SgrCode::OverlineOff => one!(Sgr::Overline(false)),
                     SgrCode::DefaultFont => one!(Sgr::Font(Font::Default)),
                     SgrCode::AltFont1 => one!(Sgr::Font(Font::Alternate(1))),
                     SgrCode::AltFont2 => one!(Sgr::Font(Font::Alternate(2))),
@@ -1723,6 +1862,8 @@ pub enum SgrCode {
     BackgroundCyan = 46,
     BackgroundWhite = 47,
     BackgroundDefault = 49,
+    OverlineOn = 53,
+    OverlineOff = 55,
 
     ForegroundBrightBlack = 90,
     ForegroundBrightRed = 91,
@@ -1881,6 +2022,21 @@ mod test {
         );
     }
 
+    #[test]
+    fn lines() {
+        assert_eq!(
+            parse('m', &[53, 21], "\x1b[53m\x1b[21m"),
+            vec![
+                CSI::Sgr(Sgr::Overline(true)),
+                CSI::Sgr(Sgr::Underline(Underline::Double)),
+            ]
+        );
+        assert_eq!(
+            parse('m', &[55], "\x1b[55m"),
+            vec![CSI::Sgr(Sgr::Overline(false))]
+        );
+    }
+
     #[test]
     fn edit() {
         assert_eq!(
@@ -1999,4 +2155,77 @@ mod test {
             )))]
         );
     }
+
+    #[test]
+    fn xtversion() {
+        assert_eq!(
+            parse_int('q', &[], b'>', "\x1b[>q"),
+            vec![CSI::Device(Box::new(Device::RequestTerminalNameAndVersion))]
+        );
+        assert_eq!(
+            parse_int('q', &[0], b'>', "\x1b[>q"),
+            vec![CSI::Device(Box::new(Device::RequestTerminalNameAndVersion))]
+        );
+    }
+
+    #[test]
+    fn status_display() {
+        assert_eq!(
+            parse_int('~', &[2], b'$', "\x1b[2$~"),
+            vec![CSI::Device(Box::new(Device::SelectStatusDisplayType(
+                StatusDisplayType::HostWritable
+            )))]
+        );
+        assert_eq!(
+            parse_int('}', &[1], b'$', "\x1b[1$}"),
+            vec![CSI::Device(Box::new(Device::SelectActiveStatusDisplay(
+                ActiveStatusDisplay::StatusLine
+            )))]
+        );
+        assert_eq!(
+            parse_int('}', &[], b'$', "\x1b[0$}"),
+            vec![CSI::Device(Box::new(Device::SelectActiveStatusDisplay(
+                ActiveStatusDisplay::MainDisplay
+            )))]
+        );
+    }
+
+    #[test]
+    fn xtmodkeys() {
+        assert_eq!(
+            parse_int('m', &[4, 2], b'>', "\x1b[>4;2m"),
+            vec![CSI::Mode(Mode::XtermKeyMode {
+                resource: XtermKeyModifierResource::OtherKeys,
+                value: Some(2),
+            })]
+        );
+        assert_eq!(
+            parse_int('m', &[4], b'>', "\x1b[>4m"),
+            vec![CSI::Mode(Mode::XtermKeyMode {
+                resource: XtermKeyModifierResource::OtherKeys,
+                value: None,
+            })]
+        );
+    }
+
+    #[test]
+    fn decslrm() {
+        assert_eq!(
+            parse('s', &[], "\x1b[s"),
+            vec![CSI::Cursor(Cursor::SaveCursor)]
+        );
+        assert_eq!(
+            parse('s', &[2, 10], "\x1b[2;10s"),
+            vec![CSI::Cursor(Cursor::SetLeftAndRightMargins {
+                left: OneBased::new(2),
+                right: OneBased::new(10),
+            })]
+        );
+        assert_eq!(
+            parse_int('h', &[69], b'?', "\x1b[?69h"),
+            vec![CSI::Mode(Mode::SetDecPrivateMode(DecPrivateMode::Code(
+                DecPrivateModeCode::LeftRightMarginMode
+            )))]
+        );
+    }
 }
@@ -17,6 +17,9 @@ pub enum OperatingSystemCommand {
     QuerySelection(Selection),
     SetSelection(Selection, String),
     SystemNotification(String),
+    /// The working directory of the program, as a `file://` url that
+    /// includes the host name
+    CurrentWorkingDirectory(String),
     ITermProprietary(ITermProprietary),
 
     Unspecified(Vec<Vec<u8>>),
@@ -149,6 +152,7 @@ impl OperatingSystemCommand {
             SetHyperlink => Ok(OperatingSystemCommand::SetHyperlink(Hyperlink::parse(osc)?)),
             ManipulateSelectionData => Self::parse_selection(osc),
             SystemNotification => single_string!(SystemNotification),
+            SetCurrentWorkingDirectory => single_string!(CurrentWorkingDirectory),
             ITermProprietary => {
                 self::ITermProprietary::parse(osc).map(OperatingSystemCommand::ITermProprietary)
             }
@@ -218,6 +222,7 @@ impl Display for OperatingSystemCommand {
             QuerySelection(s) => write!(f, "52;{};?", s)?,
             SetSelection(s, val) => write!(f, "52;{};{}", s, base64::encode(val))?,
             SystemNotification(s) => write!(f, "9;{}", s)?,
+            CurrentWorkingDirectory(url) => single_string!(SetCurrentWorkingDirectory, url),
             ITermProprietary(i) => i.fmt(f)?,
         };
         write!(f, "\x07")?;
@@ -665,6 +670,17 @@ mod test {
         );
     }
 
+    #[test]
+    fn cwd() {
+        assert_eq!(
+            parse(
+                &["7", "file://host/home/wez"],
+                "\x1b]7;file://host/home/wez\x07"
+            ),
+            OperatingSystemCommand::CurrentWorkingDirectory("file://host/home/wez".into())
+        );
+    }
+
     #[test]
     fn iterm() {
         assert_eq!(
@@ -11,7 +11,7 @@ use serde_derive::*;
 use std::collections::HashMap;
 use std::fmt::{Display, Error as FmtError, Formatter};
 use std::ops::Range;
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Hyperlink {
@@ -113,6 +113,77 @@ impl Display for Hyperlink {
     }
 }
 
+/// Hyperlinks are referenced from the attributes of every cell that
+/// they cover.  A program such as `ls --hyperlink` emits the same links
+/// over and over, and without interning each of those would hold its own
+/// copy of the uri and params for as long as it remains in the scrollback.
+/// The `HyperlinkInterner` hands out a shared `Arc` for links that are
+/// equal, which both reduces memory and allows cell attributes to be
+/// compared by pointer rather than by value in the common case.
+/// Links are held weakly so that the table doesn't keep them alive after
+/// the last cell that refers to them has been discarded.
+#[derive(Debug, Default)]
+pub struct HyperlinkInterner {
+    links: HashMap<String, Vec<Weak<Hyperlink>>>,
+    inserts_since_prune: usize,
+}
+
+/// Dead entries are swept from the table after this many insertions
+const PRUNE_INTERVAL: usize = 1024;
+
+impl HyperlinkInterner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a shared reference to a link equal to `link`
+    pub fn intern(&mut self, link: Hyperlink) -> Arc<Hyperlink> {
+        if let Some(candidates) = self.links.get(&link.uri) {
+            for candidate in candidates {
+                if let Some(existing) = candidate.upgrade() {
+                    if *existing == link {
+                        return existing;
+                    }
+                }
+            }
+        }
+
+        self.inserts_since_prune += 1;
+        if self.inserts_since_prune >= PRUNE_INTERVAL {
+            self.prune();
+        }
+
+        let link = Arc::new(link);
+        self.links
+            .entry(link.uri.clone())
+            .or_insert_with(Vec::new)
+            .push(Arc::downgrade(&link));
+        link
+    }
+
+    /// Remove entries for links that are no longer referenced
+    fn prune(&mut self) {
+        self.inserts_since_prune = 0;
+        for candidates in self.links.values_mut() {
+            candidates.retain(|link| link.upgrade().is_some());
+        }
+        self.links.retain(|_, candidates| !candidates.is_empty());
+    }
+
+    /// Returns the number of distinct links that are still referenced
+    pub fn len(&self) -> usize {
+        self.links
+            .values()
+            .flat_map(|candidates| candidates.iter())
+            .filter(|link| link.upgrade().is_some())
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
 /// In addition to handling explicit escape sequences to enable
 /// hyperlinks, we also support defining rules that match text
 /// from screen lines and generate implicit hyperlinks.  This
@@ -202,6 +273,16 @@ impl Rule {
         })
     }
 
+    /// Returns the regex that the rule matches, as it was written
+    pub fn pattern(&self) -> &str {
+        self.regex.as_str()
+    }
+
+    /// Returns the format string used to produce the URL
+    pub fn format(&self) -> &str {
+        &self.format
+    }
+
     /// Given a line of text from the terminal screen, and a set of
     /// rules, return the set of RuleMatches.
     pub fn match_hyperlinks(line: &str, rules: &[Rule]) -> Vec<RuleMatch> {
@@ -264,4 +345,26 @@ mod test {
             ]
         );
     }
+
+    #[test]
+    fn intern() {
+        let mut interner = HyperlinkInterner::new();
+        let a = interner.intern(Hyperlink::new("http://example.com"));
+        let b = interner.intern(Hyperlink::new("http://example.com"));
+        assert!(Arc::ptr_eq(&a, &b));
+
+        // Same uri, but different params
+        let c = interner.intern(Hyperlink::new_with_id("http://example.com", "c"));
+        assert!(!Arc::ptr_eq(&a, &c));
+        assert_eq!(interner.len(), 2);
+
+        drop(c);
+        assert_eq!(interner.len(), 1);
+        drop(a);
+        drop(b);
+        assert!(interner.is_empty());
+
+        interner.prune();
+        assert!(interner.links.is_empty());
+    }
 }
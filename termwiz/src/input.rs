@@ -40,6 +40,10 @@ bitflags! {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum InputEvent {
     Key(KeyEvent),
+    /// A key was released.  These are only generated when the
+    /// terminal is able to report them; see
+    /// `Capabilities::key_up_events`.
+    KeyUp(KeyEvent),
     Mouse(MouseEvent),
     /// Detected that the user has resized the terminal
     Resized {
@@ -71,7 +75,7 @@ pub struct KeyEvent {
 /// Which key is pressed.  Not all of these are probable to appear
 /// on most systems.  A lot of this list is @wez trawling docs and
 /// making an entry for things that might be possible in this first pass.
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum KeyCode {
     /// The decoded unicode character
     Char(char),
@@ -173,6 +177,7 @@ pub struct InputParser {
     key_map: KeyMap<InputEvent>,
     buf: ReadBuffer,
     state: InputState,
+    report_key_up: bool,
 }
 
 #[cfg(windows)]
@@ -208,8 +213,8 @@ mod windows {
             event: &KEY_EVENT_RECORD,
             callback: &mut F,
         ) {
-            // TODO: do we want downs instead of ups?
-            if event.bKeyDown == 0 {
+            let key_down = event.bKeyDown != 0;
+            if !key_down && !self.report_key_up {
                 return;
             }
 
@@ -219,7 +224,16 @@ mod windows {
 
                     match self.key_map.lookup(unicode.encode_utf8(&mut buf)) {
                         Found::Exact(_, event) | Found::Ambiguous(_, event) => {
-                            callback(event.clone());
+                            match event {
+                                InputEvent::Key(key) if !key_down => {
+                                    callback(InputEvent::KeyUp(key.clone()))
+                                }
+                                event => {
+                                    if key_down {
+                                        callback(event.clone())
+                                    }
+                                }
+                            }
                             return;
                         }
                         _ => KeyCode::Char(unicode),
@@ -323,12 +337,17 @@ mod windows {
             };
             let modifiers = modifiers_from_ctrl_key_state(event.dwControlKeyState);
 
-            let input_event = InputEvent::Key(KeyEvent {
+            let key_event = KeyEvent {
                 key: key_code,
                 modifiers,
-            });
-            for _ in 0..event.wRepeatCount {
-                callback(input_event.clone());
+            };
+            if key_down {
+                let input_event = InputEvent::Key(key_event);
+                for _ in 0..event.wRepeatCount {
+                    callback(input_event.clone());
+                }
+            } else {
+                callback(InputEvent::KeyUp(key_event));
             }
         }
 
@@ -352,21 +371,29 @@ mod windows {
 
             let modifiers = modifiers_from_ctrl_key_state(event.dwControlKeyState);
 
+            // For wheel events, the high word of the button state holds
+            // the signed wheel delta; positive values mean that the wheel
+            // was rotated forwards (away from the user) or to the right.
+            let wheel_delta = (event.dwButtonState >> 16) as u16 as i16;
+
             if (event.dwEventFlags & MOUSE_WHEELED) != 0 {
                 buttons |= MouseButtons::VERT_WHEEL;
-                if (event.dwButtonState >> 8) != 0 {
+                if wheel_delta > 0 {
                     buttons |= MouseButtons::WHEEL_POSITIVE;
                 }
             } else if (event.dwEventFlags & MOUSE_HWHEELED) != 0 {
                 buttons |= MouseButtons::HORZ_WHEEL;
-                if (event.dwButtonState >> 8) != 0 {
+                if wheel_delta > 0 {
                     buttons |= MouseButtons::WHEEL_POSITIVE;
                 }
             }
 
+            // The position is reported in screen buffer coordinates;
+            // the terminal adjusts it to be relative to the visible
+            // window, which the parser doesn't know about.
             let mouse = InputEvent::Mouse(MouseEvent {
-                x: event.dwMousePosition.X as u16,
-                y: event.dwMousePosition.Y as u16,
+                x: event.dwMousePosition.X.max(0) as u16,
+                y: event.dwMousePosition.Y.max(0) as u16,
                 mouse_buttons: buttons,
                 modifiers,
             });
@@ -424,9 +451,17 @@ impl InputParser {
             key_map: Self::build_basic_key_map(),
             buf: ReadBuffer::new(),
             state: InputState::Normal,
+            report_key_up: false,
         }
     }
 
+    /// Enable or disable the generation of `InputEvent::KeyUp` events
+    /// for input sources that are able to describe key releases.
+    /// This is disabled by default.
+    pub fn set_report_key_up(&mut self, enable: bool) {
+        self.report_key_up = enable;
+    }
+
     fn build_basic_key_map() -> KeyMap<InputEvent> {
         let mut map = KeyMap::new();
 
@@ -660,6 +695,96 @@ impl InputParser {
         }
     }
 
+    /// Parse a key event that was encoded using the kitty keyboard
+    /// protocol: `CSI code[:alternates] [; modifiers[:event] [; text]] u`.
+    /// Keys that have a legacy encoding keep it but gain the event
+    /// type, for example `CSI 1;1:3A` for the release of the up arrow
+    /// or `CSI 15;1:3~` for the release of F5.
+    /// Returns the length of the sequence, the key and whether the
+    /// event describes the release of the key.
+    fn parse_csi_u(bytes: &[u8]) -> Option<(usize, KeyEvent, bool)> {
+        if !bytes.starts_with(b"\x1b[") {
+            return None;
+        }
+        let end = 2 + bytes[2..]
+            .iter()
+            .position(|&b| !(b.is_ascii_digit() || b == b';' || b == b':'))?;
+        if end == 2 {
+            return None;
+        }
+        let final_byte = bytes[end];
+        let params = std::str::from_utf8(&bytes[2..end]).ok()?;
+        let mut fields = params.split(';');
+
+        let code: u32 = fields.next()?.split(':').next()?.parse().ok()?;
+        let (encoded_modifiers, event) = match fields.next() {
+            Some(field) => {
+                let mut sub = field.split(':');
+                let modifiers = sub.next().and_then(|m| m.parse().ok()).unwrap_or(1);
+                let event = match sub.next() {
+                    Some(e) => e.parse().ok(),
+                    None => None,
+                };
+                (modifiers, event)
+            }
+            None => (1u32, None),
+        };
+
+        let key = match final_byte {
+            b'u' => match code {
+                9 => KeyCode::Tab,
+                13 => KeyCode::Enter,
+                27 => KeyCode::Escape,
+                127 => KeyCode::Backspace,
+                c => KeyCode::Char(std::char::from_u32(c)?),
+            },
+            // The legacy forms without an event type are left to the
+            // key map, which already knows how to decode them
+            _ if event.is_none() => return None,
+            b'A' => KeyCode::UpArrow,
+            b'B' => KeyCode::DownArrow,
+            b'C' => KeyCode::RightArrow,
+            b'D' => KeyCode::LeftArrow,
+            b'H' => KeyCode::Home,
+            b'F' => KeyCode::End,
+            b'P' => KeyCode::Function(1),
+            b'Q' => KeyCode::Function(2),
+            b'R' => KeyCode::Function(3),
+            b'S' => KeyCode::Function(4),
+            b'~' => match code {
+                1 | 7 => KeyCode::Home,
+                2 => KeyCode::Insert,
+                3 => KeyCode::Delete,
+                4 | 8 => KeyCode::End,
+                5 => KeyCode::PageUp,
+                6 => KeyCode::PageDown,
+                11..=15 => KeyCode::Function((code - 10) as u8),
+                17..=21 => KeyCode::Function((code - 11) as u8),
+                23..=24 => KeyCode::Function((code - 12) as u8),
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        let bits = encoded_modifiers.saturating_sub(1);
+        let mut modifiers = Modifiers::NONE;
+        if bits & 1 != 0 {
+            modifiers |= Modifiers::SHIFT;
+        }
+        if bits & 2 != 0 {
+            modifiers |= Modifiers::ALT;
+        }
+        if bits & 4 != 0 {
+            modifiers |= Modifiers::CTRL;
+        }
+        if bits & 8 != 0 {
+            modifiers |= Modifiers::SUPER;
+        }
+
+        // Event types are 1 for press, 2 for repeat and 3 for release
+        Some((end + 1, KeyEvent { key, modifiers }, event == Some(3)))
+    }
+
     fn dispatch_callback<F: FnMut(InputEvent)>(&mut self, mut callback: F, event: InputEvent) {
         match (self.state, event) {
             (
@@ -752,6 +877,16 @@ impl InputParser {
                             }
                             continue;
                         }
+
+                        if let Some((len, key, released)) = Self::parse_csi_u(self.buf.as_slice()) {
+                            self.buf.advance(len);
+                            if !released {
+                                callback(InputEvent::Key(key));
+                            } else if self.report_key_up {
+                                callback(InputEvent::KeyUp(key));
+                            }
+                            continue;
+                        }
                     }
 
                     match (self.key_map.lookup(self.buf.as_slice()), maybe_more) {
@@ -978,4 +1113,66 @@ mod test {
         );
     }
 
+    #[test]
+    fn kitty_key_events() {
+        let a = KeyEvent {
+            key: KeyCode::Char('a'),
+            modifiers: Modifiers::NONE,
+        };
+        let ctrl_enter = KeyEvent {
+            key: KeyCode::Enter,
+            modifiers: Modifiers::CTRL,
+        };
+
+        let mut p = InputParser::new();
+        p.set_report_key_up(true);
+        assert_eq!(
+            vec![
+                InputEvent::Key(a.clone()),
+                InputEvent::Key(a.clone()),
+                InputEvent::KeyUp(a.clone()),
+                InputEvent::Key(ctrl_enter.clone()),
+                InputEvent::KeyUp(ctrl_enter.clone()),
+            ],
+            p.parse_as_vec(b"\x1b[97u\x1b[97;1:2u\x1b[97;1:3u\x1b[13;5u\x1b[13;5:3u")
+        );
+
+        // Releases are dropped unless they were asked for
+        let mut p = InputParser::new();
+        assert_eq!(
+            vec![InputEvent::Key(ctrl_enter.clone())],
+            p.parse_as_vec(b"\x1b[13;5:1u\x1b[13;5:3u")
+        );
+
+        // Keys with a legacy encoding carry the event type in
+        // the modifiers parameter
+        let up = KeyEvent {
+            key: KeyCode::UpArrow,
+            modifiers: Modifiers::NONE,
+        };
+        let shift_f5 = KeyEvent {
+            key: KeyCode::Function(5),
+            modifiers: Modifiers::SHIFT,
+        };
+        let mut p = InputParser::new();
+        p.set_report_key_up(true);
+        assert_eq!(
+            vec![
+                InputEvent::Key(up.clone()),
+                InputEvent::KeyUp(up.clone()),
+                InputEvent::Key(shift_f5.clone()),
+                InputEvent::KeyUp(shift_f5.clone()),
+            ],
+            p.parse_as_vec(b"\x1b[1;1:1A\x1b[1;1:3A\x1b[15;2~\x1b[15;2:3~")
+        );
+
+        // Other sequences are not mistaken for CSI u
+        assert_eq!(
+            vec![InputEvent::Key(KeyEvent {
+                key: KeyCode::Function(1),
+                modifiers: Modifiers::NONE,
+            })],
+            p.parse_as_vec(b"\x1b[11~")
+        );
+    }
 }
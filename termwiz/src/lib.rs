@@ -62,6 +62,7 @@ extern crate cassowary;
 extern crate image as image_crate;
 extern crate memmem;
 extern crate ordered_float;
+extern crate rayon;
 extern crate regex;
 extern crate smallvec;
 extern crate unicode_segmentation;
@@ -77,6 +78,7 @@ pub mod image;
 pub mod input;
 pub mod istty;
 pub mod keymap;
+pub mod lineedit;
 mod range;
 mod readbuf;
 pub mod render;
@@ -0,0 +1,64 @@
+pub type RepeatCount = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Movement {
+    BackwardChar(RepeatCount),
+    ForwardChar(RepeatCount),
+    /// To the start of the current or previous word, where words are
+    /// made of alphanumeric characters
+    BackwardWord(RepeatCount),
+    /// To the end of the current or next word
+    ForwardWord(RepeatCount),
+    /// To the start of the current or previous run of characters that
+    /// are not whitespace
+    BackwardWhitespaceWord(RepeatCount),
+    /// To the start of the current line of the buffer
+    StartOfLine,
+    /// To the end of the current line of the buffer
+    EndOfLine,
+    /// To the same column of the previous line of a buffer that spans
+    /// several lines
+    BackwardLine(RepeatCount),
+    /// To the same column of the next line of a buffer that spans
+    /// several lines
+    ForwardLine(RepeatCount),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Return the current line to the caller, unless the host reports
+    /// that it is incomplete, in which case a new line is started
+    AcceptLine,
+    /// Abandon the current line
+    Cancel,
+    /// Signal the end of input
+    EndOfFile,
+    InsertChar(RepeatCount, char),
+    InsertText(RepeatCount, String),
+    Repaint,
+    Move(Movement),
+    /// Delete the text between the cursor and the position that
+    /// the movement would have placed it.  Unless the movement is by
+    /// characters, the text is saved in the kill ring; consecutive
+    /// kills are saved as a single entry.
+    Kill(Movement),
+    /// Insert the most recently killed text
+    Yank,
+    /// Immediately after a yank, replace the yanked text with the
+    /// text that was killed before it
+    YankPop,
+    HistoryPrevious,
+    HistoryNext,
+    /// Start, or advance to the next match of, an incremental search
+    /// through older history entries
+    HistoryIncSearchBackwards,
+    /// Start, or advance to the next match of, an incremental search
+    /// through newer history entries
+    HistoryIncSearchForwards,
+    /// Leave the incremental search or completion, restoring the line
+    /// that was being edited before it started
+    CancelSearch,
+    /// Ask the host for the possible completions at the cursor and
+    /// apply the first, or apply the next if completion is underway
+    Complete,
+}
@@ -0,0 +1,66 @@
+use crate::cell::AttributeChange;
+use crate::surface::Change;
+use std::ops::Range;
+use unicode_width::UnicodeWidthStr;
+
+/// A possible completion of the line, as returned by
+/// `LineEditorHost::complete`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompletionCandidate {
+    /// The byte range of the line that is replaced by `text`
+    pub range: Range<usize>,
+    /// The replacement text
+    pub text: String,
+}
+
+/// The state of a completion that has more than one candidate.
+/// Each Tab selects the next candidate, which is applied to the line
+/// as it was before completion started.
+pub(crate) struct CompletionState {
+    pub candidates: Vec<CompletionCandidate>,
+    pub index: usize,
+    pub line: String,
+    pub cursor: usize,
+}
+
+impl CompletionState {
+    /// Returns the line with the selected candidate applied, along
+    /// with the cursor position that follows the inserted text
+    pub fn apply(&self) -> (String, usize) {
+        let candidate = &self.candidates[self.index];
+        let mut line = self.line.clone();
+        line.replace_range(candidate.range.clone(), &candidate.text);
+        (line, candidate.range.start + candidate.text.len())
+    }
+
+    /// Produce the changes that render the candidates below the line,
+    /// wrapped to `cols` columns, with the selected one in reverse
+    /// video.  Returns the changes and the number of rows they occupy.
+    pub fn render_menu(&self, cols: usize) -> (Vec<Change>, usize) {
+        let mut changes = vec![];
+        let mut rows = 0;
+        let mut x = 0;
+        for (idx, candidate) in self.candidates.iter().enumerate() {
+            let width = candidate.text.width();
+            if rows == 0 || (x > 0 && x + 2 + width > cols) {
+                changes.push(Change::Text("\r\n".to_owned()));
+                rows += 1;
+                x = 0;
+            }
+            if x > 0 {
+                changes.push(Change::Text("  ".to_owned()));
+                x += 2;
+            }
+            let selected = idx == self.index;
+            if selected {
+                changes.push(AttributeChange::Reverse(true).into());
+            }
+            changes.push(Change::Text(candidate.text.clone()));
+            if selected {
+                changes.push(AttributeChange::Reverse(false).into());
+            }
+            x += width;
+        }
+        (changes, rows)
+    }
+}
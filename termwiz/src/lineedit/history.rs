@@ -0,0 +1,191 @@
+//! Storage for the lines previously entered into a `LineEditor`
+use failure::Error;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// Identifies an entry in the history.  Index 0 is the oldest entry.
+pub type HistoryIndex = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchDirection {
+    /// Search towards older entries
+    Backwards,
+    /// Search towards newer entries
+    Forwards,
+}
+
+impl SearchDirection {
+    /// Returns the index that follows `idx` in this direction, if any
+    pub fn next(self, idx: HistoryIndex) -> Option<HistoryIndex> {
+        match self {
+            SearchDirection::Backwards => idx.checked_sub(1),
+            SearchDirection::Forwards => idx.checked_add(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchResult<'a> {
+    /// The text of the matching entry
+    pub line: Cow<'a, str>,
+    /// The index of the matching entry
+    pub idx: HistoryIndex,
+    /// The byte offset of the start of the match within `line`
+    pub cursor: usize,
+}
+
+/// Represents the history of lines entered into a `LineEditor`.
+/// Embedding applications can implement this to provide their own
+/// storage.
+pub trait History {
+    /// Returns the entry at `idx`, or None if there is no such entry
+    fn get(&self, idx: HistoryIndex) -> Option<Cow<str>>;
+
+    /// Returns the index of the most recent entry, or None if the
+    /// history is empty
+    fn last(&self) -> Option<HistoryIndex>;
+
+    /// Record `line` as the most recent entry
+    fn add(&mut self, line: &str);
+
+    /// Starting with the entry at `idx` and moving in `direction`,
+    /// returns the first entry that contains `pattern`.
+    fn search(
+        &self,
+        idx: HistoryIndex,
+        direction: SearchDirection,
+        pattern: &str,
+    ) -> Option<SearchResult> {
+        let mut idx = idx;
+        loop {
+            let line = self.get(idx)?;
+            if let Some(cursor) = line.find(pattern) {
+                return Some(SearchResult { line, idx, cursor });
+            }
+            idx = direction.next(idx)?;
+        }
+    }
+}
+
+/// Holds the history in memory.  Empty lines, and lines that are the
+/// same as the most recent entry, are not recorded.
+#[derive(Debug, Default)]
+pub struct BasicHistory {
+    entries: VecDeque<String>,
+}
+
+impl BasicHistory {
+    fn should_add(&self, line: &str) -> bool {
+        !line.is_empty() && self.entries.back().map(String::as_str) != Some(line)
+    }
+}
+
+impl History for BasicHistory {
+    fn get(&self, idx: HistoryIndex) -> Option<Cow<str>> {
+        self.entries.get(idx).map(|s| Cow::Borrowed(s.as_str()))
+    }
+
+    fn last(&self) -> Option<HistoryIndex> {
+        self.entries.len().checked_sub(1)
+    }
+
+    fn add(&mut self, line: &str) {
+        if self.should_add(line) {
+            self.entries.push_back(line.to_owned());
+        }
+    }
+}
+
+/// Holds the history in memory and appends each entry that is added
+/// to a file, one entry per line, so that it persists across sessions.
+pub struct FileHistory {
+    history: BasicHistory,
+    path: PathBuf,
+}
+
+impl FileHistory {
+    /// Load the history from `path`.  The file is created when the
+    /// first entry is added if it doesn't already exist.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        let mut history = BasicHistory::default();
+        match File::open(&path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    history.add(&line?);
+                }
+            }
+            Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => bail!("failed to open history {}: {}", path.display(), err),
+        }
+        Ok(Self { history, path })
+    }
+
+    fn append(&self, line: &str) -> Result<(), Error> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", line)?;
+        Ok(())
+    }
+}
+
+impl History for FileHistory {
+    fn get(&self, idx: HistoryIndex) -> Option<Cow<str>> {
+        self.history.get(idx)
+    }
+
+    fn last(&self) -> Option<HistoryIndex> {
+        self.history.last()
+    }
+
+    /// Lines that contain newlines can't be represented in the file
+    /// and are only kept in memory.  Failure to write the file is not
+    /// reported: losing an entry is preferable to losing the line
+    /// that is being entered.
+    fn add(&mut self, line: &str) {
+        if !self.history.should_add(line) {
+            return;
+        }
+        self.history.add(line);
+        if !line.contains('\n') {
+            self.append(line).ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn search() {
+        let mut history = BasicHistory::default();
+        history.add("ls -l");
+        history.add("");
+        history.add("cargo build");
+        history.add("cargo build");
+        history.add("cargo test");
+
+        assert_eq!(history.last(), Some(2));
+        assert_eq!(history.get(1).unwrap(), "cargo build");
+
+        let found = history
+            .search(2, SearchDirection::Backwards, "build")
+            .unwrap();
+        assert_eq!(found.idx, 1);
+        assert_eq!(found.cursor, 6);
+
+        let found = history
+            .search(0, SearchDirection::Forwards, "cargo")
+            .unwrap();
+        assert_eq!(found.idx, 1);
+
+        assert_eq!(history.search(2, SearchDirection::Backwards, "rm"), None);
+        assert_eq!(history.search(3, SearchDirection::Backwards, "ls"), None);
+    }
+}
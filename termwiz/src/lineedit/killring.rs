@@ -0,0 +1,55 @@
+use std::collections::VecDeque;
+
+/// How many kills are remembered
+const MAX_ENTRIES: usize = 32;
+
+/// Holds the text removed by the kill commands so that it can be
+/// yanked back into the line.  The most recent kill is at the front.
+#[derive(Default)]
+pub(crate) struct KillRing {
+    entries: VecDeque<String>,
+    /// The entry that was most recently yanked
+    index: usize,
+}
+
+impl KillRing {
+    /// Records killed text.  When `append` is true the previous action
+    /// was also a kill, and the text is merged into the most recent
+    /// entry; `before` places it ahead of that text, as happens when
+    /// killing backwards.
+    pub fn kill(&mut self, text: &str, append: bool, before: bool) {
+        if text.is_empty() {
+            return;
+        }
+        match self.entries.front_mut() {
+            Some(entry) if append => {
+                if before {
+                    entry.insert_str(0, text);
+                } else {
+                    entry.push_str(text);
+                }
+            }
+            _ => {
+                self.entries.push_front(text.to_owned());
+                self.entries.truncate(MAX_ENTRIES);
+            }
+        }
+        self.index = 0;
+    }
+
+    /// Returns the most recent kill
+    pub fn yank(&mut self) -> Option<&str> {
+        self.index = 0;
+        self.entries.front().map(String::as_str)
+    }
+
+    /// Moves to the kill before the one that was last yanked, wrapping
+    /// around to the most recent after the oldest, and returns it
+    pub fn rotate(&mut self) -> Option<&str> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        self.index = (self.index + 1) % self.entries.len();
+        self.entries.get(self.index).map(String::as_str)
+    }
+}
@@ -0,0 +1,1375 @@
+//! The `LineEditor` struct provides line editing facilities similar
+//! to those in the unix shell: the line can be edited with the emacs
+//! style key bindings, previously entered lines can be recalled with
+//! the up and down arrows, and `CTRL-R` starts an incremental search
+//! back through them.
+//!
+//! The history is provided by the `LineEditorHost` through the
+//! `History` trait; `BasicHistory` keeps it in memory and
+//! `FileHistory` persists it to a file.  Embedding applications can
+//! implement `History` to supply their own storage.
+//!
+//! Text that is removed with the kill commands, such as `CTRL-K`,
+//! `CTRL-U`, `CTRL-W` and `ALT-D`, is saved in a kill ring; `CTRL-Y`
+//! inserts the most recent kill and `ALT-Y` then replaces it with
+//! older ones.
+//!
+//! Pressing Tab asks the host for the possible completions of the
+//! text at the cursor; pressing it again cycles through them, and
+//! Escape restores the line as it was before completion started.
+//!
+//! The line may span several lines of text: `ALT-Enter` starts a new
+//! line, as does Enter when `LineEditorHost::is_line_complete` reports
+//! that more input is needed, such as for an unclosed quote.  The
+//! lines after the first are shown after a continuation prompt, and
+//! the up and down arrows move between them before recalling history.
+//!
+//! Accepted lines are not added to the history by the editor itself;
+//! the caller decides whether they should be recorded.
+//!
+//! For prompts such as passphrases, `set_echo_mode` can mask the typed
+//! characters or hide them entirely.  While the line is not echoed
+//! normally, the history can't be recalled or searched, completion is
+//! disabled and killed text is not saved in the kill ring; the caller
+//! should likewise not add the accepted line to the history.
+//!
+//! `read_line` blocks until the line is complete.  An application that
+//! needs to service other events while the user is typing can instead
+//! call `read_line_with_timeout`, or drive the edit itself using
+//! `begin_line` and `poll_line`, and can use `perform_action` to insert
+//! text into the line or to cancel it in the meantime.
+use crate::input::{InputEvent, KeyCode, KeyEvent, Modifiers};
+use crate::surface::{Change, Position};
+use crate::terminal::{Blocking, Terminal};
+use failure::Error;
+use std::borrow::Cow;
+use std::ops::Range;
+use std::time::Duration;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+mod actions;
+mod complete;
+mod history;
+mod killring;
+
+pub use self::actions::{Action, Movement, RepeatCount};
+pub use self::complete::CompletionCandidate;
+use self::complete::CompletionState;
+pub use self::history::{
+    BasicHistory, FileHistory, History, HistoryIndex, SearchDirection, SearchResult,
+};
+use self::killring::KillRing;
+
+/// The `LineEditorHost` allows the embedding application to influence
+/// how the editor behaves
+pub trait LineEditorHost {
+    /// Returns the history that is used to recall and search lines
+    fn history(&mut self) -> &mut History;
+
+    /// Returns the changes that render `prompt`.  The default is to
+    /// render it as plain text.
+    fn render_prompt(&self, prompt: &str) -> Vec<Change> {
+        vec![Change::Text(prompt.to_owned())]
+    }
+
+    /// Called when Enter is pressed.  Returning false starts a new line
+    /// in the buffer rather than returning it to the caller, allowing
+    /// the input to continue.  The default is to accept every line.
+    fn is_line_complete(&self, _line: &str) -> bool {
+        true
+    }
+
+    /// Allows the host to override the action for an input event.
+    /// Returning None applies the default key bindings.
+    fn resolve_action(&mut self, _event: &InputEvent) -> Option<Action> {
+        None
+    }
+
+    /// Returns the possible completions of `line` with the cursor at
+    /// byte offset `cursor_pos`.  The default is to offer none.
+    fn complete(&self, _line: &str, _cursor_pos: usize) -> Vec<CompletionCandidate> {
+        vec![]
+    }
+
+    /// Controls whether the candidates are listed below the line when
+    /// there is more than one of them.  The default is to list them.
+    fn show_completion_menu(&self) -> bool {
+        true
+    }
+}
+
+/// A `LineEditorHost` with the default behavior and an in-memory
+/// history
+#[derive(Default)]
+pub struct NopLineEditorHost {
+    history: BasicHistory,
+}
+
+impl LineEditorHost for NopLineEditorHost {
+    fn history(&mut self) -> &mut History {
+        &mut self.history
+    }
+}
+
+/// The state of an incremental history search
+struct SearchState {
+    direction: SearchDirection,
+    pattern: String,
+    /// The entry from which the search started
+    start: Option<HistoryIndex>,
+    /// The index of the matching entry and the byte offset of the
+    /// match within it
+    matched: Option<(HistoryIndex, usize)>,
+    /// True if the pattern isn't found; `matched` holds the last entry
+    /// that did match
+    failed: bool,
+}
+
+impl SearchState {
+    fn search(&mut self, history: &History, from: Option<HistoryIndex>) {
+        if self.pattern.is_empty() {
+            self.matched = None;
+            self.failed = false;
+            return;
+        }
+        let found = from
+            .and_then(|idx| history.search(idx, self.direction, &self.pattern))
+            .map(|result| (result.idx, result.cursor));
+        match found {
+            Some(matched) => {
+                self.matched = Some(matched);
+                self.failed = false;
+            }
+            None => self.failed = true,
+        }
+    }
+
+    fn status(&self) -> String {
+        format!(
+            "({}{}i-search)`{}': ",
+            if self.failed { "failed " } else { "" },
+            match self.direction {
+                SearchDirection::Backwards => "reverse-",
+                SearchDirection::Forwards => "",
+            },
+            self.pattern
+        )
+    }
+}
+
+/// Controls how the text of the line is displayed while it is edited
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EchoMode {
+    /// The text is shown as it is typed
+    Normal,
+    /// Each character is shown as the specified character, such as `*`
+    Masked(char),
+    /// Nothing is shown, and the cursor stays after the prompt
+    Hidden,
+}
+
+impl Default for EchoMode {
+    fn default() -> Self {
+        EchoMode::Normal
+    }
+}
+
+/// The state of the line that is being edited, as reported by
+/// `LineEditor::poll_line`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LineStatus {
+    /// The line is still being edited
+    Pending,
+    /// The edit has finished.  Holds the accepted line, or None if
+    /// the input was cancelled or the end of input was signalled.
+    Done(Option<String>),
+}
+
+pub struct LineEditor<T: Terminal> {
+    terminal: T,
+    prompt: String,
+    /// The prompt shown before each line after the first
+    continuation_prompt: String,
+    line: String,
+    /// The byte offset of the cursor within `line`
+    cursor: usize,
+    /// The history entry that is being shown, if any
+    history_pos: Option<HistoryIndex>,
+    /// The line that was being edited before the history was recalled
+    bottom_line: Option<String>,
+    search: Option<SearchState>,
+    completion: Option<CompletionState>,
+    kill_ring: KillRing,
+    /// True if the previous action saved text in the kill ring
+    last_kill: bool,
+    /// The range of the line that was inserted by the previous action,
+    /// if it was a yank
+    last_yank: Option<Range<usize>>,
+    /// The row, relative to the prompt, on which the cursor was left
+    /// by the last render
+    cursor_row: usize,
+    /// True while a line is being edited
+    editing: bool,
+    echo: EchoMode,
+}
+
+impl<T: Terminal> LineEditor<T> {
+    pub fn new(terminal: T) -> Self {
+        Self {
+            terminal,
+            prompt: "> ".to_owned(),
+            continuation_prompt: ". ".to_owned(),
+            line: String::new(),
+            cursor: 0,
+            history_pos: None,
+            bottom_line: None,
+            search: None,
+            completion: None,
+            kill_ring: KillRing::default(),
+            last_kill: false,
+            last_yank: None,
+            cursor_row: 0,
+            editing: false,
+            echo: EchoMode::Normal,
+        }
+    }
+
+    pub fn set_prompt(&mut self, prompt: &str) {
+        self.prompt = prompt.to_owned();
+    }
+
+    pub fn set_continuation_prompt(&mut self, prompt: &str) {
+        self.continuation_prompt = prompt.to_owned();
+    }
+
+    /// Sets how the text of the line is displayed.  This takes effect
+    /// from the next render, and persists across lines.
+    pub fn set_echo_mode(&mut self, mode: EchoMode) {
+        self.echo = mode;
+    }
+
+    pub fn terminal(&mut self) -> &mut T {
+        &mut self.terminal
+    }
+
+    /// Places the terminal in raw mode and reads a line of input.
+    /// Returns None if the input was cancelled or if the end of input
+    /// was signalled.
+    pub fn read_line(&mut self, host: &mut LineEditorHost) -> Result<Option<String>, Error> {
+        self.begin_line(host)?;
+        loop {
+            if let LineStatus::Done(result) = self.poll_line(host, Blocking::Wait)? {
+                return Ok(result);
+            }
+        }
+    }
+
+    /// Like `read_line`, but returns `LineStatus::Pending` if the line
+    /// is not accepted within `timeout`, allowing the caller to service
+    /// other events before calling it again to resume the edit.
+    /// While the edit is pending, `perform_action` can be used to
+    /// cancel it or to insert text.
+    pub fn read_line_with_timeout(
+        &mut self,
+        host: &mut LineEditorHost,
+        timeout: Duration,
+    ) -> Result<LineStatus, Error> {
+        if !self.editing {
+            self.begin_line(host)?;
+        }
+        self.poll_line(host, Blocking::WaitFor(timeout))
+    }
+
+    /// Places the terminal in raw mode and starts editing a new line,
+    /// abandoning any edit that is in progress.  The input is then
+    /// processed by calling `poll_line`.
+    pub fn begin_line(&mut self, host: &mut LineEditorHost) -> Result<(), Error> {
+        self.terminal.set_raw_mode()?;
+        self.line.clear();
+        self.cursor = 0;
+        self.history_pos = None;
+        self.bottom_line = None;
+        self.search = None;
+        self.completion = None;
+        self.last_kill = false;
+        self.last_yank = None;
+        self.cursor_row = 0;
+        self.editing = true;
+        self.render(host)
+    }
+
+    /// Returns true if a line has been started with `begin_line`
+    /// and has not yet been accepted or cancelled
+    pub fn is_editing(&self) -> bool {
+        self.editing
+    }
+
+    /// Processes the input that is available for the line that is
+    /// being edited.  `blocking` controls how long to wait for the
+    /// first input event; any further events that are immediately
+    /// available are processed before returning.
+    pub fn poll_line(
+        &mut self,
+        host: &mut LineEditorHost,
+        blocking: Blocking,
+    ) -> Result<LineStatus, Error> {
+        if !self.editing {
+            bail!("poll_line called without a line being edited");
+        }
+        let mut blocking = blocking;
+        while let Some(event) = self.terminal.poll_input(blocking)? {
+            let action = host
+                .resolve_action(&event)
+                .or_else(|| self.resolve_action(&event));
+            if let Some(action) = action {
+                if let LineStatus::Done(result) = self.perform_action(host, action)? {
+                    return Ok(LineStatus::Done(result));
+                }
+            }
+            blocking = Blocking::DoNotWait;
+        }
+        Ok(LineStatus::Pending)
+    }
+
+    /// Applies `action` to the line that is being edited, as though
+    /// it had been bound to a key that was pressed.  This allows the
+    /// embedding application to inject text with `Action::InsertText`
+    /// or to end the edit with `Action::Cancel` or `Action::AcceptLine`.
+    pub fn perform_action(
+        &mut self,
+        host: &mut LineEditorHost,
+        action: Action,
+    ) -> Result<LineStatus, Error> {
+        if !self.editing {
+            bail!("perform_action called without a line being edited");
+        }
+        // Don't reveal the history, or the completions of the secret
+        // text, when the line isn't echoed
+        if self.echo != EchoMode::Normal {
+            match action {
+                Action::HistoryPrevious
+                | Action::HistoryNext
+                | Action::HistoryIncSearchBackwards
+                | Action::HistoryIncSearchForwards
+                | Action::Complete => return Ok(LineStatus::Pending),
+                _ => {}
+            }
+        }
+        let action = if self.search.is_some() {
+            match self.apply_search_action(host.history(), action) {
+                Some(action) => action,
+                None => {
+                    self.render(host)?;
+                    return Ok(LineStatus::Pending);
+                }
+            }
+        } else {
+            action
+        };
+
+        if action == Action::Complete {
+            self.complete(host);
+            self.render(host)?;
+            return Ok(LineStatus::Pending);
+        }
+        // Anything else accepts the selected candidate, except for
+        // cancelling, which restores the line
+        if let Some(completion) = self.completion.take() {
+            if action == Action::CancelSearch {
+                self.line = completion.line;
+                self.cursor = completion.cursor;
+                self.render(host)?;
+                return Ok(LineStatus::Pending);
+            }
+        }
+
+        let result = match action {
+            Action::AcceptLine if !host.is_line_complete(&self.line) => {
+                self.cursor = self.line.len();
+                self.apply_action(host.history(), Action::InsertChar(1, '\n'));
+                self.render(host)?;
+                return Ok(LineStatus::Pending);
+            }
+            Action::AcceptLine => Some(self.line.clone()),
+            Action::Cancel | Action::EndOfFile => None,
+            action => {
+                self.apply_action(host.history(), action);
+                self.render(host)?;
+                return Ok(LineStatus::Pending);
+            }
+        };
+        // Leave the cursor after the last line of the buffer
+        self.cursor = self.line.len();
+        self.render(host)?;
+        self.terminal.render(&[Change::Text("\r\n".to_owned())])?;
+        self.terminal.flush()?;
+        self.editing = false;
+        Ok(LineStatus::Done(result))
+    }
+
+    fn resolve_action(&self, event: &InputEvent) -> Option<Action> {
+        let (key, modifiers) = match event {
+            InputEvent::Key(KeyEvent { key, modifiers }) => (*key, *modifiers),
+            InputEvent::Paste(text) => return Some(Action::InsertText(1, text.clone())),
+            InputEvent::Resized { .. } => return Some(Action::Repaint),
+            _ => return None,
+        };
+        Some(match (key, modifiers) {
+            (KeyCode::Enter, Modifiers::NONE) => Action::AcceptLine,
+            (KeyCode::Enter, Modifiers::ALT) => Action::InsertChar(1, '\n'),
+            (KeyCode::Char('C'), Modifiers::CTRL) => Action::Cancel,
+            (KeyCode::Char('D'), Modifiers::CTRL) if self.line.is_empty() => Action::EndOfFile,
+            (KeyCode::Char('D'), Modifiers::CTRL) => Action::Kill(Movement::ForwardChar(1)),
+            (KeyCode::Char('A'), Modifiers::CTRL) | (KeyCode::Home, _) => {
+                Action::Move(Movement::StartOfLine)
+            }
+            (KeyCode::Char('E'), Modifiers::CTRL) | (KeyCode::End, _) => {
+                Action::Move(Movement::EndOfLine)
+            }
+            (KeyCode::Char('B'), Modifiers::CTRL) | (KeyCode::LeftArrow, Modifiers::NONE) => {
+                Action::Move(Movement::BackwardChar(1))
+            }
+            (KeyCode::Char('F'), Modifiers::CTRL) | (KeyCode::RightArrow, Modifiers::NONE) => {
+                Action::Move(Movement::ForwardChar(1))
+            }
+            // The backspace key usually sends DEL, which is decoded as
+            // the Delete key, so both delete backwards; CTRL-D deletes
+            // forwards.
+            (KeyCode::Char('H'), Modifiers::CTRL)
+            | (KeyCode::Backspace, Modifiers::NONE)
+            | (KeyCode::Delete, Modifiers::NONE) => Action::Kill(Movement::BackwardChar(1)),
+            (KeyCode::Char('K'), Modifiers::CTRL) => Action::Kill(Movement::EndOfLine),
+            (KeyCode::Char('U'), Modifiers::CTRL) => Action::Kill(Movement::StartOfLine),
+            (KeyCode::Char('W'), Modifiers::CTRL) => {
+                Action::Kill(Movement::BackwardWhitespaceWord(1))
+            }
+            (KeyCode::Char('b'), Modifiers::ALT) | (KeyCode::Char('B'), Modifiers::ALT) => {
+                Action::Move(Movement::BackwardWord(1))
+            }
+            (KeyCode::Char('f'), Modifiers::ALT) | (KeyCode::Char('F'), Modifiers::ALT) => {
+                Action::Move(Movement::ForwardWord(1))
+            }
+            (KeyCode::Char('d'), Modifiers::ALT) | (KeyCode::Char('D'), Modifiers::ALT) => {
+                Action::Kill(Movement::ForwardWord(1))
+            }
+            (KeyCode::Backspace, Modifiers::ALT) | (KeyCode::Delete, Modifiers::ALT) => {
+                Action::Kill(Movement::BackwardWord(1))
+            }
+            (KeyCode::Char('Y'), Modifiers::CTRL) => Action::Yank,
+            (KeyCode::Char('y'), Modifiers::ALT) | (KeyCode::Char('Y'), Modifiers::ALT) => {
+                Action::YankPop
+            }
+            (KeyCode::Char('P'), Modifiers::CTRL) | (KeyCode::UpArrow, Modifiers::NONE) => {
+                if self.line[..self.cursor].contains('\n') {
+                    Action::Move(Movement::BackwardLine(1))
+                } else {
+                    Action::HistoryPrevious
+                }
+            }
+            (KeyCode::Char('N'), Modifiers::CTRL) | (KeyCode::DownArrow, Modifiers::NONE) => {
+                if self.line[self.cursor..].contains('\n') {
+                    Action::Move(Movement::ForwardLine(1))
+                } else {
+                    Action::HistoryNext
+                }
+            }
+            (KeyCode::Char('R'), Modifiers::CTRL) => Action::HistoryIncSearchBackwards,
+            (KeyCode::Char('S'), Modifiers::CTRL) => Action::HistoryIncSearchForwards,
+            (KeyCode::Char('G'), Modifiers::CTRL) | (KeyCode::Escape, Modifiers::NONE) => {
+                Action::CancelSearch
+            }
+            (KeyCode::Char('L'), Modifiers::CTRL) => Action::Repaint,
+            (KeyCode::Tab, Modifiers::NONE) => Action::Complete,
+            (KeyCode::Char(c), Modifiers::NONE) | (KeyCode::Char(c), Modifiers::SHIFT) => {
+                Action::InsertChar(1, c)
+            }
+            _ => return None,
+        })
+    }
+
+    /// Returns the byte offset that `movement` would place the cursor
+    fn eval_movement(&self, movement: Movement) -> usize {
+        match movement {
+            Movement::BackwardChar(n) => {
+                let mut cursor = self.cursor;
+                for _ in 0..n {
+                    cursor = self.line[..cursor]
+                        .grapheme_indices(true)
+                        .next_back()
+                        .map(|(idx, _)| idx)
+                        .unwrap_or(0);
+                }
+                cursor
+            }
+            Movement::ForwardChar(n) => {
+                let mut cursor = self.cursor;
+                for _ in 0..n {
+                    cursor += self.line[cursor..]
+                        .graphemes(true)
+                        .next()
+                        .map(str::len)
+                        .unwrap_or(0);
+                }
+                cursor
+            }
+            Movement::BackwardWord(n) => (0..n).fold(self.cursor, |cursor, _| {
+                backward_word(&self.line, cursor, char::is_alphanumeric)
+            }),
+            Movement::ForwardWord(n) => (0..n).fold(self.cursor, |cursor, _| {
+                forward_word(&self.line, cursor, char::is_alphanumeric)
+            }),
+            Movement::BackwardWhitespaceWord(n) => (0..n).fold(self.cursor, |cursor, _| {
+                backward_word(&self.line, cursor, |c| !c.is_whitespace())
+            }),
+            Movement::StartOfLine => self.line_start(self.cursor),
+            Movement::EndOfLine => self.line_end(self.cursor),
+            Movement::BackwardLine(n) | Movement::ForwardLine(n) => {
+                let mut start = self.line_start(self.cursor);
+                let column = self.line[start..self.cursor].width();
+                for _ in 0..n {
+                    start = match movement {
+                        Movement::BackwardLine(_) if start > 0 => self.line_start(start - 1),
+                        Movement::ForwardLine(_) if self.line_end(start) < self.line.len() => {
+                            self.line_end(start) + 1
+                        }
+                        _ => break,
+                    };
+                }
+                // Move to the same column, or as close as possible
+                let mut cursor = start;
+                let mut width = 0;
+                for grapheme in self.line[start..].graphemes(true) {
+                    width += grapheme.width();
+                    if grapheme == "\n" || width > column {
+                        break;
+                    }
+                    cursor += grapheme.len();
+                }
+                cursor
+            }
+        }
+    }
+
+    /// Returns the byte offset of the start of the line of the buffer
+    /// that contains `pos`
+    fn line_start(&self, pos: usize) -> usize {
+        self.line[..pos].rfind('\n').map(|idx| idx + 1).unwrap_or(0)
+    }
+
+    /// Returns the byte offset of the end of the line of the buffer
+    /// that contains `pos`
+    fn line_end(&self, pos: usize) -> usize {
+        self.line[pos..]
+            .find('\n')
+            .map(|idx| pos + idx)
+            .unwrap_or_else(|| self.line.len())
+    }
+
+    /// Replace the line with the history entry at `idx`
+    fn recall(&mut self, history: &History, idx: HistoryIndex) {
+        if let Some(line) = history.get(idx) {
+            if self.history_pos.is_none() {
+                self.bottom_line = Some(self.line.clone());
+            }
+            self.line = line.into_owned();
+            self.cursor = self.line.len();
+            self.history_pos = Some(idx);
+        }
+    }
+
+    fn apply_action(&mut self, history: &mut History, action: Action) {
+        let last_kill = self.last_kill;
+        self.last_kill = false;
+        let last_yank = self.last_yank.take();
+
+        match action {
+            Action::InsertChar(n, c) => {
+                for _ in 0..n {
+                    self.line.insert(self.cursor, c);
+                    self.cursor += c.len_utf8();
+                }
+            }
+            Action::InsertText(n, text) => {
+                for _ in 0..n {
+                    self.line.insert_str(self.cursor, &text);
+                    self.cursor += text.len();
+                }
+            }
+            Action::Move(movement) => self.cursor = self.eval_movement(movement),
+            Action::Kill(movement) => {
+                let pos = self.eval_movement(movement);
+                let (start, end) = (pos.min(self.cursor), pos.max(self.cursor));
+                let text: String = self.line.drain(start..end).collect();
+                match movement {
+                    Movement::BackwardChar(_) | Movement::ForwardChar(_) => {}
+                    // Secrets are not retained in the kill ring
+                    _ if self.echo != EchoMode::Normal => {}
+                    _ => {
+                        self.kill_ring.kill(&text, last_kill, pos < self.cursor);
+                        self.last_kill = true;
+                    }
+                }
+                self.cursor = start;
+            }
+            Action::Yank => {
+                if let Some(text) = self.kill_ring.yank() {
+                    self.line.insert_str(self.cursor, text);
+                    self.last_yank = Some(self.cursor..self.cursor + text.len());
+                    self.cursor += text.len();
+                }
+            }
+            Action::YankPop => {
+                if let Some(range) = last_yank {
+                    if let Some(text) = self.kill_ring.rotate() {
+                        self.line.replace_range(range.clone(), text);
+                        self.cursor = range.start + text.len();
+                        self.last_yank = Some(range.start..self.cursor);
+                    }
+                }
+            }
+            Action::HistoryPrevious => {
+                let prev = match self.history_pos {
+                    Some(idx) => idx.checked_sub(1),
+                    None => history.last(),
+                };
+                if let Some(idx) = prev {
+                    self.recall(history, idx);
+                }
+            }
+            Action::HistoryNext => {
+                if let Some(idx) = self.history_pos {
+                    if history.get(idx + 1).is_some() {
+                        self.recall(history, idx + 1);
+                    } else {
+                        self.line = self.bottom_line.take().unwrap_or_default();
+                        self.cursor = self.line.len();
+                        self.history_pos = None;
+                    }
+                }
+            }
+            Action::HistoryIncSearchBackwards | Action::HistoryIncSearchForwards => {
+                let direction = if action == Action::HistoryIncSearchBackwards {
+                    SearchDirection::Backwards
+                } else {
+                    SearchDirection::Forwards
+                };
+                let start = match direction {
+                    SearchDirection::Backwards => self.history_pos.or_else(|| history.last()),
+                    SearchDirection::Forwards => self.history_pos,
+                };
+                self.search = Some(SearchState {
+                    direction,
+                    pattern: String::new(),
+                    start,
+                    matched: None,
+                    failed: false,
+                });
+            }
+            Action::AcceptLine
+            | Action::Cancel
+            | Action::EndOfFile
+            | Action::Repaint
+            | Action::CancelSearch
+            | Action::Complete => {}
+        }
+    }
+
+    /// Apply the first completion candidate, or the next one if
+    /// completion is already underway
+    fn complete(&mut self, host: &mut LineEditorHost) {
+        self.last_kill = false;
+        self.last_yank = None;
+        let state = match self.completion.take() {
+            Some(mut state) => {
+                state.index = (state.index + 1) % state.candidates.len();
+                state
+            }
+            None => {
+                let candidates = host.complete(&self.line, self.cursor);
+                if candidates.is_empty() {
+                    return;
+                }
+                CompletionState {
+                    candidates,
+                    index: 0,
+                    line: self.line.clone(),
+                    cursor: self.cursor,
+                }
+            }
+        };
+        let (line, cursor) = state.apply();
+        self.line = line;
+        self.cursor = cursor;
+        // With a single candidate there is nothing to cycle through
+        if state.candidates.len() > 1 {
+            self.completion = Some(state);
+        }
+    }
+
+    /// Applies `action` while an incremental search is active.  Actions
+    /// that don't affect the search end it, leaving the matching entry
+    /// as the line being edited, and are returned so that they can be
+    /// applied to it.
+    fn apply_search_action(&mut self, history: &mut History, action: Action) -> Option<Action> {
+        let search = self.search.as_mut()?;
+        match action {
+            Action::InsertChar(n, c) => {
+                for _ in 0..n {
+                    search.pattern.push(c);
+                }
+                let from = search.matched.map(|(idx, _)| idx).or(search.start);
+                search.search(history, from);
+            }
+            Action::InsertText(n, text) => {
+                for _ in 0..n {
+                    search.pattern.push_str(&text);
+                }
+                let from = search.matched.map(|(idx, _)| idx).or(search.start);
+                search.search(history, from);
+            }
+            Action::Kill(Movement::BackwardChar(n)) => {
+                for _ in 0..n {
+                    search.pattern.pop();
+                }
+                let from = search.start;
+                search.search(history, from);
+            }
+            Action::HistoryIncSearchBackwards | Action::HistoryIncSearchForwards => {
+                search.direction = if action == Action::HistoryIncSearchBackwards {
+                    SearchDirection::Backwards
+                } else {
+                    SearchDirection::Forwards
+                };
+                let from = match search.matched {
+                    Some((idx, _)) => search.direction.next(idx),
+                    None => search.start,
+                };
+                search.search(history, from);
+            }
+            Action::Repaint => {}
+            Action::CancelSearch => self.search = None,
+            action => {
+                if let Some((idx, cursor)) = self.search.take().and_then(|s| s.matched) {
+                    self.recall(history, idx);
+                    self.cursor = cursor;
+                }
+                return Some(action);
+            }
+        }
+        None
+    }
+
+    fn render(&mut self, host: &mut LineEditorHost) -> Result<(), Error> {
+        let cols = self.terminal.get_screen_size()?.cols;
+
+        // Start again from the first row of the previous render
+        let mut changes = vec![];
+        if self.cursor_row > 0 {
+            changes.push(Change::CursorPosition {
+                x: Position::NoChange,
+                y: Position::Relative(-(self.cursor_row as isize)),
+            });
+        }
+        changes.push(Change::Text("\r".to_owned()));
+        changes.push(Change::ClearToEndOfScreen(Default::default()));
+
+        let prompt = match &self.search {
+            Some(search) => vec![Change::Text(search.status())],
+            None => host.render_prompt(&self.prompt),
+        };
+        let continuation = host.render_prompt(&self.continuation_prompt);
+
+        let (line, cursor) = match &self.search {
+            Some(search) => match search.matched {
+                Some((idx, cursor)) => (
+                    host.history()
+                        .get(idx)
+                        .map(Cow::into_owned)
+                        .unwrap_or_default(),
+                    cursor,
+                ),
+                None => (String::new(), 0),
+            },
+            None => match self.echo {
+                EchoMode::Normal => (self.line.clone(), self.cursor),
+                EchoMode::Masked(mask) => mask_text(&self.line, self.cursor, mask),
+                EchoMode::Hidden => (String::new(), 0),
+            },
+        };
+
+        let mut pos = RenderPosition::new(cols);
+        pos.push_changes(&mut changes, prompt);
+        let mut cursor_pos = None;
+        let mut offset = 0;
+        for (idx, text) in line.split('\n').enumerate() {
+            if idx > 0 {
+                changes.push(Change::Text("\r\n".to_owned()));
+                pos.new_line();
+                pos.push_changes(&mut changes, continuation.clone());
+            }
+            if cursor_pos.is_none() && cursor <= offset + text.len() {
+                pos.push_text(&mut changes, &text[..cursor - offset]);
+                cursor_pos = Some(pos.normalized());
+                pos.push_text(&mut changes, &text[cursor - offset..]);
+            } else {
+                pos.push_text(&mut changes, text);
+            }
+            offset += text.len() + 1;
+        }
+        // If the text ended in the last column, the terminal hasn't yet
+        // moved to the next row; make it do so, so that we know where
+        // the cursor is.
+        if pos.col >= cols {
+            changes.push(Change::Text(" \r".to_owned()));
+            pos.new_line();
+        }
+        let (cursor_row, cursor_col) = cursor_pos.unwrap_or_else(|| pos.normalized());
+
+        let mut end_row = pos.row;
+        if let Some(completion) = &self.completion {
+            if host.show_completion_menu() {
+                let (menu, rows) = completion.render_menu(cols);
+                changes.extend(menu);
+                end_row += rows;
+            }
+        }
+
+        // Return to the cursor position
+        if end_row > cursor_row {
+            changes.push(Change::CursorPosition {
+                x: Position::NoChange,
+                y: Position::Relative(-((end_row - cursor_row) as isize)),
+            });
+        }
+        changes.push(Change::Text("\r".to_owned()));
+        if cursor_col > 0 {
+            changes.push(Change::CursorPosition {
+                x: Position::Relative(cursor_col as isize),
+                y: Position::NoChange,
+            });
+        }
+        self.cursor_row = cursor_row;
+
+        self.terminal.render(&changes)?;
+        self.terminal.flush()
+    }
+}
+
+/// Tracks the position of the terminal cursor, relative to the start
+/// of the prompt, as text is rendered and wraps at the edge of the
+/// screen
+struct RenderPosition {
+    cols: usize,
+    row: usize,
+    /// When this is `cols`, the terminal is waiting to wrap to the
+    /// next row before it prints the next character
+    col: usize,
+}
+
+impl RenderPosition {
+    fn new(cols: usize) -> Self {
+        Self {
+            cols,
+            row: 0,
+            col: 0,
+        }
+    }
+
+    fn push_text(&mut self, changes: &mut Vec<Change>, text: &str) {
+        for grapheme in text.graphemes(true) {
+            let width = grapheme.width();
+            // A double width character that doesn't fit is moved to
+            // the next row
+            if self.col + width > self.cols {
+                self.row += 1;
+                self.col = 0;
+            }
+            self.col += width;
+        }
+        if !text.is_empty() {
+            changes.push(Change::Text(text.to_owned()));
+        }
+    }
+
+    fn push_changes(&mut self, changes: &mut Vec<Change>, prompt: Vec<Change>) {
+        for change in prompt {
+            match change {
+                Change::Text(text) => self.push_text(changes, &text),
+                change => changes.push(change),
+            }
+        }
+    }
+
+    fn new_line(&mut self) {
+        self.row += 1;
+        self.col = 0;
+    }
+
+    /// Returns the row and column at which the next character will be
+    /// printed
+    fn normalized(&self) -> (usize, usize) {
+        if self.col >= self.cols {
+            (self.row + 1, 0)
+        } else {
+            (self.row, self.col)
+        }
+    }
+}
+
+/// Returns the byte offset of the start of the word that precedes
+/// `cursor`, where words are made of the characters matching `is_word`
+fn backward_word(line: &str, cursor: usize, is_word: fn(char) -> bool) -> usize {
+    let mut in_word = false;
+    for (idx, c) in line[..cursor].char_indices().rev() {
+        if is_word(c) {
+            in_word = true;
+        } else if in_word {
+            return idx + c.len_utf8();
+        }
+    }
+    0
+}
+
+/// Returns the byte offset of the end of the word that follows
+/// `cursor`, where words are made of the characters matching `is_word`
+fn forward_word(line: &str, cursor: usize, is_word: fn(char) -> bool) -> usize {
+    let mut in_word = false;
+    for (idx, c) in line[cursor..].char_indices() {
+        if is_word(c) {
+            in_word = true;
+        } else if in_word {
+            return cursor + idx;
+        }
+    }
+    line.len()
+}
+
+/// Returns `line` with each grapheme other than the line breaks replaced
+/// by `mask`, along with the byte offset within it that corresponds to
+/// `cursor`
+fn mask_text(line: &str, cursor: usize, mask: char) -> (String, usize) {
+    let mut masked = String::new();
+    let mut masked_cursor = None;
+    for (idx, grapheme) in line.grapheme_indices(true) {
+        if idx >= cursor && masked_cursor.is_none() {
+            masked_cursor = Some(masked.len());
+        }
+        if grapheme == "\n" {
+            masked.push('\n');
+        } else {
+            masked.push(mask);
+        }
+    }
+    let masked_cursor = masked_cursor.unwrap_or_else(|| masked.len());
+    (masked, masked_cursor)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::terminal::ScreenSize;
+    use std::collections::VecDeque;
+
+    struct FakeTerminal {
+        input: VecDeque<InputEvent>,
+    }
+
+    impl Terminal for FakeTerminal {
+        fn set_raw_mode(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+        fn get_screen_size(&mut self) -> Result<ScreenSize, Error> {
+            Ok(ScreenSize {
+                rows: 24,
+                cols: 80,
+                xpixel: 0,
+                ypixel: 0,
+            })
+        }
+        fn set_screen_size(&mut self, _size: ScreenSize) -> Result<(), Error> {
+            Ok(())
+        }
+        fn render(&mut self, _changes: &[Change]) -> Result<(), Error> {
+            Ok(())
+        }
+        fn flush(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+        fn poll_input(&mut self, blocking: Blocking) -> Result<Option<InputEvent>, Error> {
+            match self.input.pop_front() {
+                Some(event) => Ok(Some(event)),
+                None if blocking == Blocking::Wait => bail!("ran out of input"),
+                None => Ok(None),
+            }
+        }
+    }
+
+    fn key(key: KeyCode, modifiers: Modifiers) -> InputEvent {
+        InputEvent::Key(KeyEvent { key, modifiers })
+    }
+
+    fn ctrl(c: char) -> InputEvent {
+        key(KeyCode::Char(c), Modifiers::CTRL)
+    }
+
+    fn alt(c: char) -> InputEvent {
+        key(KeyCode::Char(c), Modifiers::ALT)
+    }
+
+    fn text(s: &str) -> Vec<InputEvent> {
+        s.chars()
+            .map(|c| key(KeyCode::Char(c), Modifiers::NONE))
+            .collect()
+    }
+
+    fn read_line(host: &mut LineEditorHost, events: Vec<InputEvent>) -> Option<String> {
+        let terminal = FakeTerminal {
+            input: events.into_iter().collect(),
+        };
+        LineEditor::new(terminal).read_line(host).unwrap()
+    }
+
+    fn host_with_history(lines: &[&str]) -> NopLineEditorHost {
+        let mut host = NopLineEditorHost::default();
+        for line in lines {
+            host.history().add(line);
+        }
+        host
+    }
+
+    #[test]
+    fn editing() {
+        let mut host = NopLineEditorHost::default();
+        let mut events = text("helo");
+        events.push(key(KeyCode::LeftArrow, Modifiers::NONE));
+        events.extend(text("l"));
+        events.push(ctrl('E'));
+        events.extend(text(" wurld"));
+        events.push(key(KeyCode::LeftArrow, Modifiers::NONE));
+        events.push(key(KeyCode::LeftArrow, Modifiers::NONE));
+        events.push(key(KeyCode::LeftArrow, Modifiers::NONE));
+        events.push(key(KeyCode::Delete, Modifiers::NONE));
+        events.extend(text("o"));
+        events.push(key(KeyCode::Enter, Modifiers::NONE));
+        assert_eq!(read_line(&mut host, events), Some("hello world".to_owned()));
+
+        let mut events = text("abc");
+        events.push(ctrl('C'));
+        assert_eq!(read_line(&mut host, events), None);
+        assert_eq!(read_line(&mut host, vec![ctrl('D')]), None);
+    }
+
+    #[test]
+    fn words() {
+        let mut host = NopLineEditorHost::default();
+        let enter = key(KeyCode::Enter, Modifiers::NONE);
+
+        let mut events = text("one two-three  four");
+        events.extend(vec![alt('b'), alt('b'), alt('d'), alt('f')]);
+        events.extend(text("!"));
+        events.push(enter.clone());
+        assert_eq!(
+            read_line(&mut host, events),
+            Some("one two-  four!".to_owned())
+        );
+
+        // CTRL-W removes everything back to the whitespace
+        let mut events = text("ls foo/bar ");
+        events.push(ctrl('W'));
+        events.push(enter.clone());
+        assert_eq!(read_line(&mut host, events), Some("ls ".to_owned()));
+    }
+
+    #[test]
+    fn kill_ring() {
+        let mut host = NopLineEditorHost::default();
+        let enter = key(KeyCode::Enter, Modifiers::NONE);
+
+        // Consecutive kills are yanked back as one
+        let mut events = text("one two three");
+        events.extend(vec![
+            ctrl('W'),
+            ctrl('W'),
+            ctrl('Y'),
+            ctrl('Y'),
+            enter.clone(),
+        ]);
+        assert_eq!(
+            read_line(&mut host, events),
+            Some("one two threetwo three".to_owned())
+        );
+
+        // The kill ring persists across lines, and ALT-Y replaces the
+        // yanked text with older kills
+        let mut events = text("four five");
+        events.push(ctrl('U'));
+        events.extend(text("six "));
+        events.extend(vec![ctrl('Y'), alt('y'), enter.clone()]);
+        assert_eq!(
+            read_line(&mut host, events),
+            Some("six two three".to_owned())
+        );
+
+        // ALT-Y does nothing unless it follows a yank
+        let mut events = text("x");
+        events.extend(vec![alt('y'), enter.clone()]);
+        assert_eq!(read_line(&mut host, events), Some("x".to_owned()));
+    }
+
+    /// Treats lines with an unclosed quote as incomplete
+    #[derive(Default)]
+    struct QuoteHost {
+        history: BasicHistory,
+    }
+
+    impl LineEditorHost for QuoteHost {
+        fn history(&mut self) -> &mut History {
+            &mut self.history
+        }
+
+        fn is_line_complete(&self, line: &str) -> bool {
+            line.matches('"').count() % 2 == 0
+        }
+    }
+
+    #[test]
+    fn multi_line() {
+        let mut host = QuoteHost::default();
+        let enter = key(KeyCode::Enter, Modifiers::NONE);
+
+        let mut events = text("echo \"one");
+        events.push(enter.clone());
+        events.extend(text("two\""));
+        events.push(enter.clone());
+        assert_eq!(
+            read_line(&mut host, events),
+            Some("echo \"one\ntwo\"".to_owned())
+        );
+
+        // The arrows move between the lines, keeping the column, and
+        // the line movements apply to the current line
+        let mut events = text("abcdef");
+        events.push(key(KeyCode::Enter, Modifiers::ALT));
+        events.extend(text("xy"));
+        events.push(key(KeyCode::UpArrow, Modifiers::NONE));
+        events.extend(text("-"));
+        events.push(ctrl('E'));
+        events.push(key(KeyCode::DownArrow, Modifiers::NONE));
+        events.extend(text("z"));
+        events.push(ctrl('A'));
+        events.extend(text(">"));
+        events.push(enter.clone());
+        assert_eq!(
+            read_line(&mut host, events),
+            Some("ab-cdef\n>xyz".to_owned())
+        );
+    }
+
+    #[test]
+    fn masked() {
+        let mut host = host_with_history(&["previous"]);
+        let terminal = FakeTerminal {
+            input: VecDeque::new(),
+        };
+        let mut editor = LineEditor::new(terminal);
+        editor.set_echo_mode(EchoMode::Masked('*'));
+
+        // The history can't be recalled, but pasting is allowed
+        let mut events = vec![key(KeyCode::UpArrow, Modifiers::NONE), ctrl('R')];
+        events.extend(text("se"));
+        events.push(InputEvent::Paste("cret".to_owned()));
+        events.push(key(KeyCode::Enter, Modifiers::NONE));
+        editor.terminal().input = events.into_iter().collect();
+        assert_eq!(
+            editor.read_line(&mut host).unwrap(),
+            Some("secret".to_owned())
+        );
+
+        // Killed text isn't saved, so there is nothing to yank
+        let mut events = text("one two");
+        events.extend(vec![
+            ctrl('W'),
+            ctrl('Y'),
+            key(KeyCode::Enter, Modifiers::NONE),
+        ]);
+        editor.terminal().input = events.into_iter().collect();
+        assert_eq!(
+            editor.read_line(&mut host).unwrap(),
+            Some("one ".to_owned())
+        );
+
+        assert_eq!(mask_text("ab\ncd", 4, '*'), ("**\n**".to_owned(), 4));
+        // Each grapheme is masked by a single character
+        assert_eq!(mask_text("e\u{301}x", 3, '*'), ("**".to_owned(), 1));
+        assert_eq!(mask_text("abc", 3, '*'), ("***".to_owned(), 3));
+    }
+
+    #[test]
+    fn render_position() {
+        let mut changes = vec![];
+        let mut pos = RenderPosition::new(4);
+        pos.push_text(&mut changes, "abcd");
+        assert_eq!((pos.row, pos.col), (0, 4));
+        assert_eq!(pos.normalized(), (1, 0));
+        pos.push_text(&mut changes, "e");
+        assert_eq!(pos.normalized(), (1, 1));
+        // The double width character doesn't fit on the row
+        pos.push_text(&mut changes, "ab\u{3042}");
+        assert_eq!(pos.normalized(), (2, 2));
+    }
+
+    #[test]
+    fn recall() {
+        let mut host = host_with_history(&["one", "two"]);
+        let up = key(KeyCode::UpArrow, Modifiers::NONE);
+        let down = key(KeyCode::DownArrow, Modifiers::NONE);
+        let enter = key(KeyCode::Enter, Modifiers::NONE);
+
+        let events = vec![
+            up.clone(),
+            up.clone(),
+            up.clone(),
+            down.clone(),
+            enter.clone(),
+        ];
+        assert_eq!(read_line(&mut host, events), Some("two".to_owned()));
+
+        // Moving past the newest entry restores the line being edited
+        let mut events = text("three");
+        events.extend(vec![up.clone(), down.clone(), enter.clone()]);
+        assert_eq!(read_line(&mut host, events), Some("three".to_owned()));
+    }
+
+    #[test]
+    fn incremental_search() {
+        let mut host = host_with_history(&["cargo build", "ls", "cargo test"]);
+        let enter = key(KeyCode::Enter, Modifiers::NONE);
+
+        let mut events = vec![ctrl('R')];
+        events.extend(text("car"));
+        events.push(ctrl('R'));
+        events.push(enter.clone());
+        assert_eq!(read_line(&mut host, events), Some("cargo build".to_owned()));
+
+        // Editing after the search applies to the matching entry, with
+        // the cursor at the start of the match
+        let mut events = vec![ctrl('R')];
+        events.extend(text("test"));
+        events.push(ctrl('K'));
+        events.push(enter.clone());
+        assert_eq!(read_line(&mut host, events), Some("cargo ".to_owned()));
+
+        let mut events = text("pwd");
+        events.push(ctrl('R'));
+        events.extend(text("ls"));
+        events.push(ctrl('G'));
+        events.push(enter.clone());
+        assert_eq!(read_line(&mut host, events), Some("pwd".to_owned()));
+    }
+
+    /// Completes the word before the cursor from a fixed list
+    #[derive(Default)]
+    struct WordHost {
+        history: BasicHistory,
+    }
+
+    impl LineEditorHost for WordHost {
+        fn history(&mut self) -> &mut History {
+            &mut self.history
+        }
+
+        fn complete(&self, line: &str, cursor_pos: usize) -> Vec<CompletionCandidate> {
+            let start = line[..cursor_pos]
+                .rfind(' ')
+                .map(|idx| idx + 1)
+                .unwrap_or(0);
+            let prefix = &line[start..cursor_pos];
+            ["checkout", "cherry-pick", "clone"]
+                .iter()
+                .filter(|word| word.starts_with(prefix))
+                .map(|word| CompletionCandidate {
+                    range: start..cursor_pos,
+                    text: word.to_string(),
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn completion() {
+        let mut host = WordHost::default();
+        let tab = key(KeyCode::Tab, Modifiers::NONE);
+        let enter = key(KeyCode::Enter, Modifiers::NONE);
+
+        let mut events = text("git cl");
+        events.extend(vec![tab.clone(), enter.clone()]);
+        assert_eq!(read_line(&mut host, events), Some("git clone".to_owned()));
+
+        // Tab cycles through the candidates, wrapping around
+        let mut events = text("git ch");
+        events.extend(vec![tab.clone(), tab.clone(), tab.clone(), tab.clone()]);
+        events.extend(text(" -"));
+        events.push(enter.clone());
+        assert_eq!(
+            read_line(&mut host, events),
+            Some("git cherry-pick -".to_owned())
+        );
+
+        // Escape restores the line
+        let mut events = text("git ch");
+        events.extend(vec![tab.clone(), tab.clone()]);
+        events.push(key(KeyCode::Escape, Modifiers::NONE));
+        events.push(enter.clone());
+        assert_eq!(read_line(&mut host, events), Some("git ch".to_owned()));
+
+        let mut events = text("git x");
+        events.extend(vec![tab.clone(), enter.clone()]);
+        assert_eq!(read_line(&mut host, events), Some("git x".to_owned()));
+    }
+
+    #[test]
+    fn poll_line() {
+        let mut host = NopLineEditorHost::default();
+        let timeout = Duration::from_millis(0);
+        let mut editor = LineEditor::new(FakeTerminal {
+            input: text("hello").into_iter().collect(),
+        });
+
+        assert!(!editor.is_editing());
+        assert_eq!(
+            editor.read_line_with_timeout(&mut host, timeout).unwrap(),
+            LineStatus::Pending
+        );
+        assert!(editor.is_editing());
+
+        // Text can be injected while the line is pending
+        assert_eq!(
+            editor
+                .perform_action(&mut host, Action::InsertText(1, " world".to_owned()))
+                .unwrap(),
+            LineStatus::Pending
+        );
+        editor
+            .terminal()
+            .input
+            .push_back(key(KeyCode::Enter, Modifiers::NONE));
+        assert_eq!(
+            editor.read_line_with_timeout(&mut host, timeout).unwrap(),
+            LineStatus::Done(Some("hello world".to_owned()))
+        );
+        assert!(!editor.is_editing());
+
+        // and the edit can be cancelled
+        editor.terminal().input.extend(text("abc"));
+        assert_eq!(
+            editor.read_line_with_timeout(&mut host, timeout).unwrap(),
+            LineStatus::Pending
+        );
+        assert_eq!(
+            editor.perform_action(&mut host, Action::Cancel).unwrap(),
+            LineStatus::Done(None)
+        );
+        assert!(editor.perform_action(&mut host, Action::Cancel).is_err());
+    }
+}
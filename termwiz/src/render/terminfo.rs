@@ -33,6 +33,14 @@ impl TerminfoRenderer {
         self.caps.terminfo_db().and_then(|db| db.get::<T>())
     }
 
+    /// Returns the attributes that are active after resetting them
+    /// with SGR; the hyperlink is not affected by that.
+    fn reset_attr(&self) -> CellAttributes {
+        let mut attr = CellAttributes::default();
+        attr.hyperlink = self.current_attr.hyperlink.clone();
+        attr
+    }
+
     fn attr_apply<F: FnOnce(&mut CellAttributes)>(&mut self, func: F) {
         self.pending_attr = Some(match self.pending_attr.take() {
             Some(mut attr) => {
@@ -86,12 +94,25 @@ impl TerminfoRenderer {
                         .reverse(attr.reverse())
                         .invisible(attr.invisible())
                         .to(out.by_ref())?;
+                    // sgr turns off everything else, including the colors
+                    self.current_attr = self.reset_attr();
+                    self.current_attr
+                        .set_intensity(attr.intensity())
+                        .set_underline(if attr.underline() == Underline::None {
+                            Underline::None
+                        } else {
+                            Underline::Single
+                        })
+                        .set_blink(attr.blink())
+                        .set_reverse(attr.reverse())
+                        .set_invisible(attr.invisible());
                 } else {
                     if let Some(exit) = self.get_capability::<cap::ExitAttributeMode>() {
                         exit.expand().to(out.by_ref())?;
                     } else {
                         write!(out, "{}", CSI::Sgr(Sgr::Reset))?;
                     }
+                    self.current_attr = self.reset_attr();
 
                     if attr.intensity() != self.current_attr.intensity() {
                         match attr.intensity() {
@@ -121,14 +142,6 @@ impl TerminfoRenderer {
                         Underline
                     );
 
-                    attr_on_off!(
-                        EnterUnderlineMode,
-                        ExitUnderlineMode,
-                        attr,
-                        underline,
-                        Underline
-                    );
-
                     if attr.blink() != self.current_attr.blink() {
                         if let Some(attr) = self.get_capability::<cap::EnterBlinkMode>() {
                             attr.expand().to(out.by_ref())?;
@@ -160,6 +173,18 @@ impl TerminfoRenderer {
                         CSI::Sgr(Sgr::StrikeThrough(attr.strikethrough()))
                     )?;
                 }
+
+                // terminfo only describes a single underline; the
+                // capabilities above have selected that, so upgrade it
+                if attr.underline() == Underline::Double
+                    && self.current_attr.underline() != Underline::Double
+                {
+                    write!(out, "{}", CSI::Sgr(Sgr::Underline(Underline::Double)))?;
+                }
+
+                if attr.overline() != self.current_attr.overline() {
+                    write!(out, "{}", CSI::Sgr(Sgr::Overline(attr.overline())))?;
+                }
             }
 
             let has_true_color = self.caps.color_level() == ColorLevel::TrueColor;
@@ -226,7 +251,7 @@ impl TerminfoRenderer {
                 }
             }
 
-            if self.caps.hyperlinks() {
+            if self.caps.hyperlinks() && attr.hyperlink != self.current_attr.hyperlink {
                 if let Some(link) = attr.hyperlink.as_ref() {
                     let osc = OperatingSystemCommand::SetHyperlink(Some((**link).clone()));
                     write!(out, "{}", osc)?;
@@ -417,6 +442,9 @@ impl TerminfoRenderer {
                 Change::Attribute(AttributeChange::StrikeThrough(value)) => {
                     record!(set_strikethrough, value);
                 }
+                Change::Attribute(AttributeChange::Overline(value)) => {
+                    record!(set_overline, value);
+                }
                 Change::Attribute(AttributeChange::Blink(value)) => {
                     record!(set_blink, value);
                 }
@@ -475,13 +503,13 @@ impl TerminfoRenderer {
                     x: Position::NoChange,
                     y: Position::Relative(n),
                 } if *n < 0 => {
-                    self.cursor_up(*n as u32, out)?;
+                    self.cursor_up(-*n as u32, out)?;
                 }
                 Change::CursorPosition {
                     x: Position::Relative(n),
                     y: Position::NoChange,
                 } if *n < 0 => {
-                    self.cursor_left(*n as u32, out)?;
+                    self.cursor_left(-*n as u32, out)?;
                 }
                 Change::CursorPosition {
                     x: Position::Relative(n),
@@ -506,8 +534,8 @@ impl TerminfoRenderer {
                             out,
                             "{}",
                             CSI::Cursor(Cursor::Position {
-                                line: OneBased::from_zero_based(x),
-                                col: OneBased::from_zero_based(y),
+                                line: OneBased::from_zero_based(y),
+                                col: OneBased::from_zero_based(x),
                             })
                         )?;
                     }
@@ -1048,6 +1076,70 @@ mod test {
         );
     }
 
+    #[test]
+    fn colors_restored_after_reset() {
+        let mut out = FakeTerm::new(xterm_terminfo());
+        out.render(&[
+            Change::Attribute(AttributeChange::Foreground(AnsiColor::Maroon.into())),
+            Change::Text("a".into()),
+            Change::AllAttributes(
+                CellAttributes::default()
+                    .set_intensity(Intensity::Bold)
+                    .set_foreground(AnsiColor::Maroon)
+                    .clone(),
+            ),
+            Change::Text("b".into()),
+            Change::Attribute(AttributeChange::Intensity(Intensity::Bold)),
+            Change::Text("c".into()),
+        ])
+        .unwrap();
+
+        let result = out.parse();
+        assert_eq!(
+            result,
+            vec![
+                Action::CSI(CSI::Sgr(Sgr::Foreground(AnsiColor::Maroon.into()))),
+                Action::Print('a'),
+                Action::Esc(Esc::Code(EscCode::AsciiCharacterSet)),
+                Action::CSI(CSI::Sgr(Sgr::Reset)),
+                Action::CSI(CSI::Sgr(Sgr::Intensity(Intensity::Bold))),
+                // Setting bold reset the color, so it is set again
+                Action::CSI(CSI::Sgr(Sgr::Foreground(AnsiColor::Maroon.into()))),
+                Action::Print('b'),
+                // but nothing is emitted when the attributes are unchanged
+                Action::Print('c'),
+            ]
+        );
+    }
+
+    #[test]
+    fn cursor_position_no_terminfo() {
+        let mut out = FakeTerm::new(no_terminfo_all_enabled());
+        out.render(&[
+            Change::CursorPosition {
+                x: Position::Absolute(5),
+                y: Position::Absolute(2),
+            },
+            Change::CursorPosition {
+                x: Position::Relative(3),
+                y: Position::NoChange,
+            },
+        ])
+        .unwrap();
+
+        let result = out.parse();
+        assert_eq!(
+            result,
+            vec![
+                Action::CSI(CSI::Cursor(Cursor::Position {
+                    line: OneBased::new(3),
+                    col: OneBased::new(6),
+                })),
+                Action::CSI(CSI::Cursor(Cursor::Right(3))),
+            ]
+        );
+    }
+
     #[test]
     fn truecolor() {
         let mut out = FakeTerm::new(xterm_terminfo());
@@ -20,13 +20,32 @@ bitflags! {
         const SCANNED_IMPLICIT_HYPERLINKS = 1<<2;
         /// true if we found implicit hyperlinks in the last scan
         const HAS_IMPLICIT_HYPERLINKS = 1<<3;
+        /// Printing wrapped from the last cell of this line onto the
+        /// next, so the two are part of the same logical line
+        const WRAPPED = 1<<4;
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Line {
     bits: LineBits,
     cells: Vec<Cell>,
+    /// The range of columns that have changed since the dirty bit
+    /// was last cleared.  This is only meaningful while the line is
+    /// dirty; an empty range in that state means that the extent of
+    /// the change is unknown and the whole line must be considered
+    /// to have changed.
+    #[serde(skip)]
+    damage: Range<usize>,
+}
+
+/// The damage is deliberately excluded from the comparison; it
+/// describes how the line came to have its content rather than
+/// the content itself.
+impl PartialEq for Line {
+    fn eq(&self, other: &Line) -> bool {
+        self.bits == other.bits && self.cells == other.cells
+    }
 }
 
 impl Line {
@@ -34,7 +53,11 @@ impl Line {
         let mut cells = Vec::with_capacity(width);
         cells.resize(width, Cell::default());
         let bits = LineBits::DIRTY;
-        Self { bits, cells }
+        Self {
+            bits,
+            cells,
+            damage: 0..0,
+        }
     }
 
     pub fn from_text(s: &str, attrs: &CellAttributes) -> Line {
@@ -52,6 +75,7 @@ impl Line {
         Line {
             cells,
             bits: LineBits::DIRTY,
+            damage: 0..0,
         }
     }
 
@@ -59,12 +83,87 @@ impl Line {
         let blank = Cell::default();
         self.cells.clear();
         self.cells.resize(width, blank);
-        self.bits = LineBits::DIRTY;
+        self.bits = LineBits::NONE;
+        self.set_dirty();
     }
 
     pub fn resize(&mut self, width: usize) {
         self.cells.resize(width, Cell::default());
-        self.bits |= LineBits::DIRTY;
+        self.set_dirty();
+    }
+
+    /// Returns true if the text of this line continues on the next
+    /// line because it was wrapped there when printing, rather than
+    /// being moved there by an explicit newline
+    #[inline]
+    pub fn is_wrapped(&self) -> bool {
+        (self.bits & LineBits::WRAPPED) == LineBits::WRAPPED
+    }
+
+    /// Record whether the text of this line continues on the next line
+    pub fn set_wrapped(&mut self, wrapped: bool) {
+        if wrapped {
+            self.bits |= LineBits::WRAPPED;
+        } else {
+            self.bits &= !LineBits::WRAPPED;
+        }
+    }
+
+    /// Append the cells of `other` to this line, such as when joining
+    /// the physical lines that make up a logical line.  The result is
+    /// wrapped if `other` was.
+    pub fn append_line(&mut self, mut other: Line) {
+        self.invalidate_implicit_hyperlinks();
+        other.invalidate_implicit_hyperlinks();
+        self.bits = (self.bits & !LineBits::WRAPPED)
+            | (other.bits & (LineBits::WRAPPED | LineBits::HAS_HYPERLINK));
+        self.cells.append(&mut other.cells);
+        self.set_dirty();
+    }
+
+    /// Split the line into lines that are no wider than `width`.
+    /// All but the last of the resulting lines are marked as wrapped;
+    /// the last inherits the wrapped state of this line.
+    /// A double width cell is never split across two lines, which
+    /// means that a line may be shorter than `width` before it wraps.
+    /// The length of each of the resulting lines can be used to map
+    /// a column in this line to its new position.
+    pub fn wrap(mut self, width: usize) -> Vec<Line> {
+        let width = width.max(1);
+        self.invalidate_implicit_hyperlinks();
+        let bits = (self.bits & LineBits::HAS_HYPERLINK) | LineBits::DIRTY;
+        let wrapped = self.is_wrapped();
+
+        let mut lines = vec![];
+        let mut cells: Vec<Cell> = Vec::with_capacity(width);
+        let mut iter = self.cells.into_iter();
+        while let Some(cell) = iter.next() {
+            // Keep a wide cell together with the cells that it overlaps
+            let mut group = vec![cell];
+            for _ in 1..group[0].width() {
+                match iter.next() {
+                    Some(cell) => group.push(cell),
+                    None => break,
+                }
+            }
+            if !cells.is_empty() && cells.len() + group.len() > width {
+                lines.push(Line {
+                    bits: bits | LineBits::WRAPPED,
+                    cells: std::mem::replace(&mut cells, Vec::with_capacity(width)),
+                    damage: 0..0,
+                });
+            }
+            cells.append(&mut group);
+        }
+
+        let mut last = Line {
+            bits,
+            cells,
+            damage: 0..0,
+        };
+        last.set_wrapped(wrapped);
+        lines.push(last);
+        lines
     }
 
     /// Check whether the dirty bit is set.
@@ -75,17 +174,50 @@ impl Line {
         (self.bits & LineBits::DIRTY) == LineBits::DIRTY
     }
 
-    /// Force the dirty bit set.
+    /// Force the dirty bit set, and consider the entire line to
+    /// have changed.
     /// FIXME: this is abused by term::Screen, want to remove or rethink it.
     #[inline]
     pub fn set_dirty(&mut self) {
         self.bits |= LineBits::DIRTY;
+        self.damage = 0..usize::max_value();
+    }
+
+    /// Set the dirty bit and record that the specified range of
+    /// columns has changed.  This is used to mark changes that
+    /// don't alter the cells themselves, such as the cursor moving.
+    pub fn damage_columns(&mut self, cols: Range<usize>) {
+        if cols.start >= cols.end {
+            return;
+        }
+        if self.is_dirty() && self.damage.start < self.damage.end {
+            self.damage = self.damage.start.min(cols.start)..self.damage.end.max(cols.end);
+        } else if !self.is_dirty() {
+            self.damage = cols;
+        }
+        // else: the line is already dirty with unknown extent, which
+        // subsumes this range
+        self.bits |= LineBits::DIRTY;
+    }
+
+    /// Returns the range of columns that have changed since the dirty
+    /// bit was last cleared.  The range may extend beyond the width of
+    /// the line.  Returns an empty range if the line is not dirty.
+    pub fn damaged_columns(&self) -> Range<usize> {
+        if !self.is_dirty() {
+            0..0
+        } else if self.damage.start >= self.damage.end {
+            0..usize::max_value()
+        } else {
+            self.damage.clone()
+        }
     }
 
     /// Clear the dirty bit.
     #[inline]
     pub fn clear_dirty(&mut self) {
         self.bits &= !LineBits::DIRTY;
+        self.damage = 0..0;
     }
 
     /// If we have any cells with an implicit hyperlink, remove the hyperlink
@@ -116,7 +248,7 @@ impl Line {
         }
 
         self.bits &= !LineBits::HAS_IMPLICIT_HYPERLINKS;
-        self.bits |= LineBits::DIRTY;
+        self.set_dirty();
     }
 
     /// Scan through the line and look for sequences that match the provided
@@ -239,7 +371,9 @@ impl Line {
         }
 
         self.invalidate_implicit_hyperlinks();
-        self.bits |= LineBits::DIRTY;
+        // The prior cell is included as it may be a wide character
+        // that is blanked out by invalidate_grapheme_at_or_before
+        self.damage_columns(idx.saturating_sub(1)..idx + width.max(1));
         if cell.attrs().hyperlink.is_some() {
             self.bits |= LineBits::HAS_HYPERLINK;
         }
@@ -281,6 +415,7 @@ impl Line {
         }
 
         self.cells.insert(x, cell);
+        self.damage_columns(x..usize::max_value());
     }
 
     pub fn erase_cell(&mut self, x: usize) {
@@ -288,6 +423,7 @@ impl Line {
         self.invalidate_grapheme_at_or_before(x);
         self.cells.remove(x);
         self.cells.push(Cell::default());
+        self.damage_columns(x.saturating_sub(1)..usize::max_value());
     }
 
     pub fn fill_range(&mut self, cols: impl Iterator<Item = usize>, cell: &Cell) {
@@ -326,6 +462,12 @@ impl Line {
         CellCluster::make_cluster(self.visible_cells())
     }
 
+    /// Like `cluster`, but places the clusters into `clusters`,
+    /// reusing the memory that it holds
+    pub fn cluster_into(&self, clusters: &mut Vec<CellCluster>) {
+        CellCluster::make_cluster_into(self.visible_cells(), clusters)
+    }
+
     pub fn cells(&self) -> &[Cell] {
         &self.cells
     }
@@ -403,3 +545,186 @@ impl<'a> From<&'a str> for Line {
         Line::from_text(s, &CellAttributes::default())
     }
 }
+
+/// A compact representation of a `Line`, intended for holding lines
+/// of scrollback that are not being displayed or modified.
+/// The text of the cells is held in a single string, their attributes
+/// are run-length encoded and trailing blank cells are not stored.
+/// A typical line of text takes a few hundred bytes rather than the
+/// several kilobytes used by the equivalent `Vec<Cell>`.
+#[derive(Debug, Clone)]
+pub struct CompressedLine {
+    bits: LineBits,
+    /// The number of cells in the original line
+    width: usize,
+    text: String,
+    /// The byte offset of the end of the text of each cell.
+    /// This is empty when each cell holds exactly one char, which
+    /// is the common case.
+    cell_ends: Vec<u32>,
+    /// The attributes of the stored cells, as pairs of
+    /// (number of cells, attributes)
+    attrs: Vec<(u32, CellAttributes)>,
+}
+
+impl CompressedLine {
+    pub fn new(line: &Line) -> Self {
+        let blank = Cell::default();
+        let stored = line
+            .cells
+            .iter()
+            .rposition(|cell| *cell != blank)
+            .map(|idx| idx + 1)
+            .unwrap_or(0);
+
+        let mut text = String::new();
+        let mut cell_ends = Vec::with_capacity(stored);
+        let mut single_chars = true;
+        let mut attrs: Vec<(u32, CellAttributes)> = vec![];
+
+        for cell in &line.cells[..stored] {
+            let s = cell.str();
+            text.push_str(s);
+            cell_ends.push(text.len() as u32);
+            if s.chars().count() != 1 {
+                single_chars = false;
+            }
+
+            match attrs.last_mut() {
+                Some((count, attr)) if attr == cell.attrs() => *count += 1,
+                _ => attrs.push((1, cell.attrs().clone())),
+            }
+        }
+
+        if single_chars {
+            cell_ends = vec![];
+        }
+        text.shrink_to_fit();
+        cell_ends.shrink_to_fit();
+        attrs.shrink_to_fit();
+
+        Self {
+            bits: line.bits & !LineBits::DIRTY,
+            width: line.cells.len(),
+            text,
+            cell_ends,
+            attrs,
+        }
+    }
+
+    /// Reconstitute the line.  The result is marked dirty.
+    pub fn decompress(&self) -> Line {
+        let mut cells = Vec::with_capacity(self.width);
+        let mut attrs = self
+            .attrs
+            .iter()
+            .flat_map(|(count, attr)| std::iter::repeat(attr).take(*count as usize));
+
+        if self.cell_ends.is_empty() {
+            for (c, attr) in self.text.chars().zip(&mut attrs) {
+                cells.push(Cell::new(c, attr.clone()));
+            }
+        } else {
+            let mut start = 0;
+            for (&end, attr) in self.cell_ends.iter().zip(&mut attrs) {
+                let end = end as usize;
+                cells.push(Cell::new_grapheme(&self.text[start..end], attr.clone()));
+                start = end;
+            }
+        }
+        if cells.len() < self.width {
+            cells.resize(self.width, Cell::default());
+        }
+
+        Line {
+            bits: self.bits | LineBits::DIRTY,
+            cells,
+            damage: 0..0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::AnsiColor;
+
+    #[test]
+    fn compress_round_trip() {
+        let mut line = Line::with_width(10);
+        let mut red = CellAttributes::default();
+        red.set_foreground(AnsiColor::Maroon);
+        line.set_cell(0, Cell::new('h', red.clone()));
+        line.set_cell(1, Cell::new('i', red.clone()));
+        line.set_cell(3, Cell::new('!', CellAttributes::default()));
+
+        let compressed = CompressedLine::new(&line);
+        assert_eq!(compressed.text, "hi !");
+        assert!(compressed.cell_ends.is_empty());
+        assert_eq!(compressed.attrs.len(), 2);
+        assert_eq!(compressed.decompress(), line);
+
+        line.set_cell(5, Cell::new_grapheme("e\u{301}", red.clone()));
+        let compressed = CompressedLine::new(&line);
+        assert_eq!(compressed.cell_ends, vec![1, 2, 3, 4, 5, 8]);
+        assert_eq!(compressed.decompress(), line);
+
+        let blank = Line::with_width(80);
+        let compressed = CompressedLine::new(&blank);
+        assert!(compressed.text.is_empty());
+        assert_eq!(compressed.decompress(), blank);
+    }
+
+    #[test]
+    fn cluster_into_reuses() {
+        let mut red = CellAttributes::default();
+        red.set_foreground(AnsiColor::Maroon);
+        let mut line: Line = "hello world".into();
+        line.set_cell(6, Cell::new('w', red));
+
+        let summarize = |clusters: &[CellCluster]| -> Vec<(String, Vec<usize>)> {
+            clusters
+                .iter()
+                .map(|c| (c.text.clone(), c.byte_to_cell_idx.clone()))
+                .collect()
+        };
+
+        let mut clusters = vec![];
+        line.cluster_into(&mut clusters);
+        assert_eq!(summarize(&clusters), summarize(&line.cluster()));
+        assert_eq!(clusters.len(), 3);
+
+        // Reusing the buffer for a line with fewer clusters must not
+        // leave any stale clusters behind
+        let short: Line = "hi".into();
+        short.cluster_into(&mut clusters);
+        assert_eq!(summarize(&clusters), vec![("hi".to_string(), vec![0, 1])]);
+    }
+
+    #[test]
+    fn wrap_and_append() {
+        let line: Line = "hello world".into();
+        let lines = line.clone().wrap(4);
+        let text: Vec<String> = lines.iter().map(Line::as_str).collect();
+        assert_eq!(text, vec!["hell", "o wo", "rld"]);
+        assert!(lines[0].is_wrapped());
+        assert!(lines[1].is_wrapped());
+        assert!(!lines[2].is_wrapped());
+
+        let mut joined = Line::with_width(0);
+        for l in lines {
+            joined.append_line(l);
+        }
+        assert_eq!(joined.as_str(), "hello world");
+        assert!(!joined.is_wrapped());
+
+        // The double width cell doesn't fit at the end of the first
+        // line, so it moves to the second
+        let wide: Line = "ab\u{3000}c".into();
+        let lines = wide.wrap(3);
+        let text: Vec<String> = lines.iter().map(Line::as_str).collect();
+        assert_eq!(text, vec!["ab", "\u{3000}c"]);
+        assert_eq!(lines[0].cells().len(), 2);
+        assert_eq!(lines[1].cells().len(), 3);
+    }
+}
@@ -2,6 +2,7 @@ use crate::cell::{AttributeChange, Cell, CellAttributes};
 use crate::color::ColorAttribute;
 use crate::image::ImageCell;
 use ordered_float::NotNaN;
+use rayon::prelude::*;
 use std::borrow::Cow;
 use std::cmp::min;
 use unicode_segmentation::UnicodeSegmentation;
@@ -10,7 +11,7 @@ pub mod change;
 pub mod line;
 
 pub use self::change::{Change, Image, TextureCoordinate};
-pub use self::line::Line;
+pub use self::line::{CompressedLine, Line};
 
 /// Position holds 0-based positioning information, where
 /// Absolute(0) is the start of the line or column,
@@ -88,13 +89,36 @@ pub struct Surface {
     cursor_color: ColorAttribute,
 }
 
+/// Differencing a region with at least this many cells is split
+/// across multiple threads; for smaller regions the overhead of
+/// distributing the work outweighs the gain.
+const PARALLEL_DIFF_THRESHOLD: usize = 16 * 1024;
+
+/// The number of rows that are differenced by each parallel task
+const PARALLEL_DIFF_ROWS: usize = 16;
+
+/// Unchanged text of up to this many bytes is printed again rather
+/// than moving the cursor over it, as the movement sequence would be
+/// at least as long.
+const REPRINT_THRESHOLD: usize = 4;
+
+/// A blank run at the end of a line is cleared rather than printed
+/// once at least this many of its cells have changed
+const CLEAR_LINE_THRESHOLD: usize = 4;
+
 #[derive(Default)]
 struct DiffState {
     changes: Vec<Change>,
     /// Keep track of the cursor position that the change stream
     /// selects for updates so that we can avoid emitting redundant
-    /// position changes.
+    /// position changes.  This is the (row, column) at which the
+    /// next cell would be printed.
     cursor: Option<(usize, usize)>,
+    /// The text of the unchanged cells that have been passed over
+    /// on the cursor row since the last printed cell, while they are
+    /// short enough to be worth printing again and have the active
+    /// attributes.
+    gap: Option<String>,
     /// Similarly, we keep track of the cell attributes that we have
     /// activated for change stream to avoid over-emitting.
     /// Tracking the cursor and attributes in this way helps to coalesce
@@ -106,31 +130,15 @@ impl DiffState {
     #[inline]
     fn diff_cells(&mut self, col_num: usize, row_num: usize, cell: &Cell, other_cell: &Cell) {
         if cell == other_cell {
+            self.skip_cell(row_num, other_cell);
             return;
         }
-        self.cursor = match self.cursor.take() {
-            Some((cursor_row, cursor_col))
-                if cursor_row == row_num && cursor_col == col_num - 1 =>
-            {
-                // It is on the column prior, so we don't need
-                // to explicitly move it.  Record the effective
-                // position for next time.
-                Some((row_num, col_num))
-            }
-            _ => {
-                // Need to explicitly move the cursor
-                self.changes.push(Change::CursorPosition {
-                    y: Position::Absolute(row_num),
-                    x: Position::Absolute(col_num),
-                });
-                // and remember the position for next time
-                Some((row_num, col_num))
-            }
-        };
+        self.move_cursor(col_num, row_num);
 
         // we could get fancy and try to minimize the update traffic
         // by computing a series of AttributeChange values here.
-        // For now, let's just record the new value
+        // For now, let's just record the new value; the renderer
+        // only emits the attributes that differ from those active.
         self.attr = match self.attr.take() {
             Some(ref attr) if attr == other_cell.attrs() => {
                 // Active attributes match, so we don't need
@@ -144,18 +152,237 @@ impl DiffState {
                 Some(other_cell.attrs().clone())
             }
         };
-        // A little bit of bloat in the code to avoid runs of single
-        // character Text entries; just append to the string.
-        let result_len = self.changes.len();
-        if result_len > 0 && self.changes[result_len - 1].is_text() {
-            if let Some(Change::Text(ref mut prefix)) = self.changes.get_mut(result_len - 1) {
-                prefix.push_str(other_cell.str());
+        self.push_text(other_cell.str());
+
+        // The cursor position is unknown after printing a zero
+        // width grapheme, as terminals disagree about it
+        self.cursor = match other_cell.width() {
+            0 => None,
+            width => Some((row_num, col_num + width)),
+        };
+        self.gap = Some(String::new());
+    }
+
+    /// Records that the cursor passed over an unchanged cell
+    fn skip_cell(&mut self, row_num: usize, other_cell: &Cell) {
+        let on_cursor_row = match self.cursor {
+            Some((row, _)) => row == row_num,
+            None => false,
+        };
+        let same_attr = self.attr.as_ref() == Some(other_cell.attrs());
+        self.gap = match self.gap.take() {
+            Some(mut gap)
+                if on_cursor_row
+                    && same_attr
+                    && gap.len() + other_cell.str().len() <= REPRINT_THRESHOLD =>
+            {
+                gap.push_str(other_cell.str());
+                Some(gap)
+            }
+            _ => None,
+        };
+    }
+
+    /// A little bit of bloat in the code to avoid runs of single
+    /// character Text entries; just append to the string.
+    fn push_text(&mut self, text: &str) {
+        if let Some(Change::Text(ref mut prefix)) = self.changes.last_mut() {
+            prefix.push_str(text);
+            return;
+        }
+        self.changes.push(Change::Text(text.to_string()));
+    }
+
+    /// Returns the cheapest change that moves the cursor from where
+    /// the change stream left it to `col_num`, `row_num`.
+    /// The cursor is only moved to the right within a row; after the
+    /// last column is printed the terminal may be waiting to wrap,
+    /// which leaves the cursor a column short of where we think it is.
+    fn cursor_movement(&self, col_num: usize, row_num: usize) -> Change {
+        match self.cursor {
+            Some((row, col)) if row == row_num && col < col_num => Change::CursorPosition {
+                x: Position::Relative((col_num - col) as isize),
+                y: Position::NoChange,
+            },
+            // This is usually rendered as a short \r\n sequence
+            Some((row, _)) if row + 1 == row_num && col_num == 0 => Change::CursorPosition {
+                x: Position::Absolute(0),
+                y: Position::Relative(1),
+            },
+            _ => Change::CursorPosition {
+                x: Position::Absolute(col_num),
+                y: Position::Absolute(row_num),
+            },
+        }
+    }
+
+    /// Moves the cursor to `col_num`, `row_num`, either by printing
+    /// the unchanged text in between again or by emitting a movement
+    fn move_cursor(&mut self, col_num: usize, row_num: usize) {
+        if self.cursor == Some((row_num, col_num)) {
+            return;
+        }
+        match (self.cursor, self.gap.take()) {
+            (Some((row, _)), Some(ref gap)) if row == row_num && !gap.is_empty() => {
+                self.push_text(gap);
             }
+            _ => {
+                let movement = self.cursor_movement(col_num, row_num);
+                self.changes.push(movement);
+            }
+        }
+        self.cursor = Some((row_num, col_num));
+    }
+
+    /// Clears the line from `col_num` onwards to `background`
+    fn clear_to_end_of_line(&mut self, col_num: usize, row_num: usize, background: ColorAttribute) {
+        self.move_cursor(col_num, row_num);
+        self.changes.push(Change::ClearToEndOfLine(background));
+        // Clearing also resets the attributes, and leaves the cursor
+        // where it was
+        self.attr = Some(CellAttributes::default().set_background(background).clone());
+        self.gap = None;
+    }
+
+    /// Difference the `width` cells of `line` starting at column `x`
+    /// against those of `other_line` starting at column `other_x`
+    fn diff_line(
+        &mut self,
+        row_num: usize,
+        line: &Line,
+        x: usize,
+        width: usize,
+        other_line: &Line,
+        other_x: usize,
+    ) {
+        // If the region extends to the end of the line, then the blank
+        // cells at the end of `other_line` can be cleared in one go
+        let cleared = if x + width == line.cells().len() {
+            blank_tail(&other_line.cells()[other_x..other_x + width]).and_then(
+                |(start, background)| {
+                    let changed = line.cells()[x + start..]
+                        .iter()
+                        .zip(other_line.cells()[other_x + start..].iter())
+                        .filter(|(cell, other_cell)| cell != other_cell)
+                        .count();
+                    if changed >= CLEAR_LINE_THRESHOLD {
+                        Some((x + start, background))
+                    } else {
+                        None
+                    }
+                },
+            )
         } else {
-            self.changes
-                .push(Change::Text(other_cell.str().to_string()));
+            None
+        };
+
+        for ((col_num, cell), (_, other_cell)) in line
+            .visible_cells()
+            .skip(x)
+            .take_while(|(col_num, _)| *col_num < x + width)
+            .zip(other_line.visible_cells().skip(other_x))
+        {
+            if let Some((start, background)) = cleared {
+                if col_num >= start && cell != other_cell {
+                    self.clear_to_end_of_line(col_num, row_num, background);
+                    return;
+                }
+            }
+            self.diff_cells(col_num, row_num, cell, other_cell);
+        }
+    }
+
+    /// Append the changes that a separate `DiffState` computed for
+    /// the rows that follow those processed by this one.
+    /// The other state started out without knowing which attributes
+    /// were active or where the cursor was, so its leading attribute
+    /// change may be redundant and its leading cursor movement may be
+    /// needlessly long; they are adjusted so that the result is
+    /// identical to having processed all of the rows with a single state.
+    fn append(&mut self, mut other: DiffState) {
+        if other.changes.is_empty() {
+            return;
+        }
+        // The first change is always an absolute cursor movement, as
+        // the other state didn't know the cursor position either, and
+        // is followed by the attributes unless the line was cleared.
+        let redundant_attr = match (&self.attr, other.changes.get(1)) {
+            (Some(attr), Some(Change::AllAttributes(first))) => attr == first,
+            _ => false,
+        };
+        if redundant_attr {
+            other.changes.remove(1);
+        }
+        if let Some(Change::CursorPosition {
+            x: Position::Absolute(col_num),
+            y: Position::Absolute(row_num),
+        }) = other.changes.first().cloned()
+        {
+            other.changes[0] = self.cursor_movement(col_num, row_num);
+        }
+        self.changes.append(&mut other.changes);
+        self.cursor = other.cursor;
+        self.gap = other.gap;
+        self.attr = other.attr;
+    }
+}
+
+/// If `cells` ends with blank cells that have no attributes other
+/// than their background color, returns the index of the first of
+/// them together with that color.
+fn blank_tail(cells: &[Cell]) -> Option<(usize, ColorAttribute)> {
+    let background = cells.last()?.attrs().background;
+    let blank = Cell::new(
+        ' ',
+        CellAttributes::default().set_background(background).clone(),
+    );
+    let num_blank = cells
+        .iter()
+        .rev()
+        .take_while(|cell| **cell == blank)
+        .count();
+    if num_blank == 0 {
+        None
+    } else {
+        Some((cells.len() - num_blank, background))
+    }
+}
+
+/// Computes the changes for a sequence of (row number, line, other line)
+/// tuples.  When `parallel` is true, the rows are divided into groups
+/// that are differenced concurrently and the results are stitched back
+/// together in order.
+fn diff_rows(
+    rows: &[(usize, &Line, &Line)],
+    x: usize,
+    width: usize,
+    other_x: usize,
+    parallel: bool,
+) -> Vec<Change> {
+    if !parallel {
+        let mut diff_state = DiffState::default();
+        for &(row_num, line, other_line) in rows {
+            diff_state.diff_line(row_num, line, x, width, other_line, other_x);
         }
+        return diff_state.changes;
+    }
+
+    let states: Vec<DiffState> = rows
+        .par_chunks(PARALLEL_DIFF_ROWS)
+        .map(|chunk| {
+            let mut diff_state = DiffState::default();
+            for &(row_num, line, other_line) in chunk {
+                diff_state.diff_line(row_num, line, x, width, other_line, other_x);
+            }
+            diff_state
+        })
+        .collect();
+
+    let mut result = DiffState::default();
+    for state in states {
+        result.append(state);
     }
+    result.changes
 }
 
 impl Surface {
@@ -405,6 +632,9 @@ impl Surface {
             StrikeThrough(value) => {
                 self.attributes.set_strikethrough(*value);
             }
+            Overline(value) => {
+                self.attributes.set_overline(*value);
+            }
             Invisible(value) => {
                 self.attributes.set_invisible(*value);
             }
@@ -660,33 +890,52 @@ impl Surface {
         other: &Surface,
         other_x: usize,
         other_y: usize,
+    ) -> Vec<Change> {
+        self.diff_region_with_parallelism(
+            x,
+            y,
+            width,
+            height,
+            other,
+            other_x,
+            other_y,
+            width * height >= PARALLEL_DIFF_THRESHOLD,
+        )
+    }
+
+    /// Like `diff_region`, but explicitly selects whether the rows
+    /// are differenced in parallel rather than deciding based on the
+    /// size of the region.  This exists for the benefit of the
+    /// benchmarks.
+    #[doc(hidden)]
+    #[allow(clippy::too_many_arguments)]
+    pub fn diff_region_with_parallelism(
+        &self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        other: &Surface,
+        other_x: usize,
+        other_y: usize,
+        parallel: bool,
     ) -> Vec<Change> {
         assert!(x + width <= self.width);
         assert!(y + height <= self.height);
         assert!(other_x + width <= other.width);
         assert!(other_y + height <= other.height);
 
-        let mut diff_state = DiffState::default();
-
-        for ((row_num, line), other_line) in self
+        let rows: Vec<(usize, &Line, &Line)> = self
             .lines
             .iter()
             .enumerate()
             .skip(y)
             .take_while(|(row_num, _)| *row_num < y + height)
             .zip(other.lines.iter().skip(other_y))
-        {
-            for ((col_num, cell), (_, other_cell)) in line
-                .visible_cells()
-                .skip(x)
-                .take_while(|(col_num, _)| *col_num < x + width)
-                .zip(other_line.visible_cells().skip(other_x))
-            {
-                diff_state.diff_cells(col_num, row_num, cell, other_cell);
-            }
-        }
+            .map(|((row_num, line), other_line)| (row_num, line, other_line))
+            .collect();
 
-        diff_state.changes
+        diff_rows(&rows, x, width, other_x, parallel)
     }
 
     pub fn diff_lines(&self, other_lines: Vec<&Line>) -> Vec<Change> {
@@ -1214,6 +1463,49 @@ mod test {
         }
     }
 
+    #[test]
+    fn parallel_diff_matches_serial() {
+        // Large enough to take the parallel path in diff_region
+        let (width, height) = (300, 100);
+        let mut s = Surface::new(width, height);
+        let mut other = Surface::new(width, height);
+
+        for row in 0..height {
+            other.add_change(Change::CursorPosition {
+                x: Position::Absolute(0),
+                y: Position::Absolute(row),
+            });
+            // Alternate attributes in runs that straddle the groups
+            // of rows that are differenced in parallel, so that the
+            // stitching of the attribute state is exercised
+            if (row / 5) % 2 == 0 {
+                other.add_change(AttributeChange::Intensity(Intensity::Bold));
+            } else {
+                other.add_change(AttributeChange::Intensity(Intensity::Normal));
+            }
+            if row % 7 != 0 {
+                other.add_change(format!("row {} {}", row, "x".repeat(row)));
+            }
+        }
+        s.add_change("some text that is already present");
+
+        let rows: Vec<(usize, &Line, &Line)> = s
+            .lines
+            .iter()
+            .zip(other.lines.iter())
+            .enumerate()
+            .map(|(row_num, (line, other_line))| (row_num, line, other_line))
+            .collect();
+
+        let serial = diff_rows(&rows, 0, width, 0, false);
+        let parallel = diff_rows(&rows, 0, width, 0, true);
+        assert_eq!(serial, parallel);
+        assert_eq!(s.diff_screens(&other), serial);
+
+        s.add_changes(parallel);
+        assert_eq!(s.screen_chars_to_string(), other.screen_chars_to_string());
+    }
+
     #[test]
     fn diff_screens() {
         let mut s = Surface::new(4, 3);
@@ -1243,7 +1535,7 @@ mod test {
                     Change::Text("fo".into()),
                     Change::CursorPosition {
                         x: Position::Absolute(0),
-                        y: Position::Absolute(1),
+                        y: Position::Relative(1),
                     },
                     Change::Text("aa".into()),
                 ],
@@ -1277,7 +1569,7 @@ mod test {
                     Change::Text("XO".into()),
                     Change::CursorPosition {
                         x: Position::Absolute(0),
-                        y: Position::Absolute(1),
+                        y: Position::Relative(1),
                     },
                     Change::AllAttributes(CellAttributes::default()),
                     Change::Text("z".into()),
@@ -1289,6 +1581,43 @@ mod test {
         }
     }
 
+    #[test]
+    fn diff_screens_minimal() {
+        let mut s = Surface::new(10, 2);
+        s.add_change("abcdefghij0123456789");
+
+        let mut other = Surface::new(10, 2);
+        other.add_change("aXcYefghiZ01");
+
+        let changes = s.diff_screens(&other);
+        assert_eq!(
+            changes,
+            vec![
+                Change::CursorPosition {
+                    x: Position::Absolute(1),
+                    y: Position::Absolute(0),
+                },
+                Change::AllAttributes(CellAttributes::default()),
+                // The unchanged "c" is cheaper to print than to skip
+                Change::Text("XcY".into()),
+                // but "efghi" is not
+                Change::CursorPosition {
+                    x: Position::Relative(5),
+                    y: Position::NoChange,
+                },
+                Change::Text("Z".into()),
+                Change::CursorPosition {
+                    x: Position::Absolute(2),
+                    y: Position::Absolute(1),
+                },
+                Change::ClearToEndOfLine(ColorAttribute::Default),
+            ]
+        );
+
+        s.add_changes(changes);
+        assert_eq!(s.screen_chars_to_string(), other.screen_chars_to_string());
+    }
+
     #[test]
     fn draw_screens() {
         let mut s = Surface::new(4, 4);
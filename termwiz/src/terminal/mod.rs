@@ -6,6 +6,7 @@ use crate::surface::Change;
 use failure::Error;
 use num::{self, NumCast};
 use std::fmt::Display;
+use std::time::Duration;
 
 #[cfg(unix)]
 pub mod unix;
@@ -13,6 +14,9 @@ pub mod unix;
 pub mod windows;
 
 pub mod buffered;
+mod restore;
+
+pub use self::restore::install_panic_restore_hook;
 
 #[cfg(unix)]
 pub use self::unix::UnixTerminal;
@@ -43,6 +47,8 @@ pub struct ScreenSize {
 pub enum Blocking {
     DoNotWait,
     Wait,
+    /// Wait for at most the specified duration
+    WaitFor(Duration),
 }
 
 /// `Terminal` abstracts over some basic terminal capabilities.
@@ -74,6 +80,8 @@ pub trait Terminal {
     /// `poll_input` will not return until an event is available.
     /// If blocking == `Blocking:DoNotWait` then `poll_input` will return
     /// immediately with a value of `Ok(None)`.
+    /// If blocking == `Blocking::WaitFor(duration)` then `poll_input`
+    /// will return `Ok(None)` if no event arrives within `duration`.
     ///
     /// The possible values returned as `InputEvent`s depend on the
     /// mode of the terminal.  Most modes are not returned unless
@@ -0,0 +1,177 @@
+//! Restores the terminal to a usable state when the program panics.
+//!
+//! When an application that has put the terminal into raw mode and
+//! switched to the alternate screen panics, the panic message is printed
+//! into a screen that is about to be discarded, and the user is left with
+//! a shell that doesn't echo their input.  The hook installed by
+//! `install_panic_restore_hook` undoes those changes before the panic
+//! message is printed, so that the message is visible and the shell is
+//! usable once the program has terminated.
+use crate::escape::csi::{DecPrivateMode, DecPrivateModeCode, Mode, Sgr, CSI};
+use failure::Error;
+use std::panic;
+
+/// The escape sequences that return the terminal to its default
+/// presentation: leave the alternate screen, show the cursor, stop
+/// reporting mouse and paste events and reset the text attributes.
+fn restore_sequence() -> String {
+    let mut seq = String::new();
+    for code in &[
+        DecPrivateModeCode::BracketedPaste,
+        DecPrivateModeCode::SGRMouse,
+        DecPrivateModeCode::AnyEventMouse,
+        DecPrivateModeCode::ButtonEventMouse,
+        DecPrivateModeCode::MouseTracking,
+        DecPrivateModeCode::ClearAndEnableAlternateScreen,
+    ] {
+        seq.push_str(&format!(
+            "{}",
+            CSI::Mode(Mode::ResetDecPrivateMode(DecPrivateMode::Code(
+                code.clone()
+            )))
+        ));
+    }
+    seq.push_str(&format!(
+        "{}{}",
+        CSI::Mode(Mode::SetDecPrivateMode(DecPrivateMode::Code(
+            DecPrivateModeCode::ShowCursor
+        ))),
+        CSI::Sgr(Sgr::Reset)
+    ));
+    seq
+}
+
+#[cfg(unix)]
+mod imp {
+    use failure::Error;
+    use std::fs::OpenOptions;
+    use std::os::unix::io::{IntoRawFd, RawFd};
+    use termios::{tcsetattr, Termios, TCSANOW};
+
+    /// The terminal modes in effect when the hook was installed
+    pub struct SavedState {
+        /// Deliberately never closed; the hook may run at any point
+        /// in the lifetime of the program
+        fd: RawFd,
+        termios: Termios,
+    }
+
+    impl SavedState {
+        pub fn capture() -> Result<Self, Error> {
+            let fd = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open("/dev/tty")?
+                .into_raw_fd();
+            let termios =
+                Termios::from_fd(fd).map_err(|e| format_err!("get_termios failed: {}", e))?;
+            Ok(Self { fd, termios })
+        }
+
+        pub fn restore(&self, sequence: &str) {
+            // Errors are ignored; we're already on the way out and
+            // there is nothing useful that can be done about them
+            unsafe {
+                libc::write(self.fd, sequence.as_ptr() as *const _, sequence.len());
+            }
+            tcsetattr(self.fd, TCSANOW, &self.termios).ok();
+        }
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use failure::Error;
+    use std::fs::OpenOptions;
+    use std::io::{Error as IoError, Write};
+    use std::os::windows::io::AsRawHandle;
+    use winapi::um::consoleapi;
+    use winapi::um::wincon::{
+        SetConsoleCursorInfo, CONSOLE_CURSOR_INFO, ENABLE_VIRTUAL_TERMINAL_PROCESSING,
+    };
+
+    fn get_mode(file: &std::fs::File) -> Result<u32, Error> {
+        let mut mode = 0;
+        if unsafe { consoleapi::GetConsoleMode(file.as_raw_handle() as *mut _, &mut mode) } == 0 {
+            bail!("GetConsoleMode failed: {}", IoError::last_os_error());
+        }
+        Ok(mode)
+    }
+
+    /// The console modes in effect when the hook was installed
+    pub struct SavedState {
+        input_mode: u32,
+        output_mode: u32,
+    }
+
+    impl SavedState {
+        pub fn capture() -> Result<Self, Error> {
+            let input = OpenOptions::new().read(true).write(true).open("CONIN$")?;
+            let output = OpenOptions::new().read(true).write(true).open("CONOUT$")?;
+            Ok(Self {
+                input_mode: get_mode(&input)?,
+                output_mode: get_mode(&output)?,
+            })
+        }
+
+        pub fn restore(&self, sequence: &str) {
+            // Errors are ignored; we're already on the way out and
+            // there is nothing useful that can be done about them
+            if let Ok(mut output) = OpenOptions::new().read(true).write(true).open("CONOUT$") {
+                let handle = output.as_raw_handle() as *mut _;
+                let current_mode = get_mode(&output).unwrap_or(0);
+                if current_mode & ENABLE_VIRTUAL_TERMINAL_PROCESSING != 0 {
+                    output.write_all(sequence.as_bytes()).ok();
+                    output.flush().ok();
+                } else {
+                    let info = CONSOLE_CURSOR_INFO {
+                        dwSize: 100,
+                        bVisible: 1,
+                    };
+                    unsafe {
+                        SetConsoleCursorInfo(handle, &info);
+                    }
+                }
+                unsafe {
+                    consoleapi::SetConsoleMode(handle, self.output_mode);
+                }
+            }
+            if let Ok(input) = OpenOptions::new().read(true).write(true).open("CONIN$") {
+                unsafe {
+                    consoleapi::SetConsoleMode(input.as_raw_handle() as *mut _, self.input_mode);
+                }
+            }
+        }
+    }
+}
+
+/// Captures the current modes of the terminal associated with the
+/// process and installs a panic hook that restores them, leaves the
+/// alternate screen and shows the cursor before delegating to the
+/// previously installed hook to print the panic message.
+///
+/// This should be called before the terminal is placed into raw mode,
+/// typically prior to constructing the `Terminal`.
+pub fn install_panic_restore_hook() -> Result<(), Error> {
+    let saved = imp::SavedState::capture()?;
+    let sequence = restore_sequence();
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        saved.restore(&sequence);
+        previous(info);
+    }));
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sequence() {
+        assert_eq!(
+            restore_sequence(),
+            "\x1b[?2004l\x1b[?1006l\x1b[?1003l\x1b[?1002l\x1b[?1000l\x1b[?1049l\x1b[?25h\x1b[0m"
+        );
+    }
+}
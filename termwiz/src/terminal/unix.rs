@@ -248,7 +248,8 @@ impl UnixTerminal {
         let mut write = TtyWriteHandle::new(Fd::new(write)?);
         let saved_termios = write.get_termios()?;
         let renderer = TerminfoRenderer::new(caps.clone());
-        let input_parser = InputParser::new();
+        let mut input_parser = InputParser::new();
+        input_parser.set_report_key_up(caps.key_up_events());
         let input_queue = None;
 
         let (sigwinch_pipe, pipe_write) = UnixStream::pair()?;
@@ -329,6 +330,14 @@ impl Terminal for UnixTerminal {
             decset!(AnyEventMouse);
             decset!(SGRMouse);
         }
+        if self.caps.key_up_events() {
+            // Push the kitty keyboard protocol flags that disambiguate
+            // keys, report event types and report all keys as escapes.
+            // Keys with a legacy encoding, such as the arrows and the
+            // function keys, keep their final byte in this mode; the
+            // input parser understands both forms.
+            write!(self.write, "\x1b[>11u")?;
+        }
         self.write.flush()?;
 
         Ok(())
@@ -399,10 +408,14 @@ impl Terminal for UnixTerminal {
             poll(
                 pfd.as_mut_ptr(),
                 pfd.len() as _,
-                if blocking == Blocking::DoNotWait {
-                    0 // Immediate
-                } else {
-                    -1 // Infinite
+                match blocking {
+                    Blocking::DoNotWait => 0, // Immediate
+                    Blocking::Wait => -1,     // Infinite
+                    Blocking::WaitFor(duration) => {
+                        let millis =
+                            duration.as_secs() * 1000 + u64::from(duration.subsec_millis());
+                        millis.min(libc::c_int::max_value() as u64) as libc::c_int
+                    }
                 },
             )
         };
@@ -481,6 +494,10 @@ impl Drop for UnixTerminal {
             decreset!(SGRMouse);
             decreset!(AnyEventMouse);
         }
+        if self.caps.key_up_events() {
+            // Pop the keyboard protocol flags pushed by set_raw_mode
+            write!(self.write, "\x1b[<u").unwrap();
+        }
         self.write.flush().unwrap();
 
         signal_hook::unregister(self.sigwinch_id);
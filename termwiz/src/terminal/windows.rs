@@ -4,18 +4,22 @@ use std::collections::VecDeque;
 use std::fs::OpenOptions;
 use std::io::{stdin, stdout, Error as IoError, Read, Result as IoResult, Write};
 use std::os::windows::io::{AsRawHandle, RawHandle};
+use std::time::Duration;
 use std::{mem, ptr};
+use winapi::shared::winerror::WAIT_TIMEOUT;
 use winapi::um::consoleapi;
 use winapi::um::fileapi::{ReadFile, WriteFile};
 use winapi::um::handleapi::*;
 use winapi::um::processthreadsapi::GetCurrentProcess;
+use winapi::um::synchapi::WaitForSingleObject;
+use winapi::um::winbase::{INFINITE, WAIT_OBJECT_0};
 use winapi::um::wincon::{
     FillConsoleOutputAttribute, FillConsoleOutputCharacterW, GetConsoleScreenBufferInfo,
     SetConsoleCursorPosition, SetConsoleScreenBufferSize, SetConsoleTextAttribute,
     SetConsoleWindowInfo, CONSOLE_SCREEN_BUFFER_INFO, COORD, DISABLE_NEWLINE_AUTO_RETURN,
-    ENABLE_ECHO_INPUT, ENABLE_LINE_INPUT, ENABLE_MOUSE_INPUT, ENABLE_PROCESSED_INPUT,
-    ENABLE_VIRTUAL_TERMINAL_INPUT, ENABLE_VIRTUAL_TERMINAL_PROCESSING, ENABLE_WINDOW_INPUT,
-    INPUT_RECORD, SMALL_RECT,
+    ENABLE_ECHO_INPUT, ENABLE_EXTENDED_FLAGS, ENABLE_LINE_INPUT, ENABLE_MOUSE_INPUT,
+    ENABLE_PROCESSED_INPUT, ENABLE_QUICK_EDIT_MODE, ENABLE_VIRTUAL_TERMINAL_INPUT,
+    ENABLE_VIRTUAL_TERMINAL_PROCESSING, ENABLE_WINDOW_INPUT, INPUT_RECORD, SMALL_RECT,
 };
 use winapi::um::winnt::DUPLICATE_SAME_ACCESS;
 
@@ -70,6 +74,20 @@ fn dup<H: AsRawHandle>(h: H) -> Result<RawHandle, Error> {
     }
 }
 
+impl InputHandle {
+    /// Waits for at most `timeout` for input to become available.
+    /// Returns true if there is input to read.
+    fn wait_for_input(&mut self, timeout: Duration) -> Result<bool, Error> {
+        let millis = timeout.as_secs() * 1000 + u64::from(timeout.subsec_millis());
+        let millis = millis.min(u64::from(INFINITE - 1)) as u32;
+        match unsafe { WaitForSingleObject(self.handle as *mut _, millis) } {
+            WAIT_OBJECT_0 => Ok(true),
+            WAIT_TIMEOUT => Ok(false),
+            _ => bail!("WaitForSingleObject failed: {}", IoError::last_os_error()),
+        }
+    }
+}
+
 impl Drop for InputHandle {
     fn drop(&mut self) {
         unsafe { CloseHandle(self.handle as *mut _) };
@@ -318,6 +336,19 @@ pub struct WindowsTerminal {
     renderer: WindowsConsoleRenderer,
     input_parser: InputParser,
     input_queue: Option<VecDeque<InputEvent>>,
+    /// The size that was most recently reported via
+    /// `InputEvent::Resized`, as (rows, cols)
+    last_size: (usize, usize),
+}
+
+/// Returns the size of the visible window as (rows, cols).
+/// The console behaves differently from unix style terminals when
+/// printing in the last column, so we under report the width by one
+/// to make it easier to have similar semantics to unix style terminals.
+fn visible_size(info: &CONSOLE_SCREEN_BUFFER_INFO) -> (usize, usize) {
+    let rows = 1 + info.srWindow.Bottom - info.srWindow.Top;
+    let cols = info.srWindow.Right - info.srWindow.Left;
+    (rows.max(0) as usize, cols.max(0) as usize)
 }
 
 impl Drop for WindowsTerminal {
@@ -358,8 +389,10 @@ impl WindowsTerminal {
 
         let saved_input_mode = input_handle.get_input_mode()?;
         let saved_output_mode = output_handle.get_output_mode()?;
+        let last_size = visible_size(&output_handle.get_buffer_info()?);
+        let mut input_parser = InputParser::new();
+        input_parser.set_report_key_up(caps.key_up_events());
         let renderer = WindowsConsoleRenderer::new(caps);
-        let input_parser = InputParser::new();
 
         Ok(Self {
             input_handle,
@@ -369,6 +402,7 @@ impl WindowsTerminal {
             renderer,
             input_parser,
             input_queue: None,
+            last_size,
         })
     }
 
@@ -398,27 +432,29 @@ impl Terminal for WindowsTerminal {
     fn set_raw_mode(&mut self) -> Result<(), Error> {
         let mode = self.input_handle.get_input_mode()?;
 
+        // Quick edit mode causes conhost to consume mouse input for
+        // its own selection, so it must be turned off in order for
+        // mouse events to be delivered to us.  Changing it requires
+        // that ENABLE_EXTENDED_FLAGS also be set.
         self.input_handle.set_input_mode(
-            (mode & !(ENABLE_ECHO_INPUT | ENABLE_LINE_INPUT | ENABLE_PROCESSED_INPUT))
+            (mode
+                & !(ENABLE_ECHO_INPUT
+                    | ENABLE_LINE_INPUT
+                    | ENABLE_PROCESSED_INPUT
+                    | ENABLE_QUICK_EDIT_MODE))
                 | ENABLE_MOUSE_INPUT
-                | ENABLE_WINDOW_INPUT,
+                | ENABLE_WINDOW_INPUT
+                | ENABLE_EXTENDED_FLAGS,
         )
     }
 
     fn get_screen_size(&mut self) -> Result<ScreenSize, Error> {
         let info = self.output_handle.get_buffer_info()?;
-
-        // NOTE: the default console behavior is different from unix style
-        // terminals wrt. handling printing in the last column position.
-        // We under report the width by one to make it easier to have similar
-        // semantics to unix style terminals.
-
-        let visible_width = 0 + (info.srWindow.Right - info.srWindow.Left);
-        let visible_height = 1 + (info.srWindow.Bottom - info.srWindow.Top);
+        let (rows, cols) = visible_size(&info);
 
         Ok(ScreenSize {
-            rows: cast(visible_height)?,
-            cols: cast(visible_width)?,
+            rows,
+            cols,
             xpixel: 0,
             ypixel: 0,
         })
@@ -428,7 +464,7 @@ impl Terminal for WindowsTerminal {
         // FIXME: take into account the visible window size here;
         // this probably changes the size of everything including scrollback
         let size = COORD {
-            // See the note in get_screen_size() for info on the +1.
+            // See the note on visible_size() for info on the +1.
             X: cast(size.cols + 1)?,
             Y: cast(size.rows)?,
         };
@@ -462,6 +498,12 @@ impl Terminal for WindowsTerminal {
         let pending = match (self.input_handle.get_number_of_input_events()?, blocking) {
             (0, Blocking::DoNotWait) => return Ok(None),
             (0, Blocking::Wait) => 1,
+            (0, Blocking::WaitFor(duration)) => {
+                if !self.input_handle.wait_for_input(duration)? {
+                    return Ok(None);
+                }
+                self.input_handle.get_number_of_input_events()?.max(1)
+            }
             (pending, _) => pending,
         };
 
@@ -475,8 +517,38 @@ impl Terminal for WindowsTerminal {
             None => VecDeque::new(),
         };
 
+        // Mouse positions are reported relative to the screen buffer,
+        // which may be scrolled; translate them so that they are
+        // relative to the visible window, consistent with the rest
+        // of the terminal api.
+        let info = self.output_handle.get_buffer_info()?;
+        let left = info.srWindow.Left.max(0) as u16;
+        let top = info.srWindow.Top.max(0) as u16;
+
+        // The console reports changes to the size of the screen buffer,
+        // which is usually larger than the visible window; report the
+        // size of the window instead, consistent with get_screen_size().
+        // The window can also be resized without changing the buffer,
+        // in which case no record is delivered, so the size is checked
+        // whenever input arrives and reported if it has changed.
+        let size = visible_size(&info);
+        let resized = size != self.last_size;
+        self.last_size = size;
+        let (rows, cols) = size;
+
         self.input_parser
-            .decode_input_records(&records, &mut |evt| queue.push_back(evt));
+            .decode_input_records(&records, &mut |evt| match evt {
+                InputEvent::Mouse(mut mouse) => {
+                    mouse.x = mouse.x.saturating_sub(left);
+                    mouse.y = mouse.y.saturating_sub(top);
+                    queue.push_back(InputEvent::Mouse(mouse));
+                }
+                InputEvent::Resized { .. } => {}
+                evt => queue.push_back(evt),
+            });
+        if resized {
+            queue.push_back(InputEvent::Resized { rows, cols });
+        }
 
         let result = queue.pop_front();
         self.input_queue = Some(queue);
@@ -0,0 +1,233 @@
+//! A widget that shows a list of items, one per row, and allows one
+//! of them to be selected using the keyboard or the mouse.  The list
+//! scrolls to keep the selected item visible.
+use crate::cell::AttributeChange;
+use crate::input::{InputEvent, KeyCode, KeyEvent, Modifiers, MouseButtons, MouseEvent};
+use crate::surface::{Change, CursorShape, Position};
+use crate::widgets::{
+    fit_to_width, layout, CursorShapeAndPosition, RenderArgs, UpdateArgs, Widget, WidgetEvent,
+};
+use unicode_width::UnicodeWidthStr;
+
+/// The items and selection of a `ListView`.  This is owned by the
+/// application, which lends it to the widget, so that the items can
+/// be changed and the selection observed while the widget is in use.
+#[derive(Debug, Default, Clone)]
+pub struct ListState {
+    items: Vec<String>,
+    selected: usize,
+    /// The index of the item shown in the first row
+    top: usize,
+    /// The number of rows that were shown by the last render
+    page: usize,
+    /// The item that was chosen by pressing Enter or by clicking it
+    /// once it was selected
+    activated: Option<usize>,
+}
+
+impl ListState {
+    pub fn new(items: Vec<String>) -> Self {
+        Self {
+            items,
+            ..Default::default()
+        }
+    }
+
+    pub fn items(&self) -> &[String] {
+        &self.items
+    }
+
+    /// Replaces the items, keeping the selection within range
+    pub fn set_items(&mut self, items: Vec<String>) {
+        self.items = items;
+        self.select(self.selected);
+    }
+
+    /// Returns the index of the selected item, or None if the list
+    /// is empty
+    pub fn selected(&self) -> Option<usize> {
+        if self.items.is_empty() {
+            None
+        } else {
+            Some(self.selected)
+        }
+    }
+
+    /// Selects the `idx`th item, or the last item if there are fewer
+    pub fn select(&mut self, idx: usize) {
+        self.selected = idx.min(self.items.len().saturating_sub(1));
+    }
+
+    /// Moves the selection `delta` items down, or up if negative,
+    /// stopping at either end of the list
+    pub fn move_selection(&mut self, delta: isize) {
+        let idx = if delta < 0 {
+            self.selected.saturating_sub(delta.abs() as usize)
+        } else {
+            self.selected.saturating_add(delta as usize)
+        };
+        self.select(idx);
+    }
+
+    /// Returns the item that was chosen since the last call, if any
+    pub fn take_activated(&mut self) -> Option<usize> {
+        self.activated.take()
+    }
+
+    /// Adjusts `top` so that the selection is shown within `rows` rows
+    fn scroll_to_selection(&mut self, rows: usize) {
+        if rows == 0 {
+            return;
+        }
+        if self.selected < self.top {
+            self.top = self.selected;
+        } else if self.selected >= self.top + rows {
+            self.top = self.selected + 1 - rows;
+        }
+        // Don't leave rows empty at the bottom if the list can fill them
+        self.top = self.top.min(self.items.len().saturating_sub(rows));
+    }
+}
+
+/// Shows the items of a `ListState`
+pub struct ListView<'a> {
+    state: &'a mut ListState,
+    constraints: layout::Constraints,
+}
+
+impl<'a> ListView<'a> {
+    pub fn new(state: &'a mut ListState) -> Self {
+        Self {
+            state,
+            constraints: Default::default(),
+        }
+    }
+
+    pub fn with_constraints(state: &'a mut ListState, constraints: layout::Constraints) -> Self {
+        Self { state, constraints }
+    }
+}
+
+impl<'a> Widget for ListView<'a> {
+    fn process_event(&mut self, event: &WidgetEvent, _args: &mut UpdateArgs) -> bool {
+        let page = self.state.page.max(1) as isize;
+        match event {
+            WidgetEvent::Input(InputEvent::Key(KeyEvent { key, modifiers }))
+                if *modifiers == Modifiers::NONE =>
+            {
+                match key {
+                    KeyCode::UpArrow => self.state.move_selection(-1),
+                    KeyCode::DownArrow => self.state.move_selection(1),
+                    KeyCode::PageUp => self.state.move_selection(-page),
+                    KeyCode::PageDown => self.state.move_selection(page),
+                    KeyCode::Home => self.state.select(0),
+                    KeyCode::End => self.state.select(self.state.items.len()),
+                    KeyCode::Enter => self.state.activated = self.state.selected(),
+                    _ => return false,
+                }
+                true
+            }
+            WidgetEvent::Input(InputEvent::Mouse(MouseEvent {
+                y, mouse_buttons, ..
+            })) => {
+                if mouse_buttons.contains(MouseButtons::VERT_WHEEL) {
+                    if mouse_buttons.contains(MouseButtons::WHEEL_POSITIVE) {
+                        self.state.move_selection(-1);
+                    } else {
+                        self.state.move_selection(1);
+                    }
+                } else if mouse_buttons.contains(MouseButtons::LEFT) {
+                    let idx = self.state.top + *y as usize;
+                    if idx < self.state.items.len() {
+                        // Clicking the selected item chooses it
+                        if self.state.selected == idx {
+                            self.state.activated = Some(idx);
+                        }
+                        self.state.select(idx);
+                    }
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn render(&mut self, args: &mut RenderArgs) {
+        let (width, height) = args.surface.dimensions();
+        self.state.page = height;
+        self.state.scroll_to_selection(height);
+
+        args.surface
+            .add_change(Change::ClearScreen(Default::default()));
+        let visible = self
+            .state
+            .items
+            .iter()
+            .enumerate()
+            .skip(self.state.top)
+            .take(height);
+        for (row, (idx, item)) in visible.enumerate() {
+            let text = fit_to_width(item, width);
+            let mut changes = vec![Change::CursorPosition {
+                x: Position::Absolute(0),
+                y: Position::Absolute(row),
+            }];
+            if idx == self.state.selected {
+                // Highlight the whole row, not just the text
+                let padding = width.saturating_sub(UnicodeWidthStr::width(text));
+                changes.push(AttributeChange::Reverse(true).into());
+                changes.push(format!("{}{}", text, " ".repeat(padding)).into());
+                changes.push(AttributeChange::Reverse(false).into());
+            } else {
+                changes.push(text.into());
+            }
+            args.surface.add_changes(changes);
+        }
+
+        if let Some(selected) = self.state.selected() {
+            *args.cursor = CursorShapeAndPosition {
+                coords: (0, selected.saturating_sub(self.state.top)).into(),
+                shape: CursorShape::Hidden,
+                ..Default::default()
+            };
+        }
+    }
+
+    fn get_size_constraints(&self) -> layout::Constraints {
+        self.constraints
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scrolling() {
+        let items = (0..10).map(|i| i.to_string()).collect();
+        let mut state = ListState::new(items);
+
+        state.move_selection(4);
+        state.scroll_to_selection(3);
+        assert_eq!((state.selected(), state.top), (Some(4), 2));
+
+        // Scrolling up shows the selection in the first row
+        state.move_selection(-3);
+        state.scroll_to_selection(3);
+        assert_eq!((state.selected(), state.top), (Some(1), 1));
+
+        // The selection stops at the ends of the list
+        state.move_selection(20);
+        state.scroll_to_selection(3);
+        assert_eq!((state.selected(), state.top), (Some(9), 7));
+
+        // Removing items keeps the selection in range, and the list
+        // is scrolled back to fill the rows
+        state.set_items(vec!["a".to_owned(), "b".to_owned()]);
+        state.scroll_to_selection(3);
+        assert_eq!((state.selected(), state.top), (Some(1), 0));
+
+        state.set_items(vec![]);
+        assert_eq!(state.selected(), None);
+    }
+}
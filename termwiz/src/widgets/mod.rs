@@ -8,11 +8,28 @@ use failure::Error;
 use fnv::FnvHasher;
 use std::collections::{HashMap, VecDeque};
 use std::hash::BuildHasherDefault;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
 /// fnv is a more appropriate hasher for the WidgetIds we use in this module.
 type FnvHashMap<K, V> = HashMap<K, V, BuildHasherDefault<FnvHasher>>;
 
 pub mod layout;
+pub mod list;
+pub mod textinput;
+
+/// Returns the longest prefix of `text` that fits within `width`
+/// columns, so that it can be printed without wrapping
+fn fit_to_width(text: &str, width: usize) -> &str {
+    let mut used = 0;
+    for (idx, grapheme) in text.grapheme_indices(true) {
+        used += UnicodeWidthStr::width(grapheme);
+        if used > width {
+            return &text[..idx];
+        }
+    }
+    text
+}
 
 /// Describes an event that may need to be processed by the widget
 pub enum WidgetEvent {
@@ -238,7 +255,8 @@ impl<'widget> Ui<'widget> {
                     self.do_deliver(id, &WidgetEvent::Input(InputEvent::Mouse(m)))
                 }
                 WidgetEvent::Input(InputEvent::Paste(_))
-                | WidgetEvent::Input(InputEvent::Key(_)) => self.do_deliver(id, event),
+                | WidgetEvent::Input(InputEvent::Key(_))
+                | WidgetEvent::Input(InputEvent::KeyUp(_)) => self.do_deliver(id, event),
             };
 
             if handled {
@@ -316,6 +334,7 @@ impl<'widget> Ui<'widget> {
                     }
                 }
                 WidgetEvent::Input(InputEvent::Key(_))
+                | WidgetEvent::Input(InputEvent::KeyUp(_))
                 | WidgetEvent::Input(InputEvent::Paste(_)) => {
                     if let Some(focus) = self.focused {
                         self.deliver_event(focus, &event);
@@ -0,0 +1,261 @@
+//! A widget that edits a single line of text.  The text scrolls
+//! horizontally to keep the cursor visible when it is wider than
+//! the widget.
+use crate::input::{InputEvent, KeyCode, KeyEvent, Modifiers, MouseButtons, MouseEvent};
+use crate::surface::{Change, CursorShape, Position};
+use crate::widgets::{layout, CursorShapeAndPosition, RenderArgs, UpdateArgs, Widget, WidgetEvent};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// The text and cursor position of a `TextInput`.  This is owned by
+/// the application, which lends it to the widget, so that the text
+/// can be changed and observed while the widget is in use.
+#[derive(Debug, Default, Clone)]
+pub struct TextInputState {
+    text: String,
+    /// The byte offset of the cursor within `text`
+    cursor: usize,
+    /// The column of the text that is shown in the first column of
+    /// the widget
+    scroll: usize,
+    /// The text as it was when Enter was pressed
+    submitted: Option<String>,
+}
+
+impl TextInputState {
+    pub fn new(text: &str) -> Self {
+        Self {
+            text: text.to_owned(),
+            cursor: text.len(),
+            ..Default::default()
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Replaces the text, placing the cursor at its end
+    pub fn set_text(&mut self, text: &str) {
+        self.text = text.to_owned();
+        self.cursor = text.len();
+    }
+
+    /// Returns the byte offset of the cursor within the text
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Returns the text that was submitted by pressing Enter since
+    /// the last call, if any
+    pub fn take_submitted(&mut self) -> Option<String> {
+        self.submitted.take()
+    }
+
+    /// Inserts `text` at the cursor, omitting any line breaks
+    pub fn insert(&mut self, text: &str) {
+        for c in text.chars().filter(|&c| c != '\r' && c != '\n') {
+            self.text.insert(self.cursor, c);
+            self.cursor += c.len_utf8();
+        }
+    }
+
+    /// Returns the byte offset of the grapheme before the cursor
+    fn prev_grapheme(&self) -> usize {
+        self.text[..self.cursor]
+            .grapheme_indices(true)
+            .next_back()
+            .map(|(idx, _)| idx)
+            .unwrap_or(0)
+    }
+
+    /// Returns the byte offset of the grapheme after the cursor
+    fn next_grapheme(&self) -> usize {
+        self.text[self.cursor..]
+            .graphemes(true)
+            .next()
+            .map(|g| self.cursor + g.len())
+            .unwrap_or(self.cursor)
+    }
+
+    /// Returns the column in which the cursor is shown, relative to
+    /// the start of the text
+    fn cursor_column(&self) -> usize {
+        UnicodeWidthStr::width(&self.text[..self.cursor])
+    }
+
+    /// Adjusts `scroll` so that the cursor is shown within `width`
+    /// columns
+    fn scroll_to_cursor(&mut self, width: usize) {
+        if width == 0 {
+            return;
+        }
+        let col = self.cursor_column();
+        if col < self.scroll {
+            self.scroll = col;
+        } else if col >= self.scroll + width {
+            self.scroll = col + 1 - width;
+        }
+    }
+
+    /// Places the cursor at the grapheme that is shown in `col`
+    /// columns from the start of the text
+    fn move_to_column(&mut self, col: usize) {
+        let mut x = 0;
+        for (idx, grapheme) in self.text.grapheme_indices(true) {
+            let width = UnicodeWidthStr::width(grapheme);
+            if x + width > col {
+                self.cursor = idx;
+                return;
+            }
+            x += width;
+        }
+        self.cursor = self.text.len();
+    }
+}
+
+/// Edits the text of a `TextInputState`
+pub struct TextInput<'a> {
+    state: &'a mut TextInputState,
+}
+
+impl<'a> TextInput<'a> {
+    pub fn new(state: &'a mut TextInputState) -> Self {
+        Self { state }
+    }
+}
+
+impl<'a> Widget for TextInput<'a> {
+    fn process_event(&mut self, event: &WidgetEvent, _args: &mut UpdateArgs) -> bool {
+        let state = &mut *self.state;
+        match event {
+            WidgetEvent::Input(InputEvent::Key(KeyEvent { key, modifiers })) => {
+                match (*key, *modifiers) {
+                    (KeyCode::Char(c), Modifiers::NONE) | (KeyCode::Char(c), Modifiers::SHIFT) => {
+                        state.text.insert(state.cursor, c);
+                        state.cursor += c.len_utf8();
+                    }
+                    // The backspace key usually sends DEL, which is
+                    // decoded as the Delete key, so both delete backwards
+                    (KeyCode::Backspace, Modifiers::NONE)
+                    | (KeyCode::Delete, Modifiers::NONE)
+                    | (KeyCode::Char('H'), Modifiers::CTRL) => {
+                        let start = state.prev_grapheme();
+                        state.text.drain(start..state.cursor);
+                        state.cursor = start;
+                    }
+                    (KeyCode::Char('D'), Modifiers::CTRL) => {
+                        let end = state.next_grapheme();
+                        state.text.drain(state.cursor..end);
+                    }
+                    (KeyCode::Char('U'), Modifiers::CTRL) => {
+                        state.text.drain(..state.cursor);
+                        state.cursor = 0;
+                    }
+                    (KeyCode::Char('K'), Modifiers::CTRL) => state.text.truncate(state.cursor),
+                    (KeyCode::LeftArrow, Modifiers::NONE)
+                    | (KeyCode::Char('B'), Modifiers::CTRL) => state.cursor = state.prev_grapheme(),
+                    (KeyCode::RightArrow, Modifiers::NONE)
+                    | (KeyCode::Char('F'), Modifiers::CTRL) => state.cursor = state.next_grapheme(),
+                    (KeyCode::Home, _) | (KeyCode::Char('A'), Modifiers::CTRL) => state.cursor = 0,
+                    (KeyCode::End, _) | (KeyCode::Char('E'), Modifiers::CTRL) => {
+                        state.cursor = state.text.len()
+                    }
+                    (KeyCode::Enter, Modifiers::NONE) => state.submitted = Some(state.text.clone()),
+                    _ => return false,
+                }
+                true
+            }
+            WidgetEvent::Input(InputEvent::Paste(text)) => {
+                state.insert(text);
+                true
+            }
+            WidgetEvent::Input(InputEvent::Mouse(MouseEvent {
+                x, mouse_buttons, ..
+            })) => {
+                if mouse_buttons.contains(MouseButtons::LEFT) {
+                    let col = state.scroll + *x as usize;
+                    state.move_to_column(col);
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn render(&mut self, args: &mut RenderArgs) {
+        let (width, _height) = args.surface.dimensions();
+        self.state.scroll_to_cursor(width);
+
+        // Show the graphemes that fit entirely within the widget
+        let mut visible = String::new();
+        let mut col = 0;
+        for grapheme in self.state.text.graphemes(true) {
+            let grapheme_width = UnicodeWidthStr::width(grapheme);
+            if col >= self.state.scroll {
+                if col + grapheme_width > self.state.scroll + width {
+                    break;
+                }
+                visible.push_str(grapheme);
+            }
+            col += grapheme_width;
+        }
+
+        args.surface.add_changes(vec![
+            Change::ClearScreen(Default::default()),
+            Change::CursorPosition {
+                x: Position::Absolute(0),
+                y: Position::Absolute(0),
+            },
+            visible.into(),
+        ]);
+
+        *args.cursor = CursorShapeAndPosition {
+            coords: (
+                self.state.cursor_column().saturating_sub(self.state.scroll),
+                0,
+            )
+                .into(),
+            shape: CursorShape::SteadyBar,
+            ..Default::default()
+        };
+    }
+
+    fn get_size_constraints(&self) -> layout::Constraints {
+        *layout::Constraints::default().set_fixed_height(1)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scrolling() {
+        let mut state = TextInputState::new("0123456789");
+        state.scroll_to_cursor(4);
+        assert_eq!(state.scroll, 7);
+
+        state.cursor = 2;
+        state.scroll_to_cursor(4);
+        assert_eq!(state.scroll, 2);
+
+        // Double width characters occupy two columns
+        state.set_text("\u{3042}\u{3044}x");
+        state.scroll_to_cursor(4);
+        assert_eq!(state.scroll, 2);
+        state.move_to_column(1);
+        assert_eq!(state.cursor, 0);
+        state.move_to_column(2);
+        assert_eq!(state.cursor, 3);
+    }
+
+    #[test]
+    fn insert_omits_line_breaks() {
+        let mut state = TextInputState::new("ab");
+        state.cursor = 1;
+        state.insert("x\r\ny");
+        assert_eq!(state.text(), "axyb");
+        assert_eq!(state.cursor(), 3);
+    }
+}